@@ -0,0 +1,110 @@
+//! Benchmarks of saving, getting and querying objects on each backend.
+//!
+//! Run with `cargo bench -p butane --features sqlite,pg,async-adapter --bench ops`.
+//! SQLite is benchmarked in memory, both directly and through the async
+//! adapter, and PostgreSQL on a temporary server, or on the one given by
+//! `BUTANE_PG_CONNSTR`.
+use butane::db::{Connection, ConnectionAsync};
+use butane::prelude::*;
+use butane::{model, query};
+use butane_test_helper::*;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+/// Number of rows in the table queried.
+const ROWS: i64 = 1000;
+
+#[model]
+#[derive(Clone, Debug)]
+struct Reading {
+    id: i64,
+    sensor: String,
+    value: f64,
+    note: Option<String>,
+}
+
+impl Reading {
+    fn new(id: i64) -> Self {
+        Reading {
+            id,
+            sensor: format!("sensor-{}", id % 10),
+            value: id as f64 / 3.0,
+            note: (id % 2 == 0).then(|| "calibrated".to_string()),
+        }
+    }
+}
+
+/// Migrates `conn` and fills the table of [`Reading`] with [`ROWS`] rows.
+fn populate(conn: &mut Connection) {
+    setup_db(conn);
+    for id in 0..ROWS {
+        Reading::new(id).save(conn).unwrap();
+    }
+}
+
+fn bench_backend(c: &mut Criterion, name: &str, conn: &mut Connection) {
+    populate(conn);
+    let mut group = c.benchmark_group(name);
+
+    let mut id = 0;
+    group.bench_function("get", |b| {
+        b.iter(|| {
+            id = (id + 1) % ROWS;
+            Reading::get(conn, id).unwrap()
+        })
+    });
+
+    group.throughput(Throughput::Elements(ROWS as u64));
+    group.bench_function("load", |b| {
+        b.iter(|| {
+            query!(Reading, id < { ROWS })
+                .order_asc(butane::colname!(Reading, id))
+                .load(conn)
+                .unwrap()
+        })
+    });
+
+    // Saved last, as the objects saved are added to the table loaded
+    group.throughput(Throughput::Elements(1));
+    let mut next_id = ROWS;
+    group.bench_function("save", |b| {
+        b.iter_batched(
+            || {
+                next_id += 1;
+                Reading::new(next_id)
+            },
+            |mut reading| reading.save(conn).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn sqlite(c: &mut Criterion) {
+    bench_backend(c, "sqlite", &mut sqlite_connection());
+}
+
+fn sqlite_async(c: &mut Criterion) {
+    let mut conn = sqlite_connection();
+    populate(&mut conn);
+    let conn: ConnectionAsync = conn.into_async().unwrap();
+    let mut group = c.benchmark_group("sqlite-async");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    group.bench_function("load", |b| {
+        b.iter(|| {
+            tokio_test::block_on(butane::query::QueryOpsAsync::load(
+                query!(Reading, id < { ROWS }).order_asc(butane::colname!(Reading, id)),
+                &conn,
+            ))
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn pg(c: &mut Criterion) {
+    let (mut conn, _data) = pg_connection();
+    bench_backend(c, "pg", &mut conn);
+}
+
+criterion_group!(benches, sqlite, sqlite_async, pg);
+criterion_main!(benches);
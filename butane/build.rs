@@ -1,5 +1,6 @@
 fn main() {
     println!("cargo:rerun-if-changed=tests/");
+    println!("cargo:rerun-if-changed=benches/");
 
     // Note that #[cfg(test)] and CARGO_CFG_TEST do not work in build.rs
     // See https://github.com/rust-lang/cargo/issues/4789
@@ -6,17 +6,61 @@
 
 #![deny(missing_docs)]
 
-pub use butane_codegen::{butane_type, dataresult, model, FieldType, PrimaryKeyType};
+pub use butane_codegen::{
+    butane_type, dataresult, mixin, model, FieldType, PrimaryKeyType, Supertype,
+};
+pub use butane_core::audit;
+pub use butane_core::batch::{save_all_partial, save_all_partial_atomic};
+#[cfg(feature = "async")]
+pub use butane_core::batch::{save_all_partial_async, save_all_partial_atomic_async};
+#[cfg(feature = "async")]
+pub use butane_core::blob;
+#[cfg(feature = "registry")]
+pub use butane_core::cascade;
+#[cfg(feature = "compression")]
+pub use butane_core::compression;
+#[cfg(feature = "compression")]
+pub use butane_core::compression::Compressed;
+pub use butane_core::counter;
 pub use butane_core::custom;
-pub use butane_core::fkey::{ForeignKey, ForeignKeyOpsSync};
-pub use butane_core::many::{Many, ManyOpsSync};
+pub use butane_core::deadline;
+#[cfg(feature = "async")]
+pub use butane_core::deadline::with_deadline;
+pub use butane_core::encryption;
+pub use butane_core::encryption::Encrypted;
+pub use butane_core::fkey;
+pub use butane_core::fkey::{
+    ForeignKey, ForeignKeyOpsSync, GenericForeignKey, GenericForeignKeyOpsSync,
+};
+pub use butane_core::many;
+pub use butane_core::many::{Many, ManyOpsSync, OrderedMany};
 pub use butane_core::migrations;
+pub use butane_core::notify;
+pub use butane_core::partition;
+pub use butane_core::pkgen;
 pub use butane_core::query;
+#[cfg(feature = "registry")]
+pub use butane_core::registry;
+pub use butane_core::schema;
+pub use butane_core::schema::verify_schema;
+#[cfg(feature = "async")]
+pub use butane_core::schema::verify_schema_async;
+#[cfg(feature = "fake")]
+pub use butane_core::seed;
+pub use butane_core::subtype;
+pub use butane_core::subtype::{Subtype, Supertype, SupertypeOpsSync};
+pub use butane_core::through::{Association, ManyThrough, ManyThroughOpsSync};
+pub use butane_core::validate;
 #[cfg(feature = "async")]
-pub use butane_core::{fkey::ForeignKeyOpsAsync, many::ManyOpsAsync, DataObjectOpsAsync};
 pub use butane_core::{
-    AsPrimaryKey, AutoPk, DataObject, DataObjectOpsSync, DataResult, Error, FieldType, FromSql,
-    PrimaryKeyType, Result, SqlType, SqlVal, SqlValRef, ToSql,
+    fkey::ForeignKeyOpsAsync, fkey::GenericForeignKeyOpsAsync, many::ManyOpsAsync,
+    subtype::SupertypeOpsAsync, through::ManyThroughOpsAsync, AnyDataObjectAsync,
+    DataObjectOpsAsync,
+};
+pub use butane_core::{
+    AnyDataObject, AsPrimaryKey, AutoPk, DataObject, DataObjectOpsSync, DataResult, DynDataObject,
+    Error, FieldType, FromSql, PrimaryKeyType, Result, SqlType, SqlVal, SqlValRef,
+    StatementContext, StringList, ToSql, WritableDataObject,
 };
 
 pub mod db;
@@ -39,6 +83,11 @@
 /// To refer to values from the surrounding rust function, enclose
 /// them in braces, like `filter!(Foo, bar == {bar})`
 ///
+/// # Reuse
+/// The resulting [`BoolExpr`] does not depend on a [`Query`], so it
+/// may be stored, passed between functions, and combined with other
+/// expressions using [`BoolExpr::and`] and [`BoolExpr::or`].
+///
 /// # Function-like operations
 /// Filters support some operations for which Rust does not have operators and which are instead
 /// represented syntactically as function calls.
@@ -56,6 +105,11 @@
 ///   is single literal, it is assumed to be used to match the
 ///   primary key.
 ///
+/// # SQL functions
+/// The SQL functions in [`SQL_FUNCTIONS`](crate::query::SQL_FUNCTIONS)
+/// may be called on fields and compared or matched with `like`, e.g.
+/// `filter!(Post, length(body) > 500 && lower(title).like("a%"))`.
+///
 #[cfg_attr(
     feature = "async",
     doc = r##"
@@ -75,6 +129,7 @@ struct Contestant {
 let first_place = 1;
 let e2 = filter!(Contestant, rank == { first_place });
 let e3 = filter!(Contestant, name.like("A%"));
+let e4 = e.or(e2).and(e3);
 ```
 "##
 )]
@@ -83,6 +138,57 @@ struct Contestant {
 /// [`Query`]: crate::query::Query
 pub use butane_codegen::filter;
 
+/// Constructs an update of the objects of a model, setting fields to
+/// expressions which may refer to the current values of the fields.
+///
+/// Usage: `update!(Foo, field = expr, ...)` where `Foo` is a model
+/// type. Expressions may refer to `Foo`'s fields as if they were
+/// variables, use the operators `+`, `-`, `*`, `/` and `%`, and call
+/// the SQL functions in [`SQL_FUNCTIONS`](crate::query::SQL_FUNCTIONS).
+/// Rust values must be enclosed in braces, as in
+/// [`filter!`](crate::filter).
+///
+/// The resulting [`Update`] may be restricted with its `filter`
+/// method and is run as a single `UPDATE` statement by
+/// [`UpdateOpsSync::execute`] or [`UpdateOpsAsync::execute`], so
+/// concurrent updates of e.g. a counter are not lost.
+///
+/// ```ignore
+/// let updated = update!(Post, likes = likes + 1, title = upper(title))
+///     .filter(filter!(Post, id == { id }))
+///     .execute(&conn)?;
+/// ```
+///
+/// [`Update`]: crate::query::Update
+/// [`UpdateOpsSync::execute`]: crate::query::UpdateOpsSync::execute
+/// [`UpdateOpsAsync::execute`]: crate::query::UpdateOpsAsync::execute
+pub use butane_codegen::update;
+
+/// Constructs an [`Expr`] evaluated by the database on the fields of a
+/// model, which may refer to them and call SQL functions as in
+/// [`update!`](crate::update). Useful to order a query by a function of
+/// a field:
+///
+/// ```ignore
+/// let posts = Post::query()
+///     .order_by_asc(expr!(Post, lower(title)))
+///     .load(&conn)?;
+/// ```
+///
+/// [`Expr`]: crate::query::Expr
+pub use butane_codegen::expr;
+
+/// Expands to the [`SchemaFingerprint`] of the latest migration of the
+/// crate when it is compiled, which [`verify_schema`] compares with the
+/// database at startup to fail fast if it has not been migrated:
+///
+/// ```ignore
+/// butane::verify_schema(&conn, &butane::schema_fingerprint!())?;
+/// ```
+///
+/// [`SchemaFingerprint`]: crate::schema::SchemaFingerprint
+pub use butane_codegen::schema_fingerprint;
+
 /// Constructs a filtered database query.
 ///
 /// Use as `query!(Foo, expr)`, where `Foo` is a model type. Returns [`Query`]`<Foo>`.
@@ -131,6 +237,19 @@ macro_rules! colname {
     };
 }
 
+/// Chooses relationships of a model to load along with it, for use with
+/// [`get_with`](crate::DataObjectOpsSync::get_with). Use as
+/// `related!(FIELD_NAME, ...)`, naming `ForeignKey`, `Option<ForeignKey>`
+/// or `Many` fields of the model. E.g.
+/// `Post::get_with(conn, pk, related!(blog, tags))`. Naming any other
+/// field is a compiler error.
+#[macro_export]
+macro_rules! related {
+    ($($field:ident),+ $(,)?) => {
+        |fields| [$(butane::query::Related::from(fields.$field())),+]
+    };
+}
+
 /// Finds a specific database object.
 ///
 /// Use as `find!(Foo, expr, conn)`, where `Foo` is a model type and
@@ -204,9 +323,13 @@ pub mod prelude {
     pub use super::prelude_common::*;
 
     pub use butane_core::db::BackendConnection;
-    pub use butane_core::fkey::ForeignKeyOpsSync;
+    pub use butane_core::fkey::{ForeignKeyOpsSync, GenericForeignKeyOpsSync};
     pub use butane_core::many::ManyOpsSync;
-    pub use butane_core::query::QueryOpsSync;
+    pub use butane_core::query::{CursorOpsSync, PatchOpsSync, QueryOpsSync, UpdateOpsSync};
+    #[cfg(feature = "fake")]
+    pub use butane_core::seed::SeederOpsSync;
+    pub use butane_core::subtype::SupertypeOpsSync;
+    pub use butane_core::through::ManyThroughOpsSync;
     pub use butane_core::DataObjectOpsSync;
 }
 
@@ -218,9 +341,13 @@ pub mod prelude_async {
     pub use super::prelude_common::*;
 
     pub use butane_core::db::BackendConnectionAsync;
-    pub use butane_core::fkey::ForeignKeyOpsAsync;
+    pub use butane_core::fkey::{ForeignKeyOpsAsync, GenericForeignKeyOpsAsync};
     pub use butane_core::many::ManyOpsAsync;
-    pub use butane_core::query::QueryOpsAsync;
+    pub use butane_core::query::{CursorOpsAsync, PatchOpsAsync, QueryOpsAsync, UpdateOpsAsync};
+    #[cfg(feature = "fake")]
+    pub use butane_core::seed::SeederOpsAsync;
+    pub use butane_core::subtype::SupertypeOpsAsync;
+    pub use butane_core::through::ManyThroughOpsAsync;
     pub use butane_core::DataObjectOpsAsync;
 }
 
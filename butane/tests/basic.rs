@@ -1,8 +1,21 @@
-#![allow(clippy::disallowed_names)]
+#![allow(clippy::disallowed_names, clippy::field_reassign_with_default)]
 
+use butane::audit::AuditOp;
+use butane::cascade::CascadeAction;
 use butane::colname;
-use butane::db::{Connection, ConnectionAsync};
-use butane::{butane_type, find, find_async, model, query, AutoPk, ForeignKey};
+use butane::db::{
+    Connection, ConnectionAsync, ConnectionMethods, ConnectionMethodsAsync, RetryPolicy,
+};
+use butane::encryption::KeyProvider;
+use butane::migrations::adb::OnDelete;
+use butane::notify::{ChangeOp, ChangePayload, RowChange, RowOperation};
+use butane::query::BoolExpr;
+use butane::registry::{self, ModelOpsAsync, ModelOpsSync, Relationship};
+use butane::{
+    butane_type, filter, find, find_async, mixin, model, query, related, update, AnyDataObject,
+    AnyDataObjectAsync, AutoPk, DynDataObject, Encrypted, ForeignKey, FromSql, GenericForeignKey,
+    SqlVal, SqlValRef, Supertype, ToSql,
+};
 use butane_test_helper::*;
 use butane_test_macros::butane_test;
 #[cfg(feature = "datetime")]
@@ -11,8 +24,6 @@
     offset::Utc,
     DateTime,
 };
-#[cfg(feature = "sqlite")]
-use rusqlite;
 use std::ops::Deref;
 #[cfg(feature = "pg")]
 use tokio_postgres as postgres;
@@ -43,6 +54,74 @@ fn new(id: i64) -> Self {
     }
 }
 
+#[mixin]
+struct Identified {
+    id: i64,
+}
+
+#[mixin]
+struct Audited {
+    created_by: String,
+    #[default = 1]
+    revision: i32,
+}
+
+#[model(extends(Identified, Audited))]
+#[derive(PartialEq, Eq, Debug, Default)]
+struct Document {
+    title: String,
+}
+
+#[model(extends = Audited)]
+#[derive(PartialEq, Eq, Debug, Default)]
+struct Note {
+    id: i64,
+    body: String,
+}
+
+static TICKET_IDS: butane::pkgen::Snowflake = butane::pkgen::Snowflake::new(3);
+
+fn next_code() -> String {
+    static NEXT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(1);
+    format!(
+        "C{}",
+        NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    )
+}
+
+#[model]
+#[derive(PartialEq, Eq, Debug, Default)]
+struct UlidKeyed {
+    #[butane(pk_generator = "butane::pkgen::ulid")]
+    id: String,
+    name: String,
+}
+
+#[model]
+#[derive(PartialEq, Eq, Debug, Default)]
+struct SnowflakeKeyed {
+    #[butane(pk_generator = "TICKET_IDS")]
+    id: i64,
+    name: String,
+}
+
+#[model]
+#[derive(PartialEq, Eq, Debug, Default)]
+struct CustomKeyed {
+    #[pk]
+    #[butane(pk_generator = "next_code")]
+    code: String,
+    name: String,
+}
+
+#[model]
+#[derive(Debug)]
+struct FooDetails {
+    #[pk]
+    foo: ForeignKey<Foo>,
+    notes: String,
+}
+
 #[model]
 #[derive(PartialEq, Eq, Debug)]
 struct Bar {
@@ -73,6 +152,74 @@ fn new(text: &str) -> Self {
     }
 }
 
+#[derive(Debug, Supertype)]
+enum Notification {
+    Mention(Mention),
+    Reminder(Reminder),
+}
+
+#[model]
+#[butane(subtype_of = Notification, discriminator = "kind")]
+#[derive(Debug)]
+struct Mention {
+    id: AutoPk<i64>,
+    recipient: String,
+    by: String,
+}
+
+#[model]
+#[butane(subtype_of = Notification, discriminator = "kind")]
+#[derive(Debug)]
+struct Reminder {
+    id: AutoPk<i64>,
+    recipient: String,
+    due: i64,
+}
+impl Reminder {
+    fn new(recipient: &str, due: i64) -> Self {
+        Reminder {
+            id: AutoPk::uninitialized(),
+            recipient: recipient.to_string(),
+            due,
+        }
+    }
+}
+
+#[model]
+#[derive(Debug)]
+struct Forum {
+    id: i64,
+    name: String,
+    thread_count: i64,
+}
+impl Forum {
+    fn new(id: i64, name: &str) -> Self {
+        Forum {
+            id,
+            name: name.to_string(),
+            thread_count: 0,
+        }
+    }
+}
+
+#[model]
+#[derive(Debug)]
+struct Thread {
+    id: AutoPk<i64>,
+    #[butane(counter_cache = "Forum.thread_count")]
+    forum: ForeignKey<Forum>,
+    title: String,
+}
+impl Thread {
+    fn new(forum: &Forum, title: &str) -> Self {
+        Thread {
+            id: AutoPk::uninitialized(),
+            forum: forum.into(),
+            title: title.to_string(),
+        }
+    }
+}
+
 #[model]
 struct HasOnlyPk {
     id: i64,
@@ -89,6 +236,119 @@ struct HasOnlyAutoPk {
     id: AutoPk<i64>,
 }
 
+#[model]
+#[butane(immutable)]
+#[derive(Debug, Default)]
+struct LedgerEntry {
+    id: AutoPk<i64>,
+    amount: i64,
+}
+
+#[model]
+#[butane(immutable)]
+#[derive(Debug, Default)]
+struct Event {
+    id: i64,
+    kind: String,
+}
+
+#[model]
+#[derive(Default)]
+struct Ticket {
+    id: AutoPk<i64>,
+    title: String,
+    #[readonly]
+    #[default = 3]
+    priority: i64,
+}
+
+#[model]
+#[derive(Default)]
+struct Slugged {
+    id: AutoPk<i64>,
+    #[refresh]
+    slug: String,
+}
+
+#[model]
+#[derive(Default)]
+struct Kennel {
+    id: AutoPk<i64>,
+    name: String,
+}
+
+#[model]
+#[derive(Default)]
+struct Breeder {
+    id: AutoPk<i64>,
+    name: String,
+}
+
+#[model]
+struct Dog {
+    id: AutoPk<i64>,
+    #[butane(on_delete = "cascade")]
+    kennel: ForeignKey<Kennel>,
+    #[butane(on_delete = "set_null")]
+    breeder: Option<ForeignKey<Breeder>>,
+}
+
+#[model]
+struct Puppy {
+    id: AutoPk<i64>,
+    #[butane(on_delete = "restrict")]
+    mother: Option<ForeignKey<Dog>>,
+    #[butane(no_foreign_key)]
+    father: Option<ForeignKey<Dog>>,
+}
+
+#[model]
+struct Remark {
+    id: AutoPk<i64>,
+    target: GenericForeignKey,
+    text: String,
+}
+impl Remark {
+    fn new(target: GenericForeignKey, text: &str) -> Self {
+        Remark {
+            id: AutoPk::uninitialized(),
+            target,
+            text: text.to_string(),
+        }
+    }
+}
+
+#[model]
+#[derive(Default)]
+struct Member {
+    id: AutoPk<i64>,
+    #[unique]
+    #[butane(collate = "NOCASE")]
+    name: String,
+}
+
+#[model]
+#[butane(view = "SELECT id, text FROM Baz WHERE text LIKE 'visible%'")]
+struct VisibleBaz {
+    id: i64,
+    text: String,
+}
+
+#[model]
+#[butane(view = "SELECT id, text FROM Baz", materialized)]
+struct BazSnapshot {
+    #[unique]
+    id: i64,
+    text: String,
+}
+
+#[model]
+#[butane(notify = "headline_changes")]
+struct Headline {
+    id: AutoPk<i64>,
+    text: String,
+}
+
 #[model]
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct SelfReferential {
@@ -185,6 +445,26 @@ async fn basic_query(conn: ConnectionAsync) {
     assert_eq!(found.len(), 2);
 }
 
+#[butane_test]
+async fn query_text_and_blob_values(conn: ConnectionAsync) {
+    let mut foos = Vec::new();
+    for id in 1..=4 {
+        let mut foo = Foo::new(id);
+        foo.bar = id as u32;
+        foo.baz = "é".repeat(id as usize - 1);
+        foo.blobbity = vec![id as u8; id as usize * 3 % 4];
+        foo.save(&conn).await.unwrap();
+        foos.push(foo);
+    }
+
+    let found = query!(Foo, bar > 0)
+        .order_asc(colname!(Foo, id))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found, foos);
+}
+
 #[butane_test]
 async fn basic_query_delete(conn: ConnectionAsync) {
     //create
@@ -239,6 +519,39 @@ async fn foreign_key(conn: ConnectionAsync) {
     assert_eq!(foo2, foo3);
 }
 
+#[butane_test]
+async fn foreign_key_as_pk(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    foo.save(&conn).await.unwrap();
+    let mut details = FooDetails {
+        foo: (&foo).into(),
+        notes: "first".to_string(),
+    };
+    details.save(&conn).await.unwrap();
+
+    // Saving again updates the same row
+    details.notes = "second".to_string();
+    details.save(&conn).await.unwrap();
+    assert_eq!(FooDetails::query().load(&conn).await.unwrap().len(), 1);
+
+    let details = FooDetails::get(&conn, &foo).await.unwrap();
+    assert_eq!(details.notes, "second");
+    assert_eq!(details.foo, foo);
+    assert_eq!(details.foo.load(&conn).await.unwrap(), &foo);
+    let found = query!(FooDetails, foo == { &foo })
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+
+    // The column refers to the parent
+    let mut orphan = FooDetails {
+        foo: ForeignKey::from_pk(2),
+        notes: String::new(),
+    };
+    assert!(orphan.save(&conn).await.is_err());
+}
+
 #[butane_test]
 async fn auto_pk(conn: ConnectionAsync) {
     let mut baz1 = Baz::new("baz1");
@@ -252,6 +565,413 @@ async fn auto_pk(conn: ConnectionAsync) {
     let _raw_pk: i64 = baz1.id.deref().unwrap();
 }
 
+#[butane_test]
+async fn dyn_data_object(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    foo.bar = 42;
+    foo.save(&conn).await.unwrap();
+    let mut baz = Baz::new("baz");
+    baz.save(&conn).await.unwrap();
+
+    let objects: Vec<Box<dyn DynDataObject>> = vec![Box::new(foo.clone()), Box::new(baz)];
+    assert_eq!(objects[0].table(), Foo::TABLE);
+    assert_eq!(objects[1].table(), Baz::TABLE);
+    assert_eq!(objects[0].pk_value(), SqlVal::BigInt(1));
+    let baz = Baz::get(&conn, i64::from_sql(objects[1].pk_value()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(baz.text, "baz");
+    for obj in &objects {
+        assert_eq!(obj.columns().len(), obj.to_values().len());
+    }
+
+    assert_eq!(Foo::from_values(objects[0].to_values()).unwrap(), foo);
+    let mut other: Box<dyn DynDataObject> = Box::new(Foo::new(2));
+    other.set_values(objects[0].to_values()).unwrap();
+    assert_eq!(other.to_values(), objects[0].to_values());
+    assert!(Foo::from_values(vec![SqlVal::Text("foo".to_string())]).is_err());
+}
+
+#[butane_test]
+async fn any_data_object(conn: ConnectionAsync) {
+    let mut objects: Vec<Box<dyn AnyDataObjectAsync>> =
+        vec![Box::new(Foo::new(1)), Box::new(Baz::new("baz"))];
+    let dyn_conn: &dyn ConnectionMethodsAsync = &conn;
+    for obj in &mut objects {
+        obj.save_dyn(dyn_conn).await.unwrap();
+    }
+    assert!(Foo::try_get(&conn, 1).await.unwrap().is_some());
+    // The AutoPk of the Baz is initialized by saving it
+    let baz_pk = i64::from_sql(objects[1].pk_value()).unwrap();
+    assert_eq!(Baz::get(&conn, baz_pk).await.unwrap().text, "baz");
+
+    for obj in &objects {
+        obj.delete_dyn(dyn_conn).await.unwrap();
+    }
+    assert!(Foo::try_get(&conn, 1).await.unwrap().is_none());
+    assert!(Baz::try_get(&conn, baz_pk).await.unwrap().is_none());
+}
+
+#[butane_test]
+async fn generic_foreign_key(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    foo.save(&conn).await.unwrap();
+    let mut kennel = Kennel::default();
+    kennel.name = "Barkley".to_string();
+    kennel.save(&conn).await.unwrap();
+    Remark::new(GenericForeignKey::from(&foo), "on foo")
+        .save(&conn)
+        .await
+        .unwrap();
+    Remark::new(GenericForeignKey::from(&kennel), "on kennel")
+        .save(&conn)
+        .await
+        .unwrap();
+
+    let remarks = query!(Remark, target == { GenericForeignKey::from(&kennel) })
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(remarks.len(), 1);
+    let remark = &remarks[0];
+    assert_eq!(remark.text, "on kennel");
+    assert!(remark.target.is::<Kennel>());
+    assert_eq!(remark.target.model(), "Kennel");
+    let loaded: Kennel = remark.target.load_as(&conn).await.unwrap();
+    assert_eq!(loaded.name, "Barkley");
+    let wrong = remark.target.load_as::<Foo>(&conn).await;
+    assert!(matches!(
+        wrong,
+        Err(butane::Error::WrongModel {
+            expected: "Foo",
+            ..
+        })
+    ));
+
+    // The model is resolved through the registry
+    let remark = find_async!(Remark, text == "on foo", &conn).unwrap();
+    let target = remark.target.load(&conn).await.unwrap();
+    assert_eq!(target.table(), "Foo");
+    assert_eq!(target.pk_value(), SqlVal::BigInt(1));
+}
+
+#[butane_test]
+async fn subtypes_share_table(conn: ConnectionAsync) {
+    let mut mention = Mention {
+        id: AutoPk::uninitialized(),
+        recipient: "alice".to_string(),
+        by: "bob".to_string(),
+    };
+    mention.save(&conn).await.unwrap();
+    let mut reminder = Reminder::new("alice", 10);
+    reminder.save(&conn).await.unwrap();
+    Reminder::new("bob", 20).save(&conn).await.unwrap();
+
+    // Each subtype only matches its own objects
+    assert_eq!(Mention::query().load(&conn).await.unwrap().len(), 1);
+    let reminders = query!(Reminder, recipient == "alice")
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(reminders.len(), 1);
+    assert_eq!(reminders[0].due, 10);
+    let err = Mention::get(&conn, reminder.id).await.unwrap_err();
+    assert!(matches!(err, butane::Error::NoSuchObject));
+
+    // The supertype loads each as the right variant
+    let notifications = Notification::load(&conn, filter!(Reminder, recipient == "alice"))
+        .await
+        .unwrap();
+    assert_eq!(notifications.len(), 2);
+    assert!(matches!(&notifications[0], Notification::Mention(m) if m.by == "bob"));
+    assert!(matches!(&notifications[1], Notification::Reminder(r) if r.due == 10));
+    let loaded = Notification::get(&conn, mention.id).await.unwrap();
+    assert!(matches!(loaded, Notification::Mention(_)));
+
+    // Deleting the objects of one subtype leaves the others
+    assert_eq!(Reminder::query().delete(&conn).await.unwrap(), 2);
+    let notifications = Notification::load(&conn, BoolExpr::True).await.unwrap();
+    assert_eq!(notifications.len(), 1);
+}
+
+#[butane_test]
+async fn counter_cache_counts_referring_objects(conn: ConnectionAsync) {
+    let mut rust = Forum::new(1, "rust");
+    rust.save(&conn).await.unwrap();
+    let mut sql = Forum::new(2, "sql");
+    sql.save(&conn).await.unwrap();
+
+    let mut first = Thread::new(&rust, "first");
+    first.save(&conn).await.unwrap();
+    Thread::new(&rust, "second").save(&conn).await.unwrap();
+    let mut third = Thread::new(&sql, "third");
+    assert!(third.save_ignore_conflict(&conn).await.unwrap());
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 2);
+    assert_eq!(Forum::get(&conn, 2).await.unwrap().thread_count, 1);
+
+    // Saving without changing the forum leaves the counts, moving to
+    // another forum moves the count
+    first.title = "first!".to_string();
+    first.save(&conn).await.unwrap();
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 2);
+    first.forum = (&sql).into();
+    first.save(&conn).await.unwrap();
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 1);
+    assert_eq!(Forum::get(&conn, 2).await.unwrap().thread_count, 2);
+
+    butane::update!(Thread, forum = { 1i64 })
+        .filter(filter!(Thread, title == "third"))
+        .execute(&conn)
+        .await
+        .unwrap();
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 2);
+    assert_eq!(Forum::get(&conn, 2).await.unwrap().thread_count, 1);
+
+    first.delete(&conn).await.unwrap();
+    assert_eq!(Forum::get(&conn, 2).await.unwrap().thread_count, 0);
+    assert_eq!(Thread::query().delete(&conn).await.unwrap(), 2);
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 0);
+}
+
+#[butane_test]
+async fn counter_cache_counts_each_delete_once(mut conn: ConnectionAsync) {
+    let mut rust = Forum::new(1, "rust");
+    rust.save(&conn).await.unwrap();
+    let mut threads = Vec::new();
+    for title in ["first", "second", "third", "fourth"] {
+        let mut thread = Thread::new(&rust, title);
+        thread.save(&conn).await.unwrap();
+        threads.push(thread);
+    }
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 4);
+
+    // Deleting an object which has already been deleted, such as by
+    // another copy of it, leaves the count
+    let copy = Thread::get(&conn, threads[0].id).await.unwrap();
+    threads[0].delete(&conn).await.unwrap();
+    copy.delete(&conn).await.unwrap();
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 3);
+
+    let ids = [threads[0].id, threads[1].id];
+    assert_eq!(Thread::delete_many(&conn, &ids).await.unwrap(), 1);
+    assert_eq!(Thread::delete_many(&conn, &ids).await.unwrap(), 0);
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 2);
+
+    // Within a transaction, the change and the counter are made within
+    // a savepoint
+    let tr = conn.transaction().await.unwrap();
+    threads[2].delete(&tr).await.unwrap();
+    threads[2].delete(&tr).await.unwrap();
+    tr.commit().await.unwrap();
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 1);
+
+    // Objects deleted by a cascade are no longer counted either
+    let tr = conn.transaction().await.unwrap();
+    let report = threads[3].delete_cascade(&tr).await.unwrap();
+    assert_eq!(report.total_rows(), 1);
+    let report = threads[3].delete_cascade(&tr).await.unwrap();
+    assert_eq!(report.total_rows(), 0);
+    tr.commit().await.unwrap();
+    assert_eq!(Forum::get(&conn, 1).await.unwrap().thread_count, 0);
+}
+
+#[butane_test(async)]
+async fn cancelled_counted_change_is_undone(conn: ConnectionAsync) {
+    use std::future::{poll_fn, Future};
+    use std::pin::pin;
+    use std::task::Poll;
+
+    let mut rust = Forum::new(1, "rust");
+    rust.save(&conn).await.unwrap();
+    // Cancels saving a thread after more and more of it has run
+    for polls in 1..50 {
+        let mut thread = Thread::new(&rust, "cancelled");
+        {
+            let mut save = pin!(thread.save(&conn));
+            for _ in 0..polls {
+                if poll_fn(|cx| Poll::Ready(save.as_mut().poll(cx)))
+                    .await
+                    .is_ready()
+                {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        }
+        // The connection is not left within the group
+        assert!(!conn.in_transaction());
+    }
+    // The threads and their count were saved together or not at all
+    let threads = Thread::query().load(&conn).await.unwrap();
+    assert_eq!(
+        Forum::get(&conn, 1).await.unwrap().thread_count,
+        threads.len() as i64
+    );
+}
+
+#[butane_test]
+async fn registry_models(conn: ConnectionAsync) {
+    let dog = registry::model("Dog").unwrap();
+    assert_eq!(dog.pk_column(), "id");
+    assert!(dog.auto_pk());
+    assert_eq!(
+        dog.relationships(),
+        [
+            Relationship::ForeignKey {
+                column: "kennel",
+                target: "Kennel",
+                on_delete: Some(OnDelete::Cascade),
+            },
+            Relationship::ForeignKey {
+                column: "breeder",
+                target: "Breeder",
+                on_delete: Some(OnDelete::SetNull),
+            },
+        ]
+    );
+    assert!(registry::models().any(|model| model.name() == "VisibleBaz"));
+
+    for id in [3, 1, 2] {
+        let mut foo = Foo::new(id);
+        foo.bar = id as u32;
+        foo.save(&conn).await.unwrap();
+    }
+    let foo = registry::model("Foo").unwrap();
+    let names: Vec<&str> = foo.columns().iter().map(|col| col.name()).collect();
+    assert_eq!(names, ["id", "bam", "bar", "baz", "blobbity"]);
+    let dyn_conn: &dyn ConnectionMethodsAsync = &conn;
+    let listed = ModelOpsAsync::list(foo, dyn_conn, Some(2), Some(1))
+        .await
+        .unwrap();
+    let pks: Vec<SqlVal> = listed.iter().map(|obj| obj.pk_value()).collect();
+    assert_eq!(pks, [SqlVal::BigInt(2), SqlVal::BigInt(3)]);
+
+    ModelOpsAsync::update(
+        foo,
+        dyn_conn,
+        SqlVal::BigInt(2),
+        vec![("baz".to_string(), SqlVal::Text("updated".to_string()))],
+    )
+    .await
+    .unwrap();
+    let updated = ModelOpsAsync::get(foo, dyn_conn, SqlVal::BigInt(2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.to_values()[3], SqlVal::Text("updated".to_string()));
+    assert_eq!(Foo::get(&conn, 2).await.unwrap().baz, "updated");
+    assert!(ModelOpsAsync::get(foo, dyn_conn, SqlVal::BigInt(4))
+        .await
+        .unwrap()
+        .is_none());
+
+    let err = ModelOpsAsync::update(
+        foo,
+        dyn_conn,
+        SqlVal::BigInt(2),
+        vec![("id".to_string(), SqlVal::BigInt(5))],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::UnknownColumn("Foo", _)));
+    let err = ModelOpsAsync::update(
+        foo,
+        dyn_conn,
+        SqlVal::BigInt(4),
+        vec![("bam".to_string(), SqlVal::Real(1.0))],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::NoSuchObject));
+
+    // Changes which only the model can make correctly are refused: the
+    // history of audited objects, and values encrypted by the model
+    let err = ModelOpsAsync::update(
+        registry::model("Ledger").unwrap(),
+        dyn_conn,
+        SqlVal::BigInt(1),
+        vec![("balance".to_string(), SqlVal::BigInt(0))],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::Audited("Ledger")));
+    let err = ModelOpsAsync::update(
+        registry::model("Patient").unwrap(),
+        dyn_conn,
+        SqlVal::BigInt(1),
+        vec![("name".to_string(), SqlVal::Blob(b"Mallory".to_vec()))],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::UnknownColumn("Patient", _)));
+    let err = ModelOpsAsync::update(
+        registry::model("Event").unwrap(),
+        dyn_conn,
+        SqlVal::BigInt(1),
+        vec![("kind".to_string(), SqlVal::Text("changed".to_string()))],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::Immutable("Event")));
+}
+
+#[butane_test]
+async fn view_model(conn: ConnectionAsync) {
+    for text in ["visible one", "hidden", "visible two"] {
+        Baz::new(text).save(&conn).await.unwrap();
+    }
+
+    let found = VisibleBaz::query()
+        .order_asc(colname!(VisibleBaz, id))
+        .load(&conn)
+        .await
+        .unwrap();
+    let texts: Vec<&str> = found.iter().map(|baz| baz.text.as_str()).collect();
+    assert_eq!(texts, ["visible one", "visible two"]);
+
+    let found = query!(VisibleBaz, text == "visible two")
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    let baz = VisibleBaz::get(&conn, found[0].id).await.unwrap();
+    assert_eq!(baz.text, "visible two");
+}
+
+#[butane_test]
+async fn materialized_view_model(conn: ConnectionAsync) {
+    Baz::new("first").save(&conn).await.unwrap();
+    conn.refresh_materialized_view::<BazSnapshot>()
+        .await
+        .unwrap();
+    Baz::new("second").save(&conn).await.unwrap();
+
+    let texts = |found: Vec<BazSnapshot>| -> Vec<String> {
+        found.into_iter().map(|baz| baz.text).collect()
+    };
+    let found = BazSnapshot::query()
+        .order_asc(colname!(BazSnapshot, id))
+        .load(&conn)
+        .await
+        .unwrap();
+    if conn.backend_name() == "pg" {
+        // Rows saved since the last refresh are not seen until the next one
+        assert_eq!(texts(found), ["first"]);
+    } else {
+        assert_eq!(texts(found), ["first", "second"]);
+    }
+
+    conn.refresh_materialized_view::<BazSnapshot>()
+        .await
+        .unwrap();
+    let found = BazSnapshot::query()
+        .order_asc(colname!(BazSnapshot, id))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(texts(found), ["first", "second"]);
+}
+
 #[butane_test]
 async fn only_pk(conn: ConnectionAsync) {
     let mut obj = HasOnlyPk::new(1);
@@ -277,13 +997,118 @@ async fn only_auto_pk(conn: ConnectionAsync) {
 }
 
 #[butane_test]
-async fn basic_committed_transaction(mut conn: ConnectionAsync) {
-    let tr = conn.transaction().await.unwrap();
+async fn readonly_fields_refreshed_on_save(conn: ConnectionAsync) {
+    let mut ticket = Ticket::default();
+    ticket.title = "broken".to_string();
+    ticket.save(&conn).await.unwrap();
+    // The default was generated by the database rather than written
+    assert_eq!(ticket.priority, 3);
+
+    conn.execute("UPDATE Ticket SET priority = 5")
+        .await
+        .unwrap();
+    ticket.title = "still broken".to_string();
+    ticket.priority = 1;
+    ticket.save(&conn).await.unwrap();
+    // The update does not write readonly fields but does refresh them
+    assert_eq!(ticket.priority, 5);
+    let retrieved = Ticket::get(&conn, ticket.id).await.unwrap();
+    assert_eq!(retrieved.title, "still broken");
+    assert_eq!(retrieved.priority, 5);
+}
 
-    // Create an object with a transaction and commit it
-    let mut foo = Foo::new(1);
-    foo.bar = 42;
-    foo.save(&tr).await.unwrap();
+#[butane_test]
+async fn refresh_fields_changed_by_trigger(conn: ConnectionAsync) {
+    let trigger = if conn.backend_name() == "sqlite" {
+        "CREATE TRIGGER slugged_lower AFTER INSERT ON Slugged BEGIN \
+         UPDATE Slugged SET slug = lower(NEW.slug) WHERE id = NEW.id; END;"
+    } else {
+        "CREATE FUNCTION slugged_lower() RETURNS trigger AS $$ BEGIN \
+         NEW.slug := lower(NEW.slug); RETURN NEW; END; $$ LANGUAGE plpgsql; \
+         CREATE TRIGGER slugged_lower BEFORE INSERT ON Slugged \
+         FOR EACH ROW EXECUTE FUNCTION slugged_lower();"
+    };
+    conn.execute(trigger).await.unwrap();
+
+    let mut slugged = Slugged::default();
+    slugged.slug = "Hello".to_string();
+    slugged.save(&conn).await.unwrap();
+    assert_eq!(slugged.slug, "hello");
+}
+
+#[butane_test]
+async fn immutable_objects_are_not_updated(conn: ConnectionAsync) {
+    let mut entry = LedgerEntry {
+        amount: 10,
+        ..Default::default()
+    };
+    entry.save(&conn).await.unwrap();
+    entry.amount = 20;
+    let err = entry.save(&conn).await.unwrap_err();
+    assert!(matches!(err, butane::Error::Immutable("LedgerEntry")));
+    let err = butane::update!(LedgerEntry, amount = 30)
+        .execute(&conn)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::Immutable("LedgerEntry")));
+    assert_eq!(LedgerEntry::get(&conn, entry.id).await.unwrap().amount, 10);
+
+    // Without an AutoPk, saving an existing object conflicts with its row
+    let mut event = Event {
+        id: 1,
+        kind: "opened".to_string(),
+    };
+    event.save(&conn).await.unwrap();
+    event.kind = "closed".to_string();
+    let err = event.save(&conn).await.unwrap_err();
+    assert!(matches!(err, butane::Error::Immutable("Event")));
+    assert_eq!(Event::get(&conn, 1).await.unwrap().kind, "opened");
+    let mut copy = Event {
+        id: 1,
+        kind: "copied".to_string(),
+    };
+    let err = copy.save(&conn).await.unwrap_err();
+    assert!(matches!(err, butane::Error::Immutable("Event")));
+}
+
+#[butane_test]
+async fn save_returning_stored_row(conn: ConnectionAsync) {
+    let trigger = if conn.backend_name() == "sqlite" {
+        "CREATE TRIGGER kennel_upper AFTER INSERT ON Kennel BEGIN \
+         UPDATE Kennel SET name = upper(NEW.name) WHERE id = NEW.id; END;"
+    } else {
+        "CREATE FUNCTION kennel_upper() RETURNS trigger AS $$ BEGIN \
+         NEW.name := upper(NEW.name); RETURN NEW; END; $$ LANGUAGE plpgsql; \
+         CREATE TRIGGER kennel_upper BEFORE INSERT ON Kennel \
+         FOR EACH ROW EXECUTE FUNCTION kennel_upper();"
+    };
+    conn.execute(trigger).await.unwrap();
+
+    let mut kennel = Kennel {
+        name: "Barkley".to_string(),
+        ..Default::default()
+    };
+    let stored = kennel.save_returning(&conn).await.unwrap();
+    assert_eq!(stored.id, kennel.id);
+    assert_eq!(stored.name, "BARKLEY");
+    // Only the primary key of the saved object is refreshed
+    assert_eq!(kennel.name, "Barkley");
+
+    // An upserted row is read back by its primary key
+    let mut foo = Foo::new(1);
+    foo.baz = "hello".to_string();
+    let stored = foo.save_returning(&conn).await.unwrap();
+    assert_eq!(stored, foo);
+}
+
+#[butane_test]
+async fn basic_committed_transaction(mut conn: ConnectionAsync) {
+    let tr = conn.transaction().await.unwrap();
+
+    // Create an object with a transaction and commit it
+    let mut foo = Foo::new(1);
+    foo.bar = 42;
+    foo.save(&tr).await.unwrap();
     tr.commit().await.unwrap();
 
     // Find the object
@@ -327,6 +1152,178 @@ async fn basic_rollback_transaction(mut conn: ConnectionAsync) {
     }
 }
 
+#[butane_test(async)]
+async fn closure_transaction(mut conn: ConnectionAsync) {
+    // Committed when the closure succeeds
+    let bar = conn
+        .with_transaction(|tx| {
+            Box::pin(async move {
+                let mut foo = Foo::new(1);
+                foo.bar = 42;
+                foo.save(tx).await?;
+                Ok::<_, butane::Error>(foo.bar)
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(bar, 42);
+    assert_eq!(Foo::get(&conn, 1).await.unwrap().bar, 42);
+
+    // Rolled back when it fails
+    let err = conn
+        .with_transaction(|tx| {
+            Box::pin(async move {
+                let mut foo = Foo::new(2);
+                foo.save(tx).await?;
+                Err::<(), _>(butane::Error::NoSuchObject)
+            })
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::NoSuchObject));
+    assert!(matches!(
+        Foo::get(&conn, 2).await,
+        Err(butane::Error::NoSuchObject)
+    ));
+}
+
+#[butane_test(sync)]
+async fn closure_transaction_panic(mut conn: ConnectionAsync) {
+    // Rolled back when the closure panics
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        conn.with_transaction(|tx| {
+            let mut foo = Foo::new(1);
+            foo.save(tx).await?;
+            panic!("failed after saving");
+            #[allow(unreachable_code)]
+            Ok::<_, butane::Error>(())
+        })
+    }));
+    assert!(result.is_err());
+    let foo = Foo::get(&conn, 1).await;
+    assert!(matches!(foo, Err(butane::Error::NoSuchObject)));
+
+    conn.with_transaction(|tx| {
+        let mut foo = Foo::new(1);
+        foo.save(tx).await
+    })
+    .await
+    .unwrap();
+    assert!(Foo::get(&conn, 1).await.is_ok());
+}
+
+#[butane_test(async)]
+async fn transaction_with_retry(mut conn: ConnectionAsync) {
+    fn busy() -> butane::Error {
+        butane::Error::SQLite(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        ))
+    }
+    let policy = RetryPolicy::new(3).base_delay(std::time::Duration::from_millis(1));
+
+    // Each failed attempt is rolled back before the next
+    let mut attempts = 0;
+    conn.transaction_with_retry(&policy, |tx| {
+        attempts += 1;
+        let attempt = attempts;
+        Box::pin(async move {
+            let mut foo = Foo::new(attempt);
+            foo.save(tx).await?;
+            if attempt < 3 {
+                return Err(busy());
+            }
+            Ok(())
+        })
+    })
+    .await
+    .unwrap();
+    assert_eq!(attempts, 3);
+    assert_eq!(Foo::query().load(&conn).await.unwrap().len(), 1);
+    assert!(Foo::get(&conn, 3).await.is_ok());
+
+    // Gives up once the policy allows no more attempts
+    let mut attempts = 0;
+    let err = conn
+        .transaction_with_retry(&policy, |_| {
+            attempts += 1;
+            Box::pin(async { Err::<(), _>(busy()) })
+        })
+        .await
+        .unwrap_err();
+    assert!(err.is_retryable());
+    assert_eq!(attempts, 3);
+
+    // Other errors are not retried
+    let mut attempts = 0;
+    conn.transaction_with_retry(&policy, |_| {
+        attempts += 1;
+        Box::pin(async { Err::<(), _>(butane::Error::NoSuchObject) })
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(attempts, 1);
+}
+
+#[butane_test]
+async fn pk_generators(conn: ConnectionAsync) {
+    let mut first = UlidKeyed::default();
+    first.save(&conn).await.unwrap();
+    let mut second = UlidKeyed::default();
+    second.save(&conn).await.unwrap();
+    assert_eq!(first.id.len(), 26);
+    assert_ne!(first.id, second.id);
+    assert_eq!(
+        UlidKeyed::get(&conn, first.id.clone()).await.unwrap(),
+        first
+    );
+
+    let mut first = SnowflakeKeyed::default();
+    first.save(&conn).await.unwrap();
+    let mut second = SnowflakeKeyed::default();
+    second.save(&conn).await.unwrap();
+    assert!(first.id > 0 && first.id < second.id);
+    assert_eq!((first.id >> 12) & 0x3ff, 3);
+
+    // A key which was already set is kept
+    let mut custom = CustomKeyed::default();
+    custom.save(&conn).await.unwrap();
+    assert!(custom.code.starts_with('C'));
+    let mut named = CustomKeyed {
+        code: "mine".to_string(),
+        name: String::new(),
+    };
+    named.save(&conn).await.unwrap();
+    assert_eq!(named.code, "mine");
+    assert_eq!(CustomKeyed::query().load(&conn).await.unwrap().len(), 2);
+}
+
+#[butane_test]
+async fn model_mixins(conn: ConnectionAsync) {
+    assert_eq!(Document::PKCOL, "id");
+    let columns: Vec<&str> = Document::COLUMNS.iter().map(|c| c.name()).collect();
+    assert_eq!(columns, ["id", "created_by", "revision", "title"]);
+
+    let mut doc = Document {
+        id: 1,
+        created_by: "alice".to_string(),
+        revision: 2,
+        title: "Minutes".to_string(),
+    };
+    doc.save(&conn).await.unwrap();
+    assert_eq!(Document::get(&conn, 1).await.unwrap(), doc);
+
+    let mut note = Note {
+        id: 1,
+        created_by: "bob".to_string(),
+        revision: 1,
+        body: "Remember the milk".to_string(),
+    };
+    note.save(&conn).await.unwrap();
+    let notes = query!(Note, created_by == "bob").load(&conn).await.unwrap();
+    assert_eq!(notes, [note]);
+}
+
 #[butane_test]
 async fn basic_unique_field_error_on_non_unique(conn: ConnectionAsync) {
     let mut foo1 = Foo::new(1);
@@ -337,12 +1334,12 @@ async fn basic_unique_field_error_on_non_unique(conn: ConnectionAsync) {
     foo2.bar = foo1.bar;
     let e = foo2.save(&conn).await.unwrap_err();
     // Make sure the error is one we expect
-    assert!(match e {
+    assert!(match e.root() {
         #[cfg(feature = "sqlite")]
         butane::Error::SQLite(rusqlite::Error::SqliteFailure(
             rusqlite::ffi::Error { code, .. },
             _,
-        )) if code == rusqlite::ffi::ErrorCode::ConstraintViolation => true,
+        )) if *code == rusqlite::ffi::ErrorCode::ConstraintViolation => true,
         #[cfg(feature = "pg")]
         butane::Error::Postgres(e)
             if e.code() == Some(&postgres::error::SqlState::UNIQUE_VIOLATION) =>
@@ -351,6 +1348,122 @@ async fn basic_unique_field_error_on_non_unique(conn: ConnectionAsync) {
     });
 }
 
+#[butane_test]
+async fn statement_error_has_context(conn: ConnectionAsync) {
+    let mut foo1 = Foo::new(1);
+    foo1.bar = 42;
+    foo1.save(&conn).await.unwrap();
+
+    let mut foo2 = Foo::new(2);
+    foo2.bar = foo1.bar;
+    let e = foo2.save(&conn).await.unwrap_err();
+    let context = e.statement().unwrap();
+    assert!(context.sql.starts_with("INSERT INTO Foo "));
+    assert_eq!(context.table, "Foo");
+    // Only the types of parameters are included by default
+    assert!(context.params.contains(&"big int".to_string()));
+    assert!(!context.params.iter().any(|p| p.contains('2')));
+    assert!(e.to_string().contains(&context.sql));
+
+    butane::db::set_parameter_values_in_errors(true);
+    let e = foo2.save(&conn).await.unwrap_err();
+    butane::db::set_parameter_values_in_errors(false);
+    let context = e.statement().unwrap();
+    assert!(context.params.contains(&"BigInt(2)".to_string()));
+}
+
+#[butane_test]
+async fn sensitive_values_are_redacted(conn: ConnectionAsync) {
+    #[model]
+    #[derive(Debug, Default)]
+    struct Account {
+        id: i64,
+        #[unique]
+        #[butane(sensitive)]
+        token: String,
+    }
+    assert!(Account::COLUMNS[1].is_sensitive());
+    assert!(!Account::COLUMNS[0].is_sensitive());
+
+    let token = "hunter2-secret".to_string();
+    let mut first = Account {
+        id: 1,
+        token: token.clone(),
+    };
+    first.save(&conn).await.unwrap();
+    let mut second = Account { id: 2, token };
+    let e = second.save(&conn).await.unwrap_err();
+    let context = e.statement().unwrap();
+    assert_eq!(context.table, "Account");
+    assert!(context.params.iter().all(|p| p == "<redacted>"));
+    assert!(!e.to_string().contains("hunter2"));
+    assert!(!format!("{e:?}").contains("hunter2"));
+    assert!(!format!("{e:#?}").contains("hunter2"));
+    if conn.backend_name() == "pg" {
+        // The detail of the unique violation quotes the value
+        assert!(format!("{e:?}").contains("<redacted>"));
+    }
+}
+
+#[butane_test]
+async fn sensitive_values_are_redacted_before_model_is_used(conn: ConnectionAsync) {
+    // Only used by this test, so only updated before its error
+    #[model]
+    #[derive(Debug, Default)]
+    struct ApiKey {
+        id: i64,
+        #[unique]
+        #[butane(sensitive)]
+        token: String,
+    }
+    conn.execute("INSERT INTO ApiKey (id, token) VALUES (1, 'hunter2-secret'), (2, 'other');")
+        .await
+        .unwrap();
+    let e = update!(ApiKey, token = { "hunter2-secret" })
+        .filter(filter!(ApiKey, id == 2))
+        .execute(&conn)
+        .await
+        .unwrap_err();
+    let context = e.statement().unwrap();
+    assert_eq!(context.table, "ApiKey");
+    assert!(context.params.iter().all(|p| p == "<redacted>"));
+    assert!(!e.to_string().contains("hunter2"));
+    assert!(!format!("{e:?}").contains("hunter2"));
+}
+
+#[butane_test]
+async fn save_all_partial_reports_each_item(conn: ConnectionAsync) {
+    let mut foos: Vec<Foo> = (1..=3).map(Foo::new).collect();
+    foos[0].bar = 1;
+    // Violates the unique constraint on bar
+    foos[1].bar = 1;
+    foos[2].bar = 3;
+    let results = butane::save_all_partial_async(&conn, &mut foos).await;
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(results[2].is_ok());
+    assert!(Foo::get(&conn, 3).await.is_ok());
+}
+
+#[butane_test]
+async fn save_all_partial_atomic_in_transaction(mut conn: ConnectionAsync) {
+    let tr = conn.transaction().await.unwrap();
+    let mut foos: Vec<Foo> = (1..=3).map(Foo::new).collect();
+    foos[0].bar = 1;
+    foos[1].bar = 1;
+    foos[2].bar = 3;
+    let results = butane::save_all_partial_atomic_async(&tr, &mut foos).await;
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    // The failure did not abort the transaction
+    assert!(results[2].is_ok());
+    tr.commit().await.unwrap();
+
+    assert!(Foo::get(&conn, 1).await.is_ok());
+    assert!(Foo::try_get(&conn, 2).await.unwrap().is_none());
+    assert!(Foo::get(&conn, 3).await.is_ok());
+}
+
 #[butane_test]
 async fn fkey_same_type(conn: ConnectionAsync) {
     let mut o1 = SelfReferential::new(1);
@@ -366,6 +1479,168 @@ async fn fkey_same_type(conn: ConnectionAsync) {
     assert!(inner.reference.is_none());
 }
 
+#[butane_test]
+async fn fkey_on_delete(conn: ConnectionAsync) {
+    let mut kennel = Kennel::default();
+    kennel.save(&conn).await.unwrap();
+    let mut breeder = Breeder::default();
+    breeder.save(&conn).await.unwrap();
+    let mut dog = Dog {
+        id: AutoPk::uninitialized(),
+        kennel: (&kennel).into(),
+        breeder: Some((&breeder).into()),
+    };
+    dog.save(&conn).await.unwrap();
+
+    breeder.delete(&conn).await.unwrap();
+    let dog = Dog::get(&conn, dog.id).await.unwrap();
+    assert!(dog.breeder.is_none());
+
+    kennel.delete(&conn).await.unwrap();
+    assert!(Dog::try_get(&conn, dog.id).await.unwrap().is_none());
+}
+
+#[butane_test]
+async fn delete_cascade_follows_on_delete(conn: ConnectionAsync) {
+    let mut kennel = Kennel::default();
+    kennel.save(&conn).await.unwrap();
+    let mut breeder = Breeder::default();
+    breeder.save(&conn).await.unwrap();
+    let mut dog = Dog {
+        id: AutoPk::uninitialized(),
+        kennel: (&kennel).into(),
+        breeder: Some((&breeder).into()),
+    };
+    dog.save(&conn).await.unwrap();
+
+    // The dog only stops referring to its breeder
+    let report = breeder.delete_cascade(&conn).await.unwrap();
+    assert_eq!(report.steps()[0].action, CascadeAction::SetNull);
+    assert_eq!(report.steps()[0].table, "Dog");
+    assert_eq!(report.total_rows(), 1);
+    assert!(Dog::get(&conn, dog.id).await.unwrap().breeder.is_none());
+
+    let mut puppy = Puppy {
+        id: AutoPk::uninitialized(),
+        mother: Some((&dog).into()),
+        father: Some((&dog).into()),
+    };
+    puppy.save(&conn).await.unwrap();
+    let err = kennel.delete_cascade(&conn).await.unwrap_err();
+    assert!(matches!(
+        err,
+        butane::Error::Restricted {
+            table: "Dog",
+            referring: "Puppy"
+        }
+    ));
+    assert!(Dog::try_get(&conn, dog.id).await.unwrap().is_some());
+
+    // Objects referring without a foreign key constraint are left alone
+    puppy.mother = None;
+    puppy.save(&conn).await.unwrap();
+    let report = kennel.delete_cascade(&conn).await.unwrap();
+    assert_eq!(report.rows("Dog"), 1);
+    assert!(Dog::try_get(&conn, dog.id).await.unwrap().is_none());
+    assert!(Puppy::try_get(&conn, puppy.id).await.unwrap().is_some());
+}
+
+#[butane_test]
+async fn get_with_optional_fkey(conn: ConnectionAsync) {
+    let mut kennel = Kennel {
+        name: "Barks".to_string(),
+        ..Default::default()
+    };
+    kennel.save(&conn).await.unwrap();
+    let mut breeder = Breeder {
+        name: "Pat".to_string(),
+        ..Default::default()
+    };
+    breeder.save(&conn).await.unwrap();
+    let mut dog = Dog {
+        id: AutoPk::uninitialized(),
+        kennel: (&kennel).into(),
+        breeder: Some((&breeder).into()),
+    };
+    dog.save(&conn).await.unwrap();
+    let mut stray = Dog {
+        id: AutoPk::uninitialized(),
+        kennel: (&kennel).into(),
+        breeder: None,
+    };
+    stray.save(&conn).await.unwrap();
+
+    let dog = Dog::get_with(&conn, dog.id, related!(kennel, breeder))
+        .await
+        .unwrap();
+    assert_eq!(dog.kennel.get().unwrap().name, "Barks");
+    assert_eq!(dog.breeder.unwrap().get().unwrap().name, "Pat");
+
+    let stray = Dog::get_with(&conn, stray.id, related!(kennel, breeder))
+        .await
+        .unwrap();
+    assert_eq!(stray.kennel.get().unwrap().name, "Barks");
+    assert!(stray.breeder.is_none());
+}
+
+#[butane_test]
+async fn fkey_on_delete_without_foreign_keys(conn: ConnectionAsync) {
+    if conn.backend_name() != "sqlite" {
+        // Other backends always enforce foreign keys
+        return;
+    }
+    conn.execute("PRAGMA foreign_keys = OFF;").await.unwrap();
+    let mut kennel = Kennel::default();
+    kennel.save(&conn).await.unwrap();
+    let mut breeder = Breeder::default();
+    breeder.save(&conn).await.unwrap();
+    let mut dog = Dog {
+        id: AutoPk::uninitialized(),
+        kennel: (&kennel).into(),
+        breeder: Some((&breeder).into()),
+    };
+    dog.save(&conn).await.unwrap();
+
+    breeder.delete(&conn).await.unwrap();
+    let dog = Dog::get(&conn, dog.id).await.unwrap();
+    assert!(dog.breeder.is_none());
+
+    kennel.delete(&conn).await.unwrap();
+    assert!(Dog::try_get(&conn, dog.id).await.unwrap().is_none());
+}
+
+#[butane_test]
+async fn collated_field(conn: ConnectionAsync) {
+    for name in ["carol", "Bob", "alice"] {
+        let mut member = Member {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        member.save(&conn).await.unwrap();
+    }
+
+    // Uniqueness ignores case
+    let mut member = Member {
+        name: "BOB".to_string(),
+        ..Default::default()
+    };
+    assert!(member.save(&conn).await.is_err());
+
+    let found = query!(Member, name == "ALICE").load(&conn).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name, "alice");
+
+    let names: Vec<String> = Member::query()
+        .order_asc(colname!(Member, name))
+        .load(&conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|member| member.name)
+        .collect();
+    assert_eq!(names, ["alice", "Bob", "carol"]);
+}
+
 #[butane_test]
 async fn cant_save_unsaved_fkey(conn: ConnectionAsync) {
     let foo = Foo::new(1);
@@ -494,6 +1769,37 @@ async fn save_upserts_by_default(conn: ConnectionAsync) {
     assert_eq!(retrieved.bar, 43);
 }
 
+#[butane_test]
+async fn save_ignore_conflict(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    foo.bar = 42;
+    assert!(foo.save_ignore_conflict(&conn).await.unwrap());
+
+    // Conflicts on the primary key
+    let mut foo = Foo::new(1);
+    foo.bar = 43;
+    assert!(!foo.save_ignore_conflict(&conn).await.unwrap());
+    // Conflicts on the unique bar
+    let mut foo = Foo::new(2);
+    foo.bar = 42;
+    assert!(!foo.save_ignore_conflict(&conn).await.unwrap());
+
+    let retrieved = Foo::get(&conn, 1).await.unwrap();
+    assert_eq!(retrieved.bar, 42);
+    assert!(Foo::try_get(&conn, 2).await.unwrap().is_none());
+
+    let mut baz = Baz::new("hello");
+    assert!(baz.save_ignore_conflict(&conn).await.unwrap());
+    let retrieved = Baz::get(&conn, baz.id).await.unwrap();
+    assert_eq!(retrieved.text, "hello");
+    // Already inserted
+    assert!(!baz.save_ignore_conflict(&conn).await.unwrap());
+
+    let mut ticket = Ticket::default();
+    assert!(ticket.save_ignore_conflict(&conn).await.unwrap());
+    assert_eq!(ticket.priority, 3);
+}
+
 #[butane_test(async)]
 async fn tokio_spawn(conn: ConnectionAsync) {
     // This test exists mostly to make sure it compiles. Verifies that
@@ -504,6 +1810,494 @@ async fn tokio_spawn(conn: ConnectionAsync) {
     });
 }
 
+#[butane_test]
+async fn update_hook(conn: ConnectionAsync) {
+    if conn.backend_name() != "sqlite" {
+        let err = conn.set_update_hook(None).unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(_, _)));
+        return;
+    }
+    let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = changes.clone();
+    conn.set_update_hook(Some(Box::new(move |change: RowChange<'_>| {
+        recorded
+            .lock()
+            .unwrap()
+            .push((change.operation, change.table.to_string(), change.rowid));
+    })))
+    .unwrap();
+
+    let mut baz = Baz::new("hooked");
+    baz.save(&conn).await.unwrap();
+    let rowid = baz.id.unwrap();
+    baz.text = "rehooked".to_string();
+    baz.save(&conn).await.unwrap();
+    baz.delete(&conn).await.unwrap();
+    assert_eq!(
+        *changes.lock().unwrap(),
+        [
+            (RowOperation::Insert, "Baz".to_string(), rowid),
+            (RowOperation::Update, "Baz".to_string(), rowid),
+            (RowOperation::Delete, "Baz".to_string(), rowid),
+        ]
+    );
+
+    conn.set_update_hook(None).unwrap();
+    Baz::new("unhooked").save(&conn).await.unwrap();
+    assert_eq!(changes.lock().unwrap().len(), 3);
+}
+
+#[butane_test]
+async fn custom_sql_function(conn: ConnectionAsync) {
+    use butane::query::{CompareOp, Expr};
+
+    let normalize: butane::db::ScalarFunction = Box::new(|args| match &args[0] {
+        SqlVal::Text(text) => Ok(SqlVal::Text(text.trim().to_lowercase())),
+        _ => Ok(SqlVal::Null),
+    });
+    if conn.backend_name() != "sqlite" {
+        let err = conn.create_function("normalize", 1, normalize).unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(_, _)));
+        return;
+    }
+    conn.create_function("normalize", 1, normalize).unwrap();
+    let mut foo = Foo::new(1);
+    foo.baz = "  Hello ".to_string();
+    foo.save(&conn).await.unwrap();
+    let mut foo = Foo::new(2);
+    foo.bar = 1;
+    foo.baz = "Goodbye".to_string();
+    foo.save(&conn).await.unwrap();
+
+    let normalized = Expr::Func("normalize", vec![Expr::Column("baz")]);
+    let foos = Foo::query()
+        .filter(BoolExpr::Compare(
+            normalized,
+            CompareOp::Eq,
+            Expr::Val("hello".into()),
+        ))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(foos.len(), 1);
+    assert_eq!(foos[0].id, 1);
+
+    // An error returned by the function fails the statement
+    conn.create_function(
+        "refuse",
+        -1,
+        Box::new(|_| Err(butane::Error::Internal("refused".to_string()))),
+    )
+    .unwrap();
+    let err = conn.execute("SELECT refuse(1, 2);").await.unwrap_err();
+    assert!(err.to_string().contains("refused"), "{err}");
+}
+
+#[butane_test(async)]
+async fn listen_notify(conn: ConnectionAsync) {
+    if conn.backend_name() != "pg" {
+        let err = conn.notify("greetings", "hello").await.unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(_, _)));
+        let err = conn.listen("greetings").await.unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(_, _)));
+        return;
+    }
+    let mut notifications = conn.listen("greetings").await.unwrap();
+    let mut other = conn.listen("other").await.unwrap();
+    conn.notify("greetings", "hello").await.unwrap();
+    let timeout = std::time::Duration::from_secs(10);
+    let notification = tokio::time::timeout(timeout, notifications.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(notification.channel, "greetings");
+    assert_eq!(notification.payload, "hello");
+    // Nothing was sent on the other channel
+    let short = std::time::Duration::from_millis(200);
+    assert!(tokio::time::timeout(short, other.recv()).await.is_err());
+}
+
+#[butane_test]
+async fn execute_with_params(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    foo.baz = "what?".to_string();
+    foo.save(&conn).await.unwrap();
+    let mut foo = Foo::new(2);
+    foo.bar = 1;
+    foo.save(&conn).await.unwrap();
+
+    // The question mark in the string literal is not a placeholder
+    let count = conn
+        .execute_params(
+            "UPDATE Foo SET baz = ?, bam = ? WHERE id = ? OR baz = 'what?' -- or?",
+            &[
+                SqlValRef::Text("it's fine"),
+                SqlValRef::Real(2.5),
+                SqlValRef::Int(2),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+    for id in [1, 2] {
+        let foo = Foo::get(&conn, id).await.unwrap();
+        assert_eq!(foo.baz, "it's fine");
+        assert_eq!(foo.bam, 2.5);
+    }
+}
+
+#[butane_test]
+async fn backend_capabilities(conn: ConnectionAsync) {
+    let capabilities = conn.backend().capabilities();
+    assert!(capabilities.ctes);
+    assert!(capabilities.savepoints);
+    let pg = conn.backend_name() == "pg";
+    assert_eq!(capabilities.returning, pg);
+    assert_eq!(capabilities.ilike, pg);
+    assert_eq!(capabilities.concurrent_index, pg);
+    assert_eq!(capabilities.notifications, pg);
+    assert_eq!(capabilities.cursors, pg);
+    assert!(capabilities.auto_pk);
+    assert!(capabilities.native_upsert);
+    assert_eq!(capabilities.drop_constraints, pg);
+    assert_eq!(capabilities.custom_types, pg);
+}
+
+#[butane_test]
+async fn config_parameters(mut conn: ConnectionAsync) {
+    if conn.backend_name() != "pg" {
+        let err = conn
+            .set_config("app.current_tenant", "a", false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(_, _)));
+        return;
+    }
+    assert_eq!(
+        conn.current_config("app.current_tenant").await.unwrap(),
+        None
+    );
+    conn.set_config("app.current_tenant", "a", false)
+        .await
+        .unwrap();
+
+    // A local setting only lasts until the end of the transaction
+    let tr = conn.transaction().await.unwrap();
+    tr.set_config("app.current_tenant", "b", true)
+        .await
+        .unwrap();
+    assert_eq!(
+        tr.current_config("app.current_tenant").await.unwrap(),
+        Some("b".to_string())
+    );
+    tr.commit().await.unwrap();
+    assert_eq!(
+        conn.current_config("app.current_tenant").await.unwrap(),
+        Some("a".to_string())
+    );
+}
+
+#[butane_test]
+async fn schema_introspection(conn: ConnectionAsync) {
+    // Unquoted names are folded to lowercase by Postgres
+    let tables = conn.list_tables().await.unwrap();
+    for table in ["Foo", "Bar", "Ticket"] {
+        assert!(
+            tables.iter().any(|t| t.eq_ignore_ascii_case(table)),
+            "{table} not in {tables:?}"
+        );
+    }
+    assert!(!tables.iter().any(|t| t.eq_ignore_ascii_case("VisibleBaz")));
+
+    let schema = conn.table_schema("Ticket").await.unwrap().unwrap();
+    let names: Vec<String> = schema
+        .columns
+        .iter()
+        .map(|c| c.name.to_lowercase())
+        .collect();
+    assert_eq!(names, ["id", "title", "priority"]);
+    let id = &schema.columns[0];
+    assert!(id.primary_key);
+    assert!(!id.nullable);
+    let priority = &schema.columns[2];
+    assert!(!priority.primary_key);
+    assert_eq!(priority.default.as_deref(), Some("3"));
+    let bigint = match conn.backend_name() {
+        "sqlite" => "INTEGER",
+        _ => "bigint",
+    };
+    assert_eq!(priority.type_name, bigint);
+    assert!(schema.columns[1].default.is_none());
+
+    let schema = conn.table_schema("Bar").await.unwrap().unwrap();
+    assert!(schema.column("name").unwrap().primary_key);
+    assert!(!schema.column("foo").unwrap().primary_key);
+
+    assert_eq!(conn.table_schema("NoSuchTable").await.unwrap(), None);
+
+    // The index enforcing uniqueness of bar, but not that of the primary key
+    let indexes = conn.list_indexes("Foo").await.unwrap();
+    assert_eq!(indexes.len(), 1, "{indexes:?}");
+    assert!(indexes[0].unique);
+    assert_eq!(indexes[0].columns, ["bar"]);
+    assert!(conn.list_indexes("Baz").await.unwrap().is_empty());
+}
+
+#[butane_test(async)]
+async fn model_change_notifications(conn: ConnectionAsync) {
+    if conn.backend_name() != "pg" {
+        return;
+    }
+    let mut notifications = conn.listen("headline_changes").await.unwrap();
+    let mut headline = Headline {
+        id: AutoPk::uninitialized(),
+        text: "Butane adds notifications".to_string(),
+    };
+    headline.save(&conn).await.unwrap();
+    headline.delete(&conn).await.unwrap();
+
+    let timeout = std::time::Duration::from_secs(10);
+    for op in [ChangeOp::Save, ChangeOp::Delete] {
+        let notification = tokio::time::timeout(timeout, notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let payload = ChangePayload::parse(&notification.payload).unwrap();
+        assert_eq!(payload.table, "Headline");
+        assert_eq!(payload.op, op);
+        assert_eq!(payload.pk, headline.id.to_string());
+    }
+}
+
+#[butane_test(async)]
+async fn deadline_bounds_operations(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    butane::with_deadline(std::time::Duration::from_secs(60), async {
+        foo.save(&conn).await?;
+        // Nested deadlines are bounded by the outer one
+        butane::with_deadline(std::time::Duration::from_secs(3600), async {
+            let remaining = butane::deadline::remaining()?.unwrap();
+            assert!(remaining <= std::time::Duration::from_secs(60));
+            Foo::get(&conn, 1).await
+        })
+        .await
+    })
+    .await
+    .unwrap();
+    assert!(butane::deadline::current().is_none());
+
+    let err = butane::with_deadline(std::time::Duration::ZERO, Foo::get(&conn, 1))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::DeadlineExceeded));
+
+    // Waits the database cannot bound, such as a stalled pool checkout,
+    // are cancelled too
+    let start = std::time::Instant::now();
+    let err = butane::with_deadline(std::time::Duration::from_millis(200), async {
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+        Foo::get(&conn, 1).await
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::DeadlineExceeded));
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+}
+
+#[butane_test]
+async fn query_timeout(conn: ConnectionAsync) {
+    let mut foo = Foo::new(1);
+    foo.save(&conn).await.unwrap();
+
+    let found = Foo::query()
+        .with_timeout(std::time::Duration::from_secs(60))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    // The timeout only applies while the query runs
+    assert!(butane::deadline::current().is_none());
+
+    let err = Foo::query()
+        .with_timeout(std::time::Duration::ZERO)
+        .load(&conn)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::DeadlineExceeded));
+    let err = query!(Foo, id == 1)
+        .with_timeout(std::time::Duration::ZERO)
+        .delete(&conn)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::DeadlineExceeded));
+    assert!(Foo::try_get(&conn, 1).await.unwrap().is_some());
+}
+
+#[butane_test(async)]
+async fn deadline_interrupts_statement(conn: ConnectionAsync) {
+    let slow_sql = match conn.backend_name() {
+        "pg" => "SELECT pg_sleep(30);",
+        _ => {
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) \
+             SELECT count(*) FROM c;"
+        }
+    };
+    let start = std::time::Instant::now();
+    let err = butane::with_deadline(
+        std::time::Duration::from_millis(200),
+        conn.execute(slow_sql),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::DeadlineExceeded));
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+    // The connection is still usable afterwards, without the deadline
+    // bounding statements which take longer than it did
+    let legit_sql = match conn.backend_name() {
+        "pg" => "SELECT pg_sleep(0.5);",
+        _ => {
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 3000000) \
+             SELECT count(*) FROM c;"
+        }
+    };
+    conn.execute(legit_sql).await.unwrap();
+    let mut foo = Foo::new(1);
+    foo.save(&conn).await.unwrap();
+
+    // Likewise when the operation is cancelled by the caller before the
+    // deadline, which leaves it no chance to clean up after itself. The
+    // caller does not wait for the statement to be interrupted.
+    let start = std::time::Instant::now();
+    let cancelled = tokio::time::timeout(
+        std::time::Duration::from_millis(100),
+        butane::with_deadline(std::time::Duration::from_secs(2), conn.execute(slow_sql)),
+    )
+    .await;
+    assert!(cancelled.is_err());
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    conn.execute(legit_sql).await.unwrap();
+}
+
+#[model]
+#[derive(Debug, Default)]
+struct Attachment {
+    id: i64,
+    contents: butane::blob::LargeObject,
+}
+
+/// Reads and overwrites `blob`, which holds "hello world".
+async fn stream_hello_world<C>(blob: butane::blob::BlobStream<'_, C>)
+where
+    C: butane::db::ConnectionMethodsAsync + ?Sized,
+{
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    let mut blob = blob.with_chunk_size(4);
+    assert_eq!(blob.len(), 11);
+    let mut data = Vec::new();
+    blob.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, b"hello world");
+
+    // Writes overwrite in place and stop at the end of the blob
+    blob.seek(std::io::SeekFrom::Start(6)).await.unwrap();
+    blob.write_all(b"there").await.unwrap();
+    assert_eq!(blob.write(b"!").await.unwrap(), 0);
+    blob.flush().await.unwrap();
+    blob.rewind().await.unwrap();
+    let mut data = String::new();
+    blob.read_to_string(&mut data).await.unwrap();
+    assert_eq!(data, "hello there");
+}
+
+#[butane_test(async)]
+async fn blob_streaming(conn: ConnectionAsync) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut foo = Foo::new(1);
+    foo.blobbity = b"hello world".to_vec();
+    foo.save(&conn).await.unwrap();
+    let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+
+    if conn.backend_name() == "pg" {
+        // Postgres only streams large objects
+        let err = foo
+            .open_blob(&conn, Foo::fields().blobbity())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(..)));
+
+        let mut attachment = Attachment {
+            id: 1,
+            ..Default::default()
+        };
+        attachment.save(&conn).await.unwrap();
+        let contents = Attachment::fields().contents();
+        let blob = attachment.open_blob(&conn, contents.clone()).await.unwrap();
+        assert!(blob.is_empty());
+        // There is no large object to replace
+        let err = attachment
+            .create_blob(&conn, contents.clone(), 11)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, butane::Error::NoSuchObject));
+
+        attachment.contents = butane::blob::LargeObject::create(&conn).await.unwrap();
+        attachment.save(&conn).await.unwrap();
+        let mut blob = attachment
+            .create_blob(&conn, contents.clone(), 11)
+            .await
+            .unwrap();
+        blob.write_all(b"hello world").await.unwrap();
+        blob.shutdown().await.unwrap();
+        let blob = attachment.open_blob(&conn, contents.clone()).await.unwrap();
+        stream_hello_world(blob).await;
+
+        // Replacing the contents zeroes them
+        let mut blob = attachment
+            .create_blob(&conn, contents.clone(), payload.len() as u64 + 1)
+            .await
+            .unwrap();
+        blob.write_all(&payload).await.unwrap();
+        blob.shutdown().await.unwrap();
+        let attachment = Attachment::get(&conn, 1).await.unwrap();
+        let mut data = Vec::new();
+        let mut blob = attachment.open_blob(&conn, contents.clone()).await.unwrap();
+        blob.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data[..payload.len()], payload);
+        assert_eq!(data[payload.len()..], [0]);
+
+        let unsaved = Attachment {
+            id: 2,
+            ..Default::default()
+        };
+        let err = unsaved.open_blob(&conn, contents).await.unwrap_err();
+        assert!(matches!(err, butane::Error::NoSuchObject));
+    } else {
+        let blob = foo
+            .open_blob(&conn, Foo::fields().blobbity())
+            .await
+            .unwrap();
+        stream_hello_world(blob).await;
+
+        let mut blob = foo
+            .create_blob(&conn, Foo::fields().blobbity(), payload.len() as u64)
+            .await
+            .unwrap();
+        blob.write_all(&payload).await.unwrap();
+        blob.shutdown().await.unwrap();
+        let foo = Foo::get(&conn, 1).await.unwrap();
+        assert_eq!(foo.blobbity, payload);
+
+        let unsaved = Foo::new(2);
+        let err = unsaved
+            .open_blob(&conn, Foo::fields().blobbity())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, butane::Error::NoSuchObject));
+    }
+}
+
 #[model]
 struct TypeVariantsTest {
     id: i64,
@@ -553,3 +2347,352 @@ pub struct r#type {
     let retrieved = r#type::get(&conn, "1").await.unwrap();
     assert_eq!(retrieved.foo, "test");
 }
+
+#[butane_test]
+async fn reserved_word_names(conn: ConnectionAsync) {
+    #[model]
+    #[derive(Debug, Default, PartialEq)]
+    pub struct Order {
+        id: i64,
+        #[unique]
+        group: String,
+        limit: i32,
+    }
+
+    let mut order = Order {
+        id: 1,
+        group: "a".to_string(),
+        limit: 10,
+    };
+    order.save(&conn).await.unwrap();
+    order.limit = 20;
+    order.save(&conn).await.unwrap();
+    Order {
+        id: 2,
+        group: "b".to_string(),
+        limit: 5,
+    }
+    .save(&conn)
+    .await
+    .unwrap();
+
+    let found = Order::query()
+        .filter(filter!(Order, group == "a"))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found, vec![order]);
+    let limits: Vec<i32> = Order::query()
+        .filter(filter!(Order, limit > 0))
+        .order_desc("group")
+        .load(&conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|o| o.limit)
+        .collect();
+    assert_eq!(limits, vec![5, 20]);
+
+    let deleted = Order::query()
+        .filter(filter!(Order, group == "b"))
+        .delete(&conn)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    assert!(conn.has_table("Order").await.unwrap());
+}
+
+#[model]
+#[butane(audited)]
+#[derive(Debug, Default)]
+struct Ledger {
+    id: i64,
+    owner: String,
+    balance: i64,
+}
+
+#[butane_test]
+async fn audited_changes_are_recorded(conn: ConnectionAsync) {
+    let mut ledger = Ledger {
+        id: 1,
+        owner: "alice".to_string(),
+        balance: 10,
+    };
+    ledger.save(&conn).await.unwrap();
+    ledger.balance = 20;
+    ledger.save(&conn).await.unwrap();
+    ledger.delete(&conn).await.unwrap();
+
+    let history = Ledger::history(&conn, 1).await.unwrap();
+    let ops: Vec<AuditOp> = history.iter().map(|entry| entry.op).collect();
+    assert_eq!(ops, [AuditOp::Insert, AuditOp::Update, AuditOp::Delete]);
+    assert!(history[0].old_values.is_none());
+    let balance =
+        |values: &Option<butane::audit::AuditValues>| values.as_ref().unwrap()["balance"].clone();
+    assert_eq!(balance(&history[0].new_values), SqlVal::BigInt(10));
+    assert_eq!(balance(&history[1].old_values), SqlVal::BigInt(10));
+    assert_eq!(balance(&history[1].new_values), SqlVal::BigInt(20));
+    assert_eq!(balance(&history[2].old_values), SqlVal::BigInt(20));
+    assert!(history[2].new_values.is_none());
+    assert!(history.iter().all(|entry| entry.actor.is_none()));
+    assert!(history[0].changed_at <= history[2].changed_at);
+
+    assert!(Ledger::history(&conn, 2).await.unwrap().is_empty());
+    let not_audited = Foo::history(&conn, 1).await;
+    assert!(matches!(not_audited, Err(butane::Error::NotAudited("Foo"))));
+}
+
+#[butane_test(async)]
+async fn audited_changes_record_actor(conn: ConnectionAsync) {
+    butane::audit::with_actor("bob", async {
+        Ledger {
+            id: 1,
+            owner: "bob".to_string(),
+            balance: 5,
+        }
+        .save(&conn)
+        .await
+        .unwrap();
+    })
+    .await;
+    let history = Ledger::history(&conn, 1).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].actor.as_deref(), Some("bob"));
+}
+
+#[butane_test]
+async fn audited_state_as_of(conn: ConnectionAsync) {
+    // Entries are timestamped to the millisecond
+    let tick = || {
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let now = std::time::SystemTime::now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        now
+    };
+    let before = tick();
+    let mut ledger = Ledger {
+        id: 1,
+        owner: "alice".to_string(),
+        balance: 10,
+    };
+    ledger.save(&conn).await.unwrap();
+    let created = tick();
+    ledger.balance = 20;
+    ledger.save(&conn).await.unwrap();
+    Ledger {
+        id: 2,
+        owner: "bob".to_string(),
+        balance: 5,
+    }
+    .save(&conn)
+    .await
+    .unwrap();
+    let updated = tick();
+    ledger.delete(&conn).await.unwrap();
+    let deleted = tick();
+
+    assert!(Ledger::as_of(&conn, 1, before).await.unwrap().is_none());
+    let past = Ledger::as_of(&conn, 1, created).await.unwrap().unwrap();
+    assert_eq!(past.balance, 10);
+    assert_eq!(past.owner, "alice");
+    let past = Ledger::as_of(&conn, 1, updated).await.unwrap().unwrap();
+    assert_eq!(past.balance, 20);
+    assert!(Ledger::as_of(&conn, 1, deleted).await.unwrap().is_none());
+
+    let all = Ledger::all_as_of(&conn, before).await.unwrap();
+    assert!(all.is_empty());
+    let all = Ledger::all_as_of(&conn, updated).await.unwrap();
+    let balances: Vec<i64> = all.iter().map(|ledger| ledger.balance).collect();
+    assert_eq!(balances, [20, 5]);
+    let all = Ledger::all_as_of(&conn, deleted).await.unwrap();
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].id, 2);
+}
+
+/// Reversible stand-in for a cipher, which must never be used for real
+/// data. The associated data is checked by prefixing it to the plaintext.
+struct XorKeys;
+impl KeyProvider for XorKeys {
+    fn current_key_id(&self) -> &str {
+        "test-key"
+    }
+    fn encrypt(
+        &self,
+        key_id: &str,
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> butane::Result<(Vec<u8>, Vec<u8>)> {
+        let nonce = vec![0x5a; 12];
+        let bound = [aad, plaintext].concat();
+        Ok((nonce.clone(), xor(key_id, &nonce, &bound)?))
+    }
+    fn decrypt(
+        &self,
+        key_id: &str,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> butane::Result<Vec<u8>> {
+        let bound = xor(key_id, nonce, ciphertext)?;
+        match bound.strip_prefix(aad) {
+            Some(plaintext) => Ok(plaintext.to_vec()),
+            None => Err(butane::Error::Encryption(
+                "associated data does not match".to_string(),
+            )),
+        }
+    }
+}
+
+fn xor(key_id: &str, nonce: &[u8], data: &[u8]) -> butane::Result<Vec<u8>> {
+    if key_id != "test-key" {
+        return Err(butane::Error::Encryption(format!("unknown key {key_id}")));
+    }
+    Ok(data
+        .iter()
+        .zip(nonce.iter().cycle())
+        .map(|(b, n)| b ^ n)
+        .collect())
+}
+
+#[model]
+#[derive(Debug)]
+struct Patient {
+    id: i64,
+    name: Encrypted<String>,
+    scan: Encrypted<Vec<u8>>,
+    notes: Option<Encrypted<String>>,
+}
+
+#[butane_test]
+async fn encrypted_fields(mut conn: ConnectionAsync) {
+    let mut patient = Patient {
+        id: 1,
+        name: Encrypted::new("Alice".to_string()),
+        scan: Encrypted::new(vec![1, 2, 3]),
+        notes: None,
+    };
+    // Values cannot be encrypted without a key provider
+    let saved = patient.save(&conn).await;
+    assert!(matches!(saved, Err(butane::Error::Encryption(_))));
+    conn.set_key_provider(XorKeys);
+    patient.save(&conn).await.unwrap();
+    let SqlVal::Blob(stored) = patient.name.to_sql() else {
+        panic!("encrypted values are stored as blobs");
+    };
+    assert!(!stored.windows(5).any(|w| w == b"Alice"));
+    assert_eq!(patient.name.key_id(), Some("test-key"));
+    assert_eq!(format!("{:?}", patient.name), "Encrypted(..)");
+
+    let loaded = Patient::get(&conn, 1).await.unwrap();
+    assert_eq!(*loaded.name, "Alice");
+    assert_eq!(*loaded.scan, vec![1, 2, 3]);
+    assert!(loaded.notes.is_none());
+
+    patient.notes = Some(Encrypted::new("allergic".to_string()));
+    patient.name.set("Alicia".to_string());
+    patient.save(&conn).await.unwrap();
+    let loaded = Patient::get(&conn, 1).await.unwrap();
+    assert_eq!(loaded.name.as_str(), "Alicia");
+    assert_eq!(loaded.notes.unwrap().into_inner(), "allergic");
+
+    // A value copied to another row fails to decrypt, as it is bound
+    // to the primary key of its own
+    let mut other = Patient {
+        id: 2,
+        name: Encrypted::new("Bob".to_string()),
+        scan: Encrypted::new(vec![4]),
+        notes: None,
+    };
+    other.save(&conn).await.unwrap();
+    conn.execute_params(
+        "UPDATE Patient SET name = (SELECT name FROM Patient WHERE id = 1) WHERE id = 2",
+        &[],
+    )
+    .await
+    .unwrap();
+    let swapped = Patient::get(&conn, 2).await;
+    assert!(matches!(swapped, Err(butane::Error::Encryption(_))));
+
+    // Transactions decrypt with the key provider of their connection
+    let tx = conn.transaction().await.unwrap();
+    let loaded = Patient::get(&tx, 1).await.unwrap();
+    assert_eq!(*loaded.name, "Alicia");
+    tx.commit().await.unwrap();
+}
+
+#[model]
+#[derive(Debug, Default)]
+struct Signup {
+    id: i64,
+    #[butane(max_length = 8, regex = "^[a-z]+$")]
+    username: String,
+    #[butane(min = 13, max = 130)]
+    age: i32,
+    #[butane(min = 0.0)]
+    rating: Option<f64>,
+}
+
+#[butane_test]
+async fn validation_attributes(conn: ConnectionAsync) {
+    let mut signup = Signup {
+        id: 1,
+        username: "alice".to_string(),
+        age: 30,
+        ..Default::default()
+    };
+    signup.save(&conn).await.unwrap();
+
+    signup.username = "alexandrina".to_string();
+    let err = signup.save(&conn).await.unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            butane::Error::Validation {
+                field: "username",
+                ..
+            }
+        ),
+        "{err}"
+    );
+    assert_eq!(
+        err.to_string(),
+        "Invalid value for username: must be at most 8 characters long"
+    );
+    signup.username = "Alice".to_string();
+    let err = signup.save(&conn).await.unwrap_err();
+    assert!(matches!(
+        err,
+        butane::Error::Validation {
+            field: "username",
+            ..
+        }
+    ));
+    signup.username = "alice".to_string();
+
+    signup.age = 12;
+    let err = signup.save(&conn).await.unwrap_err();
+    assert!(matches!(
+        err,
+        butane::Error::Validation { field: "age", .. }
+    ));
+    signup.age = 131;
+    assert!(signup.save(&conn).await.is_err());
+    signup.age = 13;
+
+    signup.rating = Some(-1.0);
+    let err = signup.save_ignore_conflict(&conn).await.unwrap_err();
+    assert!(matches!(
+        err,
+        butane::Error::Validation {
+            field: "rating",
+            ..
+        }
+    ));
+    signup.rating = Some(4.5);
+    signup.save(&conn).await.unwrap();
+
+    // Invalid values were never written
+    let loaded = Signup::get(&conn, 1).await.unwrap();
+    assert_eq!(loaded.username, "alice");
+    assert_eq!(loaded.age, 13);
+}
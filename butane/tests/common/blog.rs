@@ -30,6 +30,7 @@ pub fn new(id: i64, name: &str) -> Self {
 #[model]
 #[derive(Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "fake", derive(Dummy))]
+#[butane(patch)]
 pub struct Post {
     pub id: i64,
     pub title: String,
@@ -45,6 +46,7 @@ pub struct Post {
 #[model]
 #[derive(Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "fake", derive(Dummy))]
+#[butane(patch)]
 pub struct Post {
     pub id: i64,
     pub title: String,
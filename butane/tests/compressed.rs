@@ -0,0 +1,60 @@
+use butane::compression::Codec;
+use butane::db::{Connection, ConnectionAsync};
+use butane::{model, Compressed, SqlVal, ToSql};
+use butane_test_helper::*;
+use butane_test_macros::butane_test;
+
+#[model]
+#[derive(Debug, PartialEq)]
+struct RequestLog {
+    id: i64,
+    body: Compressed<String>,
+    payload: Compressed<Vec<u8>>,
+    response: Option<Compressed<String>>,
+}
+
+#[butane_test]
+async fn compressed_roundtrip(conn: ConnectionAsync) {
+    let body = "GET /index.html HTTP/1.1\r\n".repeat(100);
+    let mut log = RequestLog {
+        id: 1,
+        body: Compressed::new(body.clone()),
+        payload: Compressed::new(vec![0; 4096]),
+        response: None,
+    };
+    log.save(&conn).await.unwrap();
+    assert_eq!(log.body.codec(), Codec::Zstd);
+    assert!(log.body.stored_len() < body.len() / 10);
+
+    let loaded = RequestLog::get(&conn, 1).await.unwrap();
+    assert_eq!(loaded, log);
+    assert_eq!(*loaded.body, body);
+    assert_eq!(loaded.payload.len(), 4096);
+
+    log.response = Some("ok".to_string().into());
+    log.body.set("short".to_string());
+    log.save(&conn).await.unwrap();
+    let loaded = RequestLog::get(&conn, 1).await.unwrap();
+    // Values too small to benefit are stored as they are
+    assert_eq!(loaded.body.codec(), Codec::None);
+    assert_eq!(loaded.body.as_str(), "short");
+    assert_eq!(loaded.response.unwrap().into_inner(), "ok");
+}
+
+#[test]
+fn compressed_codec_is_recorded() {
+    let value = Compressed::new("x".repeat(1000));
+    let SqlVal::Blob(stored) = value.to_sql() else {
+        panic!("compressed values are stored as blobs");
+    };
+    assert_eq!(stored[0], 1);
+
+    let mut unknown = stored.clone();
+    unknown[0] = 200;
+    assert!(matches!(
+        <Compressed<String> as butane::FromSql>::from_sql(SqlVal::Blob(unknown)),
+        Err(butane::Error::Compression(_))
+    ));
+    let restored: Compressed<String> = butane::FromSql::from_sql(SqlVal::Blob(stored)).unwrap();
+    assert_eq!(restored, value);
+}
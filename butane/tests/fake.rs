@@ -1,4 +1,5 @@
 use butane::db::{Connection, ConnectionAsync};
+use butane::seed::Seeder;
 use butane::{find, find_async, ForeignKey};
 use butane_test_helper::*;
 use butane_test_macros::butane_test;
@@ -31,3 +32,32 @@ async fn fake_blog_post(conn: ConnectionAsync) {
     assert_eq!(post_from_db.title, post.title);
     assert_eq!(post_from_db.tags.load(&conn).await.unwrap().count(), 3);
 }
+
+#[butane_test]
+async fn seeded_blog(conn: ConnectionAsync) {
+    use butane::seed::SeederOpsAsync;
+
+    // The same seed generates the same objects
+    let blogs: Vec<Blog> = Seeder::new(7).insert(&conn, 3).await.unwrap();
+    Blog::query().delete(&conn).await.unwrap();
+    let mut seeder = Seeder::new(7);
+    let reseeded: Vec<Blog> = seeder.insert(&conn, 3).await.unwrap();
+    assert_eq!(reseeded, blogs);
+
+    let tags: Vec<Tag> = seeder.insert(&conn, 5).await.unwrap();
+    let posts: Vec<Post> = seeder.insert(&conn, 10).await.unwrap();
+    assert_eq!(seeder.count::<Post>(), 10);
+    assert_eq!(Post::query().load(&conn).await.unwrap().len(), 10);
+
+    // Relationships refer to the objects seeded before
+    let blog_ids: Vec<i64> = blogs.iter().map(|blog| blog.id).collect();
+    let tag_names: Vec<&str> = tags.iter().map(|tag| tag.tag.as_str()).collect();
+    for post in &posts {
+        assert!(blog_ids.contains(&post.blog.pk()));
+        let post_tags = post.tags.load(&conn).await.unwrap();
+        assert!(post_tags.count() <= 3);
+        for tag in post.tags.load(&conn).await.unwrap() {
+            assert!(tag_names.contains(&tag.tag.as_str()));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-#![allow(clippy::disallowed_names)]
+#![allow(clippy::disallowed_names, clippy::field_reassign_with_default)]
 
 use std::collections::{BTreeMap, HashMap};
 
@@ -6,7 +6,7 @@
 use butane::{
     butane_type,
     db::{Connection, ConnectionAsync},
-    FieldType,
+    FieldType, ForeignKey, Many,
 };
 use butane_test_helper::*;
 use butane_test_macros::butane_test;
@@ -313,3 +313,108 @@ pub struct StructWithReservedWordMemberJson {
         .unwrap();
     assert_eq!(retrieved.r#type, expected);
 }
+
+#[model]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct JsonAuthor {
+    id: i64,
+    name: String,
+}
+
+#[model]
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonArticle {
+    id: i64,
+    #[serde(with = "butane::fkey::nested")]
+    author: ForeignKey<JsonAuthor>,
+    editor: ForeignKey<JsonAuthor>,
+}
+
+#[butane_test]
+async fn foreign_key_serde(conn: ConnectionAsync) {
+    let mut author = JsonAuthor {
+        id: 1,
+        name: "Ann".to_string(),
+    };
+    author.save(&conn).await.unwrap();
+    let mut article = JsonArticle {
+        id: 2,
+        author: ForeignKey::from_pk(1),
+        editor: ForeignKey::from_pk(1),
+    };
+    article.save(&conn).await.unwrap();
+
+    // Until loaded, the nested representation is the primary key
+    let article = JsonArticle::get(&conn, 2).await.unwrap();
+    assert_eq!(
+        serde_json::to_value(&article).unwrap(),
+        serde_json::json!({"id": 2, "author": 1, "editor": 1})
+    );
+
+    article.author.load(&conn).await.unwrap();
+    article.editor.load(&conn).await.unwrap();
+    let json = serde_json::to_value(&article).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"id": 2, "author": {"id": 1, "name": "Ann"}, "editor": 1})
+    );
+
+    // Both forms are accepted when deserializing
+    let article: JsonArticle = serde_json::from_value(json).unwrap();
+    assert_eq!(article.author.get().unwrap(), &author);
+    let article: JsonArticle =
+        serde_json::from_value(serde_json::json!({"id": 2, "author": 1, "editor": 1})).unwrap();
+    assert!(article.author.get().is_err());
+    assert_eq!(article.author.pk(), 1);
+}
+
+#[model]
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonBook {
+    id: i64,
+    #[serde(with = "butane::many::pks")]
+    authors: Many<JsonAuthor>,
+    #[serde(with = "butane::many::nested")]
+    reviewers: Many<JsonAuthor>,
+}
+
+#[butane_test]
+async fn many_serde(conn: ConnectionAsync) {
+    let mut ann = JsonAuthor {
+        id: 1,
+        name: "Ann".to_string(),
+    };
+    ann.save(&conn).await.unwrap();
+    let mut bob = JsonAuthor {
+        id: 2,
+        name: "Bob".to_string(),
+    };
+    bob.save(&conn).await.unwrap();
+
+    // Values are added when a deserialized Many is saved
+    let mut book: JsonBook = serde_json::from_value(serde_json::json!({
+        "id": 3,
+        "authors": [1, 2],
+        "reviewers": [{"id": 2, "name": "Bob"}],
+    }))
+    .unwrap();
+    book.save(&conn).await.unwrap();
+
+    let book = JsonBook::get(&conn, 3).await.unwrap();
+    assert!(serde_json::to_value(&book).is_err());
+    let _ = book.authors.load(&conn).await.unwrap();
+    let _ = book.reviewers.load(&conn).await.unwrap();
+    let mut json = serde_json::to_value(&book).unwrap();
+    json["authors"]
+        .as_array_mut()
+        .unwrap()
+        .sort_by_key(|v| v.as_i64());
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "id": 3,
+            "authors": [1, 2],
+            "reviewers": [{"id": 2, "name": "Bob"}],
+        })
+    );
+}
@@ -1,4 +1,10 @@
-use butane::{model, query::OrderDirection, AutoPk, Many};
+#![allow(clippy::disallowed_names, clippy::field_reassign_with_default)]
+
+use butane::db::{Column, ConnectionMethods, ConnectionMethodsAsync};
+use butane::{
+    model, query, query::OrderDirection, related, Association, AutoPk, ForeignKey, Many,
+    ManyThrough, OrderedMany, SqlType,
+};
 use butane_test_helper::*;
 use butane_test_macros::butane_test;
 
@@ -38,6 +44,70 @@ fn new() -> Self {
     }
 }
 
+#[model]
+struct CustomManyTable {
+    id: AutoPk<i64>,
+    #[butane(many_table = "custom_tags")]
+    tags: Many<Tag>,
+}
+
+#[model]
+#[derive(Default)]
+struct DuplicateTags {
+    id: AutoPk<i64>,
+    #[butane(allow_duplicates)]
+    tags: Many<Tag>,
+}
+
+#[model]
+#[derive(Default)]
+struct Playlist {
+    id: AutoPk<i64>,
+    items: OrderedMany<AutoItem>,
+}
+
+#[model]
+#[derive(Default)]
+struct Club {
+    id: AutoPk<i64>,
+    name: String,
+    members: ManyThrough<Person, Membership>,
+}
+
+#[model]
+#[derive(Default)]
+struct Person {
+    id: AutoPk<i64>,
+    name: String,
+    clubs: ManyThrough<Club, Membership>,
+}
+
+#[model]
+struct Membership {
+    id: AutoPk<i64>,
+    club: ForeignKey<Club>,
+    person: ForeignKey<Person>,
+    role: String,
+}
+impl Membership {
+    fn new(club: &Club, person: &Person, role: &str) -> Self {
+        Membership {
+            id: AutoPk::uninitialized(),
+            club: club.into(),
+            person: person.into(),
+            role: role.to_string(),
+        }
+    }
+}
+impl Association<Person> for Membership {
+    const OWNER_COLUMN: &'static str = "club";
+    const TARGET_COLUMN: &'static str = "person";
+}
+impl Association<Club> for Membership {
+    const OWNER_COLUMN: &'static str = "person";
+    const TARGET_COLUMN: &'static str = "club";
+}
+
 #[model]
 struct AutoItem {
     id: AutoPk<i64>,
@@ -66,7 +136,7 @@ pub struct StructWithReservedWordMemberMany {
         .unwrap();
 
     assert_eq!(retrieved.r#type.load(&conn).await.unwrap().count(), 1);
-    let saved_tags: Vec<&Tag> = retrieved.r#type.get().unwrap().into_iter().collect();
+    let saved_tags: Vec<&Tag> = retrieved.r#type.get().unwrap().collect();
     assert_eq!(saved_tags.len(), 1);
     assert_eq!(saved_tags[0].tag, "reserved_rust_word");
 }
@@ -103,7 +173,7 @@ pub struct StructWithManyReservedWord {
         .unwrap();
 
     assert_eq!(retrieved.field.load(&conn).await.unwrap().count(), 1);
-    let saved_items: Vec<&r#struct> = retrieved.field.get().unwrap().into_iter().collect();
+    let saved_items: Vec<&r#struct> = retrieved.field.get().unwrap().collect();
     assert_eq!(saved_items.len(), 1);
     assert_eq!(saved_items[0].name, "test_item");
 }
@@ -127,7 +197,7 @@ async fn load_sorted_from_many(conn: ConnectionAsync) {
 
     post.tags.set(&conn, tags).await.unwrap();
 
-    let saved_tags: Vec<&Tag> = post.tags.get().unwrap().into_iter().collect();
+    let saved_tags: Vec<&Tag> = post.tags.get().unwrap().collect();
     assert_eq!(saved_tags.len(), 3);
     assert_eq!(saved_tags[0].tag, "fast");
     assert_eq!(saved_tags[1].tag, "cat");
@@ -156,6 +226,50 @@ async fn load_sorted_from_many(conn: ConnectionAsync) {
     assert!(tag_iter.next().is_none());
 }
 
+#[butane_test]
+async fn set_many_to_different_values(conn: ConnectionAsync) {
+    let mut cats_blog = Blog::new(1, "Cats");
+    cats_blog.save(&conn).await.unwrap();
+    let mut post = Post::new(
+        1,
+        "The Cheetah",
+        "This post is about a fast cat.",
+        &cats_blog,
+    );
+    post.save(&conn).await.unwrap();
+    let mut other_post = Post::new(2, "The Tiger", "This post is about a big cat.", &cats_blog);
+    other_post.save(&conn).await.unwrap();
+
+    create_tag(&conn, "fast").await;
+    create_tag(&conn, "cat").await;
+    create_tag(&conn, "striped").await;
+    let tags = |names: &[&str]| names.iter().map(|name| Tag::new(name)).collect();
+    other_post
+        .tags
+        .set(&conn, tags(&["fast", "striped"]))
+        .await
+        .unwrap();
+    post.tags.set(&conn, tags(&["fast", "cat"])).await.unwrap();
+    // Duplicates and unsaved changes are replaced too
+    post.tags.remove(&Tag::new("cat"));
+    post.tags
+        .set(&conn, tags(&["cat", "striped", "cat"]))
+        .await
+        .unwrap();
+
+    let post2 = Post::get(&conn, post.id).await.unwrap();
+    let tags: Vec<String> = post2
+        .tags
+        .load_ordered(&conn, OrderDirection::Ascending)
+        .await
+        .unwrap()
+        .map(|tag| tag.tag.clone())
+        .collect();
+    assert_eq!(tags, vec!["cat", "striped"]);
+    let other_post2 = Post::get(&conn, other_post.id).await.unwrap();
+    assert_eq!(other_post2.tags.load(&conn).await.unwrap().count(), 2);
+}
+
 #[butane_test]
 async fn remove_one_from_many(conn: ConnectionAsync) {
     let mut cats_blog = Blog::new(1, "Cats");
@@ -213,6 +327,56 @@ async fn remove_multiple_from_many(conn: ConnectionAsync) {
     assert_eq!(post2.tags.load(&conn).await.unwrap().count(), 2);
 }
 
+#[butane_test]
+async fn add_and_remove_all_in_many(conn: ConnectionAsync) {
+    let mut cats_blog = Blog::new(1, "Cats");
+    cats_blog.save(&conn).await.unwrap();
+    let mut post = Post::new(
+        1,
+        "The Cheetah",
+        "This post is about a fast cat.",
+        &cats_blog,
+    );
+    let mut other_post = Post::new(2, "The Tiger", "This post is about a big cat.", &cats_blog);
+    let tag_fast = create_tag(&conn, "fast").await;
+    let tag_cat = create_tag(&conn, "cat").await;
+    let tag_european = create_tag(&conn, "european").await;
+    let tag_striped = create_tag(&conn, "striped").await;
+
+    post.tags
+        .add_all(&[&tag_fast, &tag_cat, &tag_european, &tag_striped])
+        .unwrap();
+    post.save(&conn).await.unwrap();
+    other_post.tags.add_all(&[&tag_cat, &tag_striped]).unwrap();
+    other_post.save(&conn).await.unwrap();
+
+    post.tags.remove_all(&[&tag_european, &tag_striped]);
+    post.save(&conn).await.unwrap();
+
+    let post2 = Post::get(&conn, post.id).await.unwrap();
+    let mut tags: Vec<String> = post2
+        .tags
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|tag| tag.tag.clone())
+        .collect();
+    tags.sort();
+    assert_eq!(tags, vec!["cat", "fast"]);
+    // Only the rows of the post saved are removed
+    let other_post2 = Post::get(&conn, other_post.id).await.unwrap();
+    assert_eq!(other_post2.tags.load(&conn).await.unwrap().count(), 2);
+
+    // No values are added if any is unsaved
+    let unsaved_item = AutoItem {
+        id: AutoPk::uninitialized(),
+        val: "shiny".to_string(),
+    };
+    let mut obj = AutoPkWithMany::new();
+    let err = obj.items.add_all(&[&unsaved_item]).unwrap_err();
+    assert!(matches!(err, butane::Error::ValueNotSaved));
+}
+
 #[butane_test]
 async fn delete_all_from_many(conn: ConnectionAsync) {
     let mut cats_blog = Blog::new(1, "Cats");
@@ -284,3 +448,552 @@ async fn can_add_to_many_with_custom_table_name(conn: ConnectionAsync) {
     let tags = obj.tags.load(&conn).await.unwrap();
     assert_eq!(tags.count(), 2);
 }
+
+#[butane_test]
+async fn add_to_many_skips_values_already_added(conn: ConnectionAsync) {
+    let blue = create_tag(&conn, "blue").await;
+    let mut obj = AutoPkWithMany::new();
+    obj.tags.add(&blue).unwrap();
+    obj.tags.add(&blue).unwrap();
+    obj.tags.add_all(&[&blue]).unwrap();
+    obj.save(&conn).await.unwrap();
+
+    let mut obj = AutoPkWithMany::get(&conn, obj.id).await.unwrap();
+    assert_eq!(obj.tags.load(&conn).await.unwrap().count(), 1);
+    // Nor is a value among those loaded added again
+    obj.tags.add(&blue).unwrap();
+    obj.save(&conn).await.unwrap();
+    let obj = AutoPkWithMany::get(&conn, obj.id).await.unwrap();
+    assert_eq!(obj.tags.load(&conn).await.unwrap().count(), 1);
+
+    // The join table is indexed for lookups from either side
+    let indexes = conn.list_indexes("AutoPkWithMany_tags_Many").await.unwrap();
+    let mut indexed: Vec<(Vec<String>, bool)> = indexes
+        .into_iter()
+        .map(|index| (index.columns, index.unique))
+        .collect();
+    indexed.sort();
+    assert_eq!(
+        indexed,
+        [
+            (vec!["has".to_string()], false),
+            (vec!["owner".to_string(), "has".to_string()], true)
+        ]
+    );
+}
+
+#[butane_test]
+async fn save_skips_values_already_related(conn: ConnectionAsync) {
+    let blue = create_tag(&conn, "blue").await;
+    let mut obj = AutoPkWithMany::new();
+    obj.save(&conn).await.unwrap();
+
+    // Two copies of the object each relate the same value without
+    // knowing about the other
+    let mut other = AutoPkWithMany::get(&conn, obj.id).await.unwrap();
+    obj.tags.add(&blue).unwrap();
+    obj.save(&conn).await.unwrap();
+    other.tags.add(&blue).unwrap();
+    other.save(&conn).await.unwrap();
+
+    let obj = AutoPkWithMany::get(&conn, obj.id).await.unwrap();
+    assert_eq!(obj.tags.load(&conn).await.unwrap().count(), 1);
+}
+
+#[butane_test]
+async fn many_allowing_duplicates(conn: ConnectionAsync) {
+    let blue = create_tag(&conn, "blue").await;
+    let mut obj = DuplicateTags::default();
+    obj.tags.add(&blue).unwrap();
+    obj.tags.add(&blue).unwrap();
+    obj.save(&conn).await.unwrap();
+
+    let table = "DuplicateTags_tags_Many";
+    let has = [Column::new("has", SqlType::Text)];
+    let (_, rows) = conn
+        .query_counted(table, &has, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(rows, 2);
+
+    // Nor is a value among those loaded skipped
+    let mut obj = DuplicateTags::get(&conn, obj.id).await.unwrap();
+    assert_eq!(obj.tags.load(&conn).await.unwrap().count(), 1);
+    obj.tags.add(&blue).unwrap();
+    obj.save(&conn).await.unwrap();
+    let (_, rows) = conn
+        .query_counted(table, &has, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(rows, 3);
+
+    let indexes = conn.list_indexes(table).await.unwrap();
+    assert!(indexes.iter().all(|index| !index.unique));
+}
+
+#[butane_test]
+async fn contains_in_many(conn: ConnectionAsync) {
+    let mut cats_blog = Blog::new(1, "Cats");
+    cats_blog.save(&conn).await.unwrap();
+    let mut post = Post::new(
+        1,
+        "The Cheetah",
+        "This post is about a fast cat.",
+        &cats_blog,
+    );
+    let tag_fast = create_tag(&conn, "fast").await;
+    let tag_cat = create_tag(&conn, "cat").await;
+    let tag_european = create_tag(&conn, "european").await;
+    let tag_striped = create_tag(&conn, "striped").await;
+
+    post.tags.add(&tag_fast).unwrap();
+    post.tags.add(&tag_cat).unwrap();
+    post.save(&conn).await.unwrap();
+
+    let mut post2 = Post::get(&conn, post.id).await.unwrap();
+    assert!(post2.tags.contains(&conn, &tag_cat).await.unwrap());
+    assert!(!post2.tags.contains(&conn, &tag_european).await.unwrap());
+    // Membership checks do not load the relationship
+    assert!(post2.tags.get().is_err());
+
+    let pks = ["european".to_string(), "fast".to_string()];
+    assert!(post2.tags.contains_any(&conn, &pks).await.unwrap());
+    assert!(!post2.tags.contains_all(&conn, &pks).await.unwrap());
+    let pks = ["european".to_string(), "striped".to_string()];
+    assert!(!post2.tags.contains_any(&conn, &pks).await.unwrap());
+    let pks = ["cat".to_string(), "fast".to_string(), "cat".to_string()];
+    assert!(post2.tags.contains_all(&conn, &pks).await.unwrap());
+    assert!(!post2.tags.contains_any(&conn, &[]).await.unwrap());
+    assert!(post2.tags.contains_all(&conn, &[]).await.unwrap());
+
+    // Unsaved additions are included
+    post2.tags.add(&tag_striped).unwrap();
+    assert!(post2.tags.contains(&conn, &tag_striped).await.unwrap());
+    let pks = ["fast".to_string(), "striped".to_string()];
+    assert!(post2.tags.contains_all(&conn, &pks).await.unwrap());
+
+    // Unsaved removals are excluded
+    post2.tags.remove(&tag_fast);
+    assert!(!post2.tags.contains(&conn, &tag_fast).await.unwrap());
+    let pks = ["fast".to_string(), "european".to_string()];
+    assert!(!post2.tags.contains_any(&conn, &pks).await.unwrap());
+    let pks = ["fast".to_string(), "cat".to_string()];
+    assert!(post2.tags.contains_any(&conn, &pks).await.unwrap());
+    assert!(!post2.tags.contains_all(&conn, &pks).await.unwrap());
+    post2.save(&conn).await.unwrap();
+    assert!(!post2.tags.contains(&conn, &tag_fast).await.unwrap());
+    assert!(post2.tags.contains(&conn, &tag_striped).await.unwrap());
+}
+
+#[butane_test]
+async fn ordered_many_keeps_order(conn: ConnectionAsync) {
+    let mut items = Vec::new();
+    for val in ["a", "b", "c", "d"] {
+        let mut item = AutoItem {
+            id: AutoPk::uninitialized(),
+            val: val.to_string(),
+        };
+        item.save(&conn).await.unwrap();
+        items.push(item);
+    }
+    let [a, b, c, d] = &items[..] else {
+        unreachable!()
+    };
+
+    let mut playlist = Playlist::default();
+    playlist.items.add(c).unwrap();
+    playlist.items.add(a).unwrap();
+    playlist.items.add(b).unwrap();
+    playlist.save(&conn).await.unwrap();
+    let playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    let vals: Vec<&str> = playlist
+        .items
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|item| item.val.as_str())
+        .collect();
+    assert_eq!(vals, ["c", "a", "b"]);
+
+    // Appending does not require loading
+    let mut playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    playlist.items.add(d).unwrap();
+    assert!(matches!(
+        playlist.items.insert_at(0, d),
+        Err(butane::Error::ValueNotLoaded)
+    ));
+    playlist.save(&conn).await.unwrap();
+    let playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    let vals: Vec<&str> = playlist
+        .items
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|item| item.val.as_str())
+        .collect();
+    assert_eq!(vals, ["c", "a", "b", "d"]);
+
+    let mut playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    assert_eq!(playlist.items.load(&conn).await.unwrap().count(), 4);
+    playlist.items.move_to(d, 0).unwrap();
+    let vals: Vec<&str> = playlist
+        .items
+        .get()
+        .unwrap()
+        .map(|item| item.val.as_str())
+        .collect();
+    assert_eq!(vals, ["d", "c", "a", "b"]);
+    playlist.items.remove(a);
+    playlist.items.insert_at(1, b).unwrap();
+    assert!(matches!(
+        playlist.items.insert_at(4, a),
+        Err(butane::Error::BoundsError(_))
+    ));
+    assert!(matches!(
+        playlist.items.move_to(a, 0),
+        Err(butane::Error::NoSuchObject)
+    ));
+    playlist.save(&conn).await.unwrap();
+    let playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    let vals: Vec<&str> = playlist
+        .items
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|item| item.val.as_str())
+        .collect();
+    assert_eq!(vals, ["d", "b", "c"]);
+
+    let playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    assert!(playlist.items.contains(&conn, b).await.unwrap());
+    assert!(!playlist.items.contains(&conn, a).await.unwrap());
+    let vals: Vec<String> = playlist
+        .items
+        .load_ordered(&conn, OrderDirection::Descending)
+        .await
+        .unwrap()
+        .map(|item| item.val.clone())
+        .collect();
+    assert_eq!(vals, ["c", "b", "d"]);
+
+    // Removing then adding again keeps the value, at its new index
+    let mut playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    assert_eq!(playlist.items.load(&conn).await.unwrap().count(), 3);
+    playlist.items.remove(c);
+    playlist.items.insert_at(0, a).unwrap();
+    playlist.items.add(c).unwrap();
+    playlist.save(&conn).await.unwrap();
+    let playlist = Playlist::get(&conn, playlist.id).await.unwrap();
+    let vals: Vec<&str> = playlist
+        .items
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|item| item.val.as_str())
+        .collect();
+    assert_eq!(vals, ["a", "d", "b", "c"]);
+}
+
+#[butane_test]
+async fn many_through_association(conn: ConnectionAsync) {
+    let mut chess = Club {
+        name: "chess".to_string(),
+        ..Default::default()
+    };
+    chess.save(&conn).await.unwrap();
+    let mut rowing = Club {
+        name: "rowing".to_string(),
+        ..Default::default()
+    };
+    rowing.save(&conn).await.unwrap();
+    let mut ann = Person {
+        name: "Ann".to_string(),
+        ..Default::default()
+    };
+    ann.save(&conn).await.unwrap();
+    let mut bo = Person {
+        name: "Bo".to_string(),
+        ..Default::default()
+    };
+    bo.save(&conn).await.unwrap();
+
+    let mut membership = Membership::new(&chess, &ann, "captain");
+    chess.members.add(&conn, &mut membership).await.unwrap();
+    let mut membership = Membership::new(&chess, &bo, "member");
+    chess.members.add(&conn, &mut membership).await.unwrap();
+    let mut membership = Membership::new(&rowing, &ann, "member");
+    rowing.members.add(&conn, &mut membership).await.unwrap();
+
+    let chess = Club::get(&conn, chess.id).await.unwrap();
+    let mut names: Vec<&str> = chess
+        .members
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|p| p.name.as_str())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Ann", "Bo"]);
+    let roles: Vec<(AutoPk<i64>, &str)> = chess
+        .members
+        .load_associations(&conn)
+        .await
+        .unwrap()
+        .map(|m| (m.person.pk(), m.role.as_str()))
+        .collect();
+    assert!(roles.contains(&(ann.id, "captain")));
+    assert!(roles.contains(&(bo.id, "member")));
+
+    // The other side of the relationship
+    let mut ann = Person::get(&conn, ann.id).await.unwrap();
+    let mut clubs: Vec<&str> = ann
+        .clubs
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|c| c.name.as_str())
+        .collect();
+    clubs.sort();
+    assert_eq!(clubs, ["chess", "rowing"]);
+    assert!(ann.clubs.contains(&conn, &rowing).await.unwrap());
+
+    assert_eq!(ann.clubs.remove(&conn, &rowing).await.unwrap(), 1);
+    assert!(!ann.clubs.contains(&conn, &rowing).await.unwrap());
+    let clubs: Vec<&str> = ann
+        .clubs
+        .load(&conn)
+        .await
+        .unwrap()
+        .map(|c| c.name.as_str())
+        .collect();
+    assert_eq!(clubs, ["chess"]);
+}
+
+#[butane_test]
+async fn can_add_to_many_with_custom_many_table_name(conn: ConnectionAsync) {
+    let mut obj = CustomManyTable {
+        id: AutoPk::uninitialized(),
+        tags: Many::default(),
+    };
+    obj.tags.add(&create_tag(&conn, "blue").await).unwrap();
+    obj.tags.add(&create_tag(&conn, "red").await).unwrap();
+    obj.save(&conn).await.unwrap();
+
+    let obj = CustomManyTable::get(&conn, obj.id).await.unwrap();
+    let tags = obj.tags.load(&conn).await.unwrap();
+    assert_eq!(tags.count(), 2);
+
+    let found = query!(CustomManyTable, tags.contains("blue"))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    conn.execute("SELECT owner, has FROM custom_tags")
+        .await
+        .unwrap();
+}
+
+#[butane_test]
+async fn delete_removes_owned_many_rows(conn: ConnectionAsync) {
+    let mut obj = AutoPkWithMany::new();
+    obj.tags.add(&create_tag(&conn, "blue").await).unwrap();
+    obj.save(&conn).await.unwrap();
+
+    // The join rows refer to obj, so would prevent deleting it if left behind
+    obj.delete(&conn).await.unwrap();
+    assert!(AutoPkWithMany::try_get(&conn, obj.id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(Tag::get(&conn, "blue").await.is_ok());
+}
+
+#[butane_test]
+async fn delete_many_removes_owned_many_rows(conn: ConnectionAsync) {
+    let tag = create_tag(&conn, "blue").await;
+    let mut objs = Vec::new();
+    for _ in 0..3 {
+        let mut obj = AutoPkWithMany::new();
+        obj.tags.add(&tag).unwrap();
+        obj.save(&conn).await.unwrap();
+        objs.push(obj);
+    }
+
+    // More primary keys than fit in one statement, most of them unused
+    let mut ids: Vec<i64> = (10_000..11_500).collect();
+    ids.push(objs[0].id.unwrap());
+    ids.push(objs[1].id.unwrap());
+    let deleted = AutoPkWithMany::delete_many(&conn, &ids).await.unwrap();
+    assert_eq!(deleted, 2);
+
+    let remaining = AutoPkWithMany::query().load(&conn).await.unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, objs[2].id);
+    let tags = remaining[0].tags.load(&conn).await.unwrap();
+    assert_eq!(tags.count(), 1);
+}
+
+#[butane_test]
+async fn query_delete_removes_owned_many_rows(conn: ConnectionAsync) {
+    let tag = create_tag(&conn, "blue").await;
+    let mut objs = Vec::new();
+    for _ in 0..3 {
+        let mut obj = DuplicateTags::default();
+        obj.tags.add(&tag).unwrap();
+        obj.save(&conn).await.unwrap();
+        objs.push(obj);
+    }
+
+    let first = objs[0].id;
+    let deleted = query!(DuplicateTags, id > { first })
+        .delete(&conn)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2);
+
+    // Only the join rows of the remaining object are left behind
+    let owner = [Column::new("owner", SqlType::BigInt)];
+    let (_, rows) = conn
+        .query_counted("DuplicateTags_tags_Many", &owner, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+    let obj = DuplicateTags::get(&conn, first).await.unwrap();
+    assert_eq!(obj.tags.load(&conn).await.unwrap().count(), 1);
+}
+
+#[butane_test]
+async fn get_with_related(conn: ConnectionAsync) {
+    let mut cats_blog = Blog::new(1, "Cats");
+    cats_blog.save(&conn).await.unwrap();
+    let mut post = Post::new(
+        1,
+        "The Cheetah",
+        "This post is about a fast cat.",
+        &cats_blog,
+    );
+    post.tags.add(&create_tag(&conn, "fast").await).unwrap();
+    post.tags.add(&create_tag(&conn, "cat").await).unwrap();
+    post.save(&conn).await.unwrap();
+
+    let post = Post::get_with(&conn, post.id, related!(blog, tags))
+        .await
+        .unwrap();
+    assert_eq!(post.blog.get().unwrap().name, "Cats");
+    let mut tags: Vec<&str> = post.tags.get().unwrap().map(|t| t.tag.as_str()).collect();
+    tags.sort();
+    assert_eq!(tags, ["cat", "fast"]);
+
+    // Only the chosen relationships are loaded
+    let post = Post::get_with(&conn, post.id, related!(tags))
+        .await
+        .unwrap();
+    assert!(post.blog.get().is_err());
+    assert_eq!(post.tags.get().unwrap().count(), 2);
+
+    let mut playlist = Playlist::default();
+    let mut item = AutoItem {
+        id: AutoPk::uninitialized(),
+        val: "a".to_string(),
+    };
+    item.save(&conn).await.unwrap();
+    playlist.items.add(&item).unwrap();
+    playlist.save(&conn).await.unwrap();
+    let playlist = Playlist::get_with(&conn, playlist.id, related!(items))
+        .await
+        .unwrap();
+    assert_eq!(playlist.items.get().unwrap().next().unwrap().val, "a");
+
+    let err = Post::get_with(&conn, 2, related!(blog)).await.unwrap_err();
+    assert!(matches!(err, butane::Error::NoSuchObject));
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn relationships_are_send_sync() {
+    // Checked at compile time, so that models may be held in shared state
+    assert_send_sync::<ForeignKey<Blog>>();
+    assert_send_sync::<Many<Tag>>();
+    assert_send_sync::<OrderedMany<AutoItem>>();
+    assert_send_sync::<ManyThrough<Person, Membership>>();
+    assert_send_sync::<Post>();
+    assert_send_sync::<Playlist>();
+    assert_send_sync::<Club>();
+}
+
+#[butane_test]
+async fn delete_cascade_removes_dependents(mut conn: ConnectionAsync) {
+    let mut cats_blog = Blog::new(1, "Cats");
+    cats_blog.save(&conn).await.unwrap();
+    let mut dogs_blog = Blog::new(2, "Dogs");
+    dogs_blog.save(&conn).await.unwrap();
+    let tag_fast = create_tag(&conn, "fast").await;
+    let tag_cat = create_tag(&conn, "cat").await;
+    let mut cheetah = Post::new(1, "The Cheetah", "A fast cat.", &cats_blog);
+    cheetah.tags.add(&tag_fast).unwrap();
+    cheetah.tags.add(&tag_cat).unwrap();
+    cheetah.save(&conn).await.unwrap();
+    let mut lion = Post::new(2, "The Lion", "A big cat.", &cats_blog);
+    lion.tags.add(&tag_cat).unwrap();
+    lion.save(&conn).await.unwrap();
+    let mut greyhound = Post::new(3, "The Greyhound", "A fast dog.", &dogs_blog);
+    greyhound.tags.add(&tag_fast).unwrap();
+    greyhound.save(&conn).await.unwrap();
+
+    // A dry run reports the posts and their tags, and deletes nothing
+    let report = cats_blog.delete_cascade_dry_run(&conn).await.unwrap();
+    let mut posts = report.objects("Post");
+    posts.sort_by_key(|pk| format!("{pk:?}"));
+    assert_eq!(posts, [1i64.into(), 2i64.into()]);
+    assert_eq!(report.rows("Post_tags_Many"), 3);
+    assert_eq!(report.rows("Blog"), 1);
+    assert_eq!(report.total_rows(), 6);
+    // Dependents come before what they refer to
+    let tables: Vec<&str> = report.steps().iter().map(|step| step.table).collect();
+    assert_eq!(tables, ["Post_tags_Many", "Post", "Blog"]);
+    assert_eq!(Post::query().load(&conn).await.unwrap().len(), 3);
+
+    let tr = conn.transaction().await.unwrap();
+    let deleted = cats_blog.delete_cascade(&tr).await.unwrap();
+    tr.commit().await.unwrap();
+    assert_eq!(deleted, report);
+
+    assert!(Blog::try_get(&conn, 1).await.unwrap().is_none());
+    let remaining = Post::query().load(&conn).await.unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, 3);
+    assert_eq!(remaining[0].tags.load(&conn).await.unwrap().count(), 1);
+    assert!(Tag::get(&conn, "cat").await.is_ok());
+
+    // Outside a transaction, the deletes are made within one of their own
+    let deleted = dogs_blog.delete_cascade(&conn).await.unwrap();
+    assert_eq!(deleted.rows("Post_tags_Many"), 1);
+    assert_eq!(deleted.total_rows(), 3);
+    assert!(Blog::try_get(&conn, 2).await.unwrap().is_none());
+    assert!(Post::query().load(&conn).await.unwrap().is_empty());
+}
+
+#[test]
+fn registry_describes_relationships() {
+    use butane::migrations::adb::OnDelete;
+    use butane::registry::{self, Relationship};
+
+    let post = registry::model("Post").unwrap();
+    assert_eq!(post.table(), "Post");
+    assert_eq!(
+        post.relationships(),
+        [
+            Relationship::Many {
+                field: "tags",
+                table: "Post_tags_Many",
+                target: "tags"
+            },
+            Relationship::ForeignKey {
+                column: "blog",
+                target: "Blog",
+                on_delete: Some(OnDelete::Cascade),
+            },
+        ]
+    );
+}
@@ -1,9 +1,6 @@
 #[cfg(any(feature = "pg", feature = "sqlite"))]
 use butane::db::ConnectionManager;
 use butane_test_helper::*;
-#[cfg(any(feature = "pg", feature = "sqlite"))]
-#[cfg(any(feature = "pg", feature = "sqlite"))]
-use r2d2;
 use std::ops::DerefMut;
 
 #[cfg(feature = "sqlite")]
@@ -64,3 +61,41 @@ async fn deadpool_test_pg_async() {
     assert_eq!(pool.status().size, 1);
     assert_eq!(pool.status().available, 1);
 }
+
+#[cfg(feature = "pg")]
+#[tokio::test]
+async fn deadpool_pg_deadline_leaves_no_timeout() {
+    let (connspec, _data) = pg_connspec().await;
+    let manager = ConnectionManager::new(connspec);
+    // A single connection, so that it is the one checked out each time
+    let pool: deadpool::managed::Pool<ConnectionManager> =
+        deadpool::managed::Pool::builder(manager)
+            .max_size(1)
+            .build()
+            .unwrap();
+    {
+        let conn = pool.get().await.unwrap();
+        let err = butane::with_deadline(
+            std::time::Duration::from_millis(200),
+            conn.execute("SELECT pg_sleep(30);"),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, butane::Error::DeadlineExceeded));
+    }
+    {
+        let conn = pool.get().await.unwrap();
+        // Cancelled by the caller before the deadline
+        let cancelled = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            butane::with_deadline(
+                std::time::Duration::from_millis(300),
+                conn.execute("SELECT pg_sleep(30);"),
+            ),
+        )
+        .await;
+        assert!(cancelled.is_err());
+    }
+    let conn = pool.get().await.unwrap();
+    conn.execute("SELECT pg_sleep(0.5);").await.unwrap();
+}
@@ -1,6 +1,9 @@
-use butane::db::{Connection, ConnectionAsync};
-use butane::query::BoolExpr;
-use butane::{colname, filter, find, find_async, model, query, AutoPk, Many, SqlVal};
+use butane::db::{Connection, ConnectionAsync, ConnectionMethods, ConnectionMethodsAsync};
+use butane::query::{BoolExpr, Cte, DynFieldExpr, Expr, Patch, Update};
+use butane::{
+    colname, expr, filter, find, find_async, model, query, update, AutoPk, Many, SqlVal, SqlValRef,
+    StringList,
+};
 use butane_test_helper::*;
 use butane_test_macros::butane_test;
 #[cfg(feature = "datetime")]
@@ -8,7 +11,7 @@
 
 mod common;
 use common::blog;
-use common::blog::{Blog, Post, PostMetadata, Tag};
+use common::blog::{Blog, Post, PostMetadata, PostPatch, Tag};
 
 #[butane_test]
 async fn equality(conn: ConnectionAsync) {
@@ -112,6 +115,334 @@ async fn combination_allof(conn: ConnectionAsync) {
     assert_eq!(posts[0].title, "The Tiger");
 }
 
+#[butane_test]
+async fn runtime_fields(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let title = "The Tiger".to_string();
+    let posts = Post::query()
+        .filter(Post::fields().title().eq(&title))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].title, "The Tiger");
+
+    let likes = DynFieldExpr::<Post>::new("likes").unwrap();
+    let mut posts = Post::query()
+        .filter(likes.lt(5).unwrap())
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 2);
+    posts.sort_by(|p1, p2| p1.id.partial_cmp(&p2.id).unwrap());
+    assert_eq!(posts[0].title, "The Tiger");
+    assert_eq!(posts[1].title, "Mt. Everest");
+
+    assert!(likes.lt("five").is_err());
+    assert!(DynFieldExpr::<Post>::new("nonexistent").is_err());
+}
+
+#[butane_test]
+async fn combination_methods(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let max_likes = 5;
+    let published = filter!(Post, published == true);
+    let unpopular = filter!(Post, likes < { max_likes });
+    let posts = Post::query()
+        .filter(published.clone().and(unpopular.clone()))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].title, "The Tiger");
+
+    let posts = Post::query()
+        .filter(published.or(unpopular))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 4);
+}
+
+#[butane_test]
+async fn sql_functions(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let titles = |posts: Vec<Post>| posts.into_iter().map(|p| p.title).collect::<Vec<_>>();
+
+    let posts = query!(Post, length(title) > 10)
+        .order_asc(colname!(Post, id))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["Sir Charles", "Mt. Everest"]);
+
+    let posts = Post::query()
+        .filter(!filter!(Post, length(title) > 10))
+        .order_asc(colname!(Post, id))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["The Tiger", "Mount Doom"]);
+
+    let posts = query!(Post, lower(title).like("m%") && abs(likes - 3) > 5)
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["Mount Doom"]);
+
+    let posts = Post::query()
+        .order_by_asc(expr!(Post, lower(title)))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(
+        titles(posts),
+        ["Mount Doom", "Mt. Everest", "Sir Charles", "The Tiger"]
+    );
+
+    // Values in the ordering follow those in the filter
+    let posts = query!(Post, title.like("M%"))
+        .order_by_desc(expr!(Post, coalesce(lower(title), { "x" })))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["Mt. Everest", "Mount Doom"]);
+}
+
+#[butane_test]
+async fn combination_not(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let titles = |mut posts: Vec<Post>| {
+        posts.sort_by(|p1, p2| p1.id.partial_cmp(&p2.id).unwrap());
+        posts.into_iter().map(|p| p.title).collect::<Vec<_>>()
+    };
+    let published = filter!(Post, published == true);
+    let unpopular = filter!(Post, likes < 5);
+
+    let posts = Post::query()
+        .filter(!published.clone().and(unpopular.clone()))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["Sir Charles", "Mount Doom", "Mt. Everest"]);
+
+    let posts = Post::query()
+        .filter(published.clone().or(unpopular.clone()).not().not())
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 4);
+
+    // OR must bind less tightly than AND when nested within it
+    let posts = Post::query()
+        .filter(
+            filter!(Post, title == "Mt. Everest")
+                .or(filter!(Post, title == "The Tiger"))
+                .and(published.clone()),
+        )
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["The Tiger"]);
+
+    let posts = Post::query()
+        .filter(!BoolExpr::any_of([
+            filter!(Post, likes > 15),
+            filter!(Post, title.like("Mt%")),
+        ]))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["The Tiger", "Mount Doom"]);
+
+    let posts = Post::query()
+        .filter(BoolExpr::all_of([published, !unpopular]))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(titles(posts), ["Sir Charles", "Mount Doom"]);
+
+    let posts = Post::query()
+        .filter(BoolExpr::any_of([]))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert!(posts.is_empty());
+    let posts = Post::query()
+        .filter(BoolExpr::all_of([]))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 4);
+}
+
+#[butane_test]
+async fn update_expressions(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let bonus = 3;
+    let cnt = update!(Post, likes = likes * 2 + { bonus }, title = upper(title))
+        .filter(filter!(Post, published == true && likes < 15))
+        .execute(&conn)
+        .await
+        .unwrap();
+    assert_eq!(cnt, 2);
+
+    let mut posts = Post::query().load(&conn).await.unwrap();
+    posts.sort_by(|p1, p2| p1.id.partial_cmp(&p2.id).unwrap());
+    let changed: Vec<(&str, i32)> = posts.iter().map(|p| (p.title.as_str(), p.likes)).collect();
+    assert_eq!(
+        changed,
+        [
+            ("THE TIGER", 11),
+            ("Sir Charles", 20),
+            ("MOUNT DOOM", 23),
+            ("Mt. Everest", 0)
+        ]
+    );
+
+    // Without a filter every object is updated, and later assignments
+    // see the values from before the update
+    let cnt = update!(Post, likes = -(likes - 1), published = false, body = title)
+        .execute(&conn)
+        .await
+        .unwrap();
+    assert_eq!(cnt, 4);
+    let post = find_async!(Post, title == "THE TIGER", &conn).unwrap();
+    assert_eq!(post.likes, -10);
+    assert!(!post.published);
+    assert_eq!(post.body, "THE TIGER");
+}
+
+#[butane_test]
+async fn patch(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let tiger = find_async!(Post, title == "The Tiger", &conn).unwrap();
+    let lion = || PostPatch {
+        title: Some("The Lion".to_string()),
+        likes: Some(42),
+        ..Default::default()
+    };
+
+    let mut expected = find_async!(Post, title == "The Tiger", &conn).unwrap();
+    lion().apply(&mut expected);
+    assert_eq!(expected.title, "The Lion");
+    assert_eq!(expected.likes, 42);
+
+    lion().patch(&conn, tiger.id).await.unwrap();
+    let post = Post::get(&conn, tiger.id).await.unwrap();
+    assert_eq!(post, expected);
+    assert_eq!(post.body, tiger.body);
+
+    // Other objects are unchanged
+    let posts = Post::query()
+        .filter(filter!(Post, likes == 42))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 1);
+
+    // An empty patch changes nothing
+    PostPatch::default().patch(&conn, tiger.id).await.unwrap();
+    let err = PostPatch {
+        published: Some(false),
+        ..Default::default()
+    }
+    .patch(&conn, 1000)
+    .await
+    .unwrap_err();
+    assert!(matches!(err, butane::Error::NoSuchObject));
+}
+
+#[butane_test]
+async fn tuple_results(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let rows = conn
+        .query_as::<(String, i32)>(
+            "SELECT title, likes FROM Post WHERE likes > ? ORDER BY likes DESC",
+            &[SqlValRef::Int(5)],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            ("Sir Charles".to_string(), 20),
+            ("Mount Doom".to_string(), 10)
+        ]
+    );
+    let (count,) = conn
+        .query_as::<(i64,)>("SELECT count(*) FROM Post", &[])
+        .await
+        .unwrap()[0];
+    assert_eq!(count, 4);
+
+    let rows = Post::query()
+        .filter(filter!(Post, likes > 5))
+        .order_asc(colname!(Post, title))
+        .load_fields(&conn, (Post::fields().title(), Post::fields().likes()))
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            ("Mount Doom".to_string(), 10),
+            ("Sir Charles".to_string(), 20)
+        ]
+    );
+}
+
+#[butane_test]
+async fn conditional_expressions(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let cnt = update!(
+        Post,
+        body = if likes >= 15 {
+            "gold"
+        } else if likes >= 5 {
+            "silver"
+        } else {
+            "bronze"
+        }
+    )
+    .execute(&conn)
+    .await
+    .unwrap();
+    assert_eq!(cnt, 4);
+    let mut posts = Post::query().load(&conn).await.unwrap();
+    posts.sort_by_key(|p| p.id);
+    let tiers: Vec<&str> = posts.iter().map(|p| p.body.as_str()).collect();
+    assert_eq!(tiers, ["bronze", "gold", "silver", "bronze"]);
+
+    // Unpublished posts first, then by likes
+    let posts = Post::query()
+        .order_by_desc(expr!(Post, if published == false { 100 } else { likes }))
+        .load(&conn)
+        .await
+        .unwrap();
+    let titles: Vec<&str> = posts.iter().map(|p| p.title.as_str()).collect();
+    assert_eq!(
+        titles,
+        ["Mt. Everest", "Sir Charles", "Mount Doom", "The Tiger"]
+    );
+
+    // No post has this many likes, so the maximum is NULL
+    let most_likes = Post::query()
+        .filter(filter!(Post, likes > 100))
+        .scalar(Expr::Func("max", vec![Expr::Column("likes")]));
+    let cnt = Update::<Post>::new()
+        .set(
+            colname!(Post, likes),
+            Expr::coalesce([most_likes, Expr::Val(SqlVal::Int(7))]),
+        )
+        .filter(filter!(Post, title == "The Tiger"))
+        .execute(&conn)
+        .await
+        .unwrap();
+    assert_eq!(cnt, 1);
+    let post = find_async!(Post, title == "The Tiger", &conn).unwrap();
+    assert_eq!(post.likes, 7);
+}
+
 #[butane_test]
 async fn not_found(conn: ConnectionAsync) {
     blog::setup_blog(&conn).await;
@@ -278,6 +609,42 @@ async fn offset(conn: ConnectionAsync) {
     assert_eq!(posts[1].title, "The Tiger");
 }
 
+#[butane_test]
+async fn load_page(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let query = Post::query().order_asc(colname!(Post, title));
+    let page = query.clone().page(&conn, 2, 3).await.unwrap();
+    assert_eq!(page.total, 4);
+    assert_eq!(page.page, 2);
+    assert_eq!(page.pages, 2);
+    assert!(page.has_previous());
+    assert!(!page.has_next());
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].title, "The Tiger");
+
+    let page = query.clone().limit(1).page(&conn, 1, 3).await.unwrap();
+    assert_eq!(page.items.len(), 3);
+    assert_eq!(page.items[0].title, "Mount Doom");
+    assert!(page.has_next());
+
+    // Past the last page there are no items, but the total is still known
+    let page = query.clone().page(&conn, 5, 3).await.unwrap();
+    assert!(page.items.is_empty());
+    assert_eq!(page.total, 4);
+    assert_eq!(page.pages, 2);
+
+    let page = query!(Post, title == "Nowhere")
+        .page(&conn, 1, 3)
+        .await
+        .unwrap();
+    assert!(page.items.is_empty());
+    assert_eq!(page.total, 0);
+    assert_eq!(page.pages, 1);
+
+    let result = query.page(&conn, 0, 3).await;
+    assert!(matches!(result, Err(butane::Error::BoundsError(_))));
+}
+
 #[model]
 #[derive(PartialEq, Debug)]
 struct HasAutopk {
@@ -327,3 +694,417 @@ async fn query_in(conn: ConnectionAsync) {
         .unwrap();
     assert_eq!(posts.len(), 2);
 }
+
+#[butane_test]
+async fn normalized_filters(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let title = Post::fields().title();
+    let expr = BoolExpr::any_of(
+        ["The Tiger", "Sir Charles", "The Tiger"]
+            .into_iter()
+            .map(|t| title.eq(&t))
+            .chain([title.is_in(vec!["Mount Doom", "Sir Charles"])]),
+    )
+    .and(BoolExpr::True.and(filter!(Post, published == true)));
+    assert_eq!(
+        expr.clone().normalize(),
+        BoolExpr::AllOf(vec![
+            BoolExpr::In(
+                "title",
+                vec![
+                    SqlVal::Text("The Tiger".to_string()),
+                    SqlVal::Text("Sir Charles".to_string()),
+                    SqlVal::Text("Mount Doom".to_string()),
+                ]
+            ),
+            filter!(Post, published == true),
+        ])
+    );
+    let posts = Post::query().filter(expr).load(&conn).await.unwrap();
+    assert_eq!(posts.len(), 3);
+
+    // Repeated values are those which are equal, not those which look alike
+    assert_eq!(
+        BoolExpr::In(
+            "rating",
+            vec![SqlVal::Real(0.0), SqlVal::Real(-0.0), SqlVal::Int(0)]
+        )
+        .normalize(),
+        BoolExpr::In("rating", vec![SqlVal::Real(0.0), SqlVal::Int(0)])
+    );
+
+    // An OR of thousands of comparisons binds one parameter per value
+    let titles: Vec<String> = (0..20_000).map(|i| format!("Post {i}")).collect();
+    let expr = BoolExpr::any_of(
+        titles
+            .iter()
+            .map(|t| title.eq(t))
+            .chain([title.eq(&"The Tiger")]),
+    );
+    let posts = Post::query().filter(expr).load(&conn).await.unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].title, "The Tiger");
+}
+
+#[butane_test]
+async fn long_in_lists(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    // More values than are bound to one statement, here past the limits
+    // of both SQLite and Postgres, so the list is split
+    let mut titles: Vec<String> = (0..70_000).map(|i| format!("Post {i}")).collect();
+    titles.insert(1200, "Sir Charles".to_string());
+    titles.push("The Tiger".to_string());
+    let in_titles = || Post::fields().title().is_in(titles.clone());
+
+    let mut found: Vec<String> = Post::query()
+        .filter(in_titles())
+        .load(&conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|post| post.title)
+        .collect();
+    found.sort();
+    assert_eq!(found, ["Sir Charles", "The Tiger"]);
+
+    // The limit and offset apply across the chunks, in their order
+    let posts = Post::query()
+        .filter(in_titles())
+        .offset(1)
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].title, "The Tiger");
+    let post = Post::query()
+        .filter(in_titles())
+        .load_first(&conn)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(post.title, "Sir Charles");
+
+    // An ordered query is filtered through a temporary table of the
+    // values, so that its order, limit and offset apply to all of the
+    // results
+    let mut ordered_titles = titles.clone();
+    ordered_titles.push("Mount Doom".to_string());
+    let ordered = || {
+        Post::query()
+            .filter(Post::fields().title().is_in(ordered_titles.clone()))
+            .order_asc("title")
+    };
+    let posts = ordered().offset(1).limit(1).load(&conn).await.unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].title, "Sir Charles");
+    let post = ordered().load_first(&conn).await.unwrap().unwrap();
+    assert_eq!(post.title, "Mount Doom");
+
+    let cnt = update!(Post, likes = likes + 1)
+        .filter(in_titles().and(filter!(Post, published == true)))
+        .execute(&conn)
+        .await
+        .unwrap();
+    assert_eq!(cnt, 2);
+    let post = find_async!(Post, title == "The Tiger", &conn).unwrap();
+    assert_eq!(post.likes, 5);
+
+    // Only one list is split, so a second one which cannot fit beside
+    // it is refused rather than bound
+    let two_lists = || in_titles().and(Post::fields().body().is_in(titles.clone()));
+    let err = Post::query()
+        .filter(two_lists())
+        .load(&conn)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::TooManyParameters { .. }));
+    let err = update!(Post, likes = likes + 1)
+        .filter(two_lists())
+        .execute(&conn)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::TooManyParameters { .. }));
+    let err = Post::query()
+        .filter(two_lists())
+        .delete(&conn)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, butane::Error::TooManyParameters { .. }));
+    let post = find_async!(Post, title == "The Tiger", &conn).unwrap();
+    assert_eq!(post.likes, 5);
+
+    for text in ["first", "second", "third"] {
+        HasAutopk::new(text).save(&conn).await.unwrap();
+    }
+    let mut texts: Vec<String> = (0..70_000).map(|i| format!("text {i}")).collect();
+    texts.extend(["first".to_string(), "third".to_string()]);
+    let cnt = query!(HasAutopk, text.is_in({ texts }))
+        .delete(&conn)
+        .await
+        .unwrap();
+    assert_eq!(cnt, 2);
+    let rest = HasAutopk::query().load(&conn).await.unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].text, "second");
+
+    // Integers are bound as parameters by some backends, so long lists
+    // of them are split too, here past SQLite's limit of 32766.
+    let mut ids: Vec<i64> = (1_000_000..1_040_000).collect();
+    ids.insert(20_000, rest[0].id.unwrap());
+    let in_ids = || HasAutopk::fields().id().is_in(ids.clone());
+    let found = HasAutopk::query()
+        .filter(in_ids())
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].text, "second");
+    let cnt = HasAutopk::query()
+        .filter(in_ids())
+        .delete(&conn)
+        .await
+        .unwrap();
+    assert_eq!(cnt, 1);
+    assert!(HasAutopk::query().load(&conn).await.unwrap().is_empty());
+}
+
+#[model]
+#[derive(Debug)]
+struct TreeNode {
+    id: i64,
+    name: String,
+    parent: Option<i64>,
+}
+
+#[butane_test]
+async fn recursive_cte(conn: ConnectionAsync) {
+    // root -> {a -> {a1, a2}, b}, and unrelated
+    for (id, name, parent) in [
+        (1, "root", None),
+        (2, "a", Some(1)),
+        (3, "b", Some(1)),
+        (4, "a1", Some(2)),
+        (5, "a2", Some(2)),
+        (6, "unrelated", None),
+    ] {
+        let mut node = TreeNode {
+            id,
+            name: name.to_string(),
+            parent,
+        };
+        node.save(&conn).await.unwrap();
+    }
+    let names = |mut nodes: Vec<TreeNode>| {
+        nodes.sort_by_key(|n| n.id);
+        nodes.into_iter().map(|n| n.name).collect::<Vec<_>>()
+    };
+
+    let subtree = Cte::new(
+        "subtree",
+        "node",
+        TreeNode::TABLE,
+        "id",
+        filter!(TreeNode, id == 2),
+    )
+    .recursive(TreeNode::TABLE, "id", "parent");
+    let nodes = TreeNode::query()
+        .filter(subtree.contains("id"))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(names(nodes), ["a", "a1", "a2"]);
+
+    let ancestors = Cte::new(
+        "ancestors",
+        "node",
+        TreeNode::TABLE,
+        "parent",
+        filter!(TreeNode, name == "a2"),
+    )
+    .recursive(TreeNode::TABLE, "parent", "id");
+    let nodes = TreeNode::query()
+        .filter(ancestors.contains("id"))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(names(nodes), ["root", "a"]);
+
+    // A CTE may be combined with other filters, and used to delete
+    let nodes = TreeNode::query()
+        .filter(subtree.contains("id").and(filter!(TreeNode, name != "a")))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(names(nodes), ["a1", "a2"]);
+    let deleted = TreeNode::query()
+        .filter(!subtree.contains("id"))
+        .delete(&conn)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 3);
+}
+
+#[butane_test]
+async fn exists_and_scalar_subqueries(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let blog_names = |mut blogs: Vec<Blog>| {
+        blogs.sort_by_key(|b| b.id);
+        blogs.into_iter().map(|b| b.name).collect::<Vec<_>>()
+    };
+    let post_titles = |mut posts: Vec<Post>| {
+        posts.sort_by_key(|p| p.id);
+        posts.into_iter().map(|p| p.title).collect::<Vec<_>>()
+    };
+
+    let has_unpublished = Post::query()
+        .filter(filter!(Post, published == false).and(Post::fields().blog().matches_outer()))
+        .exists();
+    let blogs = Blog::query()
+        .filter(has_unpublished.clone())
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(blog_names(blogs), ["Mountains"]);
+    let blogs = Blog::query()
+        .filter(!has_unpublished)
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(blog_names(blogs), ["Cats"]);
+
+    let average_likes = Post::query().scalar(Expr::Func("avg", vec![Expr::Column("likes")]));
+    let posts = Post::query()
+        .filter(BoolExpr::Gt(colname!(Post, likes), average_likes))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(post_titles(posts), ["Sir Charles", "Mount Doom"]);
+
+    let most_liked_blog = Post::query()
+        .order_desc(colname!(Post, likes))
+        .limit(1)
+        .scalar(Expr::Column(colname!(Post, blog)));
+    let posts = Post::query()
+        .filter(BoolExpr::Eq(colname!(Post, blog), most_liked_blog))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(post_titles(posts), ["The Tiger", "Sir Charles"]);
+}
+
+#[butane_test]
+async fn load_map(conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let posts = query!(Post, published == true)
+        .load_map(&conn)
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 3);
+    assert_eq!(posts[&1].title, "The Tiger");
+    assert_eq!(posts[&3].title, "Mount Doom");
+    assert!(!posts.contains_key(&4));
+
+    let posts = Post::query()
+        .load_map_by(&conn, |post| post.title.clone())
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 4);
+    assert_eq!(posts["Mt. Everest"].id, 4);
+
+    let mut val = HasAutopk::new("first");
+    val.save(&conn).await.unwrap();
+    let vals = HasAutopk::query().load_map(&conn).await.unwrap();
+    assert_eq!(vals[&val.id].text, "first");
+}
+
+#[butane_test]
+async fn server_side_cursor(mut conn: ConnectionAsync) {
+    blog::setup_blog(&conn).await;
+    let is_pg = conn.backend_name() == "pg";
+    let tx = conn.transaction().await.unwrap();
+    if !is_pg {
+        let err = Post::query().declare_cursor(&tx).await.unwrap_err();
+        assert!(matches!(err, butane::Error::Unsupported(_, _)));
+        return;
+    }
+    let mut cursor = query!(Post, published == true)
+        .order_asc(colname!(Post, id))
+        .declare_cursor(&tx)
+        .await
+        .unwrap();
+    let page = cursor.fetch(&tx, 2).await.unwrap();
+    let titles: Vec<String> = page.into_iter().map(|p| p.title).collect();
+    assert_eq!(titles, ["The Tiger", "Sir Charles"]);
+    let page = cursor.fetch(&tx, 2).await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].title, "Mount Doom");
+    assert!(cursor.fetch(&tx, 2).await.unwrap().is_empty());
+    cursor.close(&tx).await.unwrap();
+    tx.commit().await.unwrap();
+}
+
+#[model]
+#[derive(Debug, Default)]
+struct Snippet {
+    id: i64,
+    labels: StringList,
+}
+
+#[butane_test]
+async fn string_list_filters(conn: ConnectionAsync) {
+    for (id, labels) in [
+        (1, vec!["rust", "sql"]),
+        (2, vec!["rust"]),
+        (3, vec!["go", "it's"]),
+        (4, vec![]),
+    ] {
+        let mut snippet = Snippet {
+            id,
+            labels: labels.into_iter().collect(),
+        };
+        snippet.save(&conn).await.unwrap();
+    }
+    let ids = |snippets: Vec<Snippet>| {
+        let mut ids: Vec<i64> = snippets.into_iter().map(|s| s.id).collect();
+        ids.sort();
+        ids
+    };
+
+    let loaded = Snippet::get(&conn, 3).await.unwrap();
+    assert_eq!(*loaded.labels, vec!["go", "it's"]);
+    assert!(Snippet::get(&conn, 4).await.unwrap().labels.is_empty());
+
+    let found = query!(Snippet, labels.contains("rust"))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(ids(found), vec![1, 2]);
+    let label = "it's".to_string();
+    let found = query!(Snippet, labels.contains({ label.clone() }))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(ids(found), vec![3]);
+    let found = query!(Snippet, labels.contains_all(["rust", "sql"]))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(ids(found), vec![1]);
+    let found = query!(Snippet, labels.overlaps(["sql", "go"]))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(ids(found), vec![1, 3]);
+    let found = Snippet::query()
+        .filter(!Snippet::fields().labels().contains("rust"))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert_eq!(ids(found), vec![3, 4]);
+    let none: [&str; 0] = [];
+    let found = query!(Snippet, labels.overlaps({ none }))
+        .load(&conn)
+        .await
+        .unwrap();
+    assert!(found.is_empty());
+}
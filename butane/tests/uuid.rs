@@ -16,6 +16,22 @@ fn new(id: Uuid) -> Self {
     }
 }
 
+#[model]
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct AutoUuid {
+    #[butane(auto_uuid = "v7")]
+    id: Uuid,
+    bar: u32,
+}
+
+#[model]
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct GeneratedUuid {
+    #[butane(pk_generator = "butane::pkgen::uuid_v4")]
+    id: Uuid,
+    bar: u32,
+}
+
 #[butane_test]
 async fn basic_uuid(conn: ConnectionAsync) {
     //create
@@ -35,3 +51,37 @@ async fn basic_uuid(conn: ConnectionAsync) {
     let foo3 = FooUU::get(&conn, id).await.unwrap();
     assert_eq!(foo2, foo3);
 }
+
+#[butane_test]
+async fn auto_uuid_v7(conn: ConnectionAsync) {
+    let mut first = AutoUuid {
+        id: Uuid::nil(),
+        bar: 1,
+    };
+    first.save(&conn).await.unwrap();
+    assert_eq!(first.id.get_version_num(), 7);
+    let mut second = AutoUuid {
+        id: Uuid::nil(),
+        bar: 2,
+    };
+    second.save(&conn).await.unwrap();
+    assert_ne!(first.id, second.id);
+
+    // An id which was already set is kept
+    let id = first.id;
+    first.bar = 3;
+    first.save(&conn).await.unwrap();
+    assert_eq!(first.id, id);
+    assert_eq!(AutoUuid::get(&conn, id).await.unwrap().bar, 3);
+}
+
+#[butane_test]
+async fn pk_generator_uuid_v4(conn: ConnectionAsync) {
+    let mut obj = GeneratedUuid {
+        id: Uuid::nil(),
+        bar: 1,
+    };
+    obj.save(&conn).await.unwrap();
+    assert_eq!(obj.id.get_version_num(), 4);
+    assert_eq!(GeneratedUuid::get(&conn, obj.id).await.unwrap(), obj);
+}
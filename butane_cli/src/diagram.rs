@@ -0,0 +1,227 @@
+//! Entity-relationship diagrams of a schema.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use butane::migrations::adb::{AColumn, ARef, ATable, TypeIdentifier, ADB, MANY_SUFFIX};
+
+use crate::Result;
+
+/// Output format of a diagram.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DiagramFormat {
+    /// Mermaid `erDiagram`.
+    #[default]
+    Mermaid,
+    /// Graphviz DOT.
+    Dot,
+}
+impl FromStr for DiagramFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mermaid" => Ok(DiagramFormat::Mermaid),
+            "dot" => Ok(DiagramFormat::Dot),
+            _ => Err(anyhow::anyhow!(
+                "Unknown diagram format '{s}'. Expected 'mermaid' or 'dot'."
+            )),
+        }
+    }
+}
+
+/// A relationship between two tables.
+#[derive(Debug)]
+struct Edge<'a> {
+    from: &'a str,
+    to: &'a str,
+    label: &'a str,
+    kind: EdgeKind,
+}
+
+#[derive(Debug)]
+enum EdgeKind {
+    /// A [butane::ForeignKey] field.
+    ForeignKey { nullable: bool },
+    /// A [butane::Many] field, stored in a separate table.
+    Many,
+}
+
+/// The tables referred to by a table backing a [butane::Many] field,
+/// along with the field name.
+fn many_edge(table: &ATable) -> Option<Edge<'_>> {
+    let name = &table.name;
+    if table.pk().is_some() {
+        return None;
+    }
+    let from = referenced_table(table.column("owner")?)?;
+    let to = referenced_table(table.column("has")?)?;
+    // The field name, unless the table has a custom name
+    let label = name
+        .to_lowercase()
+        .ends_with(&MANY_SUFFIX.to_lowercase())
+        .then(|| name[..name.len() - MANY_SUFFIX.len()].strip_prefix(from))
+        .flatten()
+        .and_then(|s| s.strip_prefix('_'))
+        .unwrap_or(name);
+    Some(Edge {
+        from,
+        to,
+        label,
+        kind: EdgeKind::Many,
+    })
+}
+
+fn referenced_table(column: &AColumn) -> Option<&str> {
+    match column.reference() {
+        Some(ARef::Literal(literal)) => Some(literal.table_name()),
+        _ => None,
+    }
+}
+
+fn type_name(column: &AColumn) -> String {
+    match column.typeid() {
+        Ok(TypeIdentifier::Ty(ty)) => ty.to_string(),
+        Ok(TypeIdentifier::Name(name)) => name,
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+/// Short descriptions of the constraints on a column.
+fn column_keys(column: &AColumn) -> Vec<&'static str> {
+    let mut keys = Vec::new();
+    if column.is_pk() {
+        keys.push("PK");
+    }
+    if referenced_table(column).is_some() {
+        keys.push("FK");
+    }
+    if column.unique() {
+        keys.push("UK");
+    }
+    keys
+}
+
+/// Renders an entity-relationship diagram of the tables in `db`.
+///
+/// Tables backing [butane::Many] fields are shown as relationships
+/// between the tables they join rather than as entities.
+pub fn render(db: &ADB, format: DiagramFormat) -> String {
+    let mut entities = Vec::new();
+    let mut edges = Vec::new();
+    let mut many_edges = Vec::new();
+    for table in db.tables() {
+        if let Some(edge) = many_edge(table) {
+            many_edges.push(edge);
+            continue;
+        }
+        entities.push(table);
+        for column in &table.columns {
+            if let Some(to) = referenced_table(column) {
+                edges.push(Edge {
+                    from: &table.name,
+                    to,
+                    label: column.name(),
+                    kind: EdgeKind::ForeignKey {
+                        nullable: column.nullable(),
+                    },
+                });
+            }
+        }
+    }
+    edges.append(&mut many_edges);
+    match format {
+        DiagramFormat::Mermaid => render_mermaid(&entities, &edges),
+        DiagramFormat::Dot => render_dot(&entities, &edges),
+    }
+}
+
+fn render_mermaid(entities: &[&ATable], edges: &[Edge]) -> String {
+    let mut out = String::from("erDiagram\n");
+    for table in entities {
+        writeln!(out, "    {} {{", table.name).unwrap();
+        for column in &table.columns {
+            write!(
+                out,
+                "        {} {}",
+                type_name(column).replace(' ', "_"),
+                column.name()
+            )
+            .unwrap();
+            let keys = column_keys(column);
+            if !keys.is_empty() {
+                write!(out, " {}", keys.join(", ")).unwrap();
+            }
+            if column.nullable() {
+                out.push_str(" \"nullable\"");
+            }
+            out.push('\n');
+        }
+        out.push_str("    }\n");
+    }
+    for edge in edges {
+        let cardinality = match edge.kind {
+            EdgeKind::ForeignKey { nullable: false } => "}o--||",
+            EdgeKind::ForeignKey { nullable: true } => "}o--o|",
+            EdgeKind::Many => "}o--o{",
+        };
+        writeln!(
+            out,
+            "    {} {cardinality} {} : {}",
+            edge.from, edge.to, edge.label
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Escapes characters with special meaning in a DOT record label.
+fn escape_record(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn render_dot(entities: &[&ATable], edges: &[Edge]) -> String {
+    let mut out = String::from("digraph {\n    node [shape=record];\n");
+    for table in entities {
+        let mut label = format!("{{{}|", escape_record(&table.name));
+        for column in &table.columns {
+            write!(
+                label,
+                "{}: {}",
+                escape_record(column.name()),
+                escape_record(&type_name(column))
+            )
+            .unwrap();
+            let mut keys = column_keys(column);
+            if column.nullable() {
+                keys.push("nullable");
+            }
+            if !keys.is_empty() {
+                write!(label, " ({})", keys.join(", ")).unwrap();
+            }
+            label.push_str("\\l");
+        }
+        label.push('}');
+        writeln!(out, "    \"{}\" [label=\"{label}\"];", table.name).unwrap();
+    }
+    for edge in edges {
+        let style = match edge.kind {
+            EdgeKind::ForeignKey { .. } => "",
+            EdgeKind::Many => ", dir=both, arrowhead=crow, arrowtail=crow",
+        };
+        writeln!(
+            out,
+            "    \"{}\" -> \"{}\" [label=\"{}\"{style}];",
+            edge.from, edge.to, edge.label
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
@@ -13,15 +13,21 @@
     fs::File,
     io::Write,
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
+#[cfg(feature = "pg")]
+use std::process::Command;
+
 use butane::db::Backend;
 use butane::db::{Connection, ConnectionMethods};
 use butane::migrations::adb;
 use butane::migrations::adb::{diff, AColumn, ARef, Operation, ADB};
 use butane::migrations::{
-    copy_migration, FsMigrations, MemMigrations, Migration, MigrationMut, Migrations, MigrationsMut,
+    copy_migration, FsMigrations, HookStage, MemMigrations, Migration, MigrationMut, Migrations,
+    MigrationsMut,
 };
+use butane::partition::{self, PartitionBounds};
 use butane::query::BoolExpr;
 use butane::{db, migrations};
 use cargo_metadata::MetadataCommand;
@@ -29,8 +35,14 @@
 use nonempty::NonEmpty;
 use serde::{Deserialize, Serialize};
 
+pub mod diagram;
+pub use diagram::DiagramFormat;
+
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
 
+/// The environment selected with [`select_environment`].
+static ENVIRONMENT: OnceLock<String> = OnceLock::new();
+
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct CliState {
     embedded: bool,
@@ -70,14 +82,21 @@ pub fn init(base_dir: &PathBuf, name: &str, connstr: &str, connect: bool) -> Res
         db::connect(&spec)?;
     }
     std::fs::create_dir_all(base_dir)?;
-    spec.save(base_dir)?;
+    match ENVIRONMENT.get() {
+        Some(environment) => spec.save_environment(base_dir, environment)?,
+        None => spec.save(base_dir)?,
+    }
 
     Ok(())
 }
 
 /// Make a migration.
 /// The backends are selected from the existing migrations, or the initialised connection.
-pub fn make_migration(base_dir: &Path, name: Option<&String>) -> Result<()> {
+///
+/// If `backfill` is set, NOT NULL columns added to existing tables are
+/// added as nullable, backfilled with their default and then made NOT
+/// NULL, which is safe on large tables.
+pub fn make_migration(base_dir: &Path, name: Option<&String>, backfill: bool) -> Result<()> {
     let name = match name {
         Some(name) => format!("{}_{}", default_name(), name),
         None => default_name(),
@@ -89,7 +108,11 @@ pub fn make_migration(base_dir: &Path, name: Option<&String>) -> Result<()> {
     }
     let backends = load_backends(base_dir)?;
 
-    let created = ms.create_migration(&backends, &name, ms.latest().as_ref())?;
+    let created = if backfill {
+        ms.create_backfill_migration(&backends, &name, ms.latest().as_ref())?
+    } else {
+        ms.create_migration(&backends, &name, ms.latest().as_ref())?
+    };
     if created {
         update_embedded(base_dir)?;
         println!("Created migration {name}");
@@ -158,6 +181,12 @@ pub fn print_ops(ops: Vec<Operation>) -> Result<()> {
             RemoveTable(name) => {
                 println!("Remove table {}", name);
             }
+            RenameTable(from, to) => {
+                println!("Rename table {from} to {to}");
+            }
+            RenameColumn(table_name, from, to) => {
+                println!("Rename column {table_name}.{from} to {table_name}.{to}");
+            }
             AddColumn(table_name, column) => {
                 println!(
                     "New column {table_name}.{}: {:?}",
@@ -165,6 +194,21 @@ pub fn print_ops(ops: Vec<Operation>) -> Result<()> {
                     column.typeid()?
                 );
             }
+            BackfillColumn(table_name, column) => {
+                println!("Backfill column {table_name}.{}", column.name());
+            }
+            AddIndex(table_name, index) => {
+                println!(
+                    "New index on {table_name}.{}",
+                    index.columns().collect::<Vec<_>>().join(", ")
+                );
+            }
+            RemoveIndex(table_name, index) => {
+                println!(
+                    "Remove index on {table_name}.{}",
+                    index.columns().collect::<Vec<_>>().join(", ")
+                );
+            }
             RemoveColumn(table_name, column_name) => {
                 println!("Remove column {table_name}.{column_name}");
             }
@@ -176,6 +220,12 @@ pub fn print_ops(ops: Vec<Operation>) -> Result<()> {
                 println!("Change column {}.{column_name}", table_name);
                 print_column_diff(old, new)?;
             }
+            SetTableComment(table_name, _) => {
+                println!("Change comment on table {table_name}");
+            }
+            SetColumnComment(table_name, column_name, _) => {
+                println!("Change comment on column {table_name}.{column_name}");
+            }
         }
     }
     Ok(())
@@ -240,7 +290,7 @@ pub fn detach_latest_migration(base_dir: &PathBuf) -> Result<()> {
         eprintln!("Can not detach initial migration");
         std::process::exit(1);
     }
-    if let Ok(spec) = db::ConnectionSpec::load(base_dir) {
+    if let Ok(spec) = load_selected_connspec(base_dir) {
         let conn = db::connect(&spec)?;
         if let Some(top_applied_migration) = ms.last_applied_migration(&conn)? {
             if top_applied_migration == top_migration {
@@ -281,6 +331,33 @@ pub fn migrate(base_dir: &PathBuf, name: Option<String>) -> Result<()> {
     Ok(())
 }
 
+/// Check that the database is fully migrated and that no model changes
+/// are missing a migration, exiting with a non-zero status otherwise.
+/// Intended for gating CI and deployments.
+pub fn check_migrations(base_dir: &PathBuf) -> Result<()> {
+    let mut ms = get_migrations(base_dir)?;
+    let mut ok = true;
+    if ms.has_unmigrated_changes()? {
+        eprintln!("Models have changed without a migration. Create one with make-migration.");
+        ok = false;
+    }
+    let spec = load_connspec(base_dir)?;
+    let conn = db::connect(&spec)?;
+    let to_apply = ms.unapplied_migrations(&conn)?;
+    if !to_apply.is_empty() {
+        eprintln!("{} migrations to apply:", to_apply.len());
+        for m in to_apply {
+            eprintln!("  {}", m.name());
+        }
+        ok = false;
+    }
+    if !ok {
+        std::process::exit(1);
+    }
+    println!("Migrations are up to date");
+    Ok(())
+}
+
 pub fn unmigrate(base_dir: &PathBuf, name: Option<String>) -> Result<()> {
     let spec = load_connspec(base_dir)?;
     let conn = butane::db::connect(&spec)?;
@@ -339,6 +416,37 @@ pub fn unmigrate_to(base_dir: &Path, mut conn: Connection, to: &str) -> Result<(
     Ok(())
 }
 
+/// Roll back the latest `steps` applied migrations, latest first. Each
+/// migration is rolled back in its own transaction.
+pub fn unmigrate_steps(base_dir: &PathBuf, steps: usize) -> Result<()> {
+    let spec = load_connspec(base_dir)?;
+    let mut conn = butane::db::connect(&spec)?;
+    let ms = get_migrations(base_dir)?;
+
+    let mut to_unapply = Vec::new();
+    let mut m_opt = ms.last_applied_migration(&conn)?;
+    while let Some(m) = m_opt {
+        if to_unapply.len() == steps {
+            break;
+        }
+        m_opt = m.migration_from()?.and_then(|name| ms.get_migration(&name));
+        to_unapply.push(m);
+    }
+    if to_unapply.len() < steps {
+        eprintln!(
+            "Cannot roll back {steps} migrations, only {} applied.",
+            to_unapply.len()
+        );
+        std::process::exit(1);
+    }
+
+    for m in to_unapply {
+        println!("Rolling back migration {}", m.name());
+        m.downgrade(&mut conn)?;
+    }
+    Ok(())
+}
+
 pub fn unmigrate_latest(base_dir: &Path, mut conn: Connection) -> Result<()> {
     match get_migrations(base_dir)?.last_applied_migration(&conn)? {
         Some(m) => {
@@ -404,13 +512,34 @@ pub fn update_embedded(base_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Select the named environment, whose connection is used instead of
+/// `connection.json`. See [`db::ConnectionSpec::load_environment`].
+pub fn select_environment(name: &str) {
+    ENVIRONMENT
+        .set(name.to_string())
+        .expect("environment already selected");
+}
+
+/// Load the connection spec of the selected environment, or the default
+/// connection spec if no environment has been selected.
+fn load_selected_connspec(base_dir: impl AsRef<Path>) -> butane::Result<db::ConnectionSpec> {
+    match ENVIRONMENT.get() {
+        Some(environment) => db::ConnectionSpec::load_environment(base_dir, environment),
+        None => db::ConnectionSpec::load(base_dir),
+    }
+}
+
 pub fn load_connspec(base_dir: &PathBuf) -> Result<db::ConnectionSpec> {
-    match db::ConnectionSpec::load(base_dir) {
+    match load_selected_connspec(base_dir) {
         Ok(spec) => Ok(spec),
         Err(butane::Error::IO(_)) => {
             eprintln!("No Butane connection info found. Did you run butane init?");
             std::process::exit(1);
         }
+        Err(butane::Error::UnknownEnvironment(environment)) => {
+            eprintln!("No Butane connection info found for environment {environment}. Did you run butane --env {environment} init?");
+            std::process::exit(1);
+        }
         Err(e) => Err(e.into()),
     }
 }
@@ -451,7 +580,7 @@ pub fn add_backend(base_dir: &Path, backend_name: &str) -> Result<()> {
         if from_db.tables().count() == 0 {
             // This is the first migration. Create the butane_migration table
             ops.push(adb::Operation::AddTableIfNotExists(
-                migrations::migrations_table(),
+                migrations::migrations_table(&m.migrations_table()?),
             ));
         }
 
@@ -561,7 +690,7 @@ pub fn load_backends(base_dir: &Path) -> Result<NonEmpty<Box<dyn Backend>>> {
     }
 
     // Otherwise use the backend used during `init`.
-    if let Ok(spec) = db::ConnectionSpec::load(base_dir) {
+    if let Ok(spec) = load_selected_connspec(base_dir) {
         return Ok(nonempty::nonempty![spec.get_backend().unwrap()]);
     }
 
@@ -575,12 +704,23 @@ pub fn list_migrations(base_dir: &PathBuf) -> Result<()> {
     let conn = db::connect(&spec)?;
     let ms = get_migrations(base_dir)?;
     let unapplied = ms.unapplied_migrations(&conn)?;
+    let applied = ms.applied(&conn)?;
     let all = ms.all_migrations()?;
     for m in all {
         let m_state = if unapplied.contains(&m) {
-            "not applied"
+            "not applied".to_string()
         } else {
-            "applied"
+            match applied
+                .iter()
+                .find(|a| a.name == m.name())
+                .and_then(|a| a.applied_at)
+            {
+                Some(applied_at) => format!(
+                    "applied {}",
+                    chrono::DateTime::<Utc>::from(applied_at).format("%Y-%m-%d %H:%M:%S UTC")
+                ),
+                None => "applied".to_string(),
+            }
         };
         println!("Migration '{}' ({})", m.name(), m_state);
     }
@@ -627,6 +767,7 @@ pub fn collapse_migrations(base_dir: &PathBuf, new_initial_name: Option<&String>
     ms.clear_migrations(&conn)?;
     ms.create_migration_to(&backends, &name, None, latest_db)?;
     let new_migration = ms.latest().unwrap();
+    new_migration.prepare_migrations_table(&conn)?;
     new_migration.mark_applied(&conn)?;
 
     update_embedded(base_dir)?;
@@ -652,18 +793,314 @@ pub fn clear_data(base_dir: &PathBuf) -> Result<()> {
             std::process::exit(1);
         }
     };
-    for table in latest.db()?.tables() {
+    for table in latest.db()?.tables().filter(|table| !table.is_view()) {
         println!("Deleting data from {}", &table.name);
         conn.delete_where(&table.name, BoolExpr::True)?;
     }
     Ok(())
 }
 
+/// Create a partition of a table partitioned with `#[butane(partition_by = "...")]`.
+pub fn create_partition(
+    base_dir: &PathBuf,
+    table: &str,
+    name: &str,
+    bounds: &PartitionBounds,
+) -> Result<()> {
+    let spec = load_connspec(base_dir)?;
+    let conn = db::connect(&spec)?;
+    partition::create_partition(&conn, table, name, bounds)?;
+    println!("Created partition {name} of {table}");
+    Ok(())
+}
+
+/// Back up the database to `file`, with `pg_dump` on PostgreSQL and the
+/// backup API on SQLite. With `butane_only`, only the tables of the
+/// models as of the latest applied migration and the table recording
+/// migrations are backed up.
+pub fn backup(base_dir: &PathBuf, file: &Path, butane_only: bool) -> Result<()> {
+    let spec = load_connspec(base_dir)?;
+    let tables = if butane_only {
+        Some(butane_tables(base_dir, &spec)?)
+    } else {
+        None
+    };
+    match spec.backend_name().as_str() {
+        #[cfg(feature = "pg")]
+        db::pg::BACKEND_NAME => {
+            let mut command = pg_tool_command("pg_dump", &spec)?;
+            command
+                .args(["--format=custom", "--no-owner", "--file"])
+                .arg(file);
+            for table in tables.iter().flatten() {
+                command.arg("--table").arg(quote_pg_table(table));
+            }
+            run_tool(command)?;
+        }
+        #[cfg(feature = "sqlite")]
+        db::sqlite::BACKEND_NAME => {
+            open_sqlite(&spec)?.backup(rusqlite::DatabaseName::Main, file, None)?;
+            if let Some(tables) = tables {
+                drop_sqlite_tables_except(&rusqlite::Connection::open(file)?, &tables)?;
+            }
+        }
+        name => {
+            return Err(anyhow::anyhow!(
+                "Backing up {name} databases is not supported"
+            ))
+        }
+    }
+    println!("Backed up database to {}", file.display());
+    Ok(())
+}
+
+/// Restore the database from `file` written by [`backup`]. On SQLite the
+/// whole database is replaced by the backup, whereas on PostgreSQL only
+/// the tables in the backup are.
+pub fn restore(base_dir: &PathBuf, file: &Path) -> Result<()> {
+    let spec = load_connspec(base_dir)?;
+    match spec.backend_name().as_str() {
+        #[cfg(feature = "pg")]
+        db::pg::BACKEND_NAME => {
+            let mut command = pg_tool_command("pg_restore", &spec)?;
+            command
+                .args([
+                    "--clean",
+                    "--if-exists",
+                    "--no-owner",
+                    "--single-transaction",
+                ])
+                .arg(file);
+            run_tool(command)?;
+        }
+        #[cfg(feature = "sqlite")]
+        db::sqlite::BACKEND_NAME => {
+            open_sqlite(&spec)?.restore(
+                rusqlite::DatabaseName::Main,
+                file,
+                None::<fn(rusqlite::backup::Progress)>,
+            )?;
+        }
+        name => {
+            return Err(anyhow::anyhow!(
+                "Restoring {name} databases is not supported"
+            ))
+        }
+    }
+    println!("Restored database from {}", file.display());
+    Ok(())
+}
+
+/// The tables of the models as of the latest applied migration, and the
+/// table recording migrations.
+fn butane_tables(base_dir: &Path, spec: &db::ConnectionSpec) -> Result<Vec<String>> {
+    let conn = db::connect(spec)?;
+    let ms = get_migrations(base_dir)?;
+    let Some(latest) = ms.last_applied_migration(&conn)? else {
+        return Err(anyhow::anyhow!(
+            "No migrations have been applied, so no tables are managed by butane"
+        ));
+    };
+    let mut tables: Vec<String> = latest.db()?.tables().map(|t| t.name.clone()).collect();
+    tables.push(ms.migrations_table()?);
+    Ok(tables)
+}
+
+/// Quotes a table name, which may be qualified with a schema, as a
+/// `pg_dump` pattern matching only that table, under the name
+/// PostgreSQL stores for it.
+#[cfg(feature = "pg")]
+fn quote_pg_table(name: &str) -> String {
+    name.split('.')
+        .map(|part| {
+            let stored = db::pg::stored_identifier(part);
+            format!("\"{}\"", stored.replace('"', "\"\""))
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// A command running the PostgreSQL client tool `program`, such as
+/// `pg_dump`, connecting to the database of `spec`. Only the host, port,
+/// user and database name are passed as arguments, as the arguments of a
+/// process are visible to every user of the machine. The password and
+/// any other parameters are passed in the environment of the process.
+/// The database defaults to the user name, as with libpq, and it is an
+/// error if neither is given.
+#[cfg(feature = "pg")]
+pub fn pg_tool_command(program: &str, spec: &db::ConnectionSpec) -> Result<Command> {
+    use tokio_postgres::config::{Host, SslMode};
+
+    let config: tokio_postgres::Config = spec.connection_string().parse()?;
+    let mut command = Command::new(program);
+    let hosts: Vec<String> = config
+        .get_hosts()
+        .iter()
+        .map(|host| match host {
+            Host::Tcp(name) => name.clone(),
+            Host::Unix(path) => path.to_string_lossy().into_owned(),
+        })
+        .collect();
+    if !hosts.is_empty() {
+        command.arg("--host").arg(hosts.join(","));
+    }
+    let ports: Vec<String> = config.get_ports().iter().map(u16::to_string).collect();
+    if !ports.is_empty() {
+        command.arg("--port").arg(ports.join(","));
+    }
+    if let Some(user) = config.get_user() {
+        command.arg("--username").arg(user);
+    }
+    // Without a database pg_restore writes a script rather than restoring
+    let dbname = config.get_dbname().or(config.get_user()).ok_or_else(|| {
+        anyhow::anyhow!("The connection string names neither a database nor a user")
+    })?;
+    command.arg("--dbname").arg(dbname);
+    if let Some(password) = config.get_password() {
+        command.env("PGPASSWORD", std::str::from_utf8(password)?);
+    }
+    let hostaddrs: Vec<String> = config
+        .get_hostaddrs()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    if !hostaddrs.is_empty() {
+        command.env("PGHOSTADDR", hostaddrs.join(","));
+    }
+    if let Some(options) = config.get_options() {
+        command.env("PGOPTIONS", options);
+    }
+    if let Some(name) = config.get_application_name() {
+        command.env("PGAPPNAME", name);
+    }
+    match config.get_ssl_mode() {
+        SslMode::Disable => command.env("PGSSLMODE", "disable"),
+        SslMode::Require => command.env("PGSSLMODE", "require"),
+        _ => command.env("PGSSLMODE", "prefer"),
+    };
+    Ok(command)
+}
+
+/// Runs an external tool such as `pg_dump`, failing if it does.
+#[cfg(feature = "pg")]
+fn run_tool(mut command: Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .map_err(|e| anyhow::anyhow!("Could not run {program}: {e}"))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{program} failed with {status}"));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(spec: &db::ConnectionSpec) -> Result<rusqlite::Connection> {
+    let (path, _) = db::sqlite::SQLiteOptions::from_connection_string(spec.connection_string())?;
+    Ok(rusqlite::Connection::open(path)?)
+}
+
+/// Drops the tables of the SQLite database `conn` other than `keep`.
+#[cfg(feature = "sqlite")]
+fn drop_sqlite_tables_except(conn: &rusqlite::Connection, keep: &[String]) -> Result<()> {
+    let tables = conn
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    for table in tables.iter().filter(|table| !keep.contains(table)) {
+        conn.execute_batch(&format!("DROP TABLE \"{}\";", table.replace('"', "\"\"")))?;
+    }
+    conn.execute_batch("VACUUM;")?;
+    Ok(())
+}
+
+/// Set the commands run at `stage` of applying the migration `name` on
+/// `backend_name`, or remove them if `sql` is `None`.
+pub fn set_migration_hook(
+    base_dir: &Path,
+    name: &str,
+    stage: &str,
+    backend_name: &str,
+    sql: Option<&str>,
+) -> Result<()> {
+    let ms = get_migrations(base_dir)?;
+    let Some(mut migration) = ms.get_migration(name) else {
+        return Err(anyhow::anyhow!("No such migration {name}"));
+    };
+    if !migration.sql_backends()?.iter().any(|b| b == backend_name) {
+        return Err(anyhow::anyhow!(
+            "Migration {name} has no SQL for backend {backend_name}"
+        ));
+    }
+    let stage: HookStage = stage.parse()?;
+    migration.set_hook_sql(backend_name, stage, sql)?;
+    update_embedded(base_dir)?;
+    Ok(())
+}
+
+/// Show the table recording which migrations have been applied, or set
+/// it if `name` is given and no migrations have been created yet.
+pub fn migrations_table(base_dir: &Path, name: Option<&str>) -> Result<()> {
+    let mut ms = get_migrations(base_dir)?;
+    let Some(name) = name else {
+        match ms.latest() {
+            Some(latest) => println!("{}", latest.migrations_table()?),
+            None => println!("{}", ms.current().migrations_table()?),
+        }
+        return Ok(());
+    };
+    if ms.latest().is_some() {
+        return Err(anyhow::anyhow!(
+            "The migrations table can only be set before the first migration is created."
+        ));
+    }
+    ms.current().set_migrations_table(name)?;
+    println!("Migrations table set to {name}.");
+    Ok(())
+}
+
 pub fn clean(base_dir: &Path) -> Result<()> {
     get_migrations(base_dir)?.clear_current()?;
     Ok(())
 }
 
+/// Show the identifier case policy, or set it if `case` is given.
+pub fn identifier_case(base_dir: &Path, case: Option<&str>) -> Result<()> {
+    let mut ms = get_migrations(base_dir)?;
+    let Some(case) = case else {
+        println!("{}", ms.current().identifier_case()?);
+        return Ok(());
+    };
+    let case: adb::IdentifierCase = case.parse()?;
+    // Tables recorded under the old policy would otherwise linger in
+    // the current state alongside their renamed counterparts.
+    ms.clear_current()?;
+    ms.current().set_identifier_case(case)?;
+    println!(
+        "Identifier case set to {case}. Rebuild the models, then create a migration to apply it to existing databases."
+    );
+    Ok(())
+}
+
+/// Print an entity-relationship diagram of the schema as of the
+/// migration `name`, which may be `current`, or else the latest migration.
+pub fn diagram(base_dir: &Path, name: Option<&str>, format: &str) -> Result<()> {
+    let format: DiagramFormat = format.parse()?;
+    let mut ms = get_migrations(base_dir)?;
+    let db = match name {
+        Some("current") => ms.current().db()?,
+        Some(name) => ms
+            .get_migration(name)
+            .ok_or_else(|| CliError::NoSuchMigration(name.to_string()))?
+            .db()?,
+        None => ms.latest().ok_or(CliError::NoMigrations)?.db()?,
+    };
+    print!("{}", diagram::render(&db, format));
+    Ok(())
+}
+
 pub fn get_migrations(base_dir: &Path) -> Result<FsMigrations> {
     let root = base_dir.join("migrations");
     if root.is_dir() {
@@ -745,6 +1182,10 @@ pub enum CliError {
         "No butane migrations directory found. Add at least one model to your project and build."
     )]
     NoButaneMigrationsDir,
+    #[error("No such migration {0}")]
+    NoSuchMigration(String),
+    #[error("There are no migrations. Create one with make-migration, or use 'current'.")]
+    NoMigrations,
 }
 
 pub fn handle_error(r: Result<()>) {
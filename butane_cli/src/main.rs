@@ -3,13 +3,16 @@
 
 use std::path::PathBuf;
 
+use butane::partition::PartitionBounds;
+use butane::SqlVal;
 use butane_cli::{
-    add_backend, base_dir, clean, clear_data, collapse_migrations, delete_table,
-    describe_migration, detach_latest_migration, embed, get_migrations, handle_error, init,
-    list_backends, list_migrations, make_migration, migrate, regenerate_migrations, remove_backend,
-    unmigrate,
+    add_backend, backup, base_dir, check_migrations, clean, clear_data, collapse_migrations,
+    create_partition, delete_table, describe_migration, detach_latest_migration, diagram, embed,
+    get_migrations, handle_error, identifier_case, init, list_backends, list_migrations,
+    make_migration, migrate, migrations_table, regenerate_migrations, remove_backend, restore,
+    select_environment, set_migration_hook, unmigrate, unmigrate_steps,
 };
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{ArgAction, ArgGroup, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(author, version, about = "Manages butane database migrations.")]
@@ -19,6 +22,9 @@ struct Cli {
     command: Commands,
     #[arg(short = 'p', long, default_value=base_dir().into_os_string())]
     path: PathBuf,
+    /// Named environment, such as 'dev' or 'prod', whose connection to use instead of the default connection.
+    #[arg(short = 'e', long = "env", global = true)]
+    environment: Option<String>,
     #[command(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
 }
@@ -37,6 +43,10 @@ enum Commands {
     MakeMigration {
         /// Name to use for the migration.
         name: String,
+        /// Add NOT NULL columns to existing tables as nullable, backfill
+        /// them with their default, then make them NOT NULL.
+        #[arg(long)]
+        safe: bool,
     },
     /// Detach the latest migration.
     #[command(
@@ -57,6 +67,9 @@ enum Commands {
     Migrate {
         /// Migration to migrate to.
         name: Option<String>,
+        /// Apply nothing, but exit non-zero if there are unapplied migrations or model changes without a migration.
+        #[arg(long, conflicts_with = "name")]
+        check: bool,
     },
     /// Regenerate migrations in place.
     Regenerate,
@@ -73,11 +86,14 @@ enum Commands {
     },
     /// Embed migrations in the source code.
     Embed,
-    /// Undo migrations. With no arguments, undoes the latest migration. If the name of a migration is specified, rolls back until that migration is the latest applied migration.
+    /// Undo migrations. With no arguments, undoes the latest migration. If the name of a migration is specified, rolls back until that migration is the latest applied migration. With --steps, rolls back that many of the latest applied migrations.
     #[command(alias = "rollback")]
     Unmigrate {
         /// Migration to roll back to.
         name: Option<String>,
+        /// Number of applied migrations to roll back, latest first.
+        #[arg(long, conflicts_with = "name", value_parser = clap::value_parser!(u32).range(1..))]
+        steps: Option<u32>,
     },
     /// Clear.
     Clear {
@@ -89,6 +105,47 @@ enum Commands {
         #[clap(subcommand)]
         subcommand: DeleteCommands,
     },
+    /// Show or set the case policy for table and column names. With 'lower', all names are folded to lowercase so that migrations behave identically on backends which fold unquoted identifiers (PostgreSQL) and those which do not (SQLite).
+    IdentifierCase {
+        /// Policy to use, 'preserve' (the default) or 'lower'.
+        case: Option<String>,
+    },
+    /// Show or set the table recording which migrations have been applied, 'butane_migrations' by default. It may be qualified with an existing schema, e.g. 'tooling.butane_migrations', and can only be set before the first migration is created.
+    MigrationsTable {
+        /// Name of the table.
+        name: Option<String>,
+    },
+    /// Partitions of tables partitioned with `#[butane(partition_by = "...")]`. PostgreSQL only.
+    Partition {
+        #[clap(subcommand)]
+        subcommand: PartitionCommands,
+    },
+    /// Commands run immediately before or after a migration is applied, such as disabling triggers or `ANALYZE`. They are recorded with the migration, so only take effect if it has not been applied yet.
+    Hook {
+        #[clap(subcommand)]
+        subcommand: HookCommands,
+    },
+    /// Print an entity-relationship diagram of the tables, columns and relationships between them.
+    Diagram {
+        /// Output format, 'mermaid' or 'dot'.
+        #[arg(short, long, default_value = "mermaid", value_parser = ["mermaid", "dot"])]
+        format: String,
+        /// Migration to describe the schema of, or `current`. Defaults to the latest migration.
+        name: Option<String>,
+    },
+    /// Back up the database to a file, with pg_dump on PostgreSQL and the SQLite backup API on SQLite.
+    Backup {
+        /// File to write the backup to.
+        file: PathBuf,
+        /// Only back up the tables of the models as of the latest applied migration, and the table recording migrations.
+        #[arg(long)]
+        butane_only: bool,
+    },
+    /// Restore the database from a file written by backup. On SQLite the whole database is replaced, whereas on PostgreSQL only the tables in the backup are replaced, using pg_restore.
+    Restore {
+        /// File to restore the backup from.
+        file: PathBuf,
+    },
     /// Clean current migration state. Deletes the current migration working state which is generated on each build. This can be used as a workaround to remove stale tables from the schema, as Butane does not currently auto-detect model removals. The next build will recreate with only tables for the extant models.
     Clean,
 }
@@ -135,6 +192,66 @@ enum DeleteCommands {
     },
 }
 
+#[derive(Subcommand)]
+enum PartitionCommands {
+    /// Create a partition of a partitioned table, holding the values given by exactly one of --from and --to, --in, --modulus and --remainder, or --default.
+    #[command(group(
+        ArgGroup::new("bounds")
+            .required(true)
+            .args(["from", "values", "modulus", "default"])
+    ))]
+    Create {
+        /// Name of the partitioned table.
+        table: String,
+        /// Name of the new partition.
+        name: String,
+        /// Lowest value held by a range partition.
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+        /// Lowest value above those held by a range partition.
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+        /// Values held by a list partition.
+        #[arg(long = "in", value_name = "VALUE", num_args = 1..)]
+        values: Vec<String>,
+        /// Divisor of the hash of values, for a hash partition.
+        #[arg(long, requires = "remainder")]
+        modulus: Option<u32>,
+        /// Remainder of the hash of values held by a hash partition.
+        #[arg(long, requires = "modulus")]
+        remainder: Option<u32>,
+        /// Hold the values not held by any other partition.
+        #[arg(long)]
+        default: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HookCommands {
+    /// Set the commands run at a stage of applying a migration on a backend, replacing any already set.
+    Set {
+        /// Name of the migration.
+        migration: String,
+        /// When the commands run, 'before' or 'after' the migration.
+        #[arg(value_parser = ["before", "after"])]
+        stage: String,
+        /// Backend name the commands are for.
+        backend: String,
+        /// SQL commands to run.
+        sql: String,
+    },
+    /// Remove the commands run at a stage of applying a migration on a backend.
+    Remove {
+        /// Name of the migration.
+        migration: String,
+        /// When the commands run, 'before' or 'after' the migration.
+        #[arg(value_parser = ["before", "after"])]
+        stage: String,
+        /// Backend name the commands are for.
+        backend: String,
+    },
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -142,6 +259,10 @@ fn main() {
         .filter_level(cli.verbose.log_level_filter())
         .init();
 
+    if let Some(environment) = &cli.environment {
+        select_environment(environment);
+    }
+
     let mut base_dir = cli.path;
     if !base_dir.ends_with(".butane") {
         base_dir.push(".butane");
@@ -173,12 +294,18 @@ fn main() {
             BackendCommands::Remove { name } => handle_error(remove_backend(&base_dir, name)),
             BackendCommands::List => handle_error(list_backends(&base_dir)),
         },
-        Commands::MakeMigration { name } => handle_error(make_migration(&base_dir, Some(name))),
+        Commands::MakeMigration { name, safe } => {
+            handle_error(make_migration(&base_dir, Some(name), *safe))
+        }
         Commands::DescribeMigration { name } => handle_error(describe_migration(&base_dir, name)),
         Commands::Regenerate => handle_error(regenerate_migrations(&base_dir)),
         Commands::DetachMigration => handle_error(detach_latest_migration(&base_dir)),
-        Commands::Migrate { name } => handle_error(migrate(&base_dir, name.to_owned())),
-        Commands::Unmigrate { name } => handle_error(unmigrate(&base_dir, name.to_owned())),
+        Commands::Migrate { check: true, .. } => handle_error(check_migrations(&base_dir)),
+        Commands::Migrate { name, .. } => handle_error(migrate(&base_dir, name.to_owned())),
+        Commands::Unmigrate {
+            steps: Some(steps), ..
+        } => handle_error(unmigrate_steps(&base_dir, *steps as usize)),
+        Commands::Unmigrate { name, .. } => handle_error(unmigrate(&base_dir, name.to_owned())),
         Commands::Embed => handle_error(embed(&base_dir)),
         Commands::List => handle_error(list_migrations(&base_dir)),
         Commands::Collapse { name } => handle_error(collapse_migrations(&base_dir, Some(name))),
@@ -189,5 +316,70 @@ fn main() {
             DeleteCommands::Table { name } => handle_error(delete_table(&base_dir, name)),
         },
         Commands::Clean => handle_error(clean(&base_dir)),
+        Commands::Backup { file, butane_only } => {
+            handle_error(backup(&base_dir, file, *butane_only))
+        }
+        Commands::Restore { file } => handle_error(restore(&base_dir, file)),
+        Commands::Diagram { format, name } => {
+            handle_error(diagram(&base_dir, name.as_deref(), format))
+        }
+        Commands::Partition { subcommand } => match subcommand {
+            PartitionCommands::Create {
+                table,
+                name,
+                from,
+                to,
+                values,
+                modulus,
+                remainder,
+                default,
+            } => {
+                // Values are passed as text, which the database converts
+                // to the type of the partition column.
+                let bounds = if let (Some(from), Some(to)) = (from, to) {
+                    PartitionBounds::Range {
+                        from: SqlVal::Text(from.clone()),
+                        to: SqlVal::Text(to.clone()),
+                    }
+                } else if let (Some(modulus), Some(remainder)) = (modulus, remainder) {
+                    PartitionBounds::Hash {
+                        modulus: *modulus,
+                        remainder: *remainder,
+                    }
+                } else if *default {
+                    PartitionBounds::Default
+                } else {
+                    PartitionBounds::List(values.iter().cloned().map(SqlVal::Text).collect())
+                };
+                handle_error(create_partition(&base_dir, table, name, &bounds))
+            }
+        },
+        Commands::Hook { subcommand } => match subcommand {
+            HookCommands::Set {
+                migration,
+                stage,
+                backend,
+                sql,
+            } => handle_error(set_migration_hook(
+                &base_dir,
+                migration,
+                stage,
+                backend,
+                Some(sql),
+            )),
+            HookCommands::Remove {
+                migration,
+                stage,
+                backend,
+            } => handle_error(set_migration_hook(
+                &base_dir, migration, stage, backend, None,
+            )),
+        },
+        Commands::MigrationsTable { name } => {
+            handle_error(migrations_table(&base_dir, name.as_deref()))
+        }
+        Commands::IdentifierCase { case } => {
+            handle_error(identifier_case(&base_dir, case.as_deref()))
+        }
     }
 }
@@ -0,0 +1,37 @@
+use butane::migrations::{Migration, Migrations};
+use butane_cli::diagram::render;
+use butane_cli::DiagramFormat;
+
+fn example_db() -> butane::migrations::adb::ADB {
+    let example_dir = std::env::current_dir()
+        .unwrap()
+        .join("../examples/getting_started/.butane");
+    let ms = butane_cli::get_migrations(&example_dir).unwrap();
+    ms.latest().unwrap().db().unwrap()
+}
+
+#[test]
+fn diagram_mermaid() {
+    let diagram = render(&example_db(), DiagramFormat::Mermaid);
+    let lines: Vec<&str> = diagram.lines().collect();
+    assert_eq!(lines[0], "erDiagram");
+    assert!(lines.contains(&"        big_int id PK"));
+    assert!(lines.contains(&"        big_int blog FK"));
+    assert!(lines.contains(&"        string byline \"nullable\""));
+    assert!(lines.contains(&"    Post }o--|| Blog : blog"));
+    assert!(lines.contains(&"    Post }o--o{ Tag : tags"));
+    // The table backing the Many field is shown only as a relationship
+    assert!(!diagram.contains("Post_tags_Many"));
+}
+
+#[test]
+fn diagram_dot() {
+    let diagram = render(&example_db(), DiagramFormat::Dot);
+    assert!(diagram.starts_with("digraph {\n"));
+    assert!(diagram.contains("\"Tag\" [label=\"{Tag|tag: string (PK)\\l}\"];"));
+    assert!(diagram.contains("\"Post\" -> \"Blog\" [label=\"blog\"];"));
+    assert!(diagram.contains(
+        "\"Post\" -> \"Tag\" [label=\"tags\", dir=both, arrowhead=crow, arrowtail=crow];"
+    ));
+    assert!(diagram.ends_with("}\n"));
+}
@@ -1,21 +1,61 @@
 use butane_core::make_compile_error;
+use butane_core::migrations::adb::{AColumn, ATable, IdentifierCase, TypeIdentifier, ADB};
+use butane_core::SqlType;
 use proc_macro2::Span;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, quote_spanned, ToTokens};
-use syn::{spanned::Spanned, BinOp, Expr, ExprBinary, ExprMethodCall, ExprPath, Ident, LitStr};
+use syn::{
+    spanned::Spanned, BinOp, Expr, ExprBinary, ExprMethodCall, ExprPath, Ident, Lit, LitStr,
+};
 
-pub fn for_expr(dbres: &Ident, expr: &Expr) -> TokenStream2 {
-    handle_expr(&quote!(<#dbres as butane::DataResult>::DBO::fields()), expr)
+use crate::update::value_expr;
+
+/// The table of the model being filtered, as of the current migration.
+/// Used to check literals compared with its fields at compile time.
+pub struct ModelTable {
+    table: ATable,
+    identifier_case: IdentifierCase,
+}
+
+impl ModelTable {
+    /// Finds the table of the model `dbres` in `db`, if it is a model
+    /// whose table is named after it.
+    pub fn find(db: &ADB, dbres: &Ident) -> Option<Self> {
+        let identifier_case = db.identifier_case();
+        let table = db.get_table(&identifier_case.fold(&dbres.to_string()))?;
+        Some(ModelTable {
+            table: table.clone(),
+            identifier_case,
+        })
+    }
+
+    fn column(&self, field: &ExprPath) -> Option<&AColumn> {
+        let name = field.path.get_ident()?.to_string();
+        let name = name.strip_prefix("r#").unwrap_or(&name);
+        self.table.column(&self.identifier_case.fold(name))
+    }
 }
 
-pub fn handle_expr(fields: &impl ToTokens, expr: &Expr) -> TokenStream2 {
+pub fn for_expr(dbres: &Ident, expr: &Expr, table: Option<&ModelTable>) -> TokenStream2 {
+    handle_expr(
+        &quote!(<#dbres as butane::DataResult>::DBO::fields()),
+        expr,
+        table,
+    )
+}
+
+pub fn handle_expr(
+    fields: &impl ToTokens,
+    expr: &Expr,
+    table: Option<&ModelTable>,
+) -> TokenStream2 {
     match expr {
-        Expr::Binary(binop) => handle_bin_op(fields, binop),
-        Expr::MethodCall(mcall) => handle_call(fields, mcall),
+        Expr::Binary(binop) => handle_bin_op(fields, binop, table),
+        Expr::MethodCall(mcall) => handle_call(fields, mcall, table),
         Expr::Path(path) => handle_path(fields, path),
         Expr::Lit(lit) => lit.lit.clone().into_token_stream(),
         Expr::Block(block) => handle_block(&block.block),
-        Expr::Group(group) => handle_expr(fields, group.expr.as_ref()),
+        Expr::Group(group) => handle_expr(fields, group.expr.as_ref(), table),
         _ => {
             let lit = LitStr::new(
                 &format!(
@@ -38,9 +78,33 @@ fn handle_block(block: &syn::Block) -> TokenStream2 {
     quote!(#(#stmts)*)
 }
 
-fn handle_bin_op(fields: &impl ToTokens, binop: &ExprBinary) -> TokenStream2 {
-    let left = handle_expr(fields, &binop.left);
-    let right = handle_expr(fields, &binop.right);
+fn handle_bin_op(
+    fields: &impl ToTokens,
+    binop: &ExprBinary,
+    table: Option<&ModelTable>,
+) -> TokenStream2 {
+    let is_comparison = matches!(
+        binop.op,
+        BinOp::Eq(_) | BinOp::Ne(_) | BinOp::Lt(_) | BinOp::Gt(_) | BinOp::Le(_) | BinOp::Ge(_)
+    );
+    if is_comparison {
+        if is_call(&binop.left) || is_call(&binop.right) {
+            let op = match binop.op {
+                BinOp::Eq(_) => quote!(Eq),
+                BinOp::Ne(_) => quote!(Ne),
+                BinOp::Lt(_) => quote!(Lt),
+                BinOp::Gt(_) => quote!(Gt),
+                BinOp::Le(_) => quote!(Le),
+                _ => quote!(Ge),
+            };
+            return compare(fields, &binop.left, op, &binop.right);
+        }
+        if let Some(err) = check_comparison(table, &binop.left, &binop.right) {
+            return err;
+        }
+    }
+    let left = handle_expr(fields, &binop.left, table);
+    let right = handle_expr(fields, &binop.right, table);
     match binop.op {
         BinOp::Eq(_) => quote!(#left.eq(&#right)),
         BinOp::Ne(_) => quote!(#left.ne(&#right)),
@@ -54,21 +118,49 @@ fn handle_bin_op(fields: &impl ToTokens, binop: &ExprBinary) -> TokenStream2 {
     }
 }
 
-fn handle_call(fields: &impl ToTokens, mcall: &ExprMethodCall) -> TokenStream2 {
-    let method = mcall.method.to_string();
-    match method.as_str() {
-        "contains" | "matches" => {
-            if mcall.args.len() != 1 {
-                return make_compile_error!(mcall.span()=> "expected one argument to '{}'", method);
-            };
-        }
-        _ => (),
+fn is_call(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(_) => true,
+        Expr::Paren(paren) => is_call(&paren.expr),
+        Expr::Group(group) => is_call(&group.expr),
+        _ => false,
+    }
+}
+
+/// Compares two expressions evaluated by the database, at least one of
+/// which calls an SQL function.
+fn compare(fields: &impl ToTokens, left: &Expr, op: TokenStream2, right: &Expr) -> TokenStream2 {
+    let fields = fields.to_token_stream();
+    let left = match value_expr(&fields, left) {
+        Ok(left) => left,
+        Err(err) => return err,
+    };
+    let right = match value_expr(&fields, right) {
+        Ok(right) => right,
+        Err(err) => return err,
     };
+    quote!(butane::query::BoolExpr::Compare(#left, butane::query::CompareOp::#op, #right))
+}
+
+fn handle_call(
+    fields: &impl ToTokens,
+    mcall: &ExprMethodCall,
+    table: Option<&ModelTable>,
+) -> TokenStream2 {
+    let method = mcall.method.to_string();
+    if matches!(
+        method.as_str(),
+        "contains" | "contains_all" | "overlaps" | "matches"
+    ) && mcall.args.len() != 1
+    {
+        return make_compile_error!(mcall.span()=> "expected one argument to '{}'", method);
+    }
     let first_arg = || mcall.args.first().unwrap();
     match method.as_str() {
         "matches" => handle_matches(fields, &mcall.receiver, first_arg()),
         "contains" => handle_contains(fields, &mcall.receiver, first_arg()),
-        "like" => handle_like(fields, &mcall.receiver, first_arg()),
+        "contains_all" | "overlaps" => handle_list(fields, mcall),
+        "like" => handle_like(fields, &mcall.receiver, first_arg(), table),
         "is_in" => handle_in(fields, &mcall.receiver, first_arg()),
         _ => make_compile_error!("Unknown method call {}", method),
     }
@@ -86,7 +178,7 @@ fn handle_matches(fields: &impl ToTokens, receiver: &Expr, expr: &Expr) -> Token
         quote!(#fex.subfilterpk(#lit))
     } else {
         // Arbitrary expression
-        let q = handle_expr(&quote!(#fex.fields()), expr);
+        let q = handle_expr(&quote!(#fex.fields()), expr, None);
         let span = receiver.span();
         quote_spanned!(span=> #fex.subfilter(#q))
     }
@@ -99,20 +191,41 @@ fn handle_contains(fields: &impl ToTokens, receiver: &Expr, expr: &Expr) -> Toke
         quote!(#fex.containspk(#lit))
     } else {
         // Arbitrary expression
-        let q = handle_expr(&quote!(#fex.fields()), expr);
+        let q = handle_expr(&quote!(#fex.fields()), expr, None);
         let span = receiver.span();
         quote_spanned!(span=> #fex.contains(#q))
     }
 }
 
-fn handle_like(fields: &impl ToTokens, receiver: &Expr, expr: &Expr) -> TokenStream2 {
+/// Filters a list field by the values of a Rust expression, such as
+/// `tags.overlaps(["rust", "sql"])`.
+fn handle_list(fields: &impl ToTokens, mcall: &ExprMethodCall) -> TokenStream2 {
+    let fex = fieldexpr(fields, &mcall.receiver);
+    let method = &mcall.method;
+    let vals = mcall.args.first().unwrap();
+    let span = mcall.receiver.span();
+    quote_spanned!(span=> #fex.#method(#vals))
+}
+
+fn handle_like(
+    fields: &impl ToTokens,
+    receiver: &Expr,
+    expr: &Expr,
+    table: Option<&ModelTable>,
+) -> TokenStream2 {
+    if is_call(receiver) {
+        return compare(fields, receiver, quote!(Like), expr);
+    }
+    if let Some(err) = check_like(table, receiver, expr) {
+        return err;
+    }
     let fex = fieldexpr(fields, receiver);
     match expr {
         Expr::Binary(_) => make_compile_error!("Unexpected binary expression as parameter to like"),
         Expr::Call(_) => make_compile_error!("Unexpected call expression as parameter to like"),
         _ => {
             // Arbitrary expression
-            let q = handle_expr(fields, expr);
+            let q = handle_expr(fields, expr, table);
             let span = receiver.span();
             quote_spanned!(span=> #fex.like(#q))
         }
@@ -134,3 +247,76 @@ fn fieldexpr<F>(fields: &impl ToTokens, field: &F) -> TokenStream2
     let span = field.span();
     quote_spanned!(span=> #fields.#fieldexpr_ident())
 }
+
+/// The column of `field` and its type, if `field` names a column whose
+/// type is known from the current migration.
+pub(crate) fn column_type<'a>(
+    table: Option<&'a ModelTable>,
+    field: &Expr,
+) -> Option<(&'a AColumn, SqlType)> {
+    let Expr::Path(field) = field else {
+        return None;
+    };
+    let column = table?.column(field)?;
+    match column.typeid() {
+        Ok(TypeIdentifier::Ty(ty)) => Some((column, ty)),
+        _ => None,
+    }
+}
+
+/// Whether a literal may be compared with a column of type `ty`, or
+/// `None` if that cannot be decided from the literal alone.
+pub(crate) fn literal_matches(lit: &Lit, ty: &SqlType) -> Option<bool> {
+    if !matches!(
+        lit,
+        Lit::Str(_) | Lit::ByteStr(_) | Lit::Int(_) | Lit::Float(_) | Lit::Bool(_)
+    ) {
+        return None;
+    }
+    match ty {
+        SqlType::Bool => Some(matches!(lit, Lit::Bool(_))),
+        SqlType::Int | SqlType::BigInt => Some(matches!(lit, Lit::Int(_))),
+        SqlType::Real => Some(matches!(lit, Lit::Float(_))),
+        SqlType::Text => Some(matches!(lit, Lit::Str(_))),
+        SqlType::Blob => Some(matches!(lit, Lit::ByteStr(_))),
+        // Dates, JSON and custom types have no literal syntax of their own
+        _ => None,
+    }
+}
+
+/// Checks a literal or `None` compared with a field against the type of
+/// the field's column, returning a compile error if they cannot match.
+fn check_comparison(
+    table: Option<&ModelTable>,
+    field: &Expr,
+    value: &Expr,
+) -> Option<TokenStream2> {
+    let (column, ty) = column_type(table, field)?;
+    match value {
+        Expr::Path(path) if path.path.is_ident("None") && !column.nullable() => Some(
+            make_compile_error!(value.span()=> "field '{}' is not nullable, so cannot be compared with None", column.name()),
+        ),
+        Expr::Lit(lit) if literal_matches(&lit.lit, &ty) == Some(false) => Some(
+            make_compile_error!(value.span()=> "field '{}' has type {}, so cannot be compared with '{}'", column.name(), ty, lit.lit.to_token_stream()),
+        ),
+        _ => None,
+    }
+}
+
+/// Checks that `like` is used on a text field with a string pattern.
+fn check_like(table: Option<&ModelTable>, field: &Expr, pattern: &Expr) -> Option<TokenStream2> {
+    if let Expr::Lit(lit) = pattern {
+        if !matches!(lit.lit, Lit::Str(_)) {
+            return Some(
+                make_compile_error!(pattern.span()=> "the pattern of 'like' must be a string, not '{}'", lit.lit.to_token_stream()),
+            );
+        }
+    }
+    let (column, ty) = column_type(table, field)?;
+    if ty == SqlType::Text {
+        return None;
+    }
+    Some(
+        make_compile_error!(field.span()=> "field '{}' has type {}, so cannot be used with 'like'", column.name(), ty),
+    )
+}
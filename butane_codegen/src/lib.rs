@@ -16,26 +16,120 @@
 use syn::{Expr, Ident};
 
 mod filter;
+mod update;
 
 #[cfg(test)]
 mod test_field_type;
+#[cfg(test)]
+mod test_filter;
 
 /// Attribute macro which marks a struct as being a data model and
-/// generates an implementation of [`DataObject`](butane_core::DataObject). This
+/// generates an implementation of [`DataObject`](butane_core::DataObject), as well
+/// as of the object-safe [`DynDataObject`](butane_core::DynDataObject). This
 /// macro will also write information to disk at compile time necessary to
 /// generate migrations
 ///
 /// ## Restrictions on model types:
-/// 1. The type of each field must implement [`FieldType`] or be [`Many`].
+/// 1. The type of each field must implement [`FieldType`] or be [`Many`], [`OrderedMany`] or [`ManyThrough`].
 /// 2. There must be a primary key field. This must be either annotated with a `#[pk]` attribute or named `id`.
 ///
 /// ## Helper Attributes
 /// * `#[table = "NAME"]` used on the struct to specify the name of the table (defaults to struct name)
-/// * `#[pk]` on a field to specify that it is the primary key.
+/// * `#[pk]` on a field to specify that it is the primary key. A [`ForeignKey`] may be the
+///   primary key, so that the model shares the primary key of the one it refers to as a
+///   one-to-one extension of it. The column is then both its primary key and a reference to the
+///   other table, and the model it refers to must implement `Clone`.
 /// * `#[unique]` on a field indicates that the field's value must be unique
 ///   (perhaps implemented as the SQL UNIQUE constraint by some backends).
 /// * `#[default]` should be used on fields added by later migrations to avoid errors on existing objects.
 ///   Unnecessary if the new field is an `Option<>`
+/// * `#[readonly]` on a field populated by the database, such as one with a `#[default]` or a
+///   generated column. It is never written on save, but is refreshed with the value in the
+///   database afterwards, using `INSERT ... RETURNING` where supported.
+/// * `#[refresh]` on a field which the database may change, such as with a trigger. It is written
+///   on save as usual, then refreshed with the value in the database like a `#[readonly]` field.
+/// * `#[butane(many_table = "NAME")]` on a [`Many`] field to specify the name of its table
+///   (defaults to `{table}_{field}_Many`), e.g. to map onto an existing schema.
+/// * `#[butane(allow_duplicates)]` on a [`Many`] field to let it relate the same value more than
+///   once. Otherwise its table has a unique index on `owner` and `has`, and adding a value which
+///   is already related does nothing.
+/// * `#[butane(on_delete = "cascade" | "set_null" | "restrict")]` on a [`ForeignKey`] field to
+///   specify what happens to this object when the one it refers to is deleted. `set_null`
+///   requires an `Option<ForeignKey<T>>`.
+/// * `#[butane(collate = "NAME")]` on a field to compare, order and check the uniqueness of its
+///   values with the named collation, e.g. `"NOCASE"` to ignore case. PostgreSQL quotes the name,
+///   so it may be any collation in the database such as the ICU collation `"und-x-icu"`, and
+///   `NOCASE` creates and uses a case-insensitive ICU collation. As it is nondeterministic,
+///   PostgreSQL does not support `LIKE` on such a column before version 18.
+/// * `#[butane(auto_uuid = "v7")]` on a `Uuid` primary key to generate a time-ordered UUID on save
+///   if it is nil, which gives better index locality than random UUIDs.
+/// * `#[butane(pk_generator = "PATH")]` on the primary key to set it on save, if it has its
+///   default value, with the [`PkGenerator`](butane_core::pkgen::PkGenerator) at `PATH`, such as
+///   a function returning the key. See [`pkgen`](butane_core::pkgen) for the generators provided.
+/// * `#[butane(backfill)]` on a field added to an existing model to add its column in steps
+///   which are safe on a populated table: as nullable, then filled in with its default in
+///   batches, then made `NOT NULL`. `butane makemigration --safe` does this for every field.
+/// * `#[butane(index)]` on a field to index its column.
+/// * `#[butane(index(concurrently))]` on a field to build its index on PostgreSQL without
+///   blocking writes to the table, when it is added to a column which already exists. The
+///   statements building and dropping it run before the transaction applying the rest of the
+///   migration, and an index left invalid by a failed attempt is dropped when it is retried.
+/// * `#[butane(sensitive)]` on a field whose values must not appear in logs or errors, such as a
+///   password hash or token. The parameters of statements on its table are replaced by
+///   `<redacted>` in the debug log and in the context of errors, and the detail of errors from
+///   PostgreSQL, which may quote the values of a constraint, is left out.
+/// * `#[butane(max_length = N)]`, `#[butane(min = X)]`, `#[butane(max = X)]` and
+///   `#[butane(regex = "PATTERN")]` on a field to check its value on save, which fails with
+///   `Error::Validation` if it is not accepted. See [`validate`](butane_core::validate).
+/// * `#[butane(no_foreign_key)]` on a [`ForeignKey`] or [`Many`] field to create its columns
+///   without foreign key constraints, leaving referential integrity to the application.
+/// * `#[butane(counter_cache = "MODEL.FIELD")]` on a [`ForeignKey`] field to keep the integer
+///   field `FIELD` of the object of `MODEL` it refers to equal to the number of objects referring
+///   to it, by adding to it when they are saved or deleted. See [`counter`](butane_core::counter).
+/// * `#[butane(partition_by = "range(COLUMN)" | "list(COLUMN)" | "hash(COLUMN)")]` used on the
+///   struct to create the table as a partitioned table on PostgreSQL, which other backends
+///   ignore. Partitions are created with [`create_partition`](butane_core::partition::create_partition)
+///   or `butane partition create`. PostgreSQL requires unique constraints to include the
+///   partition column, so the primary key is combined with it, and saving an object without an
+///   [`AutoPk`](butane_core::AutoPk) only works if the partition column is the primary key.
+///   Partitioning only takes effect when the table is created.
+/// * `#[butane(view = "QUERY")]` used on the struct to back the model with a view defined by
+///   the SQL query, rather than a table. The model may be queried as usual, but cannot be
+///   saved or deleted, as it does not implement [`WritableDataObject`](butane_core::WritableDataObject).
+///   The query must select a column for each field, and the primary key must identify rows.
+///   Migrations replace the view whenever it changes.
+/// * `#[butane(view = "QUERY", materialized)]` used on the struct to back the model with a
+///   materialized view on PostgreSQL, which stores the results of the query until refreshed with
+///   `Connection::refresh_materialized_view`. Other backends create a plain view. Fields of a
+///   materialized view marked `#[unique]` or `#[butane(index)]` are indexed.
+/// * `#[butane(notify = "CHANNEL")]` used on the struct to send a notification on the channel
+///   each time an object is saved or deleted, so that other processes can invalidate cached
+///   copies. Only supported on PostgreSQL; see [`notify`](butane_core::notify).
+/// * `#[butane(patch)]` used on the struct to also generate a struct named after the model with
+///   the suffix `Patch`, such as `PostPatch`, with the derives of the model and an `Option` of each
+///   field other than the primary key and readonly fields. It implements
+///   [`Patch`](butane_core::query::Patch), to set the fields which are `Some` on an object, or in
+///   the database with a single `UPDATE` of only those columns.
+/// * `#[butane(audited)]` used on the struct to record each insert, update and delete of an object
+///   by `save`, `save_ignore_conflict` or `delete` in a history table named after the model's table
+///   with the suffix `_History`, holding its values before and after, the actor and the time. See
+///   [`audit`](butane_core::audit).
+/// * `#[butane(immutable)]` used on the struct for append-only tables, such as of events. Objects
+///   are inserted by `save` but never updated: saving an object whose `AutoPk` is initialized or
+///   which has the primary key of an existing object, or executing an
+///   [`Update`](butane_core::query::Update) of the model, fails with `Error::Immutable`. `patch`
+///   may not be used with it.
+/// * `#[butane(subtype_of = TYPE, discriminator = "COLUMN")]` used on the struct to store the model
+///   in the table named after `TYPE`, shared with its other subtypes, with the name of the model in
+///   the column `COLUMN`. Queries and updates of the model only match its own objects. `TYPE` is
+///   an enum with a variant wrapping each subtype, deriving [`Supertype`](macro@Supertype), which
+///   loads the objects of all of them. See [`subtype`](butane_core::subtype).
+/// * `#[model(extends = MIXIN)]` or `#[model(extends(MIXIN, ...))]` to add the fields of one or more
+///   mixins declared with [`mixin`](macro@mixin) to the model.
+///
+/// Doc comments on the struct and its fields describe its table and their columns with
+/// `COMMENT ON` on PostgreSQL, and migrations update them when the doc comments change. Other
+/// backends ignore them.
 ///
 /// For example
 /// ```ignore
@@ -53,12 +147,51 @@
 ///
 ///
 /// [`FieldType`]: crate::FieldType
+/// [`ForeignKey`]: butane_core::fkey::ForeignKey
 /// [`Many`]: butane_core::many::Many
+/// [`OrderedMany`]: butane_core::many::OrderedMany
+/// [`ManyThrough`]: butane_core::through::ManyThrough
 #[proc_macro_attribute]
-pub fn model(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn model(args: TokenStream, input: TokenStream) -> TokenStream {
+    if let Some(expanded) = codegen::model_extends(args.into(), input.clone().into()) {
+        return expanded.into();
+    }
     codegen::model_with_migrations(input.into(), &mut migrations_for_dir()).into()
 }
 
+/// Attribute macro which declares a mixin, a group of fields to be
+/// shared by several models. Rather than a struct, it defines a macro
+/// of the same name which `#[model(extends = Mixin)]` uses to add the
+/// fields of the mixin to the start of the model, so that they are
+/// saved, loaded and migrated as if written in the model itself.
+///
+/// ```ignore
+/// #[mixin]
+/// pub struct Timestamps {
+///   pub created_at: NaiveDateTime,
+///   #[default = "1970-01-01T00:00:00"]
+///   pub updated_at: NaiveDateTime,
+/// }
+///
+/// #[model(extends = Timestamps)]
+/// pub struct Post {
+///   pub id: AutoPk<i32>,
+///   pub title: String,
+/// }
+/// ```
+///
+/// Fields may have the same helper attributes as those of a model, and
+/// a mixin may even provide the primary key. Several mixins are applied
+/// in order with `#[model(extends(Timestamps, SoftDelete))]`. The types
+/// of the fields are resolved where the model is declared, so must be
+/// in scope there. The mixin must be declared before the models using
+/// it, either earlier in the same module or imported by path from
+/// another module of the crate.
+#[proc_macro_attribute]
+pub fn mixin(_args: TokenStream, input: TokenStream) -> TokenStream {
+    codegen::mixin(input.into()).into()
+}
+
 /// Attribute macro which generates an implementation of
 /// [`DataResult`](butane_core::DataResult). Continuing with our blog
 /// post example from [model](macro@model), we could create a `DataResult` with
@@ -78,7 +211,7 @@ pub fn model(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// present in the Model.
 #[proc_macro_attribute]
 pub fn dataresult(args: TokenStream, input: TokenStream) -> TokenStream {
-    codegen::dataresult(args.into(), input.into()).into()
+    codegen::dataresult_with_migrations(args.into(), input.into(), &mut migrations_for_dir()).into()
 }
 
 /// Macro to construct a [`BoolExpr`] (for use with a [`Query`]) from
@@ -117,6 +250,19 @@ pub fn dataresult(args: TokenStream, input: TokenStream) -> TokenStream {
 ///   primary key.
 /// * `is_in`: checks if a value is one of the provided parameters, e.g. `title.is_in(vec!["Foo", "Bar"])`.
 ///
+/// # SQL functions
+/// The SQL functions in [`SQL_FUNCTIONS`] may be called on fields and
+/// compared or matched with `like`, e.g.
+/// `filter!(Post, length(body) > 500 && lower(title).like("a%"))`.
+///
+/// [`SQL_FUNCTIONS`]: butane_core::query::SQL_FUNCTIONS
+///
+/// Literals compared with a field are checked against the type of its
+/// column in the current migration state, as recorded by `#[model]`, so
+/// that e.g. `rank == "first"` or `name.like(1)` is a compile error.
+/// `None` may only be compared with nullable fields. Fields of models
+/// which have not yet been recorded are not checked.
+///
 /// # Examples
 /// ```ignore
 /// # use butane_core::query::BoolExpr;
@@ -176,7 +322,58 @@ pub fn filter(input: TokenStream) -> TokenStream {
             .into()
         }
     };
-    filter::for_expr(&tyid, &expr).into()
+    let table = current_db().and_then(|db| filter::ModelTable::find(&db, &tyid));
+    filter::for_expr(&tyid, &expr, table.as_ref()).into()
+}
+
+/// Constructs an update of the objects of a model, setting fields to
+/// expressions evaluated by the database against each object.
+///
+/// Usage: `update!(Foo, field = expr, ...)` where `Foo` is a model type
+/// and each `expr` may refer to `Foo`'s fields as if they were
+/// variables. Expressions may use the arithmetic operators `+`, `-`,
+/// `*`, `/` and `%`, and call the SQL functions in
+/// [`SQL_FUNCTIONS`](butane_core::query::SQL_FUNCTIONS), e.g.
+/// `update!(Post, likes = likes + 1, title = upper(title))`. As with
+/// `filter!`, values from the surrounding Rust function must be
+/// enclosed in braces.
+///
+/// An `if` expression, with any `else if` branches, becomes a SQL
+/// `CASE` expression whose conditions are written as in `filter!`,
+/// e.g. `update!(Post, tier = if likes >= 100 { "gold" } else { "none" })`.
+/// Without a final `else`, it evaluates to NULL if no condition holds.
+///
+/// Returns an [`Update`] which may be restricted to some objects with
+/// its `filter` method, then run as a single `UPDATE` statement. This
+/// avoids loading and saving the objects, in which concurrent changes
+/// could be lost.
+///
+/// [`Update`]: butane_core::query::Update
+#[proc_macro]
+pub fn update(input: TokenStream) -> TokenStream {
+    let input = match syn::parse::<update::UpdateInput>(input) {
+        Ok(input) => input,
+        Err(_) => {
+            return make_compile_error!("Expected update!(Type, field = expression, ...)").into()
+        }
+    };
+    let table = current_db().and_then(|db| filter::ModelTable::find(&db, &input.model));
+    update::for_input(&input, table.as_ref()).into()
+}
+
+/// Constructs an expression evaluated by the database on the fields of
+/// a model, for example to order a query by it.
+///
+/// Usage: `expr!(Foo, expr)` where `Foo` is a model type and `expr`
+/// may refer to `Foo`'s fields as in [`update!`], e.g.
+/// `Post::query().order_by_asc(expr!(Post, lower(title)))`.
+#[proc_macro]
+pub fn expr(input: TokenStream) -> TokenStream {
+    let input = match syn::parse::<update::ExprInput>(input) {
+        Ok(input) => input,
+        Err(_) => return make_compile_error!("Expected expr!(Type, expression)").into(),
+    };
+    update::for_expr_input(&input).into()
 }
 
 /// Attribute macro which marks a type as being available to butane
@@ -215,10 +412,38 @@ pub fn butane_type(args: TokenStream, input: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Expands to the `SchemaFingerprint` of the latest migration. See
+/// `butane::schema_fingerprint`.
+#[proc_macro]
+pub fn schema_fingerprint(input: TokenStream) -> TokenStream {
+    use migrations::{Migration, Migrations};
+    if !input.is_empty() {
+        return make_compile_error!("Expected schema_fingerprint!()").into();
+    }
+    let db = match migrations_for_dir().latest().map(|m| m.db()) {
+        Some(Ok(db)) => db,
+        Some(Err(e)) => {
+            return make_compile_error!("Cannot load the latest migration: {}", e).into();
+        }
+        None => return make_compile_error!("There are no migrations").into(),
+    };
+    let (names, hashes): (Vec<String>, Vec<u64>) = butane_core::schema::table_fingerprints(&db)
+        .into_iter()
+        .unzip();
+    quote!(butane::schema::SchemaFingerprint::new(&[#((#names, #hashes)),*])).into()
+}
+
 fn migrations_for_dir() -> migrations::FsMigrations {
     migrations::from_root(migrations_dir())
 }
 
+/// The current database schema recorded by `#[model]`, if any.
+fn current_db() -> Option<migrations::adb::ADB> {
+    use migrations::{Migration, MigrationsMut};
+    let mut migrations = migrations_for_dir();
+    migrations.current().db().ok()
+}
+
 fn migrations_dir() -> PathBuf {
     let mut dir = PathBuf::from(
         std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR expected to be set"),
@@ -456,3 +681,78 @@ impl butane::PrimaryKeyType for #ident {}
     )
     .into()
 }
+
+/// Derive macro for [`Supertype`](butane_core::subtype::Supertype), on an
+/// enum with a variant wrapping each model declared as a subtype of it
+/// with `#[butane(subtype_of = ...)]`. Each subtype is also converted
+/// into the enum with `From`.
+/// E.g.
+/// ```ignore
+/// #[derive(Supertype)]
+/// pub enum Event {
+///   Signup(Signup),
+///   Purchase(Purchase),
+/// }
+/// ```
+#[proc_macro_derive(Supertype)]
+pub fn derive_supertype(input: TokenStream) -> TokenStream {
+    let derive_input = syn::parse_macro_input!(input as syn::DeriveInput);
+    derive_supertype_impl(derive_input).into()
+}
+
+fn derive_supertype_impl(derive_input: syn::DeriveInput) -> TokenStream2 {
+    let ident = &derive_input.ident;
+    let syn::Data::Enum(data_enum) = &derive_input.data else {
+        return make_compile_error!("Supertype can only be derived for enums");
+    };
+    let mut variants = Vec::new();
+    let mut types = Vec::new();
+    for variant in &data_enum.variants {
+        match &variant.fields {
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                variants.push(&variant.ident);
+                types.push(&fields.unnamed[0].ty);
+            }
+            _ => {
+                return syn::Error::new_spanned(variant, "each variant must wrap one subtype")
+                    .to_compile_error()
+            }
+        }
+    }
+    let Some(first) = types.first() else {
+        return make_compile_error!("Supertype requires a variant for each subtype");
+    };
+    let indices = (0..variants.len()).map(syn::Index::from);
+    quote!(
+        impl butane::subtype::Supertype for #ident {
+            const TABLE: &'static str = <#first as butane::DataObject>::TABLE;
+            const PKCOL: &'static str = <#first as butane::DataObject>::PKCOL;
+            const DISCRIMINATOR: &'static str =
+                <#first as butane::subtype::Subtype>::DISCRIMINATOR;
+            const SUBTYPES: &'static [(&'static str, &'static [butane::db::Column])] = &[
+                #((
+                    <#types as butane::subtype::Subtype>::KIND,
+                    <#types as butane::DataResult>::COLUMNS,
+                )),*
+            ];
+            fn from_row(
+                subtype: usize,
+                row: &dyn butane::db::BackendRow,
+            ) -> butane::Result<Self> {
+                match subtype {
+                    #(#indices => Ok(Self::#variants(
+                        <#types as butane::DataResult>::from_row(row)?
+                    )),)*
+                    _ => Err(butane::Error::BoundsError(format!("no subtype {subtype}"))),
+                }
+            }
+        }
+        #(
+            impl From<#types> for #ident {
+                fn from(obj: #types) -> Self {
+                    Self::#variants(obj)
+                }
+            }
+        )*
+    )
+}
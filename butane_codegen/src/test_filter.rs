@@ -0,0 +1,78 @@
+use butane_core::migrations::adb::{AColumn, ATable, DeferredSqlType, TypeIdentifier, ADB};
+use butane_core::SqlType;
+use syn::{parse_quote, Expr, Ident};
+
+use super::filter::{for_expr, ModelTable};
+
+fn column(name: &str, ty: SqlType, nullable: bool) -> AColumn {
+    let ty = DeferredSqlType::KnownId(TypeIdentifier::Ty(ty));
+    AColumn::new(name, ty, nullable, false, false, false, None, None)
+}
+
+fn contestant_db() -> ADB {
+    let mut table = ATable::new("Contestant".to_string());
+    table.add_column(column("name", SqlType::Text, false));
+    table.add_column(column("rank", SqlType::Int, false));
+    table.add_column(column("score", SqlType::Real, true));
+    let mut db = ADB::new();
+    db.replace_table(table);
+    db
+}
+
+fn filter_output(expr: Expr) -> String {
+    let db = contestant_db();
+    let tyid: Ident = parse_quote!(Contestant);
+    let table = ModelTable::find(&db, &tyid);
+    assert!(table.is_some(), "Contestant table should be found");
+    for_expr(&tyid, &expr, table.as_ref()).to_string()
+}
+
+#[test]
+fn matching_comparisons_compile() {
+    for expr in [
+        parse_quote!(name == "Pete" && rank < 3),
+        parse_quote!(score == None || score >= 1.5),
+        parse_quote!(name.like("P%")),
+        parse_quote!(rank == { 1 + 1 }),
+    ] {
+        let output = filter_output(expr);
+        assert!(!output.contains("compile_error"), "{output}");
+    }
+}
+
+#[test]
+fn mismatched_comparisons_are_compile_errors() {
+    for (expr, message) in [
+        (parse_quote!(rank == "first"), "field 'rank' has type"),
+        (parse_quote!(name != 3), "field 'name' has type"),
+        (parse_quote!(score > 1), "field 'score' has type"),
+        (parse_quote!(name == None), "is not nullable"),
+        (parse_quote!(rank.like("1%")), "cannot be used with 'like'"),
+        (parse_quote!(name.like(1)), "must be a string"),
+    ] {
+        let output = filter_output(expr);
+        assert!(output.contains("compile_error"), "{output}");
+        assert!(output.contains(message), "{output}");
+    }
+}
+
+#[test]
+fn unknown_models_are_not_checked() {
+    let db = contestant_db();
+    assert!(ModelTable::find(&db, &parse_quote!(Unknown)).is_none());
+    let output = for_expr(&parse_quote!(Unknown), &parse_quote!(rank == "first"), None);
+    assert!(!output.to_string().contains("compile_error"));
+}
+
+#[test]
+fn only_allowed_sql_functions_compile() {
+    let output = filter_output(parse_quote!(length(name) > 3 && lower(name).like("p%")));
+    assert!(!output.contains("compile_error"), "{output}");
+
+    let output = filter_output(parse_quote!(pg_sleep(rank) > 0));
+    assert!(output.contains("compile_error"), "{output}");
+    assert!(
+        output.contains("unsupported SQL function 'pg_sleep'"),
+        "{output}"
+    );
+}
@@ -0,0 +1,197 @@
+use butane_core::make_compile_error;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{spanned::Spanned, BinOp, Block, Expr, ExprAssign, Ident, Stmt, Token, UnOp};
+
+use crate::filter::{self, column_type, literal_matches, ModelTable};
+
+/// Input of `update!(Type, column = expression, ...)`.
+pub struct UpdateInput {
+    pub model: Ident,
+    assignments: Punctuated<Expr, Token![,]>,
+}
+
+impl Parse for UpdateInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let model = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let assignments = Punctuated::parse_terminated(input)?;
+        Ok(UpdateInput { model, assignments })
+    }
+}
+
+pub fn for_input(input: &UpdateInput, table: Option<&ModelTable>) -> TokenStream2 {
+    let model = &input.model;
+    let fields = quote!(<#model as butane::DataResult>::DBO::fields());
+    let mut update = quote!(butane::query::Update::<#model>::new());
+    for assignment in &input.assignments {
+        let Expr::Assign(ExprAssign { left, right, .. }) = assignment else {
+            return make_compile_error!(assignment.span()=> "Expected an assignment such as `column = expression`");
+        };
+        let Expr::Path(column) = left.as_ref() else {
+            return make_compile_error!(left.span()=> "Expected a field name");
+        };
+        if let Some(err) = check_assignment(table, left, right) {
+            return err;
+        }
+        let value = match value_expr(&fields, right) {
+            Ok(value) => value,
+            Err(err) => return err,
+        };
+        let span = column.span();
+        update = quote_spanned!(span=> #update.set(#fields.#column().name(), #value));
+    }
+    update
+}
+
+/// Input of `expr!(Type, expression)`.
+pub struct ExprInput {
+    model: Ident,
+    expr: Expr,
+}
+
+impl Parse for ExprInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let model = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let expr = input.parse()?;
+        Ok(ExprInput { model, expr })
+    }
+}
+
+pub fn for_expr_input(input: &ExprInput) -> TokenStream2 {
+    let model = &input.model;
+    let fields = quote!(<#model as butane::DataResult>::DBO::fields());
+    value_expr(&fields, &input.expr).unwrap_or_else(|err| err)
+}
+
+/// Converts an expression on the fields of a model to an expression
+/// evaluated by the database.
+pub(crate) fn value_expr(fields: &TokenStream2, expr: &Expr) -> Result<TokenStream2, TokenStream2> {
+    let span = expr.span();
+    Ok(match expr {
+        Expr::Path(path) if path.path.is_ident("None") => {
+            quote_spanned!(span=> butane::query::Expr::Val(butane::SqlVal::Null))
+        }
+        Expr::Path(path) => {
+            quote_spanned!(span=> butane::query::Expr::Column(#fields.#path().name()))
+        }
+        Expr::Lit(_) | Expr::Block(_) => {
+            quote_spanned!(span=> butane::query::Expr::Val(butane::ToSql::to_sql(&#expr)))
+        }
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Neg(_)) => match unary.expr.as_ref() {
+            Expr::Lit(_) => {
+                quote_spanned!(span=> butane::query::Expr::Val(butane::ToSql::to_sql(&(#expr))))
+            }
+            operand => {
+                let operand = value_expr(fields, operand)?;
+                quote_spanned!(span=> butane::query::Expr::Arith(
+                    Box::new(butane::query::Expr::Val(butane::SqlVal::Int(0))),
+                    butane::query::ArithOp::Sub,
+                    Box::new(#operand),
+                ))
+            }
+        },
+        Expr::Binary(binop) => {
+            let op = match binop.op {
+                BinOp::Add(_) => quote!(Add),
+                BinOp::Sub(_) => quote!(Sub),
+                BinOp::Mul(_) => quote!(Mul),
+                BinOp::Div(_) => quote!(Div),
+                BinOp::Rem(_) => quote!(Rem),
+                _ => {
+                    return Err(
+                        make_compile_error!(binop.op.span()=> "Unsupported operator in expression"),
+                    )
+                }
+            };
+            let left = value_expr(fields, &binop.left)?;
+            let right = value_expr(fields, &binop.right)?;
+            quote_spanned!(span=> butane::query::Expr::Arith(
+                Box::new(#left),
+                butane::query::ArithOp::#op,
+                Box::new(#right),
+            ))
+        }
+        Expr::Call(call) => {
+            let func = match call.func.as_ref() {
+                Expr::Path(path) if path.path.get_ident().is_some() => {
+                    path.path.get_ident().unwrap().to_string()
+                }
+                func => {
+                    return Err(make_compile_error!(func.span()=> "Expected an SQL function name"))
+                }
+            };
+            if !butane_core::query::SQL_FUNCTIONS.contains(&func.as_str()) {
+                return Err(make_compile_error!(call.func.span()=>
+                    "unsupported SQL function '{}', expected one of {}",
+                    func,
+                    butane_core::query::SQL_FUNCTIONS.join(", ")
+                ));
+            }
+            let args = call
+                .args
+                .iter()
+                .map(|arg| value_expr(fields, arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            quote_spanned!(span=> butane::query::Expr::Func(#func, vec![#(#args),*]))
+        }
+        Expr::If(_) => case_expr(fields, expr)?,
+        Expr::Paren(paren) => value_expr(fields, &paren.expr)?,
+        Expr::Group(group) => value_expr(fields, &group.expr)?,
+        _ => return Err(make_compile_error!(span=> "Unsupported expression")),
+    })
+}
+
+/// Converts an `if` expression, with any `else if` branches, to a
+/// `CASE` expression whose conditions are written as in `filter!`.
+fn case_expr(fields: &TokenStream2, expr: &Expr) -> Result<TokenStream2, TokenStream2> {
+    let mut whens = Vec::new();
+    let mut next = Some(expr);
+    let mut otherwise = quote!(None);
+    while let Some(expr) = next.take() {
+        match expr {
+            Expr::If(expr_if) => {
+                let cond = filter::handle_expr(fields, &expr_if.cond, None);
+                let value = block_expr(fields, &expr_if.then_branch)?;
+                whens.push(quote!((#cond, #value)));
+                next = expr_if.else_branch.as_ref().map(|(_, expr)| expr.as_ref());
+            }
+            Expr::Block(block) => {
+                let value = block_expr(fields, &block.block)?;
+                otherwise = quote!(Some(#value));
+            }
+            _ => return Err(make_compile_error!(expr.span()=> "Unsupported expression")),
+        }
+    }
+    Ok(quote_spanned!(expr.span()=> butane::query::Expr::case(vec![#(#whens),*], #otherwise)))
+}
+
+/// Converts the single expression making up a branch of an `if`.
+fn block_expr(fields: &TokenStream2, block: &Block) -> Result<TokenStream2, TokenStream2> {
+    match block.stmts.as_slice() {
+        [Stmt::Expr(expr, None)] => value_expr(fields, expr),
+        _ => Err(make_compile_error!(block.span()=> "Expected a single expression")),
+    }
+}
+
+/// Checks a literal or `None` assigned to a field against the type of
+/// the field's column.
+fn check_assignment(
+    table: Option<&ModelTable>,
+    field: &Expr,
+    value: &Expr,
+) -> Option<TokenStream2> {
+    let (column, ty) = column_type(table, field)?;
+    match value {
+        Expr::Path(path) if path.path.is_ident("None") && !column.nullable() => Some(
+            make_compile_error!(value.span()=> "field '{}' is not nullable, so cannot be set to None", column.name()),
+        ),
+        Expr::Lit(lit) if literal_matches(&lit.lit, &ty) == Some(false) => Some(
+            make_compile_error!(value.span()=> "field '{}' has type {}, so cannot be set to '{}'", column.name(), ty, quote!(#lit)),
+        ),
+        _ => None,
+    }
+}
@@ -0,0 +1,412 @@
+//! Histories of the changes made to objects of models declared with
+//! `#[butane(audited)]`.
+//!
+//! The migrations of an audited model create a history table alongside
+//! its own, named after it with the suffix [`HISTORY_SUFFIX`]. Each time
+//! an object is inserted, updated or deleted by `save`,
+//! `save_ignore_conflict` or `delete`, an [`AuditEntry`] is added to it
+//! holding the values of the object's columns before and after the
+//! change, the actor who made it and when. The actor is the one set for
+//! the operations run within [`with_actor`] or [`with_actor_sync`]. The
+//! history of an object is loaded with
+//! [`history`](crate::DataObjectOpsSync::history).
+//!
+//! The history also allows past states to be reconstructed, for
+//! compliance reports or to undo changes:
+//! [`as_of`](crate::DataObjectOpsSync::as_of) loads an object as it was
+//! at a given time, and [`all_as_of`](crate::DataObjectOpsSync::all_as_of)
+//! every object which existed then. Objects are rebuilt from the values
+//! of their columns, so their many-to-many relationships are not
+//! reconstructed, and those last changed before the model was audited
+//! are not found.
+//!
+//! Changes made by statements which may affect many rows, such as
+//! [`delete`](crate::query::QueryOpsSync::delete) on a query, are not
+//! recorded.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fallible_iterator::FallibleIterator;
+
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{BackendRow, BackendRows, Column, ConnectionMethods};
+use crate::encryption::KeyProvider;
+pub use crate::migrations::adb::HISTORY_SUFFIX;
+use crate::query::{BoolExpr, Expr, Order, OrderDirection};
+use crate::{internal, DataObject, Error, FieldType, FromSql, Result, SqlType, SqlVal};
+
+thread_local! {
+    /// Actor of synchronous operations, set with [`with_actor_sync`].
+    static SYNC_ACTOR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+#[cfg(feature = "async")]
+tokio::task_local! {
+    static ACTOR: String;
+}
+
+/// Runs `fut` with `actor` recorded as the actor of the changes it makes
+/// to audited models.
+#[cfg(feature = "async")]
+pub async fn with_actor<F: Future>(actor: impl Into<String>, fut: F) -> F::Output {
+    ACTOR.scope(actor.into(), fut).await
+}
+
+/// Runs `f` with `actor` recorded as the actor of the changes it makes
+/// to audited models on this thread.
+pub fn with_actor_sync<R>(actor: impl Into<String>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SYNC_ACTOR.set(self.0.take());
+        }
+    }
+    let _restore = Restore(SYNC_ACTOR.replace(Some(actor.into())));
+    f()
+}
+
+/// Returns the actor currently in effect, if any.
+pub fn current_actor() -> Option<String> {
+    #[cfg(feature = "async")]
+    if let Ok(actor) = ACTOR.try_with(String::clone) {
+        return Some(actor);
+    }
+    SYNC_ACTOR.with_borrow(Clone::clone)
+}
+
+/// Kind of change recorded in an [`AuditEntry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditOp {
+    /// The object was inserted.
+    Insert,
+    /// The object was updated.
+    Update,
+    /// The object was deleted.
+    Delete,
+}
+
+impl AuditOp {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditOp::Insert => "insert",
+            AuditOp::Update => "update",
+            AuditOp::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for AuditOp {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "insert" => Ok(AuditOp::Insert),
+            "update" => Ok(AuditOp::Update),
+            "delete" => Ok(AuditOp::Delete),
+            _ => Err(Error::UnknownEnumVariant(s.to_string())),
+        }
+    }
+}
+
+/// Values of the columns of an object, by column name.
+pub type AuditValues = BTreeMap<String, SqlVal>;
+
+/// A change to an object of an audited model, as recorded in its
+/// history table. The values recorded are those of all the columns of
+/// the object, as read from the database before and after the change.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    /// The change made.
+    pub op: AuditOp,
+    /// The values before the change, or `None` if it was an insert.
+    pub old_values: Option<AuditValues>,
+    /// The values after the change, or `None` if it was a delete.
+    pub new_values: Option<AuditValues>,
+    /// The actor in effect when the change was made.
+    pub actor: Option<String>,
+    /// When the change was made, to the millisecond.
+    pub changed_at: SystemTime,
+}
+
+impl AuditEntry {
+    fn from_row(row: &(dyn BackendRow + '_)) -> Result<Self> {
+        let values = |idx| -> Result<Option<AuditValues>> {
+            Option::<String>::from_sql_ref(row.get(idx, SqlType::Text)?)?
+                .map(|json| serde_json::from_str(&json))
+                .transpose()
+                .map_err(Error::from)
+        };
+        let millis = i64::from_sql_ref(row.get(4, SqlType::BigInt)?)?;
+        Ok(AuditEntry {
+            op: String::from_sql_ref(row.get(0, SqlType::Text)?)?.parse()?,
+            old_values: values(1)?,
+            new_values: values(2)?,
+            actor: Option::<String>::from_sql_ref(row.get(3, SqlType::Text)?)?,
+            changed_at: UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64),
+        })
+    }
+}
+
+/// Columns of a history table read into an [`AuditEntry`], following
+/// its auto-incrementing `id` and the primary key `object_pk` of the
+/// object changed.
+const ENTRY_COLUMNS: &[Column] = &[
+    Column::new("op", SqlType::Text),
+    Column::new("old_values", SqlType::Text),
+    Column::new("new_values", SqlType::Text),
+    Column::new("actor", SqlType::Text),
+    Column::new("changed_at", SqlType::BigInt),
+];
+
+/// Milliseconds since the Unix epoch of `time`, as recorded in the
+/// `changed_at` column of a history table.
+fn epoch_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+fn history_table<T: DataObject>() -> Result<&'static str> {
+    T::AUDIT_TABLE.ok_or(Error::NotAudited(T::TABLE))
+}
+
+/// Rebuilds an object of `T` from the values recorded in its history.
+fn object_from_values<T: DataObject>(
+    mut values: AuditValues,
+    keys: Option<&dyn KeyProvider>,
+) -> Result<T> {
+    let values = T::COLUMNS
+        .iter()
+        .map(|col| {
+            values
+                .remove(col.name())
+                .ok_or_else(|| Error::ColumnNotFound(T::TABLE.to_string(), col.name().to_string()))
+        })
+        .collect::<Result<Vec<SqlVal>>>()?;
+    internal::from_values(values, keys)
+}
+
+/// Reads the operation and new values of an entry, as selected by
+/// [`STATE_COLUMNS`] following the primary key of the object.
+fn state_from_row(
+    row: &(dyn BackendRow + '_),
+    offset: usize,
+) -> Result<(AuditOp, Option<AuditValues>)> {
+    let op = String::from_sql_ref(row.get(offset, SqlType::Text)?)?.parse()?;
+    let values = Option::<String>::from_sql_ref(row.get(offset + 1, SqlType::Text)?)?
+        .map(|json| serde_json::from_str(&json))
+        .transpose()?;
+    Ok((op, values))
+}
+
+/// Columns of a history table giving the state of an object after an entry.
+const STATE_COLUMNS: &[Column] = &[
+    Column::new("op", SqlType::Text),
+    Column::new("new_values", SqlType::Text),
+];
+
+fn values_json<T: DataObject>(values: Option<Vec<SqlVal>>) -> Result<SqlVal> {
+    Ok(match values {
+        Some(values) => {
+            let values: AuditValues = T::COLUMNS
+                .iter()
+                .map(|col| col.name().to_string())
+                .zip(values)
+                .collect();
+            SqlVal::Text(serde_json::to_string(&values)?)
+        }
+        None => SqlVal::Null,
+    })
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Loads the values of the object of `T` with primary key `pk` to be
+/// recorded in its history, or `None` if there is no such object.
+pub(crate) async fn recorded_values<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pk: SqlVal,
+) -> Result<Option<Vec<SqlVal>>> {
+    let pkcol = Column::new(T::PKCOL, <T::PKType as FieldType>::SQLTYPE);
+    match conn.query_by_pk(T::TABLE, &pkcol, pk, T::COLUMNS).await {
+        Ok(values) => Ok(Some(values)),
+        Err(Error::NoSuchObject) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        recorded_values(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Adds an entry for a change to the object of `T` with primary key
+/// `pk` to its history, if `T` is audited, given the values from
+/// `recorded_values` before the change. Those after it are loaded
+/// unless it was a delete.
+pub(crate) async fn record_change<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    op: AuditOp,
+    pk: SqlVal,
+    old_values: Option<Vec<SqlVal>>,
+) -> Result<()> {
+    let Some(table) = T::AUDIT_TABLE else {
+        return Ok(());
+    };
+    let new_values = match op {
+        AuditOp::Delete => None,
+        _ => recorded_values::<T>(conn, pk.clone()).await?,
+    };
+    let changed_at = epoch_millis(SystemTime::now());
+    let columns: Vec<Column> =
+        std::iter::once(Column::new("object_pk", <T::PKType as FieldType>::SQLTYPE))
+            .chain(ENTRY_COLUMNS.iter().cloned())
+            .collect();
+    let values = [
+        pk,
+        SqlVal::Text(op.as_str().to_string()),
+        values_json::<T>(old_values)?,
+        values_json::<T>(new_values)?,
+        current_actor().map_or(SqlVal::Null, SqlVal::Text),
+        SqlVal::BigInt(changed_at),
+    ];
+    let values: Vec<_> = values.iter().map(SqlVal::as_ref).collect();
+    conn.insert_only(table, &columns, &values).await
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Loads the history of the object of `T` with primary key `pk`, oldest
+/// first.
+pub(crate) async fn load_history<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pk: SqlVal,
+) -> Result<Vec<AuditEntry>> {
+    conn.query(
+        history_table::<T>()?,
+        ENTRY_COLUMNS,
+        Some(BoolExpr::Eq("object_pk", Expr::Val(pk))),
+        None,
+        None,
+        Some(&[Order {
+            direction: OrderDirection::Ascending,
+            column: "id",
+            expr: None,
+        }]),
+    )
+    .await?
+    .mapped(AuditEntry::from_row)
+    .collect()
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Reconstructs the object of `T` with primary key `pk` as it was at
+/// `at`, or `None` if it did not exist then.
+pub(crate) async fn load_as_of<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pk: SqlVal,
+    at: SystemTime,
+) -> Result<Option<T>> {
+    let filter = BoolExpr::And(
+        Box::new(BoolExpr::Eq("object_pk", Expr::Val(pk))),
+        Box::new(BoolExpr::Le(
+            "changed_at",
+            Expr::Val(SqlVal::BigInt(epoch_millis(at))),
+        )),
+    );
+    let mut rows = conn
+        .query(
+            history_table::<T>()?,
+            STATE_COLUMNS,
+            Some(filter),
+            Some(1),
+            None,
+            Some(&[Order {
+                direction: OrderDirection::Descending,
+                column: "id",
+                expr: None,
+            }]),
+        )
+        .await?;
+    match rows.next()? {
+        Some(row) => match state_from_row(row, 0)? {
+            (_, Some(values)) => object_from_values(values, conn.key_provider()).map(Some),
+            (_, None) => Ok(None),
+        },
+        None => Ok(None),
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Reconstructs every object of `T` which existed at `at`, in the order
+/// they were first recorded.
+pub(crate) async fn load_all_as_of<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    at: SystemTime,
+) -> Result<Vec<T>> {
+    let pk_type = <T::PKType as FieldType>::SQLTYPE;
+    let columns: Vec<Column> = std::iter::once(Column::new("object_pk", pk_type.clone()))
+        .chain(STATE_COLUMNS.iter().cloned())
+        .collect();
+    let entries: Vec<(SqlVal, Option<AuditValues>)> = conn
+        .query(
+            history_table::<T>()?,
+            &columns,
+            Some(BoolExpr::Le(
+                "changed_at",
+                Expr::Val(SqlVal::BigInt(epoch_millis(at))),
+            )),
+            None,
+            None,
+            Some(&[Order {
+                direction: OrderDirection::Ascending,
+                column: "id",
+                expr: None,
+            }]),
+        )
+        .await?
+        .mapped(|row| {
+            let pk = SqlVal::from(row.get(0, pk_type.clone())?);
+            let (_, values) = state_from_row(row, 1)?;
+            Ok((pk, values))
+        })
+        .collect()?;
+    // Keep the last state of each object, in the order of its first entry
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut states: Vec<Option<AuditValues>> = Vec::new();
+    for (pk, values) in entries {
+        match positions.get(&pk.to_string()) {
+            Some(&idx) => states[idx] = values,
+            None => {
+                positions.insert(pk.to_string(), states.len());
+                states.push(values);
+            }
+        }
+    }
+    states
+        .into_iter()
+        .flatten()
+        .map(|values| object_from_values(values, conn.key_provider()))
+        .collect()
+}
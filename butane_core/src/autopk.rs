@@ -1,6 +1,7 @@
 //! Contains the [AutoPk] type for autoincrementing primary keys.
 
 use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 
 use serde::{Deserialize, Serialize};
@@ -124,6 +125,17 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::f
 
 impl<T: PrimaryKeyType + Copy> Copy for AutoPk<T> {}
 
+// Uninitialized primary keys compare unequal even to themselves, but
+// objects loaded from the database always have initialized ones, so
+// they may still be used as keys of maps of loaded objects.
+impl<T: PrimaryKeyType + Eq> Eq for AutoPk<T> {}
+
+impl<T: PrimaryKeyType + Hash> Hash for AutoPk<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
 impl<T: PrimaryKeyType + Ord> PartialOrd for AutoPk<T> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         match &self.inner {
@@ -0,0 +1,82 @@
+//! Saving many objects at once, reporting the outcome of each.
+#![deny(missing_docs)]
+
+use crate::db::ConnectionMethods;
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::{Result, WritableDataObject};
+
+/// Name of the savepoint each item is saved within by `save_all_partial_atomic`.
+const SAVEPOINT: &str = "butane_save_all_partial";
+
+/// Saves each of `items`, continuing past any which fail.
+///
+/// Returns the result of saving each item, in the same order as
+/// `items`, so that failures such as constraint violations can be
+/// reported for individual items rather than aborting the whole batch.
+///
+/// A failed save may leave part of an item saved, such as its row but
+/// not its many-to-many relationships. Use `save_all_partial_atomic`
+/// to save each item entirely or not at all.
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"), DataObjectOps),
+    sync(keep_self),
+    async(feature = "async", self = "save_all_partial_async")
+)]
+pub async fn save_all_partial<T: WritableDataObject>(
+    conn: &impl ConnectionMethods,
+    items: &mut [T],
+) -> Vec<Result<()>> {
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        results.push(crate::DataObjectOps::save(item, conn).await);
+    }
+    results
+}
+
+/// Like `save_all_partial`, but saves each item within a savepoint,
+/// which is rolled back if the save fails.
+///
+/// Savepoints must be used within a [`Transaction`](crate::db::Transaction)
+/// on backends such as PostgreSQL, where an error otherwise aborts the
+/// whole transaction. The [`AutoPk`](crate::AutoPk) of an item which
+/// failed after being inserted remains initialized even though the
+/// insert was rolled back.
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        save_in_savepoint(snake)
+    ),
+    sync(keep_self),
+    async(feature = "async", self = "save_all_partial_atomic_async")
+)]
+pub async fn save_all_partial_atomic<T: WritableDataObject>(
+    conn: &impl ConnectionMethods,
+    items: &mut [T],
+) -> Vec<Result<()>> {
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        results.push(save_in_savepoint(conn, item).await);
+    }
+    results
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"), DataObjectOps),
+    sync(),
+    async(feature = "async")
+)]
+async fn save_in_savepoint<T: WritableDataObject>(
+    conn: &impl ConnectionMethods,
+    item: &mut T,
+) -> Result<()> {
+    conn.execute(&format!("SAVEPOINT {SAVEPOINT};")).await?;
+    let result = crate::DataObjectOps::save(item, conn).await;
+    if result.is_err() {
+        conn.execute(&format!("ROLLBACK TO SAVEPOINT {SAVEPOINT};"))
+            .await?;
+    }
+    conn.execute(&format!("RELEASE SAVEPOINT {SAVEPOINT};"))
+        .await?;
+    result
+}
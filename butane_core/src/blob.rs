@@ -0,0 +1,311 @@
+//! Streaming access to blob fields, for values too large to be
+//! conveniently loaded into memory at once.
+//!
+//! A [`BlobStream`] is opened on a field of a saved object with
+//! [`open_blob`](crate::DataObjectOpsAsync::open_blob), or with
+//! [`create_blob`](crate::DataObjectOpsAsync::create_blob) to first
+//! replace its value with zeroes of a given length. It implements
+//! [`AsyncRead`], [`AsyncWrite`] and [`AsyncSeek`], transferring the
+//! blob one chunk at a time.
+//!
+//! A blob has a fixed length while it is open: writes overwrite its
+//! bytes in place and stop at its end. SQLite uses incremental blob
+//! I/O on `Vec<u8>` fields. Postgres cannot update part of a `bytea`
+//! without rewriting all of it, so it only streams [`LargeObject`]
+//! fields, reading and writing each chunk of the large object with
+//! `lo_get` and `lo_put`. As with [`tokio::fs::File`], a write may
+//! still be in progress when it returns, so
+//! [`flush`](tokio::io::AsyncWriteExt::flush) must be called to wait for
+//! it and learn whether it succeeded.
+
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use crate::db::{BlobRef, ConnectionMethodsAsync};
+use crate::{Error, FieldType, FromSql, Result, SqlType, SqlVal, SqlValRef, ToSql};
+
+/// Default number of bytes transferred by each read or write.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Marker for field types which hold a blob and so may be opened with
+/// [`open_blob`](crate::DataObjectOpsAsync::open_blob).
+pub trait BlobField: Into<crate::SqlVal> {
+    /// Whether the field refers to a [`LargeObject`] rather than holding
+    /// the blob itself.
+    const LARGE_OBJECT: bool = false;
+}
+impl BlobField for Vec<u8> {}
+impl BlobField for Option<Vec<u8>> {}
+impl BlobField for LargeObject {
+    const LARGE_OBJECT: bool = true;
+}
+
+/// A Postgres large object, for a blob too large to be stored in a
+/// `bytea` column and written with a single statement. Its column holds
+/// the object's oid, as a `BIGINT`, and its contents are only accessed
+/// by streaming them with
+/// [`open_blob`](crate::DataObjectOpsAsync::open_blob). The default
+/// value refers to no large object and opens as an empty blob.
+///
+/// A large object is created with [`LargeObject::create`] and assigned
+/// to the field before the object is saved. It is not deleted along
+/// with the object, for which Postgres provides the `lo_manage` trigger
+/// and `vacuumlo`. Large objects are not supported by other backends.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct LargeObject {
+    oid: u32,
+}
+
+impl LargeObject {
+    /// Creates an empty large object.
+    pub async fn create(conn: &impl ConnectionMethodsAsync) -> Result<Self> {
+        let rows: Vec<(i64,)> = conn.query_as("SELECT lo_create(0)::bigint", &[]).await?;
+        let (oid,) = rows.into_iter().next().ok_or(Error::NoSuchObject)?;
+        Self::from_sql(SqlVal::BigInt(oid))
+    }
+
+    /// Returns the oid of the large object, or `None` if there is none.
+    pub fn oid(&self) -> Option<u32> {
+        (self.oid != 0).then_some(self.oid)
+    }
+}
+
+impl FromSql for LargeObject {
+    fn from_sql_ref(val: SqlValRef<'_>) -> Result<Self> {
+        match val {
+            SqlValRef::BigInt(oid) => Ok(LargeObject {
+                oid: u32::try_from(oid).map_err(|_| Error::OutOfRange)?,
+            }),
+            _ => Err(Error::CannotConvertSqlVal(SqlType::BigInt, val.into())),
+        }
+    }
+}
+
+impl ToSql for LargeObject {
+    fn to_sql(&self) -> SqlVal {
+        SqlVal::BigInt(self.oid.into())
+    }
+    fn to_sql_ref(&self) -> SqlValRef<'_> {
+        SqlValRef::BigInt(self.oid.into())
+    }
+}
+
+impl FieldType for LargeObject {
+    const SQLTYPE: SqlType = SqlType::BigInt;
+    type RefType = Self;
+}
+
+type BoxFuture<'c, T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'c>>;
+
+enum Pending<'c> {
+    Idle,
+    Read(BoxFuture<'c, Vec<u8>>),
+    Write(BoxFuture<'c, ()>),
+}
+
+/// A blob in the database opened for streaming reads and writes.
+pub struct BlobStream<'c, C: ?Sized> {
+    conn: &'c C,
+    blob: BlobRef,
+    len: u64,
+    pos: u64,
+    chunk_size: usize,
+    /// Bytes read from the blob starting at `pos` which have not yet
+    /// been returned.
+    buffer: Vec<u8>,
+    pending: Pending<'c>,
+    seek: Option<u64>,
+}
+
+impl<'c, C> BlobStream<'c, C>
+where
+    C: ConnectionMethodsAsync + ?Sized,
+{
+    /// Opens `blob` on `conn`. A NULL blob is treated as empty.
+    pub async fn open(conn: &'c C, blob: BlobRef) -> crate::Result<Self> {
+        let len = conn.blob_len(&blob).await?.unwrap_or(0);
+        Ok(BlobStream {
+            conn,
+            blob,
+            len,
+            pos: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            buffer: Vec::new(),
+            pending: Pending::Idle,
+            seek: None,
+        })
+    }
+
+    /// Replaces `blob` with `len` zero bytes and opens it.
+    pub async fn create(conn: &'c C, blob: BlobRef, len: u64) -> crate::Result<Self> {
+        conn.allocate_blob(&blob, len).await?;
+        Self::open(conn, blob).await
+    }
+
+    /// Sets the largest number of bytes transferred by each read or
+    /// write, [`DEFAULT_CHUNK_SIZE`] unless changed.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Returns the length of the blob in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the blob is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the blob being streamed.
+    pub fn blob(&self) -> &BlobRef {
+        &self.blob
+    }
+
+    /// Number of bytes which may be transferred from the current
+    /// position, at most `max` and the chunk size.
+    fn available(&self, max: usize) -> usize {
+        let remaining = self.len.saturating_sub(self.pos);
+        usize::try_from(remaining)
+            .unwrap_or(usize::MAX)
+            .min(max)
+            .min(self.chunk_size)
+    }
+
+    /// Waits for the pending read or write to finish, keeping the
+    /// bytes read in the buffer.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let result = match &mut self.pending {
+            Pending::Idle => return Poll::Ready(Ok(())),
+            Pending::Read(future) => ready!(future.as_mut().poll(cx)).map(|data| {
+                self.buffer = data;
+            }),
+            Pending::Write(future) => ready!(future.as_mut().poll(cx)),
+        };
+        self.pending = Pending::Idle;
+        Poll::Ready(result.map_err(io::Error::other))
+    }
+}
+
+impl<'c, C> AsyncRead for BlobStream<'c, C>
+where
+    C: ConnectionMethodsAsync + ?Sized,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_pending(cx))?;
+            if !this.buffer.is_empty() {
+                let n = this.buffer.len().min(buf.remaining());
+                buf.put_slice(&this.buffer[..n]);
+                this.buffer.drain(..n);
+                this.pos += n as u64;
+                return Poll::Ready(Ok(()));
+            }
+            let n = this.available(buf.remaining());
+            if n == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let (conn, blob, pos) = (this.conn, this.blob.clone(), this.pos);
+            this.pending = Pending::Read(Box::pin(async move {
+                let data = conn.read_blob(&blob, pos, n).await?;
+                if data.is_empty() {
+                    // The blob was shortened since it was opened
+                    return Err(crate::Error::NoSuchObject);
+                }
+                Ok(data)
+            }));
+        }
+    }
+}
+
+impl<'c, C> AsyncWrite for BlobStream<'c, C>
+where
+    C: ConnectionMethodsAsync + ?Sized,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        this.buffer.clear();
+        let n = this.available(buf.len());
+        if n == 0 {
+            return Poll::Ready(Ok(0));
+        }
+        let (conn, blob, pos) = (this.conn, this.blob.clone(), this.pos);
+        let data = buf[..n].to_vec();
+        let mut future: BoxFuture<'c, ()> =
+            Box::pin(async move { conn.write_blob(&blob, pos, &data).await });
+        this.pos += n as u64;
+        match future.as_mut().poll(cx) {
+            Poll::Ready(result) => result.map_err(io::Error::other)?,
+            Poll::Pending => this.pending = Pending::Write(future),
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+}
+
+impl<'c, C> AsyncSeek for BlobStream<'c, C>
+where
+    C: ConnectionMethodsAsync + ?Sized,
+{
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => this.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => this.pos.checked_add_signed(offset),
+        };
+        match target {
+            Some(target) => {
+                this.seek = Some(target);
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        if let Some(target) = this.seek.take() {
+            this.pos = target;
+            this.buffer.clear();
+        }
+        Poll::Ready(Ok(this.pos))
+    }
+}
+
+impl<C: ?Sized> std::fmt::Debug for BlobStream<'_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlobStream")
+            .field("blob", &self.blob)
+            .field("len", &self.len)
+            .field("pos", &self.pos)
+            .finish_non_exhaustive()
+    }
+}
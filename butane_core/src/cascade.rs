@@ -0,0 +1,465 @@
+//! Deleting an object together with everything which depends on it.
+//!
+//! The dependents are found through the relationships of the models in
+//! the [`registry`](crate::registry): objects with a `ForeignKey` to a
+//! deleted object are deleted in turn, as are the rows of `Many`
+//! relationships owned by or referring to a deleted object. Everything
+//! is deleted before whatever it refers to, so that no foreign key
+//! constraint is violated.
+//!
+//! The `on_delete` action declared on a `ForeignKey` field is followed:
+//! with `set_null` the field is set to null rather than its object
+//! deleted, and with `restrict` the delete fails with
+//! [`Error::Restricted`] if any object refers to a deleted one. Objects
+//! referring through a field declared with `no_foreign_key` are left as
+//! they are.
+//!
+//! ```ignore
+//! let report = blog.delete_cascade_dry_run(&conn)?;
+//! println!("would delete {} posts", report.objects("Post").len());
+//! blog.delete_cascade(&conn)?;
+//! ```
+#![deny(missing_docs)]
+
+use fallible_iterator::FallibleIterator;
+
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{atomic, helper, BackendRows, Column, ConnectionMethods};
+use crate::migrations::adb::OnDelete;
+use crate::query::{BoolExpr, Expr};
+use crate::registry::{self, ModelInfo, Relationship};
+use crate::{counter, DataObject, Error, Result, SqlType, SqlVal, SqlValRef};
+
+/// Name of the savepoint a cascading delete is made within, when made
+/// within a transaction already in progress.
+const SAVEPOINT: &str = "butane_delete_cascade";
+
+/// What a cascading delete does to the rows of a [`CascadeStep`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CascadeAction {
+    /// The rows are deleted.
+    Delete,
+    /// `column`, a `ForeignKey` declared with `on_delete = "set_null"`,
+    /// is set to null in the rows.
+    SetNull,
+}
+
+/// Rows deleted from one table by a cascading delete, or which would be
+/// deleted by it, or otherwise changed as with [`CascadeAction::SetNull`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CascadeStep {
+    /// What is done to the rows.
+    pub action: CascadeAction,
+    /// The table the rows are deleted from.
+    pub table: &'static str,
+    /// The column identifying the rows: the primary key for objects,
+    /// `owner` or `has` for the rows of a many-to-many relationship, or
+    /// the foreign key set to null.
+    pub column: &'static str,
+    /// The values of `column` in the rows.
+    pub values: Vec<SqlVal>,
+    /// The number of rows.
+    pub rows: usize,
+}
+
+/// What a cascading delete removes, or would remove, in the order it
+/// removes it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CascadeReport {
+    steps: Vec<CascadeStep>,
+}
+
+impl CascadeReport {
+    /// The rows deleted from each table, in the order they are deleted.
+    pub fn steps(&self) -> &[CascadeStep] {
+        &self.steps
+    }
+
+    /// The primary keys of the objects deleted of the model whose type
+    /// or table is called `model`.
+    pub fn objects(&self, model: &str) -> Vec<SqlVal> {
+        let Some(model) = registry::model(model) else {
+            return Vec::new();
+        };
+        self.steps
+            .iter()
+            .filter(|step| step.action == CascadeAction::Delete)
+            .filter(|step| step.table == model.table() && step.column == model.pk_column())
+            .flat_map(|step| step.values.iter().cloned())
+            .collect()
+    }
+
+    /// The number of rows deleted from `table`.
+    pub fn rows(&self, table: &str) -> usize {
+        self.deleted()
+            .filter(|step| step.table == table)
+            .map(|step| step.rows)
+            .sum()
+    }
+
+    /// The number of rows deleted from all tables.
+    pub fn total_rows(&self) -> usize {
+        self.deleted().map(|step| step.rows).sum()
+    }
+
+    fn deleted(&self) -> impl Iterator<Item = &CascadeStep> {
+        self.steps
+            .iter()
+            .filter(|step| step.action == CascadeAction::Delete)
+    }
+}
+
+/// An object to be deleted, found `depth` relationships away from the
+/// one whose delete was requested.
+struct Dependent {
+    model: &'static ModelInfo,
+    pk: SqlVal,
+    depth: usize,
+}
+
+/// Finds the registered model of `T`, which is backed by a table.
+fn model_of<T: DataObject>() -> Result<&'static ModelInfo> {
+    registry::models()
+        .find(|model| model.table() == T::TABLE && !model.view())
+        .ok_or_else(|| Error::Internal(format!("model of table {} is not registered", T::TABLE)))
+}
+
+/// The registered models backed by a table, one for each table.
+fn table_models() -> Vec<&'static ModelInfo> {
+    let mut models: Vec<&'static ModelInfo> = Vec::new();
+    for model in registry::models().filter(|model| !model.view()) {
+        if !models.iter().any(|m| m.table() == model.table()) {
+            models.push(model);
+        }
+    }
+    models
+}
+
+/// The primary key column of `model`.
+fn pk_column(model: &ModelInfo) -> Result<Column> {
+    model
+        .columns()
+        .iter()
+        .find(|col| col.name() == model.pk_column())
+        .cloned()
+        .ok_or_else(|| Error::Internal(format!("{} has no primary key column", model.table())))
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Loads `select` from the rows of `table` whose `column` is one of
+/// `values`, with as few statements as the number of parameters allows.
+async fn select_in(
+    conn: &impl ConnectionMethods,
+    table: &str,
+    select: &Column,
+    column: &'static str,
+    values: &[SqlVal],
+) -> Result<Vec<SqlVal>> {
+    let mut found = Vec::new();
+    for chunk in values.chunks(conn.max_statement_params()) {
+        let expr = BoolExpr::In(column, chunk.to_vec());
+        let rows = conn
+            .query(
+                table,
+                std::slice::from_ref(select),
+                Some(expr),
+                None,
+                None,
+                None,
+            )
+            .await?;
+        let mut vals: Vec<SqlVal> = rows
+            .mapped(|row| Ok(SqlVal::from(row.get(0, select.ty().clone())?)))
+            .collect()?;
+        found.append(&mut vals);
+    }
+    Ok(found)
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Counts the rows of `table` whose `column` is one of `values`.
+async fn count_in(
+    conn: &impl ConnectionMethods,
+    table: &str,
+    column: &'static str,
+    values: &[SqlVal],
+) -> Result<usize> {
+    let count = Column::new("count", SqlType::BigInt);
+    let mut total = 0;
+    for chunk in values.chunks(conn.max_statement_params()) {
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE {} IN ({});",
+            helper::quote_reserved_word(table),
+            helper::quote_reserved_word(column),
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let params: Vec<SqlValRef<'_>> = chunk.iter().map(SqlValRef::from).collect();
+        let rows = conn
+            .query_params(&sql, &params, std::slice::from_ref(&count))
+            .await?;
+        let counted: Option<i64> = rows
+            .mapped(|row| match row.get(0, SqlType::BigInt)? {
+                SqlValRef::BigInt(n) => Ok(n),
+                _ => Err(Error::Internal("COUNT(*) returned no number".into())),
+            })
+            .nth(0)?;
+        total += counted.unwrap_or(0) as usize;
+    }
+    Ok(total)
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        select_in(snake),
+        count_in(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Works out what deleting the object of `T` with primary key `pk`
+/// deletes, without deleting anything.
+pub(crate) async fn plan<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pk: SqlVal,
+) -> Result<CascadeReport> {
+    let models = table_models();
+    let mut dependents = vec![Dependent {
+        model: model_of::<T>()?,
+        pk,
+        depth: 0,
+    }];
+    // Indices of the dependents found at the last depth
+    let mut frontier = vec![0];
+    while !frontier.is_empty() {
+        let mut next = Vec::new();
+        for &referred in &models {
+            let pks: Vec<SqlVal> = frontier
+                .iter()
+                .map(|&i| &dependents[i])
+                .filter(|dependent| dependent.model.table() == referred.table())
+                .map(|dependent| dependent.pk.clone())
+                .collect();
+            if pks.is_empty() {
+                continue;
+            }
+            let depth = dependents[frontier[0]].depth + 1;
+            for &referring in &models {
+                for relationship in referring.relationships() {
+                    let Relationship::ForeignKey {
+                        column,
+                        target,
+                        on_delete,
+                    } = relationship
+                    else {
+                        continue;
+                    };
+                    if *target != referred.table() {
+                        continue;
+                    }
+                    let pkcol = pk_column(referring)?;
+                    let referring_pks = match on_delete {
+                        Some(OnDelete::Cascade) => {
+                            select_in(conn, referring.table(), &pkcol, column, &pks).await?
+                        }
+                        Some(OnDelete::Restrict) => {
+                            if count_in(conn, referring.table(), column, &pks).await? > 0 {
+                                return Err(Error::Restricted {
+                                    table: referred.table(),
+                                    referring: referring.table(),
+                                });
+                            }
+                            continue;
+                        }
+                        // Set to null just before deleting what they refer to
+                        Some(OnDelete::SetNull) | None => continue,
+                    };
+                    for pk in referring_pks {
+                        let known = dependents
+                            .iter()
+                            .position(|d| d.model.table() == referring.table() && d.pk == pk);
+                        match known {
+                            // Found again further away, so must be deleted sooner.
+                            // The depth is bounded in case the references form a cycle.
+                            Some(i) if dependents[i].depth < depth && depth <= dependents.len() => {
+                                dependents[i].depth = depth;
+                                next.push(i);
+                            }
+                            Some(_) => (),
+                            None => {
+                                dependents.push(Dependent {
+                                    model: referring,
+                                    pk,
+                                    depth,
+                                });
+                                next.push(dependents.len() - 1);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        next.sort_unstable();
+        next.dedup();
+        frontier = next;
+    }
+
+    // The furthest dependents are deleted first, each after the rows
+    // of the relationships which refer to it
+    let deepest = dependents.iter().map(|d| d.depth).max().unwrap_or(0);
+    let mut steps = Vec::new();
+    for depth in (0..=deepest).rev() {
+        for &model in &models {
+            let pks: Vec<SqlVal> = dependents
+                .iter()
+                .filter(|d| d.depth == depth && d.model.table() == model.table())
+                .map(|d| d.pk.clone())
+                .collect();
+            if pks.is_empty() {
+                continue;
+            }
+            for relationship in model.relationships() {
+                if let Relationship::Many { table, .. } = relationship {
+                    let rows = count_in(conn, table, "owner", &pks).await?;
+                    steps.push(CascadeStep {
+                        action: CascadeAction::Delete,
+                        table,
+                        column: "owner",
+                        values: pks.clone(),
+                        rows,
+                    });
+                }
+            }
+            for owner in &models {
+                for relationship in owner.relationships() {
+                    match relationship {
+                        Relationship::Many { table, target, .. } if *target == model.table() => {
+                            let rows = count_in(conn, table, "has", &pks).await?;
+                            steps.push(CascadeStep {
+                                action: CascadeAction::Delete,
+                                table,
+                                column: "has",
+                                values: pks.clone(),
+                                rows,
+                            });
+                        }
+                        Relationship::ForeignKey {
+                            column,
+                            target,
+                            on_delete: Some(OnDelete::SetNull),
+                        } if *target == model.table() => {
+                            let rows = count_in(conn, owner.table(), column, &pks).await?;
+                            steps.push(CascadeStep {
+                                action: CascadeAction::SetNull,
+                                table: owner.table(),
+                                column,
+                                values: pks.clone(),
+                                rows,
+                            });
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            steps.push(CascadeStep {
+                action: CascadeAction::Delete,
+                table: model.table(),
+                column: model.pk_column(),
+                rows: pks.len(),
+                values: pks,
+            });
+        }
+    }
+    steps.retain(|step| step.rows > 0);
+    Ok(CascadeReport { steps })
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        delete_planned(snake),
+        begin(snake),
+        end(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Deletes the object of `T` with primary key `pk` and everything which
+/// depends on it, as planned by `plan`, within a transaction, or within
+/// a savepoint if `conn` is already a transaction.
+pub(crate) async fn delete_all<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pk: SqlVal,
+) -> Result<CascadeReport> {
+    let atomic = atomic::begin(conn, SAVEPOINT).await?;
+    let result = delete_planned::<T>(conn, pk).await;
+    atomic::end(atomic, result).await
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        plan(snake),
+        delete_rows_counted(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Deletes what `plan` finds is to be deleted, and sets to null what it
+/// finds is to be, recording the number of rows actually changed. The
+/// counter caches kept by the objects deleted are decremented.
+async fn delete_planned<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pk: SqlVal,
+) -> Result<CascadeReport> {
+    let mut report = plan::<T>(conn, pk).await?;
+    let models = table_models();
+    for step in &mut report.steps {
+        if step.action == CascadeAction::SetNull {
+            let mut rows = 0;
+            for chunk in step.values.chunks(conn.max_statement_params()) {
+                let filter = BoolExpr::In(step.column, chunk.to_vec());
+                let null = vec![(step.column, Expr::Val(SqlVal::Null))];
+                rows += conn.update_where(step.table, null, filter).await?;
+            }
+            step.rows = rows;
+            continue;
+        }
+        let counted = models.iter().find(|model| {
+            model.table() == step.table
+                && model.pk_column() == step.column
+                && !model.counter_caches().is_empty()
+        });
+        step.rows = match counted {
+            Some(model) => {
+                let mut rows = 0;
+                for chunk in step.values.chunks(conn.max_statement_params()) {
+                    let filter = BoolExpr::In(step.column, chunk.to_vec());
+                    rows += counter::delete_rows_counted(
+                        conn,
+                        step.table,
+                        model.columns(),
+                        model.counter_caches(),
+                        filter,
+                    )
+                    .await?;
+                }
+                rows
+            }
+            None => {
+                conn.delete_many(step.table, step.column, &step.values)
+                    .await?
+            }
+        };
+    }
+    Ok(report)
+}
@@ -5,16 +5,30 @@
 use syn::{spanned::Spanned, Field, ItemStruct, LitStr};
 
 use super::{
-    extract_path_from_type, fields, get_autopk_sql_type, get_type_argument, is_auto,
-    is_many_to_many, is_row_field, make_ident_literal_str, make_lit, pk_field,
+    extract_path_from_type, fields, get_auto_uuid, get_autopk_sql_type, get_collation,
+    get_counter_cache, get_foreign_key_type_argument, get_many_table_name, get_many_type_argument,
+    get_notify, get_on_delete, get_partition_by, get_pk_generator, get_subtype_of, get_validations,
+    get_view, is_allow_duplicates, is_audited, is_auto, is_backfill, is_encrypted, is_foreign_key,
+    is_immutable, is_index, is_index_concurrently, is_many_through, is_many_to_many,
+    is_no_foreign_key, is_option, is_ordered_many, is_patch, is_readonly, is_refreshed,
+    is_row_field, is_sensitive, make_lit, pk_field,
+};
+use crate::migrations::adb::{
+    DeferredSqlType, IdentifierCase, OnDelete, TypeIdentifier, HISTORY_SUFFIX, MANY_SUFFIX,
 };
-use crate::migrations::adb::{DeferredSqlType, TypeIdentifier, MANY_SUFFIX};
 use crate::SqlType;
 
 /// Configuration that can be specified with attributes to override default behavior
 #[derive(Clone, Debug, Default)]
 pub struct Config {
     pub table_name: Option<String>,
+    pub identifier_case: IdentifierCase,
+}
+impl Config {
+    /// String literal naming `ident` in the database.
+    fn ident_lit(&self, ident: &Ident) -> LitStr {
+        make_lit(&self.identifier_case.fold(&ident.strip_raw().to_string()))
+    }
 }
 
 /// Code generation to implement the DataObject trait for a model
@@ -31,15 +45,139 @@ pub fn impl_dbobject(ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
     let pk_field = pk_field(ast_struct).unwrap();
     let pktype = &pk_field.ty;
     let pkident = pk_field.ident.clone().unwrap();
-    let pklit = make_ident_literal_str(&pkident);
+    let pklit = config.ident_lit(&pkident);
     let auto_pk = is_auto(&pk_field);
 
+    let row_idents: Vec<Ident> = fields(ast_struct)
+        .filter(|f| is_row_field(f))
+        .map(|f| f.ident.clone().unwrap())
+        .collect();
     let values: Vec<TokenStream2> = push_values(ast_struct, |_| true);
     let values_no_pk: Vec<TokenStream2> = push_values(ast_struct, |f: &Field| f != &pk_field);
-    let insert_cols = columns(ast_struct, |f| !is_auto(f));
+    let mut insert_cols = columns(ast_struct, config, |f| !is_auto(f) && !is_readonly(f));
+    let refreshed_cols = columns(ast_struct, config, is_refreshed);
+    let set_refreshed_values_fn = impl_set_refreshed_values(ast_struct);
+    let validate_fn = impl_validate(ast_struct);
+    let encrypt_fields_fn = impl_encrypt_fields(ast_struct, config, &pkident);
+    let writable = match get_view(ast_struct) {
+        Ok(Some(_)) => TokenStream2::new(),
+        _ => quote!(impl butane::WritableDataObject for #tyname {}),
+    };
+    let generate_pk_fn = match (get_auto_uuid(&pk_field), get_pk_generator(&pk_field)) {
+        (Ok(Some(_)), _) => quote!(
+            fn generate_pk(&mut self) {
+                if self.#pkident.is_nil() {
+                    self.#pkident = butane::internal::new_uuid_v7();
+                }
+            }
+        ),
+        (_, Ok(Some(generator))) => quote!(
+            fn generate_pk(&mut self) {
+                if self.#pkident == <#pktype as ::std::default::Default>::default() {
+                    self.#pkident = butane::pkgen::PkGenerator::generate(&#generator);
+                }
+            }
+        ),
+        _ => TokenStream2::new(),
+    };
+    let notify_channel = match get_notify(ast_struct) {
+        Ok(Some(channel)) => quote!(
+            const NOTIFY_CHANNEL: Option<&'static str> = Some(#channel);
+        ),
+        _ => TokenStream2::new(),
+    };
+    let audit_table = if is_audited(ast_struct) {
+        let history_table = history_table_lit(ast_struct, config);
+        quote!(
+            const AUDIT_TABLE: Option<&'static str> = Some(#history_table);
+        )
+    } else {
+        TokenStream2::new()
+    };
+    let immutable = if is_immutable(ast_struct) {
+        quote!(
+            const IMMUTABLE: bool = true;
+        )
+    } else {
+        TokenStream2::new()
+    };
+    let subtype = match get_subtype_of(ast_struct) {
+        Ok(Some((base, discriminator))) => Some((
+            base,
+            make_lit(&config.identifier_case.fold(&discriminator.value())),
+            make_lit(&tyname.strip_raw().to_string()),
+        )),
+        _ => None,
+    };
+    let (discriminator, push_discriminator, subtype_impl) = match &subtype {
+        Some((base, discriminator, kind)) => {
+            insert_cols.extend(quote!(
+                butane::db::Column::new(#discriminator, butane::SqlType::Text),
+            ));
+            (
+                quote!(
+                    const DISCRIMINATOR: Option<(&'static str, &'static str)> =
+                        Some((#discriminator, #kind));
+                ),
+                quote!(values.push(butane::SqlValRef::Text(#kind));),
+                quote!(
+                    impl butane::subtype::Subtype for #tyname {
+                        type Supertype = #base;
+                        const DISCRIMINATOR: &'static str = #discriminator;
+                        const KIND: &'static str = #kind;
+                    }
+                ),
+            )
+        }
+        None => (
+            TokenStream2::new(),
+            TokenStream2::new(),
+            TokenStream2::new(),
+        ),
+    };
+    let many_tables: Vec<LitStr> = fields(ast_struct)
+        .filter(|f| is_many_to_many(f))
+        .map(|f| many_table_lit(ast_struct, f, config))
+        .collect();
+    let counter_caches: Vec<TokenStream2> = fields(ast_struct)
+        .filter_map(|f| {
+            // Malformed attributes are reported by verify_fields
+            let (_, counter) = get_counter_cache(f).ok().flatten()?;
+            let target = get_foreign_key_type_argument(f)?;
+            let column = config.ident_lit(f.ident.as_ref().unwrap());
+            let counter = make_lit(&config.identifier_case.fold(&counter));
+            Some(quote!(
+                butane::counter::CounterCache {
+                    column: #column,
+                    table: <#target as butane::DataObject>::TABLE,
+                    pkcol: <#target as butane::DataObject>::PKCOL,
+                    counter: #counter,
+                }
+            ))
+        })
+        .collect();
+    let counter_caches = if counter_caches.is_empty() {
+        TokenStream2::new()
+    } else {
+        quote!(
+            const COUNTER_CACHES: &'static [butane::counter::CounterCache] = &[
+                #(#counter_caches),*
+            ];
+        )
+    };
 
     let many_save_sync = impl_many_save(ast_struct, config, false);
     let save_many_to_many_async = def_for_save_many_to_many_async(ast_struct, config);
+    let load_related_sync = def_for_load_related(ast_struct, config, false);
+    let load_related_async = def_for_load_related_async(ast_struct, config);
+    let registration = def_for_registry(ast_struct, config);
+    let sensitive_table = if fields(ast_struct).any(|f| is_row_field(f) && is_sensitive(f)) {
+        quote!(butane::db::inventory::submit! {
+            butane::db::SensitiveTable(#tablelit)
+        })
+    } else {
+        TokenStream2::new()
+    };
 
     let conn_arg_name = if many_save_sync.is_empty() {
         syn::Ident::new("_conn", Span::call_site())
@@ -47,7 +185,7 @@ pub fn impl_dbobject(ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
         syn::Ident::new("conn", Span::call_site())
     };
 
-    let non_auto_values_fn = if values.is_empty() {
+    let non_auto_values_fn = if values.is_empty() && subtype.is_none() {
         quote!(
             fn non_auto_values(&self, _include_pk: bool) -> Vec<butane::SqlValRef> {
                 return vec![];
@@ -64,6 +202,7 @@ fn non_auto_values(&self, include_pk: bool) -> Vec<butane::SqlValRef> {
                 } else {
                     #(#values_no_pk)*
                 }
+                #push_discriminator
                 values
             }
         )
@@ -80,10 +219,22 @@ impl butane::internal::DataObjectInternal for #tyname {
             const NON_AUTO_COLUMNS: &'static [butane::db::Column] = &[
                 #insert_cols
             ];
+            const REFRESHED_COLUMNS: &'static [butane::db::Column] = &[
+                #refreshed_cols
+            ];
+            const MANY_TABLES: &'static [&'static str] = &[#(#many_tables),*];
+            #notify_channel
+            #audit_table
+            #immutable
+            #discriminator
+            #counter_caches
 
             fn pk_mut(&mut self) -> &mut impl butane::PrimaryKeyType {
                 &mut self.#pkident
             }
+            #generate_pk_fn
+            #validate_fn
+            #encrypt_fields_fn
             #save_many_to_many_async
             fn save_many_to_many_sync(
                 &mut self,
@@ -92,7 +243,10 @@ fn save_many_to_many_sync(
                 #many_save_sync
                 Ok(())
             }
+            #load_related_async
+            #load_related_sync
             #non_auto_values_fn
+            #set_refreshed_values_fn
         }
 
         impl butane::DataObject for #tyname {
@@ -106,6 +260,37 @@ fn pk(&self) -> &Self::PKType {
                 &self.#pkident
             }
         }
+        impl butane::AsPrimaryKey<#tyname> for #pktype {
+            fn as_pk(&self) -> std::borrow::Cow<'_, #pktype> {
+                std::borrow::Cow::Borrowed(self)
+            }
+        }
+        #writable
+        #registration
+        #sensitive_table
+        #subtype_impl
+
+        impl butane::DynDataObject for #tyname {
+            fn table(&self) -> &'static str {
+                #tablelit
+            }
+            fn columns(&self) -> &'static [butane::db::Column] {
+                <Self as butane::DataResult>::COLUMNS
+            }
+            fn pk_value(&self) -> butane::SqlVal {
+                butane::ToSql::to_sql(&self.#pkident)
+            }
+            fn to_values(&self) -> Vec<butane::SqlVal> {
+                vec![#(butane::ToSql::to_sql(&self.#row_idents)),*]
+            }
+            fn set_values(&mut self, values: Vec<butane::SqlVal>) -> butane::Result<()> {
+                *self = butane::internal::from_values(values, None)?;
+                Ok(())
+            }
+            fn from_values(values: Vec<butane::SqlVal>) -> butane::Result<Self> {
+                butane::internal::from_values(values, None)
+            }
+        }
         impl butane::ToSql for #tyname {
             fn to_sql(&self) -> butane::SqlVal {
                 #[allow(unused_imports)]
@@ -161,22 +346,31 @@ fn as_pk(&self) -> std::borrow::Cow<<#tyname as butane::DataObject>::PKType> {
 pub fn impl_dataresult(ast_struct: &ItemStruct, dbo: &Ident, config: &Config) -> TokenStream2 {
     let tyname = &ast_struct.ident;
     let numdbfields = fields(ast_struct).filter(|f| is_row_field(f)).count();
-    let rows = rows_for_from(ast_struct);
-    let cols = columns(ast_struct, |_| true);
+    let rows = rows_for_from(ast_struct, config);
+    let cols = columns(ast_struct, config, |_| true);
 
     let many_init: TokenStream2 = fields(ast_struct)
-        .filter(|f| is_many_to_many(f))
+        .filter(|f| is_many_to_many(f) || is_many_through(f))
         .map(|f| {
             let ident = f.ident.clone().expect("Fields must be named for butane");
+            if is_many_through(f) {
+                return quote!(obj.#ident.ensure_init(butane::ToSql::to_sql(obj.pk())););
+            }
             let many_table_lit = many_table_lit(ast_struct, f, config);
             let pksqltype =
                 quote!(<<Self as butane::DataObject>::PKType as butane::FieldType>::SQLTYPE);
+            let allow_duplicates = if is_allow_duplicates(f) {
+                quote!(obj.#ident.allow_duplicates();)
+            } else {
+                quote!()
+            };
             quote!(
                 obj.#ident.ensure_init(
                     #many_table_lit,
                     butane::ToSql::to_sql(obj.pk()),
                     #pksqltype,
                 );
+                #allow_duplicates
             )
         })
         .collect();
@@ -197,21 +391,58 @@ pub fn impl_dataresult(ast_struct: &ItemStruct, dbo: &Ident, config: &Config) ->
         )
     };
 
+    let check_len = quote!(
+        if row.len() != #numdbfields {
+            return Err(butane::Error::BoundsError(
+                "Found unexpected number of columns in row for DataResult".to_string()
+            ));
+        }
+    );
+    let from_row_fns = if fields(ast_struct).any(|f| is_row_field(f) && is_encrypted(f)) {
+        // Encrypted values are bound to the primary key, which is read first
+        let pk_field = pk_field(ast_struct);
+        let Some((pk_index, pk_field)) = fields(ast_struct)
+            .filter(|f| is_row_field(f))
+            .enumerate()
+            .find(|(_, f)| Some(*f) == pk_field.as_ref())
+        else {
+            return make_compile_error!(
+                ast_struct.span() => "a DataResult with Encrypted fields must include the primary key"
+            );
+        };
+        let pkty = &pk_field.ty;
+        quote!(
+            fn from_row(row: &dyn butane::db::BackendRow) -> butane::Result<Self> {
+                Self::from_row_decrypting(row, None)
+            }
+            fn from_row_decrypting(
+                row: &dyn butane::db::BackendRow,
+                keys: Option<&dyn butane::encryption::KeyProvider>,
+            ) -> butane::Result<Self> {
+                use butane::DataObject;
+                #check_len
+                let keys = butane::encryption::require_key_provider(keys)?;
+                let pk = row.get(#pk_index, <#pkty as butane::FieldType>::SQLTYPE)?;
+                #from_row_body
+            }
+        )
+    } else {
+        quote!(
+            fn from_row(row: &dyn butane::db::BackendRow) -> butane::Result<Self> {
+                use butane::DataObject;
+                #check_len
+                #from_row_body
+            }
+        )
+    };
+
     quote!(
         impl butane::DataResult for #tyname {
             type DBO = #dbo;
             const COLUMNS: &'static [butane::db::Column] = &[
                 #cols
             ];
-            fn from_row(row: &dyn butane::db::BackendRow) -> butane::Result<Self> {
-                use butane::DataObject;
-                if row.len() != #numdbfields {
-                    return Err(butane::Error::BoundsError(
-                        "Found unexpected number of columns in row for DataResult".to_string()
-                    ));
-                }
-                #from_row_body
-            }
+            #from_row_fns
             fn query() -> butane::query::Query<Self> {
                 #[allow(unused_imports)]
                 use butane::DataObject;
@@ -223,8 +454,8 @@ fn query() -> butane::query::Query<Self> {
 
 fn make_tablelit(config: &Config, tyname: &Ident) -> LitStr {
     match &config.table_name {
-        Some(s) => make_lit(s),
-        None => make_ident_literal_str(tyname),
+        Some(s) => make_lit(&config.identifier_case.fold(s)),
+        None => config.ident_lit(tyname),
     }
 }
 
@@ -233,11 +464,12 @@ pub fn add_fieldexprs(ast_struct: &ItemStruct, config: &Config) -> TokenStream2
     let tyname = &ast_struct.ident;
     let vis = &ast_struct.vis;
     let fieldexprs: Vec<TokenStream2> = fields(ast_struct)
+        .filter(|f| !is_many_through(f))
         .map(|f| {
             if is_many_to_many(f) {
                 fieldexpr_func_many(f, ast_struct, config)
             } else {
-                fieldexpr_func_regular(f, ast_struct)
+                fieldexpr_func_regular(f, ast_struct, config)
             }
         })
         .collect();
@@ -263,9 +495,100 @@ fn default() -> Self {
     )
 }
 
-fn fieldexpr_func_regular(f: &Field, ast_struct: &ItemStruct) -> TokenStream2 {
+/// Generates the patch struct of a model with `#[butane(patch)]`, with
+/// an optional field for each column other than the primary key and
+/// those which are readonly.
+pub fn add_patch(ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
+    if !is_patch(ast_struct) {
+        return TokenStream2::new();
+    }
+    let tyname = &ast_struct.ident;
+    let vis = &ast_struct.vis;
+    let patch_type = Ident::new(&format!("{}Patch", tyname.strip_raw()), Span::call_site());
+    let doc = make_lit(&format!(
+        "Changes to some of the fields of a [`{}`], generated by `#[butane(patch)]`.",
+        tyname.strip_raw()
+    ));
+    let pk_field = pk_field(ast_struct);
+    let patch_fields: Vec<&Field> = fields(ast_struct)
+        .filter(|f| {
+            is_row_field(f) && Some(*f) != pk_field.as_ref() && !is_readonly(f) && !is_encrypted(f)
+        })
+        .collect();
+    let idents: Vec<&Ident> = patch_fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .collect();
+    let decls = patch_fields.iter().map(|f| {
+        let fvis = &f.vis;
+        let fident = &f.ident;
+        let fty = &f.ty;
+        let docs = f.attrs.iter().filter(|a| a.path().is_ident("doc"));
+        quote!(#(#docs)* #fvis #fident: Option<#fty>)
+    });
+    let lits = patch_fields.iter().map(|f| field_ident_lit(f, config));
+    // Derives of the model are kept, except Default which is implemented
+    // below as the patch changing nothing
+    let derives = ast_struct
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("derive"))
+        .filter_map(|a| {
+            a.parse_args_with(
+                syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_terminated,
+            )
+            .ok()
+        })
+        .flatten()
+        .filter(|path| !path.is_ident("Default"));
+    let serde_attrs = ast_struct
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("serde"));
+    quote!(
+        #[doc = #doc]
+        #[derive(#(#derives),*)]
+        #(#serde_attrs)*
+        #vis struct #patch_type {
+            #(#decls,)*
+        }
+        impl std::default::Default for #patch_type {
+            fn default() -> Self {
+                #patch_type {
+                    #(#idents: None,)*
+                }
+            }
+        }
+        impl butane::query::Patch for #patch_type {
+            type Model = #tyname;
+            #[allow(unused_variables)]
+            fn apply(self, obj: &mut #tyname) {
+                #(
+                    if let Some(value) = self.#idents {
+                        obj.#idents = value;
+                    }
+                )*
+            }
+            fn into_update(self) -> butane::query::Update<#tyname> {
+                let update = butane::query::Update::new();
+                #(
+                    let update = match self.#idents {
+                        Some(value) => update.set(
+                            #lits,
+                            butane::query::Expr::Val(butane::ToSql::into_sql(value)),
+                        ),
+                        None => update,
+                    };
+                )*
+                update
+            }
+        }
+    )
+}
+
+fn fieldexpr_func_regular(f: &Field, ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
     let fty = &f.ty;
-    let fidlit = field_ident_lit(f);
+    let fidlit = field_ident_lit(f, config);
     fieldexpr_func(
         f,
         ast_struct,
@@ -276,7 +599,7 @@ fn fieldexpr_func_regular(f: &Field, ast_struct: &ItemStruct) -> TokenStream2 {
 
 fn fieldexpr_func_many(f: &Field, ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
     let tyname = &ast_struct.ident;
-    let fty = get_type_argument(&f.ty, "Many").expect("Many field misdetected");
+    let fty = get_many_type_argument(f).expect("Many field misdetected");
     let many_table_lit = many_table_lit(ast_struct, f, config);
     fieldexpr_func(
         f,
@@ -310,7 +633,7 @@ fn fieldexpr_func(
     )
 }
 
-fn field_ident_lit(f: &Field) -> TokenStream2 {
+fn field_ident_lit(f: &Field, config: &Config) -> TokenStream2 {
     let fid = match &f.ident {
         Some(fid) => fid,
         None => {
@@ -320,7 +643,7 @@ fn field_ident_lit(f: &Field) -> TokenStream2 {
             )
         }
     };
-    make_ident_literal_str(fid).into_token_stream()
+    config.ident_lit(fid).into_token_stream()
 }
 
 fn fields_type(tyname: &Ident) -> Ident {
@@ -328,12 +651,30 @@ fn fields_type(tyname: &Ident) -> Ident {
     Ident::new(&format!("{stripped}Fields"), Span::call_site())
 }
 
-fn rows_for_from(ast_struct: &ItemStruct) -> Vec<TokenStream2> {
+fn rows_for_from(ast_struct: &ItemStruct, config: &Config) -> Vec<TokenStream2> {
     let mut i: usize = 0;
     fields(ast_struct)
         .map(|f| {
             let ident = f.ident.clone().unwrap();
-            if is_row_field(f) {
+            if is_row_field(f) && is_encrypted(f) {
+                // Opened with the key provider and primary key read
+                // by from_row_decrypting
+                let fty = &f.ty;
+                let lit = field_ident_lit(f, config);
+                let ret = quote!(
+                    #ident: <#fty as butane::encryption::EncryptedField>::open(
+                        row.get(#i, <#fty as butane::FieldType>::SQLTYPE)?,
+                        keys,
+                        &butane::encryption::associated_data(
+                            <<Self as butane::DataResult>::DBO as butane::DataObject>::TABLE,
+                            #lit,
+                            pk.clone(),
+                        ),
+                    )?
+                );
+                i += 1;
+                ret
+            } else if is_row_field(f) {
                 let fty = &f.ty;
                 let ret = quote!(
                     #ident: butane::FromSql::from_sql_ref(
@@ -342,8 +683,8 @@ fn rows_for_from(ast_struct: &ItemStruct) -> Vec<TokenStream2> {
                 );
                 i += 1;
                 ret
-            } else if is_many_to_many(f) {
-                quote!(#ident: butane::Many::new())
+            } else if is_many_to_many(f) || is_many_through(f) {
+                quote!(#ident: Default::default())
             } else {
                 make_compile_error!(f.span()=> "Unexpected struct field")
             }
@@ -351,7 +692,7 @@ fn rows_for_from(ast_struct: &ItemStruct) -> Vec<TokenStream2> {
         .collect()
 }
 
-fn columns<P>(ast_struct: &ItemStruct, mut predicate: P) -> TokenStream2
+fn columns<P>(ast_struct: &ItemStruct, config: &Config, mut predicate: P) -> TokenStream2
 where
     P: FnMut(&Field) -> bool,
 {
@@ -359,9 +700,15 @@ fn columns<P>(ast_struct: &ItemStruct, mut predicate: P) -> TokenStream2
         .filter(|f| is_row_field(f) && predicate(f))
         .map(|f| match f.ident.clone() {
             Some(fname) => {
-                let ident = make_ident_literal_str(&fname);
+                let ident = config.ident_lit(&fname);
                 let fty = &f.ty;
-                quote!(butane::db::Column::new(#ident, <#fty as butane::FieldType>::SQLTYPE),)
+                let column =
+                    quote!(butane::db::Column::new(#ident, <#fty as butane::FieldType>::SQLTYPE));
+                if is_sensitive(f) {
+                    quote!(#column.sensitive(),)
+                } else {
+                    quote!(#column,)
+                }
             }
             None => quote_spanned! {
                 f.span() =>
@@ -376,12 +723,29 @@ fn many_table_lit(ast_struct: &ItemStruct, field: &Field, config: &Config) -> Li
         .ident
         .clone()
         .expect("Fields must be named for butane");
+    if let Ok(Some(name)) = get_many_table_name(field) {
+        return make_lit(&config.identifier_case.fold(&name));
+    }
+    let binding = ast_struct.ident.strip_raw().to_string();
+    let tyname = match &config.table_name {
+        Some(s) => s,
+        None => &binding,
+    };
+    let name = format!("{}_{}{MANY_SUFFIX}", &tyname, &ident.strip_raw());
+    make_lit(&config.identifier_case.fold(&name))
+}
+
+fn history_table_lit(ast_struct: &ItemStruct, config: &Config) -> LitStr {
     let binding = ast_struct.ident.strip_raw().to_string();
     let tyname = match &config.table_name {
         Some(s) => s,
         None => &binding,
     };
-    make_lit(&format!("{}_{}{MANY_SUFFIX}", &tyname, &ident.strip_raw()))
+    make_lit(
+        &config
+            .identifier_case
+            .fold(&format!("{tyname}{HISTORY_SUFFIX}")),
+    )
 }
 
 fn verify_fields(ast_struct: &ItemStruct) -> Option<TokenStream2> {
@@ -390,7 +754,231 @@ fn verify_fields(ast_struct: &ItemStruct) -> Option<TokenStream2> {
         return Some(make_compile_error!(ast_struct.span() => "No pk field found"));
     };
     let pk_field = pk_field.unwrap();
+    match get_partition_by(ast_struct) {
+        Err(err) => return Some(err.to_compile_error()),
+        Ok(Some(partition_by))
+            if !fields(ast_struct).any(|f| {
+                is_row_field(f)
+                    && f.ident.as_ref().unwrap().strip_raw() == partition_by.column.as_str()
+            }) =>
+        {
+            return Some(make_compile_error!(
+                ast_struct.span() => "partition_by must name a column of the model"
+            ));
+        }
+        Ok(_) => (),
+    }
+    let view = match get_view(ast_struct) {
+        Err(err) => return Some(err.to_compile_error()),
+        Ok(view) => view,
+    };
+    match &view {
+        Some(view) if !view.materialized => {
+            if let Some(f) = fields(ast_struct).find(|f| is_index(f)) {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("index is only supported on tables and materialized views");
+                ));
+            }
+        }
+        Some(_) => {
+            if let Some(f) = fields(ast_struct).find(|f| is_index_concurrently(f)) {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("index(concurrently) is not supported on materialized views");
+                ));
+            }
+        }
+        None => {
+            if let Some(f) = fields(ast_struct).find(|f| is_index(f) && !is_row_field(f)) {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("index is only supported on fields stored in a column");
+                ));
+            }
+        }
+    }
+    match get_subtype_of(ast_struct) {
+        Err(err) => return Some(err.to_compile_error()),
+        Ok(Some(_)) if view.is_some() => {
+            return Some(make_compile_error!(
+                ast_struct.span() => "subtype_of is not supported on views"
+            ));
+        }
+        Ok(Some((_, discriminator)))
+            if fields(ast_struct)
+                .any(|f| f.ident.as_ref().unwrap().strip_raw() == discriminator.value()) =>
+        {
+            return Some(quote_spanned!(
+                discriminator.span() =>
+                    compile_error!("the discriminator must not be a field of the model");
+            ));
+        }
+        Ok(_) => (),
+    }
+    if is_auto(&pk_field) {
+        if let Some(f) = fields(ast_struct).find(|f| is_encrypted(f)) {
+            return Some(quote_spanned!(
+                f.span() =>
+                    compile_error!("Encrypted fields are bound to the primary key, so are not supported with AutoPk");
+            ));
+        }
+    }
+    if is_immutable(ast_struct) && is_patch(ast_struct) {
+        return Some(make_compile_error!(
+            ast_struct.span() => "patch is not supported on immutable models"
+        ));
+    }
+    if view.is_some() {
+        if let Ok(Some(_)) = get_partition_by(ast_struct) {
+            return Some(make_compile_error!(
+                ast_struct.span() => "partition_by is not supported on views"
+            ));
+        }
+        if is_audited(ast_struct) {
+            return Some(make_compile_error!(
+                ast_struct.span() => "audited is not supported on views"
+            ));
+        }
+        if is_immutable(ast_struct) {
+            return Some(make_compile_error!(
+                ast_struct.span() => "immutable is not supported on views"
+            ));
+        }
+        if let Some(f) = fields(ast_struct).find(|f| !is_row_field(f)) {
+            return Some(quote_spanned!(
+                f.span() =>
+                    compile_error!("Many fields are not supported on views");
+            ));
+        }
+    }
     for f in fields(ast_struct) {
+        match get_many_table_name(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(Some(_)) if !is_many_to_many(f) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("many_table is only supported on Many fields");
+                ))
+            }
+            Ok(_) => (),
+        }
+        match get_auto_uuid(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(Some(_)) if &pk_field != f => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("auto_uuid is only supported on the primary key");
+                ))
+            }
+            Ok(_) => (),
+        }
+        match get_pk_generator(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(Some(_)) if &pk_field != f => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("pk_generator is only supported on the primary key");
+                ))
+            }
+            Ok(Some(_)) if is_auto(f) || matches!(get_auto_uuid(f), Ok(Some(_))) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("pk_generator cannot be combined with AutoPk or auto_uuid");
+                ))
+            }
+            Ok(_) => (),
+        }
+        if is_allow_duplicates(f) && (!is_many_to_many(f) || is_ordered_many(f)) {
+            return Some(quote_spanned!(
+                f.span() =>
+                    compile_error!("allow_duplicates is only supported on Many fields");
+            ));
+        }
+        match get_counter_cache(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(Some((model, _))) => match get_foreign_key_type_argument(f) {
+                None => {
+                    return Some(quote_spanned!(
+                        f.span() =>
+                            compile_error!("counter_cache is only supported on ForeignKey fields");
+                    ))
+                }
+                Some(target)
+                    if !target
+                        .segments
+                        .last()
+                        .is_some_and(|segment| segment.ident.strip_raw() == model.as_str()) =>
+                {
+                    return Some(quote_spanned!(
+                        f.span() =>
+                            compile_error!("counter_cache must name the model the ForeignKey refers to");
+                    ))
+                }
+                Some(_) => (),
+            },
+            Ok(None) => (),
+        }
+        if is_no_foreign_key(f) && !is_foreign_key(f) && !is_many_to_many(f) {
+            return Some(quote_spanned!(
+                f.span() =>
+                    compile_error!("no_foreign_key is only supported on ForeignKey and Many fields");
+            ));
+        }
+        match get_validations(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(validations) if !validations.is_empty() && !is_row_field(f) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("validation is only supported on fields stored in a column");
+                ))
+            }
+            Ok(_) => (),
+        }
+        match get_collation(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(Some(_)) if !is_row_field(f) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("collate is only supported on fields stored in a column");
+                ))
+            }
+            Ok(_) => (),
+        }
+        if is_backfill(f) && (!is_row_field(f) || is_option(f) || &pk_field == f) {
+            return Some(quote_spanned!(
+                f.span() =>
+                    compile_error!("backfill is only supported on non-optional fields which are not the primary key");
+            ));
+        }
+        match get_on_delete(f) {
+            Err(err) => return Some(err.to_compile_error()),
+            Ok(Some(_)) if !is_foreign_key(f) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("on_delete is only supported on ForeignKey fields");
+                ))
+            }
+            Ok(Some(_)) if is_no_foreign_key(f) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("on_delete requires a foreign key constraint");
+                ))
+            }
+            Ok(Some(OnDelete::SetNull)) if !is_option(f) => {
+                return Some(quote_spanned!(
+                    f.span() =>
+                        compile_error!("on_delete = \"set_null\" requires an Option<ForeignKey> field");
+                ))
+            }
+            Ok(_) => (),
+        }
+        if is_refreshed(f) && (!is_row_field(f) || &pk_field == f) {
+            return Some(quote_spanned!(
+                f.span() =>
+                    compile_error!("readonly and refresh are only supported on columns other than the primary key");
+            ));
+        }
         if is_auto(f) {
             let path = extract_path_from_type(&f.ty);
             match get_autopk_sql_type(path) {
@@ -413,6 +1001,112 @@ fn verify_fields(ast_struct: &ItemStruct) -> Option<TokenStream2> {
     None
 }
 
+/// Builds `set_refreshed_values`, assigning refreshed fields in column order.
+fn impl_set_refreshed_values(ast_struct: &ItemStruct) -> TokenStream2 {
+    let assignments: Vec<TokenStream2> = fields(ast_struct)
+        .filter(|f| is_row_field(f) && is_refreshed(f))
+        .map(|f| {
+            let ident = f.ident.clone().unwrap();
+            quote!(
+                self.#ident = butane::FromSql::from_sql(
+                    values.next().ok_or(butane::Error::BoundsError(
+                        "too few refreshed values".into()
+                    ))?
+                )?;
+            )
+        })
+        .collect();
+    if assignments.is_empty() {
+        return TokenStream2::new();
+    }
+    quote!(
+        fn set_refreshed_values(&mut self, values: Vec<butane::SqlVal>) -> butane::Result<()> {
+            let mut values = values.into_iter();
+            #(#assignments)*
+            Ok(())
+        }
+    )
+}
+
+fn impl_validate(ast_struct: &ItemStruct) -> TokenStream2 {
+    let checks: Vec<TokenStream2> = fields(ast_struct)
+        .filter(|f| is_row_field(f))
+        .filter_map(|f| {
+            // Invalid attributes are reported by verify_fields
+            let validations = get_validations(f).ok()?;
+            if validations.is_empty() {
+                return None;
+            }
+            let ident = f.ident.clone().unwrap();
+            let name = make_lit(&ident.strip_raw().to_string());
+            let mut calls = Vec::new();
+            if let Some(max_length) = validations.max_length {
+                calls.push(quote!(butane::validate::max_length(#name, value, #max_length)?;));
+            }
+            if let Some(min) = validations.min {
+                calls.push(quote!(butane::validate::min(#name, value, #min)?;));
+            }
+            if let Some(max) = validations.max {
+                calls.push(quote!(butane::validate::max(#name, value, #max)?;));
+            }
+            if let Some(regex) = validations.regex {
+                calls.push(quote!(butane::validate::regex(#name, value, #regex)?;));
+            }
+            Some(if is_option(f) {
+                quote!(
+                    if let Some(value) = &self.#ident {
+                        #(#calls)*
+                    }
+                )
+            } else {
+                quote!(
+                    let value = &self.#ident;
+                    #(#calls)*
+                )
+            })
+        })
+        .collect();
+    if checks.is_empty() {
+        return TokenStream2::new();
+    }
+    quote!(
+        fn validate(&self) -> butane::Result<()> {
+            #({ #checks })*
+            Ok(())
+        }
+    )
+}
+
+/// Encrypts each encrypted field before a save, bound to its table,
+/// column and the primary key of the object. See [`crate::encryption`].
+fn impl_encrypt_fields(ast_struct: &ItemStruct, config: &Config, pkident: &Ident) -> TokenStream2 {
+    let (idents, lits): (Vec<Ident>, Vec<TokenStream2>) = fields(ast_struct)
+        .filter(|f| is_row_field(f) && is_encrypted(f))
+        .map(|f| (f.ident.clone().unwrap(), field_ident_lit(f, config)))
+        .unzip();
+    if idents.is_empty() {
+        return TokenStream2::new();
+    }
+    quote!(
+        fn encrypt_fields(
+            &mut self,
+            keys: Option<&dyn butane::encryption::KeyProvider>,
+        ) -> butane::Result<()> {
+            use butane::DataObject;
+            let keys = butane::encryption::require_key_provider(keys)?;
+            let pk = butane::ToSql::to_sql(&self.#pkident);
+            #(
+                butane::encryption::EncryptedField::seal(
+                    &mut self.#idents,
+                    keys,
+                    &butane::encryption::associated_data(Self::TABLE, #lits, pk.as_ref()),
+                )?;
+            )*
+            Ok(())
+        }
+    )
+}
+
 /// Builds code for pushing SqlVals for each column satisfying predicate into a vec called `values`
 /// that excludes any auto values.
 fn push_values<P>(ast_struct: &ItemStruct, mut predicate: P) -> Vec<TokenStream2>
@@ -420,7 +1114,7 @@ fn push_values<P>(ast_struct: &ItemStruct, mut predicate: P) -> Vec<TokenStream2
     P: FnMut(&Field) -> bool,
 {
     fields(ast_struct)
-        .filter(|f| is_row_field(f) && !is_auto(f) && predicate(f))
+        .filter(|f| is_row_field(f) && !is_auto(f) && !is_readonly(f) && predicate(f))
         .map(|f| {
             let ident = f.ident.clone().unwrap();
             quote!(values.push(butane::ToSql::to_sql_ref(&self.#ident));)
@@ -430,9 +1124,15 @@ fn push_values<P>(ast_struct: &ItemStruct, mut predicate: P) -> Vec<TokenStream2
 
 fn impl_many_save(ast_struct: &ItemStruct, config: &Config, is_async: bool) -> TokenStream2 {
     fields(ast_struct)
-        .filter(|f| is_many_to_many(f))
+        .filter(|f| is_many_to_many(f) || is_many_through(f))
         .map(|f| {
             let ident = f.ident.clone().expect("Fields must be named for butane");
+            if is_many_through(f) {
+                // Nothing to save, but the owner may have only just been assigned a pk
+                return quote!(
+                    self.#ident.ensure_init(butane::ToSql::to_sql(butane::DataObject::pk(self)));
+                );
+            }
             let many_table_lit = many_table_lit(ast_struct, f, config);
             let pksqltype =
                 quote!(<<Self as butane::DataObject>::PKType as butane::FieldType>::SQLTYPE);
@@ -443,6 +1143,12 @@ fn impl_many_save(ast_struct: &ItemStruct, config: &Config, is_async: bool) -> T
                 quote!(butane::ManyOpsSync::save(&mut self.#ident, conn)?;)
             };
 
+            let allow_duplicates = if is_allow_duplicates(f) {
+                quote!(self.#ident.allow_duplicates();)
+            } else {
+                quote!()
+            };
+
             // Save needs to ensure_initialized
             quote!(
                 self.#ident.ensure_init(
@@ -450,6 +1156,7 @@ fn impl_many_save(ast_struct: &ItemStruct, config: &Config, is_async: bool) -> T
                     butane::ToSql::to_sql(butane::DataObject::pk(self)),
                     #pksqltype,
                 );
+                #allow_duplicates
                 #save_with_conn
             )
         })
@@ -480,3 +1187,139 @@ async fn save_many_to_many_async(
 fn def_for_save_many_to_many_async(_ast_struct: &ItemStruct, _config: &Config) -> TokenStream2 {
     quote!()
 }
+
+/// Registers the model in [`crate::registry`], describing its relationships.
+#[cfg(feature = "registry")]
+fn def_for_registry(ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
+    let tyname = &ast_struct.ident;
+    let namelit = super::make_ident_literal_str(tyname);
+    let relationships: Vec<TokenStream2> = fields(ast_struct)
+        .filter_map(|f| {
+            if let Some(target) = get_many_type_argument(f) {
+                let fieldlit = super::make_ident_literal_str(f.ident.as_ref()?);
+                let many_table_lit = many_table_lit(ast_struct, f, config);
+                return Some(quote!(
+                    butane::registry::Relationship::Many {
+                        field: #fieldlit,
+                        table: #many_table_lit,
+                        target: <#target as butane::DataObject>::TABLE,
+                    }
+                ));
+            }
+            let target = super::get_foreign_key_type_argument(f)?;
+            let fidlit = field_ident_lit(f, config);
+            let on_delete = if is_no_foreign_key(f) {
+                quote!(None)
+            } else {
+                let action = match get_on_delete(f) {
+                    Ok(Some(OnDelete::SetNull)) => quote!(SetNull),
+                    Ok(Some(OnDelete::Restrict)) => quote!(Restrict),
+                    _ => quote!(Cascade),
+                };
+                quote!(Some(butane::migrations::adb::OnDelete::#action))
+            };
+            Some(quote!(
+                butane::registry::Relationship::ForeignKey {
+                    column: #fidlit,
+                    target: <#target as butane::DataObject>::TABLE,
+                    on_delete: #on_delete,
+                }
+            ))
+        })
+        .collect();
+    let encrypted = fields(ast_struct)
+        .filter(|f| is_row_field(f) && is_encrypted(f))
+        .map(|f| field_ident_lit(f, config));
+    let view = matches!(get_view(ast_struct), Ok(Some(_)));
+    quote!(
+        const _: () = {
+            const RELATIONSHIPS: &[butane::registry::Relationship] = &[#(#relationships),*];
+            butane::registry::inventory::submit! {
+                butane::registry::ModelInfo::new::<#tyname>(
+                    #namelit,
+                    #view,
+                    RELATIONSHIPS,
+                    &[#(#encrypted),*],
+                )
+            }
+        };
+    )
+}
+
+#[cfg(not(feature = "registry"))]
+fn def_for_registry(_ast_struct: &ItemStruct, _config: &Config) -> TokenStream2 {
+    quote!()
+}
+
+/// Defines loading the relationships of the model by name for `get_with`,
+/// or nothing if it has no `ForeignKey` or `Many` fields.
+fn def_for_load_related(ast_struct: &ItemStruct, config: &Config, is_async: bool) -> TokenStream2 {
+    let arms: Vec<TokenStream2> = fields(ast_struct)
+        .filter_map(|f| {
+            let ident = f.ident.clone().expect("Fields must be named for butane");
+            if is_many_to_many(f) {
+                let many_table_lit = many_table_lit(ast_struct, f, config);
+                let load = if is_async {
+                    quote!(let _ = butane::ManyOpsAsync::load(&self.#ident, conn).await?;)
+                } else {
+                    quote!(let _ = butane::ManyOpsSync::load(&self.#ident, conn)?;)
+                };
+                return Some(quote!(#many_table_lit => { #load }));
+            }
+            if !is_foreign_key(f) {
+                return None;
+            }
+            let fidlit = field_ident_lit(f, config);
+            let load = if is_async {
+                quote!(butane::ForeignKeyOpsAsync::load(fkey, conn).await?;)
+            } else {
+                quote!(butane::ForeignKeyOpsSync::load(fkey, conn)?;)
+            };
+            Some(if is_option(f) {
+                quote!(#fidlit => if let Some(fkey) = &self.#ident { #load })
+            } else {
+                quote!(#fidlit => { let fkey = &self.#ident; #load })
+            })
+        })
+        .collect();
+    if arms.is_empty() {
+        return quote!();
+    }
+
+    let signature = if is_async {
+        quote!(
+            async fn load_related_async(
+                &self,
+                conn: &impl butane::db::ConnectionMethodsAsync,
+                name: &str,
+            ) -> butane::Result<()>
+        )
+    } else {
+        quote!(
+            fn load_related_sync(
+                &self,
+                conn: &impl butane::db::ConnectionMethods,
+                name: &str,
+            ) -> butane::Result<()>
+        )
+    };
+    quote!(
+        #signature {
+            match name {
+                #(#arms)*
+                _ => return Err(butane::Error::UnknownRelation(name.to_string())),
+            }
+            Ok(())
+        }
+    )
+}
+
+#[cfg(feature = "async")]
+fn def_for_load_related_async(ast_struct: &ItemStruct, config: &Config) -> TokenStream2 {
+    def_for_load_related(ast_struct, config, true)
+}
+
+#[cfg(not(feature = "async"))]
+fn def_for_load_related_async(_ast_struct: &ItemStruct, _config: &Config) -> TokenStream2 {
+    quote!()
+}
@@ -2,12 +2,18 @@
 use syn::{Field, ItemStruct};
 
 use super::{
-    dbobj, extract_path_from_type, fields, get_default, get_deferred_sql_type, get_many_sql_type,
-    is_auto, is_foreign_key, is_many_to_many, is_option, is_row_field, is_unique, pk_field,
+    dbobj, extract_path_from_type, fields, get_collation, get_default, get_deferred_sql_type,
+    get_doc_comment, get_many_sql_type, get_many_table_name, get_on_delete, get_partition_by,
+    get_subtype_of, get_view, is_allow_duplicates, is_audited, is_auto, is_backfill,
+    is_foreign_key, is_index, is_index_concurrently, is_many_to_many, is_no_foreign_key, is_option,
+    is_ordered_many, is_row_field, is_unique, lenient_attribute, pk_field,
+};
+use crate::migrations::adb::{
+    create_history_table, create_many_table, create_ordered_many_table, AColumn, AIndex, ARef,
+    ATable, DeferredSqlType, TypeIdentifier, TypeKey,
 };
-use crate::migrations::adb::{create_many_table, AColumn, ARef, ATable, DeferredSqlType, TypeKey};
 use crate::migrations::{MigrationMut, MigrationsMut};
-use crate::Result;
+use crate::{Result, SqlType};
 
 pub fn write_table_to_disk<M>(
     ms: &mut impl MigrationsMut<M = M>,
@@ -18,7 +24,18 @@ pub fn write_table_to_disk<M>(
     M: MigrationMut,
 {
     let current_migration = ms.current();
-    for table in create_atables(ast_struct, config) {
+    let mut tables = create_atables(ast_struct, config);
+    for table in &mut tables {
+        table.fold_identifiers(config.identifier_case);
+    }
+    if lenient_attribute(get_subtype_of(ast_struct)).is_some() {
+        // The table is shared with the other subtypes, whose columns are kept
+        let kind = ast_struct.ident.strip_raw().to_string();
+        let table = tables.remove(0);
+        let shared = current_migration.db()?.get_table(&table.name).cloned();
+        tables.insert(0, merge_subtype_table(shared, table, kind));
+    }
+    for table in tables {
         current_migration.add_modified_table(&table)?;
     }
     if let Some(name) = &config.table_name {
@@ -38,6 +55,14 @@ fn create_atables(ast_struct: &ItemStruct, config: &dbobj::Config) -> Vec<ATable
         None => ast_struct.ident.strip_raw().to_string(),
     };
     let mut table = ATable::new(name);
+    table.partition_by = lenient_attribute(get_partition_by(ast_struct));
+    table.view = lenient_attribute(get_view(ast_struct));
+    let discriminator = lenient_attribute(get_subtype_of(ast_struct))
+        .map(|(_, discriminator)| discriminator.value());
+    // The table of subtypes is described by the doc comment of their supertype
+    if table.view.is_none() && discriminator.is_none() {
+        table.comment = get_doc_comment(&ast_struct.attrs);
+    }
     let pk = pk_field(ast_struct)
         .expect("No primary key found. Expected 'id' field or field with #[pk] attribute.");
     let mut result: Vec<ATable> = Vec::new();
@@ -49,30 +74,109 @@ fn create_atables(ast_struct: &ItemStruct, config: &dbobj::Config) -> Vec<ATable
             .strip_raw()
             .to_string();
         if is_row_field(f) {
+            if let Some(view) = table.view.as_mut().filter(|view| view.materialized) {
+                if is_unique(f) || is_index(f) {
+                    view.indexes.push(AIndex {
+                        column: name.clone(),
+                        extra_columns: Vec::new(),
+                        unique: is_unique(f),
+                        concurrently: false,
+                    });
+                }
+            } else if is_index(f) {
+                table.indexes.push(AIndex {
+                    column: name.clone(),
+                    extra_columns: Vec::new(),
+                    unique: false,
+                    concurrently: is_index_concurrently(f),
+                });
+            }
             let path = extract_path_from_type(&f.ty);
             let deferred_type = get_deferred_sql_type(path);
+            // The rows of the other subtypes sharing the table have no
+            // value for the column
+            let nullable = is_nullable(f) || (discriminator.is_some() && f != &pk);
             let mut col = AColumn::new(
                 name,
                 deferred_type.clone(),
-                is_nullable(f),
+                nullable,
                 f == &pk,
                 is_auto(f),
                 is_unique(f),
                 get_default(f).expect("Malformed default attribute"),
                 None,
             );
-            if is_foreign_key(f) {
-                col.add_reference(&ARef::Deferred(deferred_type))
+            col.set_collation(lenient_attribute(get_collation(f)));
+            col.set_backfill(is_backfill(f));
+            col.set_comment(get_doc_comment(&f.attrs));
+            // Views cannot have foreign key constraints
+            if is_foreign_key(f) && !is_no_foreign_key(f) && table.view.is_none() {
+                col.add_reference(&ARef::Deferred(deferred_type));
+                col.set_on_delete(lenient_attribute(get_on_delete(f)));
             }
             table.add_column(col);
         } else if is_many_to_many(f) {
             result.push(many_table(&table.name, f, &pk));
         }
     }
+    if let Some(discriminator) = discriminator {
+        table.add_column(AColumn::new_simple(
+            discriminator.clone(),
+            DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+        ));
+        table.indexes.push(AIndex {
+            column: discriminator,
+            extra_columns: Vec::new(),
+            unique: false,
+            concurrently: false,
+        });
+    }
+    if is_audited(ast_struct) && table.view.is_none() {
+        let pk_field_path = extract_path_from_type(&pk.ty);
+        result.push(create_history_table(
+            &table.name,
+            get_deferred_sql_type(pk_field_path),
+        ));
+    }
     result.insert(0, table);
     result
 }
 
+/// Merges `table`, of the subtype `kind`, into `shared`, the table as
+/// written for the subtypes stored in it so far. The columns which only
+/// `kind` had, and no longer has, are removed.
+fn merge_subtype_table(shared: Option<ATable>, mut table: ATable, kind: String) -> ATable {
+    let names: Vec<String> = table
+        .columns
+        .iter()
+        .map(|col| col.name().to_string())
+        .collect();
+    let Some(mut shared) = shared else {
+        table.subtype_columns.insert(kind, names);
+        return table;
+    };
+    let previous = shared.subtype_columns.remove(&kind).unwrap_or_default();
+    for name in previous {
+        let kept = names.contains(&name)
+            || shared
+                .subtype_columns
+                .values()
+                .any(|columns| columns.contains(&name));
+        if !kept {
+            shared.remove_column(&name);
+            shared.remove_index(&name);
+        }
+    }
+    for col in table.columns {
+        shared.replace_column(col);
+    }
+    for index in table.indexes {
+        shared.replace_index(index);
+    }
+    shared.subtype_columns.insert(kind, names);
+    shared
+}
+
 fn many_table(main_table_name: &str, many_field: &Field, pk_field: &Field) -> ATable {
     let field_name = many_field
         .ident
@@ -91,13 +195,32 @@ fn many_table(main_table_name: &str, many_field: &Field, pk_field: &Field) -> AT
     let pk_field_path = extract_path_from_type(&pk_field.ty);
     let pk_field_type = get_deferred_sql_type(pk_field_path);
 
-    create_many_table(
+    let create_table = if is_ordered_many(many_field) {
+        create_ordered_many_table
+    } else {
+        create_many_table
+    };
+    let mut table = create_table(
         main_table_name,
         &field_name,
         many_field_type,
         &pk_field_name,
         pk_field_type,
-    )
+    );
+    if let Some(name) = lenient_attribute(get_many_table_name(many_field)) {
+        table.name = name;
+    }
+    if is_no_foreign_key(many_field) {
+        for col in &mut table.columns {
+            col.remove_reference();
+        }
+    }
+    if is_allow_duplicates(many_field) {
+        for index in &mut table.indexes {
+            index.unique = false;
+        }
+    }
+    table
 }
 
 fn is_nullable(field: &Field) -> bool {
@@ -0,0 +1,77 @@
+//! Mixins, groups of fields shared by several models.
+//!
+//! `#[mixin]` turns a struct into a `macro_rules!` macro of the same
+//! name, which adds its fields to the start of a struct passed to it.
+//! `#[model(extends = Mixin)]` passes the model to that macro, which
+//! then applies `#[model]` again, so that the model is generated from
+//! the struct with all its fields and its migrations see them as usual.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::parse::Parser;
+use syn::{Fields, ItemStruct, Path};
+
+/// Implementation of `#[butane::mixin]`.
+pub fn mixin(input: TokenStream2) -> TokenStream2 {
+    let ast_struct: ItemStruct = match syn::parse2(input) {
+        Ok(ast_struct) => ast_struct,
+        Err(err) => return err.to_compile_error(),
+    };
+    let fields = match &ast_struct.fields {
+        Fields::Named(fields) => fields.named.iter(),
+        _ => return make_compile_error!(ast_struct.ident.span()=> "A mixin must have named fields"),
+    };
+    let ident = &ast_struct.ident;
+    quote!(
+        #[allow(unused_macros)]
+        macro_rules! #ident {
+            ($(#[$attr:meta])* $vis:vis struct $name:ident { $($field:tt)* }) => {
+                $(#[$attr])*
+                $vis struct $name {
+                    #(#fields,)*
+                    $($field)*
+                }
+            };
+        }
+        #[allow(unused_imports)]
+        pub(crate) use #ident;
+    )
+}
+
+/// Handles the `extends` argument of `#[butane::model]`, returning the
+/// expansion of the model if it names any mixins, or `None` if it
+/// should be generated as usual.
+pub fn model_extends(args: TokenStream2, input: TokenStream2) -> Option<TokenStream2> {
+    let mut mixins: Vec<Path> = Vec::new();
+    let parser = syn::meta::parser(|meta| {
+        if !meta.path.is_ident("extends") {
+            return Err(meta.error("unknown model argument, expected `extends`"));
+        }
+        if meta.input.peek(syn::Token![=]) {
+            mixins.push(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            meta.parse_nested_meta(|mixin| {
+                mixins.push(mixin.path);
+                Ok(())
+            })
+        }
+    });
+    if let Err(err) = parser.parse2(args) {
+        return Some(err.to_compile_error());
+    }
+    // The last mixin is applied first, as each adds its fields to the
+    // start, and then applies the model attribute with the others
+    let (last, rest) = mixins.split_last()?;
+    let model = if rest.is_empty() {
+        quote!(#[butane::model])
+    } else {
+        quote!(#[butane::model(extends(#(#rest),*))])
+    };
+    Some(quote!(
+        #last! {
+            #model
+            #input
+        }
+    ))
+}
@@ -17,7 +17,9 @@
     MetaNameValue,
 };
 
-use crate::migrations::adb::{DeferredSqlType, TypeIdentifier, TypeKey};
+use crate::migrations::adb::{
+    AView, DeferredSqlType, IdentifierCase, OnDelete, PartitionBy, TypeIdentifier, TypeKey,
+};
 use crate::migrations::{MigrationMut, MigrationsMut};
 use crate::{SqlType, SqlVal};
 
@@ -36,6 +38,8 @@ macro_rules! make_compile_error {
 
 mod dbobj;
 mod migration;
+mod mixin;
+pub use mixin::{mixin, model_extends};
 
 // Recursion error occurs when chrono items are each feature gated.
 // https://github.com/rust-phf/rust-phf/pull/342 is a recent feature.
@@ -44,11 +48,23 @@ macro_rules! make_compile_error {
 #[cfg(not(feature = "chrono"))]
 static PATH_MAPPINGS: Map<&'static str, &'static str> = phf_map! {
     "butane::AutoPk" => "AutoPk",
+    "butane::Compressed" => "Compressed",
+    "butane::Encrypted" => "Encrypted",
     "butane::ForeignKey" => "ForeignKey",
+    "butane::GenericForeignKey" => "GenericForeignKey",
     "butane::Many" => "Many",
+    "butane::ManyThrough" => "ManyThrough",
+    "butane::OrderedMany" => "OrderedMany",
+    "butane::StringList" => "StringList",
     "butane::autopk::AutoPk" => "AutoPk",
+    "butane::blob::LargeObject" => "LargeObject",
+    "butane::compression::Compressed" => "Compressed",
+    "butane::encryption::Encrypted" => "Encrypted",
     "butane::fkey::ForeignKey" => "ForeignKey",
+    "butane::fkey::GenericForeignKey" => "GenericForeignKey",
     "butane::many::Many" => "Many",
+    "butane::many::OrderedMany" => "OrderedMany",
+    "butane::through::ManyThrough" => "ManyThrough",
     #[cfg(feature = "json")]
     "serde_json::Value" => "Value",
     #[cfg(feature = "uuid")]
@@ -59,11 +75,23 @@ macro_rules! make_compile_error {
 #[cfg(feature = "chrono")]
 static PATH_MAPPINGS: Map<&'static str, &'static str> = phf_map! {
     "butane::AutoPk" => "AutoPk",
+    "butane::Compressed" => "Compressed",
+    "butane::Encrypted" => "Encrypted",
     "butane::ForeignKey" => "ForeignKey",
+    "butane::GenericForeignKey" => "GenericForeignKey",
     "butane::Many" => "Many",
+    "butane::ManyThrough" => "ManyThrough",
+    "butane::OrderedMany" => "OrderedMany",
+    "butane::StringList" => "StringList",
     "butane::autopk::AutoPk" => "AutoPk",
+    "butane::blob::LargeObject" => "LargeObject",
+    "butane::compression::Compressed" => "Compressed",
+    "butane::encryption::Encrypted" => "Encrypted",
     "butane::fkey::ForeignKey" => "ForeignKey",
+    "butane::fkey::GenericForeignKey" => "GenericForeignKey",
     "butane::many::Many" => "Many",
+    "butane::many::OrderedMany" => "OrderedMany",
+    "butane::through::ManyThrough" => "ManyThrough",
     "chrono::DateTime" => "DateTime",
     "chrono::NaiveDate" => "NaiveDate",
     "chrono::NaiveDateTime" => "NaiveDateTime",
@@ -94,7 +122,11 @@ pub fn model_with_migrations<M>(
     // attributes but proc macro attributes can't yet (nor can they
     // create field attributes)
     let mut ast_struct: ItemStruct = syn::parse2(input).unwrap();
-    let config: dbobj::Config = config_from_attributes(&ast_struct);
+    let mut config: dbobj::Config = config_from_attributes(&ast_struct);
+    config.identifier_case = match ms.current().identifier_case() {
+        Ok(case) => case,
+        Err(err) => return identifier_case_error(err),
+    };
 
     // Filter out our helper attributes
     let attrs: Vec<Attribute> = filter_helper_attributes(&ast_struct);
@@ -105,6 +137,7 @@ pub fn model_with_migrations<M>(
 
     let impltraits = dbobj::impl_dbobject(&ast_struct, &config);
     let fieldexprs = dbobj::add_fieldexprs(&ast_struct, &config);
+    let patch = dbobj::add_patch(&ast_struct, &config);
 
     let fields: Punctuated<Field, syn::token::Comma> =
         match remove_helper_field_attributes(&mut ast_struct.fields) {
@@ -121,15 +154,44 @@ pub fn model_with_migrations<M>(
         }
         #impltraits
         #fieldexprs
+        #patch
     )
 }
 
-/// Implementation of `#[butane::dataresult(<Model>)]`.
+/// Implementation of `#[butane::dataresult(<Model>)]`, with names
+/// written as they are in the model.
+#[deprecated(
+    note = "use `dataresult_with_migrations`, which follows the identifier case of the migrations"
+)]
 pub fn dataresult(args: TokenStream2, input: TokenStream2) -> TokenStream2 {
+    dataresult_with_identifier_case(args, input, IdentifierCase::Preserve)
+}
+
+/// Implementation of `#[butane::dataresult(<Model>)]`.
+pub fn dataresult_with_migrations<M>(
+    args: TokenStream2,
+    input: TokenStream2,
+    ms: &mut impl MigrationsMut<M = M>,
+) -> TokenStream2
+where
+    M: MigrationMut,
+{
+    match ms.current().identifier_case() {
+        Ok(case) => dataresult_with_identifier_case(args, input, case),
+        Err(err) => identifier_case_error(err),
+    }
+}
+
+fn dataresult_with_identifier_case(
+    args: TokenStream2,
+    input: TokenStream2,
+    identifier_case: IdentifierCase,
+) -> TokenStream2 {
     let dbo: Ident = syn::parse2(args)
         .expect("Model type must be specified as argument to dataresult attribute");
     let mut ast_struct: ItemStruct = syn::parse2(input).unwrap();
-    let config: dbobj::Config = config_from_attributes(&ast_struct);
+    let mut config: dbobj::Config = config_from_attributes(&ast_struct);
+    config.identifier_case = identifier_case;
 
     // Filter out our helper attributes
     let attrs: Vec<Attribute> = filter_helper_attributes(&ast_struct);
@@ -154,6 +216,16 @@ pub fn dataresult(args: TokenStream2, input: TokenStream2) -> TokenStream2 {
     )
 }
 
+/// Reports that the identifier case of the migrations could not be read,
+/// such as from a malformed `info.json`.
+fn identifier_case_error(err: crate::Error) -> TokenStream2 {
+    syn::Error::new(
+        Span::call_site(),
+        format!("cannot read the identifier case of the migrations: {err}"),
+    )
+    .to_compile_error()
+}
+
 fn parse_butane_type_args(args: TokenStream2) -> std::result::Result<TypeIdentifier, TokenStream2> {
     let args: Vec<TokenTree> = args.into_iter().collect();
     if args.is_empty() {
@@ -256,7 +328,7 @@ fn filter_helper_attributes(ast_struct: &ItemStruct) -> Vec<Attribute> {
         .attrs
         .clone()
         .into_iter()
-        .filter(|a| !a.path().is_ident("table"))
+        .filter(|a| !a.path().is_ident("table") && !a.path().is_ident("butane"))
         .collect()
 }
 
@@ -277,6 +349,15 @@ fn config_from_attributes(ast_struct: &ItemStruct) -> dbobj::Config {
             }
         }
     }
+    // A subtype shares the table named after the type it is a subtype of
+    if config.table_name.is_none() {
+        if let Ok(Some((base, _))) = get_subtype_of(ast_struct) {
+            config.table_name = base
+                .segments
+                .last()
+                .map(|segment| segment.ident.strip_raw().to_string());
+        }
+    }
     config
 }
 
@@ -291,6 +372,9 @@ fn remove_helper_field_attributes(
                         && !a.path().is_ident("sqltype")
                         && !a.path().is_ident("default")
                         && !a.path().is_ident("unique")
+                        && !a.path().is_ident("readonly")
+                        && !a.path().is_ident("refresh")
+                        && !a.path().is_ident("butane")
                 });
             }
             Ok(fields)
@@ -316,6 +400,24 @@ fn is_auto(field: &Field) -> bool {
     get_type_argument(&field.ty, "AutoPk").is_some()
 }
 
+/// Whether the field is populated by the database rather than written on save.
+fn is_readonly(field: &Field) -> bool {
+    field
+        .attrs
+        .iter()
+        .any(|attr| attr.path().is_ident("readonly"))
+}
+
+/// Whether the field is read back from the database after a save,
+/// either because it is readonly or because it has `#[refresh]`.
+fn is_refreshed(field: &Field) -> bool {
+    is_readonly(field)
+        || field
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("refresh"))
+}
+
 fn is_unique(field: &Field) -> bool {
     field
         .attrs
@@ -323,6 +425,468 @@ fn is_unique(field: &Field) -> bool {
         .any(|attr| attr.path().is_ident("unique"))
 }
 
+/// Options given in the `#[butane(...)]` attributes of a field.
+#[derive(Default)]
+struct ButaneFieldAttributes {
+    many_table: Option<LitStr>,
+    on_delete: Option<LitStr>,
+    collate: Option<LitStr>,
+    auto_uuid: Option<LitStr>,
+    pk_generator: Option<LitStr>,
+    counter_cache: Option<LitStr>,
+    no_foreign_key: bool,
+    allow_duplicates: bool,
+    index: bool,
+    index_concurrently: bool,
+    backfill: bool,
+    sensitive: bool,
+    max_length: Option<syn::LitInt>,
+    min: Option<syn::Expr>,
+    max: Option<syn::Expr>,
+    regex: Option<LitStr>,
+}
+
+fn get_butane_attributes(field: &Field) -> syn::Result<ButaneFieldAttributes> {
+    let mut attributes = ButaneFieldAttributes::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("butane")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("many_table") {
+                attributes.many_table = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("on_delete") {
+                attributes.on_delete = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("collate") {
+                attributes.collate = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("auto_uuid") {
+                attributes.auto_uuid = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("pk_generator") {
+                attributes.pk_generator = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("counter_cache") {
+                attributes.counter_cache = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("no_foreign_key") {
+                attributes.no_foreign_key = true;
+            } else if meta.path.is_ident("allow_duplicates") {
+                attributes.allow_duplicates = true;
+            } else if meta.path.is_ident("index") {
+                attributes.index = true;
+                if meta.input.peek(syn::token::Paren) {
+                    meta.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("concurrently") {
+                            attributes.index_concurrently = true;
+                            Ok(())
+                        } else {
+                            Err(meta.error("unsupported index option"))
+                        }
+                    })?;
+                }
+            } else if meta.path.is_ident("backfill") {
+                attributes.backfill = true;
+            } else if meta.path.is_ident("sensitive") {
+                attributes.sensitive = true;
+            } else if meta.path.is_ident("max_length") {
+                attributes.max_length = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("min") {
+                attributes.min = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("max") {
+                attributes.max = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("regex") {
+                attributes.regex = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported butane attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attributes)
+}
+
+/// Custom name of the table of a `Many` field.
+///
+/// Example:
+/// `#[butane(many_table = "post_tags")]`
+fn get_many_table_name(field: &Field) -> syn::Result<Option<String>> {
+    Ok(get_butane_attributes(field)?
+        .many_table
+        .map(|lit| lit.value()))
+}
+
+/// Action on a `ForeignKey` field when the row it refers to is deleted.
+///
+/// Example:
+/// `#[butane(on_delete = "cascade")]`
+fn get_on_delete(field: &Field) -> syn::Result<Option<OnDelete>> {
+    match get_butane_attributes(field)?.on_delete {
+        Some(lit) => lit.value().parse().map(Some).map_err(|_| {
+            syn::Error::new(
+                lit.span(),
+                "on_delete must be \"cascade\", \"set_null\" or \"restrict\"",
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Text of the doc comments among `attrs`, such as those of a model or
+/// field, used to describe its table or column.
+///
+/// Example:
+/// `/// The author of the post.`
+fn get_doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(MetaNameValue {
+                path,
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: Lit::Str(lit), ..
+                    }),
+                ..
+            }) if path.is_ident("doc") => Some(lit.value()),
+            _ => None,
+        })
+        .flat_map(|doc| {
+            doc.split('\n')
+                .map(|line| {
+                    line.strip_prefix(' ')
+                        .unwrap_or(line)
+                        .trim_end()
+                        .to_string()
+                })
+                .collect::<Vec<String>>()
+        })
+        .collect();
+    let doc = lines.join("\n");
+    let doc = doc.trim();
+    (!doc.is_empty()).then(|| doc.to_string())
+}
+
+/// Collation used to compare and order the values of a field.
+///
+/// Example:
+/// `#[butane(collate = "NOCASE")]`
+fn get_collation(field: &Field) -> syn::Result<Option<String>> {
+    match get_butane_attributes(field)?.collate {
+        Some(lit) => {
+            let name = lit.value();
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '@'));
+            if valid {
+                Ok(Some(name))
+            } else {
+                Err(syn::Error::new(
+                    lit.span(),
+                    "collate must be a collation name such as \"NOCASE\" or \"und-x-icu\"",
+                ))
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Version of the UUID generated for a primary key which has not been set.
+///
+/// Example:
+/// `#[butane(auto_uuid = "v7")]`
+fn get_auto_uuid(field: &Field) -> syn::Result<Option<LitStr>> {
+    let version = get_butane_attributes(field)?.auto_uuid;
+    match version {
+        Some(lit) if lit.value() != "v7" => Err(syn::Error::new(
+            lit.span(),
+            "auto_uuid only supports \"v7\"",
+        )),
+        _ => Ok(version),
+    }
+}
+
+/// Path of the [`PkGenerator`](crate::pkgen::PkGenerator) of a primary
+/// key which has not been set.
+///
+/// Example:
+/// `#[butane(pk_generator = "butane::pkgen::ulid")]`
+fn get_pk_generator(field: &Field) -> syn::Result<Option<syn::Path>> {
+    match get_butane_attributes(field)?.pk_generator {
+        Some(lit) => lit.parse().map(Some).map_err(|_| {
+            syn::Error::new(lit.span(), "pk_generator must be the path of a generator")
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Model and field counting the objects referring to an object through
+/// a `ForeignKey` field.
+///
+/// Example:
+/// `#[butane(counter_cache = "Blog.post_count")]`
+fn get_counter_cache(field: &Field) -> syn::Result<Option<(String, String)>> {
+    match get_butane_attributes(field)?.counter_cache {
+        Some(lit) => match lit.value().split_once('.') {
+            Some((model, counter)) if !model.is_empty() && !counter.is_empty() => {
+                Ok(Some((model.to_string(), counter.to_string())))
+            }
+            _ => Err(syn::Error::new(
+                lit.span(),
+                "counter_cache must be \"Model.field\"",
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Options given in the `#[butane(...)]` attributes of a model struct.
+#[derive(Default)]
+struct ButaneStructAttributes {
+    partition_by: Option<LitStr>,
+    view: Option<LitStr>,
+    materialized: bool,
+    notify: Option<LitStr>,
+    patch: bool,
+    audited: bool,
+    immutable: bool,
+    subtype_of: Option<syn::Path>,
+    discriminator: Option<LitStr>,
+}
+
+fn get_butane_struct_attributes(ast_struct: &ItemStruct) -> syn::Result<ButaneStructAttributes> {
+    let mut attributes = ButaneStructAttributes::default();
+    for attr in ast_struct
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("butane"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("partition_by") {
+                attributes.partition_by = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("view") {
+                attributes.view = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("materialized") {
+                attributes.materialized = true;
+            } else if meta.path.is_ident("notify") {
+                attributes.notify = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("patch") {
+                attributes.patch = true;
+            } else if meta.path.is_ident("audited") {
+                attributes.audited = true;
+            } else if meta.path.is_ident("immutable") {
+                attributes.immutable = true;
+            } else if meta.path.is_ident("subtype_of") {
+                attributes.subtype_of = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("discriminator") {
+                attributes.discriminator = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported butane attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attributes)
+}
+
+/// Partitioning of the table of a model, given by an attribute on the struct.
+///
+/// Example:
+/// `#[butane(partition_by = "range(created_at)")]`
+fn get_partition_by(ast_struct: &ItemStruct) -> syn::Result<Option<PartitionBy>> {
+    match get_butane_struct_attributes(ast_struct)?.partition_by {
+        Some(lit) => lit.value().parse().map(Some).map_err(|_| {
+            syn::Error::new(
+                lit.span(),
+                "partition_by must be \"range(column)\", \"list(column)\" or \"hash(column)\"",
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+/// View backing a model, given by attributes on the struct. Its
+/// indexes are not included.
+///
+/// Example:
+/// `#[butane(view = "SELECT id, title FROM Post WHERE published")]`
+/// `#[butane(view = "SELECT author AS id, count(*) AS posts FROM Post GROUP BY author", materialized)]`
+fn get_view(ast_struct: &ItemStruct) -> syn::Result<Option<AView>> {
+    let attributes = get_butane_struct_attributes(ast_struct)?;
+    match attributes.view {
+        Some(lit) => Ok(Some(AView {
+            materialized: attributes.materialized,
+            ..AView::new(lit.value())
+        })),
+        None if attributes.materialized => Err(syn::Error::new(
+            ast_struct.ident.span(),
+            "materialized requires a view",
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Channel notified when an object of the model is saved or deleted.
+///
+/// Example:
+/// `#[butane(notify = "post_changes")]`
+fn get_notify(ast_struct: &ItemStruct) -> syn::Result<Option<LitStr>> {
+    Ok(get_butane_struct_attributes(ast_struct)?.notify)
+}
+
+/// The value of an attribute read outside of model generation, which
+/// reports malformed attributes itself and so treats them here as absent.
+fn lenient_attribute<T>(attribute: syn::Result<Option<T>>) -> Option<T> {
+    attribute.ok().flatten()
+}
+
+/// A flag of the `#[butane]` attributes of a model, see [`lenient_attribute`].
+fn struct_attribute_flag(
+    ast_struct: &ItemStruct,
+    flag: impl FnOnce(&ButaneStructAttributes) -> bool,
+) -> bool {
+    get_butane_struct_attributes(ast_struct).is_ok_and(|attributes| flag(&attributes))
+}
+
+/// A flag of the `#[butane]` attributes of a field, see [`lenient_attribute`].
+fn attribute_flag(field: &Field, flag: impl FnOnce(&ButaneFieldAttributes) -> bool) -> bool {
+    get_butane_attributes(field).is_ok_and(|attributes| flag(&attributes))
+}
+
+/// Whether a patch struct is generated for the model.
+///
+/// Example:
+/// `#[butane(patch)]`
+fn is_patch(ast_struct: &ItemStruct) -> bool {
+    struct_attribute_flag(ast_struct, |attributes| attributes.patch)
+}
+
+/// Whether changes to objects of the model are recorded in a history
+/// table. See [`crate::audit`].
+///
+/// Example:
+/// `#[butane(audited)]`
+fn is_audited(ast_struct: &ItemStruct) -> bool {
+    struct_attribute_flag(ast_struct, |attributes| attributes.audited)
+}
+
+/// Whether objects of the model may only be inserted, never updated.
+///
+/// Example:
+/// `#[butane(immutable)]`
+fn is_immutable(ast_struct: &ItemStruct) -> bool {
+    struct_attribute_flag(ast_struct, |attributes| attributes.immutable)
+}
+
+/// The type a model is a subtype of, whose table it shares with the
+/// other subtypes, and the column telling them apart.
+///
+/// Example:
+/// `#[butane(subtype_of = Event, discriminator = "kind")]`
+fn get_subtype_of(ast_struct: &ItemStruct) -> syn::Result<Option<(syn::Path, LitStr)>> {
+    let attributes = get_butane_struct_attributes(ast_struct)?;
+    match (attributes.subtype_of, attributes.discriminator) {
+        (Some(base), Some(discriminator)) => Ok(Some((base, discriminator))),
+        (None, None) => Ok(None),
+        _ => Err(syn::Error::new(
+            ast_struct.ident.span(),
+            "subtype_of and discriminator must be given together",
+        )),
+    }
+}
+
+/// Whether a field of a materialized view is indexed.
+///
+/// Example:
+/// `#[butane(index)]`
+fn is_index(field: &Field) -> bool {
+    attribute_flag(field, |attributes| attributes.index)
+}
+
+/// Whether the index on a field is added to and removed from an existing
+/// table without blocking writes to it.
+///
+/// Example:
+/// `#[butane(index(concurrently))]`
+fn is_index_concurrently(field: &Field) -> bool {
+    attribute_flag(field, |attributes| attributes.index_concurrently)
+}
+
+/// Whether the columns of a `ForeignKey` or `Many` field are created
+/// without foreign key constraints.
+///
+/// Example:
+/// `#[butane(no_foreign_key)]`
+fn is_no_foreign_key(field: &Field) -> bool {
+    attribute_flag(field, |attributes| attributes.no_foreign_key)
+}
+
+/// Whether a `Many` field may relate the same value more than once.
+///
+/// Example:
+/// `#[butane(allow_duplicates)]`
+fn is_allow_duplicates(field: &Field) -> bool {
+    attribute_flag(field, |attributes| attributes.allow_duplicates)
+}
+
+/// Whether adding the column of a field to an existing table adds it as
+/// nullable, backfills it with its default and then makes it NOT NULL.
+///
+/// Example:
+/// `#[butane(backfill)]`
+fn is_backfill(field: &Field) -> bool {
+    attribute_flag(field, |attributes| attributes.backfill)
+}
+
+/// Whether the values of a field are redacted from logs and errors.
+///
+/// Example:
+/// `#[butane(sensitive)]`
+fn is_sensitive(field: &Field) -> bool {
+    attribute_flag(field, |attributes| attributes.sensitive)
+}
+
+/// Whether the field is [`Encrypted`](crate::encryption::Encrypted), or
+/// an optional one.
+fn is_encrypted(field: &Field) -> bool {
+    let path =
+        get_type_argument(&field.ty, "Option").unwrap_or_else(|| extract_path_from_type(&field.ty));
+    get_path_argument(path, "Encrypted").is_some()
+}
+
+/// Checks made by `save` on the value of a field. See [`crate::validate`].
+#[derive(Default)]
+struct FieldValidations {
+    max_length: Option<syn::LitInt>,
+    min: Option<syn::Expr>,
+    max: Option<syn::Expr>,
+    regex: Option<LitStr>,
+}
+
+impl FieldValidations {
+    fn is_empty(&self) -> bool {
+        self.max_length.is_none()
+            && self.min.is_none()
+            && self.max.is_none()
+            && self.regex.is_none()
+    }
+}
+
+/// Validation of the value of a field before it is saved.
+///
+/// Example:
+/// `#[butane(max_length = 255, regex = "^[a-z]+$")]`
+/// `#[butane(min = 0, max = 100)]`
+fn get_validations(field: &Field) -> syn::Result<FieldValidations> {
+    let attributes = get_butane_attributes(field)?;
+    if let Some(max_length) = &attributes.max_length {
+        max_length.base10_parse::<usize>()?;
+    }
+    if let Some(regex) = &attributes.regex {
+        Regex::new(&regex.value()).map_err(|e| syn::Error::new(regex.span(), e))?;
+    }
+    Ok(FieldValidations {
+        max_length: attributes.max_length,
+        min: attributes.min,
+        max: attributes.max,
+        regex: attributes.regex,
+    })
+}
+
 fn fields(ast_struct: &ItemStruct) -> impl Iterator<Item = &Field> {
     ast_struct.fields.iter()
 }
@@ -355,17 +919,50 @@ fn get_foreign_key_sql_type(field: &Field) -> Option<DeferredSqlType> {
 
 fn get_many_sql_type(field: &Field) -> Option<DeferredSqlType> {
     let path = extract_path_from_type(&field.ty);
-    get_foreign_sql_type(path, "Many")
+    get_foreign_sql_type(path, "Many").or_else(|| get_foreign_sql_type(path, "OrderedMany"))
+}
+
+/// Gets the type argument of a `ForeignKey` or `Option<ForeignKey>` field.
+fn get_foreign_key_type_argument(field: &Field) -> Option<&syn::Path> {
+    let path =
+        get_type_argument(&field.ty, "Option").unwrap_or_else(|| extract_path_from_type(&field.ty));
+    get_path_argument(path, "ForeignKey")
+}
+
+/// Gets the type argument of a `Many` or `OrderedMany` field.
+fn get_many_type_argument(field: &Field) -> Option<&syn::Path> {
+    get_type_argument(&field.ty, "Many").or_else(|| get_type_argument(&field.ty, "OrderedMany"))
 }
 
 fn get_autopk_sql_type(path: &syn::Path) -> Option<DeferredSqlType> {
     get_path_argument(path, "AutoPk").map(get_deferred_sql_type)
 }
 
+/// Encrypted and compressed fields are stored as blobs, whatever the
+/// type of their value.
+fn get_opaque_sql_type(path: &syn::Path) -> Option<DeferredSqlType> {
+    get_path_argument(path, "Encrypted")
+        .or_else(|| get_path_argument(path, "Compressed"))
+        .and_then(|_| some_known(SqlType::Blob))
+}
+
 fn is_many_to_many(field: &Field) -> bool {
     get_many_sql_type(field).is_some()
 }
 
+/// Whether the field is a [`ManyThrough`](crate::through::ManyThrough),
+/// which has no table of its own.
+fn is_many_through(field: &Field) -> bool {
+    match &field.ty {
+        syn::Type::Path(path) => PATH_RESOLVER.resolve(&path.path) == Some("ManyThrough"),
+        _ => false,
+    }
+}
+
+fn is_ordered_many(field: &Field) -> bool {
+    get_type_argument(&field.ty, "OrderedMany").is_some()
+}
+
 fn is_foreign_key(field: &Field) -> bool {
     get_foreign_key_sql_type(field).is_some()
 }
@@ -377,7 +974,7 @@ fn is_option(field: &Field) -> bool {
 /// Check for special fields which won't correspond to rows and don't
 /// implement FieldType
 fn is_row_field(f: &Field) -> bool {
-    !is_many_to_many(f)
+    !is_many_to_many(f) && !is_many_through(f)
 }
 
 /// Gets the type argument of a type.
@@ -438,6 +1035,7 @@ pub fn get_deferred_sql_type(path: &syn::Path) -> DeferredSqlType {
         .or_else(|| get_option_sql_type(path))
         .or_else(|| get_foreign_sql_type(path, "ForeignKey"))
         .or_else(|| get_autopk_sql_type(path))
+        .or_else(|| get_opaque_sql_type(path))
         .unwrap_or_else(|| {
             DeferredSqlType::Deferred(TypeKey::CustomType(
                 path.strip_raw()
@@ -507,6 +1105,13 @@ pub fn get_primitive_sql_type(path: &syn::Path) -> Option<DeferredSqlType> {
         || *path == parse_quote!(::std::vec::Vec<u8>)
     {
         return some_known(SqlType::Blob);
+    } else if PATH_RESOLVER.resolve(path) == Some("StringList") {
+        return some_known(SqlType::TextList);
+    } else if PATH_RESOLVER.resolve(path) == Some("GenericForeignKey") {
+        return some_known(SqlType::Text);
+    } else if PATH_RESOLVER.resolve(path) == Some("LargeObject") {
+        // The oid of a Postgres large object
+        return some_known(SqlType::BigInt);
     }
 
     #[cfg(feature = "json")]
@@ -537,16 +1142,15 @@ pub fn get_primitive_sql_type(path: &syn::Path) -> Option<DeferredSqlType> {
         if let Some(syn::PathSegment { ident, arguments }) = last_path_segment(&ty) {
             match ident.to_string().as_str() {
                 "NaiveDateTime" => return some_known(SqlType::Timestamp),
-                "DateTime" => {
-                    // Only if the parameter is UTC, as we don't support attached
-                    // time zones
+                // Only if the parameter is UTC, as we don't support attached
+                // time zones
+                "DateTime"
                     if template_type(arguments)
                         .map(|ident| ident.to_string())
                         .unwrap_or_default()
-                        == "Utc"
-                    {
-                        return some_known(SqlType::Timestamp);
-                    }
+                        == "Utc" =>
+                {
+                    return some_known(SqlType::Timestamp);
                 }
                 "NaiveDate" => return some_known(SqlType::Date),
                 _ => {}
@@ -629,6 +1233,7 @@ fn sqltype_from_name(name: &Ident) -> Option<TypeIdentifier> {
         "Real" => return some_id(SqlType::Real),
         "Text" => return some_id(SqlType::Text),
         "Blob" => return some_id(SqlType::Blob),
+        "TextList" => return some_id(SqlType::TextList),
         #[cfg(feature = "json")]
         "Json" => return some_id(SqlType::Json),
         #[cfg(feature = "datetime")]
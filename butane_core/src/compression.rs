@@ -0,0 +1,208 @@
+//! Transparent compression of large text and blob fields, such as logs
+//! or bodies of messages which would otherwise dominate the size of the
+//! database.
+//!
+//! A field of type [`Compressed<String>`] or [`Compressed<Vec<u8>>`]
+//! holds its value uncompressed in memory, and is stored as a blob
+//! compressed with zstd. The first byte of the stored value records the
+//! [`Codec`] it was compressed with, so that values written with
+//! different codecs can be read alongside each other should the codec
+//! change. Values too small to benefit from compression, or which do not
+//! shrink when compressed, are stored as they are.
+//!
+//! As the stored value is opaque to the database, compressed fields
+//! cannot usefully be filtered on or sorted.
+
+use std::fmt;
+use std::ops::Deref;
+
+use crate::{Error, FieldType, FromSql, Result, SqlType, SqlVal, SqlValRef, ToSql};
+
+/// Values of fewer bytes than this are not worth compressing.
+const MIN_COMPRESSED_LEN: usize = 64;
+
+/// The codec a [`Compressed`] value is stored with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Codec {
+    /// Stored as it is.
+    None,
+    /// Compressed with zstd.
+    Zstd,
+}
+
+impl Codec {
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Zstd),
+            _ => Err(Error::Compression(format!("unknown codec {byte}"))),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => 1,
+        }
+    }
+}
+
+/// A type whose values may be [`Compressed`].
+pub trait Compressible: Sized {
+    /// The bytes to compress.
+    fn as_bytes(&self) -> &[u8];
+
+    /// Recreates a value from its decompressed bytes.
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self>;
+}
+
+impl Compressible for String {
+    fn as_bytes(&self) -> &[u8] {
+        self.as_str().as_bytes()
+    }
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        String::from_utf8(bytes).map_err(|e| Error::Compression(e.to_string()))
+    }
+}
+
+impl Compressible for Vec<u8> {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Ok(bytes)
+    }
+}
+
+/// A field value which is stored compressed, see the [module
+/// documentation](self). Dereferences to the uncompressed value.
+#[derive(Clone)]
+pub struct Compressed<T> {
+    value: T,
+    // The value as stored: the codec byte, then the value encoded with it.
+    stored: Vec<u8>,
+}
+
+impl<T: Compressible> Compressed<T> {
+    /// Compresses `value`.
+    pub fn new(value: T) -> Self {
+        let stored = compress(value.as_bytes());
+        Compressed { value, stored }
+    }
+
+    /// Replaces the value, compressing it.
+    pub fn set(&mut self, value: T) {
+        *self = Self::new(value);
+    }
+
+    /// The codec the value is stored with.
+    pub fn codec(&self) -> Codec {
+        // The stored value was either created by `compress` or validated
+        // by `decompress`
+        Codec::from_byte(self.stored[0]).expect("invalid compressed value")
+    }
+
+    /// The number of bytes the value takes up in the database.
+    pub fn stored_len(&self) -> usize {
+        self.stored.len()
+    }
+
+    /// Returns the uncompressed value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() >= MIN_COMPRESSED_LEN {
+        if let Ok(compressed) = zstd::bulk::compress(bytes, zstd::DEFAULT_COMPRESSION_LEVEL) {
+            if compressed.len() < bytes.len() {
+                let mut stored = Vec::with_capacity(1 + compressed.len());
+                stored.push(Codec::Zstd.to_byte());
+                stored.extend_from_slice(&compressed);
+                return stored;
+            }
+        }
+    }
+    let mut stored = Vec::with_capacity(1 + bytes.len());
+    stored.push(Codec::None.to_byte());
+    stored.extend_from_slice(bytes);
+    stored
+}
+
+fn decompress<T: Compressible>(stored: &[u8]) -> Result<T> {
+    let (&codec, encoded) = stored
+        .split_first()
+        .ok_or_else(|| Error::Compression("empty compressed value".to_string()))?;
+    let bytes = match Codec::from_byte(codec)? {
+        Codec::None => encoded.to_vec(),
+        Codec::Zstd => {
+            zstd::stream::decode_all(encoded).map_err(|e| Error::Compression(e.to_string()))?
+        }
+    };
+    T::from_bytes(bytes)
+}
+
+impl<T: Compressible> From<T> for Compressed<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> Deref for Compressed<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: PartialEq> PartialEq for Compressed<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Compressed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Compressed").field(&self.value).finish()
+    }
+}
+
+impl<T: Compressible> FromSql for Compressed<T> {
+    fn from_sql_ref(val: SqlValRef<'_>) -> Result<Self> {
+        match val {
+            SqlValRef::Blob(stored) => Ok(Compressed {
+                value: decompress(stored)?,
+                stored: stored.to_vec(),
+            }),
+            _ => Err(Error::CannotConvertSqlVal(SqlType::Blob, val.into())),
+        }
+    }
+    fn from_sql(val: SqlVal) -> Result<Self> {
+        match val {
+            SqlVal::Blob(stored) => Ok(Compressed {
+                value: decompress(&stored)?,
+                stored,
+            }),
+            _ => Err(Error::CannotConvertSqlVal(SqlType::Blob, val)),
+        }
+    }
+}
+
+impl<T> ToSql for Compressed<T> {
+    fn to_sql(&self) -> SqlVal {
+        SqlVal::Blob(self.stored.clone())
+    }
+    fn to_sql_ref(&self) -> SqlValRef<'_> {
+        SqlValRef::Blob(&self.stored)
+    }
+    fn into_sql(self) -> SqlVal {
+        SqlVal::Blob(self.stored)
+    }
+}
+
+impl<T: Compressible> FieldType for Compressed<T> {
+    const SQLTYPE: SqlType = SqlType::Blob;
+    type RefType = Self;
+}
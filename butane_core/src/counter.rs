@@ -0,0 +1,366 @@
+//! Counts of the objects referring to an object, kept in a column of it
+//! so that listing pages need no `COUNT` queries.
+//!
+//! A [`ForeignKey`](crate::ForeignKey) field declared with
+//! `#[butane(counter_cache = "Blog.post_count")]` keeps the integer field
+//! `post_count` of the `Blog` it refers to equal to the number of
+//! objects referring to it. The counter is changed by a single `UPDATE`
+//! adding to it, so concurrent changes are not lost, when an object is
+//! saved or deleted, when objects are deleted by a query, by
+//! `delete_many` or by `delete_cascade`, and when an
+//! [`Update`](crate::query::Update) sets the field. The change and the
+//! counters it adjusts are made together, within a savepoint or a
+//! transaction of their own, and only the objects which were actually
+//! deleted are no longer counted.
+//!
+//! ```ignore
+//! #[model]
+//! struct Blog {
+//!     id: i64,
+//!     #[default = 0]
+//!     post_count: i64,
+//! }
+//!
+//! #[model]
+//! struct Post {
+//!     id: AutoPk<i64>,
+//!     #[butane(counter_cache = "Blog.post_count")]
+//!     blog: ForeignKey<Blog>,
+//! }
+//! ```
+//!
+//! The counter is only changed in the database, so an object loaded
+//! before the objects referring to it changed is not updated. Changes
+//! made other than through Butane, such as by `ON DELETE CASCADE`, are
+//! not counted.
+#![deny(missing_docs)]
+
+use fallible_iterator::FallibleIterator;
+
+#[cfg(feature = "async")]
+use crate::db::atomic::AtomicAsync;
+use crate::db::atomic::{self, AtomicSync};
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{BackendRows, Column, ConnectionMethods};
+use crate::query::{ArithOp, BoolExpr, Expr};
+use crate::{DataObject, Error, FieldType, Result, SqlVal};
+
+/// A column of the model a `ForeignKey` field refers to, counting the
+/// objects referring to each of its objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CounterCache {
+    /// The column of the foreign key.
+    pub column: &'static str,
+    /// The table of the model referred to.
+    pub table: &'static str,
+    /// The primary key column of the model referred to.
+    pub pkcol: &'static str,
+    /// The column counting the objects referring to it.
+    pub counter: &'static str,
+}
+
+/// Name of the savepoint a change and the counters it adjusts are made
+/// within.
+const SAVEPOINT: &str = "butane_counter_cache";
+
+/// The foreign key columns of `caches`, among the `columns` of `table`.
+fn counted_columns(
+    table: &str,
+    columns: &[Column],
+    caches: &[CounterCache],
+) -> Result<Vec<Column>> {
+    caches
+        .iter()
+        .map(|cache| {
+            columns
+                .iter()
+                .find(|col| col.name() == cache.column)
+                .cloned()
+                .ok_or_else(|| Error::Internal(format!("{table} has no column {}", cache.column)))
+        })
+        .collect()
+}
+
+/// The objects counted by counter caches in a row of the values of
+/// their `counted_columns`, as by `counted`.
+fn counted_in_row(values: Vec<SqlVal>) -> impl Iterator<Item = (usize, SqlVal)> {
+    values
+        .into_iter()
+        .enumerate()
+        .filter(|(_, value)| *value != SqlVal::Null)
+}
+
+/// The objects counted by the counter caches of `obj`, each as the index
+/// of its counter cache and its primary key.
+pub(crate) fn counted_by<T: DataObject>(obj: &T) -> Vec<(usize, SqlVal)> {
+    if T::COUNTER_CACHES.is_empty() {
+        return Vec::new();
+    }
+    let values = obj.non_auto_values(true);
+    T::COUNTER_CACHES
+        .iter()
+        .enumerate()
+        .filter_map(|(i, cache)| {
+            let position = T::NON_AUTO_COLUMNS
+                .iter()
+                .position(|col| col.name() == cache.column)?;
+            let value = SqlVal::from(values[position].clone());
+            (value != SqlVal::Null).then_some((i, value))
+        })
+        .collect()
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// The objects counted by the counter caches of the objects of `T`
+/// matching `filter`, each as the index of its counter cache and its
+/// primary key.
+pub(crate) async fn counted<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    filter: BoolExpr,
+) -> Result<Vec<(usize, SqlVal)>> {
+    if T::COUNTER_CACHES.is_empty() {
+        return Ok(Vec::new());
+    }
+    let columns = counted_columns(T::TABLE, T::COLUMNS, T::COUNTER_CACHES)?;
+    let rows: Vec<Vec<SqlVal>> = conn
+        .query(T::TABLE, &columns, Some(filter), None, None, None)
+        .await?
+        .mapped(|row| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(i, col)| Ok(SqlVal::from(row.get(i, col.ty().clone())?)))
+                .collect()
+        })
+        .collect()?;
+    Ok(rows.into_iter().flat_map(counted_in_row).collect())
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// The primary keys of the objects of `T` matching `filter`.
+pub(crate) async fn pks_where<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    filter: BoolExpr,
+) -> Result<Vec<SqlVal>> {
+    let pkcol = Column::new(T::PKCOL, <T::PKType as FieldType>::SQLTYPE);
+    conn.query(
+        T::TABLE,
+        std::slice::from_ref(&pkcol),
+        Some(filter),
+        None,
+        None,
+        None,
+    )
+    .await?
+    .mapped(|row| Ok(SqlVal::from(row.get(0, pkcol.ty().clone())?)))
+    .collect()
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        counted(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Like `counted`, for the objects of `T` with the primary keys `pks`,
+/// with as few statements as the number of parameters allows.
+pub(crate) async fn counted_by_pks<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pks: &[SqlVal],
+) -> Result<Vec<(usize, SqlVal)>> {
+    let mut found = Vec::new();
+    if T::COUNTER_CACHES.is_empty() {
+        return Ok(found);
+    }
+    for chunk in pks.chunks(conn.max_statement_params()) {
+        let filter = BoolExpr::In(T::PKCOL, chunk.to_vec());
+        found.append(&mut counted::<T>(conn, filter).await?);
+    }
+    Ok(found)
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        adjust_caches(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Updates the counters of the objects no longer counted by the counter
+/// caches of `T`, in `removed`, and of those now counted, in `added`, as
+/// returned by `counted`. The counter of an object in both is unchanged.
+pub(crate) async fn adjust<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    removed: Vec<(usize, SqlVal)>,
+    added: Vec<(usize, SqlVal)>,
+) -> Result<()> {
+    adjust_caches(conn, T::COUNTER_CACHES, removed, added).await
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Like `adjust`, for the counter caches `caches`.
+async fn adjust_caches(
+    conn: &impl ConnectionMethods,
+    caches: &[CounterCache],
+    removed: Vec<(usize, SqlVal)>,
+    added: Vec<(usize, SqlVal)>,
+) -> Result<()> {
+    let mut changes: Vec<(usize, SqlVal, i64)> = Vec::new();
+    for (counted, by) in [(removed, -1), (added, 1)] {
+        for (cache, pk) in counted {
+            match changes.iter_mut().find(|(c, p, _)| *c == cache && *p == pk) {
+                Some(change) => change.2 += by,
+                None => changes.push((cache, pk, by)),
+            }
+        }
+    }
+    for (cache, pk, by) in changes {
+        if by == 0 {
+            continue;
+        }
+        let cache = &caches[cache];
+        let count = Expr::Arith(
+            Box::new(Expr::Column(cache.counter)),
+            ArithOp::Add,
+            Box::new(Expr::Val(SqlVal::BigInt(by))),
+        );
+        conn.update_where(
+            cache.table,
+            vec![(cache.counter, count)],
+            BoolExpr::Eq(cache.pkcol, Expr::Val(pk)),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        Atomic,
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        begin(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Begins making a change to the objects of `T` together with the
+/// adjustment of the counters it affects, if `T` has counter caches, to
+/// be ended by `end_change`.
+pub(crate) async fn begin_change<T: DataObject>(
+    conn: &impl ConnectionMethods,
+) -> Result<Option<Atomic<'_>>> {
+    if T::COUNTER_CACHES.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(atomic::begin(conn, SAVEPOINT).await?))
+}
+
+#[maybe_async_cfg::maybe(idents(Atomic, end(snake)), sync(), async(feature = "async"))]
+/// Ends the change begun by `begin_change`, which is kept if `result` is `Ok`
+/// and undone otherwise.
+pub(crate) async fn end_change<R>(atomic: Option<Atomic<'_>>, result: Result<R>) -> Result<R> {
+    match atomic {
+        Some(atomic) => atomic::end(atomic, result).await,
+        None => result,
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        delete_rows_counted(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Deletes the objects of `T` matching `filter` from `table`, returning
+/// the number deleted, and decrements the counters of the objects they
+/// referred to.
+pub(crate) async fn delete_where_counted<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    table: &str,
+    filter: BoolExpr,
+) -> Result<usize> {
+    delete_rows_counted(conn, table, T::COLUMNS, T::COUNTER_CACHES, filter).await
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        delete_rows_counted(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Like `delete_where_counted`, for the objects of `T` with the primary keys
+/// `pks`, with as few statements as the number of parameters allows.
+pub(crate) async fn delete_many_counted<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    pks: &[SqlVal],
+) -> Result<usize> {
+    if T::COUNTER_CACHES.is_empty() {
+        return conn.delete_many(T::TABLE, T::PKCOL, pks).await;
+    }
+    let mut deleted = 0;
+    for chunk in pks.chunks(conn.max_statement_params()) {
+        let filter = BoolExpr::In(T::PKCOL, chunk.to_vec());
+        deleted +=
+            delete_rows_counted(conn, T::TABLE, T::COLUMNS, T::COUNTER_CACHES, filter).await?;
+    }
+    Ok(deleted)
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync, async = "ConnectionMethodsAsync"),
+        adjust_caches(snake),
+        begin(snake),
+        end(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Deletes the rows of `table`, whose columns are `columns`, matching
+/// `filter`, returning the number deleted, and decrements the counters
+/// of `caches` for the rows actually deleted, so that rows deleted
+/// concurrently by another connection are not uncounted twice.
+pub(crate) async fn delete_rows_counted(
+    conn: &impl ConnectionMethods,
+    table: &str,
+    columns: &[Column],
+    caches: &[CounterCache],
+    filter: BoolExpr,
+) -> Result<usize> {
+    if caches.is_empty() {
+        return conn.delete_where(table, filter).await;
+    }
+    let columns = counted_columns(table, columns, caches)?;
+    let atomic = atomic::begin(conn, SAVEPOINT).await?;
+    let result = match conn.delete_returning(table, filter, &columns).await {
+        Ok(rows) => {
+            let deleted = rows.len();
+            let removed = rows.into_iter().flat_map(counted_in_row).collect();
+            adjust_caches(conn, caches, removed, Vec::new())
+                .await
+                .map(|()| deleted)
+        }
+        Err(e) => Err(e),
+    };
+    atomic::end(atomic, result).await
+}
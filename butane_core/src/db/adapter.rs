@@ -5,7 +5,8 @@
 //! between threads.
 
 use super::*;
-use crate::query::Order;
+use crate::deadline;
+use crate::query::{Expr, Order};
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
@@ -44,58 +45,33 @@ fn new() -> Self {
 
     /// Invokes a blocking function `func` as if it were async. This
     /// is implemented by running it on the special thread created when the `AsyncAdapterEnv` was created.
-    async fn invoke<'c, 's, 'result, F, T, U>(
-        &'s self,
-        context: &SyncSendPtrMut<T>,
-        func: F,
-    ) -> Result<U>
+    /// As `func` owns everything it uses, the future may be cancelled,
+    /// such as at a deadline, without waiting for the worker thread,
+    /// which then discards the result.
+    async fn invoke<F, T, U>(&self, context: &SyncSendPtrMut<T>, func: F) -> Result<U>
     where
-        F: FnOnce(&'c T) -> Result<U> + Send,
-        F: 'result,
-        U: Send + 'result,
-        T: ?Sized + 'c,
-        's: 'result,
-        'c: 'result,
+        F: FnOnce(&T) -> Result<U> + Send + 'static,
+        U: Send + 'static,
+        T: ?Sized,
     {
-        // func itself must be `Send`, but we do not require &T to be
-        // Send (and thus don't require T to be Sync).  We do this by
-        // basically unsafely sending our raw context pointer over to
-        // the worker thread anyway.  The key observation on why we
-        // believe this to be sound is that we actually created the
-        // context over on the worker thread in the first place (see
-        // [AsyncAdapter::new]) and we do not allow direct access to
-        // it. So despite fact that we pass the context pointer back
-        // and forth, it's essentially owned by the worker thread -- all operations
-        // with context occur on that worker thread.
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let func_taking_ptr = |ctx: SyncSendPtrMut<T>| func(unsafe { ctx.inner.as_ref() }.unwrap());
-        unsafe {
-            let wrapped_func = move || _ = tx.send(func_taking_ptr(context.clone_unsafe()));
-            self.invoke_internal_unsafe(wrapped_func)?;
-        }
-        rx.await?
+        self.invoke_mut(context, |context| func(context)).await
     }
 
-    async fn invoke_mut<'c, 's, 'result, F, T, U>(
-        &'s self,
-        context: &SyncSendPtrMut<T>,
-        func: F,
-    ) -> Result<U>
+    async fn invoke_mut<F, T, U>(&self, context: &SyncSendPtrMut<T>, func: F) -> Result<U>
     where
-        F: FnOnce(&'c mut T) -> Result<U> + Send,
-        F: 'result,
-        U: Send + 'result,
-        T: ?Sized + 'c,
-        's: 'result,
-        'c: 'result,
+        F: FnOnce(&mut T) -> Result<U> + Send + 'static,
+        U: Send + 'static,
+        T: ?Sized,
     {
+        // The deadline lives in the task context, which the worker
+        // thread does not have access to, so we hand it over explicitly.
+        let deadline = deadline::current();
+        deadline::remaining()?;
         let (tx, rx) = tokio::sync::oneshot::channel();
-        let func_taking_ptr = |ctx: SyncSendPtrMut<T>| func(unsafe { ctx.inner.as_mut().unwrap() });
-        unsafe {
-            let wrapped_func = move || _ = tx.send(func_taking_ptr(context.clone_unsafe()));
-            self.invoke_internal_unsafe(wrapped_func)?;
-        }
-        rx.await?
+        self.invoke_detached_mut(context, move |context| {
+            _ = tx.send(deadline::scope_sync(deadline, || func(context)));
+        })?;
+        rx.await?.map_err(deadline_error)
     }
 
     fn invoke_blocking<'c, 's, 'result, F, T, U>(&'s self, context: *const T, func: F) -> Result<U>
@@ -117,6 +93,43 @@ fn invoke_blocking<'c, 's, 'result, F, T, U>(&'s self, context: *const T, func:
         rx.recv()?
     }
 
+    /// Invokes a blocking function `func` without waiting for it, for
+    /// where waiting is not possible, such as on drop. The context
+    /// outlives `func`, as it is itself dropped by a function sent to
+    /// the worker thread after any sent before.
+    fn invoke_detached<F, T>(&self, context: &SyncSendPtrMut<T>, func: F) -> Result<()>
+    where
+        F: FnOnce(&T) + Send + 'static,
+        T: ?Sized,
+    {
+        self.invoke_detached_mut(context, |context| func(context))
+    }
+
+    fn invoke_detached_mut<F, T>(&self, context: &SyncSendPtrMut<T>, func: F) -> Result<()>
+    where
+        F: FnOnce(&mut T) + Send + 'static,
+        T: ?Sized,
+    {
+        // func itself must be `Send`, but we do not require &T to be
+        // Send (and thus don't require T to be Sync).  We do this by
+        // basically unsafely sending our raw context pointer over to
+        // the worker thread anyway.  The key observation on why we
+        // believe this to be sound is that we actually created the
+        // context over on the worker thread in the first place (see
+        // [AsyncAdapter::new]) and we do not allow direct access to
+        // it. So despite fact that we pass the context pointer back
+        // and forth, it's essentially owned by the worker thread -- all operations
+        // with context occur on that worker thread.
+        unsafe {
+            let context = context.clone_unsafe();
+            self.invoke_internal_unsafe(move || {
+                // Moves the whole pointer wrapper, which is Send
+                let context = context;
+                func(context.inner.as_mut().unwrap())
+            })
+        }
+    }
+
     unsafe fn invoke_internal_unsafe<'s, 'result>(
         &'s self,
         // wrapped_func is a complete encapsulation of the function we
@@ -128,9 +141,10 @@ unsafe fn invoke_internal_unsafe<'s, 'result>(
         // issue here because Rust itself has no way of knowing how
         // long our sync worker thread is going to use it for.  But
         // *we* know that our worker thread will immediately execute
-        // the function and the caller to this method will wait to
-        // hear from the worker thread before proceeding (and thus
-        // before letting the lifetime lapse)
+        // the function and the caller to this method will either wait
+        // to hear from the worker thread before proceeding (and thus
+        // before letting the lifetime lapse), or send a function which
+        // owns everything it uses but the context
         // https://stackoverflow.com/questions/52424449/
         let boxed_func: Box<dyn FnOnce() + Send + 'result> = Box::new(wrapped_func);
         let static_func: Box<dyn FnOnce() + Send + 'static> =
@@ -140,6 +154,16 @@ unsafe fn invoke_internal_unsafe<'s, 'result>(
     }
 }
 
+/// Reports a failure of an operation which was still running at the
+/// deadline as the deadline being exceeded, as the backend may have
+/// interrupted it for that reason.
+fn deadline_error(e: Error) -> Error {
+    match deadline::remaining() {
+        Err(deadline_exceeded) => deadline_exceeded,
+        Ok(_) => e,
+    }
+}
+
 impl Drop for AsyncAdapterEnv {
     fn drop(&mut self) {
         let r = self.sender.send(Command::Shutdown);
@@ -214,24 +238,18 @@ fn create_with_same_env<U: ?Sized>(&self, context_ptr: SyncSendPtrMut<U>) -> Asy
     }
 
     /// Invokes the provided function with a sync method.
-    async fn invoke<'c, 's, 'result, F, U>(&'s self, func: F) -> Result<U>
+    async fn invoke<F, U>(&self, func: F) -> Result<U>
     where
-        F: FnOnce(&'c T) -> Result<U> + Send,
-        F: 'result,
-        U: Send + 'result,
-        's: 'result,
-        'c: 'result,
-        's: 'c,
+        F: FnOnce(&T) -> Result<U> + Send + 'static,
+        U: Send + 'static,
     {
         self.env.invoke(&self.context, func).await
     }
 
-    async fn invoke_mut<'c, 'result, F, U>(&'c self, func: F) -> Result<U>
+    async fn invoke_mut<F, U>(&self, func: F) -> Result<U>
     where
-        F: FnOnce(&'c mut T) -> Result<U> + Send,
-        F: 'result,
-        U: Send + 'result,
-        'c: 'result,
+        F: FnOnce(&mut T) -> Result<U> + Send + 'static,
+        U: Send + 'static,
     {
         self.env.invoke_mut(&self.context, func).await
     }
@@ -245,6 +263,13 @@ fn invoke_blocking<'c, 'result, F, U>(&'c self, func: F) -> Result<U>
     {
         self.env.invoke_blocking(self.context.inner, func)
     }
+
+    fn invoke_detached<F>(&self, func: F) -> Result<()>
+    where
+        F: FnOnce(&T) + Send + 'static,
+    {
+        self.env.invoke_detached(&self.context, func)
+    }
 }
 
 impl<T> AsyncAdapter<T> {
@@ -285,13 +310,55 @@ fn drop(&mut self) {
     }
 }
 
+// The arguments of each method are copied into the function sent to the
+// worker thread, so that the function owns them, as it may outlive the
+// method's future. See AsyncAdapterEnv::invoke.
 #[async_trait]
 impl<T> ConnectionMethodsAsync for AsyncAdapter<T>
 where
     T: ConnectionMethods + ?Sized,
 {
     async fn execute(&self, sql: &str) -> Result<()> {
-        self.invoke(|conn| conn.execute(sql)).await
+        let sql = sql.to_string();
+        self.invoke(move |conn| conn.execute(&sql)).await
+    }
+    async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        let sql = sql.to_string();
+        let params = to_owned_values(params);
+        self.invoke(move |conn| conn.execute_params(&sql, &as_refs(&params)))
+            .await
+    }
+    fn max_statement_params(&self) -> usize {
+        self.invoke_blocking(|conn| Ok(conn.max_statement_params()))
+            .unwrap_or(MAX_STATEMENT_PARAMS)
+    }
+    fn in_transaction(&self) -> bool {
+        self.invoke_blocking(|conn| Ok(conn.in_transaction()))
+            .unwrap_or(false)
+    }
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        // The worker undoes the statements before running any later ones
+        #[allow(unused_variables)] // used only when logging is enabled
+        if let Err(e) = self.invoke_detached(move |conn| conn.abandon_atomic(savepoint)) {
+            crate::warn!("failed to roll back abandoned statements: {}", e);
+        }
+    }
+    async fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        let sql = sql.to_string();
+        let params = to_owned_values(params);
+        let columns = columns.to_vec();
+        let rows = self
+            .invoke(move |conn| {
+                let rows = conn.query_params(&sql, &as_refs(&params), &columns)?;
+                super::connmethods::BufferedRows::read(rows, &columns)
+            })
+            .await?;
+        Ok(Box::new(rows))
     }
 
     async fn query<'c>(
@@ -303,15 +370,38 @@ async fn query<'c>(
         offset: Option<i32>,
         sort: Option<&[Order]>,
     ) -> Result<RawQueryResult<'c>> {
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let sort = sort.map(<[Order]>::to_vec);
         let rows = self
-            .invoke(|conn| {
-                let rows: Box<dyn BackendRows> =
-                    conn.query(table, columns, expr, limit, offset, sort)?;
-                let vec_rows = super::connmethods::vec_from_backend_rows(rows, columns)?;
-                Ok(Box::new(vec_rows))
+            .invoke(move |conn| {
+                let rows = conn.query(&table, &columns, expr, limit, offset, sort.as_deref())?;
+                super::connmethods::BufferedRows::read(rows, &columns)
+            })
+            .await?;
+        Ok(Box::new(rows))
+    }
+    async fn query_counted<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<(RawQueryResult<'c>, u64)> {
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let sort = sort.map(<[Order]>::to_vec);
+        let (rows, total) = self
+            .invoke(move |conn| {
+                let (rows, total) =
+                    conn.query_counted(&table, &columns, expr, limit, offset, sort.as_deref())?;
+                let buffered = super::connmethods::BufferedRows::read(rows, &columns)?;
+                Ok((buffered, total))
             })
             .await?;
-        Ok(rows)
+        Ok((Box::new(rows), total))
     }
     async fn insert_returning_pk(
         &self,
@@ -320,8 +410,50 @@ async fn insert_returning_pk(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<SqlVal> {
-        self.invoke(|conn| conn.insert_returning_pk(table, columns, pkcol, values))
-            .await
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let pkcol = pkcol.clone();
+        let values = to_owned_values(values);
+        self.invoke(move |conn| {
+            conn.insert_returning_pk(&table, &columns, &pkcol, &as_refs(&values))
+        })
+        .await
+    }
+    async fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let pkcol = pkcol.clone();
+        let returning = returning.to_vec();
+        let values = to_owned_values(values);
+        self.invoke(move |conn| {
+            conn.insert_returning(&table, &columns, &pkcol, &returning, &as_refs(&values))
+        })
+        .await
+    }
+    async fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let pkcol = pkcol.clone();
+        let returning = returning.to_vec();
+        let values = to_owned_values(values);
+        self.invoke(move |conn| {
+            conn.insert_or_ignore(&table, &columns, &pkcol, &returning, &as_refs(&values))
+        })
+        .await
     }
     /// Like `insert_returning_pk` but with no return value.
     async fn insert_only(
@@ -330,7 +462,10 @@ async fn insert_only(
         columns: &[Column],
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        self.invoke(|conn| conn.insert_only(table, columns, values))
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let values = to_owned_values(values);
+        self.invoke(move |conn| conn.insert_only(&table, &columns, &as_refs(&values)))
             .await
     }
     /// Insert unless there's a conflict on the primary key column, in which case update.
@@ -341,7 +476,11 @@ async fn insert_or_replace(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        self.invoke(|conn| conn.insert_or_replace(table, columns, pkcol, values))
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let pkcol = pkcol.clone();
+        let values = to_owned_values(values);
+        self.invoke(move |conn| conn.insert_or_replace(&table, &columns, &pkcol, &as_refs(&values)))
             .await
     }
     async fn update(
@@ -352,31 +491,169 @@ async fn update(
         columns: &[Column],
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        self.invoke(|conn| conn.update(table, pkcol, pk, columns, values))
-            .await
+        let table = table.to_string();
+        let pk = SqlVal::from(pk);
+        let columns = columns.to_vec();
+        let values = to_owned_values(values);
+        self.invoke(move |conn| {
+            conn.update(&table, pkcol, pk.as_ref(), &columns, &as_refs(&values))
+        })
+        .await
     }
     async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
-        self.invoke(|conn| conn.delete_where(table, expr)).await
+        let table = table.to_string();
+        self.invoke(move |conn| conn.delete_where(&table, expr))
+            .await
+    }
+    async fn delete_returning(
+        &self,
+        table: &str,
+        expr: BoolExpr,
+        returning: &[Column],
+    ) -> Result<Vec<Vec<SqlVal>>> {
+        let table = table.to_string();
+        let returning = returning.to_vec();
+        self.invoke(move |conn| conn.delete_returning(&table, expr, &returning))
+            .await
+    }
+    async fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        let table = table.to_string();
+        self.invoke(move |conn| conn.update_where(&table, assignments, expr))
+            .await
     }
     /// Tests if a table exists in the database.
     async fn has_table(&self, table: &str) -> Result<bool> {
-        self.invoke(|conn| conn.has_table(table)).await
+        let table = table.to_string();
+        self.invoke(move |conn| conn.has_table(&table)).await
+    }
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        self.invoke(|conn| conn.list_tables()).await
+    }
+    async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        let table = table.to_string();
+        self.invoke(move |conn| conn.table_schema(&table)).await
+    }
+    async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        let table = table.to_string();
+        self.invoke(move |conn| conn.list_indexes(&table)).await
+    }
+    async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        let channel = channel.to_string();
+        let payload = payload.to_string();
+        self.invoke(move |conn| conn.notify(&channel, &payload))
+            .await
+    }
+    async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        let name = name.to_string();
+        let value = value.to_string();
+        self.invoke(move |conn| conn.set_config(&name, &value, local))
+            .await
+    }
+    async fn current_config(&self, name: &str) -> Result<Option<String>> {
+        let name = name.to_string();
+        self.invoke(move |conn| conn.current_config(&name)).await
+    }
+    async fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        let name = name.to_string();
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        let sort = sort.map(<[Order]>::to_vec);
+        self.invoke(move |conn| {
+            conn.declare_cursor(
+                &name,
+                &table,
+                &columns,
+                expr,
+                limit,
+                offset,
+                sort.as_deref(),
+            )
+        })
+        .await
+    }
+    async fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        let name = name.to_string();
+        let columns = columns.to_vec();
+        let rows = self
+            .invoke(move |conn| {
+                let rows = conn.fetch_cursor(&name, &columns, count)?;
+                super::connmethods::BufferedRows::read(rows, &columns)
+            })
+            .await?;
+        Ok(Box::new(rows))
+    }
+    async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        let blob = blob.clone();
+        self.invoke(move |conn| conn.blob_len(&blob)).await
+    }
+    async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let blob = blob.clone();
+        self.invoke(move |conn| conn.read_blob(&blob, offset, len))
+            .await
+    }
+    async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        let blob = blob.clone();
+        let data = data.to_vec();
+        self.invoke(move |conn| conn.write_blob(&blob, offset, &data))
+            .await
+    }
+    async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        let blob = blob.clone();
+        self.invoke(move |conn| conn.allocate_blob(&blob, len))
+            .await
     }
 }
 
+/// Copies `values` so that they may be sent to the worker thread.
+fn to_owned_values(values: &[SqlValRef<'_>]) -> Vec<SqlVal> {
+    values.iter().cloned().map(SqlVal::from).collect()
+}
+
+/// Borrows the values copied by [`to_owned_values`].
+fn as_refs(values: &[SqlVal]) -> Vec<SqlValRef<'_>> {
+    values.iter().map(SqlVal::as_ref).collect()
+}
+
 #[async_trait]
 impl<T> BackendConnectionAsync for AsyncAdapter<T>
 where
     T: BackendConnection,
 {
     async fn transaction<'c>(&'c mut self) -> Result<TransactionAsync<'c>> {
-        let transaction_ptr: SyncSendPtrMut<dyn BackendTransaction> = self
+        let transaction_ptr: SyncSendPtrMut<dyn BackendTransaction<'static>> = self
             .invoke_mut(|conn| {
                 let transaction: Transaction = conn.transaction()?;
                 let transaction_ptr: *mut dyn BackendTransaction = Box::into_raw(transaction.trans);
+                // The transaction borrows the connection, which is only
+                // dropped on the worker thread after it. Its lifetime is
+                // restored below, once it has been sent back.
+                let transaction_ptr: *mut dyn BackendTransaction<'static> =
+                    unsafe { std::mem::transmute(transaction_ptr) };
                 Ok(unsafe { SyncSendPtrMut::new(transaction_ptr) })
             })
             .await?;
+        let transaction_ptr: *mut dyn BackendTransaction<'c> =
+            unsafe { std::mem::transmute(transaction_ptr.inner) };
+        let transaction_ptr = unsafe { SyncSendPtrMut::new(transaction_ptr) };
         let transaction_adapter = self.create_with_same_env(transaction_ptr);
         Ok(TransactionAsync::new(Box::new(transaction_adapter)))
     }
@@ -394,6 +671,14 @@ fn backend_name(&self) -> &'static str {
     fn is_closed(&self) -> bool {
         ok_or_panic_with_adapter_error(self.invoke_blocking(|conn| Ok(conn.is_closed())))
     }
+
+    fn set_update_hook(&self, hook: Option<UpdateHook>) -> Result<()> {
+        self.invoke_blocking(|conn| conn.set_update_hook(hook))
+    }
+
+    fn create_function(&self, name: &str, n_args: i32, func: ScalarFunction) -> Result<()> {
+        self.invoke_blocking(|conn| conn.create_function(name, n_args, func))
+    }
 }
 
 fn ok_or_panic_with_adapter_error<T>(r: Result<T>) -> T {
@@ -420,9 +705,7 @@ impl<T> AsyncAdapter<T>
     T: BackendConnection + 'static,
 {
     pub fn into_connection(self) -> ConnectionAsync {
-        ConnectionAsync {
-            conn: Box::new(self),
-        }
+        ConnectionAsync::new(Box::new(self))
     }
 }
 
@@ -0,0 +1,109 @@
+//! Grouping the statements of an operation made of several, such as a
+//! change and the counters it adjusts, so that they take effect
+//! entirely or not at all.
+
+use super::ConnectionMethods;
+#[cfg(feature = "async")]
+use super::ConnectionMethodsAsync;
+use crate::{warn, Result};
+
+/// The statements undoing a group: those run since the savepoint
+/// `savepoint`, or the whole transaction if there is none.
+pub(crate) fn rollback_sql(savepoint: Option<&str>) -> String {
+    match savepoint {
+        Some(savepoint) => {
+            format!("ROLLBACK TO SAVEPOINT {savepoint}; RELEASE SAVEPOINT {savepoint};")
+        }
+        None => "ROLLBACK;".to_string(),
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// A group of statements begun by `begin`, within the savepoint
+/// `savepoint` or, if there is none, within a transaction begun for
+/// them. Should it be dropped before `end`, such as when the operation
+/// is cancelled at a deadline, the connection is asked to undo them with
+/// [`ConnectionMethods::abandon_atomic`] rather than being left within
+/// the group.
+#[must_use]
+pub(crate) struct Atomic<'c> {
+    conn: &'c dyn ConnectionMethods,
+    savepoint: Option<&'static str>,
+    ended: bool,
+}
+
+#[maybe_async_cfg::maybe(
+    idents(Atomic, ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+impl Drop for Atomic<'_> {
+    fn drop(&mut self) {
+        if !self.ended {
+            self.conn.abandon_atomic(self.savepoint);
+        }
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(Atomic, ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Groups the statements which follow, until `end`: within the
+/// savepoint `savepoint` if `conn` is a transaction, as savepoints may
+/// not be made outside one on some backends, and within a new
+/// transaction otherwise.
+pub(crate) async fn begin<'c>(
+    conn: &'c impl ConnectionMethods,
+    savepoint: &'static str,
+) -> Result<Atomic<'c>> {
+    let savepoint = conn.in_transaction().then_some(savepoint);
+    // Made first, so that the group is undone should beginning it be
+    // cancelled after the statement has been sent
+    let mut atomic = Atomic {
+        conn,
+        savepoint,
+        ended: false,
+    };
+    let sql = match savepoint {
+        Some(savepoint) => format!("SAVEPOINT {savepoint};"),
+        None => "BEGIN;".to_string(),
+    };
+    if let Err(e) = conn.execute(&sql).await {
+        atomic.ended = true;
+        return Err(e);
+    }
+    Ok(atomic)
+}
+
+#[maybe_async_cfg::maybe(idents(Atomic), sync(), async(feature = "async"))]
+/// Ends the group of statements begun by `begin`, keeping their changes
+/// if `result` is `Ok` and undoing them otherwise. Should undoing them
+/// fail too, the error is logged, and that of `result` returned.
+pub(crate) async fn end<T>(mut atomic: Atomic<'_>, result: Result<T>) -> Result<T> {
+    let conn = atomic.conn;
+    let ended = match (atomic.savepoint, result.is_ok()) {
+        (Some(savepoint), true) => {
+            conn.execute(&format!("RELEASE SAVEPOINT {savepoint};"))
+                .await
+        }
+        (None, true) => conn.execute("COMMIT;").await,
+        (savepoint, false) => conn.execute(&rollback_sql(savepoint)).await,
+    };
+    // Only once the group has been ended may it no longer be abandoned
+    atomic.ended = true;
+    match ended {
+        Err(e) if result.is_ok() => Err(e),
+        #[allow(unused_variables)] // used only when logging is enabled
+        Err(e) => {
+            warn!("failed to roll back: {}", e);
+            result
+        }
+        Ok(()) => result,
+    }
+}
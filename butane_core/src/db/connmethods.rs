@@ -4,10 +4,21 @@
 use std::ops::{Deref, DerefMut};
 
 use async_trait::async_trait;
+use fallible_iterator::FallibleIterator;
 
-use crate::query::{BoolExpr, Expr, Order};
+use crate::encryption::KeyProvider;
+use crate::query::{BoolExpr, Expr, FromRow, Order};
 use crate::{Result, SqlType, SqlVal, SqlValRef};
 
+/// The most parameters bound to one statement which every backend
+/// accepts, returned by default by
+/// [`ConnectionMethods::max_statement_params`].
+pub(crate) const MAX_STATEMENT_PARAMS: usize = 999;
+
+/// The backend named by the errors of the default implementations of
+/// optional [`ConnectionMethods`], which cannot know its name.
+const UNNAMED_BACKEND: &str = "(unnamed)";
+
 /// Methods available on a database connection. Most users do not need
 /// to call these methods directly and will instead use methods on
 /// [DataObject][crate::DataObject] or the `query!` macro. This trait is
@@ -20,6 +31,84 @@
 #[async_trait]
 pub trait ConnectionMethods: super::internal::AsyncRequiresSync {
     async fn execute(&self, sql: &str) -> Result<()>;
+    /// Executes the statement `sql`, binding `params` to its `?`
+    /// placeholders in order, and returns the number of rows affected.
+    /// The placeholders are translated to the syntax of the backend, so
+    /// values never need to be interpolated into hand-written SQL.
+    /// Question marks in quoted strings, quoted identifiers and comments
+    /// are not placeholders.
+    #[allow(unused_variables)]
+    async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "parameters"))
+    }
+    /// The most parameters which may be bound to one statement. Longer
+    /// `IN` lists and batches of rows are split across statements.
+    fn max_statement_params(&self) -> usize {
+        MAX_STATEMENT_PARAMS
+    }
+    /// Whether statements are run within a transaction, in which
+    /// savepoints may be made.
+    fn in_transaction(&self) -> bool {
+        false
+    }
+    /// Undoes the statements of a group which an operation made
+    /// atomic, such as a save and the counters it adjusts, but which was
+    /// cancelled before it could end the group: back to the savepoint
+    /// `savepoint`, or the whole transaction begun for the group if
+    /// there is none. Semver exempt.
+    #[doc(hidden)]
+    #[maybe_async_cfg::only_if(key = "sync")]
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        #[allow(unused_variables)] // used only when logging is enabled
+        if let Err(e) = self.execute(&super::atomic::rollback_sql(savepoint)) {
+            crate::warn!("failed to roll back abandoned statements: {}", e);
+        }
+    }
+    /// As for sync connections, except that this is called on drop so
+    /// must not wait for the database. Connections may instead undo the
+    /// statements before their next one; by default, this only logs that
+    /// they cannot. Semver exempt.
+    #[doc(hidden)]
+    #[maybe_async_cfg::only_if(key = "async")]
+    #[allow(unused_variables)] // used only when logging is enabled
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        crate::warn!(
+            "cannot roll back abandoned statements: {}",
+            super::atomic::rollback_sql(savepoint)
+        );
+    }
+    /// The key provider encrypting and decrypting the
+    /// [`Encrypted`](crate::encryption::Encrypted) fields of objects
+    /// saved and loaded through this connection, if one has been set
+    /// with [`Connection::set_key_provider`](super::Connection::set_key_provider).
+    fn key_provider(&self) -> Option<&dyn KeyProvider> {
+        None
+    }
+    /// Runs the query `sql`, binding `params` to its `?` placeholders as
+    /// with `execute_params`, and returns its rows, which must have the
+    /// given `columns`. Only the types of the columns are used, not
+    /// their names.
+    #[allow(unused_variables)]
+    async fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "parameters"))
+    }
+    /// Runs the query `sql` as with `query_params`, returning each row
+    /// converted to a `T`, usually a tuple such as `(String, i64)`, so
+    /// that ad-hoc queries need no model. See [`FromRow`].
+    async fn query_as<T: FromRow>(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<Vec<T>>
+    where
+        Self: Sized,
+    {
+        self.query_params(sql, params, T::COLUMNS)
+            .await?
+            .mapped(T::from_row)
+            .collect()
+    }
     async fn query<'c>(
         &'c self,
         table: &str,
@@ -29,6 +118,31 @@ async fn query<'c>(
         offset: Option<i32>,
         sort: Option<&[Order]>,
     ) -> Result<RawQueryResult<'c>>;
+    /// Like `query`, but also returns the number of rows for which
+    /// `expr` is true, regardless of `limit` and `offset`. Backends may
+    /// count the rows with the same statement that selects them.
+    async fn query_counted<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<(RawQueryResult<'c>, u64)> {
+        let count = [Column::new("COUNT(*)", SqlType::BigInt)];
+        let rows = self
+            .query(table, &count, expr.clone(), None, None, None)
+            .await?;
+        let total = match first_row_values(rows, &count)?.as_deref() {
+            Some([SqlVal::BigInt(total)]) => *total as u64,
+            _ => return Err(crate::Error::Internal("COUNT(*) returned no row".into())),
+        };
+        let rows = self
+            .query(table, columns, expr, limit, offset, sort)
+            .await?;
+        Ok((rows, total))
+    }
     async fn insert_returning_pk(
         &self,
         table: &str,
@@ -36,6 +150,44 @@ async fn insert_returning_pk(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<SqlVal>;
+    /// Like `insert_returning_pk`, but also returns the values of the
+    /// `returning` columns, which may have been generated by the
+    /// database. The primary key is the first value returned, followed
+    /// by the `returning` columns in order.
+    async fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        let pk = self
+            .insert_returning_pk(table, columns, pkcol, values)
+            .await?;
+        let mut result = self
+            .query_by_pk(table, pkcol, pk.clone(), returning)
+            .await?;
+        result.insert(0, pk);
+        Ok(result)
+    }
+    /// Returns the values of `columns` in the row with primary key `pk`.
+    async fn query_by_pk(
+        &self,
+        table: &str,
+        pkcol: &Column,
+        pk: SqlVal,
+        columns: &[Column],
+    ) -> Result<Vec<SqlVal>> {
+        if columns.is_empty() {
+            return Ok(Vec::new());
+        }
+        let expr = BoolExpr::Eq(pkcol.name(), Expr::Val(pk));
+        let rows = self
+            .query(table, columns, Some(expr), Some(1), None, None)
+            .await?;
+        first_row_values(rows, columns)?.ok_or(crate::Error::NoSuchObject)
+    }
     /// Like `insert_returning_pk` but with no return value.
     async fn insert_only(
         &self,
@@ -43,6 +195,47 @@ async fn insert_only(
         columns: &[Column],
         values: &[SqlValRef<'_>],
     ) -> Result<()>;
+    /// Like `insert_only`, but inserts several rows, given as the values
+    /// of `columns` for each row in turn. The rows are inserted with a
+    /// single statement unless there are too many parameters for one.
+    async fn insert_only_many(
+        &self,
+        table: &str,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let rows_per_statement = (self.max_statement_params() / columns.len()).max(1);
+        for rows in values.chunks(rows_per_statement * columns.len()) {
+            let mut sql = String::new();
+            super::helper::sql_insert_rows(table, columns, rows.len() / columns.len(), &mut sql);
+            self.execute_params(&sql, rows).await?;
+        }
+        Ok(())
+    }
+    /// Like `insert_only_many`, but rows which conflict with an existing
+    /// row, such as on a unique index, are skipped rather than failing
+    /// the statement.
+    async fn insert_or_ignore_many(
+        &self,
+        table: &str,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let rows_per_statement = (self.max_statement_params() / columns.len()).max(1);
+        for rows in values.chunks(rows_per_statement * columns.len()) {
+            let mut sql = String::new();
+            super::helper::sql_insert_rows(table, columns, rows.len() / columns.len(), &mut sql);
+            sql.push_str(" ON CONFLICT DO NOTHING");
+            self.execute_params(&sql, rows).await?;
+        }
+        Ok(())
+    }
     /// Insert unless there's a conflict on the primary key column, in which case update.
     async fn insert_or_replace(
         &self,
@@ -51,6 +244,24 @@ async fn insert_or_replace(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<()>;
+    /// Insert unless there's a conflict, such as on the primary key or a
+    /// unique column, in which case do nothing. Returns the primary key
+    /// and `returning` columns like `insert_returning`, or `None` if
+    /// nothing was inserted.
+    #[allow(unused_variables)]
+    async fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "inserts ignoring conflicts",
+        ))
+    }
     async fn update(
         &self,
         table: &str,
@@ -64,9 +275,231 @@ async fn delete(&self, table: &str, pkcol: &'static str, pk: SqlVal) -> Result<(
             .await?;
         Ok(())
     }
+    /// Deletes the rows of `table` whose `pkcol` is one of `pks`, and
+    /// returns the number of rows deleted. The primary keys are listed
+    /// in as few statements as the number of parameters allows.
+    async fn delete_many(&self, table: &str, pkcol: &'static str, pks: &[SqlVal]) -> Result<usize> {
+        let mut deleted = 0;
+        for chunk in pks.chunks(self.max_statement_params()) {
+            deleted += self
+                .delete_where(table, BoolExpr::In(pkcol, chunk.to_vec()))
+                .await?;
+        }
+        Ok(deleted)
+    }
     async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize>;
+    /// Deletes the rows of `table` for which `expr` is true, as with
+    /// `delete_where`, and returns the values of the `returning`
+    /// columns of each row deleted. Backends without `DELETE ...
+    /// RETURNING` select the rows before deleting them, so return
+    /// exactly those deleted only if no other connection can delete
+    /// them in between, as within a SQLite transaction.
+    async fn delete_returning(
+        &self,
+        table: &str,
+        expr: BoolExpr,
+        returning: &[Column],
+    ) -> Result<Vec<Vec<SqlVal>>> {
+        let rows = self
+            .query(table, returning, Some(expr.clone()), None, None, None)
+            .await?
+            .mapped(|row| {
+                returning
+                    .iter()
+                    .enumerate()
+                    .map(|(i, col)| Ok(SqlVal::from(row.get(i, col.ty().clone())?)))
+                    .collect::<Result<Vec<SqlVal>>>()
+            })
+            .collect()?;
+        self.delete_where(table, expr).await?;
+        Ok(rows)
+    }
+    /// Sets each column in `assignments` to the value of its expression,
+    /// evaluated against the current row, in all rows of `table` for
+    /// which `expr` is true. Returns the number of rows updated.
+    #[allow(unused_variables)]
+    async fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "updates of matching rows",
+        ))
+    }
     /// Tests if a table exists in the database.
     async fn has_table(&self, table: &str) -> Result<bool>;
+    /// Lists the tables in the database, in order of name. Views and
+    /// tables internal to the database are not included. Only the
+    /// current schema is listed on Postgres, and the main database on
+    /// SQLite.
+    #[allow(unused_variables)]
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "schema introspection",
+        ))
+    }
+    /// Describes the columns of `table`, or returns `None` if it does not
+    /// exist. As with [`has_table`](Self::has_table), `table` may be
+    /// qualified with a schema.
+    #[allow(unused_variables)]
+    async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "schema introspection",
+        ))
+    }
+    /// Lists the indexes on `table` other than that of its primary key,
+    /// including those enforcing unique constraints, in order of name.
+    #[allow(unused_variables)]
+    async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "schema introspection",
+        ))
+    }
+    /// Sends a notification with `payload` to the sessions listening on
+    /// `channel`. See [`crate::notify`].
+    #[allow(unused_variables)]
+    async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "notifications"))
+    }
+    /// Sets the configuration parameter `name`, such as a custom
+    /// `app.current_tenant` read by row-level security policies, to
+    /// `value`. If `local` is true the setting only lasts until the end
+    /// of the current transaction, as with `SET LOCAL`, and otherwise
+    /// for the rest of the session. Only the Postgres backend supports
+    /// configuration parameters.
+    #[allow(unused_variables)]
+    async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "configuration parameters",
+        ))
+    }
+    /// Returns the value of the configuration parameter `name`, or
+    /// `None` if it has not been set.
+    #[allow(unused_variables)]
+    async fn current_config(&self, name: &str) -> Result<Option<String>> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "configuration parameters",
+        ))
+    }
+    /// Declares a server-side cursor `name` over the rows `query` with
+    /// the same arguments would return, to be read in pages with
+    /// [`fetch_cursor`](Self::fetch_cursor). Must be called within a
+    /// transaction, at the end of which the cursor is closed. Only the
+    /// Postgres backend supports cursors.
+    #[allow(clippy::too_many_arguments)]
+    #[allow(unused_variables)]
+    async fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "server-side cursors",
+        ))
+    }
+    /// Fetches the next `count` rows from the cursor `name`, or fewer if
+    /// it has run out of rows.
+    #[allow(unused_variables)]
+    async fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        Err(crate::Error::Unsupported(
+            UNNAMED_BACKEND,
+            "server-side cursors",
+        ))
+    }
+    /// Returns the length in bytes of `blob`, or `None` if it is NULL.
+    #[allow(unused_variables)]
+    async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "blob streaming"))
+    }
+    /// Reads up to `len` bytes of `blob` starting at `offset`. Fewer
+    /// bytes are returned if the end of the blob is reached.
+    #[allow(unused_variables)]
+    async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "blob streaming"))
+    }
+    /// Overwrites the bytes of `blob` starting at `offset` with `data`,
+    /// which must not extend past the end of the blob.
+    #[allow(unused_variables)]
+    async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "blob streaming"))
+    }
+    /// Replaces `blob` with `len` zero bytes, to be overwritten with
+    /// [`write_blob`](Self::write_blob).
+    #[allow(unused_variables)]
+    async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        Err(crate::Error::Unsupported(UNNAMED_BACKEND, "blob streaming"))
+    }
+}
+
+/// Identifies a blob in the database, as the value of `column` in the
+/// row of `table` whose primary key column `pkcol` has the value `pk`,
+/// or as the large object whose oid is that value if `large_object`.
+/// Most users do not need to use this directly, and will instead use
+/// [`open_blob`](crate::DataObjectOpsAsync::open_blob).
+#[derive(Clone, Debug)]
+pub struct BlobRef {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub pkcol: &'static str,
+    pub pk: SqlVal,
+    pub large_object: bool,
+}
+
+/// The columns of a table in the database, as returned by
+/// [`table_schema`](ConnectionMethods::table_schema).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    /// Columns in the order they are defined.
+    pub columns: Vec<ColumnSchema>,
+}
+impl TableSchema {
+    /// Gets the column named `name`, if it exists.
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
+/// A column of a table in the database. See [`TableSchema`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    /// The type of the column as the database describes it, such as
+    /// `INTEGER` on SQLite or `bigint` on Postgres.
+    pub type_name: String,
+    pub nullable: bool,
+    pub primary_key: bool,
+    /// The SQL expression of the column's default, if it has one.
+    pub default: Option<String>,
+}
+
+/// An index on a table in the database, as returned by
+/// [`list_indexes`](ConnectionMethods::list_indexes).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexSchema {
+    pub name: String,
+    /// Indexed columns, in index order. Indexed expressions are not included.
+    pub columns: Vec<String>,
+    pub unique: bool,
 }
 
 /// Represents a database column. Most users do not need to use this
@@ -75,10 +508,22 @@ async fn delete(&self, table: &str, pkcol: &'static str, pk: SqlVal) -> Result<(
 pub struct Column {
     name: &'static str,
     ty: SqlType,
+    sensitive: bool,
 }
 impl Column {
     pub const fn new(name: &'static str, ty: SqlType) -> Self {
-        Column { name, ty }
+        Column {
+            name,
+            ty,
+            sensitive: false,
+        }
+    }
+    /// Marks the column as holding sensitive values, which are redacted
+    /// from logs and errors. Returns `self` as this method is expected
+    /// to be chained.
+    pub const fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
     }
     pub fn name(&self) -> &'static str {
         self.name
@@ -86,6 +531,10 @@ pub fn name(&self) -> &'static str {
     pub fn ty(&self) -> &SqlType {
         &self.ty
     }
+    /// Whether the column holds sensitive values, see [`Column::sensitive`].
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
 }
 
 /// Backend-specific row abstraction. Only implementors of new
@@ -156,16 +605,124 @@ pub fn new(rows: Vec<T>) -> Self {
     }
 }
 
+/// Rows read from another [`BackendRows`] so that they outlive it, such
+/// as to send them to another thread. The text and blob values of all
+/// the rows are copied into one buffer each, rather than each value
+/// into its own allocation, and [`BackendRow::get`] borrows them from
+/// there.
 #[cfg(feature = "async-adapter")]
-pub(crate) fn vec_from_backend_rows<'a>(
-    mut other: Box<dyn BackendRows + 'a>,
+#[derive(Debug)]
+pub(crate) struct BufferedRows {
+    cells: Vec<BufferedCell>,
+    text: String,
+    blobs: Vec<u8>,
+    width: usize,
+    rows: usize,
+    /// The number of rows returned by `next` so far.
+    read: usize,
+}
+
+#[cfg(feature = "async-adapter")]
+#[derive(Debug)]
+enum BufferedCell {
+    Text(std::ops::Range<usize>),
+    Blob(std::ops::Range<usize>),
+    Val(SqlVal),
+}
+
+#[cfg(feature = "async-adapter")]
+impl BufferedRows {
+    pub(crate) fn read<'a>(
+        mut other: Box<dyn BackendRows + 'a>,
+        columns: &[Column],
+    ) -> Result<Self> {
+        let mut rows = BufferedRows {
+            cells: Vec::new(),
+            text: String::new(),
+            blobs: Vec::new(),
+            width: columns.len(),
+            rows: 0,
+            read: 0,
+        };
+        while let Some(row) = other.next()? {
+            if row.len() != columns.len() {
+                return Err(crate::Error::BoundsError(
+                    "row length doesn't match columns specifier length".into(),
+                ));
+            }
+            for (idx, col) in columns.iter().enumerate() {
+                let cell = match row.get(idx, col.ty.clone())? {
+                    SqlValRef::Text(text) => {
+                        let start = rows.text.len();
+                        rows.text.push_str(text);
+                        BufferedCell::Text(start..rows.text.len())
+                    }
+                    SqlValRef::Blob(blob) => {
+                        let start = rows.blobs.len();
+                        rows.blobs.extend_from_slice(blob);
+                        BufferedCell::Blob(start..rows.blobs.len())
+                    }
+                    val => BufferedCell::Val(val.into()),
+                };
+                rows.cells.push(cell);
+            }
+            rows.rows += 1;
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(feature = "async-adapter")]
+impl BackendRows for BufferedRows {
+    fn next(&mut self) -> Result<Option<&dyn BackendRow>> {
+        if self.read == self.rows {
+            return Ok(None);
+        }
+        self.read += 1;
+        Ok(Some(self))
+    }
+
+    fn current(&self) -> Option<&dyn BackendRow> {
+        (self.read > 0).then_some(self as &dyn BackendRow)
+    }
+}
+
+/// The row most recently returned by `next`.
+#[cfg(feature = "async-adapter")]
+impl BackendRow for BufferedRows {
+    fn get(&self, idx: usize, ty: SqlType) -> Result<SqlValRef<'_>> {
+        if idx >= self.width || self.read == 0 {
+            return Err(crate::Error::BoundsError("idx out of bounds".into()));
+        }
+        let val = match &self.cells[(self.read - 1) * self.width + idx] {
+            BufferedCell::Text(range) => SqlValRef::Text(&self.text[range.clone()]),
+            BufferedCell::Blob(range) => SqlValRef::Blob(&self.blobs[range.clone()]),
+            BufferedCell::Val(val) => val.as_ref(),
+        };
+        match val.sqltype() {
+            Some(val_ty) if val_ty != ty => Err(crate::Error::CannotConvertSqlVal(ty, val.into())),
+            _ => Ok(val),
+        }
+    }
+    fn len(&self) -> usize {
+        self.width
+    }
+}
+
+/// Returns the values of the first row, if any.
+pub(crate) fn first_row_values<'a>(
+    mut rows: Box<dyn BackendRows + 'a>,
     columns: &[Column],
-) -> Result<VecRows<VecRow>> {
-    let mut rows: Vec<VecRow> = Vec::new();
-    while let Some(row) = other.next()? {
-        rows.push(VecRow::new(row, columns)?)
+) -> Result<Option<Vec<SqlVal>>> {
+    match rows.next()? {
+        Some(row) => columns
+            .iter()
+            .enumerate()
+            .map(|(idx, col)| row.get(idx, col.ty().clone()).map(SqlVal::from))
+            .collect::<Result<Vec<SqlVal>>>()
+            .map(Some),
+        None => Ok(None),
     }
-    Ok(VecRows::new(rows))
 }
 impl<T> BackendRows for VecRows<T>
 where
@@ -192,33 +749,17 @@ fn current(&self) -> Option<&dyn BackendRow> {
     }
 }
 
-#[cfg(feature = "async-adapter")]
 #[derive(Debug)]
 pub(crate) struct VecRow {
     values: Vec<SqlVal>,
 }
 
-#[cfg(feature = "async-adapter")]
 impl VecRow {
-    fn new(original: &dyn BackendRow, columns: &[Column]) -> Result<Self> {
-        if original.len() != columns.len() {
-            return Err(crate::Error::BoundsError(
-                "row length doesn't match columns specifier length".into(),
-            ));
-        }
-        Ok(Self {
-            values: (0..(columns.len()))
-                .map(|i| {
-                    original
-                        .get(i, columns[i].ty.clone())
-                        .map(|valref| valref.into())
-                })
-                .collect::<Result<Vec<SqlVal>>>()?,
-        })
+    pub(crate) fn from_values(values: Vec<SqlVal>) -> Self {
+        Self { values }
     }
 }
 
-#[cfg(feature = "async-adapter")]
 impl BackendRow for VecRow {
     fn get(&self, idx: usize, ty: SqlType) -> Result<SqlValRef<'_>> {
         self.values
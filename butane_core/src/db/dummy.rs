@@ -6,7 +6,7 @@
 
 use super::*;
 use crate::migrations::adb;
-use crate::query::{BoolExpr, Order};
+use crate::query::{BoolExpr, Expr, Order};
 use crate::{Error, Result, SqlVal, SqlValRef};
 
 #[derive(Clone, Debug)]
@@ -57,6 +57,17 @@ impl ConnectionMethods for DummyConnection {
     async fn execute(&self, sql: &str) -> Result<()> {
         Err(Error::PoisonedConnection)
     }
+    async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        Err(Error::PoisonedConnection)
+    }
     async fn query<'c>(
         &'c self,
         table: &str,
@@ -85,6 +96,16 @@ async fn insert_only(
     ) -> Result<()> {
         Err(Error::PoisonedConnection)
     }
+    async fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        Err(Error::PoisonedConnection)
+    }
     async fn insert_or_replace(
         &self,
         table: &str,
@@ -107,9 +128,67 @@ async fn update(
     async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
         Err(Error::PoisonedConnection)
     }
+    async fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        Err(Error::PoisonedConnection)
+    }
     async fn has_table(&self, table: &str) -> Result<bool> {
         Err(Error::PoisonedConnection)
     }
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn current_config(&self, name: &str) -> Result<Option<String>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        Err(Error::PoisonedConnection)
+    }
+    async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        Err(Error::PoisonedConnection)
+    }
 }
 
 #[maybe_async_cfg::maybe(
@@ -6,8 +6,12 @@
 use std::borrow::Cow;
 use std::fmt::Write;
 
+use sqlparser::dialect::GenericDialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::tokenizer::{Token, Tokenizer};
+
 use super::Column;
-use crate::migrations::adb::{AColumn, TypeIdentifier};
+use crate::migrations::adb::{AColumn, AIndex, ATable, TypeIdentifier, ADB};
 use crate::query::Expr::{Condition, Placeholder, Val};
 use crate::query::{BoolExpr::*, Expr, Join, Order, OrderDirection};
 use crate::Error;
@@ -19,9 +23,67 @@ pub(crate) trait PlaceholderSource {
     fn next_placeholder(&mut self) -> Cow<'_, str>;
 }
 
+/// Replaces each `?` placeholder in the hand-written statement `sql`
+/// with the next one from `pls`. Question marks within quoted strings,
+/// quoted identifiers and comments are left unchanged.
+pub(crate) fn translate_placeholders(sql: &str, pls: &mut impl PlaceholderSource) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '?' => {
+                out.push_str(&pls.next_placeholder());
+                continue;
+            }
+            '\'' | '"' => {
+                // A doubled quote is an escape, and is handled as the
+                // end of one quoted part followed by the start of another
+                out.push(c);
+                for q in chars.by_ref() {
+                    out.push(q);
+                    if q == c {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                out.push(c);
+                for q in chars.by_ref() {
+                    out.push(q);
+                    if q == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                out.push(c);
+                out.push(chars.next().unwrap());
+                let mut prev = '\0';
+                for q in chars.by_ref() {
+                    out.push(q);
+                    if prev == '*' && q == '/' {
+                        break;
+                    }
+                    prev = q;
+                }
+                continue;
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Returns whether `word` is a reserved word, and so needs quoting.
+pub fn is_reserved_word(word: &str) -> bool {
+    sqlparser::keywords::ALL_KEYWORDS.contains(&word.to_uppercase().as_str())
+}
+
 /// Quotes the `word` if it is a reserved word.
 pub fn quote_reserved_word(word: &str) -> Cow<'_, str> {
-    if sqlparser::keywords::ALL_KEYWORDS.contains(&word.to_uppercase().as_str()) {
+    if is_reserved_word(word) {
         format!("\"{}\"", word).into()
     } else {
         word.into()
@@ -50,26 +112,90 @@ pub fn sql_for_expr<F, P, W>(expr: Expr, f: F, values: &mut Vec<SqlVal>, pls: &m
             }
         },
         Placeholder => w.write_str(&pls.next_placeholder()),
+        Expr::Arith(a, op, b) => {
+            write!(w, "(").unwrap();
+            f(*a, values, pls, w);
+            write!(w, " {} ", op.sql()).unwrap();
+            f(*b, values, pls, w);
+            write!(w, ")")
+        }
+        Expr::QualifiedColumn(col) => {
+            sql_column(col, w);
+            Ok(())
+        }
+        Expr::Scalar(scalar) => {
+            // (SELECT <value> FROM <table> WHERE <filter> [ORDER BY ...] [LIMIT <limit>])
+            let scalar = *scalar;
+            write!(w, "(SELECT ").unwrap();
+            f(scalar.value, values, pls, w);
+            write!(w, " FROM {} WHERE ", quote_reserved_word(&scalar.table)).unwrap();
+            f(Condition(Box::new(scalar.filter)), values, pls, w);
+            if !scalar.sort.is_empty() {
+                sql_order(&scalar.sort, &f, values, pls, w);
+            }
+            if let Some(limit) = scalar.limit {
+                sql_limit(limit, w);
+            }
+            write!(w, ")")
+        }
+        Expr::Func(name, args) => {
+            write!(w, "{name}(").unwrap();
+            let mut remaining = args.len();
+            for arg in args {
+                f(arg, values, pls, w);
+                if remaining > 1 {
+                    write!(w, ", ").unwrap();
+                    remaining -= 1;
+                }
+            }
+            write!(w, ")")
+        }
+        Expr::Case(whens, otherwise) if whens.is_empty() => {
+            // CASE requires at least one WHEN
+            f(otherwise.map_or(Val(SqlVal::Null), |e| *e), values, pls, w);
+            Ok(())
+        }
+        Expr::Case(whens, otherwise) => {
+            // CASE WHEN <cond> THEN <value> ... [ELSE <value>] END
+            write!(w, "CASE").unwrap();
+            for (cond, value) in whens {
+                write!(w, " WHEN ").unwrap();
+                f(Condition(Box::new(cond)), values, pls, w);
+                write!(w, " THEN ").unwrap();
+                f(value, values, pls, w);
+            }
+            if let Some(otherwise) = otherwise {
+                write!(w, " ELSE ").unwrap();
+                f(*otherwise, values, pls, w);
+            }
+            write!(w, " END")
+        }
         Condition(c) => match *c {
             True => write!(w, "TRUE"),
             Eq(col, ex) => match ex {
-                Expr::Val(SqlVal::Null) => write!(w, "{col} IS NULL"),
-                _ => write!(w, "{col} = ").and_then(|_| Ok(f(ex, values, pls, w))),
+                Expr::Val(SqlVal::Null) => write!(w, "{} IS NULL", quote_reserved_word(col)),
+                _ => write!(w, "{} = ", quote_reserved_word(col))
+                    .and_then(|_| Ok(f(ex, values, pls, w))),
             },
             Ne(col, ex) => match ex {
-                Expr::Val(SqlVal::Null) => write!(w, "{col} IS NOT NULL"),
-                _ => write!(w, "{col} <> ").and_then(|_| Ok(f(ex, values, pls, w))),
+                Expr::Val(SqlVal::Null) => write!(w, "{} IS NOT NULL", quote_reserved_word(col)),
+                _ => write!(w, "{} <> ", quote_reserved_word(col))
+                    .and_then(|_| Ok(f(ex, values, pls, w))),
             },
-            Lt(col, ex) => write!(w, "{col} < ").and_then(|_| Ok(f(ex, values, pls, w))),
-            Gt(col, ex) => write!(w, "{col} > ").and_then(|_| Ok(f(ex, values, pls, w))),
-            Le(col, ex) => write!(w, "{col} <= ").and_then(|_| Ok(f(ex, values, pls, w))),
-            Ge(col, ex) => write!(w, "{col} >= ").and_then(|_| Ok(f(ex, values, pls, w))),
-            Like(col, ex) => write!(w, "{col} like ").and_then(|_| Ok(f(ex, values, pls, w))),
+            Lt(col, ex) => write!(w, "{} < ", quote_reserved_word(col))
+                .and_then(|_| Ok(f(ex, values, pls, w))),
+            Gt(col, ex) => write!(w, "{} > ", quote_reserved_word(col))
+                .and_then(|_| Ok(f(ex, values, pls, w))),
+            Le(col, ex) => write!(w, "{} <= ", quote_reserved_word(col))
+                .and_then(|_| Ok(f(ex, values, pls, w))),
+            Ge(col, ex) => write!(w, "{} >= ", quote_reserved_word(col))
+                .and_then(|_| Ok(f(ex, values, pls, w))),
+            Like(col, ex) => write!(w, "{} like ", quote_reserved_word(col))
+                .and_then(|_| Ok(f(ex, values, pls, w))),
             AllOf(conds) => {
                 let mut remaining = conds.len();
                 for cond in conds {
-                    // future perf improvement: figure out a clean way to avoid the extra boxing
-                    f(Condition(Box::new(cond)), values, pls, w);
+                    sql_for_operand(cond, &f, values, pls, w);
                     if remaining > 1 {
                         write!(w, " AND ").unwrap();
                         remaining -= 1;
@@ -78,9 +204,9 @@ pub fn sql_for_expr<F, P, W>(expr: Expr, f: F, values: &mut Vec<SqlVal>, pls: &m
                 Ok(())
             }
             And(a, b) => {
-                f(Condition(a), values, pls, w);
+                sql_for_operand(*a, &f, values, pls, w);
                 write!(w, " AND ").unwrap();
-                f(Condition(b), values, pls, w);
+                sql_for_operand(*b, &f, values, pls, w);
                 Ok(())
             }
             Or(a, b) => {
@@ -89,7 +215,14 @@ pub fn sql_for_expr<F, P, W>(expr: Expr, f: F, values: &mut Vec<SqlVal>, pls: &m
                 f(Condition(b), values, pls, w);
                 Ok(())
             }
-            Not(a) => write!(w, "NOT ").and_then(|_| Ok(f(Condition(a), values, pls, w))),
+            Not(a) => match negate(*a) {
+                Ok(negated) => Ok(f(Condition(Box::new(negated)), values, pls, w)),
+                Err(a) => {
+                    write!(w, "NOT (").unwrap();
+                    f(Condition(Box::new(a)), values, pls, w);
+                    write!(w, ")")
+                }
+            },
             Subquery {
                 col,
                 tbl2,
@@ -125,6 +258,62 @@ pub fn sql_for_expr<F, P, W>(expr: Expr, f: F, values: &mut Vec<SqlVal>, pls: &m
                 write!(w, ")").unwrap();
                 Ok(())
             }
+            InCte { col, cte } => {
+                // <col> IN (WITH [RECURSIVE] <name>(<column>) AS (
+                //   SELECT <col> FROM <table> WHERE <filter>
+                //   [UNION SELECT <table>.<col> FROM <table> JOIN <name> ON <table>.<join> = <name>.<column>]
+                // ) SELECT <column> FROM <name>)
+                let name = quote_reserved_word(cte.name());
+                let column = quote_reserved_word(cte.column());
+                let recursive = if cte.step().is_some() { "RECURSIVE " } else { "" };
+                let anchor = cte.anchor();
+                write!(
+                    w,
+                    "{} IN (WITH {recursive}{name}({column}) AS (SELECT {} FROM {} WHERE ",
+                    quote_reserved_word(col),
+                    quote_reserved_word(anchor.column),
+                    quote_reserved_word(&anchor.table),
+                )
+                .unwrap();
+                f(Expr::Condition(Box::new(anchor.filter.clone())), values, pls, w);
+                if let Some(step) = cte.step() {
+                    let table = quote_reserved_word(&step.table);
+                    write!(
+                        w,
+                        " UNION SELECT {table}.{} FROM {table} JOIN {name} ON {table}.{} = {name}.{column}",
+                        quote_reserved_word(step.column),
+                        quote_reserved_word(step.join_column),
+                    )
+                    .unwrap();
+                }
+                write!(w, ") SELECT {column} FROM {name})")
+            }
+            Exists { tbl, expr } => {
+                write!(
+                    w,
+                    "EXISTS (SELECT 1 FROM {} WHERE ",
+                    quote_reserved_word(&tbl)
+                )
+                .unwrap();
+                f(Condition(expr), values, pls, w);
+                write!(w, ")")
+            }
+            Compare(left, op, right) => match (op, right) {
+                (query::CompareOp::Eq, Expr::Val(SqlVal::Null)) => {
+                    f(left, values, pls, w);
+                    write!(w, " IS NULL")
+                }
+                (query::CompareOp::Ne, Expr::Val(SqlVal::Null)) => {
+                    f(left, values, pls, w);
+                    write!(w, " IS NOT NULL")
+                }
+                (op, right) => {
+                    f(left, values, pls, w);
+                    write!(w, " {} ", op.sql()).unwrap();
+                    f(right, values, pls, w);
+                    Ok(())
+                }
+            },
             In(col, vals) => {
                 write!(w, "{} IN (", quote_reserved_word(col)).unwrap();
                 let mut remaining = vals.len();
@@ -137,17 +326,137 @@ pub fn sql_for_expr<F, P, W>(expr: Expr, f: F, values: &mut Vec<SqlVal>, pls: &m
                 }
                 write!(w, ")")
             }
+            // Lists are stored as JSON arrays, except by backends which
+            // have arrays of their own
+            ListContains(_, vals) if vals.is_empty() => write!(w, "TRUE"),
+            ListContains(col, vals) => {
+                let mut remaining = vals.len();
+                for val in vals {
+                    write!(
+                        w,
+                        "EXISTS (SELECT 1 FROM json_each({}) WHERE value = ",
+                        quote_reserved_word(col)
+                    )
+                    .unwrap();
+                    f(Expr::Val(SqlVal::Text(val)), values, pls, w);
+                    write!(w, ")").unwrap();
+                    if remaining > 1 {
+                        write!(w, " AND ").unwrap();
+                        remaining -= 1;
+                    }
+                }
+                Ok(())
+            }
+            ListOverlaps(_, vals) if vals.is_empty() => write!(w, "FALSE"),
+            ListOverlaps(col, vals) => {
+                write!(
+                    w,
+                    "EXISTS (SELECT 1 FROM json_each({}) WHERE value IN (",
+                    quote_reserved_word(col)
+                )
+                .unwrap();
+                let mut remaining = vals.len();
+                for val in vals {
+                    f(Expr::Val(SqlVal::Text(val)), values, pls, w);
+                    if remaining > 1 {
+                        write!(w, ", ").unwrap();
+                        remaining -= 1;
+                    }
+                }
+                write!(w, "))")
+            }
         },
     }
     .unwrap()
 }
 
+/// Writes an operand of `AND`, parenthesizing it if it is an `OR`,
+/// which binds less tightly.
+fn sql_for_operand<F, P, W>(
+    cond: query::BoolExpr,
+    f: &F,
+    values: &mut Vec<SqlVal>,
+    pls: &mut P,
+    w: &mut W,
+) where
+    F: Fn(Expr, &mut Vec<SqlVal>, &mut P, &mut W),
+    W: Write,
+{
+    if matches!(cond, Or(..)) {
+        write!(w, "(").unwrap();
+        f(Condition(Box::new(cond)), values, pls, w);
+        write!(w, ")").unwrap();
+    } else {
+        f(Condition(Box::new(cond)), values, pls, w);
+    }
+}
+
+/// Pushes a negation into `expr` where SQL has no need of `NOT`: double
+/// negations cancel, comparisons are inverted and, by De Morgan's laws,
+/// `AND` and `OR` are swapped. Inverted comparisons behave the same as
+/// negated ones with `NULL`s. Returns `expr` unchanged as the error if
+/// it must be negated with `NOT`.
+fn negate(expr: query::BoolExpr) -> std::result::Result<query::BoolExpr, query::BoolExpr> {
+    Ok(match expr {
+        Not(a) => *a,
+        Eq(col, ex) => Ne(col, ex),
+        Ne(col, ex) => Eq(col, ex),
+        Lt(col, ex) => Ge(col, ex),
+        Gt(col, ex) => Le(col, ex),
+        Le(col, ex) => Gt(col, ex),
+        Ge(col, ex) => Lt(col, ex),
+        Compare(left, op, right) => {
+            use query::CompareOp;
+            let op = match op {
+                CompareOp::Eq => CompareOp::Ne,
+                CompareOp::Ne => CompareOp::Eq,
+                CompareOp::Lt => CompareOp::Ge,
+                CompareOp::Gt => CompareOp::Le,
+                CompareOp::Le => CompareOp::Gt,
+                CompareOp::Ge => CompareOp::Lt,
+                CompareOp::Like => return Err(Compare(left, op, right)),
+            };
+            Compare(left, op, right)
+        }
+        And(a, b) => Or(Box::new(a.not()), Box::new(b.not())),
+        Or(a, b) => And(Box::new(a.not()), Box::new(b.not())),
+        AllOf(conds) => query::BoolExpr::any_of(conds.into_iter().map(query::BoolExpr::not)),
+        expr => return Err(expr),
+    })
+}
+
 pub fn sql_select(columns: &[Column], table: &str, w: &mut impl Write) {
     write!(w, "SELECT ").unwrap();
     list_columns(columns, w);
     write!(w, " FROM {}", quote_reserved_word(table)).unwrap();
 }
 
+/// Writes to `w` the SQL of an UPDATE of `table` setting each column in
+/// `assignments` to its expression, in the rows for which `expr` is
+/// true. Values contained in the expressions are added to `values`.
+pub fn sql_update_where<F, P, W>(
+    table: &str,
+    assignments: Vec<(&'static str, Expr)>,
+    expr: query::BoolExpr,
+    f: F,
+    values: &mut Vec<SqlVal>,
+    pls: &mut P,
+    w: &mut W,
+) where
+    F: Fn(Expr, &mut Vec<SqlVal>, &mut P, &mut W),
+    W: Write,
+{
+    write!(w, "UPDATE {} SET ", quote_reserved_word(table)).unwrap();
+    let mut sep = "";
+    for (column, value) in assignments {
+        write!(w, "{sep}{} = ", quote_reserved_word(column)).unwrap();
+        f(value, values, pls, w);
+        sep = ", ";
+    }
+    write!(w, " WHERE ").unwrap();
+    f(Condition(Box::new(expr)), values, pls, w);
+}
+
 pub fn sql_insert_with_placeholders(
     table: &str,
     columns: &[Column],
@@ -169,6 +478,19 @@ pub fn sql_insert_with_placeholders(
     }
 }
 
+/// Writes to `w` the SQL of an INSERT to `table` of `rows` rows of
+/// `columns`, with `?` placeholders for the values.
+pub fn sql_insert_rows(table: &str, columns: &[Column], rows: usize, w: &mut impl Write) {
+    write!(w, "INSERT INTO {} (", quote_reserved_word(table)).unwrap();
+    list_columns(columns, w);
+    write!(w, ") VALUES ").unwrap();
+    let row = vec!["?"; columns.len()].join(", ");
+    for n in 0..rows {
+        let sep = if n == 0 { "" } else { ", " };
+        write!(w, "{sep}({row})").unwrap();
+    }
+}
+
 /// Writes to `w` the SQL of an UPDATE to `table` of `columns` using values in `pls`,
 /// for the row uniquely identified by `pkcol`.
 pub fn sql_update_with_placeholders(
@@ -207,17 +529,114 @@ pub fn sql_offset(offset: i32, w: &mut impl Write) {
     write!(w, " OFFSET {offset}").unwrap();
 }
 
-/// Writes to `w` the SQL of the list of column `order`.
-pub fn sql_order(order: &[Order], w: &mut impl Write) {
+/// Writes to `w` the ORDER BY clause for `order`. Values contained in
+/// the expressions ordered by are added to `values`.
+pub fn sql_order<F, P, W>(order: &[Order], f: F, values: &mut Vec<SqlVal>, pls: &mut P, w: &mut W)
+where
+    F: Fn(Expr, &mut Vec<SqlVal>, &mut P, &mut W),
+    W: Write,
+{
     write!(w, " ORDER BY ").unwrap();
-    order.iter().fold("", |sep, o| {
+    let mut sep = "";
+    for o in order {
         let sql_dir = match o.direction {
             OrderDirection::Ascending => "ASC",
             OrderDirection::Descending => "DESC",
         };
-        write!(w, "{}{} {}", sep, quote_reserved_word(o.column), sql_dir).unwrap();
-        ", "
-    });
+        w.write_str(sep).unwrap();
+        match &o.expr {
+            Some(expr) => f(expr.clone(), values, pls, w),
+            None => w.write_str(&quote_reserved_word(o.column)).unwrap(),
+        }
+        write!(w, " {sql_dir}").unwrap();
+        sep = ", ";
+    }
+}
+
+/// Return the SQL to create `table` as a view, if it is one.
+pub fn create_view(table: &ATable) -> Option<String> {
+    table.view.as_ref().map(|view| {
+        format!(
+            "CREATE VIEW {} AS {};",
+            quote_reserved_word(&table.name),
+            view.query.trim().trim_end_matches(';')
+        )
+    })
+}
+
+/// Return the SQL to create `index` on the table `tbl_name`.
+pub fn create_index(tbl_name: &str, index: &AIndex) -> String {
+    format!(
+        "CREATE {}INDEX {} ON {} ({});",
+        if index.unique { "UNIQUE " } else { "" },
+        quote_reserved_word(&index.name(tbl_name)),
+        quote_reserved_word(tbl_name),
+        index_columns(index)
+    )
+}
+
+/// Return the SQL listing the columns of `index`.
+pub fn index_columns(index: &AIndex) -> String {
+    index
+        .columns()
+        .map(quote_reserved_word)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether `index` is the unique index on the owner and value of the
+/// `Many` table `tbl_name` which already exists in `existing`. Before it
+/// is added, any rows which relate a value to an owner more than once,
+/// as were allowed without it, must be deleted.
+pub fn is_new_unique_many_index(existing: &ADB, tbl_name: &str, index: &AIndex) -> bool {
+    index.unique
+        && index.columns().eq(["owner", "has"])
+        && existing
+            .get_table(tbl_name)
+            .is_some_and(|table| table.column("owner").is_some() && table.column("has").is_some())
+}
+
+/// Return the SQL to drop `index` from the table `tbl_name`.
+pub fn drop_index(tbl_name: &str, index: &AIndex) -> String {
+    format!(
+        "DROP INDEX IF EXISTS {};",
+        quote_reserved_word(&index.name(tbl_name))
+    )
+}
+
+/// Return the SQL to drop the table `name` if it is a view in `current`.
+pub fn drop_view(current: &ADB, name: &str) -> Option<String> {
+    current
+        .get_table(name)
+        .filter(|table| table.is_view())
+        .map(|_| format!("DROP VIEW {};", quote_reserved_word(name)))
+}
+
+/// Return the `ON DELETE` clause for a column referring to another, if any.
+pub fn on_delete_clause(col: &AColumn) -> String {
+    match col.on_delete() {
+        Some(action) => format!(" ON DELETE {}", action.sql()),
+        None => String::new(),
+    }
+}
+
+/// Return the `COLLATE` constraint for a column with an explicit collation, if any.
+pub fn collate_constraint(col: &AColumn) -> Option<String> {
+    col.collation().map(|name| {
+        if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            format!("COLLATE {name}")
+        } else {
+            format!("COLLATE \"{name}\"")
+        }
+    })
+}
+
+/// Return the `DEFAULT` clause for a column with an explicit default, if any.
+pub fn default_clause(col: &AColumn) -> Result<String> {
+    match col.default() {
+        Some(val) => Ok(format!(" DEFAULT {}", sql_literal_value(val)?)),
+        None => Ok(String::new()),
+    }
 }
 
 /// Return column default.
@@ -236,6 +655,7 @@ pub fn column_default(col: &AColumn) -> Result<SqlVal> {
             SqlType::Real => SqlVal::Real(0.0),
             SqlType::Text => SqlVal::Text("".to_string()),
             SqlType::Blob => SqlVal::Blob(Vec::new()),
+            SqlType::TextList => SqlVal::TextList(Vec::new()),
             #[cfg(feature = "json")]
             SqlType::Json => SqlVal::Json(serde_json::Value::default()),
             #[cfg(feature = "datetime")]
@@ -250,20 +670,23 @@ pub fn column_default(col: &AColumn) -> Result<SqlVal> {
     })
 }
 
+/// Returns SQL setting the NULL values of `col` to its default, in a single statement.
+pub fn backfill_column(tbl_name: &str, col: &AColumn) -> Result<String> {
+    let default = column_default(col)?;
+    Ok(format!(
+        "UPDATE {} SET {col} = {} WHERE {col} IS NULL;",
+        quote_reserved_word(tbl_name),
+        sql_literal_value(&default)?,
+        col = quote_reserved_word(col.name()),
+    ))
+}
+
 /// Writes to `w` the SQL of the list of `columns`.
 pub fn list_columns(columns: &[Column], w: &mut impl Write) {
-    let mut colnames: Vec<&'static str> = Vec::new();
-    columns.iter().for_each(|c| colnames.push(c.name()));
-    write!(
-        w,
-        "{}",
-        colnames
-            .iter()
-            .map(|x| quote_reserved_word(x))
-            .collect::<Vec<Cow<str>>>()
-            .join(", ")
-    )
-    .unwrap();
+    columns.iter().fold("", |sep, c| {
+        write!(w, "{}{}", sep, quote_reserved_word(c.name())).unwrap();
+        ", "
+    });
 }
 
 fn sql_joins(joins: Vec<Join>, w: &mut impl Write) {
@@ -292,7 +715,7 @@ fn sql_column(col: query::Column, w: &mut impl Write) {
             quote_reserved_word(table),
             quote_reserved_word(col.name())
         ),
-        None => w.write_str(col.name()),
+        None => w.write_str(&quote_reserved_word(col.name())),
     }
     .unwrap()
 }
@@ -307,6 +730,10 @@ pub fn sql_literal_value(val: &SqlVal) -> Result<String> {
         Real(val) => Ok(val.to_string()),
         Text(val) => Ok(format!("'{val}'")),
         Blob(val) => Ok(format!("x'{}'", hex::encode_upper(val))),
+        TextList(val) => Ok(format!(
+            "'{}'",
+            serde_json::to_string(val)?.replace('\'', "''")
+        )),
         #[cfg(feature = "json")]
         Json(val) => Ok(format!("{val}")),
         #[cfg(feature = "datetime")]
@@ -316,3 +743,83 @@ pub fn sql_literal_value(val: &SqlVal) -> Result<String> {
         Custom(val) => Err(Error::LiteralForCustomUnsupported(*(*val).clone())),
     }
 }
+
+/// Splits the batch `sql` into its non-empty statements, without their
+/// terminating semicolons. A semicolon within a string literal, quoted
+/// identifier or comment, or within a `BEGIN ... END` block such as a
+/// trigger body, does not end a statement. If `sql` cannot be
+/// tokenized it is returned as a single statement.
+pub(crate) fn split_statements(sql: &str) -> Vec<&str> {
+    let Ok(tokens) = Tokenizer::new(&GenericDialect {}, sql).tokenize_with_location() else {
+        return vec![sql.trim()];
+    };
+    let mut ends = Vec::new();
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens[i + 1..]
+            .iter()
+            .map(|t| &t.token)
+            .find(|t| !matches!(t, Token::Whitespace(_)));
+        let next_word = match next {
+            Some(Token::Word(word)) => Some(word.value.to_ascii_uppercase()),
+            _ => None,
+        };
+        match &token.token {
+            Token::Word(word) if word.keyword == Keyword::CASE => depth += 1,
+            // Not `BEGIN;` or `BEGIN TRANSACTION`, which start a transaction
+            Token::Word(word)
+                if word.keyword == Keyword::BEGIN
+                    && !matches!(next, None | Some(Token::SemiColon))
+                    && !matches!(
+                        next_word.as_deref(),
+                        Some("TRANSACTION" | "WORK" | "DEFERRED" | "IMMEDIATE" | "EXCLUSIVE")
+                    ) =>
+            {
+                depth += 1
+            }
+            // `END IF` and the like close a block which did not open one
+            Token::Word(word)
+                if word.keyword == Keyword::END
+                    && !matches!(
+                        next_word.as_deref(),
+                        Some("IF" | "LOOP" | "WHILE" | "REPEAT")
+                    ) =>
+            {
+                depth = depth.saturating_sub(1)
+            }
+            Token::SemiColon if depth == 0 => ends.push(token.span.start),
+            _ => {}
+        }
+    }
+
+    // Find the byte offsets of the semicolons, counting lines and
+    // columns as the tokenizer does.
+    let mut offsets = Vec::with_capacity(ends.len());
+    let mut ends = ends.into_iter().peekable();
+    let (mut line, mut column) = (1, 1);
+    for (offset, c) in sql.char_indices() {
+        if ends
+            .next_if(|end| end.line == line && end.column == column)
+            .is_some()
+        {
+            offsets.push(offset);
+        }
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    let mut statements = Vec::with_capacity(offsets.len() + 1);
+    let mut start = 0;
+    for end in offsets.into_iter().chain(std::iter::once(sql.len())) {
+        let statement = sql[start..end].trim();
+        if !statement.is_empty() {
+            statements.push(statement);
+        }
+        start = end + 1;
+    }
+    statements
+}
@@ -5,6 +5,7 @@ macro_rules! connection_method_wrapper {
             idents(
                 Connection(sync = "Connection"),
                 ConnectionMethods(sync = "ConnectionMethods"),
+                DynConnection(sync = "DynConnection"),
                 Transaction(sync = "Transaction")
             ),
             sync(keep_self),
@@ -15,6 +16,39 @@ impl ConnectionMethods for $ty {
             async fn execute(&self, sql: &str) -> Result<()> {
                 ConnectionMethods::execute(self.wrapped_connection_methods()?, sql).await
             }
+            async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+                self.wrapped_connection_methods()?
+                    .execute_params(sql, params)
+                    .await
+            }
+            fn max_statement_params(&self) -> usize {
+                self.wrapped_connection_methods()
+                    .map_or($crate::db::MAX_STATEMENT_PARAMS, |conn| {
+                        conn.max_statement_params()
+                    })
+            }
+            fn in_transaction(&self) -> bool {
+                self.wrapped_connection_methods()
+                    .is_ok_and(|conn| conn.in_transaction())
+            }
+            fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+                if let Ok(conn) = self.wrapped_connection_methods() {
+                    conn.abandon_atomic(savepoint)
+                }
+            }
+            fn key_provider(&self) -> Option<&dyn $crate::encryption::KeyProvider> {
+                self.wrapped_key_provider()
+            }
+            async fn query_params<'c>(
+                &'c self,
+                sql: &str,
+                params: &[SqlValRef<'_>],
+                columns: &[Column],
+            ) -> Result<RawQueryResult<'c>> {
+                self.wrapped_connection_methods()?
+                    .query_params(sql, params, columns)
+                    .await
+            }
             async fn query<'c>(
                 &'c self,
                 table: &str,
@@ -28,6 +62,19 @@ async fn query<'c>(
                     .query(table, columns, expr, limit, offset, sort)
                     .await
             }
+            async fn query_counted<'c>(
+                &'c self,
+                table: &str,
+                columns: &[Column],
+                expr: Option<BoolExpr>,
+                limit: Option<i32>,
+                offset: Option<i32>,
+                sort: Option<&[$crate::query::Order]>,
+            ) -> Result<(RawQueryResult<'c>, u64)> {
+                self.wrapped_connection_methods()?
+                    .query_counted(table, columns, expr, limit, offset, sort)
+                    .await
+            }
             async fn insert_returning_pk(
                 &self,
                 table: &str,
@@ -39,6 +86,30 @@ async fn insert_returning_pk(
                     .insert_returning_pk(table, columns, pkcol, values)
                     .await
             }
+            async fn insert_returning(
+                &self,
+                table: &str,
+                columns: &[Column],
+                pkcol: &Column,
+                returning: &[Column],
+                values: &[SqlValRef<'_>],
+            ) -> Result<Vec<SqlVal>> {
+                self.wrapped_connection_methods()?
+                    .insert_returning(table, columns, pkcol, returning, values)
+                    .await
+            }
+            async fn insert_or_ignore(
+                &self,
+                table: &str,
+                columns: &[Column],
+                pkcol: &Column,
+                returning: &[Column],
+                values: &[SqlValRef<'_>],
+            ) -> Result<Option<Vec<SqlVal>>> {
+                self.wrapped_connection_methods()?
+                    .insert_or_ignore(table, columns, pkcol, returning, values)
+                    .await
+            }
             async fn insert_only(
                 &self,
                 table: &str,
@@ -82,9 +153,95 @@ async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
                     .delete_where(table, expr)
                     .await
             }
+            async fn delete_returning(
+                &self,
+                table: &str,
+                expr: BoolExpr,
+                returning: &[Column],
+            ) -> Result<Vec<Vec<SqlVal>>> {
+                self.wrapped_connection_methods()?
+                    .delete_returning(table, expr, returning)
+                    .await
+            }
+            async fn update_where(
+                &self,
+                table: &str,
+                assignments: Vec<(&'static str, Expr)>,
+                expr: BoolExpr,
+            ) -> Result<usize> {
+                self.wrapped_connection_methods()?
+                    .update_where(table, assignments, expr)
+                    .await
+            }
             async fn has_table(&self, table: &str) -> Result<bool> {
                 self.wrapped_connection_methods()?.has_table(table).await
             }
+            async fn list_tables(&self) -> Result<Vec<String>> {
+                self.wrapped_connection_methods()?.list_tables().await
+            }
+            async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+                self.wrapped_connection_methods()?.table_schema(table).await
+            }
+            async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+                self.wrapped_connection_methods()?.list_indexes(table).await
+            }
+            async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+                self.wrapped_connection_methods()?
+                    .notify(channel, payload)
+                    .await
+            }
+            async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+                self.wrapped_connection_methods()?
+                    .set_config(name, value, local)
+                    .await
+            }
+            async fn current_config(&self, name: &str) -> Result<Option<String>> {
+                self.wrapped_connection_methods()?
+                    .current_config(name)
+                    .await
+            }
+            async fn declare_cursor(
+                &self,
+                name: &str,
+                table: &str,
+                columns: &[Column],
+                expr: Option<BoolExpr>,
+                limit: Option<i32>,
+                offset: Option<i32>,
+                sort: Option<&[$crate::query::Order]>,
+            ) -> Result<()> {
+                self.wrapped_connection_methods()?
+                    .declare_cursor(name, table, columns, expr, limit, offset, sort)
+                    .await
+            }
+            async fn fetch_cursor<'c>(
+                &'c self,
+                name: &str,
+                columns: &[Column],
+                count: u32,
+            ) -> Result<RawQueryResult<'c>> {
+                self.wrapped_connection_methods()?
+                    .fetch_cursor(name, columns, count)
+                    .await
+            }
+            async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+                self.wrapped_connection_methods()?.blob_len(blob).await
+            }
+            async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+                self.wrapped_connection_methods()?
+                    .read_blob(blob, offset, len)
+                    .await
+            }
+            async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+                self.wrapped_connection_methods()?
+                    .write_blob(blob, offset, data)
+                    .await
+            }
+            async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+                self.wrapped_connection_methods()?
+                    .allocate_blob(blob, len)
+                    .await
+            }
         }
     };
 }
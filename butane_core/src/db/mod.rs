@@ -15,21 +15,28 @@
 #![allow(missing_docs)]
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs;
 use std::io::Write;
 use std::ops::{Deref, DerefMut};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
 
 use async_trait::async_trait;
 use dyn_clone::DynClone;
 use serde::{Deserialize, Serialize};
 
-use crate::query::{BoolExpr, Order};
-use crate::{migrations::adb, Error, Result, SqlVal, SqlValRef};
+use crate::encryption::{KeyProvider, Keys};
+use crate::notify::UpdateHook;
+use crate::partition::PartitionBounds;
+use crate::query::{BoolExpr, Expr, Order};
+use crate::{migrations::adb, DataObject, Error, Result, SqlVal, SqlValRef};
 
 #[cfg(feature = "async-adapter")]
 mod adapter;
+pub(crate) mod atomic;
 #[cfg(feature = "async-adapter")]
 pub use adapter::connect_async_via_sync;
 #[cfg(feature = "async")]
@@ -44,15 +51,25 @@
 #[cfg(feature = "async")]
 pub use connmethods::ConnectionMethodsAsync;
 pub use connmethods::{
-    BackendRow, BackendRows, Column, ConnectionMethods, MapDeref, QueryResult, RawQueryResult,
+    BackendRow, BackendRows, BlobRef, Column, ColumnSchema, ConnectionMethods, IndexSchema,
+    MapDeref, QueryResult, RawQueryResult, TableSchema,
 };
-mod helper;
+pub(crate) use connmethods::{VecRow, MAX_STATEMENT_PARAMS};
+pub(crate) mod helper;
 mod macros;
+#[cfg(feature = "odbc")]
+pub mod odbc;
 #[cfg(feature = "pg")]
 pub mod pg;
+mod retry;
+pub use retry::RetryPolicy;
 
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
+#[cfg(any(feature = "sqlite", feature = "sqlite-wasm"))]
+mod sqlite_dialect;
+#[cfg(feature = "sqlite-wasm")]
+pub mod sqlite_wasm;
 
 // Macros are always exported at the root of the crate
 use crate::connection_method_wrapper;
@@ -82,6 +99,12 @@ pub trait AsyncRequiresSync: Sync {}
     impl<T: Sync> AsyncRequiresSync for T {}
 }
 
+/// Rust function registered as an SQL function with
+/// [`create_function`](BackendConnection::create_function). It is called
+/// with the values of the arguments, and an error it returns fails the
+/// statement calling it.
+pub type ScalarFunction = Box<dyn Fn(&[SqlVal]) -> Result<SqlVal> + Send>;
+
 /// Database connection.
 #[maybe_async_cfg::maybe(
     idents(
@@ -103,6 +126,32 @@ pub trait BackendConnection: ConnectionMethods + Debug + Send {
     /// Tests if the connection has been closed. Backends which do not
     /// support this check should return false.
     fn is_closed(&self) -> bool;
+    /// Sets `hook` to be called for each row inserted, updated or deleted
+    /// through this connection, replacing any hook set before, or removes
+    /// the hook if `None`. Only supported by SQLite, which does not call
+    /// the hook for tables created `WITHOUT ROWID`, nor for rows deleted
+    /// by `DELETE` without a `WHERE` clause.
+    fn set_update_hook(&self, _hook: Option<UpdateHook>) -> Result<()> {
+        Err(Error::Unsupported(self.backend_name(), "update hooks"))
+    }
+    /// Registers `func` as an SQL function called `name`, which may
+    /// then be used in statements run through this connection, replacing
+    /// any function with the same name and number of arguments. The
+    /// function takes `n_args` arguments, or any number if `n_args` is
+    /// -1. Only supported by SQLite, which passes integer arguments as
+    /// [`SqlVal::BigInt`].
+    fn create_function(&self, _name: &str, _n_args: i32, _func: ScalarFunction) -> Result<()> {
+        Err(Error::Unsupported(
+            self.backend_name(),
+            "custom SQL functions",
+        ))
+    }
+    /// Starts listening for notifications sent on `channel`, returning a
+    /// stream of them. See [`crate::notify`].
+    #[maybe_async_cfg::only_if(key = "async")]
+    async fn listen(&self, _channel: &str) -> Result<crate::notify::NotificationStream> {
+        Err(Error::Unsupported(self.backend_name(), "notifications"))
+    }
 }
 
 #[maybe_async_cfg::maybe(
@@ -129,6 +178,16 @@ fn backend_name(&self) -> &'static str {
     fn is_closed(&self) -> bool {
         self.deref().is_closed()
     }
+    fn set_update_hook(&self, hook: Option<UpdateHook>) -> Result<()> {
+        self.deref().set_update_hook(hook)
+    }
+    fn create_function(&self, name: &str, n_args: i32, func: ScalarFunction) -> Result<()> {
+        self.deref().create_function(name, n_args, func)
+    }
+    #[maybe_async_cfg::only_if(key = "async")]
+    async fn listen(&self, channel: &str) -> Result<crate::notify::NotificationStream> {
+        self.deref().listen(channel).await
+    }
 }
 
 #[maybe_async_cfg::maybe(
@@ -145,6 +204,29 @@ impl ConnectionMethods for Box<dyn BackendConnection> {
     async fn execute(&self, sql: &str) -> Result<()> {
         self.deref().execute(sql).await
     }
+    async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.deref().execute_params(sql, params).await
+    }
+    fn max_statement_params(&self) -> usize {
+        self.deref().max_statement_params()
+    }
+    fn in_transaction(&self) -> bool {
+        self.deref().in_transaction()
+    }
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        self.deref().abandon_atomic(savepoint)
+    }
+    fn key_provider(&self) -> Option<&dyn KeyProvider> {
+        self.deref().key_provider()
+    }
+    async fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.deref().query_params(sql, params, columns).await
+    }
     async fn query<'c>(
         &'c self,
         table: &str,
@@ -158,6 +240,19 @@ async fn query<'c>(
             .query(table, columns, expr, limit, offset, sort)
             .await
     }
+    async fn query_counted<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<(RawQueryResult<'c>, u64)> {
+        self.deref()
+            .query_counted(table, columns, expr, limit, offset, sort)
+            .await
+    }
     async fn insert_returning_pk(
         &self,
         table: &str,
@@ -169,6 +264,30 @@ async fn insert_returning_pk(
             .insert_returning_pk(table, columns, pkcol, values)
             .await
     }
+    async fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.deref()
+            .insert_returning(table, columns, pkcol, returning, values)
+            .await
+    }
+    async fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.deref()
+            .insert_or_ignore(table, columns, pkcol, returning, values)
+            .await
+    }
     async fn insert_only(
         &self,
         table: &str,
@@ -201,9 +320,77 @@ async fn update(
     async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
         self.deref().delete_where(table, expr).await
     }
+    async fn delete_returning(
+        &self,
+        table: &str,
+        expr: BoolExpr,
+        returning: &[Column],
+    ) -> Result<Vec<Vec<SqlVal>>> {
+        self.deref().delete_returning(table, expr, returning).await
+    }
+    async fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.deref().update_where(table, assignments, expr).await
+    }
     async fn has_table(&self, table: &str) -> Result<bool> {
         self.deref().has_table(table).await
     }
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        self.deref().list_tables().await
+    }
+    async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.deref().table_schema(table).await
+    }
+    async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.deref().list_indexes(table).await
+    }
+    async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.deref().notify(channel, payload).await
+    }
+    async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.deref().set_config(name, value, local).await
+    }
+    async fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.deref().current_config(name).await
+    }
+    async fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[crate::query::Order]>,
+    ) -> Result<()> {
+        self.deref()
+            .declare_cursor(name, table, columns, expr, limit, offset, sort)
+            .await
+    }
+    async fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.deref().fetch_cursor(name, columns, count).await
+    }
+    async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.deref().blob_len(blob).await
+    }
+    async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.deref().read_blob(blob, offset, len).await
+    }
+    async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.deref().write_blob(blob, offset, data).await
+    }
+    async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.deref().allocate_blob(blob, len).await
+    }
 }
 
 /// Database connection. May be a connection to any type of database
@@ -216,6 +403,7 @@ async fn has_table(&self, table: &str) -> Result<bool> {
 #[derive(Debug)]
 pub struct Connection {
     conn: Box<dyn BackendConnection>,
+    keys: Keys,
 }
 
 #[maybe_async_cfg::maybe(
@@ -225,16 +413,48 @@ pub struct Connection {
 )]
 impl Connection {
     pub fn new(conn: Box<dyn BackendConnection>) -> Self {
-        Self { conn }
+        Self {
+            conn,
+            keys: Keys::default(),
+        }
+    }
+
+    /// Sets the key provider encrypting and decrypting the
+    /// [`Encrypted`](crate::encryption::Encrypted) fields of objects
+    /// saved and loaded through this connection and the transactions
+    /// begun from it, replacing any set before.
+    pub fn set_key_provider(&mut self, provider: impl KeyProvider + 'static) {
+        self.keys = Keys::new(provider);
     }
     pub async fn execute(&self, sql: impl AsRef<str>) -> Result<()> {
         self.conn.execute(sql.as_ref()).await
     }
+
+    /// Checks that the database is reachable by running a trivial statement.
+    ///
+    /// Fails with [`Error::ConnectionClosed`] if the session has been
+    /// dropped and cannot be re-established.
+    pub async fn ping(&self) -> Result<()> {
+        self.conn.execute("SELECT 1").await
+    }
+
+    /// Refreshes the materialized view backing the model `T`, declared with
+    /// `#[butane(view = "...", materialized)]`, with the current results of its query.
+    pub async fn refresh_materialized_view<T: DataObject>(&self) -> Result<()> {
+        match self.conn.backend().refresh_materialized_view_sql(T::TABLE) {
+            Some(sql) => self.conn.execute(&sql).await,
+            None => Ok(()),
+        }
+    }
     // For use with connection_method_wrapper macro.
     #[allow(clippy::unnecessary_wraps)]
     fn wrapped_connection_methods(&self) -> Result<&dyn BackendConnection> {
         Ok(self.conn.as_ref())
     }
+    // For use with connection_method_wrapper macro.
+    fn wrapped_key_provider(&self) -> Option<&dyn KeyProvider> {
+        self.keys.get()
+    }
 
     /// Consume this connection and convert it into an async one.
     /// Note that the under the hood this adds an adapter layer which runs
@@ -243,7 +463,10 @@ fn wrapped_connection_methods(&self) -> Result<&dyn BackendConnection> {
     #[maybe_async_cfg::only_if(key = "sync")]
     #[cfg(feature = "async-adapter")]
     pub fn into_async(self) -> Result<ConnectionAsync> {
-        Ok(adapter::AsyncAdapter::new(|| Ok(self))?.into_connection())
+        let keys = self.keys.clone();
+        let mut conn = adapter::AsyncAdapter::new(|| Ok(self))?.into_connection();
+        conn.keys = keys;
+        Ok(conn)
     }
 
     /// Runs the provided function with a synchronous wrapper around this asynchronous connection.
@@ -276,6 +499,120 @@ pub async fn with_sync<F, T>(&mut self, f: F) -> Result<T>
             Err(e) => Err(e),
         }
     }
+
+    /// Runs `f` within a new transaction, which is committed if `f`
+    /// returns `Ok` and rolled back if it returns `Err` or panics.
+    ///
+    /// Errors beginning or ending the transaction are converted into
+    /// the error type of `f`, so it may return either [`Error`] or an
+    /// application error which can be converted from it.
+    #[maybe_async_cfg::only_if(key = "sync")]
+    pub fn with_transaction<F, T, E>(&mut self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&Transaction<'_>) -> std::result::Result<T, E>,
+        E: From<Error>,
+    {
+        // If f panics, dropping the transaction rolls it back
+        let tx = self.transaction()?;
+        match f(&tx) {
+            Ok(val) => {
+                tx.commit()?;
+                Ok(val)
+            }
+            Err(e) => {
+                tx.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs the future returned by `f` within a new transaction, which
+    /// is committed if the future resolves to `Ok` and rolled back if it
+    /// resolves to `Err` or panics. The future borrows the transaction,
+    /// so must be boxed, as in
+    /// `conn.with_transaction(|tx| Box::pin(async move { ... }))`.
+    ///
+    /// Errors beginning or ending the transaction are converted into
+    /// the error type of `f`, so it may return either [`Error`] or an
+    /// application error which can be converted from it.
+    #[maybe_async_cfg::only_if(key = "async")]
+    pub async fn with_transaction<F, T, E>(&mut self, f: F) -> std::result::Result<T, E>
+    where
+        F: for<'t> FnOnce(
+            &'t TransactionAsync<'_>,
+        )
+            -> futures_util::future::BoxFuture<'t, std::result::Result<T, E>>,
+        E: From<Error>,
+    {
+        // If the future panics, dropping the transaction rolls it back
+        let tx = self.transaction().await?;
+        match f(&tx).await {
+            Ok(val) => {
+                tx.commit().await?;
+                Ok(val)
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Like [`with_transaction`](Self::with_transaction), but runs `f`
+    /// again in a new transaction, after a backoff delay, each time the
+    /// transaction fails with an error for which
+    /// [`Error::is_retryable`] is true, such as a serialization failure
+    /// or deadlock, until `policy` allows no more attempts. This is
+    /// required for correct use of the `SERIALIZABLE` isolation level,
+    /// which may be set by `f` before any other statement with
+    /// `SET TRANSACTION ISOLATION LEVEL SERIALIZABLE`.
+    #[maybe_async_cfg::only_if(key = "sync")]
+    pub fn transaction_with_retry<F, T>(&mut self, policy: &RetryPolicy, mut f: F) -> Result<T>
+    where
+        F: FnMut(&Transaction<'_>) -> Result<T>,
+    {
+        let mut retry = 0;
+        loop {
+            match self.with_transaction(&mut f) {
+                Err(e) if e.is_retryable() && retry + 1 < policy.max_attempts() => {
+                    std::thread::sleep(policy.delay(retry));
+                    retry += 1;
+                }
+                ret => return ret,
+            }
+        }
+    }
+
+    /// Like [`with_transaction`](Self::with_transaction), but runs `f`
+    /// again in a new transaction, after a backoff delay, each time the
+    /// transaction fails with an error for which
+    /// [`Error::is_retryable`] is true, such as a serialization failure
+    /// or deadlock, until `policy` allows no more attempts. This is
+    /// required for correct use of the `SERIALIZABLE` isolation level,
+    /// which may be set by `f` before any other statement with
+    /// `SET TRANSACTION ISOLATION LEVEL SERIALIZABLE`.
+    #[maybe_async_cfg::only_if(key = "async")]
+    pub async fn transaction_with_retry<F, T>(
+        &mut self,
+        policy: &RetryPolicy,
+        mut f: F,
+    ) -> Result<T>
+    where
+        F: for<'t> FnMut(
+            &'t TransactionAsync<'_>,
+        ) -> futures_util::future::BoxFuture<'t, Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match self.with_transaction(&mut f).await {
+                Err(e) if e.is_retryable() && retry + 1 < policy.max_attempts() => {
+                    tokio::time::sleep(policy.delay(retry)).await;
+                    retry += 1;
+                }
+                ret => return ret,
+            }
+        }
+    }
 }
 
 #[cfg(feature = "async")]
@@ -284,7 +621,10 @@ impl ConnectionAsync {
     /// Note that the under the hood this adds an adapter layer which drives
     /// the async connection  -- the async machinery is not eliminated.
     pub fn into_sync(self) -> Result<Connection> {
-        Ok(SyncAdapter::new(self)?.into_connection())
+        let keys = self.keys.clone();
+        let mut conn = SyncAdapter::new(self)?.into_connection();
+        conn.keys = keys;
+        Ok(conn)
     }
 }
 
@@ -300,7 +640,9 @@ pub fn into_sync(self) -> Result<Connection> {
 #[async_trait]
 impl BackendConnection for Connection {
     async fn transaction(&mut self) -> Result<Transaction<'_>> {
-        self.conn.transaction().await
+        let mut trans = self.conn.transaction().await?;
+        trans.keys = self.keys.clone();
+        Ok(trans)
     }
     fn backend(&self) -> Box<dyn Backend> {
         self.conn.backend()
@@ -311,6 +653,16 @@ fn backend_name(&self) -> &'static str {
     fn is_closed(&self) -> bool {
         self.conn.is_closed()
     }
+    fn set_update_hook(&self, hook: Option<UpdateHook>) -> Result<()> {
+        self.conn.set_update_hook(hook)
+    }
+    fn create_function(&self, name: &str, n_args: i32, func: ScalarFunction) -> Result<()> {
+        self.conn.create_function(name, n_args, func)
+    }
+    #[maybe_async_cfg::only_if(key = "async")]
+    async fn listen(&self, channel: &str) -> Result<crate::notify::NotificationStream> {
+        self.conn.listen(channel).await
+    }
 }
 connection_method_wrapper!(Connection);
 
@@ -348,6 +700,7 @@ pub(super) trait BackendTransaction<'c>:
 #[derive(Debug)]
 pub struct Transaction<'c> {
     pub(super) trans: Box<dyn BackendTransaction<'c> + 'c>,
+    keys: Keys,
 }
 
 #[maybe_async_cfg::maybe(
@@ -362,7 +715,10 @@ impl<'c> Transaction<'c> {
     // unused may occur if no backends are selected
     #[allow(unused)]
     pub(super) fn new(trans: Box<dyn BackendTransaction<'c> + 'c>) -> Self {
-        Transaction { trans }
+        Transaction {
+            trans,
+            keys: Keys::default(),
+        }
     }
     /// Commit the transaction.
     pub async fn commit(mut self) -> Result<()> {
@@ -378,10 +734,43 @@ fn wrapped_connection_methods(&self) -> Result<&dyn ConnectionMethods> {
         let a: &dyn BackendTransaction<'c> = self.trans.as_ref();
         Ok(a.connection_methods())
     }
+    // For use with connection_method_wrapper macro.
+    fn wrapped_key_provider(&self) -> Option<&dyn KeyProvider> {
+        self.keys.get()
+    }
 }
 
 connection_method_wrapper!(Transaction<'_>);
 
+/// A [`ConnectionMethods`] trait object, wrapped to be passed where a
+/// type implementing `ConnectionMethods` is expected, such as by
+/// [`AnyDataObject`](crate::AnyDataObject).
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(self = "DynConnection"),
+    async(feature = "async")
+)]
+pub(crate) struct DynConnection<'a>(pub(crate) &'a dyn ConnectionMethods);
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(keep_self),
+    async(feature = "async")
+)]
+impl DynConnection<'_> {
+    // For use with connection_method_wrapper macro.
+    #[allow(clippy::unnecessary_wraps)]
+    fn wrapped_connection_methods(&self) -> Result<&dyn ConnectionMethods> {
+        Ok(self.0)
+    }
+    // For use with connection_method_wrapper macro.
+    fn wrapped_key_provider(&self) -> Option<&dyn KeyProvider> {
+        self.0.key_provider()
+    }
+}
+
+connection_method_wrapper!(DynConnection<'_>);
+
 #[maybe_async_cfg::maybe(
     idents(
         BackendTransaction(sync = "BackendTransaction"),
@@ -439,6 +828,29 @@ impl<'bt> ConnectionMethods for Box<dyn BackendTransaction<'bt> + 'bt> {
     async fn execute(&self, sql: &str) -> Result<()> {
         self.deref().execute(sql).await
     }
+    async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.deref().execute_params(sql, params).await
+    }
+    fn max_statement_params(&self) -> usize {
+        self.deref().max_statement_params()
+    }
+    fn in_transaction(&self) -> bool {
+        self.deref().in_transaction()
+    }
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        self.deref().abandon_atomic(savepoint)
+    }
+    fn key_provider(&self) -> Option<&dyn KeyProvider> {
+        self.deref().key_provider()
+    }
+    async fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.deref().query_params(sql, params, columns).await
+    }
     async fn query<'c>(
         &'c self,
         table: &str,
@@ -452,6 +864,19 @@ async fn query<'c>(
             .query(table, columns, expr, limit, offset, sort)
             .await
     }
+    async fn query_counted<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<(RawQueryResult<'c>, u64)> {
+        self.deref()
+            .query_counted(table, columns, expr, limit, offset, sort)
+            .await
+    }
     async fn insert_returning_pk(
         &self,
         table: &str,
@@ -463,6 +888,30 @@ async fn insert_returning_pk(
             .insert_returning_pk(table, columns, pkcol, values)
             .await
     }
+    async fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.deref()
+            .insert_returning(table, columns, pkcol, returning, values)
+            .await
+    }
+    async fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.deref()
+            .insert_or_ignore(table, columns, pkcol, returning, values)
+            .await
+    }
     async fn insert_only(
         &self,
         table: &str,
@@ -495,9 +944,118 @@ async fn update(
     async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
         self.deref().delete_where(table, expr).await
     }
+    async fn delete_returning(
+        &self,
+        table: &str,
+        expr: BoolExpr,
+        returning: &[Column],
+    ) -> Result<Vec<Vec<SqlVal>>> {
+        self.deref().delete_returning(table, expr, returning).await
+    }
+    async fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.deref().update_where(table, assignments, expr).await
+    }
     async fn has_table(&self, table: &str) -> Result<bool> {
         self.deref().has_table(table).await
     }
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        self.deref().list_tables().await
+    }
+    async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.deref().table_schema(table).await
+    }
+    async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.deref().list_indexes(table).await
+    }
+    async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.deref().notify(channel, payload).await
+    }
+    async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.deref().set_config(name, value, local).await
+    }
+    async fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.deref().current_config(name).await
+    }
+    async fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[crate::query::Order]>,
+    ) -> Result<()> {
+        self.deref()
+            .declare_cursor(name, table, columns, expr, limit, offset, sort)
+            .await
+    }
+    async fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.deref().fetch_cursor(name, columns, count).await
+    }
+    async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.deref().blob_len(blob).await
+    }
+    async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.deref().read_blob(blob, offset, len).await
+    }
+    async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.deref().write_blob(blob, offset, data).await
+    }
+    async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.deref().allocate_blob(blob, len).await
+    }
+}
+
+/// Describes which features of SQL and of Butane a [`Backend`]
+/// supports, so that generic code can check for a feature rather than
+/// for the name of a backend. The default supports none of them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Whether `INSERT`, `UPDATE` and `DELETE` statements may return
+    /// values with a `RETURNING` clause.
+    pub returning: bool,
+    /// Whether the case-insensitive `ILIKE` operator is available.
+    pub ilike: bool,
+    /// Whether columns may have array types.
+    pub arrays: bool,
+    /// Whether queries may use common table expressions (`WITH`),
+    /// including the recursive ones built with [`Cte`](crate::query::Cte).
+    pub ctes: bool,
+    /// Whether indexes may be built without blocking writes to the
+    /// table, as with `#[butane(index(concurrently))]`.
+    pub concurrent_index: bool,
+    /// Whether savepoints may be created within a transaction.
+    pub savepoints: bool,
+    /// Whether [`notify`](crate::notify) and `listen` are supported.
+    pub notifications: bool,
+    /// Whether server-side [`Cursor`](crate::query::Cursor)s are supported.
+    pub cursors: bool,
+    /// Whether [`AutoPk`](crate::AutoPk) primary keys are supported.
+    pub auto_pk: bool,
+    /// Whether `LIMIT` and `OFFSET` are executed by the database.
+    /// When false, they are applied while reading the result rows.
+    pub server_side_limit: bool,
+    /// Whether upserts are performed in a single statement. When
+    /// false, an `UPDATE` is attempted first and followed by an
+    /// `INSERT` if no row matched.
+    pub native_upsert: bool,
+    /// Whether migrations may change existing columns.
+    pub alter_column: bool,
+    /// Whether named foreign key constraints can be dropped.
+    pub drop_constraints: bool,
+    /// Whether custom SQL types are supported.
+    pub custom_types: bool,
 }
 
 /// Database backend. A boxed implementation can be returned by name via [get_backend][crate::db::get_backend].
@@ -513,7 +1071,27 @@ pub trait Backend: Send + Sync + DynClone {
     /// This is not the same as the primary key of the table.
     /// It may be `None` if the backend does not support this.
     fn row_id_column(&self) -> Option<&'static str>;
+    /// The features supported by the backend.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
     fn create_migration_sql(&self, current: &adb::ADB, ops: Vec<adb::Operation>) -> Result<String>;
+    /// SQL to refresh the materialized view `view`, or `None` if the
+    /// backend creates materialized views as plain views, which are always
+    /// up to date.
+    fn refresh_materialized_view_sql(&self, _view: &str) -> Option<String> {
+        None
+    }
+    /// SQL to create a partition of a table partitioned with
+    /// `#[butane(partition_by = "...")]`. See [`crate::partition`].
+    fn create_partition_sql(
+        &self,
+        _table: &str,
+        _partition: &str,
+        _bounds: &PartitionBounds,
+    ) -> Result<String> {
+        Err(Error::Unsupported(self.name(), "table partitioning"))
+    }
     /// Establish a new sync connection.
     ///
     /// The format of the connection string is backend-dependent.
@@ -532,6 +1110,10 @@ pub trait Backend: Send + Sync + DynClone {
 /// Connection specification. Contains the name of a database backend
 /// and the backend-specific connection string. See [`connect`]
 /// to make a [`Connection`] from a `ConnectionSpec`.
+///
+/// When loaded, `${NAME}` in the connection string is replaced with the
+/// value of the environment variable `NAME`, so that credentials need not
+/// be saved with it.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ConnectionSpec {
     pub backend_name: String,
@@ -555,7 +1137,48 @@ pub fn save(&self, path: &Path) -> Result<()> {
     /// Load a previously saved connection spec.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
         let path = conn_complete_if_dir(path.as_ref());
-        serde_json::from_reader(fs::File::open(path)?).map_err(|e| e.into())
+        let spec: Self = serde_json::from_reader(fs::File::open(path)?)?;
+        spec.interpolate_env()
+    }
+    /// Save the connection spec for the named environment, such as `dev`
+    /// or `prod`, alongside those of other environments.
+    pub fn save_environment(&self, path: &Path, environment: &str) -> Result<()> {
+        let path = environments_complete_if_dir(path);
+        let mut environments = load_environments(&path)?.unwrap_or_default();
+        environments.insert(environment.to_string(), self.clone());
+        let mut f = fs::File::create(path)?;
+        let mut contents = serde_json::to_string_pretty(&environments)?;
+        contents.push('\n');
+        f.write_all(contents.as_bytes()).map_err(|e| e.into())
+    }
+    /// Load the connection spec previously saved for the named environment.
+    pub fn load_environment(path: impl AsRef<Path>, environment: &str) -> Result<Self> {
+        let path = environments_complete_if_dir(path.as_ref());
+        let mut environments = load_environments(&path)?
+            .ok_or_else(|| Error::UnknownEnvironment(environment.to_string()))?;
+        environments
+            .remove(environment)
+            .ok_or_else(|| Error::UnknownEnvironment(environment.to_string()))?
+            .interpolate_env()
+    }
+    /// Replace `${NAME}` in the connection string with the value of the
+    /// environment variable `NAME`.
+    fn interpolate_env(mut self) -> Result<Self> {
+        let mut conn_str = String::with_capacity(self.conn_str.len());
+        let mut rest = self.conn_str.as_str();
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..].find('}').ok_or_else(|| {
+                Error::UnknownConnectString(format!("unterminated ${{ in {}", self.conn_str))
+            })?;
+            let name = &rest[start + 2..start + end];
+            let value = std::env::var(name).map_err(|_| Error::MissingEnvVar(name.to_string()))?;
+            conn_str.push_str(&rest[..start]);
+            conn_str.push_str(&value);
+            rest = &rest[start + end + 1..];
+        }
+        conn_str.push_str(rest);
+        self.conn_str = conn_str;
+        Ok(self)
     }
     pub fn get_backend(&self) -> Result<Box<dyn Backend>> {
         match get_backend(&self.backend_name) {
@@ -694,6 +1317,24 @@ fn try_from(value: &String) -> Result<Self> {
     }
 }
 
+fn environments_complete_if_dir(path: &Path) -> Cow<'_, Path> {
+    if path.is_dir() {
+        Cow::from(path.join("environments.json"))
+    } else {
+        Cow::from(path)
+    }
+}
+
+/// Load the connection specs of each environment, or `None` if none have
+/// been saved.
+fn load_environments(path: &Path) -> Result<Option<BTreeMap<String, ConnectionSpec>>> {
+    match fs::File::open(path) {
+        Ok(f) => Ok(Some(serde_json::from_reader(f)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 fn conn_complete_if_dir(path: &Path) -> Cow<'_, Path> {
     if path.is_dir() {
         Cow::from(path.join("connection.json"))
@@ -710,9 +1351,23 @@ fn name(&self) -> &'static str {
     fn row_id_column(&self) -> Option<&'static str> {
         self.deref().row_id_column()
     }
+    fn capabilities(&self) -> Capabilities {
+        self.deref().capabilities()
+    }
     fn create_migration_sql(&self, current: &adb::ADB, ops: Vec<adb::Operation>) -> Result<String> {
         self.deref().create_migration_sql(current, ops)
     }
+    fn refresh_materialized_view_sql(&self, view: &str) -> Option<String> {
+        self.deref().refresh_materialized_view_sql(view)
+    }
+    fn create_partition_sql(
+        &self,
+        table: &str,
+        partition: &str,
+        bounds: &PartitionBounds,
+    ) -> Result<String> {
+        self.deref().create_partition_sql(table, partition, bounds)
+    }
     fn connect(&self, conn_str: &str) -> Result<Connection> {
         self.deref().connect(conn_str)
     }
@@ -722,13 +1377,127 @@ async fn connect_async(&self, conn_str: &str) -> Result<ConnectionAsync> {
     }
 }
 
-/// Find a backend by name.
+/// Whether errors include the values of parameters, see
+/// [`set_parameter_values_in_errors`].
+static PARAMETER_VALUES_IN_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the [`StatementContext`](crate::StatementContext)
+/// attached to errors from generated statements includes the values of
+/// their parameters, rather than only their types. Disabled by default,
+/// as errors are often logged and the values may be sensitive.
+pub fn set_parameter_values_in_errors(enabled: bool) {
+    PARAMETER_VALUES_IN_ERRORS.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn parameter_values_in_errors() -> bool {
+    PARAMETER_VALUES_IN_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Placeholder shown instead of the values of sensitive columns.
+pub const REDACTED: &str = "<redacted>";
+
+#[doc(hidden)]
+pub use inventory;
+
+/// A table with columns marked `#[butane(sensitive)]`, registered by
+/// `#[model]` so that statements on it are redacted before the model is
+/// first used. Semver exempt.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SensitiveTable(pub &'static str);
+
+inventory::collect!(SensitiveTable);
+
+/// Tables with columns marked `#[butane(sensitive)]` noted as their
+/// models are used, for targets on which the tables registered as a
+/// [`SensitiveTable`] are not collected, such as WebAssembly without
+/// its constructors run.
+static SENSITIVE_TABLES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Notes that statements on `table` may carry the values of `columns`,
+/// so that they are redacted if any of them are sensitive.
+pub(crate) fn note_sensitive_columns(table: &str, columns: &[Column]) {
+    if !columns.iter().any(Column::is_sensitive) || has_sensitive_columns(table) {
+        return;
+    }
+    let mut tables = SENSITIVE_TABLES.write().unwrap_or_else(|e| e.into_inner());
+    if !tables.iter().any(|t| t == table) {
+        tables.push(table.to_string());
+    }
+}
+
+/// Whether `table` has columns marked `#[butane(sensitive)]`. The
+/// parameters of statements on such a table are replaced by
+/// [`REDACTED`] in logs and errors, as are details of errors from the
+/// database which may quote them. Backends implemented outside Butane
+/// should do the same.
+pub fn has_sensitive_columns(table: &str) -> bool {
+    if inventory::iter::<SensitiveTable>
+        .into_iter()
+        .any(|sensitive| sensitive.0 == table)
+    {
+        return true;
+    }
+    SENSITIVE_TABLES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|t| t == table)
+}
+
+/// Formats the parameters of a statement on `table` for the debug log.
+#[cfg(feature = "debug")]
+#[cfg_attr(
+    not(any(
+        feature = "sqlite",
+        feature = "sqlite-wasm",
+        feature = "pg",
+        feature = "odbc"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn params_for_log(table: &str, params: &dyn Debug) -> String {
+    if has_sensitive_columns(table) {
+        REDACTED.to_string()
+    } else {
+        format!("{params:?}")
+    }
+}
+
+/// Backends added with [`register_backend`].
+static REGISTERED_BACKENDS: RwLock<Vec<Box<dyn Backend>>> = RwLock::new(Vec::new());
+
+/// Registers a backend, usually one implemented outside Butane, so that
+/// [`get_backend`], [`connect`] and [`connect_async`] find it by its
+/// [`name`](Backend::name), such as when given a [`ConnectionSpec`]
+/// loaded from a file. It replaces any backend with the same name,
+/// including those built into Butane.
+pub fn register_backend(backend: Box<dyn Backend>) {
+    let mut backends = REGISTERED_BACKENDS
+        .write()
+        .unwrap_or_else(|e| e.into_inner());
+    backends.retain(|b| b.name() != backend.name());
+    backends.push(backend);
+}
+
+/// Find a backend by name, either one registered with
+/// [`register_backend`] or one built into Butane.
 pub fn get_backend(name: &str) -> Option<Box<dyn Backend>> {
+    let backends = REGISTERED_BACKENDS
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(backend) = backends.iter().find(|b| b.name() == name) {
+        return Some(backend.clone());
+    }
     match name {
         #[cfg(feature = "sqlite")]
         sqlite::BACKEND_NAME => Some(Box::new(sqlite::SQLiteBackend::new())),
         #[cfg(feature = "pg")]
         pg::BACKEND_NAME => Some(Box::new(pg::PgBackend::new())),
+        #[cfg(feature = "odbc")]
+        odbc::BACKEND_NAME => Some(Box::new(odbc::OdbcBackend::new())),
+        #[cfg(feature = "sqlite-wasm")]
+        sqlite_wasm::BACKEND_NAME => Some(Box::new(sqlite_wasm::SQLiteWasmBackend::new())),
         _ => None,
     }
 }
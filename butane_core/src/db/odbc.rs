@@ -0,0 +1,1494 @@
+//! Generic ODBC database backend.
+//!
+//! This is a lowest-common-denominator backend for databases which
+//! Butane does not support natively. It speaks a conservative subset
+//! of SQL through the system ODBC driver manager, so anything which
+//! cannot be expressed portably is either emulated or rejected. See
+//! [`CAPABILITIES`] for the details.
+//!
+//! The connection string is handed to the driver manager unchanged,
+//! e.g. `Driver={MariaDB};Server=localhost;Database=test;Uid=user;Pwd=secret;`.
+use std::borrow::Cow;
+use std::fmt::{Debug, Write};
+
+use async_trait::async_trait;
+#[cfg(feature = "datetime")]
+use chrono::naive::{NaiveDate, NaiveDateTime};
+use odbc_api::parameter::{InputParameter, VarCharBox};
+use odbc_api::{ConnectionOptions, Cursor, CursorRow, IntoParameter};
+
+use super::connmethods::{VecRow, VecRows};
+#[cfg(feature = "async")]
+use super::ConnectionAsync;
+use super::MAX_STATEMENT_PARAMS;
+use super::{
+    helper, Backend, BlobRef, Capabilities, Column, IndexSchema, RawQueryResult, TableSchema,
+};
+use super::{BackendConnection, BackendTransaction, Connection, ConnectionMethods, Transaction};
+use crate::migrations::adb::{AColumn, ARef, ATable, Operation, TypeIdentifier, ADB};
+use crate::query::{BoolExpr, Expr, Order};
+use crate::{debug, query, warn, Error, Result, SqlType, SqlVal, SqlValRef};
+
+#[cfg(feature = "datetime")]
+const ODBC_DT_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+#[cfg(feature = "datetime")]
+const ODBC_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The name of the ODBC backend.
+pub const BACKEND_NAME: &str = "odbc";
+
+/// The capabilities of the ODBC backend: only what every SQL dialect
+/// supports. There is no portable way to create or read back a
+/// database-generated key, so [`AutoPk`](crate::AutoPk) is not supported.
+pub const CAPABILITIES: Capabilities = Capabilities {
+    returning: false,
+    ilike: false,
+    arrays: false,
+    ctes: false,
+    concurrent_index: false,
+    savepoints: false,
+    notifications: false,
+    cursors: false,
+    auto_pk: false,
+    server_side_limit: false,
+    native_upsert: false,
+    alter_column: false,
+    drop_constraints: false,
+    custom_types: false,
+};
+
+/// ODBC [`Backend`] implementation.
+#[derive(Debug, Default, Clone)]
+pub struct OdbcBackend;
+impl OdbcBackend {
+    pub fn new() -> OdbcBackend {
+        OdbcBackend {}
+    }
+}
+impl OdbcBackend {
+    fn connect(&self, conn_str: &str) -> Result<OdbcConnection> {
+        OdbcConnection::open(conn_str)
+    }
+}
+
+#[async_trait]
+impl Backend for OdbcBackend {
+    fn name(&self) -> &'static str {
+        BACKEND_NAME
+    }
+
+    fn row_id_column(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
+
+    fn create_migration_sql(&self, existing: &ADB, ops: Vec<Operation>) -> Result<String> {
+        let mut current: ADB = existing.clone();
+        let mut lines = ops
+            .into_iter()
+            .map(|o| {
+                if let Operation::AddIndex(tbl, index) = &o {
+                    if helper::is_new_unique_many_index(existing, tbl, index) {
+                        warn!(
+                            "Rows of {} relating a value more than once must be deleted before \
+                             its unique index is added, which the ODBC backend cannot do",
+                            tbl
+                        );
+                    }
+                }
+                let sql = sql_for_op(&current, &o);
+                current.transform_with(o);
+                sql
+            })
+            .collect::<Result<Vec<String>>>()?;
+        lines.retain(|s| !s.is_empty());
+        Ok(lines.join("\n"))
+    }
+
+    fn connect(&self, conn_str: &str) -> Result<Connection> {
+        Ok(Connection::new(Box::new(self.connect(conn_str)?)))
+    }
+
+    #[cfg(feature = "async-adapter")]
+    async fn connect_async(&self, conn_str: &str) -> Result<ConnectionAsync> {
+        super::adapter::connect_async_via_sync(self, conn_str).await
+    }
+
+    #[cfg(all(feature = "async", not(feature = "async-adapter")))]
+    async fn connect_async(&self, _conn_str: &str) -> Result<ConnectionAsync> {
+        Err(Error::NoAsyncAdapter(BACKEND_NAME))
+    }
+}
+
+/// ODBC database connection.
+pub struct OdbcConnection {
+    conn: odbc_api::Connection<'static>,
+}
+impl OdbcConnection {
+    fn open(conn_str: &str) -> Result<Self> {
+        let env = odbc_api::environment()?;
+        let conn = env.connect_with_connection_string(conn_str, ConnectionOptions::default())?;
+        Ok(OdbcConnection { conn })
+    }
+
+    // For use with connection_method_wrapper macro
+    #[allow(clippy::unnecessary_wraps)]
+    fn wrapped_connection_methods(&self) -> Result<&odbc_api::Connection<'static>> {
+        Ok(&self.conn)
+    }
+}
+impl Debug for OdbcConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OdbcConnection").finish_non_exhaustive()
+    }
+}
+
+impl ConnectionMethods for OdbcConnection {
+    fn execute(&self, sql: &str) -> Result<()> {
+        ConnectionMethods::execute(self.wrapped_connection_methods()?, sql)
+    }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.wrapped_connection_methods()?
+            .execute_params(sql, params)
+    }
+    fn max_statement_params(&self) -> usize {
+        self.wrapped_connection_methods()
+            .map_or(MAX_STATEMENT_PARAMS, |conn| conn.max_statement_params())
+    }
+    fn in_transaction(&self) -> bool {
+        self.wrapped_connection_methods()
+            .is_ok_and(|conn| conn.in_transaction())
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .query_params(sql, params, columns)
+    }
+    fn query<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .query(table, columns, expr, limit, offset, sort)
+    }
+    fn insert_returning_pk(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        values: &[SqlValRef<'_>],
+    ) -> Result<SqlVal> {
+        self.wrapped_connection_methods()?
+            .insert_returning_pk(table, columns, pkcol, values)
+    }
+    fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.wrapped_connection_methods()?
+            .insert_returning(table, columns, pkcol, returning, values)
+    }
+    fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.wrapped_connection_methods()?
+            .insert_or_ignore(table, columns, pkcol, returning, values)
+    }
+    fn insert_or_ignore_many(
+        &self,
+        table: &str,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .insert_or_ignore_many(table, columns, values)
+    }
+    fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .insert_only(table, columns, values)
+    }
+    fn insert_or_replace(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .insert_or_replace(table, columns, pkcol, values)
+    }
+    fn update(
+        &self,
+        table: &str,
+        pkcol: Column,
+        pk: SqlValRef<'_>,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .update(table, pkcol, pk, columns, values)
+    }
+    fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
+        self.wrapped_connection_methods()?.delete_where(table, expr)
+    }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.wrapped_connection_methods()?
+            .update_where(table, assignments, expr)
+    }
+    fn has_table(&self, table: &str) -> Result<bool> {
+        self.wrapped_connection_methods()?.has_table(table)
+    }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        self.wrapped_connection_methods()?.list_tables()
+    }
+    fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.wrapped_connection_methods()?.table_schema(table)
+    }
+    fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.wrapped_connection_methods()?.list_indexes(table)
+    }
+    fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.wrapped_connection_methods()?.notify(channel, payload)
+    }
+    fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .set_config(name, value, local)
+    }
+    fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.wrapped_connection_methods()?.current_config(name)
+    }
+    fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .declare_cursor(name, table, columns, expr, limit, offset, sort)
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .fetch_cursor(name, columns, count)
+    }
+    fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.wrapped_connection_methods()?.blob_len(blob)
+    }
+    fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.wrapped_connection_methods()?
+            .read_blob(blob, offset, len)
+    }
+    fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .write_blob(blob, offset, data)
+    }
+    fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.wrapped_connection_methods()?.allocate_blob(blob, len)
+    }
+}
+
+impl BackendConnection for OdbcConnection {
+    fn transaction(&mut self) -> Result<Transaction<'_>> {
+        let trans = Box::new(OdbcTransaction::new(&self.conn)?);
+        Ok(Transaction::new(trans))
+    }
+    fn backend(&self) -> Box<dyn Backend> {
+        Box::new(OdbcBackend {})
+    }
+    fn backend_name(&self) -> &'static str {
+        BACKEND_NAME
+    }
+    fn is_closed(&self) -> bool {
+        self.conn.is_dead().unwrap_or(false)
+    }
+}
+
+impl ConnectionMethods for odbc_api::Connection<'static> {
+    fn execute(&self, sql: &str) -> Result<()> {
+        if cfg!(feature = "log") {
+            debug!("execute sql {sql}");
+        }
+        // Drivers are not required to accept several statements at
+        // once, so they are sent one at a time.
+        for stmt in helper::split_statements(sql) {
+            odbc_api::Connection::execute(self, stmt, (), None)?;
+        }
+        Ok(())
+    }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        let sql = helper::translate_placeholders(sql, &mut OdbcPlaceholderSource::new());
+        if cfg!(feature = "log") {
+            debug!("execute sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {params:?}");
+        }
+        execute_counting(self, &sql, params.iter().cloned())
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        let sql = helper::translate_placeholders(sql, &mut OdbcPlaceholderSource::new());
+        if cfg!(feature = "log") {
+            debug!("query sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {params:?}");
+        }
+        let params = parameters(params.iter().cloned())?;
+        let mut rows: Vec<VecRow> = Vec::new();
+        let Some(mut cursor) = odbc_api::Connection::execute(self, &sql, params.as_slice(), None)?
+        else {
+            return Ok(Box::new(VecRows::new(rows)));
+        };
+        while let Some(mut row) = cursor.next_row()? {
+            rows.push(read_row(&mut row, columns)?);
+        }
+        Ok(Box::new(VecRows::new(rows)))
+    }
+
+    fn query<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        order: Option<&[Order]>,
+    ) -> Result<RawQueryResult<'c>> {
+        let mut sqlquery = String::new();
+        helper::sql_select(columns, table, &mut sqlquery);
+        let mut values: Vec<SqlVal> = Vec::new();
+        let mut pls = OdbcPlaceholderSource::new();
+        if let Some(expr) = expr {
+            sqlquery.write_str(" WHERE ").unwrap();
+            sql_for_expr(
+                query::Expr::Condition(Box::new(expr)),
+                &mut values,
+                &mut pls,
+                &mut sqlquery,
+            );
+        }
+
+        if let Some(order) = order {
+            helper::sql_order(order, sql_for_expr, &mut values, &mut pls, &mut sqlquery)
+        }
+
+        debug!("query sql {sqlquery}");
+        #[cfg(feature = "debug")]
+        debug!("values {}", super::params_for_log(table, &values));
+
+        let params = parameters(values.iter().map(SqlVal::as_ref))?;
+        let mut rows: Vec<VecRow> = Vec::new();
+        let Some(mut cursor) =
+            odbc_api::Connection::execute(self, &sqlquery, params.as_slice(), None).map_err(
+                |e| {
+                    Error::from(e).in_statement(&sqlquery, table, values.iter().map(SqlVal::as_ref))
+                },
+            )?
+        else {
+            return Ok(Box::new(VecRows::new(rows)));
+        };
+        // LIMIT and OFFSET are not portable, so they are applied here
+        // rather than by the database.
+        let mut skip = offset.unwrap_or(0).max(0);
+        let limit = limit.map(|l| l.max(0) as usize);
+        while limit.map_or(true, |l| rows.len() < l) {
+            let Some(mut row) = cursor.next_row()? else {
+                break;
+            };
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            rows.push(read_row(&mut row, columns)?);
+        }
+        Ok(Box::new(VecRows::new(rows)))
+    }
+    fn insert_returning_pk(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        values: &[SqlValRef<'_>],
+    ) -> Result<SqlVal> {
+        // Without a portable way to read back a generated key, the key
+        // must be one of the inserted values.
+        let pk = columns
+            .iter()
+            .position(|c| c.name() == pkcol.name())
+            .and_then(|idx| values.get(idx))
+            .ok_or(Error::Unsupported(
+                BACKEND_NAME,
+                "database-generated primary keys",
+            ))?;
+        let pk: SqlVal = pk.clone().into();
+        self.insert_only(table, columns, values)?;
+        Ok(pk)
+    }
+    fn insert_or_ignore(
+        &self,
+        _table: &str,
+        _columns: &[Column],
+        _pkcol: &Column,
+        _returning: &[Column],
+        _values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        // There is no portable syntax for ignoring conflicts
+        Err(Error::Unsupported(
+            BACKEND_NAME,
+            "ignoring insert conflicts",
+        ))
+    }
+    fn insert_or_ignore_many(
+        &self,
+        _table: &str,
+        _columns: &[Column],
+        _values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        Err(Error::Unsupported(
+            BACKEND_NAME,
+            "ignoring insert conflicts",
+        ))
+    }
+    fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
+        let mut sql = String::new();
+        helper::sql_insert_with_placeholders(
+            table,
+            columns,
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        if cfg!(feature = "log") {
+            debug!("insert sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("values {}", super::params_for_log(table, &values));
+        }
+        execute_generated(self, &sql, table, values.iter().cloned())?;
+        Ok(())
+    }
+    fn insert_or_replace(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        let pk_idx = columns
+            .iter()
+            .position(|c| c.name() == pkcol.name())
+            .ok_or_else(|| Error::Internal("primary key column missing from upsert".into()))?;
+        let (update_columns, update_values): (Vec<Column>, Vec<SqlValRef>) = columns
+            .iter()
+            .cloned()
+            .zip(values.iter().cloned())
+            .enumerate()
+            .filter(|(i, _)| *i != pk_idx)
+            .map(|(_, cv)| cv)
+            .unzip();
+        let pk = values[pk_idx].clone();
+        let exists = if update_columns.is_empty() {
+            // Nothing to update, only whether the row is already present matters.
+            let found = self.query(
+                table,
+                std::slice::from_ref(pkcol),
+                Some(BoolExpr::Eq(pkcol.name(), Expr::Val(pk.into()))),
+                Some(1),
+                None,
+                None,
+            )?;
+            let mut found = found;
+            found.next()?.is_some()
+        } else {
+            let mut sql = String::new();
+            helper::sql_update_with_placeholders(
+                table,
+                pkcol.clone(),
+                &update_columns,
+                &mut OdbcPlaceholderSource::new(),
+                &mut sql,
+            );
+            let placeholder_values = update_values.into_iter().chain(std::iter::once(pk));
+            execute_generated(self, &sql, table, placeholder_values)? > 0
+        };
+        if !exists {
+            self.insert_only(table, columns, values)?;
+        }
+        Ok(())
+    }
+    fn update(
+        &self,
+        table: &str,
+        pkcol: Column,
+        pk: SqlValRef,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        let mut sql = String::new();
+        helper::sql_update_with_placeholders(
+            table,
+            pkcol,
+            columns,
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        let placeholder_values = [values, &[pk]].concat();
+        if cfg!(feature = "log") {
+            debug!("update sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!(
+                "placeholders {}",
+                super::params_for_log(table, &placeholder_values)
+            );
+        }
+        execute_generated(self, &sql, table, placeholder_values)?;
+        Ok(())
+    }
+    fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
+        let mut sql = String::new();
+        let mut values: Vec<SqlVal> = Vec::new();
+        write!(
+            &mut sql,
+            "DELETE FROM {} WHERE ",
+            helper::quote_reserved_word(table)
+        )
+        .unwrap();
+        sql_for_expr(
+            query::Expr::Condition(Box::new(expr)),
+            &mut values,
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        if cfg!(feature = "log") {
+            debug!("delete where sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {}", super::params_for_log(table, &values));
+        }
+        execute_generated(self, &sql, table, values.iter().map(SqlVal::as_ref))
+    }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        let mut sql = String::new();
+        let mut values: Vec<SqlVal> = Vec::new();
+        helper::sql_update_where(
+            table,
+            assignments,
+            expr,
+            sql_for_expr,
+            &mut values,
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        if cfg!(feature = "log") {
+            debug!("update where sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {}", super::params_for_log(table, &values));
+        }
+        execute_generated(self, &sql, table, values.iter().map(SqlVal::as_ref))
+    }
+    fn has_table(&self, table: &str) -> Result<bool> {
+        let mut cursor = self.tables("", "", table, "TABLE")?;
+        Ok(cursor.next_row()?.is_some())
+    }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        Err(Error::Unsupported(BACKEND_NAME, "schema introspection"))
+    }
+    fn table_schema(&self, _table: &str) -> Result<Option<TableSchema>> {
+        Err(Error::Unsupported(BACKEND_NAME, "schema introspection"))
+    }
+    fn list_indexes(&self, _table: &str) -> Result<Vec<IndexSchema>> {
+        Err(Error::Unsupported(BACKEND_NAME, "schema introspection"))
+    }
+    fn notify(&self, _channel: &str, _payload: &str) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "notifications"))
+    }
+    fn set_config(&self, _name: &str, _value: &str, _local: bool) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "configuration parameters"))
+    }
+    fn current_config(&self, _name: &str) -> Result<Option<String>> {
+        Err(Error::Unsupported(BACKEND_NAME, "configuration parameters"))
+    }
+    fn declare_cursor(
+        &self,
+        _name: &str,
+        _table: &str,
+        _columns: &[Column],
+        _expr: Option<BoolExpr>,
+        _limit: Option<i32>,
+        _offset: Option<i32>,
+        _sort: Option<&[Order]>,
+    ) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "server-side cursors"))
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        _name: &str,
+        _columns: &[Column],
+        _count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        Err(Error::Unsupported(BACKEND_NAME, "server-side cursors"))
+    }
+    fn blob_len(&self, _blob: &BlobRef) -> Result<Option<u64>> {
+        Err(Error::Unsupported(BACKEND_NAME, "blob streaming"))
+    }
+    fn read_blob(&self, _blob: &BlobRef, _offset: u64, _len: usize) -> Result<Vec<u8>> {
+        Err(Error::Unsupported(BACKEND_NAME, "blob streaming"))
+    }
+    fn write_blob(&self, _blob: &BlobRef, _offset: u64, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "blob streaming"))
+    }
+    fn allocate_blob(&self, _blob: &BlobRef, _len: u64) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "blob streaming"))
+    }
+}
+
+/// Executes `sql` with the given parameter values and returns the number of affected rows.
+fn execute_counting<'a>(
+    conn: &odbc_api::Connection<'static>,
+    sql: &str,
+    values: impl IntoIterator<Item = SqlValRef<'a>>,
+) -> Result<usize> {
+    let params = parameters(values)?;
+    let mut stmt = conn.preallocate()?;
+    stmt.execute(sql, params.as_slice())?;
+    Ok(stmt.row_count()?.unwrap_or(0))
+}
+
+/// Executes the statement `sql` generated for `table`, attaching it to
+/// any error, and returns the number of affected rows.
+fn execute_generated<'a>(
+    conn: &odbc_api::Connection<'static>,
+    sql: &str,
+    table: &str,
+    values: impl IntoIterator<Item = SqlValRef<'a>> + Clone,
+) -> Result<usize> {
+    execute_counting(conn, sql, values.clone()).map_err(|e| e.in_statement(sql, table, values))
+}
+
+/// ODBC transactions are a mode of the connection rather than a
+/// separate object: autocommit is switched off for the lifetime of
+/// the transaction.
+struct OdbcTransaction<'c> {
+    conn: &'c odbc_api::Connection<'static>,
+    finished: bool,
+}
+impl<'c> OdbcTransaction<'c> {
+    fn new(conn: &'c odbc_api::Connection<'static>) -> Result<Self> {
+        conn.set_autocommit(false)?;
+        Ok(OdbcTransaction {
+            conn,
+            finished: false,
+        })
+    }
+    fn get(&self) -> Result<&'c odbc_api::Connection<'static>> {
+        if self.finished {
+            Err(Self::already_consumed())
+        } else {
+            Ok(self.conn)
+        }
+    }
+    fn wrapped_connection_methods(&self) -> Result<&odbc_api::Connection<'static>> {
+        self.get()
+    }
+    fn finish(&mut self, commit: bool) -> Result<()> {
+        let conn = self.get()?;
+        self.finished = true;
+        if commit {
+            conn.commit()?;
+        } else {
+            conn.rollback()?;
+        }
+        conn.set_autocommit(true)?;
+        Ok(())
+    }
+    fn already_consumed() -> Error {
+        Error::Internal("transaction has already been consumed".to_string())
+    }
+}
+impl Debug for OdbcTransaction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OdbcTransaction")
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
+}
+impl Drop for OdbcTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // Dropping an unfinished transaction rolls it back.
+            #[allow(unused_variables)] // used only when logging is enabled
+            if let Err(e) = self.finish(false) {
+                warn!("failed to roll back dropped ODBC transaction {}", e);
+            }
+        }
+    }
+}
+impl ConnectionMethods for OdbcTransaction<'_> {
+    fn execute(&self, sql: &str) -> Result<()> {
+        ConnectionMethods::execute(self.wrapped_connection_methods()?, sql)
+    }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.wrapped_connection_methods()?
+            .execute_params(sql, params)
+    }
+    fn max_statement_params(&self) -> usize {
+        self.wrapped_connection_methods()
+            .map_or(MAX_STATEMENT_PARAMS, |conn| conn.max_statement_params())
+    }
+    fn in_transaction(&self) -> bool {
+        !self.finished
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .query_params(sql, params, columns)
+    }
+    fn query<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .query(table, columns, expr, limit, offset, sort)
+    }
+    fn insert_returning_pk(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        values: &[SqlValRef<'_>],
+    ) -> Result<SqlVal> {
+        self.wrapped_connection_methods()?
+            .insert_returning_pk(table, columns, pkcol, values)
+    }
+    fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.wrapped_connection_methods()?
+            .insert_returning(table, columns, pkcol, returning, values)
+    }
+    fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.wrapped_connection_methods()?
+            .insert_or_ignore(table, columns, pkcol, returning, values)
+    }
+    fn insert_or_ignore_many(
+        &self,
+        table: &str,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .insert_or_ignore_many(table, columns, values)
+    }
+    fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .insert_only(table, columns, values)
+    }
+    fn insert_or_replace(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .insert_or_replace(table, columns, pkcol, values)
+    }
+    fn update(
+        &self,
+        table: &str,
+        pkcol: Column,
+        pk: SqlValRef<'_>,
+        columns: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .update(table, pkcol, pk, columns, values)
+    }
+    fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
+        self.wrapped_connection_methods()?.delete_where(table, expr)
+    }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.wrapped_connection_methods()?
+            .update_where(table, assignments, expr)
+    }
+    fn has_table(&self, table: &str) -> Result<bool> {
+        self.wrapped_connection_methods()?.has_table(table)
+    }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        self.wrapped_connection_methods()?.list_tables()
+    }
+    fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.wrapped_connection_methods()?.table_schema(table)
+    }
+    fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.wrapped_connection_methods()?.list_indexes(table)
+    }
+    fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.wrapped_connection_methods()?.notify(channel, payload)
+    }
+    fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .set_config(name, value, local)
+    }
+    fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.wrapped_connection_methods()?.current_config(name)
+    }
+    fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .declare_cursor(name, table, columns, expr, limit, offset, sort)
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .fetch_cursor(name, columns, count)
+    }
+    fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.wrapped_connection_methods()?.blob_len(blob)
+    }
+    fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.wrapped_connection_methods()?
+            .read_blob(blob, offset, len)
+    }
+    fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .write_blob(blob, offset, data)
+    }
+    fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.wrapped_connection_methods()?.allocate_blob(blob, len)
+    }
+}
+
+impl<'c> BackendTransaction<'c> for OdbcTransaction<'c> {
+    fn commit(&mut self) -> Result<()> {
+        self.finish(true)
+    }
+    fn rollback(&mut self) -> Result<()> {
+        self.finish(false)
+    }
+    // Workaround for https://github.com/rust-lang/rfcs/issues/2765
+    fn connection_methods(&self) -> &dyn ConnectionMethods {
+        self
+    }
+}
+
+/// Converts values to ODBC input parameters. Values without a
+/// portable binary representation are sent as text and left to the
+/// driver to convert.
+fn parameters<'a>(
+    values: impl IntoIterator<Item = SqlValRef<'a>>,
+) -> Result<Vec<Box<dyn InputParameter>>> {
+    values.into_iter().map(|v| parameter(&v)).collect()
+}
+
+fn parameter(valref: &SqlValRef<'_>) -> Result<Box<dyn InputParameter>> {
+    use SqlValRef::*;
+    Ok(match valref {
+        Null => Box::new(VarCharBox::null()),
+        Bool(b) => Box::new(*b as i16),
+        Int(i) => Box::new(*i),
+        BigInt(i) => Box::new(*i),
+        Real(r) => Box::new(*r),
+        Text(t) => Box::new(t.to_string().into_parameter()),
+        Blob(b) => Box::new(b.to_vec().into_parameter()),
+        TextList(v) => Box::new(serde_json::to_string(v)?.into_parameter()),
+        #[cfg(feature = "json")]
+        Json(v) => Box::new(serde_json::to_string(v)?.into_parameter()),
+        #[cfg(feature = "datetime")]
+        Date(date) => Box::new(date.format(ODBC_DATE_FORMAT).to_string().into_parameter()),
+        #[cfg(feature = "datetime")]
+        Timestamp(dt) => Box::new(dt.format(ODBC_DT_FORMAT).to_string().into_parameter()),
+        Custom(c) => return Err(Error::IncompatibleCustom(c.clone().into(), BACKEND_NAME)),
+    })
+}
+
+fn read_row(row: &mut CursorRow<'_>, columns: &[Column]) -> Result<VecRow> {
+    let mut buf: Vec<u8> = Vec::new();
+    let values = columns
+        .iter()
+        .enumerate()
+        .map(|(i, col)| {
+            let idx = (i + 1) as u16;
+            let present = if *col.ty() == SqlType::Blob {
+                row.get_binary(idx, &mut buf)?
+            } else {
+                row.get_text(idx, &mut buf)?
+            };
+            if present {
+                sql_val_from_odbc(&buf, col)
+            } else {
+                Ok(SqlVal::Null)
+            }
+        })
+        .collect::<Result<Vec<SqlVal>>>()?;
+    Ok(VecRow::from_values(values))
+}
+
+/// Interprets a value fetched from the driver, which is text for
+/// everything except blobs.
+fn sql_val_from_odbc(buf: &[u8], col: &Column) -> Result<SqlVal> {
+    let mismatch = |detail: String| Error::SqlResultTypeMismatch {
+        col: col.name().to_string(),
+        detail,
+    };
+    if *col.ty() == SqlType::Blob {
+        return Ok(SqlVal::Blob(buf.to_vec()));
+    }
+    let text = std::str::from_utf8(buf).map_err(|e| mismatch(e.to_string()))?;
+    let trimmed = text.trim();
+    Ok(match col.ty() {
+        SqlType::Bool => SqlVal::Bool(match trimmed {
+            "1" | "t" | "T" | "true" | "TRUE" => true,
+            "0" | "f" | "F" | "false" | "FALSE" => false,
+            other => return Err(mismatch(format!("{other} is not a boolean"))),
+        }),
+        SqlType::Int => SqlVal::Int(trimmed.parse().map_err(|_| mismatch(text.to_string()))?),
+        SqlType::BigInt => SqlVal::BigInt(trimmed.parse().map_err(|_| mismatch(text.to_string()))?),
+        SqlType::Real => SqlVal::Real(trimmed.parse().map_err(|_| mismatch(text.to_string()))?),
+        SqlType::Text => SqlVal::Text(text.to_string()),
+        SqlType::TextList => SqlVal::TextList(serde_json::from_str(text)?),
+        #[cfg(feature = "json")]
+        SqlType::Json => SqlVal::Json(serde_json::from_str(text)?),
+        #[cfg(feature = "datetime")]
+        SqlType::Date => SqlVal::Date(NaiveDate::parse_from_str(trimmed, ODBC_DATE_FORMAT)?),
+        #[cfg(feature = "datetime")]
+        SqlType::Timestamp => SqlVal::Timestamp(
+            NaiveDateTime::parse_from_str(trimmed, ODBC_DT_FORMAT)
+                .or_else(|_| NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%dT%H:%M:%S%.f"))?,
+        ),
+        SqlType::Blob => unreachable!(),
+        SqlType::Custom(v) => return Err(Error::IncompatibleCustomT(v.clone(), BACKEND_NAME)),
+    })
+}
+
+fn sql_for_expr<W>(
+    expr: query::Expr,
+    values: &mut Vec<SqlVal>,
+    pls: &mut OdbcPlaceholderSource,
+    w: &mut W,
+) where
+    W: Write,
+{
+    match expr {
+        // Boolean literals are not portable
+        Expr::Condition(c) => match *c {
+            BoolExpr::True => w.write_str("1 = 1").unwrap(),
+            BoolExpr::ListContains(col, vals) => sql_for_list(col, " AND ", vals, values, pls, w),
+            BoolExpr::ListOverlaps(col, vals) => sql_for_list(col, " OR ", vals, values, pls, w),
+            c => helper::sql_for_expr(Expr::Condition(Box::new(c)), sql_for_expr, values, pls, w),
+        },
+        _ => helper::sql_for_expr(expr, sql_for_expr, values, pls, w),
+    }
+}
+
+/// Writes the test of the list in `col` for each of `vals`, combined by
+/// `conjunction`.
+///
+/// Lists are stored as compact JSON arrays of strings, in which an
+/// unescaped quote preceded by `[` or `,` can only open an element. An
+/// element is therefore matched by `LIKE` on its JSON encoding between
+/// these delimiters, which needs nothing beyond standard SQL.
+fn sql_for_list<W>(
+    col: &str,
+    conjunction: &str,
+    vals: Vec<String>,
+    values: &mut Vec<SqlVal>,
+    pls: &mut OdbcPlaceholderSource,
+    w: &mut W,
+) where
+    W: Write,
+{
+    if vals.is_empty() {
+        // Every list contains no values, and none overlaps them
+        let sql = if conjunction == " AND " {
+            "1 = 1"
+        } else {
+            "1 = 0"
+        };
+        return w.write_str(sql).unwrap();
+    }
+    let col = helper::quote_reserved_word(col);
+    w.write_str("(").unwrap();
+    for (i, val) in vals.iter().enumerate() {
+        if i > 0 {
+            w.write_str(conjunction).unwrap();
+        }
+        let element = escape_like(&serde_json::to_string(val).unwrap());
+        w.write_str("(").unwrap();
+        for (j, (open, close)) in [("[", "]"), ("[", ","), (",", "]"), (",", ",")]
+            .into_iter()
+            .enumerate()
+        {
+            if j > 0 {
+                w.write_str(" OR ").unwrap();
+            }
+            write!(w, "{col} LIKE ").unwrap();
+            let pattern = format!("%{}{element}{}%", escape_like(open), escape_like(close));
+            sql_for_expr(Expr::Val(SqlVal::Text(pattern)), values, pls, w);
+            w.write_str(" ESCAPE '!'").unwrap();
+        }
+        w.write_str(")").unwrap();
+    }
+    w.write_str(")").unwrap();
+}
+
+/// Escapes the characters of `text` which `LIKE` treats specially in any
+/// of the common dialects, with `!` as the escape character.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '!' | '%' | '_' | '[') {
+            escaped.push('!');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn sql_for_op(current: &ADB, op: &Operation) -> Result<String> {
+    match op {
+        Operation::AddTable(table) => create_table(table, false),
+        Operation::AddTableConstraints(table) => create_table_fkey_constraints(table),
+        Operation::AddTableIfNotExists(table) => create_table(table, true),
+        Operation::RemoveTable(name) => {
+            Ok(helper::drop_view(current, name).unwrap_or_else(|| drop_table(name)))
+        }
+        Operation::RemoveTableConstraints(table) => {
+            if table.columns.iter().any(|c| c.reference().is_some()) {
+                warn!(
+                    "Foreign key constraints on {} cannot be removed by the ODBC backend",
+                    table.name
+                );
+            }
+            Ok(String::new())
+        }
+        Operation::RenameTable(from, to) => Ok(rename_table(from, to)),
+        Operation::RenameColumn(tbl, from, to) => Ok(rename_column(tbl, from, to)),
+        Operation::AddColumn(tbl, col) => add_column(tbl, col),
+        Operation::BackfillColumn(tbl, col) => helper::backfill_column(tbl, col),
+        Operation::RemoveColumn(tbl, name) => Ok(remove_column(tbl, name)),
+        Operation::ChangeColumn(_, _, _) => Err(Error::Unsupported(
+            BACKEND_NAME,
+            "changing existing columns",
+        )),
+        Operation::AddIndex(tbl, index) => Ok(helper::create_index(tbl, index)),
+        Operation::RemoveIndex(tbl, index) => Ok(helper::drop_index(tbl, index)),
+        // Comments are set differently by each database, if at all
+        Operation::SetTableComment(_, _) | Operation::SetColumnComment(_, _, _) => {
+            Ok(String::new())
+        }
+    }
+}
+
+fn create_table(table: &ATable, allow_exists: bool) -> Result<String> {
+    if let Some(sql) = helper::create_view(table) {
+        return Ok(sql);
+    }
+    let coldefs = table
+        .columns
+        .iter()
+        .map(|col| Ok(define_column(col)? + &helper::default_clause(col)?))
+        .collect::<Result<Vec<String>>>()?
+        .join(",\n");
+    let modifier = if allow_exists { "IF NOT EXISTS " } else { "" };
+    Ok(format!(
+        "CREATE TABLE {}{} (\n{}\n);",
+        modifier,
+        helper::quote_reserved_word(&table.name),
+        coldefs
+    ))
+}
+
+fn create_table_fkey_constraints(table: &ATable) -> Result<String> {
+    Ok(table
+        .columns
+        .iter()
+        .filter(|column| column.reference().is_some())
+        .map(|column| define_fkey_constraint(&table.name, column))
+        .collect::<Result<Vec<String>>>()?
+        .join("\n"))
+}
+
+fn define_column(col: &AColumn) -> Result<String> {
+    let mut constraints: Vec<String> = helper::collate_constraint(col).into_iter().collect();
+    if !col.nullable() {
+        constraints.push("NOT NULL".to_string());
+    }
+    if col.is_pk() {
+        constraints.push("PRIMARY KEY".to_string());
+    }
+    if col.unique() {
+        constraints.push("UNIQUE".to_string());
+    }
+    if constraints.is_empty() {
+        return Ok(format!(
+            "{} {}",
+            helper::quote_reserved_word(col.name()),
+            col_sqltype(col)?,
+        ));
+    }
+    Ok(format!(
+        "{} {} {}",
+        helper::quote_reserved_word(col.name()),
+        col_sqltype(col)?,
+        constraints.join(" ")
+    ))
+}
+
+fn define_fkey_constraint(table_name: &str, column: &AColumn) -> Result<String> {
+    let reference = column
+        .reference()
+        .as_ref()
+        .expect("must have a references value");
+    match reference {
+        ARef::Literal(literal) => Ok(format!(
+            "ALTER TABLE {} ADD FOREIGN KEY ({}) REFERENCES {}({}){};",
+            helper::quote_reserved_word(table_name),
+            helper::quote_reserved_word(column.name()),
+            helper::quote_reserved_word(literal.table_name()),
+            helper::quote_reserved_word(literal.column_name()),
+            helper::on_delete_clause(column),
+        )),
+        ARef::Deferred(_) => Err(Error::Unsupported(
+            BACKEND_NAME,
+            "foreign keys to unresolved types",
+        )),
+    }
+}
+
+fn col_sqltype(col: &AColumn) -> Result<Cow<'_, str>> {
+    if col.is_auto() {
+        return Err(Error::Unsupported(
+            BACKEND_NAME,
+            "database-generated primary keys",
+        ));
+    }
+    match col.typeid()? {
+        TypeIdentifier::Name(name) => Ok(Cow::Owned(name)),
+        TypeIdentifier::Ty(ty) => Ok(Cow::Borrowed(sqltype(&ty)?)),
+    }
+}
+
+fn sqltype(ty: &SqlType) -> Result<&'static str> {
+    Ok(match ty {
+        SqlType::Bool => "SMALLINT",
+        SqlType::Int => "INTEGER",
+        SqlType::BigInt => "BIGINT",
+        SqlType::Real => "DOUBLE PRECISION",
+        SqlType::Text => "VARCHAR(4000)",
+        SqlType::Blob => "VARBINARY(8000)",
+        SqlType::TextList => "VARCHAR(4000)",
+        #[cfg(feature = "json")]
+        SqlType::Json => "VARCHAR(4000)",
+        #[cfg(feature = "datetime")]
+        SqlType::Date => "DATE",
+        #[cfg(feature = "datetime")]
+        SqlType::Timestamp => "TIMESTAMP",
+        SqlType::Custom(c) => return Err(Error::IncompatibleCustomT(c.clone(), BACKEND_NAME)),
+    })
+}
+
+fn drop_table(name: &str) -> String {
+    format!("DROP TABLE {};", helper::quote_reserved_word(name))
+}
+
+fn rename_table(from: &str, to: &str) -> String {
+    // Case-insensitive databases may refuse a rename which only changes
+    // case, so go through a temporary name.
+    let tmp = format!("{to}__butane_tmp");
+    format!(
+        "ALTER TABLE {} RENAME TO {};\nALTER TABLE {} RENAME TO {};",
+        helper::quote_reserved_word(from),
+        helper::quote_reserved_word(&tmp),
+        helper::quote_reserved_word(&tmp),
+        helper::quote_reserved_word(to)
+    )
+}
+
+fn rename_column(tbl_name: &str, from: &str, to: &str) -> String {
+    format!(
+        "ALTER TABLE {} RENAME COLUMN {} TO {};",
+        helper::quote_reserved_word(tbl_name),
+        helper::quote_reserved_word(from),
+        helper::quote_reserved_word(to)
+    )
+}
+
+fn add_column(tbl_name: &str, col: &AColumn) -> Result<String> {
+    let default: SqlVal = helper::column_default(col)?;
+    let mut stmts = vec![format!(
+        "ALTER TABLE {} ADD {} DEFAULT {};",
+        helper::quote_reserved_word(tbl_name),
+        define_column(col)?,
+        helper::sql_literal_value(&default)?
+    )];
+    if col.reference().is_some() {
+        stmts.push(define_fkey_constraint(tbl_name, col)?);
+    }
+    Ok(stmts.join("\n"))
+}
+
+fn remove_column(tbl_name: &str, name: &str) -> String {
+    format!(
+        "ALTER TABLE {} DROP COLUMN {};",
+        helper::quote_reserved_word(tbl_name),
+        helper::quote_reserved_word(name)
+    )
+}
+
+#[derive(Debug)]
+struct OdbcPlaceholderSource;
+impl OdbcPlaceholderSource {
+    fn new() -> Self {
+        OdbcPlaceholderSource {}
+    }
+}
+impl helper::PlaceholderSource for OdbcPlaceholderSource {
+    fn next_placeholder(&mut self) -> Cow<'_, str> {
+        // ODBC placeholders are always a question mark.
+        Cow::Borrowed("?")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::adb::{DeferredSqlType, TypeKey};
+
+    fn read(text: &str, ty: SqlType) -> Result<SqlVal> {
+        sql_val_from_odbc(text.as_bytes(), &Column::new("col", ty))
+    }
+
+    #[test]
+    fn read_text_values() {
+        assert_eq!(read("1", SqlType::Bool).unwrap(), SqlVal::Bool(true));
+        assert_eq!(read("F", SqlType::Bool).unwrap(), SqlVal::Bool(false));
+        // Some drivers pad fixed-width numbers
+        assert_eq!(read(" 42 ", SqlType::Int).unwrap(), SqlVal::Int(42));
+        assert_eq!(
+            read("-9000000000", SqlType::BigInt).unwrap(),
+            SqlVal::BigInt(-9_000_000_000)
+        );
+        assert_eq!(read("0.25", SqlType::Real).unwrap(), SqlVal::Real(0.25));
+        // Text is returned as stored, without trimming
+        assert_eq!(
+            read(" padded ", SqlType::Text).unwrap(),
+            SqlVal::Text(" padded ".to_string())
+        );
+        assert_eq!(
+            read(r#"["a","b"]"#, SqlType::TextList).unwrap(),
+            SqlVal::TextList(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            sql_val_from_odbc(&[0, 159, 146], &Column::new("col", SqlType::Blob)).unwrap(),
+            SqlVal::Blob(vec![0, 159, 146])
+        );
+    }
+
+    #[cfg(feature = "datetime")]
+    #[test]
+    fn read_datetime_values() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(
+            read("2024-02-29", SqlType::Date).unwrap(),
+            SqlVal::Date(date)
+        );
+        let dt = date.and_hms_milli_opt(13, 14, 15, 500).unwrap();
+        for text in ["2024-02-29 13:14:15.5", "2024-02-29T13:14:15.500"] {
+            assert_eq!(
+                read(text, SqlType::Timestamp).unwrap(),
+                SqlVal::Timestamp(dt)
+            );
+        }
+    }
+
+    #[test]
+    fn read_mismatched_values() {
+        for (text, ty) in [
+            ("yes", SqlType::Bool),
+            ("4.5", SqlType::Int),
+            ("", SqlType::BigInt),
+            ("NaN?", SqlType::Real),
+        ] {
+            assert!(matches!(
+                read(text, ty),
+                Err(Error::SqlResultTypeMismatch { .. })
+            ));
+        }
+        assert!(matches!(
+            sql_val_from_odbc(&[0xff, 0xfe], &Column::new("col", SqlType::Text)),
+            Err(Error::SqlResultTypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn portable_sqltypes() {
+        assert_eq!(sqltype(&SqlType::Bool).unwrap(), "SMALLINT");
+        assert_eq!(sqltype(&SqlType::Int).unwrap(), "INTEGER");
+        assert_eq!(sqltype(&SqlType::BigInt).unwrap(), "BIGINT");
+        assert_eq!(sqltype(&SqlType::Real).unwrap(), "DOUBLE PRECISION");
+        assert_eq!(sqltype(&SqlType::Text).unwrap(), "VARCHAR(4000)");
+        assert_eq!(sqltype(&SqlType::Blob).unwrap(), "VARBINARY(8000)");
+        assert_eq!(sqltype(&SqlType::TextList).unwrap(), "VARCHAR(4000)");
+    }
+
+    #[test]
+    fn where_clause() {
+        let expr = BoolExpr::And(
+            Box::new(BoolExpr::True),
+            Box::new(BoolExpr::Eq("title", Expr::Val(SqlVal::Text("x".into())))),
+        );
+        let mut sql = String::new();
+        let mut values = Vec::new();
+        sql_for_expr(
+            Expr::Condition(Box::new(expr)),
+            &mut values,
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        assert_eq!(sql, "1 = 1 AND title = ?");
+        assert_eq!(values, vec![SqlVal::Text("x".into())]);
+    }
+
+    #[test]
+    fn list_filters() {
+        let mut sql = String::new();
+        let mut values = Vec::new();
+        sql_for_expr(
+            Expr::Condition(Box::new(BoolExpr::ListContains(
+                "tags",
+                vec!["a_b".to_string()],
+            ))),
+            &mut values,
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        assert_eq!(
+            sql,
+            "((tags LIKE ? ESCAPE '!' OR tags LIKE ? ESCAPE '!' \
+             OR tags LIKE ? ESCAPE '!' OR tags LIKE ? ESCAPE '!'))"
+        );
+        assert_eq!(
+            values,
+            vec![
+                SqlVal::Text(r#"%!["a!_b"]%"#.to_string()),
+                SqlVal::Text(r#"%!["a!_b",%"#.to_string()),
+                SqlVal::Text(r#"%,"a!_b"]%"#.to_string()),
+                SqlVal::Text(r#"%,"a!_b",%"#.to_string()),
+            ]
+        );
+
+        let mut sql = String::new();
+        sql_for_expr(
+            Expr::Condition(Box::new(BoolExpr::ListOverlaps("tags", Vec::new()))),
+            &mut Vec::new(),
+            &mut OdbcPlaceholderSource::new(),
+            &mut sql,
+        );
+        assert_eq!(sql, "1 = 0");
+    }
+
+    #[test]
+    fn unresolved_foreign_key() {
+        let mut column = AColumn::new_simple(
+            "author",
+            DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+        );
+        column.add_reference(&ARef::Deferred(DeferredSqlType::Deferred(TypeKey::PK(
+            "Author".to_string(),
+        ))));
+        assert!(matches!(
+            define_fkey_constraint("post", &column),
+            Err(Error::Unsupported(BACKEND_NAME, _))
+        ));
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            helper::translate_placeholders(
+                "SELECT a FROM t WHERE a = ? AND b = '?'",
+                &mut OdbcPlaceholderSource::new()
+            ),
+            "SELECT a FROM t WHERE a = ? AND b = '?'"
+        );
+    }
+}
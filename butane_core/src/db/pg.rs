@@ -1,39 +1,75 @@
 //! Postgresql database backend
 use std::borrow::Cow;
 use std::fmt::{Debug, Write};
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
 
 use async_trait::async_trait;
 use bytes::BufMut;
 #[cfg(feature = "datetime")]
 use chrono::{NaiveDate, NaiveDateTime};
 use futures_util::stream::StreamExt;
+use tokio::sync::mpsc;
 use tokio_postgres as postgres;
-use tokio_postgres::GenericClient;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::{AsyncMessage, GenericClient};
 
+use super::atomic;
 use super::connmethods::VecRows;
 use super::helper;
 use crate::custom::{SqlTypeCustom, SqlValRefCustom};
 use crate::db::{
     Backend, BackendConnectionAsync as BackendConnection, BackendRow,
-    BackendTransactionAsync as BackendTransaction, Column, Connection, ConnectionAsync,
-    ConnectionMethodsAsync as ConnectionMethods, RawQueryResult, SyncAdapter,
-    TransactionAsync as Transaction,
+    BackendTransactionAsync as BackendTransaction, BlobRef, Capabilities, Column, ColumnSchema,
+    Connection, ConnectionAsync, ConnectionMethodsAsync as ConnectionMethods, IndexSchema,
+    RawQueryResult, SyncAdapter, TableSchema, TransactionAsync as Transaction,
 };
-use crate::migrations::adb::{AColumn, ARef, ATable, Operation, TypeIdentifier, ADB};
+use crate::migrations::adb::{AColumn, AIndex, ARef, ATable, Operation, TypeIdentifier, ADB};
+use crate::migrations::{BATCHED_MARKER, OUTSIDE_TRANSACTION_MARKER};
+use crate::notify::{Notification, NotificationStream};
+use crate::partition::PartitionBounds;
 use crate::query::{BoolExpr, Expr};
-use crate::{debug, query, warn, Error, Result, SqlType, SqlVal, SqlValRef};
+use crate::{deadline, debug, query, warn, Error, Result, SqlType, SqlVal, SqlValRef};
 
 /// The name of the postgres backend.
 pub const BACKEND_NAME: &str = "pg";
 /// The internal row creation order field name.
 pub const ROW_ID_COLUMN_NAME: &str = "ctid";
+/// Number of rows updated by each statement when backfilling a column.
+const BACKFILL_BATCH_SIZE: usize = 10_000;
+/// Case-insensitive ICU collation used for columns with the `NOCASE`
+/// collation, which Postgres does not provide itself.
+const NOCASE_COLLATION: &str = "butane_nocase";
 
 /// Postgres [`Backend`] implementation.
 #[derive(Debug, Default, Clone)]
-pub struct PgBackend;
+pub struct PgBackend {
+    reconnect: bool,
+}
 impl PgBackend {
     pub fn new() -> PgBackend {
-        PgBackend {}
+        PgBackend::default()
+    }
+
+    /// Sets whether connections opened by this backend transparently
+    /// re-establish their session when the server has closed it, for
+    /// example after dropping an idle connection. Defaults to `false`.
+    ///
+    /// The reconnect happens before the next statement is sent, and
+    /// restores the latest value of each parameter set for the session,
+    /// by a `SET` statement run through
+    /// [`execute`](crate::db::ConnectionMethods::execute) or by
+    /// [`set_config`](crate::db::ConnectionMethods::set_config) without
+    /// `local`, outside of a transaction, unless it has since been `RESET`.
+    /// A statement which was in flight when the session
+    /// was lost is not retried and fails with [`Error::ConnectionClosed`].
+    /// Without reconnecting, every statement on a closed connection fails
+    /// with [`Error::ConnectionClosed`].
+    pub fn with_reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
     }
 }
 
@@ -47,16 +83,89 @@ fn row_id_column(&self) -> Option<&'static str> {
         Some(ROW_ID_COLUMN_NAME)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            returning: true,
+            ilike: true,
+            arrays: true,
+            ctes: true,
+            concurrent_index: true,
+            savepoints: true,
+            notifications: true,
+            cursors: true,
+            auto_pk: true,
+            server_side_limit: true,
+            native_upsert: true,
+            alter_column: true,
+            drop_constraints: true,
+            custom_types: true,
+        }
+    }
+
     fn create_migration_sql(&self, current: &ADB, ops: Vec<Operation>) -> Result<String> {
         let mut current: ADB = (*current).clone();
+        let backfilled: Vec<(&str, &str)> = ops
+            .iter()
+            .filter_map(|o| match o {
+                Operation::BackfillColumn(tbl, col) => Some((tbl.as_str(), col.name())),
+                _ => None,
+            })
+            .collect();
         let mut lines = ops
             .iter()
-            .map(|o| sql_for_op(&mut current, o))
+            .map(|o| match o {
+                Operation::AddColumn(tbl, col)
+                    if backfilled.contains(&(tbl.as_str(), col.name())) =>
+                {
+                    add_backfilled_column(&current, tbl, col)
+                }
+                _ => sql_for_op(&mut current, o),
+            })
             .collect::<Result<Vec<String>>>()?;
         lines.retain(|s| !s.is_empty());
         Ok(lines.join("\n"))
     }
 
+    fn refresh_materialized_view_sql(&self, view: &str) -> Option<String> {
+        Some(format!(
+            "REFRESH MATERIALIZED VIEW {};",
+            helper::quote_reserved_word(view)
+        ))
+    }
+
+    fn create_partition_sql(
+        &self,
+        table: &str,
+        partition: &str,
+        bounds: &PartitionBounds,
+    ) -> Result<String> {
+        let bounds = match bounds {
+            PartitionBounds::Range { from, to } => format!(
+                "FOR VALUES FROM ({}) TO ({})",
+                helper::sql_literal_value(from)?,
+                helper::sql_literal_value(to)?
+            ),
+            PartitionBounds::List(values) => format!(
+                "FOR VALUES IN ({})",
+                values
+                    .iter()
+                    .map(helper::sql_literal_value)
+                    .collect::<Result<Vec<String>>>()?
+                    .join(", ")
+            ),
+            PartitionBounds::Hash { modulus, remainder } => {
+                format!("FOR VALUES WITH (MODULUS {modulus}, REMAINDER {remainder})")
+            }
+            PartitionBounds::Default => "DEFAULT".to_string(),
+        };
+        Ok(format!(
+            "CREATE TABLE {} PARTITION OF {} {};",
+            helper::quote_reserved_word(partition),
+            helper::quote_reserved_word(table),
+            bounds
+        ))
+    }
+
     fn connect(&self, path: &str) -> Result<Connection> {
         debug!("Postgres connecting via sync adapter");
         let conn = SyncAdapter::new(self.clone())?.connect(path)?;
@@ -64,29 +173,224 @@ fn connect(&self, path: &str) -> Result<Connection> {
     }
 
     async fn connect_async(&self, path: &str) -> Result<ConnectionAsync> {
-        Ok(ConnectionAsync {
-            conn: Box::new(PgConnection::open(path).await?),
-        })
+        Ok(ConnectionAsync::new(Box::new(
+            PgConnection::open(path, self.reconnect).await?,
+        )))
+    }
+}
+
+/// A change to the session, replayed after reconnecting.
+#[derive(Clone)]
+enum SessionChange {
+    /// A statement run by `execute` or `listen`, such as `LISTEN`.
+    Statement(String),
+    /// The parameter `name` set by the statement `sql` run by `execute`.
+    Set { name: String, sql: String },
+    /// The parameter `name` set for the rest of the session by `set_config`.
+    Config { name: String, value: String },
+}
+
+impl SessionChange {
+    /// The parameter set by the change, if it sets one.
+    fn parameter(&self) -> Option<&str> {
+        match self {
+            SessionChange::Statement(_) => None,
+            SessionChange::Set { name, .. } | SessionChange::Config { name, .. } => Some(name),
+        }
+    }
+}
+
+/// A change to a parameter of the session made by a statement.
+#[derive(Debug, PartialEq)]
+enum ParameterChange {
+    /// `SET` the parameter.
+    Set(String),
+    /// `RESET` the parameter.
+    Reset(String),
+    /// `RESET ALL`.
+    ResetAll,
+}
+
+/// The change made by the single `SET` or `RESET` statement `sql` to a
+/// parameter of the session, named in lowercase as by `set_config`.
+fn parameter_change(sql: &str) -> Option<ParameterChange> {
+    let sql = sql.trim().trim_end_matches(';');
+    let mut words = sql
+        .split(|c: char| c.is_whitespace() || c == '=')
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase);
+    let command = words.next()?;
+    let mut first = words.next()?;
+    if command == "set" && first == "session" {
+        first = words.next()?;
+    }
+    // Parameters with a syntax of their own
+    let name = match (first.as_str(), words.next().as_deref()) {
+        ("time", Some("zone")) => "timezone".to_string(),
+        ("schema", _) => "search_path".to_string(),
+        ("names", _) => "client_encoding".to_string(),
+        ("xml", Some("option")) => "xmloption".to_string(),
+        ("authorization", _) => "session_authorization".to_string(),
+        ("characteristics", _) => "transaction_characteristics".to_string(),
+        _ => first.trim_matches('"').to_string(),
+    };
+    match command.as_str() {
+        "set" => Some(ParameterChange::Set(name)),
+        "reset" if name == "all" => Some(ParameterChange::ResetAll),
+        "reset" => Some(ParameterChange::Reset(name)),
+        _ => None,
+    }
+}
+
+/// Whether the statement `sql` begins a transaction block, `Some(true)`,
+/// or ends one, `Some(false)`, rather than a savepoint or neither.
+fn transaction_control(sql: &str) -> Option<bool> {
+    let mut words = sql
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_uppercase);
+    let command = words.next()?;
+    let mut next = words.next();
+    if matches!(next.as_deref(), Some("WORK" | "TRANSACTION")) {
+        next = words.next();
+    }
+    match (command.as_str(), next.as_deref()) {
+        (_, Some("PREPARED" | "TO")) => None,
+        ("BEGIN", _) => Some(true),
+        ("START", _) if sql.to_ascii_uppercase().contains("TRANSACTION") => Some(true),
+        ("COMMIT" | "END" | "ABORT" | "ROLLBACK", _) => Some(false),
+        _ => None,
+    }
+}
+
+/// Removes the recorded changes to the parameter `name` from `session`,
+/// or to every parameter if `None`.
+fn forget_parameter(session: &mut Vec<SessionChange>, name: Option<&str>) {
+    session.retain(|change| match (change.parameter(), name) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(parameter), Some(name)) => parameter != name,
+    });
+}
+
+/// Records in `session` the change made by the single statement
+/// `statement` to the session, if it makes one.
+fn record_session_change(session: &mut Vec<SessionChange>, statement: &str) {
+    let words: Vec<String> = statement
+        .split_whitespace()
+        .take(2)
+        .map(str::to_ascii_uppercase)
+        .collect();
+    let is_session_statement = match words.first().map(String::as_str) {
+        Some("RESET") => true,
+        Some("SET") => !matches!(
+            words.get(1).map(String::as_str),
+            Some("LOCAL" | "TRANSACTION")
+        ),
+        _ => false,
+    };
+    if !is_session_statement {
+        return;
+    }
+    // Only the latest value of each parameter need be replayed
+    match parameter_change(statement) {
+        Some(ParameterChange::Set(name)) => {
+            forget_parameter(session, Some(&name));
+            session.push(SessionChange::Set {
+                name,
+                sql: statement.to_string(),
+            });
+        }
+        Some(ParameterChange::Reset(name)) => forget_parameter(session, Some(&name)),
+        Some(ParameterChange::ResetAll) => forget_parameter(session, None),
+        None => session.push(SessionChange::Statement(statement.to_string())),
     }
 }
 
+/// Receivers of the notifications for each channel listened to.
+type Listeners = Arc<Mutex<Vec<(String, mpsc::UnboundedSender<Notification>)>>>;
+
 /// Pg database connection.
 pub struct PgConnection {
-    #[cfg(feature = "debug")]
     params: Box<str>,
-    client: postgres::Client,
+    client: RwLock<Arc<postgres::Client>>,
+    reconnect: bool,
+    /// Session changes to replay after reconnecting.
+    session: Mutex<Vec<SessionChange>>,
+    listeners: Listeners,
+    /// Whether the statement timeout of a deadline may still be set.
+    timeout_set: AtomicBool,
+    /// Statements undoing abandoned groups, run before the next statement.
+    abandoned: Mutex<String>,
+    /// Whether a transaction block has been begun with `BEGIN` by
+    /// `execute`, within which the session may not be replaced.
+    transaction_open: AtomicBool,
 }
 
 impl PgConnection {
-    async fn open(params: &str) -> Result<Self> {
-        let client = Self::connect(params).await?;
+    async fn open(params: &str, reconnect: bool) -> Result<Self> {
+        let listeners = Listeners::default();
+        let client = Self::connect(params, listeners.clone()).await?;
         Ok(Self {
-            #[cfg(feature = "debug")]
             params: params.into(),
-            client,
+            client: RwLock::new(Arc::new(client)),
+            reconnect,
+            session: Mutex::new(Vec::new()),
+            listeners,
+            timeout_set: AtomicBool::new(false),
+            abandoned: Mutex::default(),
+            transaction_open: AtomicBool::new(false),
         })
     }
-    async fn connect(params: &str) -> Result<postgres::Client> {
+    fn current_client(&self) -> Arc<postgres::Client> {
+        self.client
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+    /// Opens a new session if the server has closed the current one,
+    /// replaying the recorded session changes.
+    async fn reconnect_if_closed(&self) -> Result<()> {
+        if !self.current_client().is_closed() {
+            return Ok(());
+        }
+        if !self.reconnect {
+            return Err(Error::ConnectionClosed);
+        }
+        if self.transaction_open.load(Ordering::Acquire) {
+            // The statements of the transaction so far died with the
+            // session, so running the rest on a new one would lose them
+            warn!("Postgres connection closed within a transaction, not reconnecting");
+            return Err(Error::ConnectionClosed);
+        }
+        warn!("Postgres connection closed, reconnecting");
+        let client = Self::connect(&self.params, self.listeners.clone()).await?;
+        let session = self
+            .session
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        for change in session {
+            match change {
+                SessionChange::Statement(sql) | SessionChange::Set { sql, .. } => {
+                    client.batch_execute(&sql).await?
+                }
+                SessionChange::Config { name, value } => {
+                    let params: &[&DynToSqlPg] = &[&name, &value];
+                    let future = client.execute("SELECT set_config($1, $2, false)", params);
+                    future.await?;
+                }
+            }
+        }
+        *self.client.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(client);
+        // Nothing abandoned survives the old session
+        self.abandoned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        Ok(())
+    }
+    async fn connect(params: &str, listeners: Listeners) -> Result<postgres::Client> {
         cfg_if::cfg_if! {
             if #[cfg(feature = "tls")] {
                 let connector = native_tls::TlsConnector::new()?;
@@ -95,38 +399,125 @@ async fn connect(params: &str) -> Result<postgres::Client> {
                 let connector = postgres::NoTls;
             }
         }
-        let (client, conn) = postgres::connect(params, connector).await?;
+        let (client, mut conn) = postgres::connect(params, connector).await?;
         tokio::spawn(async move {
-            #[allow(unused_variables)] // used only when logging is enabled
-            if let Err(e) = conn.await {
-                warn!("Postgres connection error {}", e);
+            let mut messages = futures_util::stream::poll_fn(move |cx| conn.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(n)) => {
+                        let notification = Notification {
+                            channel: n.channel().to_string(),
+                            payload: n.payload().to_string(),
+                            process_id: n.process_id(),
+                        };
+                        let mut listeners =
+                            listeners.lock().unwrap_or_else(PoisonError::into_inner);
+                        // Listeners whose stream has been dropped are removed
+                        listeners.retain(|(channel, sender)| {
+                            *channel != notification.channel
+                                || sender.send(notification.clone()).is_ok()
+                        });
+                    }
+                    #[allow(unused_variables)] // used only when logging is enabled
+                    Ok(AsyncMessage::Notice(notice)) => {
+                        debug!("Postgres notice {}", notice);
+                    }
+                    Ok(_) => {}
+                    #[allow(unused_variables)] // used only when logging is enabled
+                    Err(e) => {
+                        warn!("Postgres connection error {}", e);
+                        break;
+                    }
+                }
             }
         });
         Ok(client)
     }
 }
+#[async_trait]
 impl PgConnectionLike for PgConnection {
     type Client = postgres::Client;
-    fn client(&self) -> Result<&Self::Client> {
-        Ok(&self.client)
+    type ClientRef<'a> = Arc<postgres::Client>;
+    fn client(&self) -> Result<Self::ClientRef<'_>> {
+        Ok(self.current_client())
+    }
+    async fn ensure_open(&self) -> Result<()> {
+        self.reconnect_if_closed().await
+    }
+    fn timeout_set(&self) -> &AtomicBool {
+        &self.timeout_set
+    }
+    fn abandoned(&self) -> &Mutex<String> {
+        &self.abandoned
+    }
+    fn in_transaction(&self) -> bool {
+        self.transaction_open.load(Ordering::Acquire)
+    }
+    fn executed(&self, sql: &str) {
+        // Only the statements which change the session are replayed, not
+        // the rest of their batch
+        for statement in helper::split_statements(sql) {
+            if let Some(open) = transaction_control(statement) {
+                self.transaction_open.store(open, Ordering::Release);
+                continue;
+            }
+            let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+            record_session_change(&mut session, statement);
+        }
+    }
+    fn configured(&self, name: &str, value: &str, local: bool) {
+        if local {
+            return;
+        }
+        let name = name.to_ascii_lowercase();
+        let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        forget_parameter(&mut session, Some(&name));
+        session.push(SessionChange::Config {
+            name,
+            value: value.to_string(),
+        });
     }
 }
 
 #[async_trait]
 impl BackendConnection for PgConnection {
     async fn transaction(&mut self) -> Result<Transaction<'_>> {
-        let trans: postgres::Transaction<'_> = self.client.transaction().await?;
+        self.reconnect_if_closed().await?;
+        let client = self
+            .client
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let client = Arc::get_mut(client).ok_or_else(|| {
+            Error::Internal("postgres client is in use by another operation".to_string())
+        })?;
+        let trans: postgres::Transaction<'_> = client.transaction().await?;
         let trans = Box::new(PgTransaction::new(trans));
         Ok(Transaction::new(trans))
     }
     fn backend(&self) -> Box<dyn Backend> {
-        Box::new(PgBackend {})
+        Box::new(PgBackend {
+            reconnect: self.reconnect,
+        })
     }
     fn backend_name(&self) -> &'static str {
         BACKEND_NAME
     }
     fn is_closed(&self) -> bool {
-        self.client.is_closed()
+        self.current_client().is_closed()
+    }
+    async fn listen(&self, channel: &str) -> Result<NotificationStream> {
+        let sql = format!("LISTEN \"{}\"", channel.replace('"', "\"\""));
+        self.execute(&sql).await?;
+        self.session
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(SessionChange::Statement(sql));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((channel.to_string(), sender));
+        Ok(NotificationStream::new(receiver))
     }
 }
 impl Debug for PgConnection {
@@ -136,6 +527,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         d.field("params", &self.params);
         // postgres::Client doesnt expose any internal state
         d.field("conn", &!self.is_closed());
+        d.field("reconnect", &self.reconnect);
         d.finish()
     }
 }
@@ -150,11 +542,249 @@ fn sqlvalref_for_pg_query<'a>(v: &'a SqlValRef<'a>) -> &'a dyn postgres::types::
     v as &dyn postgres::types::ToSql
 }
 
+/// Runs `op`, bounded by the [`deadline`] in effect if any.
+///
+/// The statement timeout is set to the time remaining for the
+/// duration of `op`, so that the server stops work which the caller
+/// will no longer wait for. If `op` is cancelled before the timeout is
+/// reset, it is reset before the next operation instead.
+async fn bounded<C, U>(conn: &C, op: impl Future<Output = Result<U>>) -> Result<U>
+where
+    C: PgConnectionLike + Sync,
+{
+    conn.ensure_open().await?;
+    let remaining = deadline::remaining()?;
+    let client = conn.client()?;
+    undo_abandoned(conn, &*client).await;
+    let Some(remaining) = remaining else {
+        if conn.timeout_set().load(Ordering::Acquire) {
+            reset_timeout(conn, &*client).await?;
+        }
+        return op.await.map_err(closed_error);
+    };
+    let sql = format!("SET statement_timeout = {}", remaining.as_millis().max(1));
+    conn.timeout_set().store(true, Ordering::Release);
+    let future = client.batch_execute(&sql);
+    future.await.map_err(|e| closed_error(e.into()))?;
+    let result = op.await.map_err(|e| match e.root() {
+        Error::Postgres(e) if e.code() == Some(&SqlState::QUERY_CANCELED) => {
+            Error::DeadlineExceeded
+        }
+        _ => closed_error(e),
+    });
+    let reset = reset_timeout(conn, &*client).await;
+    let value = result?;
+    reset?;
+    Ok(value)
+}
+
+/// Runs the statements undoing the groups abandoned on `conn`. Failures
+/// are only logged, as the groups may have ended after all, such as when
+/// cancelled while ending them.
+async fn undo_abandoned<C>(conn: &C, client: &C::Client)
+where
+    C: PgConnectionLike + Sync,
+{
+    let sql = conn
+        .abandoned()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if sql.is_empty() {
+        return;
+    }
+    let future = client.batch_execute(&sql);
+    #[allow(unused_variables)] // used only when logging is enabled
+    if let Err(e) = future.await {
+        warn!("failed to roll back abandoned statements: {}", e);
+    }
+    conn.abandoned()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .drain(..sql.len());
+}
+
+/// Resets the statement timeout set by `bounded`.
+async fn reset_timeout<C>(conn: &C, client: &C::Client) -> Result<()>
+where
+    C: PgConnectionLike + Sync,
+{
+    let future = client.batch_execute("RESET statement_timeout");
+    future.await.map_err(|e| closed_error(e.into()))?;
+    conn.timeout_set().store(false, Ordering::Release);
+    Ok(())
+}
+
+/// Maps errors caused by the server closing the session to
+/// [`Error::ConnectionClosed`].
+fn closed_error(e: Error) -> Error {
+    match e.root() {
+        Error::Postgres(e) if e.is_closed() => Error::ConnectionClosed,
+        _ => e,
+    }
+}
+
+/// Converts an offset or length within a blob to the integer type
+/// taken by the large object functions.
+fn blob_offset(n: u64) -> Result<i64> {
+    i64::try_from(n).map_err(|_| Error::Internal("blob too large".to_string()))
+}
+
+/// Returns the SQL selecting `expr` for the large object referred to by
+/// `blob`, whose oid is `lo` in `expr` and 0 if there is none. The
+/// primary key of the row is the first parameter.
+fn large_object_sql(blob: &BlobRef, expr: &str) -> Result<String> {
+    if !blob.large_object {
+        return Err(Error::Unsupported(
+            BACKEND_NAME,
+            "streaming bytea blobs, rather than large objects",
+        ));
+    }
+    Ok(format!(
+        "SELECT {expr} FROM (SELECT {}::oid AS lo FROM {} WHERE {} = $1) AS blob;",
+        helper::quote_reserved_word(blob.column),
+        helper::quote_reserved_word(blob.table),
+        helper::quote_reserved_word(blob.pkcol)
+    ))
+}
+
 /// Shared functionality between connection and
 /// transaction. Implementation detail. Semver exempt.
+#[async_trait]
 trait PgConnectionLike {
-    type Client: postgres::GenericClient + Send;
-    fn client(&self) -> Result<&Self::Client>;
+    type Client: postgres::GenericClient + Send + Sync;
+    type ClientRef<'a>: Deref<Target = Self::Client> + Send
+    where
+        Self: 'a;
+    fn client(&self) -> Result<Self::ClientRef<'_>>;
+    /// Re-establishes the session if it has been closed and this is
+    /// supported, or fails with [`Error::ConnectionClosed`].
+    async fn ensure_open(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Whether `bounded` may have left the statement timeout set, such
+    /// as when the operation was cancelled before it could reset it.
+    fn timeout_set(&self) -> &AtomicBool;
+    /// Statements undoing the groups which cancelled operations
+    /// abandoned, run by `bounded` before the next statement.
+    fn abandoned(&self) -> &Mutex<String>;
+    /// Called after `sql` has been run successfully by `execute`.
+    fn executed(&self, _sql: &str) {}
+    /// Called after `set_config` has set the parameter `name` to `value`.
+    fn configured(&self, _name: &str, _value: &str, _local: bool) {}
+    /// Whether this is a transaction.
+    fn in_transaction(&self) -> bool {
+        false
+    }
+}
+
+/// Runs the INSERT statement `sql`, returning the primary key and
+/// `returning` columns of the inserted row, or `None` if no row was
+/// inserted.
+async fn insert_returning_row<C>(
+    conn: &C,
+    table: &str,
+    mut sql: String,
+    pkcol: &Column,
+    returning: &[Column],
+    values: &[SqlValRef<'_>],
+) -> Result<Option<Vec<SqlVal>>>
+where
+    C: PgConnectionLike + Sync,
+{
+    write!(
+        &mut sql,
+        " RETURNING {}",
+        helper::quote_reserved_word(pkcol.name())
+    )
+    .unwrap();
+    for col in returning {
+        write!(&mut sql, ", {}", helper::quote_reserved_word(col.name())).unwrap();
+    }
+    if cfg!(feature = "log") {
+        debug!("insert sql {sql}");
+    }
+
+    // use query instead of execute so we can get our result back
+    let in_statement = |e: Error| e.in_statement(&sql, table, values.iter().cloned());
+    let client = conn.client()?;
+    let future = client.query_raw(sql.as_str(), values.iter().map(sqlvalref_for_pg_query));
+    let row_stream = future.await.map_err(|e| in_statement(e.into()))?.map(|r| {
+        r.map_err(Error::Postgres).and_then(|row| {
+            std::iter::once(pkcol)
+                .chain(returning)
+                .enumerate()
+                .map(|(idx, col)| sql_val_from_postgres(&row, idx, col))
+                .collect::<Result<Vec<SqlVal>>>()
+        })
+    });
+    Box::pin(row_stream)
+        .next()
+        .await
+        .transpose()
+        .map_err(in_statement)
+}
+
+/// Runs the SELECT statement for [`ConnectionMethods::query`], returning
+/// its rows.
+async fn query_rows<C>(
+    conn: &C,
+    table: &str,
+    columns: &[Column],
+    expr: Option<BoolExpr>,
+    limit: Option<i32>,
+    offset: Option<i32>,
+    order: Option<&[query::Order]>,
+) -> Result<Vec<postgres::Row>>
+where
+    C: PgConnectionLike + Sync,
+{
+    bounded(conn, async {
+        let (sqlquery, values) = sql_for_query(table, columns, expr, limit, offset, order);
+        if cfg!(feature = "log") {
+            debug!("query sql {sqlquery}");
+        }
+
+        let in_statement = |e: postgres::Error| {
+            Error::from(e).in_statement(&sqlquery, table, values.iter().map(SqlVal::as_ref))
+        };
+        let client = conn.client()?;
+        let mut rowvec = Vec::<postgres::Row>::new();
+        // Giving the types of the parameters lets the statement be prepared
+        // and executed in a single round trip
+        let params = values
+            .iter()
+            .map(|v| (sqlval_for_pg_query(v), pgtype_for_sqltype(v.sqltype())));
+        let future = client.query_typed_raw(&sqlquery, params);
+        let rowstream = future.await.map_err(in_statement)?;
+        let mut rowstream = Box::pin(rowstream);
+        while let Some(r) = rowstream.next().await {
+            let r = r.map_err(in_statement)?;
+            check_columns(&r, columns)?;
+            rowvec.push(r);
+        }
+        Ok(rowvec)
+    })
+    .await
+}
+
+/// Executes the statement `sql` generated for `table`, attaching it to
+/// any error.
+async fn execute_generated<C>(
+    conn: &C,
+    sql: &str,
+    table: &str,
+    values: &[SqlValRef<'_>],
+) -> Result<u64>
+where
+    C: PgConnectionLike + Sync,
+{
+    let params: Vec<&DynToSqlPg> = values.iter().map(|v| v as &DynToSqlPg).collect();
+    let client = conn.client()?;
+    let future = client.execute(sql, params.as_slice());
+    future
+        .await
+        .map_err(|e| Error::from(e).in_statement(sql, table, values.iter().cloned()))
 }
 
 #[async_trait]
@@ -163,14 +793,93 @@ impl<T> ConnectionMethods for T
     T: PgConnectionLike + std::marker::Sync,
 {
     async fn execute(&self, sql: &str) -> Result<()> {
-        if cfg!(feature = "log") {
-            debug!("execute sql {sql}");
-        }
-        // Note, let binding exists only so that the self.client() reference is not held across the await
-        let future = self.client()?.batch_execute(sql.as_ref());
-        future.await?;
+        bounded(self, async {
+            if cfg!(feature = "log") {
+                debug!("execute sql {sql}");
+            }
+            let client = self.client()?;
+            let future = client.batch_execute(sql.as_ref());
+            future.await?;
+            Ok(())
+        })
+        .await?;
+        self.executed(sql);
         Ok(())
     }
+    async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        bounded(self, async {
+            let sql = helper::translate_placeholders(sql, &mut PgPlaceholderSource::new());
+            if cfg!(feature = "log") {
+                debug!("execute sql {sql}");
+                #[cfg(feature = "debug")]
+                debug!("placeholders {params:?}");
+            }
+            // Declaring the parameter types lets the server convert them,
+            // such as an INT parameter compared with a BIGINT column
+            let types: Vec<postgres::types::Type> = params
+                .iter()
+                .map(|v| pgtype_for_sqltype(v.sqltype()))
+                .collect();
+            let client = self.client()?;
+            let future = client.prepare_typed(&sql, types.as_ref());
+            let stmt = future.await?;
+            let future = client.execute_raw(&stmt, params.iter().map(sqlvalref_for_pg_query));
+            let cnt = future.await?;
+            Ok(cnt as usize)
+        })
+        .await
+    }
+    fn max_statement_params(&self) -> usize {
+        // The server accepts 65535, but tokio-postgres sends the number
+        // of parameters as a signed 16 bit integer
+        32767
+    }
+    fn in_transaction(&self) -> bool {
+        PgConnectionLike::in_transaction(self)
+    }
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        let sql = atomic::rollback_sql(savepoint);
+        self.abandoned()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_str(&sql);
+        // As it is run before any later statement, it has in effect run
+        self.executed(&sql);
+    }
+    async fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        let rowvec = bounded(self, async {
+            let sql = helper::translate_placeholders(sql, &mut PgPlaceholderSource::new());
+            if cfg!(feature = "log") {
+                debug!("query sql {sql}");
+                #[cfg(feature = "debug")]
+                debug!("placeholders {params:?}");
+            }
+            let types: Vec<postgres::types::Type> = params
+                .iter()
+                .map(|v| pgtype_for_sqltype(v.sqltype()))
+                .collect();
+            let client = self.client()?;
+            let future = client.prepare_typed(&sql, types.as_ref());
+            let stmt = future.await?;
+            let mut rowvec = Vec::<postgres::Row>::new();
+            let future = client.query_raw(&stmt, params.iter().map(sqlvalref_for_pg_query));
+            let rowstream = future.await.map_err(Error::Postgres)?;
+            let mut rowstream = Box::pin(rowstream);
+            while let Some(r) = rowstream.next().await {
+                let r = r?;
+                check_columns(&r, columns)?;
+                rowvec.push(r);
+            }
+            Ok(rowvec)
+        })
+        .await?;
+        Ok(Box::new(VecRows::new(rowvec)))
+    }
 
     async fn query<'c>(
         &'c self,
@@ -181,51 +890,47 @@ async fn query<'c>(
         offset: Option<i32>,
         order: Option<&[query::Order]>,
     ) -> Result<RawQueryResult<'c>> {
-        let mut sqlquery = String::new();
-        helper::sql_select(columns, table, &mut sqlquery);
-        let mut values: Vec<SqlVal> = Vec::new();
-        if let Some(expr) = expr {
-            sqlquery.write_str(" WHERE ").unwrap();
-            sql_for_expr(
-                query::Expr::Condition(Box::new(expr)),
-                &mut values,
-                &mut PgPlaceholderSource::new(),
-                &mut sqlquery,
-            );
-        }
-
-        if let Some(order) = order {
-            helper::sql_order(order, &mut sqlquery)
-        }
-
-        if let Some(limit) = limit {
-            helper::sql_limit(limit, &mut sqlquery)
-        }
-
-        if let Some(offset) = offset {
-            helper::sql_offset(offset, &mut sqlquery)
-        }
-
-        if cfg!(feature = "log") {
-            debug!("query sql {sqlquery}");
-        }
-
-        let types: Vec<postgres::types::Type> = values.iter().map(pgtype_for_val).collect();
-        let future = self.client()?.prepare_typed(&sqlquery, types.as_ref());
-        let stmt = future.await?;
-        let mut rowvec = Vec::<postgres::Row>::new();
-        let future = self
-            .client()?
-            .query_raw(&stmt, values.iter().map(sqlval_for_pg_query));
-        let rowstream = future.await.map_err(Error::Postgres)?;
-        let mut rowstream = Box::pin(rowstream);
-        while let Some(r) = rowstream.next().await {
-            let r = r?;
-            check_columns(&r, columns)?;
-            rowvec.push(r);
-        }
+        let rowvec = query_rows(self, table, columns, expr, limit, offset, order).await?;
         Ok(Box::new(VecRows::new(rowvec)))
     }
+    async fn query_counted<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        order: Option<&[query::Order]>,
+    ) -> Result<(RawQueryResult<'c>, u64)> {
+        // The window function counts the rows before the limit and
+        // offset are applied, so a single statement suffices
+        let mut counted = columns.to_vec();
+        counted.push(Column::new("COUNT(*) OVER ()", SqlType::BigInt));
+        let rowvec = query_rows(self, table, &counted, expr.clone(), limit, offset, order).await?;
+        let total = match rowvec.first() {
+            Some(row) => row.try_get::<_, i64>(columns.len())?,
+            // Past the last row there is no row to read the count from
+            None if offset.unwrap_or(0) > 0 || limit == Some(0) => {
+                let rows = query_rows(
+                    self,
+                    table,
+                    &counted[columns.len()..],
+                    expr,
+                    Some(1),
+                    None,
+                    None,
+                )
+                .await?;
+                match rows.first() {
+                    Some(row) => row.try_get::<_, i64>(0)?,
+                    None => 0,
+                }
+            }
+            None => 0,
+        };
+        let rows = rowvec.into_iter().map(CountedRow).collect();
+        Ok((Box::new(VecRows::new(rows)), total as u64))
+    }
     async fn insert_returning_pk(
         &self,
         table: &str,
@@ -233,30 +938,53 @@ async fn insert_returning_pk(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<SqlVal> {
-        let mut sql = String::new();
-        helper::sql_insert_with_placeholders(
-            table,
-            columns,
-            &mut PgPlaceholderSource::new(),
-            &mut sql,
-        );
-        write!(&mut sql, " RETURNING {}", pkcol.name()).unwrap();
-        if cfg!(feature = "log") {
-            debug!("insert sql {sql}");
-        }
-
-        // use query instead of execute so we can get our result back
-        let future = self
-            .client()?
-            .query_raw(sql.as_str(), values.iter().map(sqlvalref_for_pg_query));
-        let pk_stream = future
-            .await
-            .map_err(Error::Postgres)?
-            .map(|r| r.map(|x| sql_val_from_postgres(&x, 0, pkcol)));
-        Box::pin(pk_stream)
-            .next()
-            .await
-            .ok_or(Error::Internal(("could not get pk").to_string()))??
+        let mut returned = self
+            .insert_returning(table, columns, pkcol, &[], values)
+            .await?;
+        Ok(returned.remove(0))
+    }
+    async fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        bounded(self, async {
+            let mut sql = String::new();
+            helper::sql_insert_with_placeholders(
+                table,
+                columns,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            insert_returning_row(self, table, sql, pkcol, returning, values)
+                .await?
+                .ok_or(Error::Internal(("could not get pk").to_string()))
+        })
+        .await
+    }
+    async fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        bounded(self, async {
+            let mut sql = String::new();
+            helper::sql_insert_with_placeholders(
+                table,
+                columns,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            write!(&mut sql, " ON CONFLICT DO NOTHING").unwrap();
+            insert_returning_row(self, table, sql, pkcol, returning, values).await
+        })
+        .await
     }
     async fn insert_only(
         &self,
@@ -264,17 +992,18 @@ async fn insert_only(
         columns: &[Column],
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        let mut sql = String::new();
-        helper::sql_insert_with_placeholders(
-            table,
-            columns,
-            &mut PgPlaceholderSource::new(),
-            &mut sql,
-        );
-        let params: Vec<&DynToSqlPg> = values.iter().map(|v| v as &DynToSqlPg).collect();
-        let future = self.client()?.execute(sql.as_str(), params.as_slice());
-        future.await?;
-        Ok(())
+        bounded(self, async {
+            let mut sql = String::new();
+            helper::sql_insert_with_placeholders(
+                table,
+                columns,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            execute_generated(self, &sql, table, values).await?;
+            Ok(())
+        })
+        .await
     }
     async fn insert_or_replace(
         &self,
@@ -283,12 +1012,13 @@ async fn insert_or_replace(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        let mut sql = String::new();
-        sql_insert_or_replace_with_placeholders(table, columns, pkcol, &mut sql);
-        let params: Vec<&DynToSqlPg> = values.iter().map(|v| v as &DynToSqlPg).collect();
-        let future = self.client()?.execute(sql.as_str(), params.as_slice());
-        future.await?;
-        Ok(())
+        bounded(self, async {
+            let mut sql = String::new();
+            sql_insert_or_replace_with_placeholders(table, columns, pkcol, &mut sql);
+            execute_generated(self, &sql, table, values).await?;
+            Ok(())
+        })
+        .await
     }
     async fn update(
         &self,
@@ -298,70 +1028,396 @@ async fn update(
         columns: &[Column],
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        let mut sql = String::new();
-        helper::sql_update_with_placeholders(
-            table,
-            pkcol,
-            columns,
-            &mut PgPlaceholderSource::new(),
-            &mut sql,
-        );
-        let placeholder_values = [values, &[pk]].concat();
-        let params: Vec<&DynToSqlPg> = placeholder_values
-            .iter()
-            .map(|v| v as &DynToSqlPg)
-            .collect();
-        if cfg!(feature = "log") {
-            debug!("update sql {sql}");
-        }
-        let future = self.client()?.execute(sql.as_str(), params.as_slice());
-        future.await?;
-        Ok(())
+        bounded(self, async {
+            let mut sql = String::new();
+            helper::sql_update_with_placeholders(
+                table,
+                pkcol,
+                columns,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            let placeholder_values = [values, &[pk]].concat();
+            if cfg!(feature = "log") {
+                debug!("update sql {sql}");
+            }
+            execute_generated(self, &sql, table, &placeholder_values).await?;
+            Ok(())
+        })
+        .await
     }
     async fn delete(&self, table: &str, pkcol: &'static str, pk: SqlVal) -> Result<()> {
         self.delete_where(table, BoolExpr::Eq(pkcol, Expr::Val(pk)))
             .await?;
         Ok(())
     }
+    async fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        bounded(self, async {
+            let params: &[&DynToSqlPg] = &[&channel, &payload];
+            let client = self.client()?;
+            let future = client.execute("SELECT pg_notify($1, $2)", params);
+            future.await?;
+            Ok(())
+        })
+        .await
+    }
+    async fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        bounded(self, async {
+            let params: &[&DynToSqlPg] = &[&name, &value, &local];
+            let client = self.client()?;
+            let future = client.execute("SELECT set_config($1, $2, $3)", params);
+            future.await?;
+            Ok(())
+        })
+        .await?;
+        self.configured(name, value, local);
+        Ok(())
+    }
+    async fn current_config(&self, name: &str) -> Result<Option<String>> {
+        bounded(self, async {
+            let params: &[&DynToSqlPg] = &[&name];
+            let client = self.client()?;
+            // With missing_ok, an unset parameter is NULL rather than an error
+            let future = client.query_one("SELECT current_setting($1, true)", params);
+            let row = future.await?;
+            Ok(row.try_get(0)?)
+        })
+        .await
+    }
+    async fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        order: Option<&[query::Order]>,
+    ) -> Result<()> {
+        bounded(self, async {
+            let (sqlquery, values) = sql_for_query(table, columns, expr, limit, offset, order);
+            let sql = format!(
+                "DECLARE {} NO SCROLL CURSOR FOR {sqlquery}",
+                helper::quote_reserved_word(name)
+            );
+            if cfg!(feature = "log") {
+                debug!("declare cursor sql {sql}");
+            }
+            let values: Vec<SqlValRef> = values.iter().map(SqlVal::as_ref).collect();
+            execute_generated(self, &sql, table, &values).await?;
+            Ok(())
+        })
+        .await
+    }
+    async fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        let rowvec = bounded(self, async {
+            let sql = format!(
+                "FETCH FORWARD {count} FROM {}",
+                helper::quote_reserved_word(name)
+            );
+            let client = self.client()?;
+            let future = client.query(sql.as_str(), &[]);
+            let rowvec = future.await?;
+            for r in &rowvec {
+                check_columns(r, columns)?;
+            }
+            Ok(rowvec)
+        })
+        .await?;
+        Ok(Box::new(VecRows::new(rowvec)))
+    }
     async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
-        let mut sql = String::new();
-        let mut values: Vec<SqlVal> = Vec::new();
-        write!(
-            &mut sql,
-            "DELETE FROM {} WHERE ",
-            helper::quote_reserved_word(table)
-        )
-        .unwrap();
-        sql_for_expr(
-            query::Expr::Condition(Box::new(expr)),
-            &mut values,
-            &mut PgPlaceholderSource::new(),
-            &mut sql,
-        );
-        let params: Vec<&DynToSqlPg> = values.iter().map(|v| v as &DynToSqlPg).collect();
-        let future = self.client()?.execute(sql.as_str(), params.as_slice());
-        let cnt = future.await?;
-        Ok(cnt as usize)
+        bounded(self, async {
+            let mut sql = String::new();
+            let mut values: Vec<SqlVal> = Vec::new();
+            write!(
+                &mut sql,
+                "DELETE FROM {} WHERE ",
+                helper::quote_reserved_word(table)
+            )
+            .unwrap();
+            sql_for_expr(
+                query::Expr::Condition(Box::new(expr)),
+                &mut values,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            let values: Vec<SqlValRef> = values.iter().map(SqlVal::as_ref).collect();
+            let cnt = execute_generated(self, &sql, table, &values).await?;
+            Ok(cnt as usize)
+        })
+        .await
+    }
+    async fn delete_returning(
+        &self,
+        table: &str,
+        expr: BoolExpr,
+        returning: &[Column],
+    ) -> Result<Vec<Vec<SqlVal>>> {
+        bounded(self, async {
+            let mut sql = String::new();
+            let mut values: Vec<SqlVal> = Vec::new();
+            write!(
+                &mut sql,
+                "DELETE FROM {} WHERE ",
+                helper::quote_reserved_word(table)
+            )
+            .unwrap();
+            sql_for_expr(
+                query::Expr::Condition(Box::new(expr)),
+                &mut values,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            sql.push_str(" RETURNING ");
+            helper::list_columns(returning, &mut sql);
+            if cfg!(feature = "log") {
+                debug!("delete sql {sql}");
+            }
+            let values: Vec<SqlValRef> = values.iter().map(SqlVal::as_ref).collect();
+            let in_statement = |e: Error| e.in_statement(&sql, table, values.iter().cloned());
+            let client = self.client()?;
+            let future = client.query_raw(sql.as_str(), values.iter().map(sqlvalref_for_pg_query));
+            let rows = future.await.map_err(|e| in_statement(e.into()))?;
+            let mut rows = Box::pin(rows);
+            let mut deleted = Vec::new();
+            while let Some(row) = rows.next().await {
+                let row = row.map_err(|e| in_statement(e.into()))?;
+                let values = returning
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, col)| sql_val_from_postgres(&row, idx, col))
+                    .collect::<Result<Vec<SqlVal>>>()?;
+                deleted.push(values);
+            }
+            Ok(deleted)
+        })
+        .await
+    }
+    async fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        bounded(self, async {
+            let mut sql = String::new();
+            let mut values: Vec<SqlVal> = Vec::new();
+            helper::sql_update_where(
+                table,
+                assignments,
+                expr,
+                sql_for_expr,
+                &mut values,
+                &mut PgPlaceholderSource::new(),
+                &mut sql,
+            );
+            let values: Vec<SqlValRef> = values.iter().map(SqlVal::as_ref).collect();
+            let cnt = execute_generated(self, &sql, table, &values).await?;
+            Ok(cnt as usize)
+        })
+        .await
+    }
+    // A bytea cannot be updated in part without rewriting all of it, so
+    // only large objects are streamed, with a statement for each chunk.
+    async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        bounded(self, async {
+            let sql = large_object_sql(
+                blob,
+                "CASE WHEN lo = 0 THEN NULL ELSE lo_lseek64(lo_open(lo, 262144), 0, 2) END",
+            )?;
+            let params: &[&DynToSqlPg] = &[&blob.pk];
+            let client = self.client()?;
+            let future = client.query_opt(sql.as_str(), params);
+            let row = future.await?.ok_or(Error::NoSuchObject)?;
+            let len: Option<i64> = row.try_get(0)?;
+            Ok(len.map(|len| len as u64))
+        })
+        .await
+    }
+    async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        bounded(self, async {
+            let sql = large_object_sql(blob, "lo_get(lo, $2, $3)")?;
+            let offset = blob_offset(offset)?;
+            let len = i32::try_from(len).unwrap_or(i32::MAX);
+            let params: &[&DynToSqlPg] = &[&blob.pk, &offset, &len];
+            let client = self.client()?;
+            let future = client.query_opt(sql.as_str(), params);
+            let row = future.await?.ok_or(Error::NoSuchObject)?;
+            let data: Option<Vec<u8>> = row.try_get(0)?;
+            Ok(data.unwrap_or_default())
+        })
+        .await
+    }
+    async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        bounded(self, async {
+            let sql = large_object_sql(blob, "lo_put(lo, $2, $3)")?;
+            let offset = blob_offset(offset)?;
+            let params: &[&DynToSqlPg] = &[&blob.pk, &offset, &data];
+            let client = self.client()?;
+            let future = client.query_opt(sql.as_str(), params);
+            future.await?.ok_or(Error::NoSuchObject)?;
+            Ok(())
+        })
+        .await
+    }
+    async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        bounded(self, async {
+            // Truncating the large object and then extending it fills it
+            // with zeroes without writing them
+            let sql = large_object_sql(
+                blob,
+                "CASE WHEN lo = 0 THEN NULL ELSE lo_truncate64(lo_open(lo, 131072), $2) END",
+            )?;
+            let client = self.client()?;
+            for len in [0, blob_offset(len)?] {
+                let params: &[&DynToSqlPg] = &[&blob.pk, &len];
+                let future = client.query_opt(sql.as_str(), params);
+                let row = future.await?.ok_or(Error::NoSuchObject)?;
+                let truncated: Option<i32> = row.try_get(0)?;
+                truncated.ok_or(Error::NoSuchObject)?;
+            }
+            Ok(())
+        })
+        .await
     }
     async fn has_table(&self, table: &str) -> Result<bool> {
-        // future improvement, should be schema-aware
-        let future = self
-            .client()?
-            .prepare("SELECT table_name FROM information_schema.tables WHERE table_name=$1;");
-        let stmt = future.await?;
-        let tableref: &[&(dyn postgres::types::ToSql + Sync)] = &[&table];
-        let future = self.client()?.query(&stmt, tableref);
-        let rows = future.await?;
-        Ok(!rows.is_empty())
+        bounded(self, async {
+            // A table qualified with a schema is only looked for in that schema
+            let rows = match table.split_once('.') {
+                Some((schema, table)) => {
+                    let client = self.client()?;
+                    let future = client.prepare(
+                        "SELECT table_name FROM information_schema.tables WHERE table_schema=$1 AND table_name=$2;",
+                    );
+                    let stmt = future.await?;
+                    let table = stored_identifier(table);
+                    let params: &[&(dyn postgres::types::ToSql + Sync)] = &[&schema, &table];
+                    let future = client.query(&stmt, params);
+                    future.await?
+                }
+                None => {
+                    let client = self.client()?;
+                    let future = client.prepare(
+                        "SELECT table_name FROM information_schema.tables WHERE table_name=$1;",
+                    );
+                    let stmt = future.await?;
+                    let table = stored_identifier(table);
+                    let tableref: &[&(dyn postgres::types::ToSql + Sync)] = &[&table];
+                    let future = client.query(&stmt, tableref);
+                    future.await?
+                }
+            };
+            Ok(!rows.is_empty())
+        })
+        .await
+    }
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        bounded(self, async {
+            let client = self.client()?;
+            let future = client.query(
+                "SELECT table_name::text FROM information_schema.tables \
+                 WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' \
+                 ORDER BY table_name;",
+                &[],
+            );
+            let rows = future.await?;
+            rows.iter()
+                .map(|row| row.try_get(0).map_err(Error::from))
+                .collect()
+        })
+        .await
+    }
+    async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        bounded(self, async {
+            let name = helper::quote_reserved_word(table);
+            let params: &[&DynToSqlPg] = &[&name];
+            let client = self.client()?;
+            // to_regclass resolves the table as it would be in a query
+            let future = client.query(
+                "SELECT a.attname::text, format_type(a.atttypid, a.atttypmod), NOT a.attnotnull, \
+                 pg_get_expr(d.adbin, d.adrelid), COALESCE(a.attnum = ANY(i.indkey), false) \
+                 FROM pg_attribute a \
+                 LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum \
+                 LEFT JOIN pg_index i ON i.indrelid = a.attrelid AND i.indisprimary \
+                 WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped \
+                 ORDER BY a.attnum;",
+                params,
+            );
+            let rows = future.await?;
+            // Every table has at least one column
+            if rows.is_empty() {
+                return Ok(None);
+            }
+            let columns = rows
+                .iter()
+                .map(|row| {
+                    Ok(ColumnSchema {
+                        name: row.try_get(0)?,
+                        type_name: row.try_get(1)?,
+                        nullable: row.try_get(2)?,
+                        default: row.try_get(3)?,
+                        primary_key: row.try_get(4)?,
+                    })
+                })
+                .collect::<Result<Vec<ColumnSchema>>>()?;
+            Ok(Some(TableSchema {
+                name: table.to_string(),
+                columns,
+            }))
+        })
+        .await
+    }
+    async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        bounded(self, async {
+            let name = helper::quote_reserved_word(table);
+            let params: &[&DynToSqlPg] = &[&name];
+            let client = self.client()?;
+            // Expressions have an attnum of 0, so are not joined with a column
+            let future = client.query(
+                "SELECT c.relname::text, i.indisunique, ARRAY( \
+                   SELECT a.attname::text FROM unnest(i.indkey) WITH ORDINALITY AS k(attnum, n) \
+                   JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = k.attnum \
+                   ORDER BY k.n) \
+                 FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid \
+                 WHERE i.indrelid = to_regclass($1) AND NOT i.indisprimary \
+                 ORDER BY c.relname;",
+                params,
+            );
+            let rows = future.await?;
+            rows.iter()
+                .map(|row| {
+                    Ok(IndexSchema {
+                        name: row.try_get(0)?,
+                        unique: row.try_get(1)?,
+                        columns: row.try_get(2)?,
+                    })
+                })
+                .collect()
+        })
+        .await
     }
 }
 
 struct PgTransaction<'c> {
     trans: Option<postgres::Transaction<'c>>,
+    /// Whether the statement timeout of a deadline may still be set.
+    timeout_set: AtomicBool,
+    /// Statements undoing abandoned groups, run before the next statement.
+    abandoned: Mutex<String>,
 }
 impl<'c> PgTransaction<'c> {
     fn new(trans: postgres::Transaction<'c>) -> Self {
-        PgTransaction { trans: Some(trans) }
+        PgTransaction {
+            trans: Some(trans),
+            timeout_set: AtomicBool::new(false),
+            abandoned: Mutex::default(),
+        }
     }
     fn get(&self) -> Result<&postgres::Transaction<'c>> {
         match &self.trans {
@@ -384,14 +1440,35 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 
 impl<'c> PgConnectionLike for PgTransaction<'c> {
     type Client = postgres::Transaction<'c>;
-    fn client(&self) -> Result<&Self::Client> {
+    type ClientRef<'a>
+        = &'a postgres::Transaction<'c>
+    where
+        Self: 'a;
+    fn client(&self) -> Result<Self::ClientRef<'_>> {
         self.get()
     }
+    fn timeout_set(&self) -> &AtomicBool {
+        &self.timeout_set
+    }
+    fn abandoned(&self) -> &Mutex<String> {
+        &self.abandoned
+    }
+    fn in_transaction(&self) -> bool {
+        self.trans.is_some()
+    }
 }
 
 #[async_trait]
 impl<'c> BackendTransaction<'c> for PgTransaction<'c> {
     async fn commit(&mut self) -> Result<()> {
+        if self.timeout_set.load(Ordering::Acquire) {
+            // Otherwise the statement timeout would outlast the
+            // transaction. Should the transaction have failed, it is
+            // rolled back along with the timeout instead.
+            let _ = reset_timeout(self, self.get()?).await;
+        }
+        // Otherwise the statements of abandoned groups would be committed
+        undo_abandoned(self, self.get()?).await;
         match self.trans.take() {
             None => Err(Self::already_consumed()),
             Some(trans) => Ok(trans.commit().await?),
@@ -444,6 +1521,7 @@ fn to_sql(
             Real(r) => r.to_sql_checked(requested_ty, out),
             Text(t) => t.to_sql_checked(requested_ty, out),
             Blob(b) => b.to_sql_checked(requested_ty, out),
+            TextList(v) => v.as_ref().to_sql_checked(requested_ty, out),
             #[cfg(feature = "json")]
             Json(v) => v.to_sql_checked(requested_ty, out),
             #[cfg(feature = "datetime")]
@@ -501,6 +1579,9 @@ fn from_sql(
             Type::BYTEA => Ok(SqlValRef::Blob(postgres::types::FromSql::from_sql(
                 ty, raw,
             )?)),
+            Type::TEXT_ARRAY => Ok(SqlValRef::TextList(Cow::Owned(
+                postgres::types::FromSql::from_sql(ty, raw)?,
+            ))),
             #[cfg(feature = "json")]
             Type::JSONB => Ok(SqlValRef::Json(postgres::types::FromSql::from_sql(
                 ty, raw,
@@ -553,6 +1634,60 @@ fn len(&self) -> usize {
     }
 }
 
+/// A row selected by [`ConnectionMethods::query_counted`], whose last
+/// column holds the count of rows and is hidden.
+struct CountedRow(postgres::Row);
+
+impl BackendRow for CountedRow {
+    fn get(&self, idx: usize, ty: SqlType) -> Result<SqlValRef<'_>> {
+        if idx >= self.len() {
+            return Err(Error::BoundsError(format!("no column {idx} in row")));
+        }
+        BackendRow::get(&self.0, idx, ty)
+    }
+    fn len(&self) -> usize {
+        self.0.len() - 1
+    }
+}
+
+/// Builds the SELECT statement for [`ConnectionMethods::query`], returning
+/// it together with the values of its placeholders.
+fn sql_for_query(
+    table: &str,
+    columns: &[Column],
+    expr: Option<BoolExpr>,
+    limit: Option<i32>,
+    offset: Option<i32>,
+    order: Option<&[query::Order]>,
+) -> (String, Vec<SqlVal>) {
+    let mut sqlquery = String::new();
+    helper::sql_select(columns, table, &mut sqlquery);
+    let mut values: Vec<SqlVal> = Vec::new();
+    let mut pls = PgPlaceholderSource::new();
+    if let Some(expr) = expr {
+        sqlquery.write_str(" WHERE ").unwrap();
+        sql_for_expr(
+            query::Expr::Condition(Box::new(expr)),
+            &mut values,
+            &mut pls,
+            &mut sqlquery,
+        );
+    }
+
+    if let Some(order) = order {
+        helper::sql_order(order, sql_for_expr, &mut values, &mut pls, &mut sqlquery)
+    }
+
+    if let Some(limit) = limit {
+        helper::sql_limit(limit, &mut sqlquery)
+    }
+
+    if let Some(offset) = offset {
+        helper::sql_offset(offset, &mut sqlquery)
+    }
+    (sqlquery, values)
+}
+
 fn sql_for_expr<W>(
     expr: query::Expr,
     values: &mut Vec<SqlVal>,
@@ -561,7 +1696,37 @@ fn sql_for_expr<W>(
 ) where
     W: Write,
 {
-    helper::sql_for_expr(expr, sql_for_expr, values, pls, w)
+    match expr {
+        // Lists are stored as arrays, rather than as JSON
+        query::Expr::Condition(c) => match *c {
+            BoolExpr::ListContains(col, vals) => sql_for_list(col, "@>", vals, values, pls, w),
+            BoolExpr::ListOverlaps(col, vals) => sql_for_list(col, "&&", vals, values, pls, w),
+            c => helper::sql_for_expr(
+                query::Expr::Condition(Box::new(c)),
+                sql_for_expr,
+                values,
+                pls,
+                w,
+            ),
+        },
+        _ => helper::sql_for_expr(expr, sql_for_expr, values, pls, w),
+    }
+}
+
+/// Writes the comparison of the array in `col` with the array of `vals`
+/// by the array operator `op`.
+fn sql_for_list<W>(
+    col: &str,
+    op: &str,
+    vals: Vec<String>,
+    values: &mut Vec<SqlVal>,
+    pls: &mut PgPlaceholderSource,
+    w: &mut W,
+) where
+    W: Write,
+{
+    write!(w, "{} {op} ", helper::quote_reserved_word(col)).unwrap();
+    sql_for_expr(query::Expr::Val(SqlVal::TextList(vals)), values, pls, w);
 }
 
 fn sql_val_from_postgres<I>(row: &postgres::Row, idx: I, col: &Column) -> Result<SqlVal>
@@ -586,12 +1751,17 @@ fn sql_val_from_postgres<I>(row: &postgres::Row, idx: I, col: &Column) -> Result
 
 fn sql_for_op(current: &mut ADB, op: &Operation) -> Result<String> {
     match op {
-        Operation::AddTable(table) => Ok(create_table(table, false)?),
+        Operation::AddTable(table) => Ok(create_table_with_collation(table, false)?),
         Operation::AddTableConstraints(table) => Ok(create_table_fkey_constraints(table)),
-        Operation::AddTableIfNotExists(table) => Ok(create_table(table, true)?),
-        Operation::RemoveTable(name) => Ok(drop_table(name)),
+        Operation::AddTableIfNotExists(table) => Ok(create_table_with_collation(table, true)?),
+        Operation::RemoveTable(name) => Ok(drop_materialized_view(current, name)
+            .or_else(|| helper::drop_view(current, name))
+            .unwrap_or_else(|| drop_table(name))),
+        Operation::RenameTable(from, to) => Ok(rename_table(from, to)),
+        Operation::RenameColumn(tbl, from, to) => Ok(rename_column(tbl, from, to)),
         Operation::RemoveTableConstraints(table) => remove_table_fkey_constraints(table),
         Operation::AddColumn(tbl, col) => add_column(tbl, col),
+        Operation::BackfillColumn(tbl, col) => backfill_column(current, tbl, col),
         Operation::RemoveColumn(tbl, name) => Ok(remove_column(tbl, name)),
         Operation::ChangeColumn(tbl, old, new) => {
             let table = current.get_table(tbl);
@@ -606,25 +1776,202 @@ fn sql_for_op(current: &mut ADB, op: &Operation) -> Result<String> {
                 Ok(String::new())
             }
         }
+        Operation::AddIndex(tbl, index) => Ok(add_index(current, tbl, index)),
+        Operation::RemoveIndex(tbl, index) => Ok(remove_index(tbl, index)),
+        Operation::SetTableComment(tbl, comment) => Ok(comment_on_table(tbl, comment.as_deref())),
+        Operation::SetColumnComment(tbl, col, comment) => {
+            Ok(comment_on_column(tbl, col, comment.as_deref()))
+        }
+    }
+}
+
+/// Returns the SQL to add `index` to the table `tbl_name`. If the index is
+/// to be built concurrently and its column already exists in `current`, it
+/// is built without blocking writes, outside of the migration's transaction.
+/// Any index left invalid by a failed attempt is dropped first. Before the
+/// unique index of an existing `Many` table is added, duplicate rows are
+/// deleted from it.
+fn add_index(current: &ADB, tbl_name: &str, index: &AIndex) -> String {
+    let concurrently = index.concurrently
+        && current
+            .get_table(tbl_name)
+            .is_some_and(|table| index.columns().all(|col| table.column(col).is_some()));
+    if helper::is_new_unique_many_index(current, tbl_name, index) {
+        return format!(
+            "{}\n{}",
+            delete_duplicate_many_rows(tbl_name),
+            helper::create_index(tbl_name, index)
+        );
+    }
+    if !concurrently {
+        return helper::create_index(tbl_name, index);
+    }
+    let name = index.name(tbl_name);
+    let name = helper::quote_reserved_word(&name);
+    format!(
+        "{OUTSIDE_TRANSACTION_MARKER}\nDROP INDEX CONCURRENTLY IF EXISTS {name};\n\
+         {OUTSIDE_TRANSACTION_MARKER}\nCREATE {}INDEX CONCURRENTLY {name} ON {} ({});",
+        if index.unique { "UNIQUE " } else { "" },
+        helper::quote_reserved_word(tbl_name),
+        helper::index_columns(index)
+    )
+}
+
+/// Returns the SQL to delete the rows of the `Many` table `tbl_name`
+/// which repeat another.
+fn delete_duplicate_many_rows(tbl_name: &str) -> String {
+    let tbl = helper::quote_reserved_word(tbl_name);
+    format!(
+        "DELETE FROM {tbl} a USING {tbl} b \
+         WHERE a.owner = b.owner AND a.has = b.has AND a.ctid > b.ctid;"
+    )
+}
+
+/// Returns the SQL to remove `index` from the table `tbl_name`, without
+/// blocking writes if it was built concurrently.
+fn remove_index(tbl_name: &str, index: &AIndex) -> String {
+    if !index.concurrently {
+        return helper::drop_index(tbl_name, index);
+    }
+    format!(
+        "{OUTSIDE_TRANSACTION_MARKER}\nDROP INDEX CONCURRENTLY IF EXISTS {};",
+        helper::quote_reserved_word(&index.name(tbl_name))
+    )
+}
+
+/// Creates `table`, first creating the [`NOCASE_COLLATION`] if it is used,
+/// then describing it and its columns with their comments.
+fn create_table_with_collation(table: &ATable, allow_exists: bool) -> Result<String> {
+    let mut stmts: Vec<String> = create_nocase_collation(&table.columns)
+        .into_iter()
+        .collect();
+    stmts.push(create_table(table, allow_exists)?);
+    if !table.is_view() {
+        stmts.extend(
+            table
+                .comment
+                .as_deref()
+                .map(|comment| comment_on_table(&table.name, Some(comment))),
+        );
+        for col in &table.columns {
+            if let Some(comment) = col.comment() {
+                stmts.push(comment_on_column(&table.name, col.name(), Some(comment)));
+            }
+        }
+    }
+    Ok(stmts.join("\n"))
+}
+
+/// Returns the SQL to set the comment describing the table `tbl_name`,
+/// or to remove it if `comment` is `None`.
+fn comment_on_table(tbl_name: &str, comment: Option<&str>) -> String {
+    format!(
+        "COMMENT ON TABLE {} IS {};",
+        helper::quote_reserved_word(tbl_name),
+        comment_literal(comment)
+    )
+}
+
+/// Returns the SQL to set the comment describing the column `col_name`
+/// of the table `tbl_name`, or to remove it if `comment` is `None`.
+fn comment_on_column(tbl_name: &str, col_name: &str, comment: Option<&str>) -> String {
+    format!(
+        "COMMENT ON COLUMN {}.{} IS {};",
+        helper::quote_reserved_word(tbl_name),
+        helper::quote_reserved_word(col_name),
+        comment_literal(comment)
+    )
+}
+
+fn comment_literal(comment: Option<&str>) -> String {
+    match comment {
+        Some(comment) => format!("'{}'", comment.replace('\'', "''")),
+        None => "NULL".to_string(),
     }
 }
 
 fn create_table(table: &ATable, allow_exists: bool) -> Result<String> {
-    let coldefs = table
+    if let Some(sql) = create_materialized_view(table).or_else(|| helper::create_view(table)) {
+        return Ok(sql);
+    }
+    // The primary key of a partitioned table must include the partition column.
+    let partitioned_pk = match (&table.partition_by, table.pk()) {
+        (Some(partition_by), Some(pk)) if pk.name() != partition_by.column => Some(format!(
+            "PRIMARY KEY ({}, {})",
+            helper::quote_reserved_word(pk.name()),
+            helper::quote_reserved_word(&partition_by.column)
+        )),
+        _ => None,
+    };
+    let mut coldefs = table
         .columns
         .iter()
-        .map(define_column)
-        .collect::<Result<Vec<String>>>()?
-        .join(",\n");
+        .map(|col| {
+            let inline_pk = col.is_pk() && partitioned_pk.is_none();
+            Ok(define_column_as(col, inline_pk)? + &helper::default_clause(col)?)
+        })
+        .collect::<Result<Vec<String>>>()?;
+    coldefs.extend(partitioned_pk);
+    let partition = match &table.partition_by {
+        Some(partition_by) => format!(
+            " PARTITION BY {} ({})",
+            partition_by.method.sql(),
+            helper::quote_reserved_word(&partition_by.column)
+        ),
+        None => String::new(),
+    };
     let modifier = if allow_exists { "IF NOT EXISTS " } else { "" };
     Ok(format!(
-        "CREATE TABLE {}{} (\n{}\n);",
+        "CREATE TABLE {}{} (\n{}\n){};",
         modifier,
         helper::quote_reserved_word(&table.name),
-        coldefs
+        coldefs.join(",\n"),
+        partition
     ))
 }
 
+/// Returns the SQL to create `table` as a materialized view with its indexes, if it is one.
+fn create_materialized_view(table: &ATable) -> Option<String> {
+    let view = table.view.as_ref().filter(|view| view.materialized)?;
+    let mut lines = vec![format!(
+        "CREATE MATERIALIZED VIEW {} AS {};",
+        helper::quote_reserved_word(&table.name),
+        view.query.trim().trim_end_matches(';')
+    )];
+    for index in &view.indexes {
+        // The columns of a view take their collation from its query,
+        // so the index is given the collation of the field instead.
+        let collate = table
+            .column(&index.column)
+            .and_then(collate_constraint)
+            .map(|collate| format!(" {collate}"))
+            .unwrap_or_default();
+        lines.push(format!(
+            "CREATE {}INDEX {} ON {} ({}{});",
+            if index.unique { "UNIQUE " } else { "" },
+            helper::quote_reserved_word(&index.name(&table.name)),
+            helper::quote_reserved_word(&table.name),
+            helper::quote_reserved_word(&index.column),
+            collate
+        ));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Returns the SQL to drop the table `name` if it is a materialized view in `current`.
+fn drop_materialized_view(current: &ADB, name: &str) -> Option<String> {
+    current
+        .get_table(name)
+        .and_then(|table| table.view.as_ref())
+        .filter(|view| view.materialized)
+        .map(|_| {
+            format!(
+                "DROP MATERIALIZED VIEW {};",
+                helper::quote_reserved_word(name)
+            )
+        })
+}
+
 fn create_table_fkey_constraints(table: &ATable) -> String {
     table
         .columns
@@ -646,11 +1993,16 @@ fn remove_table_fkey_constraints(table: &ATable) -> Result<String> {
 }
 
 fn define_column(col: &AColumn) -> Result<String> {
-    let mut constraints: Vec<String> = Vec::new();
+    define_column_as(col, col.is_pk())
+}
+
+/// Defines a column, with a `PRIMARY KEY` constraint if `primary_key`.
+fn define_column_as(col: &AColumn, primary_key: bool) -> Result<String> {
+    let mut constraints: Vec<String> = collate_constraint(col).into_iter().collect();
     if !col.nullable() {
         constraints.push("NOT NULL".to_string());
     }
-    if col.is_pk() {
+    if primary_key {
         constraints.push("PRIMARY KEY".to_string());
     }
     if col.unique() {
@@ -671,6 +2023,39 @@ fn define_column(col: &AColumn) -> Result<String> {
     ))
 }
 
+/// Returns the name of the collation `name` given to a column, mapping
+/// `NOCASE` onto the [`NOCASE_COLLATION`].
+fn collation_name(name: &str) -> Cow<'_, str> {
+    if name.eq_ignore_ascii_case("nocase") {
+        Cow::Borrowed(NOCASE_COLLATION)
+    } else {
+        // Collation names such as "C" and "und-x-icu" are case sensitive
+        Cow::Owned(format!("\"{name}\""))
+    }
+}
+
+/// Returns the `COLLATE` constraint for a column with an explicit collation, if any.
+fn collate_constraint(col: &AColumn) -> Option<String> {
+    col.collation()
+        .map(|name| format!("COLLATE {}", collation_name(name)))
+}
+
+/// Returns the SQL to create the [`NOCASE_COLLATION`] if any of `columns` use it.
+/// It is nondeterministic, so that case-insensitively equal values are also
+/// equal for unique constraints.
+fn create_nocase_collation<'a>(columns: impl IntoIterator<Item = &'a AColumn>) -> Option<String> {
+    columns
+        .into_iter()
+        .filter_map(AColumn::collation)
+        .any(|name| name.eq_ignore_ascii_case("nocase"))
+        .then(|| {
+            format!(
+                "CREATE COLLATION IF NOT EXISTS {NOCASE_COLLATION} \
+                 (provider = icu, locale = 'und-u-ks-level2', deterministic = false);"
+            )
+        })
+}
+
 fn define_fkey_constraint(table_name: &str, column: &AColumn) -> String {
     let reference = column
         .reference()
@@ -679,11 +2064,12 @@ fn define_fkey_constraint(table_name: &str, column: &AColumn) -> String {
     match reference {
         ARef::Literal(literal) => {
             format!(
-                "ALTER TABLE {} ADD FOREIGN KEY ({}) REFERENCES {}({});",
+                "ALTER TABLE {} ADD FOREIGN KEY ({}) REFERENCES {}({}){};",
                 helper::quote_reserved_word(table_name),
                 helper::quote_reserved_word(column.name()),
                 helper::quote_reserved_word(literal.table_name()),
                 helper::quote_reserved_word(literal.column_name()),
+                helper::on_delete_clause(column),
             )
         }
         _ => panic!(),
@@ -717,6 +2103,7 @@ fn col_sqltype(col: &AColumn) -> Result<Cow<'_, str>> {
                     #[cfg(feature = "datetime")]
                     SqlType::Timestamp => Cow::Borrowed("TIMESTAMP"),
                     SqlType::Blob => Cow::Borrowed("BYTEA"),
+                    SqlType::TextList => Cow::Borrowed("TEXT[]"),
                     #[cfg(feature = "json")]
                     SqlType::Json => Cow::Borrowed("JSONB"),
                     SqlType::Custom(c) => match c {
@@ -732,21 +2119,129 @@ fn drop_table(name: &str) -> String {
     format!("DROP TABLE {};", helper::quote_reserved_word(name))
 }
 
+/// The name PostgreSQL stores for `ident`. Identifiers are only quoted
+/// if they are reserved words, and unquoted ones are folded to lowercase.
+pub fn stored_identifier(ident: &str) -> Cow<'_, str> {
+    if helper::is_reserved_word(ident) {
+        Cow::Borrowed(ident)
+    } else {
+        Cow::Owned(ident.to_lowercase())
+    }
+}
+
+fn rename_table(from: &str, to: &str) -> String {
+    if stored_identifier(from) == stored_identifier(to) {
+        // Already stored with the new name
+        return String::new();
+    }
+    format!(
+        "ALTER TABLE {} RENAME TO {};",
+        helper::quote_reserved_word(from),
+        helper::quote_reserved_word(to)
+    )
+}
+
+fn rename_column(tbl_name: &str, from: &str, to: &str) -> String {
+    if stored_identifier(from) == stored_identifier(to) {
+        // Already stored with the new name
+        return String::new();
+    }
+    format!(
+        "ALTER TABLE {} RENAME COLUMN {} TO {};",
+        helper::quote_reserved_word(tbl_name),
+        helper::quote_reserved_word(from),
+        helper::quote_reserved_word(to)
+    )
+}
+
+/// Like [`helper::sql_literal_value`], but writing lists as arrays, as
+/// they are stored on PostgreSQL.
+fn sql_literal_value(val: &SqlVal) -> Result<String> {
+    match val {
+        SqlVal::TextList(strings) => Ok(format!(
+            "ARRAY[{}]::TEXT[]",
+            strings
+                .iter()
+                .map(|s| format!("'{}'", s.replace('\'', "''")))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )),
+        _ => helper::sql_literal_value(val),
+    }
+}
+
 fn add_column(tbl_name: &str, col: &AColumn) -> Result<String> {
     let default: SqlVal = helper::column_default(col)?;
-    let mut stmts = vec![format!(
+    let mut stmts: Vec<String> = create_nocase_collation([col]).into_iter().collect();
+    stmts.push(format!(
         "ALTER TABLE {} ADD COLUMN {} DEFAULT {};",
         helper::quote_reserved_word(tbl_name),
         define_column(col)?,
-        helper::sql_literal_value(&default)?
-    )];
+        sql_literal_value(&default)?
+    ));
     if col.reference().is_some() {
         stmts.push(define_fkey_constraint(tbl_name, col));
     }
+    if let Some(comment) = col.comment() {
+        stmts.push(comment_on_column(tbl_name, col.name(), Some(comment)));
+    }
     let result = stmts.join("\n");
     Ok(result)
 }
 
+/// Returns the SQL to add the column `col`, which is to be backfilled,
+/// to the table `tbl_name`. If the table already exists in `current`,
+/// the column is added without filling in its default, which Postgres
+/// would otherwise do for every existing row, so that the batches which
+/// follow backfill it. The column is added only if it does not exist, so
+/// that a migration which fails after the batches may be run again.
+fn add_backfilled_column(current: &ADB, tbl_name: &str, col: &AColumn) -> Result<String> {
+    if current.get_table(tbl_name).is_none() {
+        return add_column(tbl_name, col);
+    }
+    let default: SqlVal = helper::column_default(col)?;
+    let mut stmts: Vec<String> = create_nocase_collation([col]).into_iter().collect();
+    stmts.push(format!(
+        "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {};",
+        helper::quote_reserved_word(tbl_name),
+        define_column(col)?
+    ));
+    stmts.push(format!(
+        "ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {};",
+        helper::quote_reserved_word(tbl_name),
+        helper::quote_reserved_word(col.name()),
+        sql_literal_value(&default)?
+    ));
+    if col.reference().is_some() {
+        stmts.push(define_fkey_constraint(tbl_name, col));
+    }
+    if let Some(comment) = col.comment() {
+        stmts.push(comment_on_column(tbl_name, col.name(), Some(comment)));
+    }
+    Ok(stmts.join("\n"))
+}
+
+/// Sets the NULL values of `col` to its default. If the table already
+/// exists in `current`, this is done in batches of
+/// [`BACKFILL_BATCH_SIZE`] rows, each committed on its own once the
+/// statements before it are, to bound the work done and the locks held
+/// by each statement.
+fn backfill_column(current: &ADB, tbl_name: &str, col: &AColumn) -> Result<String> {
+    let default = sql_literal_value(&helper::column_default(col)?)?;
+    let batched = current.get_table(tbl_name).is_some();
+    let tbl_name = helper::quote_reserved_word(tbl_name);
+    let col_name = helper::quote_reserved_word(col.name());
+    if !batched {
+        return Ok(format!(
+            "UPDATE {tbl_name} SET {col_name} = {default} WHERE {col_name} IS NULL;"
+        ));
+    }
+    Ok(format!(
+        "{BATCHED_MARKER}\nUPDATE {tbl_name} SET {col_name} = {default} WHERE {ROW_ID_COLUMN_NAME} IN \
+         (SELECT {ROW_ID_COLUMN_NAME} FROM {tbl_name} WHERE {col_name} IS NULL LIMIT {BACKFILL_BATCH_SIZE});"
+    ))
+}
+
 fn remove_column(tbl_name: &str, name: &str) -> String {
     format!(
         "ALTER TABLE {} DROP COLUMN {};",
@@ -770,13 +2265,25 @@ fn change_column(table: &ATable, old: &AColumn, new: &AColumn) -> Result<String>
             quote_reserved_word(new.name())
         ));
     }
-    if old.typeid()? != new.typeid()? {
-        // column type change
+    if old.typeid()? != new.typeid()? || old.collation() != new.collation() {
+        // column type or collation change
+        stmts.extend(create_nocase_collation([new]));
+        let collate = match (old.collation(), collate_constraint(new)) {
+            (_, Some(collate)) => format!(" {collate}"),
+            (Some(_), None) => " COLLATE \"default\"".to_string(),
+            (None, None) => String::new(),
+        };
+        let using = match convert_column_type(old.name(), old, new)? {
+            Some(expr) => format!(" USING {expr}"),
+            None => String::new(),
+        };
         stmts.push(format!(
-            "ALTER TABLE {} ALTER COLUMN {} SET DATA TYPE {};",
+            "ALTER TABLE {} ALTER COLUMN {} SET DATA TYPE {}{}{};",
             quote_reserved_word(tbl_name),
             quote_reserved_word(old.name()),
             col_sqltype(new)?,
+            collate,
+            using,
         ));
     }
     if old.nullable() != new.nullable() {
@@ -787,6 +2294,8 @@ fn change_column(table: &ATable, old: &AColumn, new: &AColumn) -> Result<String>
             if new.nullable() { "DROP" } else { "SET" }
         ));
     }
+    // Constraints are named after the stored names of the table and
+    // column, so are quoted to keep their case
     if old.is_pk() != new.is_pk() {
         // Change to primary key
         // Either way, drop the previous primary key
@@ -795,9 +2304,9 @@ fn change_column(table: &ATable, old: &AColumn, new: &AColumn) -> Result<String>
         if new.is_pk() {
             // Drop the old primary key
             stmts.push(format!(
-                "ALTER TABLE {} DROP CONSTRAINT IF EXISTS {}_pkey;",
+                "ALTER TABLE {} DROP CONSTRAINT IF EXISTS \"{}_pkey\";",
                 quote_reserved_word(tbl_name),
-                tbl_name
+                stored_identifier(tbl_name)
             ));
 
             // add the new primary key
@@ -822,10 +2331,10 @@ fn change_column(table: &ATable, old: &AColumn, new: &AColumn) -> Result<String>
         } else {
             // Standard constraint naming scheme
             stmts.push(format!(
-                "ALTER TABLE {} DROP CONSTRAINT {}_{}_key;",
+                "ALTER TABLE {} DROP CONSTRAINT \"{}_{}_key\";",
                 quote_reserved_word(tbl_name),
-                tbl_name,
-                &old.name()
+                stored_identifier(tbl_name),
+                stored_identifier(old.name())
             ));
         }
     }
@@ -841,19 +2350,19 @@ fn change_column(table: &ATable, old: &AColumn, new: &AColumn) -> Result<String>
                 "ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {};",
                 quote_reserved_word(tbl_name),
                 quote_reserved_word(old.name()),
-                helper::sql_literal_value(val)?
+                sql_literal_value(val)?
             ),
         });
     }
 
-    if old.reference() != new.reference() {
+    if old.reference() != new.reference() || old.on_delete() != new.on_delete() {
         if old.reference().is_some() {
             // Drop the old reference
             stmts.push(format!(
-                "ALTER TABLE {} DROP CONSTRAINT {}_{}_fkey;",
+                "ALTER TABLE {} DROP CONSTRAINT \"{}_{}_fkey\";",
                 quote_reserved_word(tbl_name),
-                tbl_name,
-                old.name()
+                stored_identifier(tbl_name),
+                stored_identifier(old.name())
             ));
         }
         if new.reference().is_some() {
@@ -865,6 +2374,35 @@ fn change_column(table: &ATable, old: &AColumn, new: &AColumn) -> Result<String>
     Ok(result)
 }
 
+/// The expression converting the values of column `name` from the type
+/// of `old` to that of `new`, or `None` if PostgreSQL converts them by
+/// itself, as it does between numbers and to strings. Strings formatted
+/// as UUIDs are converted to and from the 16 bytes of a `Uuid`, and
+/// other strings to and from their UTF-8 encoding. Other values are
+/// cast, which fails for values which cannot be converted.
+fn convert_column_type(name: &str, old: &AColumn, new: &AColumn) -> Result<Option<String>> {
+    use SqlType::{BigInt, Blob, Int, Real, Text};
+    use TypeIdentifier::Ty;
+    let col = helper::quote_reserved_word(name);
+    let (old_type, new_type) = (old.typeid()?, new.typeid()?);
+    if old_type == new_type {
+        return Ok(None);
+    }
+    Ok(Some(match (old_type, new_type) {
+        (Ty(Text), Ty(Blob)) => format!(
+            "CASE WHEN {col} ~* '^[0-9a-f]{{8}}(-[0-9a-f]{{4}}){{3}}-[0-9a-f]{{12}}$' \
+             THEN decode(replace({col}, '-', ''), 'hex') ELSE convert_to({col}, 'UTF8') END"
+        ),
+        (Ty(Blob), Ty(Text)) => format!(
+            "CASE WHEN length({col}) = 16 THEN regexp_replace(encode({col}, 'hex'), \
+             '(.{{8}})(.{{4}})(.{{4}})(.{{4}})(.{{12}})', '\\1-\\2-\\3-\\4-\\5') \
+             ELSE convert_from({col}, 'UTF8') END"
+        ),
+        (Ty(Int | BigInt | Real), Ty(Int | BigInt | Real)) | (_, Ty(Text)) => return Ok(None),
+        _ => format!("{col}::{}", col_sqltype(new)?),
+    }))
+}
+
 pub fn sql_insert_or_replace_with_placeholders(
     table: &str,
     columns: &[Column],
@@ -881,13 +2419,24 @@ pub fn sql_insert_or_replace_with_placeholders(
         n + 1
     });
     write!(w, ")").unwrap();
-    write!(w, " ON CONFLICT ({}) DO ", pkcol.name()).unwrap();
+    write!(
+        w,
+        " ON CONFLICT ({}) DO ",
+        helper::quote_reserved_word(pkcol.name())
+    )
+    .unwrap();
     if columns.len() > 1 {
         write!(w, "UPDATE SET (").unwrap();
         helper::list_columns(columns, w);
         write!(w, ") = (").unwrap();
         columns.iter().fold("", |sep, c| {
-            write!(w, "{}excluded.{}", sep, c.name()).unwrap();
+            write!(
+                w,
+                "{}excluded.{}",
+                sep,
+                helper::quote_reserved_word(c.name())
+            )
+            .unwrap();
             ", "
         });
         write!(w, ")").unwrap();
@@ -897,9 +2446,9 @@ pub fn sql_insert_or_replace_with_placeholders(
     }
 }
 
-fn pgtype_for_val(val: &SqlVal) -> postgres::types::Type {
+fn pgtype_for_sqltype(ty: Option<SqlType>) -> postgres::types::Type {
     use postgres::types::Type;
-    match val.sqltype() {
+    match ty {
         None => Type::UNKNOWN,
         Some(SqlType::Bool) => postgres::types::Type::BOOL,
         Some(SqlType::Int) => postgres::types::Type::INT4,
@@ -907,6 +2456,7 @@ fn pgtype_for_val(val: &SqlVal) -> postgres::types::Type {
         Some(SqlType::Real) => postgres::types::Type::FLOAT8,
         Some(SqlType::Text) => postgres::types::Type::TEXT,
         Some(SqlType::Blob) => postgres::types::Type::BYTEA,
+        Some(SqlType::TextList) => postgres::types::Type::TEXT_ARRAY,
         #[cfg(feature = "json")]
         Some(SqlType::Json) => postgres::types::Type::JSON,
         #[cfg(feature = "datetime")]
@@ -922,7 +2472,7 @@ fn pgtype_for_val(val: &SqlVal) -> postgres::types::Type {
 
 #[derive(Debug)]
 struct PgPlaceholderSource {
-    n: i8,
+    n: usize,
 }
 impl PgPlaceholderSource {
     fn new() -> Self {
@@ -936,3 +2486,110 @@ fn next_placeholder(&mut self) -> Cow<'_, str> {
         ret
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::helper::PlaceholderSource;
+
+    #[test]
+    fn parameter_changes() {
+        let set = |name: &str| Some(ParameterChange::Set(name.to_string()));
+        assert_eq!(
+            parameter_change("SET search_path TO app"),
+            set("search_path")
+        );
+        assert_eq!(
+            parameter_change("set SESSION Work_Mem='64MB';"),
+            set("work_mem")
+        );
+        assert_eq!(parameter_change("SET TIME ZONE 'UTC'"), set("timezone"));
+        assert_eq!(parameter_change("SET SCHEMA 'app'"), set("search_path"));
+        assert_eq!(
+            parameter_change("RESET search_path"),
+            Some(ParameterChange::Reset("search_path".to_string()))
+        );
+        assert_eq!(
+            parameter_change("RESET ALL"),
+            Some(ParameterChange::ResetAll)
+        );
+    }
+
+    #[test]
+    fn split_batches() {
+        assert_eq!(
+            helper::split_statements("CREATE TABLE a (x TEXT);\nINSERT INTO a VALUES ('x;y');\n"),
+            vec!["CREATE TABLE a (x TEXT)", "INSERT INTO a VALUES ('x;y')"]
+        );
+        assert_eq!(
+            helper::split_statements(
+                "-- first; second\nSELECT \"a;b\" FROM t; /* ; */ SELECT 'it''s;'"
+            ),
+            vec![
+                "-- first; second\nSELECT \"a;b\" FROM t",
+                "/* ; */ SELECT 'it''s;'"
+            ]
+        );
+        assert_eq!(
+            helper::split_statements(
+                "CREATE TRIGGER t AFTER INSERT ON a BEGIN UPDATE b SET n = n + 1; END; BEGIN; COMMIT;"
+            ),
+            vec![
+                "CREATE TRIGGER t AFTER INSERT ON a BEGIN UPDATE b SET n = n + 1; END",
+                "BEGIN",
+                "COMMIT"
+            ]
+        );
+        assert_eq!(
+            helper::split_statements(
+                "CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql;"
+            ),
+            vec!["CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql"]
+        );
+    }
+
+    #[test]
+    fn only_session_statements_are_recorded() {
+        let mut session = Vec::new();
+        let batch = "SET search_path TO app; INSERT INTO t VALUES ('SET; x'); \
+                     SET LOCAL work_mem = '1MB'; LISTEN jobs; SET a.b = 1;";
+        for statement in helper::split_statements(batch) {
+            record_session_change(&mut session, statement);
+        }
+        let recorded: Vec<String> = session
+            .iter()
+            .map(|change| match change {
+                SessionChange::Set { sql, .. } | SessionChange::Statement(sql) => sql.clone(),
+                SessionChange::Config { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(recorded, ["SET search_path TO app", "SET a.b = 1"]);
+    }
+
+    #[test]
+    fn only_latest_parameters_are_kept() {
+        let mut session = vec![SessionChange::Statement("LISTEN jobs".to_string())];
+        for name in ["search_path", "search_path", "work_mem"] {
+            forget_parameter(&mut session, Some(name));
+            session.push(SessionChange::Set {
+                name: name.to_string(),
+                sql: format!("SET {name} = 1"),
+            });
+        }
+        assert_eq!(session.len(), 3);
+        forget_parameter(&mut session, Some("work_mem"));
+        assert_eq!(session.len(), 2);
+        forget_parameter(&mut session, None);
+        assert!(matches!(session.as_slice(), [SessionChange::Statement(_)]));
+    }
+
+    #[test]
+    fn placeholders_past_i8() {
+        // Long IN lists bind far more than 127 parameters
+        let mut placeholders = PgPlaceholderSource::new();
+        for _ in 0..199 {
+            placeholders.next_placeholder();
+        }
+        assert_eq!(placeholders.next_placeholder(), "$200");
+    }
+}
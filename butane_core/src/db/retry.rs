@@ -0,0 +1,73 @@
+//! Retrying of transactions which fail due to contention with others.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How a transaction run with `transaction_with_retry` is retried when
+/// it fails with an error for which [`Error::is_retryable`] is true,
+/// such as a serialization failure.
+///
+/// Before each retry the transaction waits for a backoff delay, which
+/// starts at the base delay and doubles with each retry up to the
+/// maximum delay. A random jitter of up to half the delay is
+/// subtracted from it, so that transactions which conflicted with each
+/// other are unlikely to conflict again.
+///
+/// [`Error::is_retryable`]: crate::Error::is_retryable
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy which runs the transaction at most
+    /// `max_attempts` times, waiting 10ms before the first retry and at
+    /// most 1s before any retry.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the delay before the first retry. Returns `self` as this
+    /// method is expected to be chained.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Sets the longest delay before any retry. Returns `self` as this
+    /// method is expected to be chained.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// The most times the transaction is run, including the first.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The jittered delay before retry number `retry`, counting from 0.
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let half = delay.as_nanos() as u64 / 2;
+        // RandomState is seeded randomly, which suffices for jitter
+        let random = RandomState::new().build_hasher().finish();
+        delay - Duration::from_nanos(random % (half + 1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
@@ -6,37 +6,48 @@
 use std::pin::Pin;
 #[cfg(feature = "log")]
 use std::sync::Once;
+use std::time::Duration;
 
 use async_trait::async_trait;
 #[cfg(feature = "datetime")]
 use chrono::naive::{NaiveDate, NaiveDateTime};
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use pin_project::pin_project;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::hooks::Action;
+use rusqlite::OptionalExtension;
 
+use super::sqlite_dialect::{self, sql_for_expr, SQLitePlaceholderSource};
+#[cfg(feature = "datetime")]
+use super::sqlite_dialect::{SQLITE_DATE_FORMAT, SQLITE_DT_FORMAT};
 #[cfg(feature = "async")]
 use super::ConnectionAsync;
-use super::{helper, Backend, BackendRow, Column, RawQueryResult};
+use super::MAX_STATEMENT_PARAMS;
+use super::{helper, Backend, BackendRow, BlobRef, Column, RawQueryResult, ScalarFunction};
 use super::{BackendConnection, BackendTransaction, Connection, ConnectionMethods, Transaction};
+use super::{Capabilities, ColumnSchema, IndexSchema, RetryPolicy, TableSchema};
 use crate::db::connmethods::BackendRows;
-use crate::migrations::adb::ARef;
-use crate::migrations::adb::{AColumn, ATable, Operation, TypeIdentifier, ADB};
-use crate::query::{BoolExpr, Order};
-use crate::{debug, query, Error, Result, SqlType, SqlVal, SqlValRef};
-
-#[cfg(feature = "datetime")]
-const SQLITE_DT_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
-
-#[cfg(feature = "datetime")]
-const SQLITE_DATE_FORMAT: &str = "%Y-%m-%d";
+use crate::migrations::adb::{Operation, ADB};
+use crate::notify::{RowChange, RowOperation, UpdateHook};
+use crate::query::{BoolExpr, Expr, Order};
+use crate::{deadline, debug, query, Error, Result, SqlType, SqlVal, SqlValRef};
 
 /// The minimum SQLite version required by this backend.
 pub const SQLITE_MIN_VERSION: i32 = 3035000;
 
+/// The first SQLite version with the `unhex` function built in.
+const UNHEX_VERSION: i32 = 3041000;
+
+/// Number of virtual machine instructions between checks of the deadline.
+const DEADLINE_CHECK_OPS: i32 = 1000;
+
 /// The name of the sqlite backend.
 pub const BACKEND_NAME: &str = "sqlite";
 /// The internal row creation order field name.
 pub const ROW_ID_COLUMN_NAME: &str = "rowid";
 
+pub use super::sqlite_dialect::sql_insert_or_update;
+
 #[cfg(feature = "log")]
 fn log_callback(error_code: std::ffi::c_int, message: &str) {
     match error_code {
@@ -64,13 +75,273 @@ pub fn new() -> SQLiteBackend {
     }
 }
 impl SQLiteBackend {
-    fn connect(&self, path: &str) -> Result<SQLiteConnection> {
-        let connection = SQLiteConnection::open(Path::new(path))?;
-        connection.execute("PRAGMA foreign_keys = ON")?;
+    fn connect(&self, conn_str: &str) -> Result<SQLiteConnection> {
+        let (path, options) = SQLiteOptions::from_connection_string(conn_str)?;
+        let mut connection = SQLiteConnection::open(Path::new(&path))?;
+        options.apply(&connection.conn)?;
+        connection.busy_retry = options
+            .busy_retries
+            .map(|retries| RetryPolicy::new(retries.saturating_add(1)));
         Ok(connection)
     }
 }
 
+/// Journal mode of an SQLite database, set with `PRAGMA journal_mode`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JournalMode {
+    /// The rollback journal is deleted after each transaction (the default).
+    Delete,
+    /// The rollback journal is truncated after each transaction.
+    Truncate,
+    /// The header of the rollback journal is zeroed after each transaction.
+    Persist,
+    /// The rollback journal is kept in memory.
+    Memory,
+    /// A write-ahead log is used, allowing readers to continue while writing.
+    Wal,
+    /// There is no rollback journal, so transactions cannot be rolled back safely.
+    Off,
+}
+
+/// How often SQLite waits for data to reach storage, set with `PRAGMA synchronous`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Synchronous {
+    /// Never wait.
+    Off,
+    /// Wait at the most critical moments, which is safe in [`JournalMode::Wal`].
+    Normal,
+    /// Wait for every transaction (the default).
+    Full,
+    /// As `Full`, and also wait for the directory after deleting the rollback journal.
+    Extra,
+}
+
+macro_rules! pragma_enum_strings {
+    ($ty:ident { $($variant:ident => $name:literal),* $(,)? }) => {
+        impl $ty {
+            fn as_str(&self) -> &'static str {
+                match self {
+                    $($ty::$variant => $name,)*
+                }
+            }
+        }
+        impl std::str::FromStr for $ty {
+            type Err = Error;
+            fn from_str(s: &str) -> Result<Self> {
+                match s.to_ascii_lowercase().as_str() {
+                    $($name => Ok($ty::$variant),)*
+                    _ => Err(Error::UnknownConnectString(format!(
+                        "unknown {} '{s}'",
+                        stringify!($ty)
+                    ))),
+                }
+            }
+        }
+    };
+}
+pragma_enum_strings!(JournalMode {
+    Delete => "delete",
+    Truncate => "truncate",
+    Persist => "persist",
+    Memory => "memory",
+    Wal => "wal",
+    Off => "off",
+});
+pragma_enum_strings!(Synchronous {
+    Off => "off",
+    Normal => "normal",
+    Full => "full",
+    Extra => "extra",
+});
+
+/// Options applied to each SQLite connection when it is opened.
+///
+/// The options are carried as query parameters of a `file:` URI
+/// connection string, so they also apply to each connection opened by a
+/// pool. Build one with [`connection_string`](SQLiteOptions::connection_string),
+/// or write the parameters by hand, e.g.
+/// `file:app.db?journal_mode=wal&busy_timeout=5000`.
+/// Options which are not set keep the SQLite defaults, except that
+/// foreign keys are enforced unless `foreign_keys` is false.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SQLiteOptions {
+    /// `journal_mode`.
+    pub journal_mode: Option<JournalMode>,
+    /// `synchronous`.
+    pub synchronous: Option<Synchronous>,
+    /// How long to wait for a lock held by another connection before failing,
+    /// as the `busy_timeout` parameter in milliseconds. SQLite waits for
+    /// up to 5 seconds if this is not set.
+    pub busy_timeout: Option<Duration>,
+    /// How many times a statement run outside a transaction is retried
+    /// if it still fails because the database is busy, `busy_retries`.
+    /// Each retry first waits for a backoff delay, as described by
+    /// [`RetryPolicy`]. Statements in a transaction are not retried, as
+    /// the whole transaction must be instead.
+    pub busy_retries: Option<u32>,
+    /// Whether foreign key constraints are enforced, `foreign_keys`.
+    pub foreign_keys: Option<bool>,
+    /// `cache_size`: a number of pages if positive, or of kibibytes if negative.
+    pub cache_size: Option<i64>,
+    /// `page_size` in bytes, which only takes effect when the database is
+    /// created or vacuumed outside of WAL mode.
+    pub page_size: Option<u32>,
+}
+
+impl SQLiteOptions {
+    /// Options keeping the defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets the journal mode.
+    pub fn journal_mode(mut self, mode: JournalMode) -> Self {
+        self.journal_mode = Some(mode);
+        self
+    }
+    /// Sets the synchronous level.
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = Some(synchronous);
+        self
+    }
+    /// Sets how long to wait for a lock held by another connection.
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+    /// Sets how many times a statement failing because the database is
+    /// busy is retried.
+    pub fn busy_retries(mut self, retries: u32) -> Self {
+        self.busy_retries = Some(retries);
+        self
+    }
+    /// Sets whether foreign key constraints are enforced.
+    pub fn foreign_keys(mut self, enabled: bool) -> Self {
+        self.foreign_keys = Some(enabled);
+        self
+    }
+    /// Sets the cache size, in pages if positive or kibibytes if negative.
+    pub fn cache_size(mut self, size: i64) -> Self {
+        self.cache_size = Some(size);
+        self
+    }
+    /// Sets the page size in bytes.
+    pub fn page_size(mut self, size: u32) -> Self {
+        self.page_size = Some(size);
+        self
+    }
+
+    /// The connection string for the database at `path` with these options.
+    /// `path` may be a file name or a `file:` URI.
+    pub fn connection_string(&self, path: &str) -> String {
+        let mut params: Vec<String> = Vec::new();
+        if let Some(mode) = self.journal_mode {
+            params.push(format!("journal_mode={}", mode.as_str()));
+        }
+        if let Some(synchronous) = self.synchronous {
+            params.push(format!("synchronous={}", synchronous.as_str()));
+        }
+        if let Some(timeout) = self.busy_timeout {
+            params.push(format!("busy_timeout={}", timeout.as_millis()));
+        }
+        if let Some(retries) = self.busy_retries {
+            params.push(format!("busy_retries={retries}"));
+        }
+        if let Some(enabled) = self.foreign_keys {
+            params.push(format!("foreign_keys={enabled}"));
+        }
+        if let Some(size) = self.cache_size {
+            params.push(format!("cache_size={size}"));
+        }
+        if let Some(size) = self.page_size {
+            params.push(format!("page_size={size}"));
+        }
+        if params.is_empty() {
+            return path.to_string();
+        }
+        if path.starts_with("file:") {
+            let separator = if path.contains('?') { '&' } else { '?' };
+            format!("{path}{separator}{}", params.join("&"))
+        } else {
+            // Characters with a special meaning in URIs are escaped
+            let path = path
+                .replace('%', "%25")
+                .replace('?', "%3f")
+                .replace('#', "%23");
+            format!("file:{path}?{}", params.join("&"))
+        }
+    }
+
+    /// Splits the options out of the query parameters of a `file:` URI
+    /// connection string, returning the connection string to open and the
+    /// options. Other parameters, such as `mode`, are left for SQLite.
+    /// As with SQLite, a `?` in other connection strings is part of the
+    /// file name.
+    pub fn from_connection_string(conn_str: &str) -> Result<(String, Self)> {
+        let mut options = Self::default();
+        let query = conn_str
+            .split_once('?')
+            .filter(|(base, _)| base.starts_with("file:"));
+        let Some((base, query)) = query else {
+            return Ok((conn_str.to_string(), options));
+        };
+        let mut others: Vec<&str> = Vec::new();
+        for param in query.split('&') {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            let invalid =
+                || Error::UnknownConnectString(format!("invalid {name} '{value}' for sqlite"));
+            match name {
+                "journal_mode" => options.journal_mode = Some(value.parse()?),
+                "synchronous" => options.synchronous = Some(value.parse()?),
+                "busy_timeout" => {
+                    let millis = value.parse().map_err(|_| invalid())?;
+                    options.busy_timeout = Some(Duration::from_millis(millis));
+                }
+                "busy_retries" => {
+                    options.busy_retries = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "foreign_keys" => {
+                    options.foreign_keys = Some(match value {
+                        "true" | "on" | "1" => true,
+                        "false" | "off" | "0" => false,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "cache_size" => options.cache_size = Some(value.parse().map_err(|_| invalid())?),
+                "page_size" => options.page_size = Some(value.parse().map_err(|_| invalid())?),
+                _ => others.push(param),
+            }
+        }
+        let path = if others.is_empty() {
+            base.to_string()
+        } else {
+            format!("{base}?{}", others.join("&"))
+        };
+        Ok((path, options))
+    }
+
+    fn apply(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // The page size must be set before the journal mode, as it
+        // cannot be changed in WAL mode.
+        if let Some(size) = self.page_size {
+            conn.pragma_update(None, "page_size", size)?;
+        }
+        if let Some(mode) = self.journal_mode {
+            conn.pragma_update_and_check(None, "journal_mode", mode.as_str(), |_| Ok(()))?;
+        }
+        if let Some(synchronous) = self.synchronous {
+            conn.pragma_update(None, "synchronous", synchronous.as_str())?;
+        }
+        if let Some(timeout) = self.busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+        if let Some(size) = self.cache_size {
+            conn.pragma_update(None, "cache_size", size)?;
+        }
+        conn.pragma_update(None, "foreign_keys", self.foreign_keys.unwrap_or(true))?;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl Backend for SQLiteBackend {
     fn name(&self) -> &'static str {
@@ -81,24 +352,26 @@ fn row_id_column(&self) -> Option<&'static str> {
         Some(ROW_ID_COLUMN_NAME)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        // RETURNING is only available from SQLite 3.35, so is not relied upon
+        Capabilities {
+            ctes: true,
+            savepoints: true,
+            auto_pk: true,
+            server_side_limit: true,
+            native_upsert: true,
+            // Changed columns are copied into a rebuilt table
+            alter_column: true,
+            ..Capabilities::default()
+        }
+    }
+
     fn create_migration_sql(&self, current: &ADB, ops: Vec<Operation>) -> Result<String> {
-        let mut current: ADB = (*current).clone();
-        let mut lines = ops
-            .into_iter()
-            .map(|o| {
-                let sql = sql_for_op(&mut current, &o);
-                current.transform_with(o);
-                sql
-            })
-            .collect::<Result<Vec<String>>>()?;
-        lines.retain(|s| !s.is_empty());
-        Ok(lines.join("\n"))
+        sqlite_dialect::create_migration_sql(current, ops)
     }
 
     fn connect(&self, path: &str) -> Result<Connection> {
-        Ok(Connection {
-            conn: Box::new(self.connect(path)?),
-        })
+        Ok(Connection::new(Box::new(self.connect(path)?)))
     }
 
     #[cfg(feature = "async-adapter")]
@@ -116,6 +389,7 @@ async fn connect_async(&self, _path: &str) -> Result<ConnectionAsync> {
 #[derive(Debug)]
 pub struct SQLiteConnection {
     conn: rusqlite::Connection,
+    busy_retry: Option<RetryPolicy>,
 }
 impl SQLiteConnection {
     fn open(path: impl AsRef<Path>) -> Result<Self> {
@@ -133,9 +407,71 @@ fn open(path: impl AsRef<Path>) -> Result<Self> {
             _ = unsafe { rusqlite::trace::config_log(Some(log_callback)) };
         });
 
-        rusqlite::Connection::open(path)
-            .map(|conn| SQLiteConnection { conn })
-            .map_err(|e| e.into())
+        let conn = rusqlite::Connection::open(path)?;
+        // Interrupt statements still running when the deadline passes
+        conn.progress_handler(DEADLINE_CHECK_OPS, Some(|| deadline::remaining().is_err()));
+        if rusqlite::version_number() < UNHEX_VERSION {
+            // Migrations converting strings to blobs use unhex
+            conn.create_scalar_function(
+                "unhex",
+                2,
+                FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+                |ctx| {
+                    let text = ctx.get::<Option<String>>(0)?;
+                    let ignored = ctx.get::<String>(1)?;
+                    Ok(text.and_then(|text| unhex(&text, &ignored)))
+                },
+            )?;
+        }
+        Ok(SQLiteConnection {
+            conn,
+            busy_retry: None,
+        })
+    }
+
+    /// Runs `op`, retrying it as set by the `busy_retries` option while
+    /// it fails because the database is busy. It is only retried if it
+    /// failed outside a transaction without changing anything, so that
+    /// none of its effect is repeated, and if the deadline allows.
+    fn retry_busy<'c, T>(
+        &'c self,
+        mut op: impl FnMut(&'c rusqlite::Connection) -> Result<T>,
+    ) -> Result<T> {
+        let Some(policy) = &self.busy_retry else {
+            return op(&self.conn);
+        };
+        let mut retry = 0;
+        loop {
+            let changes = self.conn.total_changes();
+            let e = match op(&self.conn) {
+                Err(e) if e.is_retryable() => e,
+                result => return result,
+            };
+            let delay = policy.delay(retry);
+            retry += 1;
+            let unchanged = self.conn.is_autocommit() && self.conn.total_changes() == changes;
+            let in_time = deadline::remaining()?.map_or(true, |remaining| delay < remaining);
+            if retry >= policy.max_attempts() || !unchanged || !in_time {
+                return Err(e);
+            }
+            if cfg!(feature = "log") {
+                debug!("database busy, retrying in {delay:?}");
+            }
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// Like `retry_busy`, but passes `value` to `op`, cloning it only if
+    /// the operation may be retried.
+    fn retry_busy_with<'c, V: Clone, T>(
+        &'c self,
+        value: V,
+        mut op: impl FnMut(&'c rusqlite::Connection, V) -> Result<T>,
+    ) -> Result<T> {
+        if self.busy_retry.is_none() {
+            return op(&self.conn, value);
+        }
+        self.retry_busy(|conn| op(conn, value.clone()))
     }
 
     // For use with connection_method_wrapper macro
@@ -149,6 +485,25 @@ impl ConnectionMethods for SQLiteConnection {
     fn execute(&self, sql: &str) -> Result<()> {
         ConnectionMethods::execute(self.wrapped_connection_methods()?, sql)
     }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.retry_busy(|conn| conn.execute_params(sql, params))
+    }
+    fn max_statement_params(&self) -> usize {
+        self.wrapped_connection_methods()
+            .map_or(MAX_STATEMENT_PARAMS, |conn| conn.max_statement_params())
+    }
+    fn in_transaction(&self) -> bool {
+        self.wrapped_connection_methods()
+            .is_ok_and(|conn| conn.in_transaction())
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.retry_busy(|conn| conn.query_params(sql, params, columns))
+    }
     fn query<'a, 'c>(
         &'c self,
         table: &str,
@@ -158,8 +513,9 @@ fn query<'a, 'c>(
         offset: Option<i32>,
         sort: Option<&[crate::query::Order]>,
     ) -> Result<RawQueryResult<'c>> {
-        self.wrapped_connection_methods()?
-            .query(table, columns, expr, limit, offset, sort)
+        self.retry_busy_with(expr, |conn, expr| {
+            conn.query(table, columns, expr, limit, offset, sort)
+        })
     }
     fn insert_returning_pk(
         &self,
@@ -168,12 +524,30 @@ fn insert_returning_pk(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<SqlVal> {
-        self.wrapped_connection_methods()?
-            .insert_returning_pk(table, columns, pkcol, values)
+        self.retry_busy(|conn| conn.insert_returning_pk(table, columns, pkcol, values))
+    }
+    fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.retry_busy(|conn| conn.insert_returning(table, columns, pkcol, returning, values))
+    }
+    fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.retry_busy(|conn| conn.insert_or_ignore(table, columns, pkcol, returning, values))
     }
     fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
-        self.wrapped_connection_methods()?
-            .insert_only(table, columns, values)
+        self.retry_busy(|conn| conn.insert_only(table, columns, values))
     }
     fn insert_or_replace(
         &self,
@@ -182,8 +556,7 @@ fn insert_or_replace(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        self.wrapped_connection_methods()?
-            .insert_or_replace(table, columns, pkcol, values)
+        self.retry_busy(|conn| conn.insert_or_replace(table, columns, pkcol, values))
     }
     fn update(
         &self,
@@ -193,18 +566,84 @@ fn update(
         columns: &[Column],
         values: &[SqlValRef<'_>],
     ) -> Result<()> {
-        self.wrapped_connection_methods()?
-            .update(table, pkcol, pk, columns, values)
+        self.retry_busy_with((pkcol, pk), |conn, (pkcol, pk)| {
+            conn.update(table, pkcol, pk, columns, values)
+        })
     }
     fn delete(&self, table: &str, pkcol: &'static str, pk: SqlVal) -> Result<()> {
-        self.wrapped_connection_methods()?.delete(table, pkcol, pk)
+        self.retry_busy_with(pk, |conn, pk| conn.delete(table, pkcol, pk))
     }
     fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
-        self.wrapped_connection_methods()?.delete_where(table, expr)
+        self.retry_busy_with(expr, |conn, expr| conn.delete_where(table, expr))
+    }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.retry_busy_with((assignments, expr), |conn, (assignments, expr)| {
+            conn.update_where(table, assignments, expr)
+        })
     }
     fn has_table(&self, table: &str) -> Result<bool> {
         self.wrapped_connection_methods()?.has_table(table)
     }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        self.wrapped_connection_methods()?.list_tables()
+    }
+    fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.wrapped_connection_methods()?.table_schema(table)
+    }
+    fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.wrapped_connection_methods()?.list_indexes(table)
+    }
+    fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.wrapped_connection_methods()?.notify(channel, payload)
+    }
+    fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .set_config(name, value, local)
+    }
+    fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.wrapped_connection_methods()?.current_config(name)
+    }
+    fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .declare_cursor(name, table, columns, expr, limit, offset, sort)
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .fetch_cursor(name, columns, count)
+    }
+    fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.wrapped_connection_methods()?.blob_len(blob)
+    }
+    fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.wrapped_connection_methods()?
+            .read_blob(blob, offset, len)
+    }
+    fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .write_blob(blob, offset, data)
+    }
+    fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.wrapped_connection_methods()?.allocate_blob(blob, len)
+    }
 }
 
 impl BackendConnection for SQLiteConnection {
@@ -222,6 +661,35 @@ fn backend_name(&self) -> &'static str {
     fn is_closed(&self) -> bool {
         false
     }
+    fn set_update_hook(&self, hook: Option<UpdateHook>) -> Result<()> {
+        self.conn.update_hook(hook.map(|mut hook| {
+            move |action, database: &str, table: &str, rowid| {
+                let operation = match action {
+                    Action::SQLITE_INSERT => RowOperation::Insert,
+                    Action::SQLITE_UPDATE => RowOperation::Update,
+                    Action::SQLITE_DELETE => RowOperation::Delete,
+                    _ => return,
+                };
+                hook(RowChange {
+                    operation,
+                    database,
+                    table,
+                    rowid,
+                })
+            }
+        }));
+        Ok(())
+    }
+    fn create_function(&self, name: &str, n_args: i32, func: ScalarFunction) -> Result<()> {
+        self.conn
+            .create_scalar_function(name, n_args, FunctionFlags::SQLITE_UTF8, move |ctx| {
+                let args: Vec<SqlVal> = (0..ctx.len())
+                    .map(|idx| sql_val_from_rusqlite_value(ctx.get_raw(idx)))
+                    .collect();
+                func(&args).map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))
+            })?;
+        Ok(())
+    }
 }
 
 impl ConnectionMethods for rusqlite::Connection {
@@ -232,6 +700,38 @@ fn execute(&self, sql: &str) -> Result<()> {
         self.execute_batch(sql.as_ref())?;
         Ok(())
     }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        let sql = helper::translate_placeholders(sql, &mut SQLitePlaceholderSource::new());
+        if cfg!(feature = "log") {
+            debug!("execute sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {params:?}");
+        }
+        Ok(self.execute(&sql, rusqlite::params_from_iter(params))?)
+    }
+    fn max_statement_params(&self) -> usize {
+        // SQLITE_MAX_VARIABLE_NUMBER, since SQLite 3.32
+        32766
+    }
+    fn in_transaction(&self) -> bool {
+        !self.is_autocommit()
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        _columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        let sql = helper::translate_placeholders(sql, &mut SQLitePlaceholderSource::new());
+        if cfg!(feature = "log") {
+            debug!("query sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {params:?}");
+        }
+        let stmt = self.prepare_cached(&sql)?;
+        let adapter = QueryAdapter::new(stmt, rusqlite::params_from_iter(params))?;
+        Ok(Box::new(adapter))
+    }
 
     fn query<'c>(
         &'c self,
@@ -245,18 +745,19 @@ fn query<'c>(
         let mut sqlquery = String::new();
         helper::sql_select(columns, table, &mut sqlquery);
         let mut values: Vec<SqlVal> = Vec::new();
+        let mut pls = SQLitePlaceholderSource::new();
         if let Some(expr) = expr {
             sqlquery.write_str(" WHERE ").unwrap();
             sql_for_expr(
                 query::Expr::Condition(Box::new(expr)),
                 &mut values,
-                &mut SQLitePlaceholderSource::new(),
+                &mut pls,
                 &mut sqlquery,
             );
         }
 
         if let Some(order) = order {
-            helper::sql_order(order, &mut sqlquery)
+            helper::sql_order(order, sql_for_expr, &mut values, &mut pls, &mut sqlquery)
         }
 
         if let Some(limit) = limit {
@@ -275,10 +776,15 @@ fn query<'c>(
 
         debug!("query sql {sqlquery}");
         #[cfg(feature = "debug")]
-        debug!("values {values:?}");
+        debug!("values {}", super::params_for_log(table, &values));
 
-        let stmt = self.prepare(&sqlquery)?;
-        let adapter = QueryAdapter::new(stmt, rusqlite::params_from_iter(values))?;
+        let in_statement =
+            |e: Error| e.in_statement(&sqlquery, table, values.iter().map(SqlVal::as_ref));
+        let stmt = self
+            .prepare_cached(&sqlquery)
+            .map_err(|e| in_statement(e.into()))?;
+        let params = rusqlite::params_from_iter(values.iter().map(SqlVal::as_ref));
+        let adapter = QueryAdapter::new(stmt, params).map_err(in_statement)?;
         Ok(Box::new(adapter))
     }
     fn insert_returning_pk(
@@ -288,6 +794,17 @@ fn insert_returning_pk(
         pkcol: &Column,
         values: &[SqlValRef<'_>],
     ) -> Result<SqlVal> {
+        let mut returned = self.insert_returning(table, columns, pkcol, &[], values)?;
+        Ok(returned.remove(0))
+    }
+    fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
         let mut sql = String::new();
         helper::sql_insert_with_placeholders(
             table,
@@ -298,19 +815,37 @@ fn insert_returning_pk(
         if cfg!(feature = "log") {
             debug!("insert sql {sql}");
             #[cfg(feature = "debug")]
-            debug!("values {values:?}");
-        }
-        self.execute(&sql, rusqlite::params_from_iter(values))?;
-        let pk: SqlVal = self.query_row_and_then(
-            &format!(
-                "SELECT {} FROM {} WHERE ROWID = last_insert_rowid()",
-                helper::quote_reserved_word(pkcol.name()),
-                helper::quote_reserved_word(table),
-            ),
-            [],
-            |row| sql_val_from_rusqlite(row.get_ref_unwrap(0), pkcol),
-        )?;
-        Ok(pk)
+            debug!("values {}", super::params_for_log(table, &values));
+        }
+        execute_generated(self, &sql, table, values.iter().cloned())?;
+        select_last_inserted(self, table, pkcol, returning)
+    }
+    fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        let mut sql = String::new();
+        helper::sql_insert_with_placeholders(
+            table,
+            columns,
+            &mut SQLitePlaceholderSource::new(),
+            &mut sql,
+        );
+        // ON CONFLICT cannot follow DEFAULT VALUES, so use OR IGNORE
+        let sql = sql.replacen("INSERT ", "INSERT OR IGNORE ", 1);
+        if cfg!(feature = "log") {
+            debug!("insert sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("values {}", super::params_for_log(table, &values));
+        }
+        if execute_generated(self, &sql, table, values.iter().cloned())? == 0 {
+            return Ok(None);
+        }
+        select_last_inserted(self, table, pkcol, returning).map(Some)
     }
     fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
         let mut sql = String::new();
@@ -323,9 +858,9 @@ fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>])
         if cfg!(feature = "log") {
             debug!("insert sql {sql}");
             #[cfg(feature = "debug")]
-            debug!("values {values:?}");
+            debug!("values {}", super::params_for_log(table, &values));
         }
-        self.execute(&sql, rusqlite::params_from_iter(values))?;
+        execute_generated(self, &sql, table, values.iter().cloned())?;
         Ok(())
     }
     fn insert_or_replace(
@@ -337,7 +872,7 @@ fn insert_or_replace(
     ) -> Result<()> {
         let mut sql = String::new();
         sql_insert_or_update(table, columns, pkcol, &mut sql);
-        self.execute(&sql, rusqlite::params_from_iter(values))?;
+        execute_generated(self, &sql, table, values.iter().cloned())?;
         Ok(())
     }
     fn update(
@@ -360,40 +895,381 @@ fn update(
         if cfg!(feature = "log") {
             debug!("update sql {sql}");
             #[cfg(feature = "debug")]
-            debug!("placeholders {placeholder_values:?}");
+            debug!(
+                "placeholders {}",
+                super::params_for_log(table, &placeholder_values)
+            );
         }
-        self.execute(&sql, rusqlite::params_from_iter(placeholder_values))?;
+        execute_generated(self, &sql, table, placeholder_values.iter().cloned())?;
         Ok(())
     }
     fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
-        let mut sql = String::new();
+        let mut condition = String::new();
         let mut values: Vec<SqlVal> = Vec::new();
-        write!(
-            &mut sql,
-            "DELETE FROM {} WHERE ",
-            helper::quote_reserved_word(table)
-        )
-        .unwrap();
         sql_for_expr(
             query::Expr::Condition(Box::new(expr)),
             &mut values,
             &mut SQLitePlaceholderSource::new(),
-            &mut sql,
+            &mut condition,
+        );
+        let sql = format!(
+            "DELETE FROM {} WHERE {}",
+            helper::quote_reserved_word(table),
+            condition
         );
         if cfg!(feature = "log") {
             debug!("delete where sql {sql}");
             #[cfg(feature = "debug")]
-            debug!("placeholders {values:?}");
+            debug!("placeholders {}", super::params_for_log(table, &values));
+        }
+        let foreign_keys: bool = self.query_row("PRAGMA foreign_keys;", [], |row| row.get(0))?;
+        if !foreign_keys {
+            emulate_on_delete(self, table, &condition, &values, 0)?;
+        }
+        execute_generated(self, &sql, table, values.iter().map(SqlVal::as_ref))
+    }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        let mut sql = String::new();
+        let mut values: Vec<SqlVal> = Vec::new();
+        helper::sql_update_where(
+            table,
+            assignments,
+            expr,
+            sql_for_expr,
+            &mut values,
+            &mut SQLitePlaceholderSource::new(),
+            &mut sql,
+        );
+        if cfg!(feature = "log") {
+            debug!("update where sql {sql}");
+            #[cfg(feature = "debug")]
+            debug!("placeholders {}", super::params_for_log(table, &values));
         }
-        let cnt = self.execute(&sql, rusqlite::params_from_iter(values))?;
-        Ok(cnt)
+        execute_generated(self, &sql, table, values.iter().map(SqlVal::as_ref))
     }
     fn has_table(&self, table: &str) -> Result<bool> {
-        let mut stmt =
-            self.prepare("SELECT name FROM sqlite_master WHERE type='table' AND name=?;")?;
+        // A table qualified with a schema is only looked for in that attached database
+        let (schema, table) = table.split_once('.').unwrap_or(("main", table));
+        let mut stmt = self.prepare(&format!(
+            "SELECT name FROM {schema}.sqlite_master WHERE type='table' AND name=?;"
+        ))?;
         let mut rows = stmt.query([table])?;
         Ok(rows.next()?.is_some())
     }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        let mut stmt = self.prepare(
+            "SELECT name FROM main.sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name;",
+        )?;
+        let names = stmt.query_map([], |row| row.get(0))?;
+        Ok(names.collect::<rusqlite::Result<Vec<String>>>()?)
+    }
+    fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+        let mut stmt = self.prepare(
+            "SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1, ?2) ORDER BY cid;",
+        )?;
+        let columns = stmt
+            .query_map([name, schema], |row| {
+                Ok(ColumnSchema {
+                    name: row.get(0)?,
+                    type_name: row.get(1)?,
+                    nullable: !row.get::<_, bool>(2)?,
+                    default: row.get(3)?,
+                    primary_key: row.get::<_, i64>(4)? > 0,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<ColumnSchema>>>()?;
+        // Every table has at least one column
+        if columns.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(TableSchema {
+            name: table.to_string(),
+            columns,
+        }))
+    }
+    fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+        let mut stmt = self.prepare(
+            "SELECT name, \"unique\" FROM pragma_index_list(?1, ?2) WHERE origin != 'pk' ORDER BY name;",
+        )?;
+        let indexes = stmt
+            .query_map([name, schema], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<(String, bool)>>>()?;
+        let mut stmt = self.prepare(
+            "SELECT name FROM pragma_index_info(?1, ?2) WHERE name IS NOT NULL ORDER BY seqno;",
+        )?;
+        indexes
+            .into_iter()
+            .map(|(name, unique)| {
+                let columns = stmt
+                    .query_map([name.as_str(), schema], |row| row.get(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()?;
+                Ok(IndexSchema {
+                    name,
+                    columns,
+                    unique,
+                })
+            })
+            .collect()
+    }
+    fn notify(&self, _channel: &str, _payload: &str) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "notifications"))
+    }
+    fn set_config(&self, _name: &str, _value: &str, _local: bool) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "configuration parameters"))
+    }
+    fn current_config(&self, _name: &str) -> Result<Option<String>> {
+        Err(Error::Unsupported(BACKEND_NAME, "configuration parameters"))
+    }
+    fn declare_cursor(
+        &self,
+        _name: &str,
+        _table: &str,
+        _columns: &[Column],
+        _expr: Option<BoolExpr>,
+        _limit: Option<i32>,
+        _offset: Option<i32>,
+        _sort: Option<&[Order]>,
+    ) -> Result<()> {
+        Err(Error::Unsupported(BACKEND_NAME, "server-side cursors"))
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        _name: &str,
+        _columns: &[Column],
+        _count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        Err(Error::Unsupported(BACKEND_NAME, "server-side cursors"))
+    }
+    fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        if blob.large_object {
+            return Err(Error::Unsupported(BACKEND_NAME, "large objects"));
+        }
+        let sql = format!(
+            "SELECT length({}) FROM {} WHERE {} = ?;",
+            helper::quote_reserved_word(blob.column),
+            helper::quote_reserved_word(blob.table),
+            helper::quote_reserved_word(blob.pkcol)
+        );
+        let len: Option<i64> = self
+            .query_row(&sql, [&blob.pk], |row| row.get(0))
+            .optional()?
+            .ok_or(Error::NoSuchObject)?;
+        Ok(len.map(|len| len as u64))
+    }
+    fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let handle = open_blob(self, blob, true)?;
+        let mut data = vec![0; len];
+        let read = handle.read_at(&mut data, offset as usize)?;
+        data.truncate(read);
+        Ok(data)
+    }
+    fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        let mut handle = open_blob(self, blob, false)?;
+        handle.write_at(data, offset as usize)?;
+        Ok(())
+    }
+    fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        if blob.large_object {
+            return Err(Error::Unsupported(BACKEND_NAME, "large objects"));
+        }
+        let sql = format!(
+            "UPDATE {} SET {} = zeroblob(?) WHERE {} = ?;",
+            helper::quote_reserved_word(blob.table),
+            helper::quote_reserved_word(blob.column),
+            helper::quote_reserved_word(blob.pkcol)
+        );
+        let len = i64::try_from(len).map_err(|_| Error::Internal("blob too large".to_string()))?;
+        let params: [&dyn rusqlite::ToSql; 2] = [&len, &blob.pk];
+        match self.execute(&sql, params.as_slice())? {
+            0 => Err(Error::NoSuchObject),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Decodes the hexadecimal digits of `text` as SQLite's `unhex` does,
+/// skipping characters in `ignored`. Returns `None` if any other
+/// character is not a hexadecimal digit or the digits are unpaired.
+fn unhex(text: &str, ignored: &str) -> Option<Vec<u8>> {
+    let digits = text
+        .chars()
+        .filter(|c| !ignored.contains(*c))
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect(),
+    )
+}
+
+/// Opens `blob` for incremental I/O, finding its row by primary key.
+fn open_blob<'c>(
+    conn: &'c rusqlite::Connection,
+    blob: &BlobRef,
+    read_only: bool,
+) -> Result<rusqlite::blob::Blob<'c>> {
+    let sql = format!(
+        "SELECT rowid FROM {} WHERE {} = ?;",
+        helper::quote_reserved_word(blob.table),
+        helper::quote_reserved_word(blob.pkcol)
+    );
+    let rowid: i64 = conn
+        .query_row(&sql, [&blob.pk], |row| row.get(0))
+        .optional()?
+        .ok_or(Error::NoSuchObject)?;
+    // A table qualified with a schema is in that attached database
+    let (database, table) = match blob.table.split_once('.') {
+        Some((schema, table)) => (rusqlite::DatabaseName::Attached(schema), table),
+        None => (rusqlite::DatabaseName::Main, blob.table),
+    };
+    Ok(conn.blob_open(database, table, blob.column, rowid, read_only)?)
+}
+
+/// Executes the statement `sql` generated for `table`, attaching it to
+/// any error. The statement is cached, as the same statements are
+/// generated for each object saved.
+fn execute_generated<'a>(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    table: &str,
+    params: impl IntoIterator<Item = SqlValRef<'a>> + Clone,
+) -> Result<usize> {
+    conn.prepare_cached(sql)
+        .and_then(|mut stmt| stmt.execute(rusqlite::params_from_iter(params.clone())))
+        .map_err(|e| Error::from(e).in_statement(sql, table, params))
+}
+
+/// Selects the primary key and `returning` columns of the row most
+/// recently inserted into `table`. RETURNING is only available from
+/// SQLite 3.35, so the row is found by its rowid instead.
+fn select_last_inserted(
+    conn: &rusqlite::Connection,
+    table: &str,
+    pkcol: &Column,
+    returning: &[Column],
+) -> Result<Vec<SqlVal>> {
+    let mut select = format!("SELECT {}", helper::quote_reserved_word(pkcol.name()));
+    for col in returning {
+        write!(&mut select, ", {}", helper::quote_reserved_word(col.name())).unwrap();
+    }
+    write!(
+        &mut select,
+        " FROM {} WHERE ROWID = last_insert_rowid()",
+        helper::quote_reserved_word(table)
+    )
+    .unwrap();
+    let mut stmt = conn.prepare_cached(&select)?;
+    let mut rows = stmt.query([])?;
+    let row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    std::iter::once(pkcol)
+        .chain(returning)
+        .enumerate()
+        .map(|(idx, col)| sql_val_from_rusqlite(row.get_ref_unwrap(idx), col))
+        .collect()
+}
+
+/// Limit on how many tables deep [`emulate_on_delete`] cascades, which
+/// is only reached by rows referring to each other in a cycle.
+const MAX_CASCADE_DEPTH: usize = 32;
+
+/// Performs the `ON DELETE` actions of foreign keys referring to the
+/// rows of `table` matching `condition`, as SQLite does not when
+/// foreign key enforcement is disabled. Must be called before those
+/// rows are deleted.
+fn emulate_on_delete(
+    conn: &rusqlite::Connection,
+    table: &str,
+    condition: &str,
+    values: &[SqlVal],
+    depth: usize,
+) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT m.name, p.\"from\", p.\"to\", p.on_delete FROM sqlite_master m \
+         JOIN pragma_foreign_key_list(m.name) p \
+         WHERE m.type = 'table' AND p.\"table\" = ?;",
+    )?;
+    let references = stmt
+        .query_map([table], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (referring_table, from, to, action) in references {
+        let referring_condition = format!(
+            "{} IN (SELECT {} FROM {} WHERE {})",
+            helper::quote_reserved_word(&from),
+            // A reference without a column is to the primary key
+            helper::quote_reserved_word(to.as_deref().unwrap_or("rowid")),
+            helper::quote_reserved_word(table),
+            condition
+        );
+        match action.as_str() {
+            "CASCADE" => {
+                let exists: bool = conn.query_row(
+                    &format!(
+                        "SELECT EXISTS (SELECT 1 FROM {} WHERE {});",
+                        helper::quote_reserved_word(&referring_table),
+                        referring_condition
+                    ),
+                    rusqlite::params_from_iter(values),
+                    |row| row.get(0),
+                )?;
+                if !exists {
+                    continue;
+                }
+                if depth >= MAX_CASCADE_DEPTH {
+                    return Err(Error::Internal(format!(
+                        "cascading delete from {table} exceeded {MAX_CASCADE_DEPTH} tables"
+                    )));
+                }
+                emulate_on_delete(
+                    conn,
+                    &referring_table,
+                    &referring_condition,
+                    values,
+                    depth + 1,
+                )?;
+                conn.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE {};",
+                        helper::quote_reserved_word(&referring_table),
+                        referring_condition
+                    ),
+                    rusqlite::params_from_iter(values),
+                )?;
+            }
+            "SET NULL" => {
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET {} = NULL WHERE {};",
+                        helper::quote_reserved_word(&referring_table),
+                        helper::quote_reserved_word(&from),
+                        referring_condition
+                    ),
+                    rusqlite::params_from_iter(values),
+                )?;
+            }
+            // Restricting cannot be enforced without foreign key support
+            _ => (),
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -421,6 +1297,27 @@ impl ConnectionMethods for SqliteTransaction<'_> {
     fn execute(&self, sql: &str) -> Result<()> {
         ConnectionMethods::execute(self.wrapped_connection_methods()?, sql)
     }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.wrapped_connection_methods()?
+            .execute_params(sql, params)
+    }
+    fn max_statement_params(&self) -> usize {
+        self.wrapped_connection_methods()
+            .map_or(MAX_STATEMENT_PARAMS, |conn| conn.max_statement_params())
+    }
+    fn in_transaction(&self) -> bool {
+        self.wrapped_connection_methods()
+            .is_ok_and(|conn| conn.in_transaction())
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .query_params(sql, params, columns)
+    }
     fn query<'c>(
         &'c self,
         table: &str,
@@ -443,6 +1340,28 @@ fn insert_returning_pk(
         self.wrapped_connection_methods()?
             .insert_returning_pk(table, columns, pkcol, values)
     }
+    fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.wrapped_connection_methods()?
+            .insert_returning(table, columns, pkcol, returning, values)
+    }
+    fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.wrapped_connection_methods()?
+            .insert_or_ignore(table, columns, pkcol, returning, values)
+    }
     fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
         self.wrapped_connection_methods()?
             .insert_only(table, columns, values)
@@ -474,9 +1393,73 @@ fn delete(&self, table: &str, pkcol: &'static str, pk: SqlVal) -> Result<()> {
     fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
         self.wrapped_connection_methods()?.delete_where(table, expr)
     }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.wrapped_connection_methods()?
+            .update_where(table, assignments, expr)
+    }
     fn has_table(&self, table: &str) -> Result<bool> {
         self.wrapped_connection_methods()?.has_table(table)
     }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        self.wrapped_connection_methods()?.list_tables()
+    }
+    fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.wrapped_connection_methods()?.table_schema(table)
+    }
+    fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.wrapped_connection_methods()?.list_indexes(table)
+    }
+    fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.wrapped_connection_methods()?.notify(channel, payload)
+    }
+    fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .set_config(name, value, local)
+    }
+    fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.wrapped_connection_methods()?.current_config(name)
+    }
+    fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .declare_cursor(name, table, columns, expr, limit, offset, sort)
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.wrapped_connection_methods()?
+            .fetch_cursor(name, columns, count)
+    }
+    fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.wrapped_connection_methods()?.blob_len(blob)
+    }
+    fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.wrapped_connection_methods()?
+            .read_blob(blob, offset, len)
+    }
+    fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.wrapped_connection_methods()?
+            .write_blob(blob, offset, data)
+    }
+    fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.wrapped_connection_methods()?.allocate_blob(blob, len)
+    }
 }
 
 impl<'c> BackendTransaction<'c> for SqliteTransaction<'c> {
@@ -520,6 +1503,7 @@ fn sqlvalref_to_sqlite<'a>(valref: &SqlValRef<'a>) -> rusqlite::types::ToSqlOutp
         Real(r) => Owned(Value::Real(*r)),
         Text(t) => Borrowed(ValueRef::Text(t.as_bytes())),
         Blob(b) => Borrowed(ValueRef::Blob(b)),
+        TextList(v) => Owned(Value::Text(serde_json::to_string(v).unwrap())),
         #[cfg(feature = "json")]
         Json(v) => serde_json::to_string(v)
             .map(rusqlite::types::ToSqlOutput::from)
@@ -542,22 +1526,26 @@ fn sqlvalref_to_sqlite<'a>(valref: &SqlValRef<'a>) -> rusqlite::types::ToSqlOutp
 #[pin_project]
 // Debug can not be derived because rusqlite::Rows doesn't implement it.
 struct QueryAdapterInner<'a> {
-    stmt: rusqlite::Statement<'a>,
     // will always be Some when the constructor has finished. We use an option only to get the
-    // stmt in place before we can reference it.
+    // stmt in place before we can reference it. Declared before stmt so that it is dropped
+    // first, resetting the statement before that is returned to the cache.
     rows: Option<rusqlite::Rows<'a>>,
+    stmt: rusqlite::CachedStatement<'a>,
 }
 
 impl<'a> QueryAdapterInner<'a> {
-    fn new(stmt: rusqlite::Statement<'a>, params: impl rusqlite::Params) -> Result<Pin<Box<Self>>> {
-        let mut q = Box::pin(QueryAdapterInner { stmt, rows: None });
+    fn new(
+        stmt: rusqlite::CachedStatement<'a>,
+        params: impl rusqlite::Params,
+    ) -> Result<Pin<Box<Self>>> {
+        let mut q = Box::pin(QueryAdapterInner { rows: None, stmt });
         unsafe {
             //Soundness: we pin a QueryAdapterInner value containing
             //  both the stmt and the rows referencing the statement
             //  together. It is not possible to drop/move the stmt without
             //  bringing the referencing rows along with it.
             let q_ref = Pin::get_unchecked_mut(Pin::as_mut(&mut q));
-            let stmt_ref: *mut rusqlite::Statement<'a> = &mut q_ref.stmt;
+            let stmt_ref: *mut rusqlite::Statement<'a> = &mut *q_ref.stmt;
             q_ref.rows = Some((*stmt_ref).query(params)?)
         }
         Ok(q)
@@ -580,7 +1568,7 @@ struct QueryAdapter<'a> {
     inner: Pin<Box<QueryAdapterInner<'a>>>,
 }
 impl<'a> QueryAdapter<'a> {
-    fn new(stmt: rusqlite::Statement<'a>, params: impl rusqlite::Params) -> Result<Self> {
+    fn new(stmt: rusqlite::CachedStatement<'a>, params: impl rusqlite::Params) -> Result<Self> {
         Ok(QueryAdapter {
             inner: QueryAdapterInner::new(stmt, params)?,
         })
@@ -612,15 +1600,17 @@ fn len(&self) -> usize {
     }
 }
 
-fn sql_for_expr<W>(
-    expr: query::Expr,
-    values: &mut Vec<SqlVal>,
-    pls: &mut SQLitePlaceholderSource,
-    w: &mut W,
-) where
-    W: Write,
-{
-    helper::sql_for_expr(expr, sql_for_expr, values, pls, w)
+/// Converts a value whose column type is not known, such as an argument
+/// of a function, according to how SQLite stores it.
+fn sql_val_from_rusqlite_value(val: rusqlite::types::ValueRef) -> SqlVal {
+    use rusqlite::types::ValueRef;
+    match val {
+        ValueRef::Null => SqlVal::Null,
+        ValueRef::Integer(i) => SqlVal::BigInt(i),
+        ValueRef::Real(r) => SqlVal::Real(r),
+        ValueRef::Text(t) => SqlVal::Text(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => SqlVal::Blob(b.to_vec()),
+    }
 }
 
 fn sql_val_from_rusqlite(val: rusqlite::types::ValueRef, col: &Column) -> Result<SqlVal> {
@@ -640,6 +1630,10 @@ fn sql_valref_from_rusqlite<'a>(
         SqlType::BigInt => SqlValRef::BigInt(val.as_i64()?),
         SqlType::Real => SqlValRef::Real(val.as_f64()?),
         SqlType::Text => SqlValRef::Text(val.as_str()?),
+        SqlType::TextList => {
+            let list: Vec<String> = serde_json::from_str(val.as_str()?)?;
+            SqlValRef::TextList(Cow::Owned(list))
+        }
         #[cfg(feature = "json")]
         SqlType::Json => SqlValRef::Json(serde_json::from_str(val.as_str()?)?),
         #[cfg(feature = "datetime")]
@@ -653,266 +1647,10 @@ fn sql_valref_from_rusqlite<'a>(
             SQLITE_DT_FORMAT,
         )?),
         SqlType::Blob => SqlValRef::Blob(val.as_blob()?),
+        #[cfg(feature = "pg")]
         SqlType::Custom(v) => return Err(Error::IncompatibleCustomT(v.clone(), BACKEND_NAME)),
+        // There are no custom types without the backend defining them
+        #[cfg(not(feature = "pg"))]
+        SqlType::Custom(v) => match *v {},
     })
 }
-
-fn sql_for_op(current: &mut ADB, op: &Operation) -> Result<String> {
-    match op {
-        Operation::AddTable(table) => Ok(create_table(table, false)),
-        Operation::AddTableConstraints(_table) => Ok("".to_owned()),
-        Operation::AddTableIfNotExists(table) => Ok(create_table(table, true)),
-        Operation::RemoveTable(name) => Ok(drop_table(name)),
-        Operation::RemoveTableConstraints(_table) => Ok("".to_owned()),
-        Operation::AddColumn(tbl, col) => add_column(tbl, col),
-        Operation::RemoveColumn(tbl, name) => remove_column(current, tbl, name),
-        Operation::ChangeColumn(tbl, old, new) => Ok(change_column(current, tbl, old, Some(new))),
-    }
-}
-
-fn create_table(table: &ATable, allow_exists: bool) -> String {
-    let coldefs = table
-        .columns
-        .iter()
-        .map(define_column)
-        .collect::<Vec<String>>()
-        .join(",\n");
-    let modifier = if allow_exists { "IF NOT EXISTS " } else { "" };
-    let mut constraints = create_table_constraints(table);
-    if !constraints.is_empty() {
-        constraints = ",\n".to_owned() + &constraints;
-    }
-    format!(
-        "CREATE TABLE {}{} (\n{}{}\n) STRICT;",
-        modifier,
-        helper::quote_reserved_word(&table.name),
-        coldefs,
-        constraints
-    )
-}
-
-fn create_table_constraints(table: &ATable) -> String {
-    table
-        .columns
-        .iter()
-        .filter(|column| column.reference().is_some())
-        .map(define_constraint)
-        .collect::<Vec<String>>()
-        .join("\n")
-}
-
-fn define_column(col: &AColumn) -> String {
-    let mut constraints: Vec<String> = Vec::new();
-    if !col.nullable() {
-        constraints.push("NOT NULL".to_string());
-    }
-    if col.is_pk() {
-        constraints.push("PRIMARY KEY".to_string());
-    }
-    if col.is_auto() && !col.is_pk() {
-        // integer primary key is automatically an alias for ROWID,
-        // and we only allow auto on integer types
-        constraints.push("AUTOINCREMENT".to_string());
-    }
-    if col.unique() {
-        constraints.push("UNIQUE".to_string());
-    }
-    if constraints.is_empty() {
-        format!(
-            "{} {}",
-            helper::quote_reserved_word(col.name()),
-            col_sqltype(col),
-        )
-    } else {
-        format!(
-            "{} {} {}",
-            helper::quote_reserved_word(col.name()),
-            col_sqltype(col),
-            constraints.join(" ")
-        )
-    }
-}
-
-fn define_constraint(column: &AColumn) -> String {
-    let reference = column
-        .reference()
-        .as_ref()
-        .expect("must have a references value");
-    match reference {
-        ARef::Literal(literal) => {
-            format!(
-                "FOREIGN KEY ({}) REFERENCES {}({})",
-                helper::quote_reserved_word(column.name()),
-                helper::quote_reserved_word(literal.table_name()),
-                helper::quote_reserved_word(literal.column_name()),
-            )
-        }
-        _ => panic!(),
-    }
-}
-
-fn col_sqltype(col: &AColumn) -> Cow<'_, str> {
-    match col.typeid() {
-        Ok(TypeIdentifier::Ty(ty)) => Cow::Borrowed(sqltype(&ty)),
-        Ok(TypeIdentifier::Name(name)) => Cow::Owned(name),
-        // sqlite doesn't actually require that the column type be
-        // specified
-        Err(_) => Cow::Borrowed(""),
-    }
-}
-
-fn sqltype(ty: &SqlType) -> &'static str {
-    match ty {
-        SqlType::Bool => "INTEGER",
-        SqlType::Int => "INTEGER",
-        SqlType::BigInt => "INTEGER",
-        SqlType::Real => "REAL",
-        SqlType::Text => "TEXT",
-        SqlType::Blob => "BLOB",
-        #[cfg(feature = "json")]
-        SqlType::Json => "TEXT",
-        #[cfg(feature = "datetime")]
-        SqlType::Date => "TEXT",
-        #[cfg(feature = "datetime")]
-        SqlType::Timestamp => "TEXT",
-        SqlType::Custom(_) => panic!("Custom types not supported by sqlite backend"),
-    }
-}
-
-fn drop_table(name: &str) -> String {
-    format!("DROP TABLE {};", helper::quote_reserved_word(name))
-}
-
-fn add_column(tbl_name: &str, col: &AColumn) -> Result<String> {
-    let default: SqlVal = helper::column_default(col)?;
-    Ok(format!(
-        "ALTER TABLE {} ADD COLUMN {} DEFAULT {};",
-        helper::quote_reserved_word(tbl_name),
-        define_column(col),
-        helper::sql_literal_value(&default)?
-    ))
-}
-
-fn remove_column(current: &mut ADB, tbl_name: &str, name: &str) -> Result<String> {
-    let current_clone = current.clone();
-    let table = current_clone
-        .get_table(tbl_name)
-        .ok_or_else(|| Error::TableNotFound(tbl_name.to_string()))?;
-    let col = table
-        .column(name)
-        .ok_or_else(|| Error::ColumnNotFound(tbl_name.to_string(), name.to_string()))?;
-    // "ALTER TABLE b DROP COLUMN fkey;" fails due to sqlite not being
-    // able to remove the attached constraint.
-    if col.reference().is_some() {
-        Ok(change_column(current, tbl_name, col, None))
-    } else {
-        Ok(format!(
-            "ALTER TABLE {} DROP COLUMN {};",
-            helper::quote_reserved_word(tbl_name),
-            helper::quote_reserved_word(name),
-        ))
-    }
-}
-
-fn copy_table(old: &ATable, new: &ATable) -> String {
-    let column_names = new
-        .columns
-        .iter()
-        .map(|col| helper::quote_reserved_word(col.name()))
-        .collect::<Vec<Cow<str>>>()
-        .join(", ");
-    format!(
-        "INSERT INTO {} SELECT {} FROM {};",
-        helper::quote_reserved_word(&new.name),
-        column_names,
-        helper::quote_reserved_word(&old.name)
-    )
-}
-
-fn tmp_table_name(name: &str) -> String {
-    format!("{name}__butane_tmp")
-}
-
-fn change_column(
-    current: &mut ADB,
-    tbl_name: &str,
-    old: &AColumn,
-    new: Option<&AColumn>,
-) -> String {
-    let table = current.get_table(tbl_name);
-    if table.is_none() {
-        crate::warn!(
-            "Cannot alter column {} from table {} that does not exist",
-            &old.name(),
-            tbl_name
-        );
-        return "".to_string();
-    }
-    let old_table = table.unwrap();
-    let mut new_table = old_table.clone();
-    new_table.name = tmp_table_name(&new_table.name);
-    match new {
-        Some(col) => new_table.replace_column(col.clone()),
-        None => new_table.remove_column(old.name()),
-    }
-    let stmts: [&str; 4] = [
-        &create_table(&new_table, false),
-        &copy_table(old_table, &new_table),
-        &drop_table(&old_table.name),
-        &format!(
-            "ALTER TABLE {} RENAME TO {};",
-            helper::quote_reserved_word(&new_table.name),
-            helper::quote_reserved_word(tbl_name)
-        ),
-    ];
-    let result = stmts.join("\n");
-    new_table.name.clone_from(&old_table.name);
-    current.replace_table(new_table);
-    result
-}
-
-pub fn sql_insert_or_update(table: &str, columns: &[Column], pkcol: &Column, w: &mut impl Write) {
-    write!(w, "INSERT ").unwrap();
-    write!(w, "INTO {} (", helper::quote_reserved_word(table)).unwrap();
-    helper::list_columns(columns, w);
-    write!(w, ") VALUES (").unwrap();
-    columns.iter().fold("", |sep, _| {
-        write!(w, "{sep}?").unwrap();
-        ", "
-    });
-    write!(w, ")").unwrap();
-    write!(w, " ON CONFLICT ({}) DO ", pkcol.name()).unwrap();
-    if columns.len() > 1 {
-        write!(w, "UPDATE SET (").unwrap();
-        helper::list_columns(columns, w);
-        write!(w, ") = (").unwrap();
-        columns.iter().fold("", |sep, c| {
-            write!(
-                w,
-                "{}excluded.{}",
-                sep,
-                helper::quote_reserved_word(c.name())
-            )
-            .unwrap();
-            ", "
-        });
-        write!(w, ")").unwrap();
-    } else {
-        // If the pk is the only column and it already exists, then there's nothing to update.
-        write!(w, "NOTHING").unwrap();
-    }
-}
-
-#[derive(Debug)]
-struct SQLitePlaceholderSource;
-impl SQLitePlaceholderSource {
-    fn new() -> Self {
-        SQLitePlaceholderSource {}
-    }
-}
-impl helper::PlaceholderSource for SQLitePlaceholderSource {
-    fn next_placeholder(&mut self) -> Cow<'_, str> {
-        // sqlite placeholder is always a question mark.
-        Cow::Borrowed("?")
-    }
-}
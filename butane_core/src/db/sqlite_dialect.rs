@@ -0,0 +1,417 @@
+//! SQL generation for SQLite, shared by the backends which use it.
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use super::helper::{self, PlaceholderSource};
+use super::Column;
+use crate::migrations::adb::{AColumn, ARef, ATable, Operation, TypeIdentifier, ADB};
+use crate::{query, Error, Result, SqlType, SqlVal};
+
+#[cfg(feature = "datetime")]
+pub(super) const SQLITE_DT_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+#[cfg(feature = "datetime")]
+pub(super) const SQLITE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Generates the SQL for the migration `ops` from the schema `current`.
+pub(super) fn create_migration_sql(existing: &ADB, ops: Vec<Operation>) -> Result<String> {
+    let mut current: ADB = existing.clone();
+    let mut lines = ops
+        .into_iter()
+        .map(|o| {
+            let sql = match &o {
+                Operation::AddIndex(tbl, index)
+                    if helper::is_new_unique_many_index(existing, tbl, index) =>
+                {
+                    Ok(format!(
+                        "{}\n{}",
+                        delete_duplicate_many_rows(tbl),
+                        helper::create_index(tbl, index)
+                    ))
+                }
+                _ => sql_for_op(&mut current, &o),
+            };
+            current.transform_with(o);
+            sql
+        })
+        .collect::<Result<Vec<String>>>()?;
+    lines.retain(|s| !s.is_empty());
+    Ok(lines.join("\n"))
+}
+
+pub(super) fn sql_for_expr<W>(
+    expr: query::Expr,
+    values: &mut Vec<SqlVal>,
+    pls: &mut SQLitePlaceholderSource,
+    w: &mut W,
+) where
+    W: Write,
+{
+    match expr {
+        // Integers are bound like other values rather than written into
+        // the statement, so that it is the same for each value and can
+        // be reused from the statement cache
+        query::Expr::Val(val @ (SqlVal::Int(_) | SqlVal::BigInt(_))) => {
+            values.push(val);
+            w.write_str(&pls.next_placeholder()).unwrap()
+        }
+        _ => helper::sql_for_expr(expr, sql_for_expr, values, pls, w),
+    }
+}
+
+fn sql_for_op(current: &mut ADB, op: &Operation) -> Result<String> {
+    match op {
+        Operation::AddTable(table) => create_table(table, false),
+        Operation::AddTableConstraints(_table) => Ok("".to_owned()),
+        Operation::AddTableIfNotExists(table) => create_table(table, true),
+        Operation::RemoveTable(name) => {
+            Ok(helper::drop_view(current, name).unwrap_or_else(|| drop_table(name)))
+        }
+        Operation::RenameTable(from, to) => Ok(rename_table(from, to)),
+        Operation::RenameColumn(tbl, from, to) => Ok(rename_column(tbl, from, to)),
+        Operation::RemoveTableConstraints(_table) => Ok("".to_owned()),
+        Operation::AddColumn(tbl, col) => add_column(tbl, col),
+        Operation::BackfillColumn(tbl, col) => helper::backfill_column(tbl, col),
+        Operation::RemoveColumn(tbl, name) => remove_column(current, tbl, name),
+        Operation::ChangeColumn(tbl, old, new) => change_column(current, tbl, old, Some(new)),
+        Operation::AddIndex(tbl, index) => Ok(helper::create_index(tbl, index)),
+        Operation::RemoveIndex(tbl, index) => Ok(helper::drop_index(tbl, index)),
+        // SQLite has no comments on tables or columns
+        Operation::SetTableComment(_, _) | Operation::SetColumnComment(_, _, _) => {
+            Ok("".to_owned())
+        }
+    }
+}
+
+/// Returns the SQL to delete the rows of the `Many` table `tbl_name`
+/// which repeat an earlier one.
+fn delete_duplicate_many_rows(tbl_name: &str) -> String {
+    let tbl = helper::quote_reserved_word(tbl_name);
+    format!(
+        "DELETE FROM {tbl} WHERE rowid NOT IN (SELECT MIN(rowid) FROM {tbl} GROUP BY owner, has);"
+    )
+}
+
+fn create_table(table: &ATable, allow_exists: bool) -> Result<String> {
+    if let Some(sql) = helper::create_view(table) {
+        return Ok(sql);
+    }
+    let coldefs = table
+        .columns
+        .iter()
+        .map(|col| Ok(define_column(col) + &helper::default_clause(col)?))
+        .collect::<Result<Vec<String>>>()?
+        .join(",\n");
+    let modifier = if allow_exists { "IF NOT EXISTS " } else { "" };
+    let mut constraints = create_table_constraints(table);
+    if !constraints.is_empty() {
+        constraints = ",\n".to_owned() + &constraints;
+    }
+    Ok(format!(
+        "CREATE TABLE {}{} (\n{}{}\n) STRICT;",
+        modifier,
+        helper::quote_reserved_word(&table.name),
+        coldefs,
+        constraints
+    ))
+}
+
+fn create_table_constraints(table: &ATable) -> String {
+    table
+        .columns
+        .iter()
+        .filter(|column| column.reference().is_some())
+        .map(define_constraint)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn define_column(col: &AColumn) -> String {
+    let mut constraints: Vec<String> = helper::collate_constraint(col).into_iter().collect();
+    if !col.nullable() {
+        constraints.push("NOT NULL".to_string());
+    }
+    if col.is_pk() {
+        constraints.push("PRIMARY KEY".to_string());
+    }
+    if col.is_auto() && !col.is_pk() {
+        // integer primary key is automatically an alias for ROWID,
+        // and we only allow auto on integer types
+        constraints.push("AUTOINCREMENT".to_string());
+    }
+    if col.unique() {
+        constraints.push("UNIQUE".to_string());
+    }
+    if constraints.is_empty() {
+        format!(
+            "{} {}",
+            helper::quote_reserved_word(col.name()),
+            col_sqltype(col),
+        )
+    } else {
+        format!(
+            "{} {} {}",
+            helper::quote_reserved_word(col.name()),
+            col_sqltype(col),
+            constraints.join(" ")
+        )
+    }
+}
+
+fn define_constraint(column: &AColumn) -> String {
+    let reference = column
+        .reference()
+        .as_ref()
+        .expect("must have a references value");
+    match reference {
+        ARef::Literal(literal) => {
+            format!(
+                "FOREIGN KEY ({}) REFERENCES {}({}){}",
+                helper::quote_reserved_word(column.name()),
+                helper::quote_reserved_word(literal.table_name()),
+                helper::quote_reserved_word(literal.column_name()),
+                helper::on_delete_clause(column),
+            )
+        }
+        _ => panic!(),
+    }
+}
+
+fn col_sqltype(col: &AColumn) -> Cow<'_, str> {
+    match col.typeid() {
+        Ok(TypeIdentifier::Ty(ty)) => Cow::Borrowed(sqltype(&ty)),
+        Ok(TypeIdentifier::Name(name)) => Cow::Owned(name),
+        // sqlite doesn't actually require that the column type be
+        // specified
+        Err(_) => Cow::Borrowed(""),
+    }
+}
+
+fn sqltype(ty: &SqlType) -> &'static str {
+    match ty {
+        SqlType::Bool => "INTEGER",
+        SqlType::Int => "INTEGER",
+        SqlType::BigInt => "INTEGER",
+        SqlType::Real => "REAL",
+        SqlType::Text => "TEXT",
+        SqlType::Blob => "BLOB",
+        SqlType::TextList => "TEXT",
+        #[cfg(feature = "json")]
+        SqlType::Json => "TEXT",
+        #[cfg(feature = "datetime")]
+        SqlType::Date => "TEXT",
+        #[cfg(feature = "datetime")]
+        SqlType::Timestamp => "TEXT",
+        SqlType::Custom(_) => panic!("Custom types not supported by sqlite backend"),
+    }
+}
+
+fn drop_table(name: &str) -> String {
+    format!("DROP TABLE {};", helper::quote_reserved_word(name))
+}
+
+fn rename_table(from: &str, to: &str) -> String {
+    // SQLite refuses to rename a table to a name differing only by
+    // case, so go through a temporary name.
+    let tmp = tmp_table_name(to);
+    format!(
+        "ALTER TABLE {} RENAME TO {};\nALTER TABLE {} RENAME TO {};",
+        helper::quote_reserved_word(from),
+        helper::quote_reserved_word(&tmp),
+        helper::quote_reserved_word(&tmp),
+        helper::quote_reserved_word(to)
+    )
+}
+
+fn rename_column(tbl_name: &str, from: &str, to: &str) -> String {
+    format!(
+        "ALTER TABLE {} RENAME COLUMN {} TO {};",
+        helper::quote_reserved_word(tbl_name),
+        helper::quote_reserved_word(from),
+        helper::quote_reserved_word(to)
+    )
+}
+
+fn add_column(tbl_name: &str, col: &AColumn) -> Result<String> {
+    let default: SqlVal = helper::column_default(col)?;
+    Ok(format!(
+        "ALTER TABLE {} ADD COLUMN {} DEFAULT {};",
+        helper::quote_reserved_word(tbl_name),
+        define_column(col),
+        helper::sql_literal_value(&default)?
+    ))
+}
+
+fn remove_column(current: &mut ADB, tbl_name: &str, name: &str) -> Result<String> {
+    let current_clone = current.clone();
+    let table = current_clone
+        .get_table(tbl_name)
+        .ok_or_else(|| Error::TableNotFound(tbl_name.to_string()))?;
+    let col = table
+        .column(name)
+        .ok_or_else(|| Error::ColumnNotFound(tbl_name.to_string(), name.to_string()))?;
+    // "ALTER TABLE b DROP COLUMN fkey;" fails due to sqlite not being
+    // able to remove the attached constraint.
+    if col.reference().is_some() {
+        change_column(current, tbl_name, col, None)
+    } else {
+        Ok(format!(
+            "ALTER TABLE {} DROP COLUMN {};",
+            helper::quote_reserved_word(tbl_name),
+            helper::quote_reserved_word(name),
+        ))
+    }
+}
+
+/// Copies the rows of `old` to `new`, converting the values of the
+/// column `changed` from its old definition to its new one.
+fn copy_table(old: &ATable, new: &ATable, changed: Option<(&AColumn, &AColumn)>) -> String {
+    let column_names = new
+        .columns
+        .iter()
+        .map(|col| match changed {
+            Some((from, to)) if to.name() == col.name() => convert_column_type(from, to),
+            _ => helper::quote_reserved_word(col.name()),
+        })
+        .collect::<Vec<Cow<str>>>()
+        .join(", ");
+    format!(
+        "INSERT INTO {} SELECT {} FROM {};",
+        helper::quote_reserved_word(&new.name),
+        column_names,
+        helper::quote_reserved_word(&old.name)
+    )
+}
+
+/// The expression converting the values of column `old` to the type of
+/// `new`. Strings formatted as UUIDs are converted to and from the 16
+/// bytes of a `Uuid`, other strings to and from their UTF-8 encoding,
+/// and reals to integers by rounding. Other values are converted by the
+/// type of the column, which fails for values which cannot be.
+fn convert_column_type<'a>(old: &'a AColumn, new: &AColumn) -> Cow<'a, str> {
+    let col = helper::quote_reserved_word(old.name());
+    match (old.typeid(), new.typeid()) {
+        (Ok(TypeIdentifier::Ty(SqlType::Text)), Ok(TypeIdentifier::Ty(SqlType::Blob))) => format!(
+            "CASE WHEN length({col}) = 36 AND length(unhex({col}, '-')) = 16 \
+             THEN unhex({col}, '-') ELSE CAST({col} AS BLOB) END"
+        )
+        .into(),
+        (Ok(TypeIdentifier::Ty(SqlType::Blob)), Ok(TypeIdentifier::Ty(SqlType::Text))) => {
+            let hex = format!("lower(hex({col}))");
+            format!(
+                "CASE WHEN length({col}) = 16 THEN substr({hex}, 1, 8) || '-' || \
+                 substr({hex}, 9, 4) || '-' || substr({hex}, 13, 4) || '-' || \
+                 substr({hex}, 17, 4) || '-' || substr({hex}, 21) ELSE CAST({col} AS TEXT) END"
+            )
+            .into()
+        }
+        (
+            Ok(TypeIdentifier::Ty(SqlType::Real)),
+            Ok(TypeIdentifier::Ty(SqlType::Int | SqlType::BigInt)),
+        ) => format!("CAST(round({col}) AS INTEGER)").into(),
+        _ => col,
+    }
+}
+
+fn tmp_table_name(name: &str) -> String {
+    format!("{name}__butane_tmp")
+}
+
+fn change_column(
+    current: &mut ADB,
+    tbl_name: &str,
+    old: &AColumn,
+    new: Option<&AColumn>,
+) -> Result<String> {
+    let table = current.get_table(tbl_name);
+    if table.is_none() {
+        crate::warn!(
+            "Cannot alter column {} from table {} that does not exist",
+            &old.name(),
+            tbl_name
+        );
+        return Ok("".to_string());
+    }
+    let old_table = table.unwrap();
+    let mut new_table = old_table.clone();
+    new_table.name = tmp_table_name(&new_table.name);
+    match new {
+        Some(col) => new_table.replace_column(col.clone()),
+        None => {
+            new_table.remove_column(old.name());
+            new_table.remove_index(old.name());
+        }
+    }
+    let mut stmts: Vec<String> = vec![
+        create_table(&new_table, false)?,
+        copy_table(old_table, &new_table, new.map(|col| (old, col))),
+        drop_table(&old_table.name),
+        format!(
+            "ALTER TABLE {} RENAME TO {};",
+            helper::quote_reserved_word(&new_table.name),
+            helper::quote_reserved_word(tbl_name)
+        ),
+    ];
+    // Indexes are dropped with the old table
+    stmts.extend(
+        new_table
+            .indexes
+            .iter()
+            .map(|index| helper::create_index(tbl_name, index)),
+    );
+    let result = stmts.join("\n");
+    new_table.name.clone_from(&old_table.name);
+    current.replace_table(new_table);
+    Ok(result)
+}
+
+pub fn sql_insert_or_update(table: &str, columns: &[Column], pkcol: &Column, w: &mut impl Write) {
+    write!(w, "INSERT ").unwrap();
+    write!(w, "INTO {} (", helper::quote_reserved_word(table)).unwrap();
+    helper::list_columns(columns, w);
+    write!(w, ") VALUES (").unwrap();
+    columns.iter().fold("", |sep, _| {
+        write!(w, "{sep}?").unwrap();
+        ", "
+    });
+    write!(w, ")").unwrap();
+    write!(
+        w,
+        " ON CONFLICT ({}) DO ",
+        helper::quote_reserved_word(pkcol.name())
+    )
+    .unwrap();
+    if columns.len() > 1 {
+        write!(w, "UPDATE SET (").unwrap();
+        helper::list_columns(columns, w);
+        write!(w, ") = (").unwrap();
+        columns.iter().fold("", |sep, c| {
+            write!(
+                w,
+                "{}excluded.{}",
+                sep,
+                helper::quote_reserved_word(c.name())
+            )
+            .unwrap();
+            ", "
+        });
+        write!(w, ")").unwrap();
+    } else {
+        // If the pk is the only column and it already exists, then there's nothing to update.
+        write!(w, "NOTHING").unwrap();
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct SQLitePlaceholderSource;
+impl SQLitePlaceholderSource {
+    pub(super) fn new() -> Self {
+        SQLitePlaceholderSource {}
+    }
+}
+impl PlaceholderSource for SQLitePlaceholderSource {
+    fn next_placeholder(&mut self) -> Cow<'_, str> {
+        // sqlite placeholder is always a question mark.
+        Cow::Borrowed("?")
+    }
+}
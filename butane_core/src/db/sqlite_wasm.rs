@@ -0,0 +1,940 @@
+//! SQLite database backend for WebAssembly, for use in web frontends
+//! and Tauri apps.
+//!
+//! SQLite itself runs in JavaScript, compiled to WebAssembly by a
+//! library such as [wa-sqlite](https://github.com/rhashimoto/wa-sqlite)
+//! or [sql.js](https://github.com/sql-js/sql.js), which the app opens
+//! and wraps in a [`Database`]: an object with two methods, each of
+//! which takes the SQL and an array of parameters to bind to its `?`
+//! placeholders and returns a promise.
+//!
+//! * `exec(sql, params)` runs a statement, resolving to the number of
+//!   rows changed. When `params` is empty, `sql` may contain several
+//!   statements, as with the SQL of a migration.
+//! * `query(sql, params)` runs a query, resolving to an array of its
+//!   rows, each an array of the values of its columns.
+//!
+//! Values are passed as `null`, numbers, `BigInt`s for integers too
+//! large for a number, strings and `Uint8Array`s for blobs, as these
+//! libraries do. Calls must be serialized by the object, such as with
+//! a mutex, as statements of a transaction may not be interleaved with
+//! others.
+//!
+//! The database persists in the browser's origin private file system
+//! (OPFS) if it is opened with a VFS which stores it there, such as
+//! wa-sqlite's `OPFSCoopSyncVFS` in a worker. With wa-sqlite:
+//!
+//! ```js
+//! import SQLiteESMFactory from "wa-sqlite/dist/wa-sqlite.mjs";
+//! import * as SQLite from "wa-sqlite";
+//! import { OPFSCoopSyncVFS } from "wa-sqlite/src/examples/OPFSCoopSyncVFS.js";
+//!
+//! const sqlite3 = SQLite.Factory(await SQLiteESMFactory());
+//! sqlite3.vfs_register(await OPFSCoopSyncVFS.create("opfs", sqlite3.module), true);
+//!
+//! globalThis.butaneOpenSqlite = async (path) => {
+//!   const db = await sqlite3.open_v2(path);
+//!   let last = Promise.resolve();
+//!   const serialize = (f) => (last = last.then(f, f));
+//!   return {
+//!     exec: (sql, params) => serialize(async () => {
+//!       for await (const stmt of sqlite3.statements(db, sql)) {
+//!         if (params.length) sqlite3.bind_collection(stmt, params);
+//!         while (await sqlite3.step(stmt) === SQLite.SQLITE_ROW);
+//!       }
+//!       return sqlite3.changes(db);
+//!     }),
+//!     query: (sql, params) => serialize(async () => {
+//!       const rows = [];
+//!       for await (const stmt of sqlite3.statements(db, sql)) {
+//!         sqlite3.bind_collection(stmt, params);
+//!         while (await sqlite3.step(stmt) === SQLite.SQLITE_ROW) {
+//!           rows.push(sqlite3.row(stmt));
+//!         }
+//!       }
+//!       return rows;
+//!     }),
+//!   };
+//! };
+//! ```
+//!
+//! A connection is then made with [`SQLiteWasmConnection::new`] from a
+//! [`Database`], or through [`connect_async`](super::connect_async)
+//! with a [`ConnectionSpec`](super::ConnectionSpec) for the
+//! `sqlite-wasm` backend, whose connection string is passed to the
+//! global function `butaneOpenSqlite` to open the database.
+//!
+//! Only async connections are supported, as a browser's main thread
+//! may not block. Deadlines are not enforced, and the JavaScript
+//! bindings may only be called when compiled for `wasm32`.
+use std::fmt::{Debug, Write};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+#[cfg(feature = "datetime")]
+use chrono::naive::{NaiveDate, NaiveDateTime};
+use js_sys::{Array, Promise, Uint8Array};
+use pin_project::pin_project;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+use super::connmethods::{VecRow, VecRows};
+use super::sqlite_dialect::{self, sql_for_expr, sql_insert_or_update, SQLitePlaceholderSource};
+#[cfg(feature = "datetime")]
+use super::sqlite_dialect::{SQLITE_DATE_FORMAT, SQLITE_DT_FORMAT};
+use super::{
+    helper, Backend, BackendConnectionAsync as BackendConnection, BackendRow,
+    BackendTransactionAsync as BackendTransaction, BlobRef, Capabilities, Column, ColumnSchema,
+    Connection, ConnectionAsync, ConnectionMethodsAsync as ConnectionMethods, IndexSchema,
+    RawQueryResult, TableSchema, TransactionAsync as Transaction,
+};
+use crate::migrations::adb::{Operation, ADB};
+use crate::query::{BoolExpr, Expr, Order};
+use crate::{debug, query, Error, FromSql, Result, SqlType, SqlVal, SqlValRef};
+
+/// The name of the sqlite-wasm backend.
+pub const BACKEND_NAME: &str = "sqlite-wasm";
+/// The internal row creation order field name.
+pub const ROW_ID_COLUMN_NAME: &str = "rowid";
+
+#[wasm_bindgen]
+extern "C" {
+    /// A SQLite database opened in JavaScript, wrapped in an object
+    /// with `exec` and `query` methods as described in the
+    /// [module documentation](self).
+    #[derive(Clone, Debug)]
+    pub type Database;
+
+    #[wasm_bindgen(method, catch)]
+    fn exec(this: &Database, sql: &str, params: Array) -> std::result::Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn query(this: &Database, sql: &str, params: Array) -> std::result::Result<Promise, JsValue>;
+
+    #[wasm_bindgen(catch, js_name = butaneOpenSqlite)]
+    fn open_database(path: &str) -> std::result::Result<Promise, JsValue>;
+}
+
+/// Wrapper asserting that a JavaScript value, or a future holding
+/// them, is `Send` and `Sync`, as the traits for connections require.
+#[pin_project]
+#[derive(Clone, Debug)]
+struct SingleThreaded<T>(#[pin] T);
+
+// SAFETY: without the atomics target feature WebAssembly has only one
+// thread, and on other targets the JavaScript bindings cannot be called.
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl<T> Send for SingleThreaded<T> {}
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl<T> Sync for SingleThreaded<T> {}
+
+impl<F: Future> Future for SingleThreaded<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        self.project().0.poll(cx)
+    }
+}
+
+/// SQLite [`Backend`] implementation for WebAssembly.
+#[derive(Debug, Default, Clone)]
+pub struct SQLiteWasmBackend;
+impl SQLiteWasmBackend {
+    pub fn new() -> SQLiteWasmBackend {
+        SQLiteWasmBackend {}
+    }
+}
+
+#[async_trait]
+impl Backend for SQLiteWasmBackend {
+    fn name(&self) -> &'static str {
+        BACKEND_NAME
+    }
+
+    fn row_id_column(&self) -> Option<&'static str> {
+        Some(ROW_ID_COLUMN_NAME)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // The SQLite compiled to WebAssembly is always recent enough for RETURNING
+        Capabilities {
+            returning: true,
+            ctes: true,
+            savepoints: true,
+            auto_pk: true,
+            server_side_limit: true,
+            native_upsert: true,
+            // Changed columns are copied into a rebuilt table
+            alter_column: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn create_migration_sql(&self, current: &ADB, ops: Vec<Operation>) -> Result<String> {
+        sqlite_dialect::create_migration_sql(current, ops)
+    }
+
+    fn connect(&self, _path: &str) -> Result<Connection> {
+        Err(Error::Unsupported(BACKEND_NAME, "synchronous connections"))
+    }
+
+    async fn connect_async(&self, path: &str) -> Result<ConnectionAsync> {
+        Ok(ConnectionAsync::new(Box::new(
+            SQLiteWasmConnection::open(path).await?,
+        )))
+    }
+}
+
+/// SQLite database connection for WebAssembly.
+#[derive(Debug)]
+pub struct SQLiteWasmConnection {
+    db: SingleThreaded<Database>,
+}
+impl SQLiteWasmConnection {
+    /// Creates a connection to `db`, a database opened in JavaScript.
+    pub async fn new(db: Database) -> Result<Self> {
+        let conn = SQLiteWasmConnection {
+            db: SingleThreaded(db),
+        };
+        conn.execute("PRAGMA foreign_keys = ON;").await?;
+        Ok(conn)
+    }
+
+    /// Opens the database at `path` with the global JavaScript function
+    /// `butaneOpenSqlite`.
+    fn open(path: &str) -> impl Future<Output = Result<Self>> + Send {
+        let promise = open_database(path).map_err(js_error);
+        SingleThreaded(async move {
+            let db = JsFuture::from(promise?).await.map_err(js_error)?;
+            SQLiteWasmConnection::new(db.unchecked_into()).await
+        })
+    }
+}
+
+impl DatabaseLike for SQLiteWasmConnection {
+    fn database(&self) -> Result<&SingleThreaded<Database>> {
+        Ok(&self.db)
+    }
+}
+
+#[async_trait]
+impl BackendConnection for SQLiteWasmConnection {
+    async fn transaction(&mut self) -> Result<Transaction<'_>> {
+        self.execute("BEGIN;").await?;
+        let trans = Box::new(SQLiteWasmTransaction { db: Some(&self.db) });
+        Ok(Transaction::new(trans))
+    }
+    fn backend(&self) -> Box<dyn Backend> {
+        Box::new(SQLiteWasmBackend {})
+    }
+    fn backend_name(&self) -> &'static str {
+        BACKEND_NAME
+    }
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+struct SQLiteWasmTransaction<'c> {
+    db: Option<&'c SingleThreaded<Database>>,
+}
+impl SQLiteWasmTransaction<'_> {
+    /// Ends the transaction with `sql`, either COMMIT or ROLLBACK.
+    async fn end(&mut self, sql: &str) -> Result<()> {
+        let db = self.db.take().ok_or_else(Self::already_consumed)?;
+        run_exec(db, sql, &[]).await?;
+        Ok(())
+    }
+    fn already_consumed() -> Error {
+        Error::Internal("transaction has already been consumed".to_string())
+    }
+}
+
+impl DatabaseLike for SQLiteWasmTransaction<'_> {
+    fn database(&self) -> Result<&SingleThreaded<Database>> {
+        match self.db {
+            Some(db) => Ok(db),
+            None => Err(Self::already_consumed()),
+        }
+    }
+}
+
+#[async_trait]
+impl<'c> BackendTransaction<'c> for SQLiteWasmTransaction<'c> {
+    async fn commit(&mut self) -> Result<()> {
+        self.end("COMMIT;").await
+    }
+    async fn rollback(&mut self) -> Result<()> {
+        self.end("ROLLBACK;").await
+    }
+    // Workaround for https://github.com/rust-lang/rfcs/issues/2765
+    fn connection_methods(&self) -> &dyn ConnectionMethods {
+        self
+    }
+}
+
+impl Drop for SQLiteWasmTransaction<'_> {
+    fn drop(&mut self) {
+        // Promises run whether or not they are awaited, and the database
+        // serializes calls, so the rollback precedes any later statement
+        if let Some(db) = self.db.take() {
+            let _ = db.0.exec("ROLLBACK;", Array::new());
+        }
+    }
+}
+
+/// Shared functionality between connection and
+/// transaction. Implementation detail. Semver exempt.
+trait DatabaseLike {
+    fn database(&self) -> Result<&SingleThreaded<Database>>;
+}
+
+/// Converts an error thrown by JavaScript.
+fn js_error(e: JsValue) -> Error {
+    let message = match e.dyn_ref::<js_sys::Error>() {
+        Some(e) => e.message().into(),
+        None => e.as_string().unwrap_or_else(|| format!("{e:?}")),
+    };
+    Error::SQLiteWasm(message)
+}
+
+/// Runs the statement `sql` with `params`, returning the number of rows
+/// changed.
+fn run_exec(
+    db: &SingleThreaded<Database>,
+    sql: &str,
+    params: &[SqlValRef<'_>],
+) -> impl Future<Output = Result<usize>> + Send {
+    let promise = js_params(params).and_then(|params| db.0.exec(sql, params).map_err(js_error));
+    SingleThreaded(async move {
+        let changes = JsFuture::from(promise?).await.map_err(js_error)?;
+        changes
+            .as_f64()
+            .map(|n| n as usize)
+            .ok_or_else(|| Error::SQLiteWasm(format!("exec returned {changes:?}")))
+    })
+}
+
+/// Runs the query `sql` with `params`, returning its rows, which must
+/// have the given `columns`.
+fn run_query(
+    db: &SingleThreaded<Database>,
+    sql: &str,
+    params: &[SqlValRef<'_>],
+    columns: &[Column],
+) -> impl Future<Output = Result<Vec<VecRow>>> + Send {
+    let promise = js_params(params).and_then(|params| db.0.query(sql, params).map_err(js_error));
+    let types: Vec<SqlType> = columns.iter().map(|col| col.ty().clone()).collect();
+    SingleThreaded(async move {
+        let rows = JsFuture::from(promise?).await.map_err(js_error)?;
+        let rows: Array = rows
+            .dyn_into()
+            .map_err(|rows| Error::SQLiteWasm(format!("query returned {rows:?}")))?;
+        rows.iter().map(|row| row_from_js(row, &types)).collect()
+    })
+}
+
+/// Runs the query `sql` with `params`, returning the values of the first
+/// row if there is one.
+async fn query_first_row<C>(
+    conn: &C,
+    sql: &str,
+    params: &[SqlValRef<'_>],
+    columns: &[Column],
+) -> Result<Option<Vec<SqlVal>>>
+where
+    C: DatabaseLike + Sync,
+{
+    let rows = run_query(conn.database()?, sql, params, columns).await?;
+    super::connmethods::first_row_values(Box::new(VecRows::new(rows)), columns)
+}
+
+/// Runs the INSERT statement `sql`, returning the primary key and
+/// `returning` columns of the inserted row, or `None` if no row was
+/// inserted.
+async fn insert_returning_row<C>(
+    conn: &C,
+    table: &str,
+    mut sql: String,
+    pkcol: &Column,
+    returning: &[Column],
+    values: &[SqlValRef<'_>],
+) -> Result<Option<Vec<SqlVal>>>
+where
+    C: DatabaseLike + Sync,
+{
+    write!(
+        &mut sql,
+        " RETURNING {}",
+        helper::quote_reserved_word(pkcol.name())
+    )
+    .unwrap();
+    for col in returning {
+        write!(&mut sql, ", {}", helper::quote_reserved_word(col.name())).unwrap();
+    }
+    if cfg!(feature = "log") {
+        debug!("insert sql {sql}");
+        #[cfg(feature = "debug")]
+        debug!("values {}", super::params_for_log(table, &values));
+    }
+    let columns: Vec<Column> = std::iter::once(pkcol).chain(returning).cloned().collect();
+    query_first_row(conn, &sql, values, &columns)
+        .await
+        .map_err(|e| e.in_statement(&sql, table, values.iter().cloned()))
+}
+
+/// Runs the statement `sql` generated for `table`, attaching it to any
+/// error.
+async fn exec_generated(
+    db: &SingleThreaded<Database>,
+    sql: &str,
+    table: &str,
+    params: &[SqlValRef<'_>],
+) -> Result<usize> {
+    run_exec(db, sql, params)
+        .await
+        .map_err(|e| e.in_statement(sql, table, params.iter().cloned()))
+}
+
+/// Returns the value of the blob, or `None` if it is NULL.
+async fn select_blob<C>(conn: &C, blob: &BlobRef) -> Result<Option<Vec<u8>>>
+where
+    C: DatabaseLike + Sync,
+{
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} = ?;",
+        helper::quote_reserved_word(blob.column),
+        helper::quote_reserved_word(blob.table),
+        helper::quote_reserved_word(blob.pkcol)
+    );
+    let columns = [Column::new(blob.column, SqlType::Blob)];
+    let row = query_first_row(conn, &sql, &[blob.pk.as_ref()], &columns).await?;
+    match row.ok_or(Error::NoSuchObject)?.pop() {
+        Some(SqlVal::Blob(data)) => Ok(Some(data)),
+        _ => Ok(None),
+    }
+}
+
+// Implemented for each type rather than for all implementations of
+// DatabaseLike, which would conflict with other backends' implementations.
+macro_rules! impl_connection_methods {
+    ($ty:ty, $in_transaction:expr) => {
+        #[async_trait]
+        impl ConnectionMethods for $ty {
+            async fn execute(&self, sql: &str) -> Result<()> {
+                if cfg!(feature = "log") {
+                    debug!("execute sql {sql}");
+                }
+                run_exec(self.database()?, sql, &[]).await?;
+                Ok(())
+            }
+            async fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+                let sql = helper::translate_placeholders(sql, &mut SQLitePlaceholderSource::new());
+                if cfg!(feature = "log") {
+                    debug!("execute sql {sql}");
+                    #[cfg(feature = "debug")]
+                    debug!("placeholders {params:?}");
+                }
+                run_exec(self.database()?, &sql, params).await
+            }
+            fn max_statement_params(&self) -> usize {
+                // SQLITE_MAX_VARIABLE_NUMBER, since SQLite 3.32
+                32766
+            }
+            fn in_transaction(&self) -> bool {
+                $in_transaction
+            }
+            fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+                // As on dropping a transaction, the statements are undone
+                // before any later ones without waiting for the promise
+                if let Ok(db) = self.database() {
+                    let _ = db.0.exec(&super::atomic::rollback_sql(savepoint), Array::new());
+                }
+            }
+            async fn query_params<'c>(
+                &'c self,
+                sql: &str,
+                params: &[SqlValRef<'_>],
+                columns: &[Column],
+            ) -> Result<RawQueryResult<'c>> {
+                let sql = helper::translate_placeholders(sql, &mut SQLitePlaceholderSource::new());
+                if cfg!(feature = "log") {
+                    debug!("query sql {sql}");
+                    #[cfg(feature = "debug")]
+                    debug!("placeholders {params:?}");
+                }
+                let rows = run_query(self.database()?, &sql, params, columns).await?;
+                Ok(Box::new(VecRows::new(rows)))
+            }
+            async fn query<'c>(
+                &'c self,
+                table: &str,
+                columns: &[Column],
+                expr: Option<BoolExpr>,
+                limit: Option<i32>,
+                offset: Option<i32>,
+                order: Option<&[Order]>,
+            ) -> Result<RawQueryResult<'c>> {
+                let mut sqlquery = String::new();
+                helper::sql_select(columns, table, &mut sqlquery);
+                let mut values: Vec<SqlVal> = Vec::new();
+                let mut pls = SQLitePlaceholderSource::new();
+                if let Some(expr) = expr {
+                    sqlquery.write_str(" WHERE ").unwrap();
+                    sql_for_expr(
+                        query::Expr::Condition(Box::new(expr)),
+                        &mut values,
+                        &mut pls,
+                        &mut sqlquery,
+                    );
+                }
+
+                if let Some(order) = order {
+                    helper::sql_order(order, sql_for_expr, &mut values, &mut pls, &mut sqlquery)
+                }
+
+                if let Some(limit) = limit {
+                    helper::sql_limit(limit, &mut sqlquery)
+                }
+
+                if let Some(offset) = offset {
+                    if limit.is_none() {
+                        // Sqlite only supports offset in conjunction with
+                        // limit, so add a max limit if we don't have one
+                        // already.
+                        helper::sql_limit(i32::MAX, &mut sqlquery)
+                    }
+                    helper::sql_offset(offset, &mut sqlquery)
+                }
+
+                debug!("query sql {sqlquery}");
+                #[cfg(feature = "debug")]
+                debug!("values {}", super::params_for_log(table, &values));
+
+                let values: Vec<SqlValRef<'_>> = values.iter().map(SqlVal::as_ref).collect();
+                let rows = run_query(self.database()?, &sqlquery, &values, columns)
+                    .await
+                    .map_err(|e| e.in_statement(&sqlquery, table, values.iter().cloned()))?;
+                Ok(Box::new(VecRows::new(rows)))
+            }
+            async fn insert_returning_pk(
+                &self,
+                table: &str,
+                columns: &[Column],
+                pkcol: &Column,
+                values: &[SqlValRef<'_>],
+            ) -> Result<SqlVal> {
+                let mut returned = self
+                    .insert_returning(table, columns, pkcol, &[], values)
+                    .await?;
+                Ok(returned.remove(0))
+            }
+            async fn insert_returning(
+                &self,
+                table: &str,
+                columns: &[Column],
+                pkcol: &Column,
+                returning: &[Column],
+                values: &[SqlValRef<'_>],
+            ) -> Result<Vec<SqlVal>> {
+                let mut sql = String::new();
+                helper::sql_insert_with_placeholders(
+                    table,
+                    columns,
+                    &mut SQLitePlaceholderSource::new(),
+                    &mut sql,
+                );
+                insert_returning_row(self, table, sql, pkcol, returning, values)
+                    .await?
+                    .ok_or_else(|| Error::Internal("could not get pk".to_string()))
+            }
+            async fn insert_or_ignore(
+                &self,
+                table: &str,
+                columns: &[Column],
+                pkcol: &Column,
+                returning: &[Column],
+                values: &[SqlValRef<'_>],
+            ) -> Result<Option<Vec<SqlVal>>> {
+                let mut sql = String::new();
+                helper::sql_insert_with_placeholders(
+                    table,
+                    columns,
+                    &mut SQLitePlaceholderSource::new(),
+                    &mut sql,
+                );
+                // ON CONFLICT cannot follow DEFAULT VALUES, so use OR IGNORE
+                let sql = sql.replacen("INSERT ", "INSERT OR IGNORE ", 1);
+                insert_returning_row(self, table, sql, pkcol, returning, values).await
+            }
+            async fn insert_only(
+                &self,
+                table: &str,
+                columns: &[Column],
+                values: &[SqlValRef<'_>],
+            ) -> Result<()> {
+                let mut sql = String::new();
+                helper::sql_insert_with_placeholders(
+                    table,
+                    columns,
+                    &mut SQLitePlaceholderSource::new(),
+                    &mut sql,
+                );
+                if cfg!(feature = "log") {
+                    debug!("insert sql {sql}");
+                    #[cfg(feature = "debug")]
+                    debug!("values {}", super::params_for_log(table, &values));
+                }
+                exec_generated(self.database()?, &sql, table, values).await?;
+                Ok(())
+            }
+            async fn insert_or_replace(
+                &self,
+                table: &str,
+                columns: &[Column],
+                pkcol: &Column,
+                values: &[SqlValRef<'_>],
+            ) -> Result<()> {
+                let mut sql = String::new();
+                sql_insert_or_update(table, columns, pkcol, &mut sql);
+                exec_generated(self.database()?, &sql, table, values).await?;
+                Ok(())
+            }
+            async fn update(
+                &self,
+                table: &str,
+                pkcol: Column,
+                pk: SqlValRef<'_>,
+                columns: &[Column],
+                values: &[SqlValRef<'_>],
+            ) -> Result<()> {
+                let mut sql = String::new();
+                helper::sql_update_with_placeholders(
+                    table,
+                    pkcol,
+                    columns,
+                    &mut SQLitePlaceholderSource::new(),
+                    &mut sql,
+                );
+                let placeholder_values = [values, &[pk]].concat();
+                if cfg!(feature = "log") {
+                    debug!("update sql {sql}");
+                    #[cfg(feature = "debug")]
+                    debug!("placeholders {}", super::params_for_log(table, &placeholder_values));
+                }
+                exec_generated(self.database()?, &sql, table, &placeholder_values).await?;
+                Ok(())
+            }
+            async fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
+                let mut sql = format!("DELETE FROM {} WHERE ", helper::quote_reserved_word(table));
+                let mut values: Vec<SqlVal> = Vec::new();
+                sql_for_expr(
+                    query::Expr::Condition(Box::new(expr)),
+                    &mut values,
+                    &mut SQLitePlaceholderSource::new(),
+                    &mut sql,
+                );
+                if cfg!(feature = "log") {
+                    debug!("delete where sql {sql}");
+                    #[cfg(feature = "debug")]
+                    debug!("placeholders {}", super::params_for_log(table, &values));
+                }
+                let values: Vec<SqlValRef<'_>> = values.iter().map(SqlVal::as_ref).collect();
+                exec_generated(self.database()?, &sql, table, &values).await
+            }
+            async fn update_where(
+                &self,
+                table: &str,
+                assignments: Vec<(&'static str, Expr)>,
+                expr: BoolExpr,
+            ) -> Result<usize> {
+                let mut sql = String::new();
+                let mut values: Vec<SqlVal> = Vec::new();
+                helper::sql_update_where(
+                    table,
+                    assignments,
+                    expr,
+                    sql_for_expr,
+                    &mut values,
+                    &mut SQLitePlaceholderSource::new(),
+                    &mut sql,
+                );
+                if cfg!(feature = "log") {
+                    debug!("update where sql {sql}");
+                    #[cfg(feature = "debug")]
+                    debug!("placeholders {}", super::params_for_log(table, &values));
+                }
+                let values: Vec<SqlValRef<'_>> = values.iter().map(SqlVal::as_ref).collect();
+                exec_generated(self.database()?, &sql, table, &values).await
+            }
+            async fn has_table(&self, table: &str) -> Result<bool> {
+                // A table qualified with a schema is only looked for in that attached database
+                let (schema, table) = table.split_once('.').unwrap_or(("main", table));
+                let sql = format!(
+                    "SELECT name FROM {schema}.sqlite_master WHERE type='table' AND name=?;"
+                );
+                let columns = [Column::new("name", SqlType::Text)];
+                let row = query_first_row(self, &sql, &[SqlValRef::Text(table)], &columns).await?;
+                Ok(row.is_some())
+            }
+            async fn list_tables(&self) -> Result<Vec<String>> {
+                let sql = "SELECT name FROM main.sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name;";
+                let columns = [Column::new("name", SqlType::Text)];
+                let rows = run_query(self.database()?, sql, &[], &columns).await?;
+                rows.iter()
+                    .map(|row| String::from_sql_ref(row.get(0, SqlType::Text)?))
+                    .collect()
+            }
+            async fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+                let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+                let sql = "SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?, ?) ORDER BY cid;";
+                let columns = [
+                    Column::new("name", SqlType::Text),
+                    Column::new("type", SqlType::Text),
+                    Column::new("notnull", SqlType::Bool),
+                    Column::new("dflt_value", SqlType::Text),
+                    Column::new("pk", SqlType::BigInt),
+                ];
+                let params = [SqlValRef::Text(name), SqlValRef::Text(schema)];
+                let rows = run_query(self.database()?, sql, &params, &columns).await?;
+                // Every table has at least one column
+                if rows.is_empty() {
+                    return Ok(None);
+                }
+                let columns = rows
+                    .iter()
+                    .map(|row| {
+                        Ok(ColumnSchema {
+                            name: String::from_sql_ref(row.get(0, SqlType::Text)?)?,
+                            type_name: String::from_sql_ref(row.get(1, SqlType::Text)?)?,
+                            nullable: !bool::from_sql_ref(row.get(2, SqlType::Bool)?)?,
+                            default: Option::<String>::from_sql_ref(row.get(3, SqlType::Text)?)?,
+                            primary_key: i64::from_sql_ref(row.get(4, SqlType::BigInt)?)? > 0,
+                        })
+                    })
+                    .collect::<Result<Vec<ColumnSchema>>>()?;
+                Ok(Some(TableSchema {
+                    name: table.to_string(),
+                    columns,
+                }))
+            }
+            async fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+                let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+                let db = self.database()?;
+                let indexes = run_query(
+                    db,
+                    "SELECT name, \"unique\" FROM pragma_index_list(?, ?) WHERE origin != 'pk' ORDER BY name;",
+                    &[SqlValRef::Text(name), SqlValRef::Text(schema)],
+                    &[
+                        Column::new("name", SqlType::Text),
+                        Column::new("unique", SqlType::Bool),
+                    ],
+                )
+                .await?;
+                let mut result = Vec::with_capacity(indexes.len());
+                for index in indexes {
+                    let name = String::from_sql_ref(index.get(0, SqlType::Text)?)?;
+                    let unique = bool::from_sql_ref(index.get(1, SqlType::Bool)?)?;
+                    let columns = run_query(
+                        db,
+                        "SELECT name FROM pragma_index_info(?, ?) WHERE name IS NOT NULL ORDER BY seqno;",
+                        &[SqlValRef::Text(&name), SqlValRef::Text(schema)],
+                        &[Column::new("name", SqlType::Text)],
+                    )
+                    .await?
+                    .iter()
+                    .map(|row| String::from_sql_ref(row.get(0, SqlType::Text)?))
+                    .collect::<Result<Vec<String>>>()?;
+                    result.push(IndexSchema {
+                        name,
+                        columns,
+                        unique,
+                    });
+                }
+                Ok(result)
+            }
+            async fn notify(&self, _channel: &str, _payload: &str) -> Result<()> {
+                Err(Error::Unsupported(BACKEND_NAME, "notifications"))
+            }
+            async fn set_config(&self, _name: &str, _value: &str, _local: bool) -> Result<()> {
+                Err(Error::Unsupported(BACKEND_NAME, "configuration parameters"))
+            }
+            async fn current_config(&self, _name: &str) -> Result<Option<String>> {
+                Err(Error::Unsupported(BACKEND_NAME, "configuration parameters"))
+            }
+            async fn declare_cursor(
+                &self,
+                _name: &str,
+                _table: &str,
+                _columns: &[Column],
+                _expr: Option<BoolExpr>,
+                _limit: Option<i32>,
+                _offset: Option<i32>,
+                _sort: Option<&[Order]>,
+            ) -> Result<()> {
+                Err(Error::Unsupported(BACKEND_NAME, "server-side cursors"))
+            }
+            async fn fetch_cursor<'c>(
+                &'c self,
+                _name: &str,
+                _columns: &[Column],
+                _count: u32,
+            ) -> Result<RawQueryResult<'c>> {
+                Err(Error::Unsupported(BACKEND_NAME, "server-side cursors"))
+            }
+            async fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+                if blob.large_object {
+                    return Err(Error::Unsupported(BACKEND_NAME, "large objects"));
+                }
+                let sql = format!(
+                    "SELECT length({}) FROM {} WHERE {} = ?;",
+                    helper::quote_reserved_word(blob.column),
+                    helper::quote_reserved_word(blob.table),
+                    helper::quote_reserved_word(blob.pkcol)
+                );
+                let columns = [Column::new("length", SqlType::BigInt)];
+                let row = query_first_row(self, &sql, &[blob.pk.as_ref()], &columns).await?;
+                match row.ok_or(Error::NoSuchObject)?.pop() {
+                    Some(SqlVal::BigInt(len)) => Ok(Some(len as u64)),
+                    _ => Ok(None),
+                }
+            }
+            async fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+                let data = select_blob(self, blob).await?.unwrap_or_default();
+                let start = (offset as usize).min(data.len());
+                let end = start.saturating_add(len).min(data.len());
+                Ok(data[start..end].to_vec())
+            }
+            async fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+                // There is no incremental blob I/O through the JavaScript
+                // bindings, so the whole blob is rewritten
+                let mut value = select_blob(self, blob).await?.unwrap_or_default();
+                let start = offset as usize;
+                let end = start.checked_add(data.len()).ok_or(Error::OutOfRange)?;
+                value
+                    .get_mut(start..end)
+                    .ok_or(Error::OutOfRange)?
+                    .copy_from_slice(data);
+                let sql = format!(
+                    "UPDATE {} SET {} = ? WHERE {} = ?;",
+                    helper::quote_reserved_word(blob.table),
+                    helper::quote_reserved_word(blob.column),
+                    helper::quote_reserved_word(blob.pkcol)
+                );
+                let params = [SqlValRef::Blob(&value), blob.pk.as_ref()];
+                run_exec(self.database()?, &sql, &params).await?;
+                Ok(())
+            }
+            async fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+                if blob.large_object {
+                    return Err(Error::Unsupported(BACKEND_NAME, "large objects"));
+                }
+                let sql = format!(
+                    "UPDATE {} SET {} = zeroblob(?) WHERE {} = ?;",
+                    helper::quote_reserved_word(blob.table),
+                    helper::quote_reserved_word(blob.column),
+                    helper::quote_reserved_word(blob.pkcol)
+                );
+                let len = i64::try_from(len)
+                    .map_err(|_| Error::Internal("blob too large".to_string()))?;
+                let params = [SqlValRef::BigInt(len), blob.pk.as_ref()];
+                match run_exec(self.database()?, &sql, &params).await? {
+                    0 => Err(Error::NoSuchObject),
+                    _ => Ok(()),
+                }
+            }
+        }
+    };
+}
+
+impl_connection_methods!(SQLiteWasmConnection, false);
+impl_connection_methods!(SQLiteWasmTransaction<'_>, true);
+
+/// Converts parameters to the values the JavaScript bindings take.
+fn js_params(params: &[SqlValRef<'_>]) -> Result<Array> {
+    params.iter().map(js_value).collect()
+}
+
+fn js_value(valref: &SqlValRef<'_>) -> Result<JsValue> {
+    use SqlValRef::*;
+    Ok(match valref {
+        Bool(b) => JsValue::from(*b as i32),
+        Int(i) => JsValue::from(*i),
+        // Integers are passed as numbers if they can be represented exactly
+        BigInt(i) if i.unsigned_abs() <= 1 << f64::MANTISSA_DIGITS => JsValue::from(*i as f64),
+        BigInt(i) => JsValue::from(*i),
+        Real(r) => JsValue::from(*r),
+        Text(t) => JsValue::from_str(t),
+        Blob(b) => Uint8Array::from(*b).into(),
+        TextList(v) => JsValue::from(serde_json::to_string(v)?),
+        #[cfg(feature = "json")]
+        Json(v) => JsValue::from(serde_json::to_string(v)?),
+        #[cfg(feature = "datetime")]
+        Date(date) => JsValue::from(date.format(SQLITE_DATE_FORMAT).to_string()),
+        #[cfg(feature = "datetime")]
+        Timestamp(dt) => JsValue::from(dt.format(SQLITE_DT_FORMAT).to_string()),
+        Null => JsValue::NULL,
+        Custom(c) => return Err(Error::IncompatibleCustom(c.clone().into(), BACKEND_NAME)),
+    })
+}
+
+fn row_from_js(row: JsValue, types: &[SqlType]) -> Result<VecRow> {
+    let row: Array = row
+        .dyn_into()
+        .map_err(|row| Error::SQLiteWasm(format!("row returned as {row:?}")))?;
+    if row.length() as usize != types.len() {
+        return Err(Error::BoundsError(
+            "row length doesn't match columns specifier length".into(),
+        ));
+    }
+    row.iter()
+        .zip(types)
+        .map(|(val, ty)| sql_val_from_js(val, ty))
+        .collect::<Result<Vec<SqlVal>>>()
+        .map(VecRow::from_values)
+}
+
+fn sql_val_from_js(val: JsValue, ty: &SqlType) -> Result<SqlVal> {
+    if val.is_null() || val.is_undefined() {
+        return Ok(SqlVal::Null);
+    }
+    let mismatch = |val: &JsValue| Error::SQLiteWasm(format!("expected {ty}, found {val:?}"));
+    let text = |val: JsValue| val.as_string().ok_or_else(|| mismatch(&val));
+    Ok(match ty {
+        SqlType::Bool => SqlVal::Bool(js_integer(&val).ok_or_else(|| mismatch(&val))? != 0),
+        SqlType::Int => {
+            let i = js_integer(&val).ok_or_else(|| mismatch(&val))?;
+            SqlVal::Int(i32::try_from(i).map_err(|_| Error::OutOfRange)?)
+        }
+        SqlType::BigInt => SqlVal::BigInt(js_integer(&val).ok_or_else(|| mismatch(&val))?),
+        SqlType::Real => SqlVal::Real(val.as_f64().ok_or_else(|| mismatch(&val))?),
+        SqlType::Text => SqlVal::Text(text(val)?),
+        SqlType::TextList => SqlVal::TextList(serde_json::from_str(&text(val)?)?),
+        #[cfg(feature = "json")]
+        SqlType::Json => SqlVal::Json(serde_json::from_str(&text(val)?)?),
+        #[cfg(feature = "datetime")]
+        SqlType::Date => SqlVal::Date(NaiveDate::parse_from_str(&text(val)?, SQLITE_DATE_FORMAT)?),
+        #[cfg(feature = "datetime")]
+        SqlType::Timestamp => SqlVal::Timestamp(NaiveDateTime::parse_from_str(
+            &text(val)?,
+            SQLITE_DT_FORMAT,
+        )?),
+        SqlType::Blob => SqlVal::Blob(
+            val.dyn_into::<Uint8Array>()
+                .map_err(|val| mismatch(&val))?
+                .to_vec(),
+        ),
+        SqlType::Custom(v) => return Err(Error::IncompatibleCustomT(v.clone(), BACKEND_NAME)),
+    })
+}
+
+/// Reads an integer passed as either a number or a `BigInt`.
+fn js_integer(val: &JsValue) -> Option<i64> {
+    match val.as_f64() {
+        Some(n) => Some(n as i64),
+        None => i64::try_from(val.clone()).ok(),
+    }
+}
@@ -5,11 +5,12 @@
 
 use crate::db::{
     Backend, BackendConnection, BackendConnectionAsync, BackendTransaction,
-    BackendTransactionAsync, Connection, ConnectionAsync, ConnectionMethods, RawQueryResult,
-    Transaction, TransactionAsync,
+    BackendTransactionAsync, BlobRef, Connection, ConnectionAsync, ConnectionMethods, IndexSchema,
+    RawQueryResult, ScalarFunction, TableSchema, Transaction, TransactionAsync,
 };
 use crate::migrations::adb;
-use crate::query::{BoolExpr, Order};
+use crate::notify::UpdateHook;
+use crate::query::{BoolExpr, Expr, Order};
 use crate::{debug, Column, Result, SqlVal, SqlValRef};
 
 /// Adapter that allows running synchronous operations on an async type.
@@ -36,10 +37,16 @@ pub fn new(inner: T) -> Result<Self> {
             }
             Err(_) => {
                 debug!("Creating new tokio runtime");
+                #[cfg(not(target_arch = "wasm32"))]
                 let runtime = tokio::runtime::Builder::new_multi_thread()
                     .worker_threads(1)
                     .enable_all()
                     .build()?;
+                // wasm has no threads, and tokio no multi-threaded runtime there.
+                #[cfg(target_arch = "wasm32")]
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
                 Ok(Self {
                     runtime_handle: runtime.handle().clone(),
                     _runtime: Some(Arc::new(runtime)),
@@ -87,6 +94,29 @@ impl<T> crate::db::ConnectionMethods for SyncAdapter<T>
     fn execute(&self, sql: &str) -> Result<()> {
         self.block_on(self.inner.execute(sql))
     }
+    fn execute_params(&self, sql: &str, params: &[SqlValRef<'_>]) -> Result<usize> {
+        self.block_on(self.inner.execute_params(sql, params))
+    }
+    fn max_statement_params(&self) -> usize {
+        self.inner.max_statement_params()
+    }
+    fn in_transaction(&self) -> bool {
+        self.inner.in_transaction()
+    }
+    fn abandon_atomic(&self, savepoint: Option<&'static str>) {
+        self.inner.abandon_atomic(savepoint)
+    }
+    fn key_provider(&self) -> Option<&dyn crate::encryption::KeyProvider> {
+        self.inner.key_provider()
+    }
+    fn query_params<'c>(
+        &'c self,
+        sql: &str,
+        params: &[SqlValRef<'_>],
+        columns: &[Column],
+    ) -> Result<RawQueryResult<'c>> {
+        self.block_on(self.inner.query_params(sql, params, columns))
+    }
     fn query<'c>(
         &'c self,
         table: &str,
@@ -98,6 +128,20 @@ fn query<'c>(
     ) -> Result<RawQueryResult<'c>> {
         self.block_on(self.inner.query(table, columns, expr, limit, offset, sort))
     }
+    fn query_counted<'c>(
+        &'c self,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<(RawQueryResult<'c>, u64)> {
+        self.block_on(
+            self.inner
+                .query_counted(table, columns, expr, limit, offset, sort),
+        )
+    }
     fn insert_returning_pk(
         &self,
         table: &str,
@@ -110,6 +154,32 @@ fn insert_returning_pk(
                 .insert_returning_pk(table, columns, pkcol, values),
         )
     }
+    fn insert_returning(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Vec<SqlVal>> {
+        self.block_on(
+            self.inner
+                .insert_returning(table, columns, pkcol, returning, values),
+        )
+    }
+    fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &[Column],
+        pkcol: &Column,
+        returning: &[Column],
+        values: &[SqlValRef<'_>],
+    ) -> Result<Option<Vec<SqlVal>>> {
+        self.block_on(
+            self.inner
+                .insert_or_ignore(table, columns, pkcol, returning, values),
+        )
+    }
     fn insert_only(&self, table: &str, columns: &[Column], values: &[SqlValRef<'_>]) -> Result<()> {
         self.block_on(self.inner.insert_only(table, columns, values))
     }
@@ -135,9 +205,78 @@ fn update(
     fn delete_where(&self, table: &str, expr: BoolExpr) -> Result<usize> {
         self.block_on(self.inner.delete_where(table, expr))
     }
+    fn delete_returning(
+        &self,
+        table: &str,
+        expr: BoolExpr,
+        returning: &[Column],
+    ) -> Result<Vec<Vec<SqlVal>>> {
+        self.block_on(self.inner.delete_returning(table, expr, returning))
+    }
+    fn update_where(
+        &self,
+        table: &str,
+        assignments: Vec<(&'static str, Expr)>,
+        expr: BoolExpr,
+    ) -> Result<usize> {
+        self.block_on(self.inner.update_where(table, assignments, expr))
+    }
     fn has_table(&self, table: &str) -> Result<bool> {
         self.block_on(self.inner.has_table(table))
     }
+    fn list_tables(&self) -> Result<Vec<String>> {
+        self.block_on(self.inner.list_tables())
+    }
+    fn table_schema(&self, table: &str) -> Result<Option<TableSchema>> {
+        self.block_on(self.inner.table_schema(table))
+    }
+    fn list_indexes(&self, table: &str) -> Result<Vec<IndexSchema>> {
+        self.block_on(self.inner.list_indexes(table))
+    }
+    fn notify(&self, channel: &str, payload: &str) -> Result<()> {
+        self.block_on(self.inner.notify(channel, payload))
+    }
+    fn set_config(&self, name: &str, value: &str, local: bool) -> Result<()> {
+        self.block_on(self.inner.set_config(name, value, local))
+    }
+    fn current_config(&self, name: &str) -> Result<Option<String>> {
+        self.block_on(self.inner.current_config(name))
+    }
+    fn declare_cursor(
+        &self,
+        name: &str,
+        table: &str,
+        columns: &[Column],
+        expr: Option<BoolExpr>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        sort: Option<&[Order]>,
+    ) -> Result<()> {
+        self.block_on(
+            self.inner
+                .declare_cursor(name, table, columns, expr, limit, offset, sort),
+        )
+    }
+    fn fetch_cursor<'c>(
+        &'c self,
+        name: &str,
+        columns: &[Column],
+        count: u32,
+    ) -> Result<RawQueryResult<'c>> {
+        self.block_on(self.inner.fetch_cursor(name, columns, count))
+    }
+    fn blob_len(&self, blob: &BlobRef) -> Result<Option<u64>> {
+        self.block_on(self.inner.blob_len(blob))
+    }
+    fn read_blob(&self, blob: &BlobRef, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.block_on(self.inner.read_blob(blob, offset, len))
+    }
+    fn write_blob(&self, blob: &BlobRef, offset: u64, data: &[u8]) -> Result<()> {
+        self.block_on(self.inner.write_blob(blob, offset, data))
+    }
+    fn allocate_blob(&self, blob: &BlobRef, len: u64) -> Result<()> {
+        self.block_on(self.inner.allocate_blob(blob, len))
+    }
 }
 
 impl<T> BackendConnection for SyncAdapter<T>
@@ -167,6 +306,12 @@ fn backend_name(&self) -> &'static str {
     fn is_closed(&self) -> bool {
         self.inner.is_closed()
     }
+    fn set_update_hook(&self, hook: Option<UpdateHook>) -> Result<()> {
+        self.inner.set_update_hook(hook)
+    }
+    fn create_function(&self, name: &str, n_args: i32, func: ScalarFunction) -> Result<()> {
+        self.inner.create_function(name, n_args, func)
+    }
 }
 
 impl<T> SyncAdapter<T>
@@ -209,10 +354,7 @@ fn create_migration_sql(&self, current: &adb::ADB, ops: Vec<adb::Operation>) ->
     }
     fn connect(&self, conn_str: &str) -> Result<Connection> {
         let conn_async = self.block_on(self.inner.connect_async(conn_str))?;
-        let conn = Connection {
-            conn: Box::new(self.chain(conn_async.conn)),
-        };
-        Ok(conn)
+        Ok(Connection::new(Box::new(self.chain(conn_async.conn))))
     }
     async fn connect_async(&self, conn_str: &str) -> Result<ConnectionAsync> {
         self.inner.connect_async(conn_str).await
@@ -0,0 +1,147 @@
+//! Deadlines bounding the total time spent on database operations.
+//!
+//! A deadline set with [`with_deadline`] is carried in the task
+//! context and applies to every butane operation awaited within it,
+//! however deeply nested. Each operation fails with
+//! [`Error::DeadlineExceeded`] once the deadline has passed. Where the
+//! backend supports it, the remaining time is also passed to the
+//! database so that a statement which is already running is stopped
+//! rather than left to finish after the caller has given up.
+//!
+//! The future run with [`with_deadline`] is also cancelled at the
+//! deadline, so that waits the database cannot bound, such as for a
+//! connection from a pool or a stalled network, end too. A Postgres
+//! connection whose statement timeout was left set by a cancelled
+//! operation resets it before running anything else.
+//!
+//! * Postgres sets `statement_timeout` for each statement run within
+//!   a deadline.
+//! * SQLite interrupts statements still running at the deadline.
+//! * Other backends check the deadline before each operation.
+//!
+//! A single query may also be bounded with
+//! [`Query::with_timeout`](crate::query::Query::with_timeout), which
+//! runs its operations with a deadline in the same way.
+
+use std::cell::Cell;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+thread_local! {
+    /// Deadline for synchronous operations run on behalf of an async
+    /// task on another thread, where the task context is not available.
+    static SYNC_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+#[cfg(feature = "async")]
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Runs `fut` with a deadline `timeout` from now.
+///
+/// All butane operations within `fut` are bounded by the deadline, and
+/// `fut` itself is cancelled if it has not completed by then. In either
+/// case the result is [`Error::DeadlineExceeded`]. If there is already a
+/// deadline in effect, the earlier of the two applies.
+#[cfg(feature = "async")]
+pub async fn with_deadline<F, T, E>(timeout: Duration, fut: F) -> std::result::Result<T, E>
+where
+    F: Future<Output = std::result::Result<T, E>>,
+    E: From<Error>,
+{
+    let Some(deadline) = earliest(timeout) else {
+        // Too far in the future to represent, so there is no deadline
+        return fut.await;
+    };
+    match DEADLINE
+        .scope(deadline, tokio::time::timeout_at(deadline.into(), fut))
+        .await
+    {
+        Ok(result) => result,
+        Err(_) => Err(Error::DeadlineExceeded.into()),
+    }
+}
+
+/// Returns the deadline currently in effect, if any.
+pub fn current() -> Option<Instant> {
+    #[cfg(feature = "async")]
+    let task_deadline = DEADLINE.try_with(|deadline| *deadline).ok();
+    #[cfg(not(feature = "async"))]
+    let task_deadline = None;
+    task_deadline.into_iter().chain(SYNC_DEADLINE.get()).min()
+}
+
+/// Returns the earlier of the deadline `timeout` from now and the one
+/// currently in effect, or `None` if there is neither, or only a
+/// timeout too far in the future to represent.
+fn earliest(timeout: Duration) -> Option<Instant> {
+    Instant::now()
+        .checked_add(timeout)
+        .into_iter()
+        .chain(current())
+        .min()
+}
+
+/// Runs the future returned by `op`, with a deadline `timeout` from
+/// now if there is a timeout. Used to bound a single query.
+#[cfg(feature = "async")]
+pub(crate) async fn bounded_async<T, F>(
+    timeout: Option<Duration>,
+    op: impl FnOnce() -> F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match timeout {
+        Some(timeout) => with_deadline(timeout, op()).await,
+        None => op().await,
+    }
+}
+
+/// Runs `op`, with a deadline `timeout` from now in effect on this
+/// thread if there is a timeout. Used to bound a single query.
+pub(crate) fn bounded_sync<T>(
+    timeout: Option<Duration>,
+    op: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => scope_sync(earliest(timeout), || {
+            remaining()?;
+            op()
+        }),
+        None => op(),
+    }
+}
+
+/// Returns the time remaining before the deadline currently in effect,
+/// or `None` if there is no deadline.
+///
+/// Returns [`Error::DeadlineExceeded`] if the deadline has passed.
+pub fn remaining() -> Result<Option<Duration>> {
+    match current() {
+        None => Ok(None),
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => Ok(Some(remaining)),
+            _ => Err(Error::DeadlineExceeded),
+        },
+    }
+}
+
+/// Runs `f` with `deadline` in effect on this thread.
+///
+/// Used to carry the deadline of an async task to the thread running
+/// synchronous operations on its behalf.
+pub(crate) fn scope_sync<R>(deadline: Option<Instant>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Instant>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SYNC_DEADLINE.set(self.0);
+        }
+    }
+    let _restore = Restore(SYNC_DEADLINE.replace(deadline));
+    f()
+}
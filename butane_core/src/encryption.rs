@@ -0,0 +1,315 @@
+//! Encryption of the values of fields by the application, for personal
+//! or otherwise sensitive data which must be encrypted at rest beyond
+//! any encryption of the disk.
+//!
+//! A field of type [`Encrypted<String>`] or [`Encrypted<Vec<u8>>`] holds
+//! its value in plaintext in memory, and is stored as a blob holding the
+//! ciphertext along with the nonce it was encrypted with and the
+//! identifier of the key. Butane does not implement a cipher itself:
+//! the application sets a [`KeyProvider`] on each connection with
+//! [`Connection::set_key_provider`](crate::db::Connection::set_key_provider),
+//! usually backed by an AEAD such as AES-GCM and keys held by a key
+//! management service. Values are encrypted with the current key of the
+//! provider each time the object holding them is saved, and decrypted
+//! with the key they were encrypted with when it is loaded, so that keys
+//! may be rotated without rewriting existing rows.
+//!
+//! Each value is encrypted with the table, column and primary key of its
+//! row as associated data, so that a value copied to another row or
+//! column fails to decrypt. The primary key must therefore be known
+//! before the object is first saved, so models with an
+//! [`AutoPk`](crate::AutoPk) cannot have encrypted fields. For the same
+//! reason, encrypted fields are only changed by saving the object
+//! holding them, not by a patch or [`Update`](crate::query::Update).
+//!
+//! As each value is encrypted with a new nonce, equal values are stored
+//! differently, so encrypted fields cannot usefully be filtered on,
+//! sorted or declared `#[unique]`.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::{Error, FieldType, FromSql, Result, SqlType, SqlVal, SqlValRef, ToSql};
+
+/// Version of the format of stored values, written as their first byte.
+const FORMAT_VERSION: u8 = 1;
+
+/// Source of the keys encrypting [`Encrypted`] fields, which performs the
+/// encryption with them. Set on a connection with
+/// [`Connection::set_key_provider`](crate::db::Connection::set_key_provider).
+///
+/// Values are encrypted and decrypted with associated data `aad`
+/// identifying the table, column and primary key they are stored in,
+/// which must be authenticated along with the ciphertext, as by the
+/// associated data of an AEAD.
+pub trait KeyProvider: Send + Sync {
+    /// The identifier of the key to encrypt new values with, of at most
+    /// 255 bytes.
+    fn current_key_id(&self) -> &str;
+
+    /// Encrypts `plaintext` with the key `key_id` and associated data
+    /// `aad`, returning the nonce used, of at most 255 bytes, and the
+    /// ciphertext. The nonce must never have been used before with the
+    /// same key.
+    fn encrypt(&self, key_id: &str, plaintext: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+
+    /// Decrypts `ciphertext` encrypted with the key `key_id`, `nonce` and
+    /// associated data `aad`, failing if either has been tampered with.
+    fn decrypt(&self, key_id: &str, nonce: &[u8], ciphertext: &[u8], aad: &[u8])
+        -> Result<Vec<u8>>;
+}
+
+/// The key provider set on a connection, shared with the transactions
+/// begun from it.
+#[derive(Clone, Default)]
+pub(crate) struct Keys(Option<Arc<dyn KeyProvider>>);
+
+impl Keys {
+    pub(crate) fn new(provider: impl KeyProvider + 'static) -> Self {
+        Keys(Some(Arc::new(provider)))
+    }
+
+    pub(crate) fn get(&self) -> Option<&dyn KeyProvider> {
+        self.0.as_deref()
+    }
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Keys(..)"),
+            None => f.write_str("Keys(None)"),
+        }
+    }
+}
+
+/// Returns the key provider of a connection, failing if it has none.
+/// Used internally.
+#[doc(hidden)]
+pub fn require_key_provider(keys: Option<&dyn KeyProvider>) -> Result<&dyn KeyProvider> {
+    keys.ok_or_else(|| Error::Encryption("no key provider is set on the connection".to_string()))
+}
+
+/// The associated data an encrypted value of `column` is bound to, in
+/// the row of `table` with primary key `pk`. Used internally.
+#[doc(hidden)]
+pub fn associated_data(table: &str, column: &str, pk: SqlValRef<'_>) -> Vec<u8> {
+    let pk = SqlVal::from(pk).to_string();
+    let mut aad = Vec::with_capacity(12 + table.len() + column.len() + pk.len());
+    // Each part is preceded by its length, so that no two rows share
+    // the same associated data
+    for part in [table, column, pk.as_str()] {
+        aad.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        aad.extend_from_slice(part.as_bytes());
+    }
+    aad
+}
+
+/// A type whose values may be [`Encrypted`].
+pub trait Plaintext: Sized {
+    /// The bytes to encrypt.
+    fn as_bytes(&self) -> &[u8];
+
+    /// Recreates a value from its decrypted bytes.
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self>;
+}
+
+impl Plaintext for String {
+    fn as_bytes(&self) -> &[u8] {
+        self.as_str().as_bytes()
+    }
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        String::from_utf8(bytes).map_err(|e| Error::Encryption(e.to_string()))
+    }
+}
+
+impl Plaintext for Vec<u8> {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Ok(bytes)
+    }
+}
+
+/// A field value which is stored encrypted, see the [module
+/// documentation](self). Dereferences to the plaintext value.
+///
+/// Its `Debug` output omits the value, so that it is not logged.
+#[derive(Clone)]
+pub struct Encrypted<T> {
+    value: T,
+    // The value as stored, once the object holding it has been saved or
+    // loaded: the format version, then the key id and the nonce each
+    // preceded by their length, then the ciphertext.
+    stored: Option<Vec<u8>>,
+}
+
+impl<T: Plaintext> Encrypted<T> {
+    /// Holds `value`, to be encrypted when the object holding it is
+    /// saved.
+    pub fn new(value: T) -> Self {
+        Encrypted {
+            value,
+            stored: None,
+        }
+    }
+
+    /// Replaces the value, to be encrypted when the object holding it is
+    /// saved.
+    pub fn set(&mut self, value: T) {
+        *self = Self::new(value);
+    }
+
+    /// The identifier of the key the value was encrypted with when the
+    /// object holding it was last saved or loaded, if it has been.
+    pub fn key_id(&self) -> Option<&str> {
+        // The stored value was either created by `seal` or validated by `open`
+        let stored = self.stored.as_deref()?;
+        let (key_id, _, _) = split(stored).expect("invalid encrypted value");
+        Some(key_id)
+    }
+
+    /// Returns the plaintext value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+fn seal(keys: &dyn KeyProvider, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let key_id = keys.current_key_id();
+    let (nonce, ciphertext) = keys.encrypt(key_id, plaintext, aad)?;
+    let (Ok(key_id_len), Ok(nonce_len)) = (u8::try_from(key_id.len()), u8::try_from(nonce.len()))
+    else {
+        return Err(Error::Encryption(
+            "key id or nonce longer than 255 bytes".to_string(),
+        ));
+    };
+    let mut stored = Vec::with_capacity(3 + key_id.len() + nonce.len() + ciphertext.len());
+    stored.push(FORMAT_VERSION);
+    stored.push(key_id_len);
+    stored.extend_from_slice(key_id.as_bytes());
+    stored.push(nonce_len);
+    stored.extend_from_slice(&nonce);
+    stored.extend_from_slice(&ciphertext);
+    Ok(stored)
+}
+
+/// Splits a stored value into its key id, nonce and ciphertext.
+fn split(stored: &[u8]) -> Result<(&str, &[u8], &[u8])> {
+    let invalid = || Error::Encryption("invalid encrypted value".to_string());
+    let (&version, rest) = stored.split_first().ok_or_else(invalid)?;
+    if version != FORMAT_VERSION {
+        return Err(Error::Encryption(format!(
+            "unsupported encrypted value version {version}"
+        )));
+    }
+    let (&key_id_len, rest) = rest.split_first().ok_or_else(invalid)?;
+    let (key_id, rest) = rest
+        .split_at_checked(key_id_len.into())
+        .ok_or_else(invalid)?;
+    let key_id = std::str::from_utf8(key_id).map_err(|_| invalid())?;
+    let (&nonce_len, rest) = rest.split_first().ok_or_else(invalid)?;
+    let (nonce, ciphertext) = rest
+        .split_at_checked(nonce_len.into())
+        .ok_or_else(invalid)?;
+    Ok((key_id, nonce, ciphertext))
+}
+
+fn open<T: Plaintext>(keys: &dyn KeyProvider, stored: &[u8], aad: &[u8]) -> Result<T> {
+    let (key_id, nonce, ciphertext) = split(stored)?;
+    T::from_bytes(keys.decrypt(key_id, nonce, ciphertext, aad)?)
+}
+
+/// An [`Encrypted`] field, or an optional one, which the code generated
+/// for a model encrypts when saving it and decrypts when loading it.
+/// Used internally.
+#[doc(hidden)]
+pub trait EncryptedField: Sized {
+    /// Encrypts the value with the current key of `keys`.
+    fn seal(&mut self, keys: &dyn KeyProvider, aad: &[u8]) -> Result<()>;
+
+    /// Decrypts a value loaded from the database.
+    fn open(val: SqlValRef<'_>, keys: &dyn KeyProvider, aad: &[u8]) -> Result<Self>;
+}
+
+impl<T: Plaintext> EncryptedField for Encrypted<T> {
+    fn seal(&mut self, keys: &dyn KeyProvider, aad: &[u8]) -> Result<()> {
+        self.stored = Some(seal(keys, self.value.as_bytes(), aad)?);
+        Ok(())
+    }
+    fn open(val: SqlValRef<'_>, keys: &dyn KeyProvider, aad: &[u8]) -> Result<Self> {
+        match val {
+            SqlValRef::Blob(stored) => Ok(Encrypted {
+                value: open(keys, stored, aad)?,
+                stored: Some(stored.to_vec()),
+            }),
+            _ => Err(Error::CannotConvertSqlVal(SqlType::Blob, val.into())),
+        }
+    }
+}
+
+impl<T: Plaintext> EncryptedField for Option<Encrypted<T>> {
+    fn seal(&mut self, keys: &dyn KeyProvider, aad: &[u8]) -> Result<()> {
+        match self {
+            Some(encrypted) => encrypted.seal(keys, aad),
+            None => Ok(()),
+        }
+    }
+    fn open(val: SqlValRef<'_>, keys: &dyn KeyProvider, aad: &[u8]) -> Result<Self> {
+        match val {
+            SqlValRef::Null => Ok(None),
+            val => Encrypted::open(val, keys, aad).map(Some),
+        }
+    }
+}
+
+impl<T> Deref for Encrypted<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: PartialEq> PartialEq for Encrypted<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T> fmt::Debug for Encrypted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Encrypted(..)")
+    }
+}
+
+/// Encrypted values are decrypted only as part of the model holding
+/// them, whose primary key they are bound to.
+impl<T: Plaintext> FromSql for Encrypted<T> {
+    fn from_sql_ref(_val: SqlValRef<'_>) -> Result<Self> {
+        Err(Error::Encryption(
+            "encrypted values may only be loaded with the model holding them".to_string(),
+        ))
+    }
+}
+
+/// A value which has not been encrypted yet, as the object holding it
+/// has not been saved since it was set, is an empty blob, which fails
+/// to decrypt, so that its plaintext is never written.
+impl<T> ToSql for Encrypted<T> {
+    fn to_sql(&self) -> SqlVal {
+        SqlVal::Blob(self.stored.clone().unwrap_or_default())
+    }
+    fn to_sql_ref(&self) -> SqlValRef<'_> {
+        SqlValRef::Blob(self.stored.as_deref().unwrap_or_default())
+    }
+    fn into_sql(self) -> SqlVal {
+        SqlVal::Blob(self.stored.unwrap_or_default())
+    }
+}
+
+impl<T: Plaintext> FieldType for Encrypted<T> {
+    const SQLTYPE: SqlType = SqlType::Blob;
+    type RefType = Self;
+}
@@ -12,8 +12,8 @@
 #[cfg(feature = "async")]
 use crate::{util::get_or_init_once_lock_async, ConnectionMethodsAsync};
 use crate::{
-    AsPrimaryKey, ConnectionMethods, DataObject, Error, FieldType, FromSql, Result, SqlType,
-    SqlVal, SqlValRef, ToSql,
+    AsPrimaryKey, ConnectionMethods, DataObject, Error, FieldType, FromSql, PrimaryKeyType, Result,
+    SqlType, SqlVal, SqlValRef, ToSql,
 };
 
 /// Used to implement a relationship between models.
@@ -22,6 +22,12 @@
 ///
 /// See [`ForeignKeyOpsSync`] and [`ForeignKeyOpsAsync`] for operations requiring a live database connection.
 ///
+/// With serde it is serialized as the primary key of the value it refers
+/// to. See [`nested`] for a representation including the value itself.
+///
+/// It is `Send` and `Sync` if `T` is, so models referring to others
+/// may be shared with other threads and tasks.
+///
 /// # Examples
 /// ```ignore
 /// #[model]
@@ -160,6 +166,11 @@ fn as_pk(&self) -> Cow<'_, T::PKType> {
 
 impl<T: DataObject> Eq for ForeignKey<T> {}
 
+/// A foreign key may be the primary key of a model, which then shares
+/// the primary key of the model it refers to, as an extension of it in
+/// a one-to-one relationship.
+impl<T: DataObject + Clone + Send> PrimaryKeyType for ForeignKey<T> {}
+
 impl<T> ToSql for ForeignKey<T>
 where
     T: DataObject,
@@ -236,9 +247,268 @@ fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
 }
 
 #[cfg(feature = "fake")]
-/// Fake data support is currently limited to empty ForeignKey relationships.
+/// Fake ForeignKey relationships are empty, except when generated by a
+/// [`Seeder`](crate::seed::Seeder), which refers them to a random object
+/// it seeded before.
 impl<T: DataObject> Dummy<Faker> for ForeignKey<T> {
-    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &Faker, _rng: &mut R) -> Self {
-        Self::new_raw()
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &Faker, rng: &mut R) -> Self {
+        let ret = Self::new_raw();
+        if let Some(pk) = crate::seed::seeded_pk(T::TABLE, rng) {
+            ret.valpk.set(pk).unwrap();
+        }
+        ret
+    }
+}
+
+/// Alternative serde representation of a [`ForeignKey`], for use with
+/// `#[serde(with = "butane::fkey::nested")]` on a field.
+///
+/// By default a `ForeignKey` is serialized as the primary key of the
+/// value it refers to. With this representation, it is instead
+/// serialized as the whole value if that has been loaded, and as the
+/// primary key only otherwise, so that related objects can be embedded
+/// in the output by loading them first. Either form is accepted when
+/// deserializing.
+///
+/// # Examples
+/// ```ignore
+/// #[model]
+/// #[derive(Serialize, Deserialize)]
+/// struct Post {
+///   #[serde(with = "butane::fkey::nested")]
+///   blog: ForeignKey<Blog>,
+///   ...
+/// }
+/// ```
+pub mod nested {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ForeignKey;
+    use crate::DataObject;
+
+    /// Either a value or its primary key, whichever is present.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    pub(crate) enum ValueOrPk<T, P> {
+        Value(T),
+        Pk(P),
+    }
+
+    /// Serializes the value referred to by `fkey` if it has been
+    /// loaded, or its primary key otherwise.
+    pub fn serialize<T, S>(fkey: &ForeignKey<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: DataObject + Serialize,
+        T::PKType: Serialize,
+        S: Serializer,
+    {
+        match fkey.get() {
+            Ok(val) => val.serialize(serializer),
+            Err(_) => fkey.pk().serialize(serializer),
+        }
+    }
+
+    /// Deserializes a `ForeignKey` from either the value it refers to,
+    /// which is then loaded, or its primary key.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<ForeignKey<T>, D::Error>
+    where
+        T: DataObject + Deserialize<'de>,
+        T::PKType: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(
+            match ValueOrPk::<T, T::PKType>::deserialize(deserializer)? {
+                ValueOrPk::Value(val) => ForeignKey::from(val),
+                ValueOrPk::Pk(pk) => ForeignKey::from_pk(pk),
+            },
+        )
+    }
+}
+
+/// A relationship to an object of any model, for features such as
+/// comments or attachments which may belong to objects of several
+/// models, without a column for each of them.
+///
+/// It records the table of the model referred to and the primary key of
+/// the object, stored together as JSON in a single text column. There is
+/// no foreign key constraint, so the object may be deleted while still
+/// referred to. Objects may be found by what they refer to by comparing
+/// with a `GenericForeignKey` made from the object, as in
+/// `filter!(Comment, target == { GenericForeignKey::from(&post) })`.
+///
+/// See [`GenericForeignKeyOpsSync`] and [`GenericForeignKeyOpsAsync`]
+/// for loading the object referred to. With the `registry` feature, it
+/// may be loaded without knowing its model, which is resolved through
+/// the [`registry`](crate::registry).
+///
+/// # Examples
+/// ```ignore
+/// #[model]
+/// struct Comment {
+///   id: AutoPk<i64>,
+///   target: GenericForeignKey,
+///   text: String,
+/// }
+/// let comment = Comment { target: GenericForeignKey::from(&post), ... };
+/// let post: Post = comment.target.load_as(&conn)?;
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "GenericForeignKeyParts", into = "GenericForeignKeyParts")]
+pub struct GenericForeignKey {
+    model: String,
+    pk: SqlVal,
+    /// The stored representation of `model` and `pk`.
+    encoded: String,
+}
+
+/// The serialized form of a [`GenericForeignKey`], also stored in its column as JSON.
+#[derive(Serialize, Deserialize)]
+struct GenericForeignKeyParts {
+    model: String,
+    pk: SqlVal,
+}
+
+impl From<GenericForeignKeyParts> for GenericForeignKey {
+    fn from(parts: GenericForeignKeyParts) -> Self {
+        GenericForeignKey::new(parts.model, parts.pk)
+    }
+}
+
+impl From<GenericForeignKey> for GenericForeignKeyParts {
+    fn from(gfk: GenericForeignKey) -> Self {
+        GenericForeignKeyParts {
+            model: gfk.model,
+            pk: gfk.pk,
+        }
+    }
+}
+
+impl GenericForeignKey {
+    /// Refers to the object of the model with table `model` whose
+    /// primary key is `pk`.
+    pub fn new(model: impl Into<String>, pk: SqlVal) -> Self {
+        let model = model.into();
+        let parts = serde_json::json!({ "model": model, "pk": pk });
+        GenericForeignKey {
+            encoded: parts.to_string(),
+            model,
+            pk,
+        }
+    }
+
+    /// Refers to the object of `T` whose primary key is `pk`.
+    pub fn from_pk<T: DataObject>(pk: T::PKType) -> Self {
+        Self::new(T::TABLE, pk.into_sql())
+    }
+
+    /// The table of the model referred to.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The primary key of the object referred to.
+    pub fn pk(&self) -> &SqlVal {
+        &self.pk
+    }
+
+    /// Whether the object referred to is of the model `T`.
+    pub fn is<T: DataObject>(&self) -> bool {
+        self.model == T::TABLE
+    }
+
+    /// The primary key of the object referred to, which must be of the
+    /// model `T`, or [`Error::WrongModel`] is returned.
+    pub fn pk_as<T: DataObject>(&self) -> Result<T::PKType> {
+        if !self.is::<T>() {
+            return Err(Error::WrongModel {
+                expected: T::TABLE,
+                found: self.model.clone(),
+            });
+        }
+        T::PKType::from_sql_ref(self.pk.as_ref())
     }
 }
+
+impl<T: DataObject> From<&T> for GenericForeignKey {
+    fn from(obj: &T) -> Self {
+        Self::from_pk::<T>(obj.pk().clone())
+    }
+}
+
+/// [`GenericForeignKey`] operations which require a `Connection`.
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"),),
+    sync(),
+    async(feature = "async")
+)]
+pub trait GenericForeignKeyOps {
+    /// Loads the object referred to, which must be of the model `T`,
+    /// or [`Error::WrongModel`] is returned.
+    async fn load_as<T: DataObject>(&self, conn: &impl ConnectionMethods) -> Result<T>;
+
+    /// Loads the object referred to, whose model is found in the
+    /// [`registry`](crate::registry) by its table. Returns
+    /// [`Error::NoSuchObject`] if the model is not registered or the
+    /// object does not exist.
+    #[cfg(feature = "registry")]
+    async fn load(&self, conn: &impl ConnectionMethods) -> Result<Box<dyn crate::DynDataObject>>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        DataObjectOps,
+        GenericForeignKeyOps,
+        ModelOps
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+impl GenericForeignKeyOps for GenericForeignKey {
+    async fn load_as<T: DataObject>(&self, conn: &impl ConnectionMethods) -> Result<T> {
+        use crate::DataObjectOps;
+        T::get(conn, self.pk_as::<T>()?).await
+    }
+
+    #[cfg(feature = "registry")]
+    async fn load(&self, conn: &impl ConnectionMethods) -> Result<Box<dyn crate::DynDataObject>> {
+        use crate::registry::ModelOps;
+        let model = crate::registry::model(&self.model).ok_or(Error::NoSuchObject)?;
+        model
+            .get(conn, self.pk.clone())
+            .await?
+            .ok_or(Error::NoSuchObject)
+    }
+}
+
+impl ToSql for GenericForeignKey {
+    fn to_sql(&self) -> SqlVal {
+        SqlVal::Text(self.encoded.clone())
+    }
+    fn to_sql_ref(&self) -> SqlValRef<'_> {
+        SqlValRef::Text(&self.encoded)
+    }
+    fn into_sql(self) -> SqlVal {
+        SqlVal::Text(self.encoded)
+    }
+}
+
+impl FromSql for GenericForeignKey {
+    fn from_sql_ref(valref: SqlValRef) -> Result<Self> {
+        match valref {
+            SqlValRef::Text(text) => {
+                let parts: GenericForeignKeyParts = serde_json::from_str(text)
+                    .map_err(|_| Error::CannotConvertSqlVal(SqlType::Text, valref.into()))?;
+                Ok(parts.into())
+            }
+            _ => Err(Error::CannotConvertSqlVal(SqlType::Text, valref.into())),
+        }
+    }
+}
+
+impl FieldType for GenericForeignKey {
+    const SQLTYPE: SqlType = SqlType::Text;
+    type RefType = Self;
+}
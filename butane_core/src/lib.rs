@@ -9,26 +9,51 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ThisError;
 
+pub mod audit;
+pub mod batch;
+#[cfg(feature = "async")]
+pub mod blob;
+#[cfg(feature = "registry")]
+pub mod cascade;
 pub mod codegen;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod counter;
 pub mod custom;
 pub mod db;
+pub mod deadline;
+pub mod encryption;
 pub mod fkey;
 pub mod many;
 pub mod migrations;
+pub mod notify;
+pub mod partition;
+pub mod pkgen;
 pub mod query;
+#[cfg(feature = "registry")]
+pub mod registry;
+pub mod schema;
+#[cfg(feature = "fake")]
+pub mod seed;
 pub mod sqlval;
+pub mod subtype;
+pub mod through;
+pub mod validate;
 
 #[cfg(feature = "uuid")]
 pub mod uuid;
 
 mod autopk;
+mod stringlist;
 mod util;
 
 pub use autopk::AutoPk;
 use custom::SqlTypeCustom;
 use db::{BackendRow, Column, ConnectionMethods};
+use encryption::KeyProvider;
 pub use query::Query;
 pub use sqlval::{AsPrimaryKey, FieldType, FromSql, PrimaryKeyType, SqlVal, SqlValRef, ToSql};
+pub use stringlist::StringList;
 
 #[cfg(feature = "async")]
 use db::ConnectionMethodsAsync;
@@ -55,6 +80,20 @@ fn from_row<'a>(row: &(dyn BackendRow + 'a)) -> Result<Self>
     where
         Self: Sized;
 
+    /// Like [`from_row`](Self::from_row), but decrypting any
+    /// [`Encrypted`](encryption::Encrypted) fields with `keys`, the key
+    /// provider of the connection the row was loaded through.
+    fn from_row_decrypting<'a>(
+        row: &(dyn BackendRow + 'a),
+        keys: Option<&dyn KeyProvider>,
+    ) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let _ = keys;
+        Self::from_row(row)
+    }
+
     /// Create a blank query (matching all rows) for this type.
     fn query() -> Query<Self>;
 }
@@ -69,12 +108,59 @@ pub mod internal {
     /// WARNING: Semver exempt
     #[allow(async_fn_in_trait)] // Not really a public trait
     pub trait DataObjectInternal: DataResult<DBO = Self> {
-        /// Like [DataResult::COLUMNS] but omits [AutoPk].
+        /// Like [DataResult::COLUMNS] but omits [AutoPk] and readonly columns.
         const NON_AUTO_COLUMNS: &'static [Column];
 
+        /// Columns read back from the database after a save, as they may
+        /// have been generated or changed by it. These are marked with
+        /// `#[readonly]` if not written on save, or with `#[refresh]`.
+        const REFRESHED_COLUMNS: &'static [Column] = &[];
+
+        /// Tables of the many-to-many relationships owned by this model.
+        const MANY_TABLES: &'static [&'static str] = &[];
+
+        /// Channel notified when an object is saved or deleted, set with
+        /// `#[butane(notify = "...")]`. See [`crate::notify`].
+        const NOTIFY_CHANNEL: Option<&'static str> = None;
+
+        /// History table of a model declared with `#[butane(audited)]`.
+        /// See [`crate::audit`].
+        const AUDIT_TABLE: Option<&'static str> = None;
+
+        /// Whether the model is declared with `#[butane(immutable)]`, so
+        /// that its objects may be inserted but not updated.
+        const IMMUTABLE: bool = false;
+
+        /// The discriminator column and its value for the objects of a
+        /// model declared with `#[butane(subtype_of = ...)]`, which shares
+        /// its table with other models. See [`crate::subtype`].
+        const DISCRIMINATOR: Option<(&'static str, &'static str)> = None;
+
+        /// Counters of the objects referring to others, kept by fields
+        /// declared with `#[butane(counter_cache = "...")]`. See
+        /// [`crate::counter`].
+        const COUNTER_CACHES: &'static [crate::counter::CounterCache] = &[];
+
         /// Get the primary key as mutable. Used internally in the case of [AutoPk].
         fn pk_mut(&mut self) -> &mut impl PrimaryKeyType;
 
+        /// Generates the primary key before a save if it has not been set,
+        /// for primary keys generated by the application such as UUIDs.
+        fn generate_pk(&mut self) {}
+
+        /// Checks the values of fields declared with validation
+        /// attributes before a save. See [`crate::validate`].
+        fn validate(&self) -> Result<()> {
+            Ok(())
+        }
+
+        /// Encrypts the values of any [`Encrypted`](crate::encryption::Encrypted)
+        /// fields with `keys`, the key provider of the connection, before a
+        /// save. See [`crate::encryption`].
+        fn encrypt_fields(&mut self, _keys: Option<&dyn KeyProvider>) -> Result<()> {
+            Ok(())
+        }
+
         /// Saves many-to-many relationships pointed to by fields on this model.
         /// Performed automatically by `save`. You do not need to call this directly.
         #[cfg(feature = "async")]
@@ -87,9 +173,47 @@ async fn save_many_to_many_async(
         /// Performed automatically by `save`. You do not need to call this directly.
         fn save_many_to_many_sync(&mut self, conn: &impl ConnectionMethods) -> Result<()>;
 
+        /// Loads the values of the relationship identified by `name`, as
+        /// given by [`Related::name`](query::Related::name). Used by `get_with`.
+        #[cfg(feature = "async")]
+        async fn load_related_async(
+            &self,
+            _conn: &impl ConnectionMethodsAsync,
+            name: &str,
+        ) -> Result<()> {
+            Err(Error::UnknownRelation(name.to_string()))
+        }
+
+        /// Loads the values of the relationship identified by `name`, as
+        /// given by [`Related::name`](query::Related::name). Used by `get_with`.
+        fn load_related_sync(&self, _conn: &impl ConnectionMethods, name: &str) -> Result<()> {
+            Err(Error::UnknownRelation(name.to_string()))
+        }
+
         /// Returns the Sql values of all columns except not any auto columns.
         /// Used internally. You are unlikely to need to call this directly.
         fn non_auto_values(&self, include_pk: bool) -> Vec<SqlValRef<'_>>;
+
+        /// Sets the fields of [Self::REFRESHED_COLUMNS] from values read
+        /// back from the database after a save.
+        fn set_refreshed_values(&mut self, _values: Vec<SqlVal>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Loads a [`DataResult`] from the values of all its columns,
+    /// decrypting any encrypted fields with `keys`.
+    pub fn from_values<T: DataResult>(
+        values: Vec<SqlVal>,
+        keys: Option<&dyn KeyProvider>,
+    ) -> Result<T> {
+        T::from_row_decrypting(&db::VecRow::from_values(values), keys)
+    }
+
+    /// Generates a time-ordered UUID for a primary key with `#[butane(auto_uuid = "v7")]`.
+    #[cfg(feature = "uuid")]
+    pub fn new_uuid_v7() -> ::uuid::Uuid {
+        ::uuid::Uuid::now_v7()
     }
 }
 
@@ -115,12 +239,105 @@ pub trait DataObject: DataResult<DBO = Self> + internal::DataObjectInternal + Sy
     fn pk(&self) -> &Self::PKType;
 }
 
+/// A [`DataObject`] which may be saved and deleted. Implemented by
+/// every model except those backed by a view.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is backed by a view, so cannot be saved or deleted"
+)]
+pub trait WritableDataObject: DataObject {}
+
+/// An object-safe subset of [`DataObject`], implemented for every model.
+///
+/// Allows generic tooling, such as admin or export code, to operate on
+/// heterogeneous collections such as `Vec<Box<dyn DynDataObject>>`
+/// without knowing the concrete model types.
+pub trait DynDataObject {
+    /// The name of the table.
+    fn table(&self) -> &'static str;
+
+    /// Metadata for each column, in the same order as [`to_values`](Self::to_values).
+    fn columns(&self) -> &'static [Column];
+
+    /// The primary key.
+    fn pk_value(&self) -> SqlVal;
+
+    /// The values of all columns, in the same order as [`columns`](Self::columns).
+    fn to_values(&self) -> Vec<SqlVal>;
+
+    /// Replaces this object with one loaded from the values of all columns.
+    fn set_values(&mut self, values: Vec<SqlVal>) -> Result<()>;
+
+    /// Creates an object from the values of all columns, in the same
+    /// order as [`columns`](Self::columns).
+    fn from_values(values: Vec<SqlVal>) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+/// An object-safe counterpart of the [`DataObject`] operations which
+/// change the database, implemented for every model which may be saved.
+///
+/// With the methods of [`DynDataObject`], such as the table name and the
+/// primary key, it allows generic tooling to save and delete objects of
+/// heterogeneous collections such as `Vec<Box<dyn AnyDataObject>>`
+/// through a `&dyn ConnectionMethods`.
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(keep_self),
+    async(feature = "async")
+)]
+#[async_trait::async_trait(?Send)]
+pub trait AnyDataObject: DynDataObject {
+    /// Saves the object, as by `save`.
+    async fn save_dyn(&mut self, conn: &dyn ConnectionMethods) -> Result<()>;
+
+    /// Deletes the object, as by `delete`.
+    async fn delete_dyn(&self, conn: &dyn ConnectionMethods) -> Result<()>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        AnyDataObject(sync = "AnyDataObject"),
+        ConnectionMethods(sync = "ConnectionMethods"),
+        DataObjectOps,
+        DynConnection(sync = "DynConnection")
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+#[async_trait::async_trait(?Send)]
+impl<T: WritableDataObject + DynDataObject> AnyDataObject for T {
+    async fn save_dyn(&mut self, conn: &dyn ConnectionMethods) -> Result<()> {
+        DataObjectOps::save(self, &db::DynConnection(conn)).await
+    }
+
+    async fn delete_dyn(&self, conn: &dyn ConnectionMethods) -> Result<()> {
+        DataObjectOps::delete(self, &db::DynConnection(conn)).await
+    }
+}
+
 /// [`DataObject`] operations that require a live database connection.
 #[allow(async_fn_in_trait)] // Implementation is intended to be through procmacro
 #[maybe_async_cfg::maybe(
     idents(
         ConnectionMethods(sync = "ConnectionMethods"),
         save_many_to_many(snake),
+        save_with(snake),
+        load_related(snake),
+        recorded_values(snake),
+        record_change(snake),
+        adjust(snake),
+        begin_change(snake),
+        end_change(snake),
+        delete_where_counted(snake),
+        delete_many_counted(snake),
+        insert_ignoring_conflict(snake),
+        load_history(snake),
+        load_as_of(snake),
+        load_all_as_of(snake),
+        delete_all(snake),
+        plan(snake),
         QueryOps,
     ),
     sync(),
@@ -156,78 +373,287 @@ async fn try_get(conn: &impl ConnectionMethods, id: impl ToSql) -> Result<Option
             .nth(0))
     }
 
+    /// Find this object in the database based on primary key, along
+    /// with the values of the relationships chosen by `related` from its
+    /// fields, usually with the `related!` macro. For example
+    /// `Post::get_with(conn, pk, related!(blog, tags))` loads the post,
+    /// its blog and its tags, so that `post.blog.get()` and
+    /// `post.tags.get()` succeed. One query is made for the object and
+    /// one for each relationship.
+    /// Returns `Error::NoSuchObject` if the primary key does not exist.
+    async fn get_with<R>(
+        conn: &impl ConnectionMethods,
+        id: impl ToSql,
+        related: impl FnOnce(Self::Fields) -> R,
+    ) -> Result<Self>
+    where
+        Self: DataObject + Sized,
+        Self::PKType: Sync,
+        R: IntoIterator<Item = query::Related<Self>>,
+    {
+        let obj = Self::get(conn, id).await?;
+        for relation in related(Self::Fields::default()) {
+            Self::load_related(&obj, conn, relation.name()).await?;
+        }
+        Ok(obj)
+    }
+
     /// Save the object to the database, handling both inserts and updates.
     ///
     /// If the object has an AutoPk that is uninitialized, save will always
     /// perform an insert. If the AutoPk is initialized or there is no AutoPk,
-    /// save will perform an upsert (insert or replace).
+    /// save will perform an upsert (insert or replace). A primary key with
+    /// `#[butane(auto_uuid = "v7")]` is generated first if it is nil, as is one with
+    /// `#[butane(pk_generator = "...")]` if it has its default value. See [`pkgen`].
+    /// Fields with validation attributes are checked first, see [`validate`].
+    /// The primary key and any `#[readonly]` or `#[refresh]` fields are then refreshed with
+    /// the values in the database, which may have been generated by it.
     /// After saving the main object, many-to-many relationships it holds are also saved.
+    ///
+    /// Objects of a model declared with `#[butane(immutable)]` are always inserted, and
+    /// saving one whose AutoPk is initialized, or whose primary key is that of an existing
+    /// object, fails with `Error::Immutable`.
     async fn save(&mut self, conn: &impl ConnectionMethods) -> Result<()>
     where
-        Self: DataObject,
+        Self: WritableDataObject,
     {
-        let pkcol = Column::new(Self::PKCOL, <Self::PKType as FieldType>::SQLTYPE);
+        save_with(self, conn, &[]).await?;
+        Ok(())
+    }
 
-        if Self::AUTO_PK && <Self as DataResult>::COLUMNS.len() == 1 {
-            // Our only field is an AutoPk
-            if !self.pk().is_valid() {
-                let pk = conn
-                    .insert_returning_pk(Self::TABLE, &[], &pkcol, &[])
-                    .await?;
-                self.pk_mut().initialize(pk)?;
-            }
-        } else if Self::AUTO_PK {
-            // We have an AutoPk, but we also have other fields
-            // Since we expect our pk field to be invalid and to be created by the insert,
-            // we do a pure insert or update based on whether the AutoPk is already valid or not.
-            // Note that some database backends do support upsert with auto-incrementing primary
-            // keys, but butane isn't well set up to take advantage of that, including missing
-            // support for constraints and the `insert_or_update` method not providing a way to
-            // retrieve the pk.
-            if self.pk().is_valid() {
-                // pk is valid, do an update
-                conn.update(
-                    Self::TABLE,
-                    pkcol,
-                    self.pk().to_sql_ref(),
-                    Self::NON_AUTO_COLUMNS,
-                    &self.non_auto_values(false),
-                )
+    /// Save the object as by `save`, then return the row stored in the
+    /// database as a new object, with any values changed by triggers or
+    /// defaults of the database beyond those of `#[readonly]` or
+    /// `#[refresh]` fields. The row is read by the statement inserting
+    /// it where the backend allows, such as with `RETURNING` on
+    /// PostgreSQL, and with a separate query otherwise.
+    async fn save_returning(&mut self, conn: &impl ConnectionMethods) -> Result<Self>
+    where
+        Self: WritableDataObject + Sized,
+    {
+        let values = save_with(self, conn, Self::COLUMNS).await?;
+        internal::from_values(values, conn.key_provider())
+    }
+
+    /// Insert the object into the database unless that conflicts with an
+    /// existing row, such as on the primary key or a unique field.
+    ///
+    /// Returns whether the object was inserted. If it was, the primary
+    /// key and any `#[readonly]` or `#[refresh]` fields are refreshed and
+    /// its many-to-many relationships are saved, as by `save`. Otherwise
+    /// neither the object nor the database is changed. An object whose
+    /// AutoPk is initialized has already been inserted, so is not
+    /// inserted again.
+    async fn save_ignore_conflict(&mut self, conn: &impl ConnectionMethods) -> Result<bool>
+    where
+        Self: WritableDataObject,
+    {
+        if Self::AUTO_PK && self.pk().is_valid() {
+            return Ok(false);
+        }
+        self.generate_pk();
+        self.validate()?;
+        self.encrypt_fields(conn.key_provider())?;
+        db::note_sensitive_columns(Self::TABLE, Self::COLUMNS);
+        let atomic = counter::begin_change::<Self>(conn).await?;
+        let result = insert_ignoring_conflict(self, conn).await;
+        if !counter::end_change(atomic, result).await? {
+            return Ok(false);
+        }
+        if T::AUDIT_TABLE.is_some() {
+            audit::record_change::<Self>(conn, audit::AuditOp::Insert, self.pk().to_sql(), None)
                 .await?;
-            } else {
-                // invalid pk, do an insert
-                let pk = conn
-                    .insert_returning_pk(
-                        Self::TABLE,
-                        Self::NON_AUTO_COLUMNS,
-                        &pkcol,
-                        &self.non_auto_values(true),
-                    )
-                    .await?;
-                self.pk_mut().initialize(pk)?;
-            };
-        } else {
-            // No AutoPk to worry about, do an upsert
-            conn.insert_or_replace(
-                Self::TABLE,
-                Self::COLUMNS,
-                &pkcol,
-                &self.non_auto_values(true),
+        }
+        if let Some(channel) = T::NOTIFY_CHANNEL {
+            let payload =
+                notify::ChangePayload::new(T::TABLE, notify::ChangeOp::Save, &self.pk().to_sql());
+            conn.notify(channel, &payload.to_json()).await?;
+        }
+        Ok(true)
+    }
+
+    /// Delete the object from the database.
+    ///
+    /// The rows of many-to-many relationships it owns are deleted too.
+    async fn delete(&self, conn: &impl ConnectionMethods) -> Result<()>
+    where
+        Self: WritableDataObject,
+    {
+        let old_values = match T::AUDIT_TABLE {
+            Some(_) => audit::recorded_values::<Self>(conn, self.pk().to_sql()).await?,
+            None => None,
+        };
+        for table in T::MANY_TABLES {
+            let owner = query::BoolExpr::Eq("owner", query::Expr::Val(self.pk().to_sql()));
+            conn.delete_where(table, owner).await?;
+        }
+        let pk = query::BoolExpr::Eq(T::PKCOL, query::Expr::Val(self.pk().to_sql()));
+        counter::delete_where_counted::<Self>(conn, T::TABLE, pk).await?;
+        if old_values.is_some() {
+            audit::record_change::<Self>(
+                conn,
+                audit::AuditOp::Delete,
+                self.pk().to_sql(),
+                old_values,
             )
             .await?;
         }
+        if let Some(channel) = T::NOTIFY_CHANNEL {
+            let payload =
+                notify::ChangePayload::new(T::TABLE, notify::ChangeOp::Delete, &self.pk().to_sql());
+            conn.notify(channel, &payload.to_json()).await?;
+        }
+        Ok(())
+    }
 
-        Self::save_many_to_many(self, conn).await?;
+    /// Delete the objects with the primary keys `ids`, returning the
+    /// number of objects deleted. Primary keys of objects which do not
+    /// exist are ignored.
+    ///
+    /// The objects, and the rows of many-to-many relationships they own,
+    /// are deleted with a statement for each table rather than one for
+    /// each object, unless there are too many primary keys for one.
+    async fn delete_many(conn: &impl ConnectionMethods, ids: &[impl ToSql]) -> Result<usize>
+    where
+        Self: WritableDataObject + Sized,
+    {
+        let pks: Vec<SqlVal> = ids.iter().map(ToSql::to_sql).collect();
+        let mut old_values = Vec::new();
+        if T::AUDIT_TABLE.is_some() {
+            for pk in &pks {
+                old_values.push(audit::recorded_values::<Self>(conn, pk.clone()).await?);
+            }
+        }
+        for table in T::MANY_TABLES {
+            conn.delete_many(table, "owner", &pks).await?;
+        }
+        let deleted = counter::delete_many_counted::<Self>(conn, &pks).await?;
+        for (pk, old_values) in pks.iter().zip(old_values) {
+            if old_values.is_some() {
+                audit::record_change::<Self>(conn, audit::AuditOp::Delete, pk.clone(), old_values)
+                    .await?;
+            }
+        }
+        if let Some(channel) = T::NOTIFY_CHANNEL {
+            for pk in &pks {
+                let payload = notify::ChangePayload::new(T::TABLE, notify::ChangeOp::Delete, pk);
+                conn.notify(channel, &payload.to_json()).await?;
+            }
+        }
+        Ok(deleted)
+    }
 
-        Ok(())
+    /// Delete the object from the database along with everything which
+    /// depends on it, found through the relationships of the models in
+    /// the [`registry`]: objects with a `ForeignKey` to it, recursively,
+    /// and the rows of many-to-many relationships owned by or referring
+    /// to any of them. Returns what was deleted. See [`cascade`].
+    ///
+    /// Dependents are deleted before the objects they refer to, with a
+    /// statement for each table and depth. The deletes are made within a
+    /// transaction, or within a savepoint if `conn` is already a
+    /// [`Transaction`](db::Transaction), so are rolled back together if
+    /// one of them fails.
+    /// Rows are deleted directly, so histories of audited models are not
+    /// recorded and no notifications are sent.
+    #[cfg(feature = "registry")]
+    async fn delete_cascade(&self, conn: &impl ConnectionMethods) -> Result<cascade::CascadeReport>
+    where
+        Self: WritableDataObject,
+    {
+        cascade::delete_all::<T>(conn, self.pk().to_sql()).await
     }
 
-    /// Delete the object from the database.
-    async fn delete(&self, conn: &impl ConnectionMethods) -> Result<()>
+    /// Reports what [`delete_cascade`](Self::delete_cascade) would
+    /// delete, without deleting anything.
+    #[cfg(feature = "registry")]
+    async fn delete_cascade_dry_run(
+        &self,
+        conn: &impl ConnectionMethods,
+    ) -> Result<cascade::CascadeReport>
     where
         Self: DataObject,
     {
-        conn.delete(T::TABLE, T::PKCOL, self.pk().to_sql()).await
+        cascade::plan::<T>(conn, self.pk().to_sql()).await
+    }
+
+    /// Loads the history of changes to the object with primary key `id`,
+    /// oldest first, which remains after the object is deleted.
+    /// Returns `Error::NotAudited` unless the model is declared with
+    /// `#[butane(audited)]`. See [`audit`].
+    async fn history(
+        conn: &impl ConnectionMethods,
+        id: impl ToSql,
+    ) -> Result<Vec<audit::AuditEntry>>
+    where
+        Self: DataObject + Sized,
+    {
+        audit::load_history::<Self>(conn, id.borrow().to_sql()).await
+    }
+
+    /// Reconstructs the object with primary key `id` as it was at `at`
+    /// from its history. Returns `None` if it did not exist at that time,
+    /// or `Error::NotAudited` unless the model is declared with
+    /// `#[butane(audited)]`. See [`audit`].
+    async fn as_of(
+        conn: &impl ConnectionMethods,
+        id: impl ToSql,
+        at: std::time::SystemTime,
+    ) -> Result<Option<Self>>
+    where
+        Self: DataObject + Sized,
+    {
+        audit::load_as_of::<Self>(conn, id.borrow().to_sql(), at).await
+    }
+
+    /// Reconstructs every object which existed at `at` from the history
+    /// of the model, in the order they were first recorded. Returns
+    /// `Error::NotAudited` unless the model is declared with
+    /// `#[butane(audited)]`. See [`audit`].
+    async fn all_as_of(
+        conn: &impl ConnectionMethods,
+        at: std::time::SystemTime,
+    ) -> Result<Vec<Self>>
+    where
+        Self: DataObject + Sized,
+    {
+        audit::load_all_as_of::<Self>(conn, at).await
+    }
+
+    /// Opens the blob held by `field` of this object, which must have
+    /// been saved, for streaming reads and writes. See [`crate::blob`].
+    #[maybe_async_cfg::only_if(key = "async")]
+    async fn open_blob<'c, C, B>(
+        &self,
+        conn: &'c C,
+        field: query::FieldExpr<B>,
+    ) -> Result<blob::BlobStream<'c, C>>
+    where
+        Self: DataObject + Sized,
+        C: ConnectionMethods + ?Sized,
+        B: blob::BlobField,
+    {
+        blob::BlobStream::open(conn, blob_ref(self, field.name(), B::LARGE_OBJECT)?).await
+    }
+
+    /// Replaces the blob held by `field` of this object, which must have
+    /// been saved, with `len` zero bytes and opens it to be written. A
+    /// [`LargeObject`](blob::LargeObject) field must already refer to a
+    /// large object, whose contents are replaced. See [`crate::blob`].
+    #[maybe_async_cfg::only_if(key = "async")]
+    async fn create_blob<'c, C, B>(
+        &self,
+        conn: &'c C,
+        field: query::FieldExpr<B>,
+        len: u64,
+    ) -> Result<blob::BlobStream<'c, C>>
+    where
+        Self: DataObject + Sized,
+        C: ConnectionMethods + ?Sized,
+        B: blob::BlobField,
+    {
+        let blob = blob_ref(self, field.name(), B::LARGE_OBJECT)?;
+        blob::BlobStream::create(conn, blob, len).await
     }
 }
 
@@ -235,9 +661,239 @@ impl<T> DataObjectOpsSync<T> for T where T: DataObject {}
 #[cfg(feature = "async")]
 impl<T> DataObjectOpsAsync<T> for T where T: DataObject {}
 
+/// Saves `obj` as by `DataObjectOps::save`, also reading back the
+/// values of the `extra` columns as stored, which are returned.
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        save_many_to_many(snake),
+        recorded_values(snake),
+        record_change(snake),
+        begin_change(snake),
+        end_change(snake),
+        save_row(snake),
+    ),
+    sync(),
+    async(feature = "async")
+)]
+async fn save_with<T: WritableDataObject>(
+    obj: &mut T,
+    conn: &impl ConnectionMethods,
+    extra: &[Column],
+) -> Result<Vec<SqlVal>> {
+    obj.generate_pk();
+    obj.validate()?;
+    obj.encrypt_fields(conn.key_provider())?;
+    db::note_sensitive_columns(T::TABLE, T::COLUMNS);
+    let pkcol = Column::new(T::PKCOL, <T::PKType as FieldType>::SQLTYPE);
+    let returning: std::borrow::Cow<'_, [Column]> = if extra.is_empty() {
+        T::REFRESHED_COLUMNS.into()
+    } else {
+        [T::REFRESHED_COLUMNS, extra].concat().into()
+    };
+    let inserting = T::AUTO_PK && !obj.pk().is_valid();
+    if T::IMMUTABLE && T::AUTO_PK && !inserting {
+        return Err(Error::Immutable(T::TABLE));
+    }
+    // Objects of an immutable model are only ever inserted, so that
+    // saving one with an existing primary key fails rather than updates,
+    // as checked by save_row
+    let inserting = inserting || T::IMMUTABLE;
+    let old_values = if T::AUDIT_TABLE.is_some() && !inserting {
+        audit::recorded_values::<T>(conn, obj.pk().to_sql()).await?
+    } else {
+        None
+    };
+    let atomic = counter::begin_change::<T>(conn).await?;
+    let result = save_row(obj, conn, &pkcol, &returning, inserting).await;
+    let extra_values = counter::end_change(atomic, result).await?;
+
+    if T::AUDIT_TABLE.is_some() {
+        let op = match old_values {
+            Some(_) => audit::AuditOp::Update,
+            None => audit::AuditOp::Insert,
+        };
+        audit::record_change::<T>(conn, op, obj.pk().to_sql(), old_values).await?;
+    }
+    if let Some(channel) = T::NOTIFY_CHANNEL {
+        let payload =
+            notify::ChangePayload::new(T::TABLE, notify::ChangeOp::Save, &obj.pk().to_sql());
+        conn.notify(channel, &payload.to_json()).await?;
+    }
+    Ok(extra_values)
+}
+
+/// Inserts or updates the row of `obj` for `save_with`, along with its
+/// many-to-many relationships, and adjusts the counters it affects.
+/// Returns the values of the columns of `returning` following its
+/// refreshed columns.
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        save_many_to_many(snake),
+        counted(snake),
+        adjust(snake),
+    ),
+    sync(),
+    async(feature = "async")
+)]
+async fn save_row<T: WritableDataObject>(
+    obj: &mut T,
+    conn: &impl ConnectionMethods,
+    pkcol: &Column,
+    returning: &[Column],
+    inserting: bool,
+) -> Result<Vec<SqlVal>> {
+    let before = if inserting {
+        Vec::new()
+    } else {
+        let pk = query::BoolExpr::Eq(T::PKCOL, query::Expr::Val(obj.pk().to_sql()));
+        counter::counted::<T>(conn, pk).await?
+    };
+
+    let extra_values = if inserting {
+        // Since we expect our pk field to be invalid and to be created by the insert,
+        // we do a pure insert or update based on whether the AutoPk is already valid or not.
+        // Note that some database backends do support upsert with auto-incrementing primary
+        // keys, but butane isn't well set up to take advantage of that, including missing
+        // support for constraints and the `insert_or_update` method not providing a way to
+        // retrieve the pk.
+        let values = obj.non_auto_values(true);
+        // Saving an object of an immutable model again conflicts with its
+        // own row, which is reported as such rather than by the database
+        let inserted = if T::IMMUTABLE && !T::AUTO_PK {
+            conn.insert_or_ignore(T::TABLE, T::NON_AUTO_COLUMNS, pkcol, returning, &values)
+                .await?
+        } else {
+            None
+        };
+        let mut returned = match inserted {
+            Some(returned) => returned,
+            None => {
+                if T::IMMUTABLE && !T::AUTO_PK {
+                    let existing = conn
+                        .query_by_pk(
+                            T::TABLE,
+                            pkcol,
+                            obj.pk().to_sql(),
+                            std::slice::from_ref(pkcol),
+                        )
+                        .await;
+                    if existing.is_ok() {
+                        return Err(Error::Immutable(T::TABLE));
+                    }
+                }
+                // Any other conflict is reported by inserting again
+                conn.insert_returning(T::TABLE, T::NON_AUTO_COLUMNS, pkcol, returning, &values)
+                    .await?
+            }
+        };
+        obj.pk_mut().initialize(returned.remove(0))?;
+        let extra_values = returned.split_off(T::REFRESHED_COLUMNS.len());
+        obj.set_refreshed_values(returned)?;
+        extra_values
+    } else {
+        if T::AUTO_PK {
+            // pk is valid, do an update unless there is nothing to write
+            if !T::NON_AUTO_COLUMNS.is_empty() {
+                conn.update(
+                    T::TABLE,
+                    pkcol.clone(),
+                    obj.pk().to_sql_ref(),
+                    T::NON_AUTO_COLUMNS,
+                    &obj.non_auto_values(false),
+                )
+                .await?;
+            }
+        } else {
+            // No AutoPk to worry about, do an upsert
+            conn.insert_or_replace(
+                T::TABLE,
+                T::NON_AUTO_COLUMNS,
+                pkcol,
+                &obj.non_auto_values(true),
+            )
+            .await?;
+        }
+        if returning.is_empty() {
+            Vec::new()
+        } else {
+            let mut values = conn
+                .query_by_pk(T::TABLE, pkcol, obj.pk().to_sql(), returning)
+                .await?;
+            let extra_values = values.split_off(T::REFRESHED_COLUMNS.len());
+            obj.set_refreshed_values(values)?;
+            extra_values
+        }
+    };
+
+    T::save_many_to_many(obj, conn).await?;
+    counter::adjust::<T>(conn, before, counter::counted_by(obj)).await?;
+    Ok(extra_values)
+}
+
+/// Inserts `obj` for `save_ignore_conflict` unless that conflicts with
+/// an existing row, along with its many-to-many relationships, and
+/// adjusts the counters it affects. Returns whether it was inserted.
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        save_many_to_many(snake),
+        adjust(snake),
+    ),
+    sync(),
+    async(feature = "async")
+)]
+async fn insert_ignoring_conflict<T: WritableDataObject>(
+    obj: &mut T,
+    conn: &impl ConnectionMethods,
+) -> Result<bool> {
+    let pkcol = Column::new(T::PKCOL, <T::PKType as FieldType>::SQLTYPE);
+    let returned = conn
+        .insert_or_ignore(
+            T::TABLE,
+            T::NON_AUTO_COLUMNS,
+            &pkcol,
+            T::REFRESHED_COLUMNS,
+            &obj.non_auto_values(true),
+        )
+        .await?;
+    let Some(mut returned) = returned else {
+        return Ok(false);
+    };
+    let pk = returned.remove(0);
+    if T::AUTO_PK {
+        obj.pk_mut().initialize(pk)?;
+    }
+    obj.set_refreshed_values(returned)?;
+    T::save_many_to_many(obj, conn).await?;
+    counter::adjust::<T>(conn, Vec::new(), counter::counted_by(obj)).await?;
+    Ok(true)
+}
+
+/// Identifies the blob held by `column` of the saved object `obj`, or
+/// the large object it refers to.
+#[cfg(feature = "async")]
+fn blob_ref<T: DataObject>(
+    obj: &T,
+    column: &'static str,
+    large_object: bool,
+) -> Result<db::BlobRef> {
+    if !obj.pk().is_valid() {
+        return Err(Error::NoSuchObject);
+    }
+    Ok(db::BlobRef {
+        table: T::TABLE,
+        column,
+        pkcol: T::PKCOL,
+        pk: obj.pk().to_sql(),
+        large_object,
+    })
+}
+
 /// Butane errors.
 #[allow(missing_docs)]
-#[derive(Debug, ThisError)]
+#[derive(ThisError)]
 pub enum Error {
     #[error("No such object exists")]
     NoSuchObject,
@@ -267,6 +923,40 @@ pub enum Error {
     UnknownBackend(String),
     #[error("Unknown connect string {0}")]
     UnknownConnectString(String),
+    #[error("No relationship {0} to load")]
+    UnknownRelation(String),
+    #[error("Generic foreign key refers to {found}, not {expected}")]
+    WrongModel {
+        expected: &'static str,
+        found: String,
+    },
+    #[error("Model {0} is not audited")]
+    NotAudited(&'static str),
+    #[error("Model {0} is immutable, its objects cannot be updated")]
+    Immutable(&'static str),
+    #[error("Model {0} is audited, its objects may only be changed by saving them")]
+    Audited(&'static str),
+    #[error("Cannot delete from {table} while rows of {referring} refer to it")]
+    Restricted {
+        table: &'static str,
+        referring: &'static str,
+    },
+    #[error("A statement would bind {count} parameters, more than the {max} allowed")]
+    TooManyParameters { count: usize, max: usize },
+    #[error("Model {0} has no column {1} which may be updated")]
+    UnknownColumn(&'static str, String),
+    #[error("Encryption error {0}")]
+    Encryption(String),
+    #[error("Compression error {0}")]
+    Compression(String),
+    #[error("Invalid value for {field}: {detail}")]
+    Validation { field: &'static str, detail: String },
+    #[error("The tables {} of the database do not match the latest migration, has it been applied?", tables.join(", "))]
+    SchemaMismatch { tables: Vec<String> },
+    #[error("Unknown connection environment {0}")]
+    UnknownEnvironment(String),
+    #[error("Environment variable {0} is not set")]
+    MissingEnvVar(String),
     #[error("Range error")]
     OutOfRange,
     #[error("Internal logic error {0}")]
@@ -291,6 +981,12 @@ pub enum Error {
     PoisonedConnection,
     #[error("Connect connect_async for synchronous backend {0}. To support this, enable the async-adapter feature.")]
     NoAsyncAdapter(&'static str),
+    #[error("Backend {0} does not support {1}")]
+    Unsupported(&'static str, &'static str),
+    #[error("Deadline exceeded")]
+    DeadlineExceeded,
+    #[error("Database connection closed")]
+    ConnectionClosed,
     #[error("(De)serialization error {0}")]
     SerdeJson(#[from] serde_json::Error),
     #[error("IO error {0}")]
@@ -301,9 +997,15 @@ pub enum Error {
     #[cfg(feature = "sqlite")]
     #[error("Sqlite error {0}")]
     SQLiteFromSQL(rusqlite::types::FromSqlError),
+    #[cfg(feature = "sqlite-wasm")]
+    #[error("Sqlite error {0}")]
+    SQLiteWasm(String),
     #[cfg(feature = "pg")]
     #[error("Postgres error {0}")]
     Postgres(#[from] tokio_postgres::Error),
+    #[cfg(feature = "odbc")]
+    #[error("ODBC error {0}")]
+    Odbc(#[from] odbc_api::Error),
     #[cfg(feature = "datetime")]
     #[error("Chrono error {0}")]
     Chrono(#[from] chrono::ParseError),
@@ -329,6 +1031,321 @@ pub enum Error {
     TableNotFound(String),
     #[error("Column \"{0}\".\"{1}\" not found in schema definitions")]
     ColumnNotFound(String, String),
+    /// An error from the database while running a statement generated
+    /// by Butane, with the statement it occurred in.
+    #[error("{} in statement {context}", statement_source(source, context))]
+    Statement {
+        source: Box<Error>,
+        context: Box<StatementContext>,
+    },
+}
+
+// Not derived, so that the detail of a database error which may quote
+// sensitive values is left out of an Error::Statement
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoSuchObject => f.write_str("NoSuchObject"),
+            Error::BoundsError(a) => f.debug_tuple("BoundsError").field(a).finish(),
+            Error::CannotConvertSqlVal(a, b) => f
+                .debug_tuple("CannotConvertSqlVal")
+                .field(a)
+                .field(b)
+                .finish(),
+            Error::SqlResultTypeMismatch { col, detail } => f
+                .debug_struct("SqlResultTypeMismatch")
+                .field("col", col)
+                .field("detail", detail)
+                .finish(),
+            Error::UnknownSqlType(a) => f.debug_tuple("UnknownSqlType").field(a).finish(),
+            Error::ValueNotLoaded => f.write_str("ValueNotLoaded"),
+            Error::ValueNotSaved => f.write_str("ValueNotSaved"),
+            Error::NotInitialized => f.write_str("NotInitialized"),
+            Error::AlreadyInitialized => f.write_str("AlreadyInitialized"),
+            Error::MigrationError(a) => f.debug_tuple("MigrationError").field(a).finish(),
+            Error::UriParse(a) => f.debug_tuple("UriParse").field(a).finish(),
+            Error::UnknownBackend(a) => f.debug_tuple("UnknownBackend").field(a).finish(),
+            Error::UnknownConnectString(a) => {
+                f.debug_tuple("UnknownConnectString").field(a).finish()
+            }
+            Error::UnknownRelation(a) => f.debug_tuple("UnknownRelation").field(a).finish(),
+            Error::WrongModel { expected, found } => f
+                .debug_struct("WrongModel")
+                .field("expected", expected)
+                .field("found", found)
+                .finish(),
+            Error::NotAudited(a) => f.debug_tuple("NotAudited").field(a).finish(),
+            Error::Immutable(a) => f.debug_tuple("Immutable").field(a).finish(),
+            Error::Audited(a) => f.debug_tuple("Audited").field(a).finish(),
+            Error::Restricted { table, referring } => f
+                .debug_struct("Restricted")
+                .field("table", table)
+                .field("referring", referring)
+                .finish(),
+            Error::TooManyParameters { count, max } => f
+                .debug_struct("TooManyParameters")
+                .field("count", count)
+                .field("max", max)
+                .finish(),
+            Error::UnknownColumn(a, b) => f.debug_tuple("UnknownColumn").field(a).field(b).finish(),
+            Error::Encryption(a) => f.debug_tuple("Encryption").field(a).finish(),
+            Error::Compression(a) => f.debug_tuple("Compression").field(a).finish(),
+            Error::Validation { field, detail } => f
+                .debug_struct("Validation")
+                .field("field", field)
+                .field("detail", detail)
+                .finish(),
+            Error::SchemaMismatch { tables } => f
+                .debug_struct("SchemaMismatch")
+                .field("tables", tables)
+                .finish(),
+            Error::UnknownEnvironment(a) => f.debug_tuple("UnknownEnvironment").field(a).finish(),
+            Error::MissingEnvVar(a) => f.debug_tuple("MissingEnvVar").field(a).finish(),
+            Error::OutOfRange => f.write_str("OutOfRange"),
+            Error::Internal(a) => f.debug_tuple("Internal").field(a).finish(),
+            Error::CannotResolveType(a) => f.debug_tuple("CannotResolveType").field(a).finish(),
+            Error::InvalidAuto(a) => f.debug_tuple("InvalidAuto").field(a).finish(),
+            Error::NoCustomDefault => f.write_str("NoCustomDefault"),
+            Error::UnknownEnumVariant(a) => f.debug_tuple("UnknownEnumVariant").field(a).finish(),
+            Error::IncompatibleCustom(a, b) => f
+                .debug_tuple("IncompatibleCustom")
+                .field(a)
+                .field(b)
+                .finish(),
+            Error::IncompatibleCustomT(a, b) => f
+                .debug_tuple("IncompatibleCustomT")
+                .field(a)
+                .field(b)
+                .finish(),
+            Error::LiteralForCustomUnsupported(a) => f
+                .debug_tuple("LiteralForCustomUnsupported")
+                .field(a)
+                .finish(),
+            Error::SaveDeterminationNotSupported => f.write_str("SaveDeterminationNotSupported"),
+            Error::PoisonedConnection => f.write_str("PoisonedConnection"),
+            Error::NoAsyncAdapter(a) => f.debug_tuple("NoAsyncAdapter").field(a).finish(),
+            Error::Unsupported(a, b) => f.debug_tuple("Unsupported").field(a).field(b).finish(),
+            Error::DeadlineExceeded => f.write_str("DeadlineExceeded"),
+            Error::ConnectionClosed => f.write_str("ConnectionClosed"),
+            Error::SerdeJson(a) => f.debug_tuple("SerdeJson").field(a).finish(),
+            Error::IO(a) => f.debug_tuple("IO").field(a).finish(),
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(a) => f.debug_tuple("SQLite").field(a).finish(),
+            #[cfg(feature = "sqlite")]
+            Error::SQLiteFromSQL(a) => f.debug_tuple("SQLiteFromSQL").field(a).finish(),
+            #[cfg(feature = "sqlite-wasm")]
+            Error::SQLiteWasm(a) => f.debug_tuple("SQLiteWasm").field(a).finish(),
+            #[cfg(feature = "pg")]
+            Error::Postgres(a) => f.debug_tuple("Postgres").field(a).finish(),
+            #[cfg(feature = "odbc")]
+            Error::Odbc(a) => f.debug_tuple("Odbc").field(a).finish(),
+            #[cfg(feature = "datetime")]
+            Error::Chrono(a) => f.debug_tuple("Chrono").field(a).finish(),
+            Error::CellBorrow(a) => f.debug_tuple("CellBorrow").field(a).finish(),
+            #[cfg(feature = "tls")]
+            Error::TLS(a) => f.debug_tuple("TLS").field(a).finish(),
+            Error::Generic(a) => f.debug_tuple("Generic").field(a).finish(),
+            #[cfg(feature = "async")]
+            Error::TokioJoin(a) => f.debug_tuple("TokioJoin").field(a).finish(),
+            #[cfg(feature = "async")]
+            Error::TokioRecv(a) => f.debug_tuple("TokioRecv").field(a).finish(),
+            #[cfg(feature = "async-adapter")]
+            Error::CrossbeamChannel => f.write_str("CrossbeamChannel"),
+            Error::IncompatibleSQLite(a, b) => f
+                .debug_tuple("IncompatibleSQLite")
+                .field(a)
+                .field(b)
+                .finish(),
+            Error::TableNotFound(a) => f.debug_tuple("TableNotFound").field(a).finish(),
+            Error::ColumnNotFound(a, b) => {
+                f.debug_tuple("ColumnNotFound").field(a).field(b).finish()
+            }
+            Error::Statement { source, context } => f
+                .debug_struct("Statement")
+                .field("source", &StatementSourceDebug { source, context })
+                .field("context", context)
+                .finish(),
+        }
+    }
+}
+
+impl Error {
+    /// Tests if the error is due to contention with concurrent
+    /// transactions, so that the failed transaction may succeed if run
+    /// again. These are serialization failures and deadlocks on
+    /// Postgres, and the database being busy or locked on SQLite.
+    pub fn is_retryable(&self) -> bool {
+        match self.root() {
+            #[cfg(feature = "pg")]
+            Error::Postgres(e) => {
+                use tokio_postgres::error::SqlState;
+                matches!(
+                    e.code(),
+                    Some(&SqlState::T_R_SERIALIZATION_FAILURE)
+                        | Some(&SqlState::T_R_DEADLOCK_DETECTED)
+                )
+            }
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(rusqlite::Error::SqliteFailure(e, _)) => matches!(
+                e.code,
+                rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+            ),
+            _ => false,
+        }
+    }
+
+    /// The error itself or, for an [`Error::Statement`], the error from
+    /// the database it wraps.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Statement { source, .. } => source.root(),
+            _ => self,
+        }
+    }
+
+    /// The statement the error occurred in, if it is an
+    /// [`Error::Statement`].
+    pub fn statement(&self) -> Option<&StatementContext> {
+        match self {
+            Error::Statement { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Attaches the statement `sql` on `table` with parameters `params`
+    /// to an error from the database, leaving other errors unchanged.
+    #[cfg_attr(
+        not(any(
+            feature = "sqlite",
+            feature = "sqlite-wasm",
+            feature = "pg",
+            feature = "odbc"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn in_statement<'a>(
+        self,
+        sql: &str,
+        table: &str,
+        params: impl IntoIterator<Item = SqlValRef<'a>>,
+    ) -> Error {
+        if !self.is_from_database() {
+            return self;
+        }
+        let values = db::parameter_values_in_errors();
+        let sensitive = db::has_sensitive_columns(table);
+        let params = params
+            .into_iter()
+            .map(|param| match param {
+                _ if sensitive => db::REDACTED.to_string(),
+                SqlValRef::Null => "NULL".to_string(),
+                param if values => format!("{param:?}"),
+                param => param
+                    .sqltype()
+                    .map_or_else(|| "custom".to_string(), |ty| ty.to_string()),
+            })
+            .collect();
+        Error::Statement {
+            source: Box::new(self),
+            context: Box::new(StatementContext {
+                sql: sql.to_string(),
+                table: table.to_string(),
+                params,
+            }),
+        }
+    }
+
+    fn is_from_database(&self) -> bool {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(_) => true,
+            #[cfg(feature = "sqlite-wasm")]
+            Error::SQLiteWasm(_) => true,
+            #[cfg(feature = "pg")]
+            Error::Postgres(_) => true,
+            #[cfg(feature = "odbc")]
+            Error::Odbc(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The detail of an error from the database in a statement on a table
+/// with sensitive columns, which may quote their values, such as that of
+/// a Postgres unique violation.
+#[cfg_attr(not(feature = "pg"), allow(unused_variables))]
+fn sensitive_detail<'a>(source: &'a Error, context: &StatementContext) -> Option<&'a str> {
+    #[cfg(feature = "pg")]
+    if let Error::Postgres(e) = source {
+        if db::has_sensitive_columns(&context.table) {
+            return e.as_db_error().and_then(|db_error| db_error.detail());
+        }
+    }
+    None
+}
+
+/// Formats the error from the database of an [`Error::Statement`],
+/// with its [`sensitive_detail`] redacted.
+fn statement_source(source: &Error, context: &StatementContext) -> String {
+    let text = source.to_string();
+    match sensitive_detail(source, context) {
+        Some(detail) => text.replace(detail, db::REDACTED),
+        None => text,
+    }
+}
+
+/// Debug formatting of the error from the database of an
+/// [`Error::Statement`], with its [`sensitive_detail`] redacted.
+struct StatementSourceDebug<'a> {
+    source: &'a Error,
+    context: &'a StatementContext,
+}
+
+impl std::fmt::Debug for StatementSourceDebug<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(detail) = sensitive_detail(self.source, self.context) else {
+            return self.source.fmt(f);
+        };
+        let text = if f.alternate() {
+            format!("{:#?}", self.source)
+        } else {
+            format!("{:?}", self.source)
+        };
+        let redacted = text.replace(&format!("{detail:?}"), &format!("{:?}", db::REDACTED));
+        f.write_str(&redacted)
+    }
+}
+
+/// A statement generated by Butane which failed, attached to the error
+/// from the database as an [`Error::Statement`] so that the failure can
+/// be diagnosed without recreating the statement.
+///
+/// Parameters are summarized by their types, or `NULL`, so that the
+/// error may be logged without exposing the data being saved. Their
+/// values are included instead once enabled with
+/// [`db::set_parameter_values_in_errors`], except on tables with
+/// columns marked `#[butane(sensitive)]`, whose parameters are all
+/// [`db::REDACTED`].
+#[derive(Clone, Debug)]
+pub struct StatementContext {
+    /// The SQL of the statement.
+    pub sql: String,
+    /// The table the statement operated on.
+    pub table: String,
+    /// The type or value of each parameter of the statement.
+    pub params: Vec<String>,
+}
+
+impl std::fmt::Display for StatementContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` on table {} with parameters [{}]",
+            self.sql,
+            self.table,
+            self.params.join(", ")
+        )
+    }
 }
 
 #[cfg(feature = "sqlite")]
@@ -384,6 +1401,8 @@ pub enum SqlType {
     Timestamp,
     /// Blob
     Blob,
+    /// List of strings
+    TextList,
     #[cfg(feature = "json")]
     /// JSON
     Json,
@@ -404,6 +1423,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
             #[cfg(feature = "datetime")]
             Timestamp => "timestamp",
             Blob => "blob",
+            TextList => "string list",
             #[cfg(feature = "json")]
             Json => "json",
             Custom(_) => "custom",
@@ -5,25 +5,45 @@
 
 #[cfg(feature = "fake")]
 use fake::{Dummy, Faker};
+use fallible_iterator::FallibleIterator;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "async")]
 use crate::db::ConnectionMethodsAsync;
-use crate::db::{Column, ConnectionMethods};
-use crate::query::{BoolExpr, Expr, OrderDirection, Query};
+use crate::db::{helper, BackendRows, Column, ConnectionMethods};
+use crate::migrations::adb::MANY_POSITION_COLUMN;
+use crate::query::{BoolExpr, Expr, Order, OrderDirection, Query};
 use crate::util::get_or_init_once_lock;
 #[cfg(feature = "async")]
 use crate::util::get_or_init_once_lock_async;
-use crate::{sqlval::PrimaryKeyType, DataObject, Error, FieldType, Result, SqlType, SqlVal, ToSql};
+use crate::{
+    sqlval::PrimaryKeyType, DataObject, Error, FieldType, FromSql, Result, SqlType, SqlVal,
+    SqlValRef, ToSql,
+};
 
 /// Used to implement a many-to-many relationship between models.
 ///
 /// Creates a new table with columns "owner" and "has" If type T has a
 /// many-to-many relationship with U, owner type is T::PKType, has is
 /// U::PKType. Table name is T_foo_Many where foo is the name of
-/// the Many field
+/// the Many field. The table is indexed on "owner" and "has" together,
+/// and on "has" alone.
+///
+/// A value is related at most once: the index on "owner" and "has" is
+/// unique, adding a value which is already related does nothing, and
+/// saving skips join rows which already exist. Declare the field with
+/// `#[butane(allow_duplicates)]` to relate a value more than once
+/// instead. Migrating an existing table to the unique index fails if
+/// it already holds duplicate rows, which must be removed first.
 ///
 /// See [`ManyOpsSync`] and [`ManyOpsAsync`] for operations requiring a live database connection.
+///
+/// Its default serde representation is internal to Butane. For use in
+/// payloads of APIs, see [`pks`] and [`nested`] for representations as
+/// arrays of primary keys or of the values themselves.
+///
+/// Like the values it caches once loaded, it is `Send` and `Sync` if
+/// `T` is.
 //
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Many<T>
@@ -34,6 +54,8 @@ pub struct Many<T>
     owner: Option<SqlVal>,
     owner_type: SqlType,
     #[serde(skip)]
+    allow_duplicates: bool,
+    #[serde(skip)]
     new_values: Vec<SqlVal>,
     #[serde(skip)]
     removed_values: Vec<SqlVal>,
@@ -56,6 +78,7 @@ pub fn new() -> Self {
             item_table: Cow::Borrowed("not_initialized"),
             owner: None,
             owner_type: SqlType::Int,
+            allow_duplicates: false,
             new_values: Vec::new(),
             removed_values: Vec::new(),
             all_values: OnceLock::new(),
@@ -73,7 +96,15 @@ pub fn ensure_init(&mut self, item_table: &'static str, owner: SqlVal, owner_typ
         self.all_values = OnceLock::new();
     }
 
-    /// Adds a value, yet to be performed in the backend.
+    /// Used by macro-generated code for fields declared with
+    /// `#[butane(allow_duplicates)]`. You do not need to call this directly.
+    pub fn allow_duplicates(&mut self) {
+        self.allow_duplicates = true;
+    }
+
+    /// Adds a value, yet to be performed in the backend. Unless
+    /// duplicates are allowed, does nothing if the value has already
+    /// been added, or is among the loaded values.
     ///
     /// After invoking this, `get()` can not be used until `save()` is performed.
     ///
@@ -84,13 +115,61 @@ pub fn add(&mut self, new_val: &T) -> Result<()> {
         if !new_val.pk().is_valid() {
             return Err(Error::ValueNotSaved);
         }
+        let pk = new_val.pk().to_sql();
+        if self.is_added(&pk) {
+            return Ok(());
+        }
 
         // all_values is now out of date, so clear it
         self.all_values = OnceLock::new();
-        self.new_values.push(new_val.pk().to_sql());
+        self.new_values.push(pk);
+        Ok(())
+    }
+
+    /// Adds several values, yet to be performed in the backend, which
+    /// are all inserted by the same statement when saved.
+    ///
+    /// After invoking this, `get()` can not be used until `save()` is performed.
+    ///
+    /// Returns Err(ValueNotSaved), without adding any of them, if any of
+    /// the provided values uses automatic primary keys and appears to
+    /// have an uninitialized one. As with `add()`, values already added
+    /// are skipped.
+    pub fn add_all(&mut self, new_vals: &[&T]) -> Result<()> {
+        if new_vals.iter().any(|val| !val.pk().is_valid()) {
+            return Err(Error::ValueNotSaved);
+        }
+        let mut added = false;
+        for pk in new_vals.iter().map(|val| val.pk().to_sql()) {
+            if !self.is_added(&pk) {
+                self.new_values.push(pk);
+                added = true;
+            }
+        }
+        if added {
+            // all_values is now out of date, so clear it
+            self.all_values = OnceLock::new();
+        }
         Ok(())
     }
 
+    /// Whether the value with primary key `pk` is yet to be added, or
+    /// is among the loaded values and not yet to be removed. Always
+    /// false if duplicates are allowed, or if it is not yet known
+    /// whether they are because the Many is not initialised, in which
+    /// case `save()` skips the repeated values instead.
+    fn is_added(&self, pk: &SqlVal) -> bool {
+        if self.allow_duplicates || self.owner.is_none() {
+            return false;
+        }
+        self.new_values.contains(pk)
+            || (!self.removed_values.contains(pk)
+                && self
+                    .all_values
+                    .get()
+                    .is_some_and(|vals| vals.iter().any(|val| val.pk().to_sql() == *pk)))
+    }
+
     /// Removes a value, yet to be performed in the backend
     ///
     /// After invoking this, `get()` can not be used until `save()` is performed.
@@ -100,6 +179,17 @@ pub fn remove(&mut self, val: &T) {
         self.removed_values.push(val.pk().to_sql())
     }
 
+    /// Removes several values, yet to be performed in the backend,
+    /// which are all deleted by the same statement when saved.
+    ///
+    /// After invoking this, `get()` can not be used until `save()` is performed.
+    pub fn remove_all(&mut self, vals: &[&T]) {
+        // all_values is now out of date, so clear it
+        self.all_values = OnceLock::new();
+        self.removed_values
+            .extend(vals.iter().map(|val| val.pk().to_sql()));
+    }
+
     /// Returns already loaded values.
     ///
     /// Returns [`Error::ValueNotLoaded`] if `load()` has not been invoked prior.
@@ -124,6 +214,20 @@ fn query(&self) -> Result<Query<T>> {
         }))
     }
 
+    /// Whether the value with primary key `pk` is referred to once the
+    /// unsaved changes are saved, or `None` if that depends on whether
+    /// it is referred to in the backend. As `save()` removes values
+    /// after adding them, an unsaved removal outweighs an addition.
+    fn unsaved_contains(&self, pk: &SqlVal) -> Option<bool> {
+        if self.removed_values.contains(pk) {
+            Some(false)
+        } else if self.new_values.contains(pk) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
     /// Describes the columns of the Many table.
     pub fn columns(&self) -> [Column; 2] {
         [
@@ -162,6 +266,91 @@ async fn load_query_uncached<'a, T>(
     Ok(vals)
 }
 
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Queries the Many table for which of `pks` are referred to by
+/// `owner`, or for all those referred to if `pks` is `None`, without
+/// loading the referred values themselves.
+async fn load_has(
+    conn: &impl ConnectionMethods,
+    item_table: &str,
+    owner: Option<&SqlVal>,
+    has: &Column,
+    pks: Option<Vec<SqlVal>>,
+) -> Result<Vec<SqlVal>> {
+    let owner = match owner {
+        Some(o) => o,
+        // If not initialised then there are no values
+        None => return Ok(Vec::new()),
+    };
+    let owned = BoolExpr::Eq("owner", Expr::Val(owner.clone()));
+    let expr = match pks {
+        Some(pks) if pks.is_empty() => return Ok(Vec::new()),
+        Some(pks) => BoolExpr::And(Box::new(owned), Box::new(BoolExpr::In("has", pks))),
+        None => owned,
+    };
+    conn.query(
+        item_table,
+        std::slice::from_ref(has),
+        Some(expr),
+        None,
+        None,
+        None,
+    )
+    .await?
+    .mapped(|row| Ok(SqlVal::from(row.get(0, has.ty().clone())?)))
+    .collect()
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Tests whether `owner` refers to any of `pks` in the Many table,
+/// with an `EXISTS` query which stops at the first one found.
+async fn has_any(
+    conn: &impl ConnectionMethods,
+    item_table: &str,
+    owner: Option<&SqlVal>,
+    pks: &[SqlVal],
+) -> Result<bool> {
+    let owner = match owner {
+        Some(o) => o,
+        // If not initialised then there are no values
+        None => return Ok(false),
+    };
+    let found = Column::new("found", SqlType::Int);
+    // One parameter is taken by the owner
+    let chunk_len = conn.max_statement_params().saturating_sub(1).max(1);
+    for chunk in pks.chunks(chunk_len) {
+        let sql = format!(
+            "SELECT CASE WHEN EXISTS (SELECT 1 FROM {} WHERE {} = ? AND {} IN ({})) THEN 1 ELSE 0 END",
+            helper::quote_reserved_word(item_table),
+            helper::quote_reserved_word("owner"),
+            helper::quote_reserved_word("has"),
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let params: Vec<SqlValRef> = std::iter::once(owner.as_ref())
+            .chain(chunk.iter().map(SqlVal::as_ref))
+            .collect();
+        let mut rows = conn
+            .query_params(&sql, &params, std::slice::from_ref(&found))
+            .await?;
+        let exists = match rows.next()? {
+            Some(row) => SqlVal::from(row.get(0, SqlType::Int)?) == SqlVal::Int(1),
+            None => false,
+        };
+        if exists {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 /// Loads the values referred to by this many relationship from a
 /// database query if necessary and returns a reference to them.
 #[maybe_async_cfg::maybe(
@@ -197,7 +386,10 @@ pub trait ManyOps<T: DataObject> {
     ///
     /// Used by macro-generated code. You do not need to call this directly.
     ///
-    /// This will insert added values first, and then remove the removed values.
+    /// This will insert added values first, all with one statement, and
+    /// then remove the removed values, also with one statement. Unless
+    /// a [`Many`] allows duplicates, added values which are already
+    /// related are skipped.
     /// Use inside a transaction to provide atomicity.
     async fn save(&mut self, conn: &impl ConnectionMethods) -> Result<()>;
 
@@ -206,9 +398,13 @@ pub trait ManyOps<T: DataObject> {
     /// This operation is atomic.
     async fn delete(&mut self, conn: &impl ConnectionMethods) -> Result<()>;
 
-    /// Overwrite the references in the backend, and clears unsaved additions.
+    /// Overwrite the references in the backend, and clears unsaved changes.
     ///
-    /// This will call `delete()` first, and then insert the items scheduled to be added.
+    /// For a [`Many`], this loads the references in the backend and
+    /// then, as `save()` does, inserts those missing and deletes those
+    /// not in `values`, each with one statement, leaving the others
+    /// untouched. For an [`OrderedMany`], this calls `delete()` first,
+    /// and then inserts `values` in order.
     /// Use inside a transaction to provide atomicity.
     async fn set(&mut self, conn: &impl ConnectionMethods, values: Vec<T>) -> Result<()>;
 
@@ -228,6 +424,25 @@ async fn load_ordered<'a>(
     ) -> Result<impl Iterator<Item = &'a T>>
     where
         T: 'a;
+
+    /// Tests whether `item` is referred to by this many relationship,
+    /// without loading the other values.
+    ///
+    /// Unsaved additions are included, as with `load()`, and unsaved
+    /// removals are excluded.
+    async fn contains(&self, conn: &impl ConnectionMethods, item: &T) -> Result<bool>;
+
+    /// Tests whether any of the values with primary keys `pks` are referred to by
+    /// this many relationship, without loading the other values.
+    ///
+    /// Returns false if `pks` is empty.
+    async fn contains_any(&self, conn: &impl ConnectionMethods, pks: &[T::PKType]) -> Result<bool>;
+
+    /// Tests whether all of the values with primary keys `pks` are referred to by
+    /// this many relationship, without loading the other values.
+    ///
+    /// Returns true if `pks` is empty.
+    async fn contains_all(&self, conn: &impl ConnectionMethods, pks: &[T::PKType]) -> Result<bool>;
 }
 
 #[maybe_async_cfg::maybe(
@@ -236,6 +451,8 @@ async fn load_ordered<'a>(
         ManyOpsInternal,
         ManyOps,
         load_query(sync = "load_query_sync", async = "load_query_async"),
+        load_has(sync = "load_has_sync", async = "load_has_async"),
+        has_any(sync = "has_any_sync", async = "has_any_async"),
     ),
     keep_self,
     sync(),
@@ -244,25 +461,39 @@ async fn load_ordered<'a>(
 impl<T: DataObject> ManyOps<T> for Many<T> {
     async fn save(&mut self, conn: &impl ConnectionMethods) -> Result<()> {
         let owner = self.owner.as_ref().ok_or(Error::NotInitialized)?;
-        while !self.new_values.is_empty() {
-            conn.insert_only(
-                &self.item_table,
-                &self.columns(),
-                &[
-                    owner.as_ref(),
-                    self.new_values.pop().unwrap().as_ref().clone(),
-                ],
-            )
-            .await?;
+        if !self.new_values.is_empty() {
+            let mut new_values: Vec<&SqlVal> = Vec::with_capacity(self.new_values.len());
+            for has in &self.new_values {
+                if self.allow_duplicates || !new_values.contains(&has) {
+                    new_values.push(has);
+                }
+            }
+            let values: Vec<SqlValRef> = new_values
+                .into_iter()
+                .flat_map(|has| [owner.as_ref(), has.as_ref()])
+                .collect();
+            if self.allow_duplicates {
+                conn.insert_only_many(&self.item_table, &self.columns(), &values)
+                    .await?;
+            } else {
+                // Values may have been related since they were loaded,
+                // so rely on the unique index rather than on `add()`
+                conn.insert_or_ignore_many(&self.item_table, &self.columns(), &values)
+                    .await?;
+            }
+            self.new_values.clear();
         }
         if !self.removed_values.is_empty() {
             conn.delete_where(
                 &self.item_table,
-                BoolExpr::In("has", std::mem::take(&mut self.removed_values)),
+                BoolExpr::And(
+                    Box::new(BoolExpr::Eq("owner", Expr::Val(owner.clone()))),
+                    Box::new(BoolExpr::In("has", self.removed_values.clone())),
+                ),
             )
             .await?;
+            self.removed_values.clear();
         }
-        self.new_values.clear();
         Ok(())
     }
 
@@ -281,11 +512,25 @@ async fn delete(&mut self, conn: &impl ConnectionMethods) -> Result<()> {
     }
 
     async fn set(&mut self, conn: &impl ConnectionMethods, values: Vec<T>) -> Result<()> {
-        ManyOps::delete(self, conn).await?;
-
-        for value in &values {
-            self.add(value)?;
+        let owner = self.owner.as_ref().ok_or(Error::NotInitialized)?;
+        if values.iter().any(|value| !value.pk().is_valid()) {
+            return Err(Error::ValueNotSaved);
+        }
+        let has = &self.columns()[1];
+        let current = load_has(conn, &self.item_table, Some(owner), has, None).await?;
+        let mut wanted: Vec<SqlVal> = Vec::with_capacity(values.len());
+        for pk in values.iter().map(|value| value.pk().to_sql()) {
+            if !wanted.contains(&pk) {
+                wanted.push(pk);
+            }
         }
+        self.removed_values = current
+            .iter()
+            .filter(|pk| !wanted.contains(pk))
+            .cloned()
+            .collect();
+        wanted.retain(|pk| !current.contains(pk));
+        self.new_values = wanted;
         ManyOps::save(self, conn).await?;
 
         self.all_values = OnceLock::from(values);
@@ -301,10 +546,9 @@ async fn load<'a>(
     {
         let query = self.query();
         // If not initialised then there are no values
-        let vals: Result<Vec<&T>> = if query.is_err() {
-            Ok(Vec::new())
-        } else {
-            Ok(load_query(self, conn, query.unwrap()).await?.collect())
+        let vals: Result<Vec<&T>> = match query {
+            Ok(query) => Ok(load_query(self, conn, query).await?.collect()),
+            Err(_) => Ok(Vec::new()),
         };
         vals.map(|v| v.into_iter())
     }
@@ -319,17 +563,52 @@ async fn load_ordered<'a>(
     {
         let query = self.query();
         // If not initialised then there are no values
-        let vals: Result<Vec<&T>> = if query.is_err() {
-            Ok(Vec::new())
-        } else {
-            Ok(
-                load_query(self, conn, query.unwrap().order(T::PKCOL, order))
-                    .await?
-                    .collect(),
-            )
+        let vals: Result<Vec<&T>> = match query {
+            Ok(query) => Ok(load_query(self, conn, query.order(T::PKCOL, order))
+                .await?
+                .collect()),
+            Err(_) => Ok(Vec::new()),
         };
         vals.map(|v| v.into_iter())
     }
+
+    async fn contains(&self, conn: &impl ConnectionMethods, item: &T) -> Result<bool> {
+        ManyOps::contains_any(self, conn, std::slice::from_ref(item.pk())).await
+    }
+
+    async fn contains_any(&self, conn: &impl ConnectionMethods, pks: &[T::PKType]) -> Result<bool> {
+        let mut saved: Vec<SqlVal> = Vec::new();
+        for pk in pks.iter().map(|pk| pk.to_sql()) {
+            match self.unsaved_contains(&pk) {
+                Some(true) => return Ok(true),
+                Some(false) => {}
+                None => saved.push(pk),
+            }
+        }
+        has_any(conn, &self.item_table, self.owner.as_ref(), &saved).await
+    }
+
+    async fn contains_all(&self, conn: &impl ConnectionMethods, pks: &[T::PKType]) -> Result<bool> {
+        let mut pks_saved: Vec<SqlVal> = Vec::new();
+        for pk in pks.iter().map(|pk| pk.to_sql()) {
+            match self.unsaved_contains(&pk) {
+                Some(true) => {}
+                Some(false) => return Ok(false),
+                None => pks_saved.push(pk),
+            }
+        }
+        let pks = pks_saved;
+        let has = &self.columns()[1];
+        let found = load_has(
+            conn,
+            &self.item_table,
+            self.owner.as_ref(),
+            has,
+            Some(pks.clone()),
+        )
+        .await?;
+        Ok(pks.iter().all(|pk| found.contains(pk)))
+    }
 }
 
 impl<T: DataObject> PartialEq<Many<T>> for Many<T> {
@@ -345,9 +624,654 @@ fn default() -> Self {
 }
 
 #[cfg(feature = "fake")]
-/// Fake data support is currently limited to empty Many relationships.
+/// Fake Many relationships are empty, except when generated by a
+/// [`Seeder`](crate::seed::Seeder), which adds a few random objects it
+/// seeded before.
 impl<T: DataObject> Dummy<Faker> for Many<T> {
-    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &Faker, _rng: &mut R) -> Self {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &Faker, rng: &mut R) -> Self {
+        let mut many = Self::new();
+        many.new_values = crate::seed::seeded_pks(T::TABLE, rng);
+        many
+    }
+}
+
+/// Serde representation of a [`Many`] as an array of the primary keys
+/// of its values, for use with `#[serde(with = "butane::many::pks")]`
+/// on a field.
+///
+/// The values must have been loaded to be serialized. A deserialized
+/// `Many` has the values with the given primary keys yet to be added,
+/// which happens when the object it belongs to is saved.
+///
+/// # Examples
+/// ```ignore
+/// #[model]
+/// #[derive(Serialize, Deserialize)]
+/// struct Post {
+///   #[serde(with = "butane::many::pks")]
+///   tags: Many<Tag>,
+///   ...
+/// }
+/// ```
+pub mod pks {
+    use serde::ser::{Error as _, SerializeSeq};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Many;
+    use crate::{DataObject, ToSql};
+
+    /// Serializes the primary keys of the loaded values of `many`.
+    pub fn serialize<T, S>(many: &Many<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: DataObject,
+        T::PKType: Serialize,
+        S: Serializer,
+    {
+        let values = many.get().map_err(S::Error::custom)?;
+        let mut seq = serializer.serialize_seq(None)?;
+        for value in values {
+            seq.serialize_element(value.pk())?;
+        }
+        seq.end()
+    }
+
+    /// Deserializes a `Many` from an array of primary keys.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Many<T>, D::Error>
+    where
+        T: DataObject,
+        T::PKType: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let mut many = Many::new();
+        many.new_values = Vec::<T::PKType>::deserialize(deserializer)?
+            .iter()
+            .map(ToSql::to_sql)
+            .collect();
+        Ok(many)
+    }
+}
+
+/// Serde representation of a [`Many`] as an array of its values, for
+/// use with `#[serde(with = "butane::many::nested")]` on a field.
+///
+/// The values must have been loaded to be serialized. Either values or
+/// their primary keys are accepted when deserializing, and a
+/// deserialized `Many` has them yet to be added, which happens when the
+/// object it belongs to is saved.
+pub mod nested {
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Many;
+    use crate::fkey::nested::ValueOrPk;
+    use crate::{DataObject, ToSql};
+
+    /// Serializes the loaded values of `many`.
+    pub fn serialize<T, S>(many: &Many<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: DataObject + Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(many.get().map_err(S::Error::custom)?)
+    }
+
+    /// Deserializes a `Many` from an array of values or primary keys.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Many<T>, D::Error>
+    where
+        T: DataObject + Deserialize<'de>,
+        T::PKType: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let mut many = Many::new();
+        many.new_values = Vec::<ValueOrPk<T, T::PKType>>::deserialize(deserializer)?
+            .iter()
+            .map(|v| match v {
+                ValueOrPk::Value(value) => value.pk().to_sql(),
+                ValueOrPk::Pk(pk) => pk.to_sql(),
+            })
+            .collect();
+        Ok(many)
+    }
+}
+
+/// Used to implement an ordered many-to-many relationship between models.
+///
+/// Like [`Many`], but the table has an additional "position" column
+/// recording the order of the values, which is kept as values are
+/// inserted, moved and removed. Useful for user-sortable lists such as
+/// playlist items. Each value appears at most once.
+///
+/// Values added with [`add`](Self::add) are appended without loading
+/// the existing values. Inserting at or moving to a particular index
+/// requires the values to have been loaded first, and saving after
+/// doing so deletes the rows of removed values, inserts those of added
+/// values and updates the positions of the others, each with as few
+/// statements as the number of parameters allows.
+///
+/// See [`ManyOpsSync`] and [`ManyOpsAsync`] for operations requiring a live database connection.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(bound = "")]
+pub struct OrderedMany<T>
+where
+    T: DataObject,
+{
+    /// The relationship regardless of order. Its loaded values are in
+    /// order, and its unsaved additions are the values without a row
+    /// in the backend yet, or whose row is to be replaced.
+    #[serde(flatten)]
+    many: Many<T>,
+    /// Primary keys of all the values in order, once loaded.
+    #[serde(skip)]
+    #[serde(default = "OnceLock::new")]
+    order: OnceLock<Vec<SqlVal>>,
+    /// Whether `order` has been changed since it was loaded.
+    #[serde(skip)]
+    reordered: bool,
+}
+impl<T> OrderedMany<T>
+where
+    T: DataObject,
+{
+    /// Constructs a new OrderedMany. `init` must be called before it can be
+    /// loaded or saved (or those methods will return
+    /// `Error::NotInitialized`). `init` will automatically be called
+    /// when a [`DataObject`] with an `OrderedMany` field is loaded or saved.
+    ///
+    /// [`DataObject`]: super::DataObject
+    pub fn new() -> Self {
+        OrderedMany {
+            many: Many::new(),
+            order: OnceLock::new(),
+            reordered: false,
+        }
+    }
+
+    /// Used by macro-generated code. You do not need to call this directly.
+    pub fn ensure_init(&mut self, item_table: &'static str, owner: SqlVal, owner_type: SqlType) {
+        self.many.ensure_init(item_table, owner, owner_type);
+    }
+
+    /// Adds a value at the end, yet to be performed in the backend. If
+    /// the value is already present, it is moved to the end.
+    ///
+    /// After invoking this, `get()` can not be used until `load()` is performed.
+    ///
+    /// Returns Err(ValueNotSaved) if the provided value uses automatic primary keys and appears
+    /// to have an uninitialized one.
+    pub fn add(&mut self, new_val: &T) -> Result<()> {
+        if !new_val.pk().is_valid() {
+            return Err(Error::ValueNotSaved);
+        }
+        let pk = new_val.pk().to_sql();
+        // all_values is now out of date, so clear it
+        self.many.all_values = OnceLock::new();
+        match self.order.get_mut() {
+            Some(order) => {
+                let index = order.len() - usize::from(order.contains(&pk));
+                self.place(index, pk);
+            }
+            None => {
+                // Any row of the value is replaced by one at the end
+                self.many.removed_values.retain(|v| *v != pk);
+                self.many.new_values.retain(|v| *v != pk);
+                self.many.new_values.push(pk);
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts a value at `index`, yet to be performed in the
+    /// backend. If the value is already present, it is moved to `index`.
+    ///
+    /// After invoking this, `get()` can not be used until `load()` is performed.
+    ///
+    /// Returns [`Error::ValueNotLoaded`] if `load()` has not been invoked prior,
+    /// and [`Error::BoundsError`] if `index` is greater than the number of other values.
+    pub fn insert_at(&mut self, index: usize, val: &T) -> Result<()> {
+        if !val.pk().is_valid() {
+            return Err(Error::ValueNotSaved);
+        }
+        let order = self.order.get().ok_or(Error::ValueNotLoaded)?;
+        let pk = val.pk().to_sql();
+        let len = order.len() - usize::from(order.contains(&pk));
+        if index > len {
+            return Err(Error::BoundsError(format!(
+                "Cannot insert at index {index} in OrderedMany of length {len}"
+            )));
+        }
+        self.place(index, pk);
+        // all_values is now out of date, so clear it
+        self.many.all_values = OnceLock::new();
+        Ok(())
+    }
+
+    /// Places the value with primary key `pk` at `index` of the loaded
+    /// order, moving it if it is already present. A value not present
+    /// is inserted when saved, unless it was removed since being loaded,
+    /// in which case its row is kept.
+    fn place(&mut self, index: usize, pk: SqlVal) {
+        let Some(order) = self.order.get_mut() else {
+            return;
+        };
+        match order.iter().position(|v| *v == pk) {
+            Some(existing) => {
+                order.remove(existing);
+            }
+            None => {
+                if self.many.removed_values.contains(&pk) {
+                    self.many.removed_values.retain(|v| *v != pk);
+                } else {
+                    self.many.new_values.push(pk.clone());
+                }
+            }
+        }
+        order.insert(index, pk);
+        self.reordered = true;
+    }
+
+    /// Moves a value to `index`, yet to be performed in the backend.
+    ///
+    /// Returns [`Error::ValueNotLoaded`] if `load()` has not been invoked prior,
+    /// [`Error::NoSuchObject`] if the value is not present,
+    /// and [`Error::BoundsError`] if `index` is not less than the number of values.
+    pub fn move_to(&mut self, val: &T, index: usize) -> Result<()> {
+        let order = self.order.get_mut().ok_or(Error::ValueNotLoaded)?;
+        let pk = val.pk().to_sql();
+        let from = order
+            .iter()
+            .position(|v| *v == pk)
+            .ok_or(Error::NoSuchObject)?;
+        if index >= order.len() {
+            return Err(Error::BoundsError(format!(
+                "Cannot move to index {index} in OrderedMany of length {}",
+                order.len()
+            )));
+        }
+        let pk = order.remove(from);
+        order.insert(index, pk);
+        self.reordered = true;
+        if let Some(vals) = self.many.all_values.get_mut() {
+            if let Some(from) = vals
+                .iter()
+                .position(|v| v.pk().to_sql() == *order.get(index).unwrap())
+            {
+                let v = vals.remove(from);
+                vals.insert(index.min(vals.len()), v);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a value, yet to be performed in the backend.
+    pub fn remove(&mut self, val: &T) {
+        let pk = val.pk().to_sql();
+        let was_added = self.many.new_values.contains(&pk);
+        self.many.new_values.retain(|v| *v != pk);
+        match self.order.get_mut() {
+            Some(order) => {
+                if order.contains(&pk) {
+                    order.retain(|v| *v != pk);
+                    // The positions of the others keep their order
+                    if !was_added {
+                        self.many.removed_values.push(pk.clone());
+                    }
+                }
+                if let Some(vals) = self.many.all_values.get_mut() {
+                    vals.retain(|v| v.pk().to_sql() != pk);
+                }
+            }
+            None => self.many.removed_values.push(pk),
+        }
+    }
+
+    /// Returns already loaded values, in order.
+    ///
+    /// Returns [`Error::ValueNotLoaded`] if `load()` has not been invoked prior.
+    pub fn get(&self) -> Result<impl Iterator<Item = &T>> {
+        self.many.get()
+    }
+
+    /// Describes the columns of the OrderedMany table.
+    pub fn columns(&self) -> [Column; 3] {
+        let [owner, has] = self.many.columns();
+        [owner, has, Column::new(MANY_POSITION_COLUMN, SqlType::Int)]
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Loads the primary keys of the values referred to by this ordered
+/// many relationship from the backend, in order.
+async fn load_order<T>(many: &OrderedMany<T>, conn: &impl ConnectionMethods) -> Result<Vec<SqlVal>>
+where
+    T: DataObject,
+{
+    let owner = match &many.many.owner {
+        Some(o) => o,
+        // If not initialised then there are no values
+        None => return Ok(Vec::new()),
+    };
+    let has = &many.columns()[1];
+    conn.query(
+        &many.many.item_table,
+        std::slice::from_ref(has),
+        Some(BoolExpr::Eq("owner", Expr::Val(owner.clone()))),
+        None,
+        None,
+        Some(&[Order {
+            direction: OrderDirection::Ascending,
+            column: MANY_POSITION_COLUMN,
+            expr: None,
+        }]),
+    )
+    .await?
+    .mapped(|row| Ok(SqlVal::from(row.get(0, has.ty().clone())?)))
+    .collect()
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync"), QueryOps),
+    sync(),
+    async(feature = "async")
+)]
+/// Loads the values with primary keys `order`, in that order.
+async fn load_ordered_values<T>(conn: &impl ConnectionMethods, order: &[SqlVal]) -> Result<Vec<T>>
+where
+    T: DataObject,
+{
+    use crate::query::QueryOps;
+    if order.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut vals: Vec<T> = T::query()
+        .filter(BoolExpr::In(T::PKCOL, order.to_vec()))
+        .load(conn)
+        .await?;
+    vals.sort_by_cached_key(|v| {
+        let pk = v.pk().to_sql();
+        order.iter().position(|o| *o == pk)
+    });
+    Ok(vals)
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Returns the position following the last value stored in the backend
+/// for this ordered many relationship.
+async fn next_position<T>(
+    many: &OrderedMany<T>,
+    conn: &impl ConnectionMethods,
+    owner: &SqlVal,
+) -> Result<i32>
+where
+    T: DataObject,
+{
+    let position = &many.columns()[2];
+    let last: Option<i32> = conn
+        .query(
+            &many.many.item_table,
+            std::slice::from_ref(position),
+            Some(BoolExpr::Eq("owner", Expr::Val(owner.clone()))),
+            Some(1),
+            None,
+            Some(&[Order {
+                direction: OrderDirection::Descending,
+                column: MANY_POSITION_COLUMN,
+                expr: None,
+            }]),
+        )
+        .await?
+        .mapped(|row| i32::from_sql_ref(row.get(0, SqlType::Int)?))
+        .nth(0)?;
+    Ok(last.map_or(0, |p| p + 1))
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Inserts the rows of `values`, each with its position, with one
+/// statement.
+async fn insert_at_positions<T>(
+    many: &OrderedMany<T>,
+    conn: &impl ConnectionMethods,
+    owner: &SqlVal,
+    values: &[(i32, &SqlVal)],
+) -> Result<()>
+where
+    T: DataObject,
+{
+    if values.is_empty() {
+        return Ok(());
+    }
+    let values: Vec<SqlValRef> = values
+        .iter()
+        .flat_map(|(position, value)| [owner.as_ref(), value.as_ref(), SqlValRef::Int(*position)])
+        .collect();
+    conn.insert_only_many(&many.many.item_table, &many.columns(), &values)
+        .await
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync, async = "ConnectionMethodsAsync")),
+    sync(),
+    async(feature = "async")
+)]
+/// Updates the positions of the stored rows of `values`, each with its
+/// position, with as few statements as the number of parameters allows.
+async fn update_positions<T>(
+    many: &OrderedMany<T>,
+    conn: &impl ConnectionMethods,
+    owner: &SqlVal,
+    values: &[(i32, &SqlVal)],
+) -> Result<()>
+where
+    T: DataObject,
+{
+    // Each value is a parameter of its condition, position and of the
+    // filter, and the owner is one more
+    let chunk_len = (conn.max_statement_params().saturating_sub(1) / 3).max(1);
+    for chunk in values.chunks(chunk_len) {
+        let position = Expr::case(
+            chunk.iter().map(|(position, value)| {
+                (
+                    BoolExpr::Eq("has", Expr::Val((*value).clone())),
+                    Expr::Val(SqlVal::Int(*position)),
+                )
+            }),
+            None,
+        );
+        let has = chunk.iter().map(|(_, value)| (*value).clone()).collect();
+        conn.update_where(
+            &many.many.item_table,
+            vec![(MANY_POSITION_COLUMN, position)],
+            BoolExpr::And(
+                Box::new(BoolExpr::Eq("owner", Expr::Val(owner.clone()))),
+                Box::new(BoolExpr::In("has", has)),
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        ManyOps,
+        load_order(sync = "load_order_sync", async = "load_order_async"),
+        load_ordered_values(
+            sync = "load_ordered_values_sync",
+            async = "load_ordered_values_async"
+        ),
+        next_position(sync = "next_position_sync", async = "next_position_async"),
+        insert_at_positions(
+            sync = "insert_at_positions_sync",
+            async = "insert_at_positions_async"
+        ),
+        update_positions(sync = "update_positions_sync", async = "update_positions_async"),
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async", idents(get_or_init_once_lock(snake)))
+)]
+impl<T: DataObject> ManyOps<T> for OrderedMany<T> {
+    async fn save(&mut self, conn: &impl ConnectionMethods) -> Result<()> {
+        let owner = self.many.owner.clone().ok_or(Error::NotInitialized)?;
+        // The rows of added values are replaced, in case they are
+        // already present
+        let changed: Vec<SqlVal> = self
+            .many
+            .removed_values
+            .iter()
+            .chain(&self.many.new_values)
+            .cloned()
+            .collect();
+        if !changed.is_empty() {
+            conn.delete_where(
+                &self.many.item_table,
+                BoolExpr::And(
+                    Box::new(BoolExpr::Eq("owner", Expr::Val(owner.clone()))),
+                    Box::new(BoolExpr::In("has", changed)),
+                ),
+            )
+            .await?;
+        }
+        if let Some(order) = self.order.get() {
+            let (added, kept): (Vec<_>, Vec<_>) = (0..)
+                .zip(order)
+                .partition(|(_, pk)| self.many.new_values.contains(pk));
+            // Added values take indices of the loaded order, so the
+            // others must too
+            if self.reordered || !added.is_empty() {
+                update_positions(self, conn, &owner, &kept).await?;
+            }
+            insert_at_positions(self, conn, &owner, &added).await?;
+        } else if !self.many.new_values.is_empty() {
+            let position = next_position(self, conn, &owner).await?;
+            let added: Vec<(i32, &SqlVal)> = (position..).zip(&self.many.new_values).collect();
+            insert_at_positions(self, conn, &owner, &added).await?;
+        }
+        self.many.new_values.clear();
+        self.many.removed_values.clear();
+        self.reordered = false;
+        Ok(())
+    }
+
+    async fn delete(&mut self, conn: &impl ConnectionMethods) -> Result<()> {
+        ManyOps::delete(&mut self.many, conn).await?;
+        self.reordered = false;
+        self.order = OnceLock::from(Vec::new());
+        Ok(())
+    }
+
+    async fn set(&mut self, conn: &impl ConnectionMethods, values: Vec<T>) -> Result<()> {
+        let owner = self.many.owner.clone().ok_or(Error::NotInitialized)?;
+        if values.iter().any(|value| !value.pk().is_valid()) {
+            return Err(Error::ValueNotSaved);
+        }
+        // A repeated value is placed where it last appears, as with `add()`
+        let mut order: Vec<SqlVal> = Vec::with_capacity(values.len());
+        for pk in values.iter().map(|value| value.pk().to_sql()) {
+            order.retain(|v| *v != pk);
+            order.push(pk);
+        }
+        ManyOps::delete(self, conn).await?;
+        let added: Vec<(i32, &SqlVal)> = (0..).zip(&order).collect();
+        insert_at_positions(self, conn, &owner, &added).await?;
+
+        self.order = OnceLock::from(order);
+        self.many.all_values = OnceLock::from(values);
+        Ok(())
+    }
+
+    async fn load<'a>(
+        &'a self,
+        conn: &impl ConnectionMethods,
+    ) -> Result<impl Iterator<Item = &'a T>>
+    where
+        T: 'a,
+    {
+        let order = match self.order.get() {
+            Some(order) => order,
+            None => {
+                let mut order = load_order(self, conn).await?;
+                order.retain(|pk| {
+                    !self.many.removed_values.contains(pk) && !self.many.new_values.contains(pk)
+                });
+                order.extend(self.many.new_values.iter().cloned());
+                self.order.get_or_init(|| order)
+            }
+        };
+        get_or_init_once_lock(&self.many.all_values, || load_ordered_values(conn, order))
+            .await
+            .map(|v| v.iter())
+    }
+
+    /// Loads the values in order of position, or in reverse for [`OrderDirection::Descending`].
+    async fn load_ordered<'a>(
+        &'a self,
+        conn: &impl ConnectionMethods,
+        order: OrderDirection,
+    ) -> Result<impl Iterator<Item = &'a T>>
+    where
+        T: 'a,
+    {
+        let mut vals: Vec<&T> = ManyOps::load(self, conn).await?.collect();
+        if matches!(order, OrderDirection::Descending) {
+            vals.reverse();
+        }
+        Ok(vals.into_iter())
+    }
+
+    async fn contains(&self, conn: &impl ConnectionMethods, item: &T) -> Result<bool> {
+        ManyOps::contains_any(self, conn, std::slice::from_ref(item.pk())).await
+    }
+
+    async fn contains_any(&self, conn: &impl ConnectionMethods, pks: &[T::PKType]) -> Result<bool> {
+        match self.order.get() {
+            Some(order) => Ok(pks.iter().any(|pk| order.contains(&pk.to_sql()))),
+            None => ManyOps::contains_any(&self.many, conn, pks).await,
+        }
+    }
+
+    async fn contains_all(&self, conn: &impl ConnectionMethods, pks: &[T::PKType]) -> Result<bool> {
+        match self.order.get() {
+            Some(order) => Ok(pks.iter().all(|pk| order.contains(&pk.to_sql()))),
+            None => ManyOps::contains_all(&self.many, conn, pks).await,
+        }
+    }
+}
+
+impl<T: DataObject> PartialEq<OrderedMany<T>> for OrderedMany<T> {
+    fn eq(&self, other: &OrderedMany<T>) -> bool {
+        self.many == other.many
+    }
+}
+impl<T: DataObject> Eq for OrderedMany<T> {}
+impl<T: DataObject> Default for OrderedMany<T> {
+    fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(feature = "fake")]
+/// Fake OrderedMany relationships are empty, except when generated by a
+/// [`Seeder`](crate::seed::Seeder), which adds a few random objects it
+/// seeded before.
+impl<T: DataObject> Dummy<Faker> for OrderedMany<T> {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(faker: &Faker, rng: &mut R) -> Self {
+        OrderedMany {
+            many: Many::dummy_with_rng(faker, rng),
+            order: OnceLock::new(),
+            reordered: false,
+        }
+    }
+}
@@ -1,7 +1,13 @@
 //! Abstract representation of a database schema. If using the butane
 //! CLI tool, there is no need to use this module. Even if applying
 //! migrations without this tool, you are unlikely to need this module.
+//!
+//! It is however useful for tooling built on top of butane, such as
+//! schema linters, as [`diff`] determines the [`Operation`]s between
+//! two schemas in the same way as `butane makemigration`. The schema of
+//! a migration is available from [`Migration::db`](super::Migration::db).
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 
@@ -15,6 +21,12 @@
 /// Suffix added to [`crate::many::Many`] tables.
 pub const MANY_SUFFIX: &str = "_Many";
 
+/// Suffix added to the history tables of audited models, see [`crate::audit`].
+pub const HISTORY_SUFFIX: &str = "_History";
+
+/// Column of [`crate::many::OrderedMany`] tables holding the position of each value.
+pub const MANY_POSITION_COLUMN: &str = "position";
+
 #[cfg(feature = "json")]
 static JSON_MAP_PREFIXES: LazyLock<Vec<String>> = LazyLock::new(|| {
     let map_type_names: [&str; 6] = [
@@ -51,6 +63,52 @@ fn from(ty: SqlType) -> Self {
     }
 }
 
+/// Policy for normalizing the case of table and column names.
+///
+/// PostgreSQL folds unquoted identifiers to lowercase while SQLite
+/// preserves their case, so a schema containing mixed-case names is
+/// not spelled identically on every backend. Folding all identifiers
+/// to lowercase makes migrations behave the same everywhere.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierCase {
+    /// Use names exactly as they are written in the models.
+    #[default]
+    Preserve,
+    /// Fold all names to lowercase.
+    Lower,
+}
+impl IdentifierCase {
+    /// Apply this policy to `ident`.
+    pub fn fold<'a>(&self, ident: &'a str) -> Cow<'a, str> {
+        match self {
+            IdentifierCase::Preserve => Cow::Borrowed(ident),
+            IdentifierCase::Lower => Cow::Owned(ident.to_lowercase()),
+        }
+    }
+    pub(crate) fn is_preserve(&self) -> bool {
+        *self == IdentifierCase::Preserve
+    }
+}
+impl std::fmt::Display for IdentifierCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IdentifierCase::Preserve => f.write_str("preserve"),
+            IdentifierCase::Lower => f.write_str("lower"),
+        }
+    }
+}
+impl std::str::FromStr for IdentifierCase {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "preserve" => Ok(IdentifierCase::Preserve),
+            "lower" => Ok(IdentifierCase::Lower),
+            _ => Err(Error::UnknownEnumVariant(s.to_string())),
+        }
+    }
+}
+
 /// Key used to help resolve `DeferredSqlType`
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum TypeKey {
@@ -60,6 +118,14 @@ pub enum TypeKey {
     /// which butane will be made aware of with the `#\[butane_type\]` macro
     CustomType(String),
 }
+impl TypeKey {
+    fn fold_identifiers(&mut self, case: IdentifierCase) {
+        // Only primary key types are named after tables.
+        if let TypeKey::PK(name) = self {
+            *name = case.fold(name).into_owned();
+        }
+    }
+}
 impl std::fmt::Display for TypeKey {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
         match self {
@@ -176,12 +242,15 @@ fn insert_pk(&mut self, key: &str, ty: TypeIdentifier) -> bool {
 pub struct ADB {
     tables: BTreeMap<String, ATable>,
     extra_types: BTreeMap<TypeKey, DeferredSqlType>,
+    #[serde(default, skip_serializing_if = "IdentifierCase::is_preserve")]
+    identifier_case: IdentifierCase,
 }
 impl ADB {
     pub fn new() -> Self {
         ADB {
             tables: BTreeMap::new(),
             extra_types: BTreeMap::new(),
+            identifier_case: IdentifierCase::Preserve,
         }
     }
     pub fn tables(&self) -> impl Iterator<Item = &ATable> {
@@ -193,15 +262,35 @@ pub fn get_table<'a>(&'a self, name: &str) -> Option<&'a ATable> {
     pub fn types(&self) -> &BTreeMap<TypeKey, DeferredSqlType> {
         &self.extra_types
     }
-    pub fn replace_table(&mut self, table: ATable) {
+    pub fn replace_table(&mut self, mut table: ATable) {
+        table.fold_identifiers(self.identifier_case);
         self.tables.insert(table.name.clone(), table);
     }
     pub fn remove_table(&mut self, name: &str) {
         self.tables.remove(name);
     }
-    pub fn add_type(&mut self, key: TypeKey, sqltype: DeferredSqlType) {
+    pub fn add_type(&mut self, mut key: TypeKey, mut sqltype: DeferredSqlType) {
+        key.fold_identifiers(self.identifier_case);
+        sqltype.fold_identifiers(self.identifier_case);
         self.extra_types.insert(key, sqltype);
     }
+    /// The policy applied to table and column names.
+    pub fn identifier_case(&self) -> IdentifierCase {
+        self.identifier_case
+    }
+    /// Set the policy applied to table and column names, normalizing
+    /// all existing tables and types accordingly.
+    pub fn set_identifier_case(&mut self, case: IdentifierCase) {
+        self.identifier_case = case;
+        let tables = std::mem::take(&mut self.tables);
+        for table in tables.into_values() {
+            self.replace_table(table);
+        }
+        let types = std::mem::take(&mut self.extra_types);
+        for (key, sqltype) in types {
+            self.add_type(key, sqltype);
+        }
+    }
 
     /// Fixup as many DeferredSqlType::Deferred instances as possible
     /// into DeferredSqlType::Known
@@ -220,13 +309,12 @@ pub fn resolve_types(&mut self) -> Result<()> {
             changed = false;
 
             for table in &mut self.tables.values_mut() {
+                // Tables without a pk are those of Many fields
                 if let Some(pk) = table.pk() {
                     let pktype = pk.typeid();
                     if let Ok(pktype) = pktype {
                         changed |= resolver.insert_pk(&table.name, pktype.clone());
                     }
-                } else if !table.name.ends_with(MANY_SUFFIX) {
-                    unreachable!();
                 }
 
                 for col in &mut table.columns {
@@ -274,12 +362,28 @@ pub fn transform_with(&mut self, op: Operation) {
                 self.tables.insert(table.name.clone(), table);
             }
             RemoveTable(name) => self.remove_table(&name),
+            RenameTable(from, to) => {
+                if let Some(mut t) = self.tables.remove(&from) {
+                    t.name.clone_from(&to);
+                    self.tables.insert(to.clone(), t);
+                }
+                self.rename_references(&from, None, &to, None);
+            }
+            RenameColumn(table, from, to) => {
+                if let Some(t) = self.tables.get_mut(&table) {
+                    if let Some(col) = t.columns.iter_mut().find(|c| c.name == from) {
+                        col.name.clone_from(&to);
+                    }
+                }
+                self.rename_references(&table, Some(&from), &table, Some(&to));
+            }
             RemoveTableConstraints(_) => {}
             AddColumn(table, col) => {
                 if let Some(t) = self.tables.get_mut(&table) {
                     t.add_column(col);
                 }
             }
+            BackfillColumn(_, _) => {}
             RemoveColumn(table, name) => {
                 if let Some(t) = self.tables.get_mut(&table) {
                     t.remove_column(&name);
@@ -290,6 +394,60 @@ pub fn transform_with(&mut self, op: Operation) {
                     t.replace_column(new);
                 }
             }
+            AddIndex(table, index) => {
+                if let Some(t) = self.tables.get_mut(&table) {
+                    t.replace_index(index);
+                }
+            }
+            RemoveIndex(table, index) => {
+                if let Some(t) = self.tables.get_mut(&table) {
+                    t.indexes.retain(|other| !other.same_columns(&index));
+                }
+            }
+            SetTableComment(table, comment) => {
+                if let Some(t) = self.tables.get_mut(&table) {
+                    t.comment = comment;
+                }
+            }
+            SetColumnComment(table, column, comment) => {
+                if let Some(t) = self.tables.get_mut(&table) {
+                    if let Some(col) = t.columns.iter_mut().find(|c| c.name == column) {
+                        col.comment = comment;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Point references to `from_table` (and `from_column`, if given) at
+    /// `to_table` and `to_column` instead.
+    fn rename_references(
+        &mut self,
+        from_table: &str,
+        from_column: Option<&str>,
+        to_table: &str,
+        to_column: Option<&str>,
+    ) {
+        let refs = self
+            .tables
+            .values_mut()
+            .flat_map(|t| t.columns.iter_mut())
+            .filter_map(|c| match &mut c.reference {
+                Some(ARef::Literal(literal)) => Some(literal),
+                _ => None,
+            });
+        for literal in refs {
+            if literal.table_name != from_table {
+                continue;
+            }
+            match (from_column, to_column) {
+                (Some(from_column), Some(to_column)) => {
+                    if literal.column_name == from_column {
+                        to_column.clone_into(&mut literal.column_name);
+                    }
+                }
+                _ => to_table.clone_into(&mut literal.table_name),
+            }
         }
     }
 }
@@ -299,14 +457,42 @@ pub fn transform_with(&mut self, op: Operation) {
 pub struct ATable {
     pub name: String,
     pub columns: Vec<AColumn>,
+    /// How the table is partitioned, on backends which support it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_by: Option<PartitionBy>,
+    /// The definition of the table, if it is a view rather than a table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view: Option<AView>,
+    /// Indexes on the columns of the table. Those of a materialized view
+    /// are part of its [`AView`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<AIndex>,
+    /// The names of the columns of each model stored in the table, if it
+    /// is shared by models declared with `#[butane(subtype_of = ...)]`,
+    /// so that those of one may change without affecting the others.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub subtype_columns: BTreeMap<String, Vec<String>>,
+    /// Description of the table, from the doc comment of its model, on
+    /// backends which store comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
 }
 impl ATable {
     pub fn new(name: String) -> ATable {
         ATable {
             name,
             columns: Vec::new(),
+            partition_by: None,
+            view: None,
+            indexes: Vec::new(),
+            subtype_columns: BTreeMap::new(),
+            comment: None,
         }
     }
+    /// Whether this is a view rather than a table.
+    pub fn is_view(&self) -> bool {
+        self.view.is_some()
+    }
     pub fn add_column(&mut self, col: AColumn) {
         self.replace_column(col);
     }
@@ -323,9 +509,156 @@ pub fn replace_column(&mut self, col: AColumn) {
     pub fn remove_column(&mut self, name: &str) {
         self.columns.retain(|c| c.name != name);
     }
+    /// Adds `index`, replacing any index on the same columns.
+    pub fn replace_index(&mut self, index: AIndex) {
+        self.indexes.retain(|other| !other.same_columns(&index));
+        self.indexes.push(index);
+    }
+    /// Removes the indexes on the column `column`, if any, including
+    /// those on it along with other columns.
+    pub fn remove_index(&mut self, column: &str) {
+        self.indexes
+            .retain(|index| !index.columns().any(|indexed| indexed == column));
+    }
     pub fn pk(&self) -> Option<&AColumn> {
         self.columns.iter().find(|c| c.is_pk())
     }
+    /// Normalize the table and column names according to `case`.
+    pub(crate) fn fold_identifiers(&mut self, case: IdentifierCase) {
+        self.name = case.fold(&self.name).into_owned();
+        for col in &mut self.columns {
+            col.fold_identifiers(case);
+        }
+        if let Some(partition_by) = &mut self.partition_by {
+            partition_by.column = case.fold(&partition_by.column).into_owned();
+        }
+        let view_indexes = self.view.iter_mut().flat_map(|view| &mut view.indexes);
+        for index in self.indexes.iter_mut().chain(view_indexes) {
+            index.column = case.fold(&index.column).into_owned();
+            for column in &mut index.extra_columns {
+                *column = case.fold(column).into_owned();
+            }
+        }
+    }
+}
+
+/// Abstract representation of a view, which is queried like a table.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AView {
+    /// The query defining the view.
+    pub query: String,
+    /// Whether the results of the query are stored and only updated when
+    /// the view is refreshed. Backends without materialized views create
+    /// a plain view instead.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub materialized: bool,
+    /// Indexes on the columns of a materialized view.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<AIndex>,
+}
+impl AView {
+    /// A plain view defined by `query`.
+    pub fn new(query: impl Into<String>) -> Self {
+        AView {
+            query: query.into(),
+            materialized: false,
+            indexes: Vec::new(),
+        }
+    }
+}
+
+/// Abstract representation of an index on a column, or on several.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AIndex {
+    /// Name of the indexed column.
+    pub column: String,
+    /// Names of further columns indexed after `column`, for an index on
+    /// several columns.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_columns: Vec<String>,
+    /// Whether the index requires the values of the column to be unique.
+    #[serde(default)]
+    pub unique: bool,
+    /// Whether the index is added to and removed from an existing table
+    /// without blocking writes to it, on backends which support it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub concurrently: bool,
+}
+impl AIndex {
+    /// Name of this index on `table`.
+    pub fn name(&self, table: &str) -> String {
+        format!(
+            "{table}_{}_idx",
+            self.columns().collect::<Vec<_>>().join("_")
+        )
+    }
+    /// Names of the indexed columns, in order.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.column.as_str()).chain(self.extra_columns.iter().map(String::as_str))
+    }
+    /// Whether this index is on the same columns as `other`.
+    pub fn same_columns(&self, other: &AIndex) -> bool {
+        self.columns().eq(other.columns())
+    }
+}
+
+/// Method by which rows are assigned to the partitions of a table.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionMethod {
+    /// Each partition holds a range of values.
+    Range,
+    /// Each partition holds a list of values.
+    List,
+    /// Each partition holds the values with a given hash remainder.
+    Hash,
+}
+impl PartitionMethod {
+    /// The SQL for this method, as used in a `PARTITION BY` clause.
+    pub fn sql(&self) -> &'static str {
+        match self {
+            PartitionMethod::Range => "RANGE",
+            PartitionMethod::List => "LIST",
+            PartitionMethod::Hash => "HASH",
+        }
+    }
+}
+impl std::str::FromStr for PartitionMethod {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "range" => Ok(PartitionMethod::Range),
+            "list" => Ok(PartitionMethod::List),
+            "hash" => Ok(PartitionMethod::Hash),
+            _ => Err(Error::UnknownEnumVariant(s.to_string())),
+        }
+    }
+}
+
+/// Partitioning of a table by the values of a column, such as `range(created_at)`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PartitionBy {
+    pub method: PartitionMethod,
+    pub column: String,
+}
+impl std::str::FromStr for PartitionBy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (method, column) = s
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(|| Error::MigrationError(format!("Malformed partition_by '{s}'")))?;
+        let column = column.trim();
+        if column.is_empty() {
+            return Err(Error::MigrationError(format!(
+                "Malformed partition_by '{s}'"
+            )));
+        }
+        Ok(PartitionBy {
+            method: method.trim().parse()?,
+            column: column.to_string(),
+        })
+    }
 }
 
 /// SqlType which may not yet be known.
@@ -352,6 +685,11 @@ fn is_known(&self) -> bool {
             DeferredSqlType::Deferred(_) => false,
         }
     }
+    fn fold_identifiers(&mut self, case: IdentifierCase) {
+        if let DeferredSqlType::Deferred(key) = self {
+            key.fold_identifiers(case);
+        }
+    }
 }
 /// Compare, with Known and KnownId being identical if they contain the same type.
 impl PartialEq<DeferredSqlType> for DeferredSqlType {
@@ -412,6 +750,39 @@ pub fn column_name(&self) -> &str {
     }
 }
 
+/// Action taken on the rows referring to a row when it is deleted.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDelete {
+    /// Delete the referring rows too.
+    Cascade,
+    /// Set the referring column to null.
+    SetNull,
+    /// Prevent the deletion while there are referring rows.
+    Restrict,
+}
+impl OnDelete {
+    /// The SQL for this action, as used in an `ON DELETE` clause.
+    pub fn sql(&self) -> &'static str {
+        match self {
+            OnDelete::Cascade => "CASCADE",
+            OnDelete::SetNull => "SET NULL",
+            OnDelete::Restrict => "RESTRICT",
+        }
+    }
+}
+impl std::str::FromStr for OnDelete {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cascade" => Ok(OnDelete::Cascade),
+            "set_null" => Ok(OnDelete::SetNull),
+            "restrict" => Ok(OnDelete::Restrict),
+            _ => Err(Error::UnknownEnumVariant(s.to_string())),
+        }
+    }
+}
+
 /// Abstract representation of a database column schema.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct AColumn {
@@ -433,6 +804,20 @@ pub struct AColumn {
     /// Whether this column refers to another column.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     reference: Option<ARef>,
+    /// Action taken when the row this column refers to is deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on_delete: Option<OnDelete>,
+    /// Collation used to compare and order values of the column.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    collation: Option<String>,
+    /// Whether adding the column to an existing table backfills it in
+    /// steps, as with [`backfill_added_columns`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    backfill: bool,
+    /// Description of the column, from the doc comment of its field, on
+    /// backends which store comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
 }
 impl AColumn {
     /// Create new column.
@@ -456,6 +841,10 @@ pub fn new(
             unique,
             default,
             reference,
+            on_delete: None,
+            collation: None,
+            backfill: false,
+            comment: None,
         }
     }
     /// Simple column that is non-null, non-auto, non-pk, non-unique with no default
@@ -489,6 +878,38 @@ pub fn add_reference(&mut self, reference: &ARef) {
     pub fn remove_reference(&mut self) {
         self.reference = None;
     }
+    /// Returns the action taken when the row this column refers to is deleted.
+    pub fn on_delete(&self) -> Option<OnDelete> {
+        self.on_delete
+    }
+    /// Set the action taken when the row this column refers to is deleted.
+    pub fn set_on_delete(&mut self, on_delete: Option<OnDelete>) {
+        self.on_delete = on_delete;
+    }
+    /// Returns the collation used to compare and order values of the column.
+    pub fn collation(&self) -> Option<&str> {
+        self.collation.as_deref()
+    }
+    /// Set the collation used to compare and order values of the column.
+    pub fn set_collation(&mut self, collation: Option<String>) {
+        self.collation = collation;
+    }
+    /// Returns whether adding the column to an existing table backfills it in steps.
+    pub fn backfill(&self) -> bool {
+        self.backfill
+    }
+    /// Set whether adding the column to an existing table backfills it in steps.
+    pub fn set_backfill(&mut self, backfill: bool) {
+        self.backfill = backfill;
+    }
+    /// Returns the description of the column.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+    /// Set the description of the column.
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        self.comment = comment;
+    }
     /// Get the type identifier.
     pub fn typeid(&self) -> Result<TypeIdentifier> {
         match &self.sqltype {
@@ -544,6 +965,19 @@ fn resolve_reference_target(
     pub fn is_auto(&self) -> bool {
         self.auto
     }
+
+    fn fold_identifiers(&mut self, case: IdentifierCase) {
+        self.name = case.fold(&self.name).into_owned();
+        self.sqltype.fold_identifiers(case);
+        match &mut self.reference {
+            None => {}
+            Some(ARef::Literal(literal)) => {
+                literal.table_name = case.fold(&literal.table_name).into_owned();
+                literal.column_name = case.fold(&literal.column_name).into_owned();
+            }
+            Some(ARef::Deferred(sqltype)) => sqltype.fold_identifiers(case),
+        }
+    }
 }
 
 /// Create a table for the [crate::many::Many] relationship.
@@ -575,6 +1009,91 @@ pub fn create_many_table(
         col.add_reference(&ARef::Deferred(many_field_type));
     }
     table.add_column(col);
+    // Values are looked up by owner, and owners by value when one is
+    // deleted, so both are indexed. A value is related to an owner at
+    // most once unless the field allows duplicates.
+    table.indexes.push(AIndex {
+        column: "owner".to_string(),
+        extra_columns: vec!["has".to_string()],
+        unique: true,
+        concurrently: false,
+    });
+    table.indexes.push(AIndex {
+        column: "has".to_string(),
+        extra_columns: Vec::new(),
+        unique: false,
+        concurrently: false,
+    });
+    table
+}
+
+/// Create a table for the [crate::many::OrderedMany] relationship.
+/// Should not be used directly, except in tests.
+pub fn create_ordered_many_table(
+    main_table_name: &str,
+    many_field_name: &str,
+    many_field_type: DeferredSqlType,
+    main_table_pk_field_name: &str,
+    main_table_pk_field_type: DeferredSqlType,
+) -> ATable {
+    let mut table = create_many_table(
+        main_table_name,
+        many_field_name,
+        many_field_type,
+        main_table_pk_field_name,
+        main_table_pk_field_type,
+    );
+    table.add_column(AColumn::new_simple(
+        MANY_POSITION_COLUMN,
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+    ));
+    table
+}
+
+/// Create the history table of a model declared with `#[butane(audited)]`.
+/// Its `object_pk` column has no foreign key constraint, so that the
+/// history of an object remains after it is deleted.
+/// Should not be used directly, except in tests.
+pub fn create_history_table(
+    main_table_name: &str,
+    main_table_pk_field_type: DeferredSqlType,
+) -> ATable {
+    let mut table = ATable::new(format!("{main_table_name}{HISTORY_SUFFIX}"));
+    table.add_column(AColumn::new(
+        "id",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+        false, // nullable
+        true,  // pk
+        true,  // auto
+        false, // unique
+        None,  // default
+        None,  // reference
+    ));
+    table.add_column(AColumn::new_simple("object_pk", main_table_pk_field_type));
+    let text = DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text));
+    table.add_column(AColumn::new_simple("op", text.clone()));
+    for name in ["old_values", "new_values", "actor"] {
+        table.add_column(AColumn::new(
+            name,
+            text.clone(),
+            true,  // nullable
+            false, // pk
+            false, // auto
+            false, // unique
+            None,  // default
+            None,  // reference
+        ));
+    }
+    table.add_column(AColumn::new_simple(
+        "changed_at",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+    ));
+    table.indexes.push(AIndex {
+        column: "object_pk".to_string(),
+        extra_columns: Vec::new(),
+        unique: false,
+        concurrently: false,
+    });
     table
 }
 
@@ -591,34 +1110,145 @@ pub enum Operation {
     RemoveTableConstraints(ATable),
     /// Remove named table.
     RemoveTable(String),
+    /// Remove an index from a table.
+    RemoveIndex(String, AIndex),
+    /// Rename a table from the first name to the second.
+    RenameTable(String, String),
+    /// Rename a table column from the second name to the third.
+    RenameColumn(String, String, String),
     /// Add a table column.
     AddColumn(String, AColumn),
+    /// Set the NULL values of a table column to its default, in batches
+    /// where the backend supports it.
+    BackfillColumn(String, AColumn),
     /// Remove a table column.
     RemoveColumn(String, String),
     /// Change a table columns type.
     ChangeColumn(String, AColumn, AColumn),
+    /// Add an index to a table.
+    AddIndex(String, AIndex),
     /// Add table constraints referring to other tables, if the backend supports it.
     AddTableConstraints(ATable),
+    /// Set or remove the comment describing a table, if the backend supports it.
+    SetTableComment(String, Option<String>),
+    /// Set or remove the comment describing the second named column of a
+    /// table, if the backend supports it.
+    SetColumnComment(String, String, Option<String>),
+}
+
+impl Operation {
+    /// The name of the table this operation applies to. For
+    /// [`RenameTable`](Operation::RenameTable), this is the original name.
+    pub fn table_name(&self) -> &str {
+        use Operation::*;
+        match self {
+            AddTable(table)
+            | AddTableIfNotExists(table)
+            | RemoveTableConstraints(table)
+            | AddTableConstraints(table) => &table.name,
+            RemoveTable(name)
+            | RenameTable(name, _)
+            | RenameColumn(name, _, _)
+            | AddColumn(name, _)
+            | BackfillColumn(name, _)
+            | RemoveColumn(name, _)
+            | ChangeColumn(name, _, _)
+            | AddIndex(name, _)
+            | RemoveIndex(name, _)
+            | SetTableComment(name, _)
+            | SetColumnComment(name, _, _) => name,
+        }
+    }
+
+    /// Whether this operation may lose data: removing a table or
+    /// column, or changing the type of a column. Removing a view does
+    /// not lose data, but cannot be told apart from removing a table here.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            Operation::RemoveTable(_) | Operation::RemoveColumn(_, _) => true,
+            Operation::ChangeColumn(_, old, new) => old.typeid().ok() != new.typeid().ok(),
+            _ => false,
+        }
+    }
 }
 
 /// Determine the operations necessary to move the database schema from `old` to `new`.
+///
+/// When the [`IdentifierCase`] policy changes, tables and columns whose
+/// names differ only by case are renamed rather than removed and
+/// re-added, which allows reconciling an existing schema with the new
+/// policy.
+///
+/// For example, to find operations which may lose data:
+/// ```
+/// # use butane_core::migrations::adb::*;
+/// # use butane_core::SqlType;
+/// let mut old = ADB::new();
+/// let mut table = ATable::new("Post".to_string());
+/// table.add_column(AColumn::new_simple(
+///     "title",
+///     DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+/// ));
+/// old.replace_table(table);
+///
+/// let ops = diff(&old, &ADB::new());
+/// let destructive: Vec<&str> = ops
+///     .iter()
+///     .filter(|op| op.is_destructive())
+///     .map(|op| op.table_name())
+///     .collect();
+/// assert_eq!(destructive, ["Post"]);
+/// ```
 pub fn diff(old: &ADB, new: &ADB) -> Vec<Operation> {
     let mut ops: Vec<Operation> = Vec::new();
+    let old = &rename_case_changes(old, new, &mut ops);
     let new_names: BTreeSet<&String> = new.tables.keys().collect();
     let old_names: BTreeSet<&String> = old.tables.keys().collect();
 
+    // Views cannot be altered, so any change replaces them
+    let replaced: BTreeSet<&String> = new_names
+        .intersection(&old_names)
+        .filter(|name| {
+            let old_table = old.tables.get(**name).expect("no table");
+            let new_table = new.tables.get(**name).expect("no table");
+            (old_table.is_view() || new_table.is_view()) && old_table != new_table
+        })
+        .copied()
+        .collect();
+    let new_tables: BTreeSet<&String> = new_names
+        .difference(&old_names)
+        .copied()
+        .chain(replaced.iter().copied())
+        .collect();
+    let removed_tables: BTreeSet<&String> = old_names
+        .difference(&new_names)
+        .copied()
+        .chain(replaced.iter().copied())
+        .collect();
+    let is_new_view = |name: &str| new.tables.get(name).expect("no table").is_view();
+    let is_old_view = |name: &str| old.tables.get(name).expect("no table").is_view();
+
+    // Views may select from tables, so are removed before and added after them
+    for removed in removed_tables.iter().filter(|name| is_old_view(name)) {
+        ops.push(Operation::RemoveTable((*removed).to_string()));
+    }
+
     // Add new tables
-    let new_tables = new_names.difference(&old_names);
-    for added in new_tables.clone() {
+    for added in new_tables.iter().filter(|name| !is_new_view(name)) {
         let added: &str = added.as_ref();
-        ops.push(Operation::AddTable(
-            new.tables.get(added).expect("no table").clone(),
-        ));
+        let table = new.tables.get(added).expect("no table");
+        ops.push(Operation::AddTable(table.clone()));
+        for index in &table.indexes {
+            ops.push(Operation::AddIndex(table.name.clone(), index.clone()));
+        }
     }
 
     // Remove tables
-    let removed_tables = old_names.difference(&new_names);
-    for removed in removed_tables.clone() {
+    let removed_tables: Vec<&&String> = removed_tables
+        .iter()
+        .filter(|name| !is_old_view(name))
+        .collect();
+    for removed in &removed_tables {
         let removed: &str = removed.as_ref();
         let table = old.tables.get(removed).expect("no table").clone();
         ops.push(Operation::RemoveTableConstraints(table));
@@ -629,28 +1259,141 @@ pub fn diff(old: &ADB, new: &ADB) -> Vec<Operation> {
 
     // Change existing tables
     for table in new_names.intersection(&old_names) {
+        if replaced.contains(table) {
+            continue;
+        }
         let table: &str = table.as_ref();
         ops.append(&mut diff_table(
             old.tables.get(table).expect("no table"),
             new.tables.get(table).expect("no table"),
         ));
     }
-    for added in new_tables {
+    for added in &new_tables {
         let added: &str = added.as_ref();
         let table = new.tables.get(added).expect("no table");
         if table.columns.iter().any(|x| x.reference.is_some()) {
             ops.push(Operation::AddTableConstraints(table.clone()));
         }
     }
+
+    for added in new_tables.iter().filter(|name| is_new_view(name)) {
+        let added: &str = added.as_ref();
+        ops.push(Operation::AddTable(
+            new.tables.get(added).expect("no table").clone(),
+        ));
+    }
     ops
 }
 
+/// Adds rename operations to `ops` for tables and columns in `old` whose
+/// names differ from those in `new` only by case, and returns `old`
+/// with those renames applied. Nothing is renamed while both preserve
+/// the case of names, as the names then differ deliberately.
+fn rename_case_changes(old: &ADB, new: &ADB, ops: &mut Vec<Operation>) -> ADB {
+    let mut renamed = old.clone();
+    if old.identifier_case.is_preserve() && new.identifier_case.is_preserve() {
+        return renamed;
+    }
+    let mut apply = |op: Operation, renamed: &mut ADB| {
+        renamed.transform_with(op.clone());
+        ops.push(op);
+    };
+    let table_renames = case_changes(old.tables.keys(), new.tables.keys());
+    for (from, to) in table_renames {
+        apply(Operation::RenameTable(from, to), &mut renamed);
+    }
+    for table in new.tables() {
+        let Some(old_table) = renamed.tables.get(&table.name) else {
+            continue;
+        };
+        let column_renames = case_changes(
+            old_table.columns.iter().map(|c| &c.name),
+            table.columns.iter().map(|c| &c.name),
+        );
+        for (from, to) in column_renames {
+            apply(
+                Operation::RenameColumn(table.name.clone(), from, to),
+                &mut renamed,
+            );
+        }
+    }
+    renamed
+}
+
+/// Pairs up names present only in `old` with names present only in
+/// `new` which are equal ignoring case.
+fn case_changes<'a>(
+    old: impl Iterator<Item = &'a String>,
+    new: impl Iterator<Item = &'a String>,
+) -> Vec<(String, String)> {
+    let old: BTreeSet<&String> = old.collect();
+    let new: BTreeSet<&String> = new.collect();
+    let mut added: Vec<&String> = new.difference(&old).copied().collect();
+    old.difference(&new)
+        .filter_map(|removed| {
+            let idx = added
+                .iter()
+                .position(|a| a.to_lowercase() == removed.to_lowercase())?;
+            Some(((*removed).clone(), added.remove(idx).clone()))
+        })
+        .collect()
+}
+
+/// Replaces each addition of a NOT NULL column in `ops` with three
+/// steps which are safe on a populated table: the column is added as
+/// nullable, existing rows are backfilled with its default, and then it
+/// is made NOT NULL.
+///
+/// This avoids failing, or holding a lock on a large table for the
+/// duration of a rewrite, on backends which cannot add a NOT NULL column
+/// to existing rows directly.
+pub fn backfill_added_columns(ops: Vec<Operation>) -> Vec<Operation> {
+    backfill_added_columns_where(ops, |_| true)
+}
+
+/// Like [`backfill_added_columns`], but only for the columns marked to
+/// be backfilled, e.g. with `#[butane(backfill)]`. Used when creating
+/// every migration.
+pub fn backfill_marked_columns(ops: Vec<Operation>) -> Vec<Operation> {
+    backfill_added_columns_where(ops, AColumn::backfill)
+}
+
+fn backfill_added_columns_where(
+    ops: Vec<Operation>,
+    predicate: impl Fn(&AColumn) -> bool,
+) -> Vec<Operation> {
+    let mut result = Vec::with_capacity(ops.len());
+    for op in ops {
+        match op {
+            Operation::AddColumn(table, col) if !col.nullable && !col.pk && predicate(&col) => {
+                let mut nullable = col.clone();
+                nullable.nullable = true;
+                result.push(Operation::AddColumn(table.clone(), nullable.clone()));
+                result.push(Operation::BackfillColumn(table.clone(), col.clone()));
+                result.push(Operation::ChangeColumn(table, nullable, col));
+            }
+            op => result.push(op),
+        }
+    }
+    result
+}
+
 fn col_by_name<'a>(columns: &'a [AColumn], name: &str) -> Option<&'a AColumn> {
     columns.iter().find(|c| c.name == name)
 }
 
 fn diff_table(old: &ATable, new: &ATable) -> Vec<Operation> {
     let mut ops: Vec<Operation> = Vec::new();
+    // Whether an index is built concurrently only matters when it is
+    // added or removed
+    let same_index = |a: &AIndex, b: &AIndex| a.same_columns(b) && a.unique == b.unique;
+
+    // Remove indexes, before any of their columns
+    for index in &old.indexes {
+        if !new.indexes.iter().any(|other| same_index(index, other)) {
+            ops.push(Operation::RemoveIndex(old.name.clone(), index.clone()));
+        }
+    }
     let new_names: BTreeSet<&String> = new.columns.iter().map(|c| &c.name).collect();
     let old_names: BTreeSet<&String> = old.columns.iter().map(|c| &c.name).collect();
 
@@ -677,13 +1420,37 @@ fn diff_table(old: &ATable, new: &ATable) -> Vec<Operation> {
         let colname: &str = colname.as_ref();
         let col = col_by_name(&new.columns, colname).unwrap();
         let old_col = col_by_name(&old.columns, colname).unwrap();
-        if col == old_col {
-            continue;
+        // Whether a column is backfilled only matters when it is added,
+        // and its comment is set without changing the column
+        let mut unchanged = old_col.clone();
+        unchanged.backfill = col.backfill;
+        unchanged.comment.clone_from(&col.comment);
+        if col != &unchanged {
+            ops.push(Operation::ChangeColumn(
+                new.name.clone(),
+                old_col.clone(),
+                col.clone(),
+            ));
+        }
+        if col.comment != old_col.comment {
+            ops.push(Operation::SetColumnComment(
+                new.name.clone(),
+                col.name.clone(),
+                col.comment.clone(),
+            ));
+        }
+    }
+
+    // Add indexes
+    for index in &new.indexes {
+        if !old.indexes.iter().any(|other| same_index(index, other)) {
+            ops.push(Operation::AddIndex(new.name.clone(), index.clone()));
         }
-        ops.push(Operation::ChangeColumn(
+    }
+    if new.comment != old.comment {
+        ops.push(Operation::SetTableComment(
             new.name.clone(),
-            old_col.clone(),
-            col.clone(),
+            new.comment.clone(),
         ));
     }
     ops
@@ -4,12 +4,15 @@
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
+#[cfg(not(target_arch = "wasm32"))]
 use fs2::FileExt;
 use serde::{Deserialize, Serialize};
 
-use super::adb::{ATable, DeferredSqlType, TypeKey, ADB};
+use super::adb::{ATable, DeferredSqlType, IdentifierCase, TypeKey, ADB};
 use super::fs::{Filesystem, OsFilesystem};
-use super::{Migration, MigrationMut, Migrations, MigrationsMut};
+use super::{
+    HookStage, Migration, MigrationMut, Migrations, MigrationsMut, DEFAULT_MIGRATIONS_TABLE,
+};
 use crate::{Error, Result};
 
 type SqlTypeMap = BTreeMap<TypeKey, DeferredSqlType>;
@@ -29,6 +32,21 @@ struct MigrationInfo {
     table_bases: BTreeMap<String, String>,
     /// List of backends supported by this migration.
     backends: Vec<String>,
+    /// The policy applied to the case of table and column names.
+    #[serde(default, skip_serializing_if = "IdentifierCase::is_preserve")]
+    identifier_case: IdentifierCase,
+    /// A mapping of backend name to commands run immediately before
+    /// the migration is applied.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    before_apply: BTreeMap<String, String>,
+    /// A mapping of backend name to commands run immediately after
+    /// the migration is applied.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    after_apply: BTreeMap<String, String>,
+    /// The table recording which migrations have been applied, if not
+    /// the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    migrations_table: Option<String>,
 }
 impl MigrationInfo {
     fn new() -> Self {
@@ -36,6 +54,17 @@ fn new() -> Self {
             from_name: None,
             table_bases: BTreeMap::new(),
             backends: Vec::new(),
+            identifier_case: IdentifierCase::Preserve,
+            before_apply: BTreeMap::new(),
+            after_apply: BTreeMap::new(),
+            migrations_table: None,
+        }
+    }
+
+    fn hooks_mut(&mut self, stage: HookStage) -> &mut BTreeMap<String, String> {
+        match stage {
+            HookStage::Before => &mut self.before_apply,
+            HookStage::After => &mut self.after_apply,
         }
     }
 }
@@ -148,7 +177,9 @@ fn lock_shared(&self) -> Result<MigrationLock> {
     }
 
     /// Delete all of the files except info.json which is recreated
-    /// with only `from_name` set to allow migration series traversal.
+    /// with only `from_name` set to allow migration series traversal,
+    /// `identifier_case` to keep generating the same names, and the hooks
+    /// and `migrations_table` which cannot be regenerated.
     pub fn delete_db(&self) -> Result<()> {
         let entries = self.fs.list_dir(&self.root)?;
         for entry in entries {
@@ -162,6 +193,10 @@ pub fn delete_db(&self) -> Result<()> {
                         let info = self.info()?;
                         let info = MigrationInfo {
                             from_name: info.from_name,
+                            identifier_case: info.identifier_case,
+                            before_apply: info.before_apply,
+                            after_apply: info.after_apply,
+                            migrations_table: info.migrations_table,
                             ..Default::default()
                         };
                         self.write_info(&info)?;
@@ -239,6 +274,21 @@ fn remove_sql(&mut self, backend_name: &str) -> Result<()> {
         Ok(())
     }
 
+    fn set_hook_sql(
+        &mut self,
+        backend_name: &str,
+        stage: HookStage,
+        sql: Option<&str>,
+    ) -> Result<()> {
+        let mut info = self.info()?;
+        let hooks = info.hooks_mut(stage);
+        match sql {
+            Some(sql) => hooks.insert(backend_name.to_string(), sql.to_string()),
+            None => hooks.remove(backend_name),
+        };
+        self.write_info(&info)
+    }
+
     fn add_type(&mut self, key: TypeKey, sqltype: DeferredSqlType) -> Result<()> {
         let _lock = self.lock_exclusive();
         let typefile = self.root.join(TYPES_FILENAME);
@@ -267,6 +317,18 @@ fn set_migration_from(&mut self, prev: Option<String>) -> Result<()> {
         info.from_name = prev;
         self.write_info(&info)
     }
+
+    fn set_identifier_case(&mut self, case: IdentifierCase) -> Result<()> {
+        let mut info = self.info()?;
+        info.identifier_case = case;
+        self.write_info(&info)
+    }
+
+    fn set_migrations_table(&mut self, name: &str) -> Result<()> {
+        let mut info = self.info()?;
+        info.migrations_table = (name != DEFAULT_MIGRATIONS_TABLE).then(|| name.to_string());
+        self.write_info(&info)
+    }
 }
 
 impl Migration for FsMigration {
@@ -274,8 +336,8 @@ fn db(&self) -> Result<ADB> {
         self.ensure_dir()?;
         let _lock = self.lock_shared()?;
         let mut db = ADB::new();
-        let table_bases = self.info()?.table_bases;
-        for (table_name, migration_name) in table_bases {
+        let info = self.info()?;
+        for (table_name, migration_name) in info.table_bases {
             let mut filename = PathBuf::from(
                 self.root
                     .parent()
@@ -307,10 +369,22 @@ fn db(&self) -> Result<ADB> {
                 }
             }
         }
+        db.set_identifier_case(info.identifier_case);
         db.resolve_types()?;
         Ok(db)
     }
 
+    fn identifier_case(&self) -> Result<IdentifierCase> {
+        Ok(self.info()?.identifier_case)
+    }
+
+    fn migrations_table(&self) -> Result<String> {
+        Ok(self
+            .info()?
+            .migrations_table
+            .unwrap_or_else(|| DEFAULT_MIGRATIONS_TABLE.to_string()))
+    }
+
     fn migration_from(&self) -> Result<Option<Cow<'_, str>>> {
         Ok(self.info()?.from_name.map(Cow::from))
     }
@@ -331,6 +405,10 @@ fn down_sql(&self, backend_name: &str) -> Result<Option<String>> {
     fn sql_backends(&self) -> Result<Vec<String>> {
         Ok(self.info()?.backends)
     }
+
+    fn hook_sql(&self, backend_name: &str, stage: HookStage) -> Result<Option<String>> {
+        Ok(self.info()?.hooks_mut(stage).remove(backend_name))
+    }
 }
 
 impl PartialEq for FsMigration {
@@ -493,19 +571,24 @@ fn delete_migrations(&mut self) -> Result<()> {
     }
 }
 
+// Files are not locked on wasm, which has no file locks and no other
+// processes to share the migrations with.
 #[derive(Debug)]
 struct MigrationLock {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
     file: File,
 }
 impl MigrationLock {
     fn new_exclusive(path: &Path) -> Result<Self> {
         let file = Self::get_file(path)?;
+        #[cfg(not(target_arch = "wasm32"))]
         file.lock_exclusive()?;
         Ok(MigrationLock { file })
     }
 
     fn new_shared(path: &Path) -> Result<Self> {
         let file = Self::get_file(path)?;
+        #[cfg(not(target_arch = "wasm32"))]
         fs2::FileExt::lock_shared(&file)?;
         Ok(MigrationLock { file })
     }
@@ -521,6 +604,7 @@ fn get_file(path: &Path) -> Result<File> {
 }
 impl Drop for MigrationLock {
     fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
         fs2::FileExt::unlock(&self.file).unwrap();
     }
 }
@@ -3,8 +3,10 @@
 
 use serde::{Deserialize, Serialize};
 
-use super::adb::{ATable, DeferredSqlType, TypeKey, ADB};
-use super::{Migration, MigrationMut, Migrations, MigrationsMut};
+use super::adb::{ATable, DeferredSqlType, IdentifierCase, TypeKey, ADB};
+use super::{
+    HookStage, Migration, MigrationMut, Migrations, MigrationsMut, DEFAULT_MIGRATIONS_TABLE,
+};
 
 use crate::Result;
 
@@ -16,6 +18,12 @@ pub struct MemMigration {
     from: Option<String>,
     up: BTreeMap<String, String>,
     down: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    before_apply: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    after_apply: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    migrations_table: Option<String>,
 }
 
 impl MemMigration {
@@ -26,6 +34,16 @@ fn new(name: String) -> Self {
             from: None,
             up: BTreeMap::new(),
             down: BTreeMap::new(),
+            before_apply: BTreeMap::new(),
+            after_apply: BTreeMap::new(),
+            migrations_table: None,
+        }
+    }
+
+    fn hooks(&self, stage: HookStage) -> &BTreeMap<String, String> {
+        match stage {
+            HookStage::Before => &self.before_apply,
+            HookStage::After => &self.after_apply,
         }
     }
 }
@@ -37,6 +55,10 @@ fn db(&self) -> Result<ADB> {
         Ok(ret)
     }
 
+    fn identifier_case(&self) -> Result<IdentifierCase> {
+        Ok(self.db.identifier_case())
+    }
+
     fn migration_from(&self) -> Result<Option<Cow<'_, str>>> {
         Ok(self.from.as_ref().map(Cow::from))
     }
@@ -45,6 +67,13 @@ fn name(&self) -> Cow<'_, str> {
         Cow::from(&self.name)
     }
 
+    fn migrations_table(&self) -> Result<String> {
+        Ok(self
+            .migrations_table
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MIGRATIONS_TABLE.to_string()))
+    }
+
     /// The backend-specific commands to apply this migration.
     fn up_sql(&self, backend_name: &str) -> Result<Option<String>> {
         Ok(self.up.get(backend_name).map(|s| s.to_string()))
@@ -57,6 +86,10 @@ fn down_sql(&self, backend_name: &str) -> Result<Option<String>> {
     fn sql_backends(&self) -> Result<Vec<String>> {
         Ok(self.up.keys().map(|k| k.to_string()).collect())
     }
+
+    fn hook_sql(&self, backend_name: &str, stage: HookStage) -> Result<Option<String>> {
+        Ok(self.hooks(stage).get(backend_name).cloned())
+    }
 }
 impl PartialEq for MemMigration {
     fn eq(&self, other: &Self) -> bool {
@@ -90,6 +123,22 @@ fn remove_sql(&mut self, backend_name: &str) -> Result<()> {
         self.down.remove(backend_name);
         Ok(())
     }
+    fn set_hook_sql(
+        &mut self,
+        backend_name: &str,
+        stage: HookStage,
+        sql: Option<&str>,
+    ) -> Result<()> {
+        let hooks = match stage {
+            HookStage::Before => &mut self.before_apply,
+            HookStage::After => &mut self.after_apply,
+        };
+        match sql {
+            Some(sql) => hooks.insert(backend_name.to_string(), sql.to_string()),
+            None => hooks.remove(backend_name),
+        };
+        Ok(())
+    }
     fn add_type(&mut self, key: TypeKey, sqltype: DeferredSqlType) -> Result<()> {
         self.db.add_type(key, sqltype);
         self.db.resolve_types()?;
@@ -99,6 +148,14 @@ fn set_migration_from(&mut self, prev: Option<String>) -> Result<()> {
         self.from = prev;
         Ok(())
     }
+    fn set_identifier_case(&mut self, case: IdentifierCase) -> Result<()> {
+        self.db.set_identifier_case(case);
+        Ok(())
+    }
+    fn set_migrations_table(&mut self, name: &str) -> Result<()> {
+        self.migrations_table = (name != DEFAULT_MIGRATIONS_TABLE).then(|| name.to_string());
+        Ok(())
+    }
 }
 
 /// A collection of migrations stored in memory.
@@ -1,11 +1,17 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::adb::{ATable, DeferredSqlType, TypeKey, ADB};
-use super::ButaneMigration;
+use serde::{Deserialize, Serialize};
+
+use super::adb::{ATable, DeferredSqlType, IdentifierCase, Operation, TypeKey, ADB};
+use super::{
+    migrations_table, AppliedMigration, ButaneMigration, BATCHED_MARKER, OUTSIDE_TRANSACTION_MARKER,
+};
 use crate::db::{BackendConnection, ConnectionMethods};
 use crate::query::{BoolExpr, Expr};
-use crate::{sqlval::ToSql, DataObject, DataResult, Error, Result};
+use crate::{sqlval::ToSql, DataObject, Error, Result};
 
 /// Type representing a database migration. A migration describes how
 /// to bring the database from state A to state B. In general, the
@@ -18,6 +24,9 @@ pub trait Migration: Debug + PartialEq {
     /// Retrieves the full abstract database state describing all tables
     fn db(&self) -> Result<ADB>;
 
+    /// The policy applied to the case of table and column names in this migration.
+    fn identifier_case(&self) -> Result<IdentifierCase>;
+
     /// Get the name of the migration before this one (if any).
     fn migration_from(&self) -> Result<Option<Cow<'_, str>>>
     where
@@ -26,6 +35,10 @@ fn migration_from(&self) -> Result<Option<Cow<'_, str>>>
     /// The name of this migration.
     fn name(&self) -> Cow<'_, str>;
 
+    /// The name of the table recording which migrations have been applied,
+    /// [`DEFAULT_MIGRATIONS_TABLE`](super::DEFAULT_MIGRATIONS_TABLE) unless set otherwise.
+    fn migrations_table(&self) -> Result<String>;
+
     /// The backend-specific commands to apply this migration.
     fn up_sql(&self, backend_name: &str) -> Result<Option<String>>;
 
@@ -35,29 +48,92 @@ fn migration_from(&self) -> Result<Option<Cow<'_, str>>>
     /// The names of the backends this migration has sql for.
     fn sql_backends(&self) -> Result<Vec<String>>;
 
+    /// The backend-specific commands run at `stage` of applying this migration, if any.
+    fn hook_sql(&self, backend_name: &str, stage: HookStage) -> Result<Option<String>>;
+
     /// Apply the migration to a database connection. The connection
     /// must be for the same type of database as this and the database
-    /// must be in the state of the migration prior to this one
+    /// must be in the state of the migration prior to this one.
+    /// Any hooks are run in the same transaction as the migration.
+    /// Statements which cannot run in a transaction, marked with
+    /// [`OUTSIDE_TRANSACTION_MARKER`] or [`BATCHED_MARKER`], are run in
+    /// their place, after the statements before them are committed, and
+    /// the migration is recorded with the statements after the last one.
     fn apply(&self, conn: &mut impl BackendConnection) -> Result<()> {
+        self.prepare_migrations_table(conn)?;
         let backend_name = conn.backend_name();
-        let tx = conn.transaction()?;
         let sql = self
             .up_sql(backend_name)?
             .ok_or_else(|| Error::UnknownBackend(backend_name.to_string()))?;
-        tx.execute(&sql)?;
-        self.mark_applied(&tx)?;
-        tx.commit()
+        let steps = split_steps(&sql);
+        let last = steps.len() - 1;
+        for (i, step) in steps.into_iter().enumerate() {
+            let Step::Transaction(sql) = step else {
+                step.run(conn)?;
+                continue;
+            };
+            let tx = conn.transaction()?;
+            if i == 0 {
+                if let Some(hook) = self.hook_sql(backend_name, HookStage::Before)? {
+                    tx.execute(&hook)?;
+                }
+            }
+            tx.execute(&sql)?;
+            if i == last {
+                if let Some(hook) = self.hook_sql(backend_name, HookStage::After)? {
+                    tx.execute(&hook)?;
+                }
+                self.mark_applied(&tx)?;
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Create the table recording which migrations have been applied
+    /// if it does not exist, or add the columns missing from one created
+    /// by an earlier version of butane. Called by [`apply`](Migration::apply).
+    fn prepare_migrations_table(&self, conn: &impl BackendConnection) -> Result<()> {
+        let name = self.migrations_table()?;
+        let table = migrations_table(&name);
+        let backend = conn.backend();
+        if !conn.has_table(&name)? {
+            let sql = backend
+                .create_migration_sql(&ADB::new(), vec![Operation::AddTableIfNotExists(table)])?;
+            return conn.execute(&sql);
+        }
+        if conn
+            .query(&name, AppliedMigration::COLUMNS, None, Some(0), None, None)
+            .is_err()
+        {
+            let column = table
+                .column("applied_at")
+                .cloned()
+                .ok_or_else(|| Error::MigrationError("no applied_at column".to_string()))?;
+            let mut current = ADB::new();
+            let mut old_table = table;
+            old_table.remove_column("applied_at");
+            current.replace_table(old_table);
+            let sql =
+                backend.create_migration_sql(&current, vec![Operation::AddColumn(name, column)])?;
+            conn.execute(&sql)?;
+        }
+        Ok(())
     }
 
     /// Mark the migration as being applied without doing any
     /// work. Use carefully -- the caller must ensure that the
     /// database schema already matches that expected by this
-    /// migration.
+    /// migration, and that the migrations table has been prepared
+    /// with [`prepare_migrations_table`](Migration::prepare_migrations_table).
     fn mark_applied(&self, conn: &impl ConnectionMethods) -> Result<()> {
+        let applied_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
         conn.insert_only(
-            ButaneMigration::TABLE,
-            ButaneMigration::COLUMNS,
-            &[self.name().as_ref().to_sql_ref()],
+            &self.migrations_table()?,
+            AppliedMigration::COLUMNS,
+            &[self.name().as_ref().to_sql_ref(), applied_at.to_sql_ref()],
         )
     }
 
@@ -67,18 +143,83 @@ fn mark_applied(&self, conn: &impl ConnectionMethods) -> Result<()> {
     /// to the database.
     fn downgrade(&self, conn: &mut impl BackendConnection) -> Result<()> {
         let backend_name = conn.backend_name();
-        let tx = conn.transaction()?;
         let sql = self
             .down_sql(backend_name)?
             .ok_or_else(|| Error::UnknownBackend(backend_name.to_string()))?;
-        tx.execute(&sql)?;
-        let nameval = self.name().as_ref().to_sql();
-        tx.delete_where(
-            ButaneMigration::TABLE,
-            BoolExpr::Eq(ButaneMigration::PKCOL, Expr::Val(nameval)),
-        )?;
-        tx.commit()
+        let steps = split_steps(&sql);
+        let last = steps.len() - 1;
+        for (i, step) in steps.into_iter().enumerate() {
+            let Step::Transaction(sql) = step else {
+                step.run(conn)?;
+                continue;
+            };
+            let tx = conn.transaction()?;
+            tx.execute(&sql)?;
+            if i == last {
+                let nameval = self.name().as_ref().to_sql();
+                tx.delete_where(
+                    &self.migrations_table()?,
+                    BoolExpr::Eq(ButaneMigration::PKCOL, Expr::Val(nameval)),
+                )?;
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// A step of applying or undoing a migration.
+enum Step<'a> {
+    /// Statements run together in a transaction.
+    Transaction(Cow<'a, str>),
+    /// Marked with [`OUTSIDE_TRANSACTION_MARKER`], run once.
+    Once(&'a str),
+    /// Marked with [`BATCHED_MARKER`], run until it affects no rows.
+    Batched(&'a str),
+}
+
+impl Step<'_> {
+    /// Runs a step other than a transaction.
+    fn run(&self, conn: &impl BackendConnection) -> Result<()> {
+        match self {
+            Step::Transaction(_) => Err(Error::Internal(
+                "migration transaction run outside of one".to_string(),
+            )),
+            Step::Once(statement) => conn.execute(statement),
+            Step::Batched(statement) => {
+                while conn.execute_params(statement, &[])? > 0 {}
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Splits `sql` into the statements marked with
+/// [`OUTSIDE_TRANSACTION_MARKER`] or [`BATCHED_MARKER`] and the
+/// transactions of the statements between them, in order. The first
+/// and last steps are always transactions, which may be empty.
+fn split_steps<'a>(sql: &'a str) -> Vec<Step<'a>> {
+    if !sql.contains(OUTSIDE_TRANSACTION_MARKER) && !sql.contains(BATCHED_MARKER) {
+        return vec![Step::Transaction(Cow::Borrowed(sql))];
+    }
+    let mut steps = Vec::new();
+    let mut inside = Vec::new();
+    let mut lines = sql.lines();
+    while let Some(line) = lines.next() {
+        let step: fn(&'a str) -> Step<'a> = if line.trim() == OUTSIDE_TRANSACTION_MARKER {
+            Step::Once
+        } else if line.trim() == BATCHED_MARKER {
+            Step::Batched
+        } else {
+            inside.push(line);
+            continue;
+        };
+        steps.push(Step::Transaction(Cow::Owned(inside.join("\n"))));
+        inside.clear();
+        steps.extend(lines.next().map(step));
     }
+    steps.push(Step::Transaction(Cow::Owned(inside.join("\n"))));
+    steps
 }
 
 /// A migration which can be modified
@@ -106,9 +247,56 @@ pub trait MigrationMut: Migration {
     /// Remove the backend-specific commands to apply/undo this migration.
     fn remove_sql(&mut self, backend_name: &str) -> Result<()>;
 
+    /// Set the backend-specific commands run at `stage` of applying
+    /// this migration, or remove them if `sql` is `None`.
+    fn set_hook_sql(
+        &mut self,
+        backend_name: &str,
+        stage: HookStage,
+        sql: Option<&str>,
+    ) -> Result<()>;
+
     /// Adds a TypeKey -> SqlType mapping. Only meaningful on the special current migration.
     fn add_type(&mut self, key: TypeKey, sqltype: DeferredSqlType) -> Result<()>;
 
     /// Set the name of the migration before this one.
     fn set_migration_from(&mut self, prev: Option<String>) -> Result<()>;
+
+    /// Set the policy applied to the case of table and column names.
+    /// On the special current migration, this determines the names
+    /// generated for models.
+    fn set_identifier_case(&mut self, case: IdentifierCase) -> Result<()>;
+
+    /// Set the name of the table recording which migrations have been
+    /// applied, which may be qualified with a schema, e.g. `tooling.migrations`.
+    /// On the special current migration, this determines the table used
+    /// by a new series of migrations; later migrations keep the table of
+    /// the one they follow.
+    fn set_migrations_table(&mut self, name: &str) -> Result<()>;
+}
+
+/// When a migration hook runs, relative to the commands of its migration.
+///
+/// Hooks are commands such as disabling triggers or `ANALYZE` which are
+/// recorded with a migration but are not part of the schema change itself.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookStage {
+    /// Immediately before the migration is applied.
+    Before,
+    /// Immediately after the migration is applied.
+    After,
+}
+
+impl FromStr for HookStage {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "before" => Ok(HookStage::Before),
+            "after" => Ok(HookStage::After),
+            _ => Err(Error::MigrationError(format!(
+                "unknown hook stage '{s}', expected 'before' or 'after'"
+            ))),
+        }
+    }
 }
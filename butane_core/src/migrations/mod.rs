@@ -4,6 +4,7 @@
 #![allow(missing_docs)]
 
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
 use fallible_iterator::FallibleIterator;
@@ -19,7 +20,7 @@
 use adb::{AColumn, ATable, DeferredSqlType, Operation, TypeIdentifier, ADB};
 
 mod migration;
-pub use migration::{Migration, MigrationMut};
+pub use migration::{HookStage, Migration, MigrationMut};
 
 mod fs;
 
@@ -73,6 +74,108 @@ fn all_migrations(&self) -> Result<Vec<Self::M>> {
         Ok(accum.into_iter().rev().collect())
     }
 
+    /// The name of the table recording which migrations have been
+    /// applied, as set on the latest migration.
+    fn migrations_table(&self) -> Result<String> {
+        match self.latest() {
+            Some(m) => m.migrations_table(),
+            None => Ok(DEFAULT_MIGRATIONS_TABLE.to_string()),
+        }
+    }
+
+    /// Get migrations which have not yet been applied to the database,
+    /// in the order they would be applied. The same as
+    /// [`unapplied_migrations`](Migrations::unapplied_migrations).
+    fn pending(&self, conn: &impl ConnectionMethods) -> Result<Vec<Self::M>> {
+        self.unapplied_migrations(conn)
+    }
+
+    /// Whether any migrations have not yet been applied to the database.
+    fn has_pending(&self, conn: &impl ConnectionMethods) -> Result<bool> {
+        Ok(!self.unapplied_migrations(conn)?.is_empty())
+    }
+
+    /// Get the migrations recorded as applied to the database, with when
+    /// they were applied, in the order of this series. Migrations which are
+    /// not part of this series follow in the order they were recorded.
+    fn applied(&self, conn: &impl ConnectionMethods) -> Result<Vec<AppliedMigration>> {
+        let migrations_table = self.migrations_table()?;
+        if !conn.has_table(&migrations_table)? {
+            return Ok(Vec::new());
+        }
+        let rows = conn.query(
+            &migrations_table,
+            AppliedMigration::COLUMNS,
+            None,
+            None,
+            None,
+            None,
+        );
+        let mut applied: Vec<AppliedMigration> = match rows {
+            Ok(rows) => rows.mapped(AppliedMigration::from_row).collect()?,
+            // Tables created by earlier versions have no applied_at column
+            Err(_) => conn
+                .query(
+                    &migrations_table,
+                    ButaneMigration::COLUMNS,
+                    None,
+                    None,
+                    None,
+                    None,
+                )?
+                .mapped(|row| {
+                    Ok(AppliedMigration {
+                        name: ButaneMigration::from_row(row)?.name,
+                        applied_at: None,
+                    })
+                })
+                .collect()?,
+        };
+        let order: Vec<String> = self
+            .all_migrations()?
+            .iter()
+            .map(|m| m.name().to_string())
+            .collect();
+        applied.sort_by_key(|m| {
+            order
+                .iter()
+                .position(|name| *name == m.name)
+                .unwrap_or(order.len())
+        });
+        Ok(applied)
+    }
+
+    #[cfg(feature = "async")]
+    /// Get the migrations recorded as applied to the database. See [`applied`](Migrations::applied).
+    async fn applied_async(&self, conn: &mut ConnectionAsync) -> Result<Vec<AppliedMigration>>
+    where
+        Self: Send + 'static,
+    {
+        let m2 = self.clone();
+        conn.with_sync(move |conn| m2.applied(conn)).await
+    }
+
+    #[cfg(feature = "async")]
+    /// Get migrations which have not yet been applied to the database. See [`pending`](Migrations::pending).
+    async fn pending_async(&self, conn: &mut ConnectionAsync) -> Result<Vec<Self::M>>
+    where
+        Self: Send + 'static,
+        Self::M: Send + 'static,
+    {
+        let m2 = self.clone();
+        conn.with_sync(move |conn| m2.pending(conn)).await
+    }
+
+    #[cfg(feature = "async")]
+    /// Whether any migrations have not yet been applied to the database. See [`has_pending`](Migrations::has_pending).
+    async fn has_pending_async(&self, conn: &mut ConnectionAsync) -> Result<bool>
+    where
+        Self: Send + 'static,
+    {
+        let m2 = self.clone();
+        conn.with_sync(move |conn| m2.has_pending(conn)).await
+    }
+
     /// Get migrations which have not yet been applied to the database
     fn unapplied_migrations(&self, conn: &impl ConnectionMethods) -> Result<Vec<Self::M>> {
         match self.last_applied_migration(conn)? {
@@ -84,12 +187,13 @@ fn unapplied_migrations(&self, conn: &impl ConnectionMethods) -> Result<Vec<Self
     /// Get the last migration that has been applied to the database or None
     /// if no migrations have been applied
     fn last_applied_migration(&self, conn: &impl ConnectionMethods) -> Result<Option<Self::M>> {
-        if !conn.has_table(ButaneMigration::TABLE)? {
+        let migrations_table = self.migrations_table()?;
+        if !conn.has_table(&migrations_table)? {
             return Ok(None);
         }
         let migrations: Vec<ButaneMigration> = conn
             .query(
-                ButaneMigration::TABLE,
+                &migrations_table,
                 ButaneMigration::COLUMNS,
                 None,
                 None,
@@ -195,8 +299,9 @@ pub trait MigrationsMut: Migrations
     /// existence/application from the database. The database schema
     /// is not modified, nor is any other data removed. Use carefully.
     fn clear_migrations(&mut self, conn: &impl ConnectionMethods) -> Result<()> {
+        let migrations_table = self.migrations_table()?;
         self.delete_migrations()?;
-        conn.delete_where(ButaneMigration::TABLE, query::BoolExpr::True)?;
+        conn.delete_where(&migrations_table, query::BoolExpr::True)?;
         Ok(())
     }
 
@@ -213,6 +318,17 @@ fn clear_migrations(&mut self, conn: &impl ConnectionMethods) -> Result<()> {
     /// Clears the current state (as would be returned by the `current` method).
     fn clear_current(&mut self) -> Result<()>;
 
+    /// Whether the current state differs from the latest migration, i.e.
+    /// models have changed without a migration being created for them.
+    fn has_unmigrated_changes(&mut self) -> Result<bool> {
+        let from_db = match self.latest() {
+            Some(m) => m.db()?,
+            None => ADB::new(),
+        };
+        let to_db = self.current().db()?;
+        Ok(!adb::diff(&from_db, &to_db).is_empty())
+    }
+
     /// Create a migration `from` -> `current` named `name`. From may be None, in which
     /// case the migration is created from an empty database.
     /// Returns true if a migration was created, false if `from` and `current` represent identical states.
@@ -227,7 +343,8 @@ fn create_migration(
     }
 
     /// Create a migration `from` -> `to_db` named `name`. From may be None, in which
-    /// case the migration is created from an empty database.
+    /// case the migration is created from an empty database. Columns marked to be
+    /// backfilled are added as by [`adb::backfill_marked_columns`].
     /// Returns true if a migration was created, false if `from` and `current` represent identical states.
     fn create_migration_to(
         &mut self,
@@ -235,11 +352,40 @@ fn create_migration_to(
         name: &str,
         from: Option<&Self::M>,
         to_db: ADB,
+    ) -> Result<bool> {
+        self.create_migration_to_with(backends, name, from, to_db, adb::backfill_marked_columns)
+    }
+
+    /// Create a migration `from` -> `current` named `name`, adding NOT NULL
+    /// columns to existing tables in steps which are safe on populated
+    /// tables. See [`adb::backfill_added_columns`].
+    /// Returns true if a migration was created, false if `from` and `current` represent identical states.
+    fn create_backfill_migration(
+        &mut self,
+        backends: &NonEmpty<Box<dyn Backend>>,
+        name: &str,
+        from: Option<&Self::M>,
+    ) -> Result<bool> {
+        let to_db = self.current().db()?;
+        self.create_migration_to_with(backends, name, from, to_db, adb::backfill_added_columns)
+    }
+
+    /// Create a migration `from` -> `to_db` named `name`, with the
+    /// operations to apply it transformed by `plan`. From may be None, in
+    /// which case the migration is created from an empty database.
+    /// Returns true if a migration was created, false if `from` and `current` represent identical states.
+    fn create_migration_to_with(
+        &mut self,
+        backends: &NonEmpty<Box<dyn Backend>>,
+        name: &str,
+        from: Option<&Self::M>,
+        to_db: ADB,
+        plan: fn(Vec<Operation>) -> Vec<Operation>,
     ) -> Result<bool> {
         let empty_db = Ok(ADB::new());
         let from_none = from.is_none();
         let from_db = from.map_or(empty_db, |m| m.db())?;
-        let mut ops = adb::diff(&from_db, &to_db);
+        let mut ops = plan(adb::diff(&from_db, &to_db));
         if ops.is_empty() {
             return Ok(false);
         }
@@ -251,21 +397,43 @@ fn create_migration_to(
                 Operation::AddTable(table)
                 | Operation::AddTableConstraints(table)
                 | Operation::AddTableIfNotExists(table) => modified_tables.push(table.name.clone()),
-                Operation::AddColumn(table_name, _) => modified_tables.push(table_name.clone()),
-                Operation::RemoveColumn(table_name, _) => modified_tables.push(table_name.clone()),
+                Operation::AddColumn(table_name, _) | Operation::BackfillColumn(table_name, _) => {
+                    modified_tables.push(table_name.clone())
+                }
+                Operation::RemoveColumn(table_name, _)
+                | Operation::AddIndex(table_name, _)
+                | Operation::RemoveIndex(table_name, _) => modified_tables.push(table_name.clone()),
                 Operation::ChangeColumn(table_name, _, _) => {
                     modified_tables.push(table_name.clone())
                 }
+                Operation::RenameTable(_, to) => modified_tables.push(to.clone()),
+                Operation::RenameColumn(table_name, _, _) => {
+                    modified_tables.push(table_name.clone())
+                }
+                Operation::SetTableComment(table_name, _)
+                | Operation::SetColumnComment(table_name, _, _) => {
+                    modified_tables.push(table_name.clone())
+                }
                 Operation::RemoveTable(_) | Operation::RemoveTableConstraints(_) => {}
             }
         }
 
+        // The migrations table is kept for the whole series, so is only
+        // taken from the current migration when starting a new one.
+        let migrations_table_name = match from {
+            Some(from) => from.migrations_table()?,
+            None => self.current().migrations_table()?,
+        };
         if from_none {
             // This may be the first migration. Create the butane_migration table
-            ops.push(Operation::AddTableIfNotExists(migrations_table()));
+            ops.push(Operation::AddTableIfNotExists(migrations_table(
+                &migrations_table_name,
+            )));
         }
 
         let mut m = self.new_migration(name);
+        m.set_identifier_case(to_db.identifier_case())?;
+        m.set_migrations_table(&migrations_table_name)?;
         // Save the DB for use by other migrations from this one
         for table in to_db.tables() {
             if modified_tables.contains(&table.name) {
@@ -290,9 +458,28 @@ fn create_migration_to(
     }
 }
 
-/// Returns [`ATable`] describing the migration metadata.
-pub fn migrations_table() -> ATable {
-    let mut table = ATable::new("butane_migrations".to_string());
+/// Default name of the table recording which migrations have been applied.
+pub const DEFAULT_MIGRATIONS_TABLE: &str = "butane_migrations";
+
+/// Comment marking the statement on the following line of a migration's
+/// SQL as one which cannot run in a transaction, such as
+/// `CREATE INDEX CONCURRENTLY`. It is run in its place in the migration,
+/// after the statements before it are committed and before those after
+/// it are run in a new transaction, so must be idempotent for a failed
+/// migration to be retried.
+pub const OUTSIDE_TRANSACTION_MARKER: &str = "-- butane: outside transaction";
+
+/// Comment marking the statement on the following line of a migration's
+/// SQL as one which updates a bounded batch of rows, such as when
+/// backfilling a column. Like the statements marked with
+/// [`OUTSIDE_TRANSACTION_MARKER`], it is run in its place in the
+/// migration and must be idempotent. It is run repeatedly, each run
+/// committed on its own, until it affects no rows.
+pub const BATCHED_MARKER: &str = "-- butane: batched";
+
+/// Returns [`ATable`] describing the migration metadata, stored in the table `name`.
+pub fn migrations_table(name: &str) -> ATable {
+    let mut table = ATable::new(name.to_string());
     let col = AColumn::new(
         "name",
         DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
@@ -304,9 +491,46 @@ pub fn migrations_table() -> ATable {
         None,  // references
     );
     table.add_column(col);
+    // Seconds since the Unix epoch. Nullable as earlier versions did not record it.
+    let col = AColumn::new(
+        "applied_at",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+        true,  // nullable
+        false, // pk
+        false, // auto
+        false, // unique
+        None,  // default
+        None,  // references
+    );
+    table.add_column(col);
     table
 }
 
+/// A migration recorded as applied to a database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AppliedMigration {
+    /// The name of the migration.
+    pub name: String,
+    /// When the migration was applied, or `None` if it was recorded by a
+    /// version of butane which did not track this.
+    pub applied_at: Option<SystemTime>,
+}
+
+impl AppliedMigration {
+    const COLUMNS: &'static [Column] = &[
+        Column::new("name", SqlType::Text),
+        Column::new("applied_at", SqlType::BigInt),
+    ];
+
+    fn from_row(row: &dyn db::BackendRow) -> Result<Self> {
+        let applied_at: Option<i64> = FromSql::from_sql_ref(row.get(1, SqlType::BigInt)?)?;
+        Ok(AppliedMigration {
+            name: FromSql::from_sql_ref(row.get(0, SqlType::Text)?)?,
+            applied_at: applied_at.map(|secs| UNIX_EPOCH + Duration::from_secs(secs as u64)),
+        })
+    }
+}
+
 /// Create a `Migrations` from a filesystem location. The `#[model]`
 /// attribute will write migration information to a
 /// `butane/migrations` directory under the project directory.
@@ -317,6 +541,8 @@ pub fn from_root<P: AsRef<Path>>(path: P) -> FsMigrations {
 /// Copies the data in `from` to `to`.
 pub fn copy_migration(from: &impl Migration, to: &mut impl MigrationMut) -> Result<()> {
     to.set_migration_from(from.migration_from()?.map(|s| s.to_string()))?;
+    to.set_identifier_case(from.identifier_case()?)?;
+    to.set_migrations_table(&from.migrations_table()?)?;
     let db = from.db()?;
     for table in db.tables() {
         to.add_modified_table(table)?;
@@ -330,6 +556,11 @@ pub fn copy_migration(from: &impl Migration, to: &mut impl MigrationMut) -> Resu
         if let (Some(up_sql), Some(down_sql)) = (up_sql, down_sql) {
             to.add_sql(&backend_name, &up_sql, &down_sql)?;
         }
+        for stage in [HookStage::Before, HookStage::After] {
+            if let Some(sql) = from.hook_sql(&backend_name, stage)? {
+                to.set_hook_sql(&backend_name, stage, Some(&sql))?;
+            }
+        }
     }
     Ok(())
 }
@@ -355,7 +586,7 @@ fn from_row(row: &dyn db::BackendRow) -> Result<Self> {
     }
 
     fn query() -> query::Query<Self> {
-        query::Query::new("butane_migrations")
+        query::Query::new(DEFAULT_MIGRATIONS_TABLE)
     }
 }
 
@@ -363,7 +594,7 @@ impl DataObject for ButaneMigration {
     type PKType = String;
     type Fields = (); // we don't need Fields as we never filter
     const PKCOL: &'static str = "name";
-    const TABLE: &'static str = "butane_migrations";
+    const TABLE: &'static str = DEFAULT_MIGRATIONS_TABLE;
     const AUTO_PK: bool = false;
     fn pk(&self) -> &String {
         &self.name
@@ -0,0 +1,154 @@
+//! Notifications sent between database sessions, for example to
+//! invalidate caches held by other processes without an external
+//! message broker.
+//!
+//! Notifications are sent with
+//! [`notify`](crate::db::ConnectionMethods::notify) and received from the
+//! [`NotificationStream`] returned by
+//! [`listen`](crate::db::BackendConnectionAsync::listen). A notification
+//! sent within a transaction is only delivered once the transaction is
+//! committed. Only the Postgres backend supports notifications, using
+//! `LISTEN` and `NOTIFY`.
+//!
+//! A model declared with `#[butane(notify = "channel")]` sends a
+//! notification on `channel` each time one of its objects is saved or
+//! deleted. The payload is a JSON object such as
+//! `{"table":"Post","op":"save","pk":"1"}`, holding the primary key as
+//! a string.
+//!
+//! Within a single process, the rows changed through an SQLite
+//! connection may instead be observed with a hook set by
+//! [`set_update_hook`](crate::db::BackendConnection::set_update_hook),
+//! without polling.
+
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "async")]
+use futures_util::stream::Stream;
+use serde::{Deserialize, Serialize};
+
+use crate::SqlVal;
+
+/// A notification received on a channel being listened to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// The channel the notification was sent on.
+    pub channel: String,
+    /// The payload given by the sender, which may be empty.
+    pub payload: String,
+    /// The process ID of the database session which sent it.
+    pub process_id: i32,
+}
+
+/// Stream of the notifications received on a channel, returned by
+/// [`listen`](crate::db::BackendConnectionAsync::listen).
+///
+/// The stream ends when the connection is closed. Dropping the stream
+/// stops notifications being delivered to it, but the session keeps
+/// listening on the channel until it is closed or runs `UNLISTEN`.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct NotificationStream {
+    receiver: tokio::sync::mpsc::UnboundedReceiver<Notification>,
+}
+
+#[cfg(feature = "async")]
+impl NotificationStream {
+    #[cfg_attr(not(feature = "pg"), allow(dead_code))]
+    pub(crate) fn new(receiver: tokio::sync::mpsc::UnboundedReceiver<Notification>) -> Self {
+        NotificationStream { receiver }
+    }
+
+    /// Waits for the next notification, returning `None` once the
+    /// connection has been closed.
+    pub async fn recv(&mut self) -> Option<Notification> {
+        self.receiver.recv().await
+    }
+}
+
+#[cfg(feature = "async")]
+impl Stream for NotificationStream {
+    type Item = Notification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Notification>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Whether a change notified by a model was a save or a delete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOp {
+    /// The object was inserted or updated.
+    Save,
+    /// The object was deleted.
+    Delete,
+}
+
+/// Payload of the notification sent when an object of a model declared
+/// with `#[butane(notify = "channel")]` is saved or deleted.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ChangePayload {
+    /// The table of the model.
+    pub table: String,
+    /// The change made.
+    pub op: ChangeOp,
+    /// The primary key of the object, formatted as a string.
+    pub pk: String,
+}
+
+impl ChangePayload {
+    pub(crate) fn new(table: &str, op: ChangeOp, pk: &SqlVal) -> Self {
+        ChangePayload {
+            table: table.to_string(),
+            op,
+            pk: pk.to_string(),
+        }
+    }
+
+    /// Parses the payload of a [`Notification`] sent for a model change.
+    pub fn parse(payload: &str) -> crate::Result<Self> {
+        Ok(serde_json::from_str(payload)?)
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        // Serializing plain strings cannot fail
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Kind of change made to a row, reported to an [`UpdateHook`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowOperation {
+    /// The row was inserted.
+    Insert,
+    /// The row was updated.
+    Update,
+    /// The row was deleted.
+    Delete,
+}
+
+/// A row inserted, updated or deleted through a connection, reported to
+/// an [`UpdateHook`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RowChange<'a> {
+    /// The change made.
+    pub operation: RowOperation,
+    /// The name of the database containing the table, such as `main`.
+    pub database: &'a str,
+    /// The table containing the row.
+    pub table: &'a str,
+    /// The rowid of the row, which is the primary key of tables with an
+    /// integer primary key.
+    pub rowid: i64,
+}
+
+/// Callback set with
+/// [`set_update_hook`](crate::db::BackendConnection::set_update_hook).
+///
+/// It is called while the statement making the change is running, so
+/// must not use the connection.
+pub type UpdateHook = Box<dyn FnMut(RowChange<'_>) + Send>;
@@ -0,0 +1,54 @@
+//! Partitions of tables partitioned with `#[butane(partition_by = "...")]`.
+//!
+//! Partitioning is currently supported only by PostgreSQL. Other
+//! backends create partitioned tables as ordinary tables.
+
+use crate::db::BackendConnection;
+#[cfg(feature = "async")]
+use crate::db::BackendConnectionAsync;
+use crate::{Result, SqlVal};
+
+/// The values of the partition column held by a partition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PartitionBounds {
+    /// Values from `from` (inclusive) up to `to` (exclusive), for `range` partitioning.
+    Range {
+        /// Lowest value held by the partition.
+        from: SqlVal,
+        /// Lowest value above those held by the partition.
+        to: SqlVal,
+    },
+    /// The listed values, for `list` partitioning.
+    List(Vec<SqlVal>),
+    /// Values whose hash leaves `remainder` when divided by `modulus`, for `hash` partitioning.
+    Hash {
+        /// Divisor of the hash, the same for every partition of a table.
+        modulus: u32,
+        /// Remainder of the hash for values held by the partition.
+        remainder: u32,
+    },
+    /// Values not held by any other partition, for `range` or `list` partitioning.
+    Default,
+}
+
+/// Creates a partition named `partition` of the partitioned `table`,
+/// holding the values of its partition column given by `bounds`.
+///
+/// Rows may then be saved to and queried from `table` as usual, and
+/// the database routes them to the matching partition.
+#[maybe_async_cfg::maybe(
+    idents(BackendConnection(sync = "BackendConnection")),
+    sync(keep_self),
+    async(feature = "async", self = "create_partition_async")
+)]
+pub async fn create_partition(
+    conn: &impl BackendConnection,
+    table: &str,
+    partition: &str,
+    bounds: &PartitionBounds,
+) -> Result<()> {
+    let sql = conn
+        .backend()
+        .create_partition_sql(table, partition, bounds)?;
+    conn.execute(&sql).await
+}
@@ -0,0 +1,131 @@
+//! Generation of primary keys by the application.
+//!
+//! A primary key field with `#[butane(pk_generator = "PATH")]` is set
+//! by `save` to a value produced by the [`PkGenerator`] at `PATH` if it
+//! still has its default value, such as 0, an empty string or the nil
+//! UUID, so that constructors need not assign it. `PATH` may name any
+//! function returning the type of the primary key, or a static
+//! implementing [`PkGenerator`] for it, such as a [`Snowflake`]
+//! configured with the node it runs on. Generators are provided for
+//! common strategies:
+//!
+//! * [`uuid_v4`] and [`uuid_v7`] for random and time-ordered `Uuid`s.
+//! * [`ulid`] for time-ordered `String`s which sort lexicographically.
+//! * [`snowflake`] and [`Snowflake`] for time-ordered `i64`s.
+//!
+//! Keys generated by the database are instead declared with
+//! [`AutoPk`](crate::AutoPk).
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of primary keys of type `T`. Implemented for all functions
+/// returning `T`.
+pub trait PkGenerator<T> {
+    /// Generates a new primary key.
+    fn generate(&self) -> T;
+}
+
+impl<T, F> PkGenerator<T> for F
+where
+    F: Fn() -> T,
+{
+    fn generate(&self) -> T {
+        self()
+    }
+}
+
+/// Generates a random (version 4) UUID.
+#[cfg(feature = "uuid")]
+pub fn uuid_v4() -> ::uuid::Uuid {
+    ::uuid::Uuid::new_v4()
+}
+
+/// Generates a time-ordered (version 7) UUID, which gives better index
+/// locality than random ones.
+#[cfg(feature = "uuid")]
+pub fn uuid_v7() -> ::uuid::Uuid {
+    ::uuid::Uuid::now_v7()
+}
+
+/// Generates a ULID: 48 bits of the time in milliseconds followed by 80
+/// random bits, as 26 characters of Crockford's base 32.
+pub fn ulid() -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    let random = (u128::from(random_u64()) << 64 | u128::from(random_u64())) & ((1 << 80) - 1);
+    let value = u128::from(now_millis()) << 80 | random;
+    (0..26)
+        .map(|i| ALPHABET[(value >> (125 - 5 * i)) as usize & 31] as char)
+        .collect()
+}
+
+/// Generates a snowflake id with node number 0. See [`Snowflake`].
+pub fn snowflake() -> i64 {
+    static DEFAULT: Snowflake = Snowflake::new(0);
+    DEFAULT.generate()
+}
+
+/// Generator of snowflake ids: 64 bit integers made of 41 bits of the
+/// time in milliseconds since 2020, 10 bits identifying the node which
+/// generated them, and a 12 bit sequence number distinguishing those
+/// generated by the node in the same millisecond. Each node generating
+/// keys for the same table must have a different number.
+///
+/// ```
+/// use butane_core::pkgen::{PkGenerator, Snowflake};
+///
+/// static IDS: Snowflake = Snowflake::new(7);
+/// assert!(IDS.generate() < IDS.generate());
+/// ```
+#[derive(Debug)]
+pub struct Snowflake {
+    node: u16,
+    // Milliseconds since the epoch and sequence number of the last id
+    last: Mutex<(u64, u64)>,
+}
+
+impl Snowflake {
+    /// Milliseconds since the Unix epoch of 2020-01-01T00:00:00Z.
+    const EPOCH: u64 = 1_577_836_800_000;
+
+    /// Creates a generator for node number `node`, of which only the
+    /// lowest 10 bits are used.
+    pub const fn new(node: u16) -> Self {
+        Snowflake {
+            node: node & 0x3ff,
+            last: Mutex::new((0, 0)),
+        }
+    }
+}
+
+impl PkGenerator<i64> for Snowflake {
+    fn generate(&self) -> i64 {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let mut millis = now_millis().saturating_sub(Self::EPOCH).max(last.0);
+        let mut seq = if millis == last.0 { last.1 + 1 } else { 0 };
+        if seq > 0xfff {
+            // The sequence is exhausted, so wait for the next millisecond
+            while millis <= last.0 {
+                std::thread::yield_now();
+                millis = now_millis().saturating_sub(Self::EPOCH);
+            }
+            seq = 0;
+        }
+        *last = (millis, seq);
+        ((millis & 0x1ff_ffff_ffff) << 22 | u64::from(self.node) << 12 | seq) as i64
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn random_u64() -> u64 {
+    // RandomState is seeded randomly for each thread and varied for each
+    // instance, which suffices without depending on a random number crate
+    RandomState::new().build_hasher().finish()
+}
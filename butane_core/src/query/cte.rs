@@ -0,0 +1,126 @@
+//! Common table expressions, used to filter on sets of values computed
+//! by the database, such as all the descendants of a node in a tree.
+
+use std::borrow::Cow;
+
+use crate::query::{BoolExpr, TblName};
+
+/// A named common table expression (`WITH` in SQL) of a single column,
+/// which may be recursive.
+///
+/// A CTE is referenced in a filter with [`contains`](Self::contains),
+/// and is attached to the filter referencing it rather than to a
+/// [`Query`](crate::query::Query), so it may equally be used to delete
+/// or update objects.
+///
+/// For example, with a model `Category` whose `parent` field refers to
+/// its parent category, the ids of a category and all its descendants
+/// are given by
+/// ```ignore
+/// let tree = Cte::new("tree", "node", Category::TABLE, "id", filter!(Category, id == { root }))
+///     .recursive(Category::TABLE, "id", "parent");
+/// let subtree = Category::query().filter(tree.contains("id"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cte {
+    name: &'static str,
+    column: &'static str,
+    anchor: CteSelect,
+    step: Option<CteStep>,
+}
+
+/// The initial rows of a [`Cte`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CteSelect {
+    pub table: TblName,
+    pub column: &'static str,
+    pub filter: BoolExpr,
+}
+
+/// The rows added to a recursive [`Cte`] for each of its rows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CteStep {
+    pub table: TblName,
+    pub column: &'static str,
+    pub join_column: &'static str,
+}
+
+impl Cte {
+    /// The number of values in the filters of the CTE.
+    pub(crate) fn param_count(&self) -> usize {
+        self.anchor.filter.param_count()
+    }
+
+    /// Creates a CTE called `name`, whose single column `column` holds
+    /// the values of `select_column` in the rows of `table` for which
+    /// `filter` is true.
+    ///
+    /// `column` should not be the name of a column of a table used in
+    /// the CTE, to avoid ambiguity when it is made recursive.
+    pub fn new(
+        name: &'static str,
+        column: &'static str,
+        table: &'static str,
+        select_column: &'static str,
+        filter: BoolExpr,
+    ) -> Self {
+        Cte {
+            name,
+            column,
+            anchor: CteSelect {
+                table: Cow::Borrowed(table),
+                column: select_column,
+                filter,
+            },
+            step: None,
+        }
+    }
+
+    /// Makes this CTE recursive, repeatedly adding the values of
+    /// `select_column` in the rows of `table` whose `join_column` is one
+    /// of the values already in the CTE, until no new values are found.
+    /// Values appear at most once, so cycles are safe. Returns `self` as
+    /// this method is expected to be chained.
+    pub fn recursive(
+        mut self,
+        table: &'static str,
+        select_column: &'static str,
+        join_column: &'static str,
+    ) -> Self {
+        self.step = Some(CteStep {
+            table: Cow::Borrowed(table),
+            column: select_column,
+            join_column,
+        });
+        self
+    }
+
+    /// Creates a [`BoolExpr`] which evaluates to true if the value of
+    /// `col` is one of the values of this CTE.
+    pub fn contains(&self, col: &'static str) -> BoolExpr {
+        BoolExpr::InCte {
+            col,
+            cte: Box::new(self.clone()),
+        }
+    }
+
+    /// The name of this CTE.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The name of the single column of this CTE.
+    pub fn column(&self) -> &'static str {
+        self.column
+    }
+
+    /// The initial rows of this CTE.
+    pub fn anchor(&self) -> &CteSelect {
+        &self.anchor
+    }
+
+    /// The rows added for each row if this CTE is recursive.
+    pub fn step(&self) -> Option<&CteStep> {
+        self.step.as_ref()
+    }
+}
@@ -0,0 +1,94 @@
+//! Server-side cursors, through which the results of a query are read
+//! from the database in pages.
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use fallible_iterator::FallibleIterator;
+
+use crate::db::{BackendRows, ConnectionMethods, QueryResult, Transaction};
+#[cfg(feature = "async")]
+use crate::db::{ConnectionMethodsAsync, TransactionAsync};
+use crate::{DataResult, Result};
+
+static NEXT_CURSOR: AtomicU64 = AtomicU64::new(0);
+
+/// A cursor over the results of a [`Query`](super::Query), declared
+/// on the database server with
+/// [`QueryOpsSync::declare_cursor`](super::QueryOpsSync::declare_cursor)
+/// or its async counterpart.
+///
+/// Rather than the whole result set being sent at once, rows are
+/// fetched in pages of a chosen size, so very large results may be
+/// processed without holding all of them in memory. The cursor is
+/// only valid within the transaction in which it was declared, and is
+/// closed when that transaction ends if not by
+/// [`CursorOpsSync::close`] first. Only the Postgres backend supports
+/// cursors.
+#[derive(Debug)]
+pub struct Cursor<T: DataResult> {
+    name: String,
+    phantom: PhantomData<T>,
+}
+
+impl<T: DataResult> Cursor<T> {
+    /// Creates a cursor with a name unique to this process.
+    pub(super) fn new() -> Self {
+        Cursor {
+            name: format!(
+                "butane_cursor_{}",
+                NEXT_CURSOR.fetch_add(1, Ordering::Relaxed)
+            ),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The name under which the cursor is declared on the server.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// [`Cursor`] operations which require the `Transaction` the cursor
+/// was declared in.
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        Transaction(sync = "Transaction")
+    ),
+    sync(),
+    async(feature = "async")
+)]
+pub trait CursorOps<T> {
+    /// Fetches the next `count` results from the cursor. Fewer are
+    /// returned once the cursor nears the end of the results, and none
+    /// once it has reached it.
+    async fn fetch(&mut self, tx: &Transaction<'_>, count: u32) -> Result<QueryResult<T>>;
+
+    /// Closes the cursor, releasing its resources on the server before
+    /// the end of the transaction.
+    async fn close(self, tx: &Transaction<'_>) -> Result<()>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        CursorOps,
+        Transaction(sync = "Transaction")
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+impl<T: DataResult> CursorOps<T> for Cursor<T> {
+    async fn fetch(&mut self, tx: &Transaction<'_>, count: u32) -> Result<QueryResult<T>> {
+        tx.fetch_cursor(&self.name, T::COLUMNS, count)
+            .await?
+            .mapped(|row| T::from_row_decrypting(row, tx.key_provider()))
+            .collect()
+    }
+    async fn close(self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute(&format!("CLOSE {}", self.name)).await
+    }
+}
@@ -1,12 +1,17 @@
-//! Not expected to be used directly.
+//! Field expressions used to build [BoolExpr] filters.
+//!
+//! Most users will reach these through the `filter!` and `query!`
+//! macros, or through the generated `fields()` accessor on each
+//! model, e.g. `Post::fields().title().eq(&title)`.
 
 use std::borrow::{Borrow, Cow};
 use std::marker::PhantomData;
 
+use crate::db;
 use crate::fkey::ForeignKey;
 use crate::query::{BoolExpr, Column, Expr, Join};
 use crate::sqlval::{FieldType, SqlVal, ToSql};
-use crate::DataObject;
+use crate::{DataObject, DataResult, Error, Result, StringList};
 
 macro_rules! binary_op {
     ($func_name:ident, $bound:path, $cond:ident) => {
@@ -27,8 +32,12 @@ pub trait DataOrd<Rhs> {}
 impl<T> DataOrd<T> for Option<T> where T: PartialOrd<T> + FieldType {}
 impl<T> DataOrd<T> for T where T: PartialOrd<T> + FieldType {}
 
-/// Used to implement the `query!` and `filter!` macros.
-/// Manual use of this type is not recommended, but not prohibited.
+/// A typed reference to a model field, used to build [BoolExpr] filters.
+///
+/// Obtained from the generated `fields()` accessor on a model, e.g.
+/// `Post::fields().title()`. This is also what the `query!` and
+/// `filter!` macros expand to, so filters built at runtime with it
+/// are checked by the compiler in the same way.
 #[derive(Clone, Debug)]
 pub struct FieldExpr<T>
 where
@@ -78,6 +87,94 @@ pub fn is_in<U: ToSql>(&self, vals: Vec<U>) -> BoolExpr {
         BoolExpr::In(self.name, vals.into_iter().map(|v| v.to_sql()).collect())
     }
 }
+
+macro_rules! dyn_binary_op {
+    ($func_name:ident, $cond:ident, $null_allowed:literal) => {
+        /// Creates a [BoolExpr] which evaluates this column against `val`.
+        /// Returns [Error::CannotConvertSqlVal] if `val` does not have the column's type.
+        pub fn $func_name(&self, val: impl ToSql) -> Result<BoolExpr> {
+            Ok(BoolExpr::$cond(
+                self.name(),
+                Expr::Val(self.check(val.to_sql(), $null_allowed)?),
+            ))
+        }
+    };
+}
+
+/// A field of the model `T` selected by column name at runtime.
+///
+/// This is the dynamic counterpart of [FieldExpr], for cases such as
+/// search screens where the column to filter on is not known until
+/// runtime. Column names are validated against [DataResult::COLUMNS]
+/// and values are validated against the column's [SqlType][crate::SqlType]
+/// when the [BoolExpr] is built, rather than at compile time.
+#[derive(Debug)]
+pub struct DynFieldExpr<T: DataResult> {
+    column: &'static db::Column,
+    phantom: PhantomData<T>,
+}
+
+impl<T: DataResult> DynFieldExpr<T> {
+    /// Looks up the column `name` of `T`.
+    /// Returns [Error::ColumnNotFound] if `T` has no such column.
+    pub fn new(name: &str) -> Result<Self> {
+        T::COLUMNS
+            .iter()
+            .find(|col| col.name() == name)
+            .map(|column| DynFieldExpr {
+                column,
+                phantom: PhantomData,
+            })
+            .ok_or_else(|| Error::ColumnNotFound(T::DBO::TABLE.to_string(), name.to_string()))
+    }
+
+    /// Returns the name of this field.
+    pub fn name(&self) -> &'static str {
+        self.column.name()
+    }
+
+    /// Returns the column metadata of this field.
+    pub fn column(&self) -> &'static db::Column {
+        self.column
+    }
+
+    fn check(&self, val: SqlVal, null_allowed: bool) -> Result<SqlVal> {
+        if val.is_compatible(self.column.ty(), null_allowed) {
+            Ok(val)
+        } else {
+            Err(Error::CannotConvertSqlVal(self.column.ty().clone(), val))
+        }
+    }
+
+    dyn_binary_op!(eq, Eq, true);
+    dyn_binary_op!(ne, Ne, true);
+    dyn_binary_op!(lt, Lt, false);
+    dyn_binary_op!(gt, Gt, false);
+    dyn_binary_op!(le, Le, false);
+    dyn_binary_op!(ge, Ge, false);
+    dyn_binary_op!(like, Like, false);
+
+    /// Creates a [BoolExpr] which will evaluate to true if
+    /// the value of this field is contained in `vals`.
+    pub fn is_in<U: ToSql>(&self, vals: Vec<U>) -> Result<BoolExpr> {
+        let vals = vals
+            .into_iter()
+            .map(|v| self.check(v.to_sql(), false))
+            .collect::<Result<Vec<SqlVal>>>()?;
+        Ok(BoolExpr::In(self.name(), vals))
+    }
+}
+
+// Explicit impl so that Clone is implemented even if T is not Clone
+impl<T: DataResult> Clone for DynFieldExpr<T> {
+    fn clone(&self) -> Self {
+        DynFieldExpr {
+            column: self.column,
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<F: DataObject> FieldExpr<ForeignKey<F>> {
     pub fn subfilter(&self, q: BoolExpr) -> BoolExpr {
         BoolExpr::Subquery {
@@ -96,6 +193,38 @@ pub fn subfilterpk(&self, pk: F::PKType) -> BoolExpr {
     pub fn fields(&self) -> F::Fields {
         F::Fields::default()
     }
+    /// Creates a [BoolExpr] which evaluates to true if this foreign key
+    /// refers to the object being filtered by the enclosing query, for
+    /// use in a subquery such as [`Query::exists`](crate::query::Query::exists).
+    pub fn matches_outer(&self) -> BoolExpr {
+        BoolExpr::Eq(
+            self.name,
+            Expr::QualifiedColumn(Column::new(F::TABLE, F::PKCOL)),
+        )
+    }
+}
+
+impl FieldExpr<StringList> {
+    /// Creates a [BoolExpr] which evaluates to true if the list
+    /// contains `val`.
+    pub fn contains(&self, val: impl Into<String>) -> BoolExpr {
+        BoolExpr::ListContains(self.name, vec![val.into()])
+    }
+    /// Used by the `filter!` macro for `contains` with a literal.
+    #[doc(hidden)]
+    pub fn containspk(&self, val: impl Into<String>) -> BoolExpr {
+        self.contains(val)
+    }
+    /// Creates a [BoolExpr] which evaluates to true if the list
+    /// contains every one of `vals`.
+    pub fn contains_all<S: Into<String>>(&self, vals: impl IntoIterator<Item = S>) -> BoolExpr {
+        BoolExpr::ListContains(self.name, vals.into_iter().map(Into::into).collect())
+    }
+    /// Creates a [BoolExpr] which evaluates to true if the list
+    /// contains any of `vals`.
+    pub fn overlaps<S: Into<String>>(&self, vals: impl IntoIterator<Item = S>) -> BoolExpr {
+        BoolExpr::ListOverlaps(self.name, vals.into_iter().map(Into::into).collect())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -143,3 +272,44 @@ pub fn fields(&self) -> T::Fields {
         T::Fields::default()
     }
 }
+
+/// A relationship of the model `T` to load along with it, using
+/// [`get_with`](crate::DataObjectOpsSync::get_with). Made from the
+/// field expression of a `ForeignKey`, `Option<ForeignKey>` or `Many`
+/// field, usually by the `related!` macro.
+#[derive(Clone, Debug)]
+pub struct Related<T> {
+    name: &'static str,
+    phantom: PhantomData<T>,
+}
+impl<T> Related<T> {
+    /// Returns the name identifying the relationship, which is the column
+    /// of a foreign key or the table of a many-to-many relationship.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+impl<T, F: DataObject> From<FieldExpr<ForeignKey<F>>> for Related<T> {
+    fn from(field: FieldExpr<ForeignKey<F>>) -> Self {
+        Related {
+            name: field.name,
+            phantom: PhantomData,
+        }
+    }
+}
+impl<T, F: DataObject> From<FieldExpr<Option<ForeignKey<F>>>> for Related<T> {
+    fn from(field: FieldExpr<Option<ForeignKey<F>>>) -> Self {
+        Related {
+            name: field.name,
+            phantom: PhantomData,
+        }
+    }
+}
+impl<O: DataObject, T: DataObject> From<ManyFieldExpr<O, T>> for Related<O> {
+    fn from(field: ManyFieldExpr<O, T>) -> Self {
+        Related {
+            name: field.many_table,
+            phantom: PhantomData,
+        }
+    }
+}
@@ -5,21 +5,51 @@
 #![allow(missing_docs)]
 
 use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use fallible_iterator::FallibleIterator;
 
+use crate::db::{
+    atomic, helper, BackendRow, BackendRows, ConnectionMethods, QueryResult, Transaction,
+};
 #[cfg(feature = "async")]
-use crate::db::ConnectionMethodsAsync;
-use crate::db::{BackendRows, ConnectionMethods, QueryResult};
-use crate::{DataResult, Result, SqlVal};
+use crate::db::{ConnectionMethodsAsync, TransactionAsync};
+use crate::internal::DataObjectInternal;
+use crate::{counter, deadline, subtype, DataObject, DataResult, Error, Result};
+use crate::{SqlType, SqlVal, SqlValRef};
 
+mod cte;
+mod cursor;
 mod fieldexpr;
+mod page;
+mod projection;
+mod update;
 
-pub use fieldexpr::{DataOrd, FieldExpr, ManyFieldExpr};
+pub use cte::{Cte, CteSelect, CteStep};
+pub use cursor::Cursor;
+#[cfg(feature = "async")]
+pub use cursor::CursorOpsAsync;
+pub use cursor::CursorOpsSync;
+pub use fieldexpr::{DataOrd, DynFieldExpr, FieldExpr, ManyFieldExpr, Related};
+pub use page::Page;
+pub use projection::{FromRow, Projection};
+pub use update::{Patch, PatchOpsSync, Update, UpdateOpsSync};
+#[cfg(feature = "async")]
+pub use update::{PatchOpsAsync, UpdateOpsAsync};
 
 type TblName = Cow<'static, str>;
 
+/// The savepoint within which the chunks of a long `IN` list are
+/// updated or deleted.
+pub(crate) const SAVEPOINT: &str = "butane_in_list_chunks";
+
+static NEXT_IN_LIST: AtomicU64 = AtomicU64::new(0);
+
 /// Abstract representation of a database expression.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expr {
@@ -31,8 +61,131 @@ pub enum Expr {
     Placeholder,
     /// A boolean condition.
     Condition(Box<BoolExpr>),
+    /// An arithmetic operation on two expressions.
+    Arith(Box<Expr>, ArithOp, Box<Expr>),
+    /// A call of the SQL function with the given name.
+    Func(&'static str, Vec<Expr>),
+    /// A column qualified by its table, which in a subquery may refer to
+    /// the row of the enclosing query.
+    QualifiedColumn(Column),
+    /// The single value selected by a subquery, or NULL if it selects no
+    /// rows.
+    Scalar(Box<ScalarQuery>),
+    /// The value paired with the first condition which is true, or else
+    /// the fallback value, or NULL if there is none.
+    Case(Vec<(BoolExpr, Expr)>, Option<Box<Expr>>),
+}
+
+impl Expr {
+    /// The number of values in this expression, each of which may be
+    /// bound to a parameter of its statement.
+    pub(crate) fn param_count(&self) -> usize {
+        match self {
+            Expr::Val(_) | Expr::Placeholder => 1,
+            Expr::Column(_) | Expr::QualifiedColumn(_) => 0,
+            Expr::Condition(cond) => cond.param_count(),
+            Expr::Arith(a, _, b) => a.param_count() + b.param_count(),
+            Expr::Func(_, args) => args.iter().map(Expr::param_count).sum(),
+            Expr::Scalar(query) => {
+                query.value.param_count()
+                    + query.filter.param_count()
+                    + Order::param_count(&query.sort)
+            }
+            Expr::Case(whens, otherwise) => {
+                whens
+                    .iter()
+                    .map(|(cond, val)| cond.param_count() + val.param_count())
+                    .sum::<usize>()
+                    + otherwise.as_ref().map_or(0, |val| val.param_count())
+            }
+        }
+    }
+
+    /// Creates a conditional expression evaluating to the value paired
+    /// with the first of `whens` whose condition is true, or else to
+    /// `otherwise`, or NULL if it is `None`. Usually written as an `if`
+    /// expression in the `expr!` and `update!` macros.
+    pub fn case(
+        whens: impl IntoIterator<Item = (BoolExpr, Expr)>,
+        otherwise: Option<Expr>,
+    ) -> Expr {
+        Expr::Case(whens.into_iter().collect(), otherwise.map(Box::new))
+    }
+
+    /// Creates an expression evaluating to the first of `exprs` which is
+    /// not NULL, e.g. to give a nullable column a fallback value.
+    pub fn coalesce(exprs: impl IntoIterator<Item = Expr>) -> Expr {
+        Expr::Func("coalesce", exprs.into_iter().collect())
+    }
+}
+
+/// A subquery selecting a single value, used in an [`Expr::Scalar`].
+/// Usually constructed with [`Query::scalar`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalarQuery {
+    pub table: TblName,
+    pub value: Expr,
+    pub filter: BoolExpr,
+    pub sort: Vec<Order>,
+    pub limit: Option<i32>,
+}
+
+/// Arithmetic operator used in an [`Expr::Arith`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
 }
 
+impl ArithOp {
+    /// The SQL operator.
+    pub fn sql(&self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+            ArithOp::Rem => "%",
+        }
+    }
+}
+
+/// Comparison operator used in a [`BoolExpr::Compare`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Like,
+}
+
+impl CompareOp {
+    /// The SQL operator.
+    pub fn sql(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "<>",
+            CompareOp::Lt => "<",
+            CompareOp::Gt => ">",
+            CompareOp::Le => "<=",
+            CompareOp::Ge => ">=",
+            CompareOp::Like => "like",
+        }
+    }
+}
+
+/// SQL functions which may be called in the expressions of the
+/// `filter!`, `update!` and `expr!` macros, such as
+/// `filter!(Post, length(body) > 500)`. All backends support them
+/// except `date_trunc`, which only Postgres provides.
+pub const SQL_FUNCTIONS: &[&str] = &["lower", "upper", "length", "abs", "coalesce", "date_trunc"];
+
 /// Abstract representation of a boolean expression.
 #[derive(Clone, Debug, PartialEq)]
 pub enum BoolExpr {
@@ -68,20 +221,464 @@ pub enum BoolExpr {
         joins: Vec<Join>,
         expr: Box<BoolExpr>,
     },
+    /// Expression which is true if the value of `col` is one of the
+    /// values of the common table expression `cte`.
+    InCte {
+        col: &'static str,
+        cte: Box<Cte>,
+    },
+    /// Expression which is true if `expr` is true for any row in `tbl`.
+    /// Usually constructed with [`Query::exists`].
+    Exists {
+        tbl: TblName,
+        expr: Box<BoolExpr>,
+    },
+    /// Comparison of two expressions, used when the left side is not
+    /// simply a column, such as a function of one.
+    Compare(Expr, CompareOp, Expr),
+    /// Expression which is true if the list in `col`, such as a
+    /// [`StringList`](crate::StringList), contains every one of the values.
+    ListContains(&'static str, Vec<String>),
+    /// Expression which is true if the list in `col` contains any of
+    /// the values.
+    ListOverlaps(&'static str, Vec<String>),
+}
+
+impl BoolExpr {
+    /// Combines this expression with `other`, evaluating to true only if both are true.
+    ///
+    /// Useful for composing predicates built separately, e.g. with the `filter!` macro.
+    pub fn and(self, other: BoolExpr) -> BoolExpr {
+        BoolExpr::And(Box::new(self), Box::new(other))
+    }
+
+    /// Combines this expression with `other`, evaluating to true if either is true.
+    pub fn or(self, other: BoolExpr) -> BoolExpr {
+        BoolExpr::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Negates this expression. Also available as the `!` operator.
+    // Inherent so that it may be chained without importing `std::ops::Not`
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> BoolExpr {
+        BoolExpr::Not(Box::new(self))
+    }
+
+    /// Combines `exprs`, evaluating to true only if all of them are
+    /// true. True if `exprs` is empty.
+    pub fn all_of(exprs: impl IntoIterator<Item = BoolExpr>) -> BoolExpr {
+        let exprs: Vec<BoolExpr> = exprs.into_iter().collect();
+        if exprs.is_empty() {
+            BoolExpr::True
+        } else {
+            BoolExpr::AllOf(exprs)
+        }
+    }
+
+    /// Combines `exprs`, evaluating to true if any of them is true.
+    /// False if `exprs` is empty.
+    pub fn any_of(exprs: impl IntoIterator<Item = BoolExpr>) -> BoolExpr {
+        exprs
+            .into_iter()
+            .reduce(BoolExpr::or)
+            .unwrap_or_else(|| BoolExpr::True.not())
+    }
+
+    /// Returns an equivalent expression which is simpler to run, as is
+    /// done for the filters of queries and updates:
+    ///
+    /// * nested `AND`s and `OR`s are flattened, and repeated operands,
+    ///   and `TRUE` operands of an `AND`, are removed,
+    /// * comparisons of a column for equality with values and `IN`
+    ///   lists of it which are combined with `OR` are merged into a
+    ///   single `IN` list,
+    /// * repeated values of an `IN` list are removed.
+    ///
+    /// Programmatically built filters, such as an `OR` of thousands of
+    /// comparisons, thus bind fewer parameters and nest less deeply.
+    pub fn normalize(self) -> BoolExpr {
+        match self {
+            BoolExpr::And(..) | BoolExpr::AllOf(_) => {
+                let mut conds = Vec::new();
+                for cond in self.operands(true) {
+                    match cond.normalize() {
+                        BoolExpr::True => {}
+                        cond @ (BoolExpr::And(..) | BoolExpr::AllOf(_)) => {
+                            conds.extend(cond.operands(true))
+                        }
+                        cond => conds.push(cond),
+                    }
+                }
+                let mut conds = distinct(conds, hash_bool_expr);
+                match conds.len() {
+                    0 => BoolExpr::True,
+                    1 => conds.remove(0),
+                    _ => BoolExpr::AllOf(conds),
+                }
+            }
+            BoolExpr::Or(..) => {
+                let mut conds = Vec::new();
+                for cond in self.operands(false) {
+                    match cond.normalize() {
+                        BoolExpr::True => return BoolExpr::True,
+                        cond @ BoolExpr::Or(..) => conds.extend(cond.operands(false)),
+                        cond => conds.push(cond),
+                    }
+                }
+                BoolExpr::any_of(merge_equalities(conds))
+            }
+            BoolExpr::Not(expr) => match expr.normalize() {
+                BoolExpr::Not(expr) => *expr,
+                expr => expr.not(),
+            },
+            BoolExpr::In(col, vals) => BoolExpr::In(col, distinct(vals, hash_val)),
+            BoolExpr::Subquery {
+                col,
+                tbl2,
+                tbl2_col,
+                expr,
+            } => BoolExpr::Subquery {
+                col,
+                tbl2,
+                tbl2_col,
+                expr: Box::new(expr.normalize()),
+            },
+            BoolExpr::SubqueryJoin {
+                col,
+                tbl2,
+                col2,
+                joins,
+                expr,
+            } => BoolExpr::SubqueryJoin {
+                col,
+                tbl2,
+                col2,
+                joins,
+                expr: Box::new(expr.normalize()),
+            },
+            BoolExpr::Exists { tbl, expr } => BoolExpr::Exists {
+                tbl,
+                expr: Box::new(expr.normalize()),
+            },
+            expr => expr,
+        }
+    }
+
+    /// Returns the operands of this expression if it is an `AND`, for
+    /// a `conjunction`, or an `OR` otherwise, flattening nested ones, or
+    /// else the expression itself. Iterative, as long chains built by
+    /// [`all_of`](Self::all_of) or [`any_of`](Self::any_of) are deep.
+    fn operands(self, conjunction: bool) -> Vec<BoolExpr> {
+        let mut operands = Vec::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match (expr, conjunction) {
+                (BoolExpr::And(a, b), true) | (BoolExpr::Or(a, b), false) => {
+                    stack.push(*b);
+                    stack.push(*a);
+                }
+                (BoolExpr::AllOf(conds), true) => stack.extend(conds.into_iter().rev()),
+                (expr, _) => operands.push(expr),
+            }
+        }
+        operands
+    }
+
+    /// The number of values in this expression, each of which may be
+    /// bound to a parameter of its statement. Iterative, as with
+    /// [`operands`](Self::operands).
+    pub(crate) fn param_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                BoolExpr::True => {}
+                BoolExpr::Eq(_, val)
+                | BoolExpr::Ne(_, val)
+                | BoolExpr::Lt(_, val)
+                | BoolExpr::Gt(_, val)
+                | BoolExpr::Le(_, val)
+                | BoolExpr::Ge(_, val)
+                | BoolExpr::Like(_, val) => count += val.param_count(),
+                BoolExpr::AllOf(conds) => stack.extend(conds),
+                BoolExpr::And(a, b) | BoolExpr::Or(a, b) => stack.extend([&**a, &**b]),
+                BoolExpr::Not(expr)
+                | BoolExpr::Subquery { expr, .. }
+                | BoolExpr::SubqueryJoin { expr, .. }
+                | BoolExpr::Exists { expr, .. } => stack.push(expr),
+                BoolExpr::In(_, vals) => count += vals.len(),
+                BoolExpr::InCte { cte, .. } => count += cte.param_count(),
+                BoolExpr::Compare(a, _, b) => count += a.param_count() + b.param_count(),
+                BoolExpr::ListContains(_, vals) | BoolExpr::ListOverlaps(_, vals) => {
+                    count += vals.len()
+                }
+            }
+        }
+        count
+    }
+
+    /// Returns this expression with an `AND` flattened into an
+    /// `AllOf`, in which an `IN` list may be found among its operands.
+    fn flatten_and(&self) -> Cow<'_, BoolExpr> {
+        match self {
+            BoolExpr::And(..) => Cow::Owned(BoolExpr::AllOf(self.clone().operands(true))),
+            expr => Cow::Borrowed(expr),
+        }
+    }
+
+    /// The number of values in the longest `IN` list which is this
+    /// expression or one of the operands of its `AND`.
+    fn longest_in_list(&self) -> Option<usize> {
+        match self {
+            BoolExpr::In(_, vals) => Some(vals.len()),
+            BoolExpr::AllOf(conds) => conds.iter().filter_map(BoolExpr::longest_in_list).max(),
+            _ => None,
+        }
+    }
+
+    /// Applies `f` to the first `IN` list of `len` values which is this
+    /// expression or one of the operands of its `AND`, replacing it in
+    /// each of the expressions `f` returns for it.
+    fn map_in_list(
+        &self,
+        len: usize,
+        f: &mut dyn FnMut(&'static str, &[SqlVal]) -> Vec<BoolExpr>,
+    ) -> Option<Vec<BoolExpr>> {
+        match self {
+            BoolExpr::In(col, vals) if vals.len() == len => Some(f(col, vals)),
+            BoolExpr::AllOf(conds) => conds.iter().enumerate().find_map(|(i, cond)| {
+                let replacements = cond.map_in_list(len, f)?;
+                let exprs = replacements
+                    .into_iter()
+                    .map(|replacement| {
+                        let mut conds = conds.clone();
+                        conds[i] = replacement;
+                        BoolExpr::AllOf(conds)
+                    })
+                    .collect();
+                Some(exprs)
+            }),
+            _ => None,
+        }
+    }
+
+    /// Splits this expression, if it binds more than `max` parameters
+    /// and is an `IN` list or an `AND` of one, into expressions each
+    /// binding at most `max` of them, with a chunk of the list's values,
+    /// which together match the same rows. Returns the column of the
+    /// list along with them, or `None` if there is no need or no such
+    /// list. Every value is counted as a parameter, as some backends
+    /// bind integers too. Fails with [`Error::TooManyParameters`] if the
+    /// other parameters leave no room for any value of the list.
+    pub(crate) fn split_in_list(
+        &self,
+        max: usize,
+    ) -> Result<Option<(&'static str, Vec<BoolExpr>)>> {
+        let total = self.param_count();
+        if total <= max {
+            return Ok(None);
+        }
+        let expr = self.flatten_and();
+        let Some(len) = expr.longest_in_list() else {
+            return Ok(None);
+        };
+        let others = total - len;
+        if others >= max {
+            return Err(Error::TooManyParameters {
+                count: others + 1,
+                max,
+            });
+        }
+        let chunk_len = max - others;
+        let mut list_col = "";
+        let chunks = expr.map_in_list(len, &mut |col, vals| {
+            list_col = col;
+            vals.chunks(chunk_len)
+                .map(|chunk| BoolExpr::In(col, chunk.to_vec()))
+                .collect()
+        });
+        Ok(chunks.map(|chunks| (list_col, chunks)))
+    }
+
+    /// Replaces the `IN` list which [`split_in_list`](Self::split_in_list)
+    /// would split with the expression `replacement` returns for its
+    /// column. Returns the column and values of the list along with the
+    /// new expression, or `None` if there is no need or no such list.
+    /// Fails with [`Error::TooManyParameters`] if the other parameters
+    /// are too many even without the list.
+    pub(crate) fn replace_in_list(
+        &self,
+        max: usize,
+        replacement: &dyn Fn(&'static str) -> BoolExpr,
+    ) -> Result<Option<(&'static str, Vec<SqlVal>, BoolExpr)>> {
+        let total = self.param_count();
+        if total <= max {
+            return Ok(None);
+        }
+        let expr = self.flatten_and();
+        let Some(len) = expr.longest_in_list() else {
+            return Ok(None);
+        };
+        if total - len > max {
+            return Err(Error::TooManyParameters {
+                count: total - len,
+                max,
+            });
+        }
+        let mut list = ("", Vec::new());
+        let exprs = expr.map_in_list(len, &mut |col, vals| {
+            list = (col, vals.to_vec());
+            vec![replacement(col)]
+        });
+        Ok(exprs
+            .and_then(|mut exprs| exprs.pop())
+            .map(|expr| (list.0, list.1, expr)))
+    }
+}
+
+/// Merges the operands of an `OR` which compare a column for equality
+/// with a value, or are `IN` lists of it, into a single `IN` list of
+/// the column, and removes repeated operands.
+fn merge_equalities(conds: Vec<BoolExpr>) -> Vec<BoolExpr> {
+    let mut merged: Vec<BoolExpr> = Vec::with_capacity(conds.len());
+    for cond in conds {
+        let (col, vals) = match cond {
+            // Equality with NULL is IS NULL, which is not an IN list
+            BoolExpr::Eq(col, Expr::Val(val)) if val != SqlVal::Null => (col, vec![val]),
+            BoolExpr::In(col, vals) => (col, vals),
+            cond => {
+                merged.push(cond);
+                continue;
+            }
+        };
+        let list = merged
+            .iter_mut()
+            .find(|cond| matches!(cond, BoolExpr::In(other, _) if *other == col));
+        match list {
+            Some(BoolExpr::In(_, list)) => list.extend(vals),
+            _ => merged.push(BoolExpr::In(col, vals)),
+        }
+    }
+    distinct(merged, hash_bool_expr)
+        .into_iter()
+        .map(|cond| match cond {
+            BoolExpr::In(col, vals) => match <[SqlVal; 1]>::try_from(distinct(vals, hash_val)) {
+                Ok([val]) => BoolExpr::Eq(col, Expr::Val(val)),
+                Err(vals) => BoolExpr::In(col, vals),
+            },
+            cond => cond,
+        })
+        .collect()
+}
+
+/// Removes repeated items, keeping the first of each. Items are
+/// compared for equality only with those of the same `hash`, which
+/// must be equal for equal items but need not cover all of an item,
+/// as a [`SqlVal`] holding a float or JSON cannot be hashed itself.
+fn distinct<T: PartialEq>(items: Vec<T>, hash: impl Fn(&T, &mut DefaultHasher)) -> Vec<T> {
+    let mut kept: Vec<T> = Vec::with_capacity(items.len());
+    let mut buckets: HashMap<u64, Vec<usize>> = HashMap::with_capacity(items.len());
+    for item in items {
+        let mut hasher = DefaultHasher::new();
+        hash(&item, &mut hasher);
+        let bucket = buckets.entry(hasher.finish()).or_default();
+        if !bucket.iter().any(|i| kept[*i] == item) {
+            bucket.push(kept.len());
+            kept.push(item);
+        }
+    }
+    kept
+}
+
+/// Hashes the parts of `val` which determine its equality, as needed
+/// by [`distinct`].
+fn hash_val(val: &SqlVal, state: &mut DefaultHasher) {
+    std::mem::discriminant(val).hash(state);
+    match val {
+        SqlVal::Bool(b) => b.hash(state),
+        SqlVal::Int(i) => i.hash(state),
+        SqlVal::BigInt(i) => i.hash(state),
+        // 0.0 and -0.0 are equal
+        SqlVal::Real(r) if *r == 0.0 => {}
+        SqlVal::Real(r) => r.to_bits().hash(state),
+        SqlVal::Text(text) => text.hash(state),
+        SqlVal::Blob(blob) => blob.hash(state),
+        SqlVal::TextList(list) => list.hash(state),
+        #[cfg(feature = "datetime")]
+        SqlVal::Date(date) => date.hash(state),
+        #[cfg(feature = "datetime")]
+        SqlVal::Timestamp(ts) => ts.hash(state),
+        _ => {}
+    }
+}
+
+/// Hashes the columns and values of `expr`, and the structure of its
+/// boolean operators, as needed by [`distinct`].
+fn hash_bool_expr(expr: &BoolExpr, state: &mut DefaultHasher) {
+    std::mem::discriminant(expr).hash(state);
+    match expr {
+        BoolExpr::Eq(col, val)
+        | BoolExpr::Ne(col, val)
+        | BoolExpr::Lt(col, val)
+        | BoolExpr::Gt(col, val)
+        | BoolExpr::Le(col, val)
+        | BoolExpr::Ge(col, val)
+        | BoolExpr::Like(col, val) => {
+            col.hash(state);
+            if let Expr::Val(val) = val {
+                hash_val(val, state);
+            }
+        }
+        BoolExpr::AllOf(exprs) => exprs.iter().for_each(|expr| hash_bool_expr(expr, state)),
+        BoolExpr::And(a, b) | BoolExpr::Or(a, b) => {
+            hash_bool_expr(a, state);
+            hash_bool_expr(b, state);
+        }
+        BoolExpr::Not(expr) => hash_bool_expr(expr, state),
+        BoolExpr::In(col, vals) => {
+            col.hash(state);
+            vals.iter().for_each(|val| hash_val(val, state));
+        }
+        BoolExpr::ListContains(col, vals) | BoolExpr::ListOverlaps(col, vals) => {
+            col.hash(state);
+            vals.hash(state);
+        }
+        _ => {}
+    }
+}
+
+impl std::ops::Not for BoolExpr {
+    type Output = BoolExpr;
+    fn not(self) -> BoolExpr {
+        BoolExpr::not(self)
+    }
 }
 
 /// Represents the direction of a sort.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum OrderDirection {
     Ascending,
     Descending,
 }
 
 /// Represents a sorting term (ORDER BY in SQL).
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Order {
     pub direction: OrderDirection,
     pub column: &'static str,
+    /// Expression to order by in place of `column`, such as a function
+    /// of a column.
+    pub expr: Option<Expr>,
+}
+
+impl Order {
+    /// The number of values in the expressions of `sort`.
+    pub(crate) fn param_count(sort: &[Order]) -> usize {
+        sort.iter()
+            .filter_map(|order| order.expr.as_ref())
+            .map(Expr::param_count)
+            .sum()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -120,6 +717,14 @@ pub fn name(&self) -> &'static str {
 
 /// Representation of a database query.
 /// See [`QueryOpsSync`] and [`QueryOpsAsync`] for operations requiring a live database connection.
+///
+/// An `IN` list in the filter with more values than the backend may bind
+/// to one statement, such as from [`FieldExpr::is_in`], is split into
+/// chunks, for each of which a statement is run, when loading objects
+/// which are not ordered and when deleting them. When loading ordered
+/// objects, the values are instead inserted into a temporary table, from
+/// which the filter selects them, so that the order, limit and offset
+/// are applied once to all the results.
 #[derive(Debug)]
 pub struct Query<T: DataResult> {
     table: TblName,
@@ -127,28 +732,32 @@ pub struct Query<T: DataResult> {
     limit: Option<i32>,
     offset: Option<i32>,
     sort: Vec<Order>,
+    timeout: Option<Duration>,
     phantom: PhantomData<T>,
 }
 impl<T: DataResult> Query<T> {
     /// Creates a query which matches all objects in `table`. The set
     /// of matched objects can be restricted with `filter` and
-    /// `limit`.
+    /// `limit`. The query of a model sharing its table with other
+    /// [subtypes](crate::subtype) only matches the objects of the model.
     pub fn new(table: &'static str) -> Query<T> {
+        crate::db::note_sensitive_columns(table, T::DBO::COLUMNS);
         Query {
             table: Cow::Borrowed(table),
-            filter: None,
+            filter: subtype::restrict::<T::DBO>(None),
             limit: None,
             offset: None,
             sort: Vec::new(),
+            timeout: None,
             phantom: PhantomData,
         }
     }
 
     /// Restricts the query to matching only objects for which `expr`
-    /// is true. Returns `self` as this method is expected to be
-    /// chained.
+    /// is true. The expression is [normalized](BoolExpr::normalize).
+    /// Returns `self` as this method is expected to be chained.
     pub fn filter(mut self, expr: BoolExpr) -> Query<T> {
-        self.filter = Some(expr);
+        self.filter = subtype::restrict::<T::DBO>(Some(expr)).map(BoolExpr::normalize);
         self
     }
 
@@ -166,12 +775,27 @@ pub fn offset(mut self, off: i32) -> Query<T> {
         self
     }
 
+    /// Bounds the time spent running the query to `timeout`, failing
+    /// with [`Error::DeadlineExceeded`] if it takes longer, without
+    /// changing the settings of the connection. The query is run with
+    /// a deadline as by [`deadline::with_deadline`], so that the
+    /// database stops it where the backend supports this. Returns
+    /// `self` as this method is expected to be chained.
+    pub fn with_timeout(mut self, timeout: Duration) -> Query<T> {
+        self.timeout = Some(timeout);
+        self
+    }
+
     /// Order the query results by the given column. Multiple calls to
     /// this method may be made, with earlier calls taking precedence.
     /// It is recommended to use the `colname!`
     /// macro to construct the column name in a type-safe manner.
     pub fn order(mut self, column: &'static str, direction: OrderDirection) -> Query<T> {
-        self.sort.push(Order { direction, column });
+        self.sort.push(Order {
+            direction,
+            column,
+            expr: None,
+        });
         self
     }
 
@@ -184,6 +808,55 @@ pub fn order_asc(self, column: &'static str) -> Query<T> {
     pub fn order_desc(self, column: &'static str) -> Query<T> {
         self.order(column, OrderDirection::Descending)
     }
+
+    /// Order the query results by the value of `expr`, such as
+    /// `expr!(Post, lower(title))`. May be combined with `order`, with
+    /// earlier calls taking precedence.
+    pub fn order_by(mut self, expr: Expr, direction: OrderDirection) -> Query<T> {
+        self.sort.push(Order {
+            direction,
+            column: "",
+            expr: Some(expr),
+        });
+        self
+    }
+
+    /// Shorthand for `order_by(expr, OrderDirection::Ascending)`
+    pub fn order_by_asc(self, expr: Expr) -> Query<T> {
+        self.order_by(expr, OrderDirection::Ascending)
+    }
+
+    /// Shorthand for `order_by(expr, OrderDirection::Descending)`
+    pub fn order_by_desc(self, expr: Expr) -> Query<T> {
+        self.order_by(expr, OrderDirection::Descending)
+    }
+
+    /// Creates a [`BoolExpr`] which evaluates to true if this query
+    /// matches any object, without loading them. Usually used within a
+    /// filter of another query, correlated with the object it filters
+    /// using e.g. [`FieldExpr::matches_outer`]. The limit, offset and
+    /// order of this query are ignored.
+    pub fn exists(self) -> BoolExpr {
+        BoolExpr::Exists {
+            tbl: self.table,
+            expr: Box::new(self.filter.unwrap_or(BoolExpr::True)),
+        }
+    }
+
+    /// Creates an [`Expr`] whose value is `value` evaluated on the first
+    /// object matched by this query, such as an aggregate over them
+    /// like `Expr::Func("max", vec![Expr::Column("likes")])`. Evaluates
+    /// to NULL if no object matches. The order and limit of this query
+    /// are kept, but not its offset.
+    pub fn scalar(self, value: Expr) -> Expr {
+        Expr::Scalar(Box::new(ScalarQuery {
+            table: self.table,
+            value,
+            filter: self.filter.unwrap_or(BoolExpr::True),
+            sort: self.sort,
+            limit: self.limit,
+        }))
+    }
 }
 
 // Explicit impl so that Clone is implemented even if T is not Clone
@@ -195,6 +868,7 @@ fn clone(&self) -> Self {
             limit: self.limit,
             offset: self.offset,
             sort: self.sort.clone(),
+            timeout: self.timeout,
             phantom: PhantomData,
         }
     }
@@ -208,11 +882,13 @@ fn clone(&self) -> Self {
     async(feature = "async")
 )]
 trait QueryOpsInternal<T> {
-    async fn fetch(
+    async fn fetch<B>(
         self,
         conn: &impl ConnectionMethods,
+        columns: &[crate::db::Column],
         limit: Option<i32>,
-    ) -> Result<Box<dyn BackendRows + '_>>;
+        f: impl FnMut(&dyn BackendRow) -> Result<B>,
+    ) -> Result<Vec<B>>;
 }
 #[maybe_async_cfg::maybe(
     idents(ConnectionMethods(sync = "ConnectionMethods"), QueryOpsInternal),
@@ -221,36 +897,129 @@ async fn fetch(
     async(feature = "async")
 )]
 impl<T: DataResult> QueryOpsInternal<T> for Query<T> {
-    async fn fetch(
+    /// Runs the query, mapping each row with `f`. If the filter has an
+    /// `IN` list binding more parameters than a statement may, and the
+    /// query is not ordered, the query is run once for each chunk of
+    /// the list, applying the limit and offset across all of them. The
+    /// chunks of an ordered query would each be ordered separately, so
+    /// its list is instead moved into a temporary table.
+    async fn fetch<B>(
         self,
         conn: &impl ConnectionMethods,
+        columns: &[crate::db::Column],
         limit: Option<i32>,
-    ) -> Result<Box<dyn BackendRows + '_>> {
+        mut f: impl FnMut(&dyn BackendRow) -> Result<B>,
+    ) -> Result<Vec<B>> {
+        let max = conn
+            .max_statement_params()
+            .saturating_sub(Order::param_count(&self.sort));
         let sort = if self.sort.is_empty() {
             None
         } else {
             Some(self.sort.as_slice())
         };
-        conn.query(
-            &self.table,
-            T::COLUMNS,
-            self.filter,
-            limit,
-            self.offset,
-            sort,
-        )
-        .await
+        if sort.is_some() {
+            let list_table = format!(
+                "butane_in_list_{}",
+                NEXT_IN_LIST.fetch_add(1, Ordering::Relaxed)
+            );
+            let replaced = match &self.filter {
+                Some(filter) => filter.replace_in_list(max, &|col| BoolExpr::Subquery {
+                    col,
+                    tbl2: Cow::Owned(list_table.clone()),
+                    tbl2_col: "val",
+                    expr: Box::new(BoolExpr::True),
+                })?,
+                None => None,
+            };
+            if let Some((col, vals, filter)) = replaced {
+                // Copying the column gives the table's column its type
+                conn.execute(&format!(
+                    "CREATE TEMPORARY TABLE {} AS SELECT {} AS val FROM {} WHERE 1 = 0;",
+                    helper::quote_reserved_word(&list_table),
+                    helper::quote_reserved_word(col),
+                    helper::quote_reserved_word(&self.table)
+                ))
+                .await?;
+                let ty = vals
+                    .iter()
+                    .find_map(SqlVal::sqltype)
+                    .unwrap_or(SqlType::Text);
+                let vals: Vec<SqlValRef<'_>> = vals.iter().map(SqlVal::as_ref).collect();
+                let inserted = conn
+                    .insert_only_many(&list_table, &[crate::db::Column::new("val", ty)], &vals)
+                    .await;
+                let results = match inserted {
+                    Ok(()) => match conn
+                        .query(&self.table, columns, Some(filter), limit, self.offset, sort)
+                        .await
+                    {
+                        Ok(rows) => rows.mapped(f).collect(),
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e),
+                };
+                let dropped = conn
+                    .execute(&format!(
+                        "DROP TABLE {};",
+                        helper::quote_reserved_word(&list_table)
+                    ))
+                    .await;
+                let results = results?;
+                dropped?;
+                return Ok(results);
+            }
+        }
+        let chunks = match &self.filter {
+            Some(filter) if sort.is_none() => filter.split_in_list(max)?,
+            _ => None,
+        };
+        let chunks = match chunks {
+            Some((_, chunks)) => chunks,
+            None => {
+                return conn
+                    .query(&self.table, columns, self.filter, limit, self.offset, sort)
+                    .await?
+                    .mapped(f)
+                    .collect();
+            }
+        };
+        let limit = limit.map(|limit| limit.max(0) as usize);
+        let mut skip = self.offset.unwrap_or(0).max(0) as usize;
+        let mut results = Vec::new();
+        for chunk in chunks {
+            let wanted = limit.map(|limit| limit - results.len() + skip);
+            if wanted == Some(0) {
+                break;
+            }
+            let wanted = wanted.map(|wanted| i32::try_from(wanted).unwrap_or(i32::MAX));
+            let mut rows = conn
+                .query(&self.table, columns, Some(chunk), wanted, None, None)
+                .await?;
+            while let Some(row) = rows.next()? {
+                if skip > 0 {
+                    skip -= 1;
+                } else {
+                    results.push(f(row)?);
+                }
+            }
+        }
+        Ok(results)
     }
 }
 
 /// [`Query`] operations which require a `Connection`
 #[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
 #[maybe_async_cfg::maybe(
-    idents(ConnectionMethods(sync = "ConnectionMethods"), QueryOpsInternal),
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        QueryOpsInternal,
+        Transaction(sync = "Transaction")
+    ),
     sync(),
     async(feature = "async")
 )]
-pub trait QueryOps<T> {
+pub trait QueryOps<T: DataResult> {
     /// Executes the query against `conn` and returns the first result (if any).
     async fn load_first(self, conn: &impl ConnectionMethods) -> Result<Option<T>>;
 
@@ -258,14 +1027,65 @@ pub trait QueryOps<T> {
     async fn load(self, conn: &impl ConnectionMethods) -> Result<QueryResult<T>>;
 
     /// Executes the query against `conn` and deletes all matching objects.
+    ///
+    /// The rows of many-to-many relationships they own are deleted too.
     async fn delete(self, conn: &impl ConnectionMethods) -> Result<usize>;
+
+    /// Executes the query against `conn`, returning the results keyed
+    /// by their primary keys.
+    async fn load_map(self, conn: &impl ConnectionMethods) -> Result<HashMap<T::PKType, T>>
+    where
+        T: DataObject,
+        T::PKType: Eq + Hash;
+
+    /// Executes the query against `conn`, returning the results keyed
+    /// by the value `key` returns for each of them, such as one of
+    /// their fields. If several results have the same key, the last
+    /// one is kept.
+    async fn load_map_by<K, F>(
+        self,
+        conn: &impl ConnectionMethods,
+        key: F,
+    ) -> Result<HashMap<K, T>>
+    where
+        K: Eq + Hash,
+        F: FnMut(&T) -> K;
+
+    /// Declares a server-side [`Cursor`] over the results of the query
+    /// within `tx`, from which they may be fetched in pages rather than
+    /// all at once. Only supported by the Postgres backend.
+    async fn declare_cursor(self, tx: &Transaction<'_>) -> Result<Cursor<T>>;
+    /// Executes the query against `conn`, loading only the fields
+    /// selected by `fields` rather than whole objects, as a tuple for
+    /// each result. See [`Projection`].
+    async fn load_fields<P: Projection>(
+        self,
+        conn: &impl ConnectionMethods,
+        fields: P,
+    ) -> Result<QueryResult<P::Output>>;
+
+    /// Executes the query against `conn`, loading the page numbered
+    /// `page`, starting from 1, of `page_size` results, along with the
+    /// total number of results. The limit and offset of the query are
+    /// replaced, so it should be ordered for the pages to be stable.
+    async fn page(
+        self,
+        conn: &impl ConnectionMethods,
+        page: u32,
+        page_size: u32,
+    ) -> Result<Page<T>>;
 }
 
 #[maybe_async_cfg::maybe(
     idents(
         ConnectionMethods(sync = "ConnectionMethods"),
         QueryOps,
-        QueryOpsInternal
+        QueryOpsInternal,
+        Transaction(sync = "Transaction"),
+        bounded(snake),
+        begin(snake),
+        end(snake),
+        delete_chunks(snake)
     ),
     keep_self,
     sync(),
@@ -273,20 +1093,176 @@ pub trait QueryOps<T> {
 )]
 impl<T: DataResult> QueryOps<T> for Query<T> {
     async fn load_first(self, conn: &impl ConnectionMethods) -> Result<Option<T>> {
-        QueryOpsInternal::fetch(self, conn, Some(1))
-            .await?
-            .mapped(T::from_row)
-            .nth(0)
+        deadline::bounded(self.timeout, move || async move {
+            let keys = conn.key_provider();
+            let results = QueryOpsInternal::fetch(self, conn, T::COLUMNS, Some(1), |row| {
+                T::from_row_decrypting(row, keys)
+            });
+            Ok(results.await?.into_iter().next())
+        })
+        .await
     }
     async fn load(self, conn: &impl ConnectionMethods) -> Result<QueryResult<T>> {
-        let limit = self.limit.to_owned();
-        QueryOpsInternal::fetch(self, conn, limit)
-            .await?
-            .mapped(T::from_row)
-            .collect()
+        deadline::bounded(self.timeout, move || async move {
+            let limit = self.limit.to_owned();
+            let keys = conn.key_provider();
+            QueryOpsInternal::fetch(self, conn, T::COLUMNS, limit, |row| {
+                T::from_row_decrypting(row, keys)
+            })
+            .await
+        })
+        .await
     }
     async fn delete(self, conn: &impl ConnectionMethods) -> Result<usize> {
-        conn.delete_where(&self.table, self.filter.unwrap_or(BoolExpr::True))
-            .await
+        deadline::bounded(self.timeout, move || async move {
+            let filter = self.filter.unwrap_or(BoolExpr::True);
+            // Rows matching each chunk of an IN list too long for one
+            // statement are deleted in turn, all or none of them
+            let (chunks, atomic) = match filter.split_in_list(conn.max_statement_params())? {
+                Some((_, chunks)) => (chunks, Some(atomic::begin(conn, SAVEPOINT).await?)),
+                None => (vec![filter], None),
+            };
+            let deleted = delete_chunks::<T::DBO>(conn, &self.table, chunks).await;
+            match atomic {
+                Some(atomic) => atomic::end(atomic, deleted).await,
+                None => deleted,
+            }
+        })
+        .await
+    }
+    async fn load_map(self, conn: &impl ConnectionMethods) -> Result<HashMap<T::PKType, T>>
+    where
+        T: DataObject,
+        T::PKType: Eq + Hash,
+    {
+        QueryOps::load_map_by(self, conn, |obj: &T| obj.pk().clone()).await
+    }
+    async fn load_map_by<K, F>(
+        self,
+        conn: &impl ConnectionMethods,
+        mut key: F,
+    ) -> Result<HashMap<K, T>>
+    where
+        K: Eq + Hash,
+        F: FnMut(&T) -> K,
+    {
+        deadline::bounded(self.timeout, move || async move {
+            let limit = self.limit.to_owned();
+            let keys = conn.key_provider();
+            let entries = QueryOpsInternal::fetch(self, conn, T::COLUMNS, limit, |row| {
+                let obj = T::from_row_decrypting(row, keys)?;
+                Ok((key(&obj), obj))
+            });
+            Ok(entries.await?.into_iter().collect())
+        })
+        .await
+    }
+    async fn declare_cursor(self, tx: &Transaction<'_>) -> Result<Cursor<T>> {
+        deadline::bounded(self.timeout, move || async move {
+            let cursor = Cursor::new();
+            let sort = if self.sort.is_empty() {
+                None
+            } else {
+                Some(self.sort.as_slice())
+            };
+            tx.declare_cursor(
+                cursor.name(),
+                &self.table,
+                T::COLUMNS,
+                self.filter,
+                self.limit,
+                self.offset,
+                sort,
+            )
+            .await?;
+            Ok(cursor)
+        })
+        .await
+    }
+    async fn load_fields<P: Projection>(
+        self,
+        conn: &impl ConnectionMethods,
+        fields: P,
+    ) -> Result<QueryResult<P::Output>> {
+        deadline::bounded(self.timeout, move || async move {
+            let limit = self.limit.to_owned();
+            let columns = fields.columns();
+            QueryOpsInternal::fetch(self, conn, &columns, limit, P::Output::from_row).await
+        })
+        .await
+    }
+    async fn page(
+        self,
+        conn: &impl ConnectionMethods,
+        page: u32,
+        page_size: u32,
+    ) -> Result<Page<T>> {
+        if page == 0 || page_size == 0 {
+            return Err(Error::BoundsError(
+                "page numbers and sizes start from 1".to_string(),
+            ));
+        }
+        let offset = u64::from(page - 1) * u64::from(page_size);
+        let (Ok(limit), Ok(offset)) = (i32::try_from(page_size), i32::try_from(offset)) else {
+            return Err(Error::BoundsError(format!(
+                "page {page} of {page_size} results is out of range"
+            )));
+        };
+        deadline::bounded(self.timeout, move || async move {
+            let sort = if self.sort.is_empty() {
+                None
+            } else {
+                Some(self.sort.as_slice())
+            };
+            let (rows, total) = conn
+                .query_counted(
+                    &self.table,
+                    T::COLUMNS,
+                    self.filter,
+                    Some(limit),
+                    Some(offset),
+                    sort,
+                )
+                .await?;
+            let keys = conn.key_provider();
+            let items = rows
+                .mapped(|row| T::from_row_decrypting(row, keys))
+                .collect()?;
+            Ok(Page::new(items, total, page, page_size))
+        })
+        .await
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        delete_where_counted(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Deletes the objects of `T` in `table` matching each of `chunks` in
+/// turn, for `QueryOps::delete`, returning the number deleted.
+async fn delete_chunks<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    table: &str,
+    chunks: Vec<BoolExpr>,
+) -> Result<usize> {
+    let mut deleted = 0;
+    for chunk in chunks {
+        // The rows of many-to-many relationships owned by the objects
+        // are deleted first, as they refer to them
+        for many_table in <T as DataObjectInternal>::MANY_TABLES {
+            let owned = BoolExpr::Subquery {
+                col: "owner",
+                tbl2: Cow::Owned(table.to_string()),
+                tbl2_col: T::PKCOL,
+                expr: Box::new(chunk.clone()),
+            };
+            conn.delete_where(many_table, owned).await?;
+        }
+        deleted += counter::delete_where_counted::<T>(conn, table, chunk).await?;
     }
+    Ok(deleted)
 }
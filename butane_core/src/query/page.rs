@@ -0,0 +1,41 @@
+//! Pages of the results of a query, for listings which show a page of
+//! results at a time along with the number of pages.
+
+/// A page of the results of a query, loaded with
+/// [`QueryOpsSync::page`](super::QueryOpsSync::page) or its async
+/// counterpart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Page<T> {
+    /// The results on the page, of which there are fewer than the page
+    /// size on the last page and none past it.
+    pub items: Vec<T>,
+    /// The number of results matched by the query over all pages.
+    pub total: u64,
+    /// The number of the page, starting from 1.
+    pub page: u32,
+    /// The number of pages. There is always at least one page, which is
+    /// empty if the query matches nothing.
+    pub pages: u32,
+}
+
+impl<T> Page<T> {
+    pub(super) fn new(items: Vec<T>, total: u64, page: u32, page_size: u32) -> Self {
+        let pages = total.div_ceil(page_size.into()).max(1);
+        Page {
+            items,
+            total,
+            page,
+            pages: pages.try_into().unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Returns true if there is a page after this one.
+    pub fn has_next(&self) -> bool {
+        self.page < self.pages
+    }
+
+    /// Returns true if there is a page before this one.
+    pub fn has_previous(&self) -> bool {
+        self.page > 1
+    }
+}
@@ -0,0 +1,73 @@
+//! Results of queries as tuples of values, rather than as objects of a
+//! model, for reports and other ad-hoc queries.
+
+use crate::db::{BackendRow, Column};
+use crate::query::FieldExpr;
+use crate::{Error, FieldType, Result, SqlVal};
+
+/// A type which a row of the results of a query can be converted to.
+/// Implemented for tuples of up to 12 elements of [`FieldType`]s, each
+/// read from the column in the same position, so that a query may be
+/// loaded without declaring a model or [`DataResult`](crate::DataResult)
+/// for it.
+pub trait FromRow: Sized {
+    /// Metadata for each column. Only the types are meaningful, as the
+    /// columns are read by position.
+    const COLUMNS: &'static [Column];
+
+    /// Converts a row.
+    fn from_row(row: &(dyn BackendRow + '_)) -> Result<Self>;
+}
+
+/// A selection of fields of a model to load instead of whole objects,
+/// with [`QueryOpsSync::load_fields`](super::QueryOpsSync::load_fields)
+/// or its async counterpart. Implemented for tuples of up to 12
+/// [`FieldExpr`]s, such as `(Post::fields().title(), Post::fields().likes())`,
+/// which load tuples of the types of those fields.
+pub trait Projection {
+    /// The type each result is loaded as.
+    type Output: FromRow;
+
+    /// The columns selected.
+    fn columns(&self) -> Vec<Column>;
+}
+
+macro_rules! impl_tuple {
+    ($len:literal; $($t:ident $i:tt),+) => {
+        impl<$($t: FieldType),+> FromRow for ($($t,)+) {
+            const COLUMNS: &'static [Column] = &[$(Column::new("", $t::SQLTYPE)),+];
+
+            fn from_row(row: &(dyn BackendRow + '_)) -> Result<Self> {
+                if row.len() != $len {
+                    return Err(Error::BoundsError(format!(
+                        "Found {} columns in row for a tuple of {}",
+                        row.len(),
+                        $len
+                    )));
+                }
+                Ok(($($t::from_sql_ref(row.get($i, $t::SQLTYPE)?)?,)+))
+            }
+        }
+
+        impl<$($t: FieldType + Into<SqlVal>),+> Projection for ($(FieldExpr<$t>,)+) {
+            type Output = ($($t,)+);
+
+            fn columns(&self) -> Vec<Column> {
+                vec![$(Column::new(self.$i.name(), $t::SQLTYPE)),+]
+            }
+        }
+    };
+}
+
+impl_tuple!(1; A 0);
+impl_tuple!(2; A 0, B 1);
+impl_tuple!(3; A 0, B 1, C 2);
+impl_tuple!(4; A 0, B 1, C 2, D 3);
+impl_tuple!(5; A 0, B 1, C 2, D 3, E 4);
+impl_tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+impl_tuple!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_tuple!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_tuple!(9; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_tuple!(10; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_tuple!(11; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_tuple!(12; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
@@ -0,0 +1,237 @@
+//! Updates of many rows at once, setting columns to expressions which
+//! may refer to the current values of the row.
+
+use std::marker::PhantomData;
+
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{atomic, ConnectionMethods};
+use crate::query::{BoolExpr, Expr};
+use crate::{counter, subtype, DataObject, Error, Result, ToSql};
+
+/// Representation of an update of all the objects of a model matching
+/// a filter, usually constructed with the `update!` macro.
+///
+/// Each column is set to an [`Expr`] evaluated by the database against
+/// the row being updated, so e.g. a counter may be incremented without
+/// first loading it. The update is a single `UPDATE` statement.
+/// See [`UpdateOpsSync`] and [`UpdateOpsAsync`] to run it.
+#[derive(Debug)]
+pub struct Update<T: DataObject> {
+    assignments: Vec<(&'static str, Expr)>,
+    filter: Option<BoolExpr>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: DataObject> Update<T> {
+    /// Creates an update which sets no columns, of all objects. Those of
+    /// other [subtypes](crate::subtype) sharing the table are not updated.
+    pub fn new() -> Self {
+        Update {
+            assignments: Vec::new(),
+            filter: subtype::restrict::<T>(None),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets `column` to the value of `expr`. Returns `self` as this
+    /// method is expected to be chained.
+    pub fn set(mut self, column: &'static str, expr: Expr) -> Self {
+        self.assignments.push((column, expr));
+        self
+    }
+
+    /// Restricts the update to objects for which `expr` is true. The
+    /// expression is [normalized](BoolExpr::normalize). Returns `self`
+    /// as this method is expected to be chained.
+    pub fn filter(mut self, expr: BoolExpr) -> Self {
+        self.filter = subtype::restrict::<T>(Some(expr)).map(BoolExpr::normalize);
+        self
+    }
+}
+
+impl<T: DataObject> Default for Update<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Explicit impl so that Clone is implemented even if T is not Clone
+impl<T: DataObject> Clone for Update<T> {
+    fn clone(&self) -> Self {
+        Update {
+            assignments: self.assignments.clone(),
+            filter: self.filter.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Changes to some of the fields of an object, usually generated for a
+/// model `Post` as `PostPatch` by `#[butane(patch)]` on the model.
+///
+/// Each field of the patch is an `Option` of the type of the
+/// corresponding field of the model, and only those which are `Some`
+/// are changed, which suits e.g. the body of an HTTP `PATCH` request.
+/// See [`PatchOpsSync`] and [`PatchOpsAsync`] to apply a patch in the
+/// database without loading the object.
+pub trait Patch {
+    /// The model patched.
+    type Model: DataObject;
+
+    /// Sets the fields of `obj` which are given by the patch.
+    fn apply(self, obj: &mut Self::Model);
+
+    /// Converts the patch into an update of the fields it gives, of
+    /// all objects.
+    fn into_update(self) -> Update<Self::Model>;
+}
+
+/// [`Update`] operations which require a `Connection`
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(),
+    async(feature = "async")
+)]
+pub trait UpdateOps {
+    /// Executes the update against `conn`, returning the number of
+    /// objects updated. Fails with [`Error::Immutable`] for a model
+    /// declared with `#[butane(immutable)]`. If the filter has an `IN`
+    /// list too long to bind in one statement, one statement is run for
+    /// each chunk of it.
+    async fn execute(self, conn: &impl ConnectionMethods) -> Result<usize>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        UpdateOps,
+        begin_change(snake),
+        end_change(snake),
+        update_counted(snake)
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+impl<T: DataObject> UpdateOps for Update<T> {
+    async fn execute(self, conn: &impl ConnectionMethods) -> Result<usize> {
+        if T::IMMUTABLE {
+            return Err(Error::Immutable(T::TABLE));
+        }
+        if self.assignments.is_empty() {
+            return Ok(0);
+        }
+        let filter = self.filter.unwrap_or(BoolExpr::True);
+        let atomic = counter::begin_change::<T>(conn).await?;
+        let result = update_counted::<T>(conn, self.assignments, filter).await;
+        counter::end_change(atomic, result).await
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        pks_where(snake),
+        counted_by_pks(snake),
+        adjust(snake),
+        begin(snake),
+        end(snake),
+        update_chunks(snake)
+    ),
+    sync(),
+    async(feature = "async")
+)]
+/// Sets `assignments` in the objects of `T` matching `filter`, for
+/// `UpdateOps::execute`, and adjusts the counters this affects.
+async fn update_counted<T: DataObject>(
+    conn: &impl ConnectionMethods,
+    assignments: Vec<(&'static str, Expr)>,
+    filter: BoolExpr,
+) -> Result<usize> {
+    // Setting a foreign key with a counter cache moves the objects
+    // between the counters, so those updated are found beforehand
+    let counted = assignments
+        .iter()
+        .any(|(set, _)| T::COUNTER_CACHES.iter().any(|cache| cache.column == *set));
+    let pks = if counted {
+        counter::pks_where::<T>(conn, filter.clone()).await?
+    } else {
+        Vec::new()
+    };
+    let before = counter::counted_by_pks::<T>(conn, &pks).await?;
+    // Rows matching each chunk of an IN list too long for one
+    // statement are updated in turn, unless the column listed is
+    // set, when a row could match a later chunk once updated
+    let assigned: usize = assignments.iter().map(|(_, val)| val.param_count()).sum();
+    let max = conn.max_statement_params().saturating_sub(assigned);
+    let updated = match filter.split_in_list(max)? {
+        Some((col, chunks)) if !assignments.iter().any(|(set, _)| *set == col) => {
+            // All or none of the chunks are updated
+            let atomic = atomic::begin(conn, super::SAVEPOINT).await?;
+            let updated = update_chunks(conn, T::TABLE, assignments, chunks).await;
+            atomic::end(atomic, updated).await?
+        }
+        _ => conn.update_where(T::TABLE, assignments, filter).await?,
+    };
+    let after = counter::counted_by_pks::<T>(conn, &pks).await?;
+    counter::adjust::<T>(conn, before, after).await?;
+    Ok(updated)
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(),
+    async(feature = "async")
+)]
+/// Sets `assignments` in the rows of `table` matching each of `chunks`
+/// in turn, returning the number updated.
+async fn update_chunks(
+    conn: &impl ConnectionMethods,
+    table: &str,
+    assignments: Vec<(&'static str, Expr)>,
+    chunks: Vec<BoolExpr>,
+) -> Result<usize> {
+    let mut updated = 0;
+    for chunk in chunks {
+        updated += conn.update_where(table, assignments.clone(), chunk).await?;
+    }
+    Ok(updated)
+}
+
+/// [`Patch`] operations which require a `Connection`
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(),
+    async(feature = "async")
+)]
+pub trait PatchOps {
+    /// Changes the fields given by the patch of the object with primary
+    /// key `pk`, in a single `UPDATE` statement. Returns
+    /// [`Error::NoSuchObject`] if there is no such object.
+    async fn patch(self, conn: &impl ConnectionMethods, pk: impl ToSql) -> Result<()>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"), PatchOps, UpdateOps),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+impl<P: Patch> PatchOps for P {
+    async fn patch(self, conn: &impl ConnectionMethods, pk: impl ToSql) -> Result<()> {
+        let update = self.into_update();
+        if update.assignments.is_empty() {
+            return Ok(());
+        }
+        let pkcol = <P::Model as DataObject>::PKCOL;
+        let update = update.filter(BoolExpr::Eq(pkcol, Expr::Val(pk.to_sql())));
+        let updated = UpdateOps::execute(update, conn).await?;
+        if updated == 0 {
+            return Err(Error::NoSuchObject);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+//! A registry of the models compiled into the application, for generic
+//! tooling such as admin panels.
+//!
+//! With the `registry` feature, each `#[model]` registers a
+//! [`ModelInfo`] describing its table, columns, primary key and
+//! relationships. It also lists, gets and updates the objects of the
+//! model through a `&dyn ConnectionMethods`, with [`ModelOpsSync`] or
+//! [`ModelOpsAsync`], without the model type being known:
+//!
+//! ```ignore
+//! for model in butane::registry::models() {
+//!     let objects = model.list(&conn, Some(20), None)?;
+//!     println!("{}: {} objects", model.name(), objects.len());
+//! }
+//! let post = butane::registry::model("Post").unwrap();
+//! post.update(&conn, 1.into(), vec![("title".to_string(), "Renamed".into())])?;
+//! ```
+//!
+//! Models backed by a view are registered too, but may not be updated,
+//! nor may audited or immutable models, nor encrypted columns.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+#[doc(hidden)]
+pub use inventory;
+
+use crate::counter::CounterCache;
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{self, Column, ConnectionMethods};
+use crate::migrations::adb::OnDelete;
+use crate::query::{BoolExpr, Expr, QueryOpsSync, Update, UpdateOpsSync};
+#[cfg(feature = "async")]
+use crate::query::{QueryOpsAsync, UpdateOpsAsync};
+use crate::{DataObject, DynDataObject, Error, Result, SqlVal};
+
+/// A relationship of a registered model to another model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relationship {
+    /// A [`ForeignKey`](crate::fkey::ForeignKey) field.
+    ForeignKey {
+        /// The column holding the primary key of the object referred to.
+        column: &'static str,
+        /// The table of the model referred to.
+        target: &'static str,
+        /// What a [cascading delete](crate::cascade) does to the objects
+        /// referring through the field: the action declared with
+        /// `on_delete`, or [`OnDelete::Cascade`] if none is. `None` for a
+        /// field declared with `no_foreign_key`, whose objects are left
+        /// referring to the deleted object.
+        on_delete: Option<OnDelete>,
+    },
+    /// A [`Many`](crate::many::Many) or
+    /// [`OrderedMany`](crate::many::OrderedMany) field.
+    Many {
+        /// The name of the field.
+        field: &'static str,
+        /// The table holding the relationship.
+        table: &'static str,
+        /// The table of the model referred to.
+        target: &'static str,
+    },
+}
+
+/// Description of a model registered by `#[model]`, which also
+/// implements [`ModelOpsSync`] and [`ModelOpsAsync`] for its objects.
+pub struct ModelInfo {
+    name: &'static str,
+    table: &'static str,
+    pkcol: &'static str,
+    auto_pk: bool,
+    immutable: bool,
+    audited: bool,
+    view: bool,
+    columns: &'static [Column],
+    encrypted: &'static [&'static str],
+    relationships: &'static [Relationship],
+    counter_caches: &'static [CounterCache],
+    ops_sync: &'static dyn ModelOpsSync,
+    #[cfg(feature = "async")]
+    ops_async: &'static dyn ModelOpsAsync,
+}
+
+impl ModelInfo {
+    /// Describes the model `T`, called `name`, which is backed by a
+    /// view if `view` is true and has the `encrypted` columns. Used by
+    /// `#[model]`.
+    #[doc(hidden)]
+    pub const fn new<T: DataObject + DynDataObject + 'static>(
+        name: &'static str,
+        view: bool,
+        relationships: &'static [Relationship],
+        encrypted: &'static [&'static str],
+    ) -> Self {
+        ModelInfo {
+            name,
+            table: T::TABLE,
+            pkcol: T::PKCOL,
+            auto_pk: T::AUTO_PK,
+            immutable: T::IMMUTABLE,
+            audited: T::AUDIT_TABLE.is_some(),
+            view,
+            columns: T::COLUMNS,
+            encrypted,
+            relationships,
+            counter_caches: T::COUNTER_CACHES,
+            ops_sync: ModelHandle::<T>::OPS_SYNC,
+            #[cfg(feature = "async")]
+            ops_async: ModelHandle::<T>::OPS_ASYNC,
+        }
+    }
+
+    /// The name of the model type.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The name of the table.
+    pub fn table(&self) -> &'static str {
+        self.table
+    }
+
+    /// Metadata for each column, in the same order as
+    /// [`DynDataObject::to_values`].
+    pub fn columns(&self) -> &'static [Column] {
+        self.columns
+    }
+
+    /// The name of the primary key column.
+    pub fn pk_column(&self) -> &'static str {
+        self.pkcol
+    }
+
+    /// Whether the primary key is an [`AutoPk`](crate::AutoPk) set by
+    /// the database.
+    pub fn auto_pk(&self) -> bool {
+        self.auto_pk
+    }
+
+    /// Whether the model is declared with `#[butane(immutable)]`, so
+    /// that its objects may not be updated.
+    pub fn immutable(&self) -> bool {
+        self.immutable
+    }
+
+    /// Whether the model is declared with `#[butane(audited)]`, so that
+    /// its objects may only be changed by saving them, which records
+    /// their history.
+    pub fn audited(&self) -> bool {
+        self.audited
+    }
+
+    /// Whether the model is backed by a view rather than a table.
+    pub fn view(&self) -> bool {
+        self.view
+    }
+
+    /// The `ForeignKey` and `Many` relationships of the model.
+    pub fn relationships(&self) -> &'static [Relationship] {
+        self.relationships
+    }
+
+    /// The counter caches kept by the `ForeignKey` fields of the model.
+    pub fn counter_caches(&self) -> &'static [CounterCache] {
+        self.counter_caches
+    }
+}
+
+impl fmt::Debug for ModelInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModelInfo")
+            .field("name", &self.name)
+            .field("table", &self.table)
+            .field("pkcol", &self.pkcol)
+            .field("columns", &self.columns)
+            .field("relationships", &self.relationships)
+            .finish_non_exhaustive()
+    }
+}
+
+fn ops_sync(model: &ModelInfo) -> &'static dyn ModelOpsSync {
+    model.ops_sync
+}
+
+#[cfg(feature = "async")]
+fn ops_async(model: &ModelInfo) -> &'static dyn ModelOpsAsync {
+    model.ops_async
+}
+
+inventory::collect!(ModelInfo);
+
+/// Returns every registered model, in no particular order.
+pub fn models() -> impl Iterator<Item = &'static ModelInfo> {
+    inventory::iter::<ModelInfo>.into_iter()
+}
+
+/// Returns the registered model whose type or table is called `name`.
+pub fn model(name: &str) -> Option<&'static ModelInfo> {
+    models().find(|model| model.name == name || model.table == name)
+}
+
+/// Object-safe operations on the objects of a registered model, which
+/// require a connection.
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(),
+    async(feature = "async")
+)]
+#[async_trait::async_trait(?Send)]
+pub trait ModelOps: Sync {
+    /// Loads objects in order of primary key, skipping the first
+    /// `offset` and returning at most `limit`.
+    async fn list(
+        &self,
+        conn: &dyn ConnectionMethods,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Box<dyn DynDataObject>>>;
+
+    /// Loads the object with primary key `pk`, if there is one.
+    async fn get(
+        &self,
+        conn: &dyn ConnectionMethods,
+        pk: SqlVal,
+    ) -> Result<Option<Box<dyn DynDataObject>>>;
+
+    /// Sets the columns named in `values` of the object with primary
+    /// key `pk`, in a single `UPDATE` statement as for a patch. The values
+    /// are written as given: the model's `validate` is not run. Returns
+    /// [`Error::NoSuchObject`] if there is no such object,
+    /// [`Error::UnknownColumn`] for a column which does not exist or is
+    /// the primary key, automatic, readonly or encrypted,
+    /// [`Error::Audited`] if the model is audited and
+    /// [`Error::Immutable`] if it is immutable.
+    async fn update(
+        &self,
+        conn: &dyn ConnectionMethods,
+        pk: SqlVal,
+        values: Vec<(String, SqlVal)>,
+    ) -> Result<()>;
+}
+
+/// Implements the operations of [`ModelInfo`] for the model `T`.
+struct ModelHandle<T>(PhantomData<fn() -> T>);
+
+impl<T: DataObject + DynDataObject + 'static> ModelHandle<T> {
+    const OPS_SYNC: &'static dyn ModelOpsSync = &ModelHandle::<T>(PhantomData);
+    #[cfg(feature = "async")]
+    const OPS_ASYNC: &'static dyn ModelOpsAsync = &ModelHandle::<T>(PhantomData);
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        DynConnection(sync = "DynConnection"),
+        ModelOps,
+        QueryOps,
+        UpdateOps
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+#[async_trait::async_trait(?Send)]
+impl<T: DataObject + DynDataObject + 'static> ModelOps for ModelHandle<T> {
+    async fn list(
+        &self,
+        conn: &dyn ConnectionMethods,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Box<dyn DynDataObject>>> {
+        let mut query = T::query().order_asc(T::PKCOL);
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+        let objects = QueryOps::load(query, &db::DynConnection(conn)).await?;
+        Ok(objects
+            .into_iter()
+            .map(|obj| Box::new(obj) as Box<dyn DynDataObject>)
+            .collect())
+    }
+
+    async fn get(
+        &self,
+        conn: &dyn ConnectionMethods,
+        pk: SqlVal,
+    ) -> Result<Option<Box<dyn DynDataObject>>> {
+        let query = T::query().filter(BoolExpr::Eq(T::PKCOL, Expr::Val(pk)));
+        let obj = QueryOps::load_first(query, &db::DynConnection(conn)).await?;
+        Ok(obj.map(|obj| Box::new(obj) as Box<dyn DynDataObject>))
+    }
+
+    async fn update(
+        &self,
+        conn: &dyn ConnectionMethods,
+        pk: SqlVal,
+        values: Vec<(String, SqlVal)>,
+    ) -> Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let mut update = Update::<T>::new().filter(BoolExpr::Eq(T::PKCOL, Expr::Val(pk)));
+        for (name, value) in values {
+            let Some(column) = T::NON_AUTO_COLUMNS
+                .iter()
+                .find(|col| col.name() == name && col.name() != T::PKCOL)
+            else {
+                return Err(Error::UnknownColumn(T::TABLE, name));
+            };
+            update = update.set(column.name(), Expr::Val(value));
+        }
+        let updated = UpdateOps::execute(update, &db::DynConnection(conn)).await?;
+        if updated == 0 {
+            return Err(Error::NoSuchObject);
+        }
+        Ok(())
+    }
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"), ModelOps, ops(snake)),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+#[async_trait::async_trait(?Send)]
+impl ModelOps for ModelInfo {
+    async fn list(
+        &self,
+        conn: &dyn ConnectionMethods,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Box<dyn DynDataObject>>> {
+        ops(self).list(conn, limit, offset).await
+    }
+
+    async fn get(
+        &self,
+        conn: &dyn ConnectionMethods,
+        pk: SqlVal,
+    ) -> Result<Option<Box<dyn DynDataObject>>> {
+        ops(self).get(conn, pk).await
+    }
+
+    async fn update(
+        &self,
+        conn: &dyn ConnectionMethods,
+        pk: SqlVal,
+        values: Vec<(String, SqlVal)>,
+    ) -> Result<()> {
+        // Saving the object records its history, which this would skip
+        if self.audited {
+            return Err(Error::Audited(self.table));
+        }
+        // Only the model can encrypt the value, bound to its column and
+        // the primary key of the object
+        if let Some((name, _)) = values
+            .iter()
+            .find(|(name, _)| self.encrypted.contains(&name.as_str()))
+        {
+            return Err(Error::UnknownColumn(self.table, name.clone()));
+        }
+        ops(self).update(conn, pk, values).await
+    }
+}
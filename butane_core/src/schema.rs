@@ -0,0 +1,194 @@
+//! Verification at startup that the database has been migrated to the
+//! schema the application was built with.
+//!
+//! The `schema_fingerprint!` macro of the `butane` crate expands to the
+//! [`SchemaFingerprint`] of the latest migration when the application is
+//! compiled, which [`verify_schema`] compares with the tables the
+//! database reports, so that an application run against a database
+//! which has not been migrated fails fast rather than on the first query
+//! touching a changed table:
+//!
+//! ```ignore
+//! butane::verify_schema(&conn, &butane::schema_fingerprint!())?;
+//! ```
+//!
+//! The fingerprint covers the names of the tables and of their columns,
+//! the class of values each column holds (integer, real, text, blob or
+//! other), and which columns are nullable or part of the primary key.
+//! Only the class of a type is compared because not every backend can
+//! tell more: SQLite, for instance, reports booleans and 64 bit integers
+//! as `INTEGER`, and timestamps as `TEXT`. Names are compared ignoring
+//! case, as some databases fold unquoted names. Views, defaults, indexes
+//! and constraints are not compared.
+
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{ColumnSchema, ConnectionMethods};
+use crate::migrations::adb::{TypeIdentifier, ADB};
+use crate::{Error, Result, SqlType};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The fingerprint of each table of a schema, usually created by the
+/// `schema_fingerprint!` macro.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchemaFingerprint {
+    tables: &'static [(&'static str, u64)],
+}
+
+impl SchemaFingerprint {
+    /// Creates a fingerprint from that of each table, as computed by
+    /// [`table_fingerprints`].
+    #[doc(hidden)]
+    pub const fn new(tables: &'static [(&'static str, u64)]) -> Self {
+        SchemaFingerprint { tables }
+    }
+
+    /// The names of the tables of the schema.
+    pub fn tables(&self) -> impl Iterator<Item = &'static str> {
+        self.tables.iter().map(|(name, _)| *name)
+    }
+
+    /// A hash of the whole schema, such as to log which schema an
+    /// application expects.
+    pub fn hash(&self) -> u64 {
+        self.tables.iter().fold(FNV_OFFSET, |hash, (_, table)| {
+            fnv(hash, &table.to_le_bytes())
+        })
+    }
+}
+
+/// Computes the fingerprint of each table of `db`, in order of name.
+pub fn table_fingerprints(db: &ADB) -> Vec<(String, u64)> {
+    let mut tables: Vec<(String, u64)> = db
+        .tables()
+        .filter(|table| !table.is_view())
+        .map(|table| {
+            let columns = table.columns.iter().map(|col| {
+                let class = match col.typeid() {
+                    Ok(TypeIdentifier::Ty(ty)) => TypeClass::of_type(&ty),
+                    Ok(TypeIdentifier::Name(name)) => TypeClass::of_name(&name),
+                    Err(_) => TypeClass::Other,
+                };
+                (col.name(), class, col.nullable(), col.is_pk())
+            });
+            (table.name.clone(), table_hash(&table.name, columns))
+        })
+        .collect();
+    tables.sort();
+    tables
+}
+
+/// Checks that the tables of the database `conn` match `expected`,
+/// failing with [`Error::SchemaMismatch`] listing the tables which are
+/// missing or differ if not.
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(keep_self),
+    async(feature = "async", self = "verify_schema_async")
+)]
+pub async fn verify_schema(
+    conn: &impl ConnectionMethods,
+    expected: &SchemaFingerprint,
+) -> Result<()> {
+    let mut mismatched = Vec::new();
+    for (table, hash) in expected.tables {
+        let found = conn.table_schema(table).await?.map(|schema| {
+            let columns = schema.columns.iter().map(|col: &ColumnSchema| {
+                (
+                    col.name.as_str(),
+                    TypeClass::of_name(&col.type_name),
+                    col.nullable,
+                    col.primary_key,
+                )
+            });
+            table_hash(table, columns)
+        });
+        if found != Some(*hash) {
+            mismatched.push(table.to_string());
+        }
+    }
+    if !mismatched.is_empty() {
+        return Err(Error::SchemaMismatch { tables: mismatched });
+    }
+    Ok(())
+}
+
+/// The class of values held by a column, which is all of its type that
+/// every backend reports alike.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum TypeClass {
+    Integer,
+    Real,
+    Text,
+    Blob,
+    Other,
+}
+
+impl TypeClass {
+    fn of_type(ty: &SqlType) -> Self {
+        match ty {
+            SqlType::Bool | SqlType::Int | SqlType::BigInt => TypeClass::Integer,
+            SqlType::Real => TypeClass::Real,
+            SqlType::Text | SqlType::TextList => TypeClass::Text,
+            #[cfg(feature = "datetime")]
+            SqlType::Date | SqlType::Timestamp => TypeClass::Text,
+            #[cfg(feature = "json")]
+            SqlType::Json => TypeClass::Text,
+            SqlType::Blob => TypeClass::Blob,
+            SqlType::Custom(_) => TypeClass::Other,
+        }
+    }
+
+    /// The class of the type named `name`, as in migrations or as
+    /// reported by the database, such as `INTEGER` on SQLite or
+    /// `timestamp without time zone` on Postgres.
+    fn of_name(name: &str) -> Self {
+        let name = name.trim().to_lowercase();
+        match name.as_str() {
+            "bool" | "boolean" | "int" | "integer" | "smallint" | "bigint" | "serial"
+            | "bigserial" => TypeClass::Integer,
+            "real" | "float" | "double" | "double precision" => TypeClass::Real,
+            "text" | "date" | "json" | "jsonb" => TypeClass::Text,
+            "blob" | "bytea" => TypeClass::Blob,
+            _ if name.ends_with("[]")
+                || name.starts_with("timestamp")
+                || name.starts_with("character")
+                || name.starts_with("varchar") =>
+            {
+                TypeClass::Text
+            }
+            _ => TypeClass::Other,
+        }
+    }
+}
+
+/// Hashes a table from its name and the name, class of type,
+/// nullability and whether each column is part of the primary key,
+/// regardless of the order of the columns.
+fn table_hash<'a>(
+    name: &str,
+    columns: impl Iterator<Item = (&'a str, TypeClass, bool, bool)>,
+) -> u64 {
+    let mut columns: Vec<(String, TypeClass, bool, bool)> = columns
+        .map(|(name, class, nullable, pk)| (name.to_lowercase(), class, nullable, pk))
+        .collect();
+    columns.sort();
+    let mut hash = fnv(FNV_OFFSET, name.to_lowercase().as_bytes());
+    for (name, class, nullable, pk) in columns {
+        hash = fnv(hash, &[0]);
+        hash = fnv(hash, name.as_bytes());
+        hash = fnv(hash, &[0, class as u8, nullable.into(), pk.into()]);
+    }
+    hash
+}
+
+/// Continues the 64 bit FNV-1a hash `hash` with `bytes`, which is
+/// stable across platforms and compiler versions unlike the hashers of
+/// the standard library.
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
@@ -0,0 +1,194 @@
+//! Reproducible fake datasets, for demo environments and load testing.
+//!
+//! A [`Seeder`] generates objects of each model with [`fake`] from an
+//! RNG seeded with a given seed, so the same seed and counts produce
+//! the same dataset. Models are seeded one at a time, and a
+//! [`ForeignKey`](crate::fkey::ForeignKey) or [`Many`](crate::many::Many)
+//! field of a generated object refers to objects of the models seeded
+//! before it, so parents must be seeded before their children:
+//!
+//! ```ignore
+//! let mut seeder = Seeder::new(42);
+//! seeder.insert::<Blog>(&conn, 5)?;
+//! seeder.insert::<Tag>(&conn, 20)?;
+//! let posts: Vec<Post> = seeder.insert(&conn, 100)?;
+//! ```
+//!
+//! Objects of models with an [`AutoPk`](crate::AutoPk) are only
+//! reproduced if the database assigns the same primary keys, as it
+//! does when seeding an empty database. The sequence of values of the
+//! RNG may change between versions of `rand`.
+#![deny(missing_docs)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use fake::{Dummy, Fake, Faker};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{self, ConnectionMethods};
+use crate::{DataObject, Result, SqlVal, SqlValRef, ToSql, WritableDataObject};
+
+/// The most objects a generated [`Many`](crate::many::Many) refers to.
+const MAX_SEEDED_MANY: usize = 3;
+
+thread_local! {
+    /// Primary keys of the objects of the [`Seeder`] generating objects
+    /// on this thread, by table.
+    static SEEDED: RefCell<HashMap<&'static str, Vec<SqlVal>>> = RefCell::new(HashMap::new());
+}
+
+/// Generates and inserts a reproducible fake dataset. See the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct Seeder {
+    rng: StdRng,
+    seeded: HashMap<&'static str, Vec<SqlVal>>,
+}
+
+impl Seeder {
+    /// Creates a seeder generating objects from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Seeder {
+            rng: StdRng::seed_from_u64(seed),
+            seeded: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of objects of `T` seeded so far.
+    pub fn count<T: DataObject>(&self) -> usize {
+        self.seeded.get(T::TABLE).map_or(0, Vec::len)
+    }
+
+    fn generate<T: DataObject + Dummy<Faker>>(&mut self, count: usize) -> Vec<T> {
+        let rng = &mut self.rng;
+        let _guard = SeededGuard::install(&mut self.seeded);
+        (0..count).map(|_| Faker.fake_with_rng(rng)).collect()
+    }
+
+    fn record<T: DataObject>(&mut self, items: &[T]) {
+        self.seeded
+            .entry(T::TABLE)
+            .or_default()
+            .extend(items.iter().map(|item| item.pk().to_sql()));
+    }
+}
+
+/// [`Seeder`] operations which require a `Connection`.
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods")),
+    sync(),
+    async(feature = "async")
+)]
+pub trait SeederOps {
+    /// Generates `count` objects of `T` and inserts them, returning
+    /// them.
+    ///
+    /// Objects are inserted in bulk, with as few statements as
+    /// possible, unless `T` has an [`AutoPk`](crate::AutoPk),
+    /// many-to-many relationships, or columns or behaviour which need
+    /// each object to be saved individually. Run within a transaction
+    /// to insert all the objects, or none if one fails.
+    async fn insert<T>(&mut self, conn: &impl ConnectionMethods, count: usize) -> Result<Vec<T>>
+    where
+        T: WritableDataObject + Dummy<Faker>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        DataObjectOps,
+        SeederOps
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+impl SeederOps for Seeder {
+    async fn insert<T>(&mut self, conn: &impl ConnectionMethods, count: usize) -> Result<Vec<T>>
+    where
+        T: WritableDataObject + Dummy<Faker>,
+    {
+        let mut items: Vec<T> = self.generate(count);
+        if insertable_in_bulk::<T>() {
+            for item in &mut items {
+                item.generate_pk();
+                item.validate()?;
+                item.encrypt_fields(conn.key_provider())?;
+            }
+            db::note_sensitive_columns(T::TABLE, T::COLUMNS);
+            let values: Vec<SqlValRef<'_>> = items
+                .iter()
+                .flat_map(|item| item.non_auto_values(true))
+                .collect();
+            conn.insert_only_many(T::TABLE, T::NON_AUTO_COLUMNS, &values)
+                .await?;
+        } else {
+            for item in &mut items {
+                crate::DataObjectOps::save(item, conn).await?;
+            }
+        }
+        self.record(&items);
+        Ok(items)
+    }
+}
+
+/// Returns true if objects of `T` may be inserted with a single
+/// statement, as saving them one at a time does nothing more.
+fn insertable_in_bulk<T: DataObject>() -> bool {
+    !T::AUTO_PK
+        && T::MANY_TABLES.is_empty()
+        && T::REFRESHED_COLUMNS.is_empty()
+        && T::NOTIFY_CHANNEL.is_none()
+        && T::AUDIT_TABLE.is_none()
+        && T::COUNTER_CACHES.is_empty()
+}
+
+/// Makes the objects seeded by a [`Seeder`] available to the `Dummy`
+/// implementations of relationships while it generates objects.
+struct SeededGuard<'a> {
+    seeded: &'a mut HashMap<&'static str, Vec<SqlVal>>,
+}
+
+impl<'a> SeededGuard<'a> {
+    fn install(seeded: &'a mut HashMap<&'static str, Vec<SqlVal>>) -> Self {
+        SEEDED.with(|current| std::mem::swap(&mut *current.borrow_mut(), seeded));
+        SeededGuard { seeded }
+    }
+}
+
+impl Drop for SeededGuard<'_> {
+    fn drop(&mut self) {
+        SEEDED.with(|current| std::mem::swap(&mut *current.borrow_mut(), self.seeded));
+    }
+}
+
+/// Picks the primary key of an object of `table` seeded by the
+/// [`Seeder`] generating objects on this thread, if any.
+pub(crate) fn seeded_pk<R: Rng + ?Sized>(table: &str, rng: &mut R) -> Option<SqlVal> {
+    SEEDED.with(|seeded| {
+        let seeded = seeded.borrow();
+        let pks = seeded.get(table).filter(|pks| !pks.is_empty())?;
+        Some(pks[rng.random_range(0..pks.len())].clone())
+    })
+}
+
+/// Picks the primary keys of up to a few distinct objects of `table`
+/// seeded by the [`Seeder`] generating objects on this thread.
+pub(crate) fn seeded_pks<R: Rng + ?Sized>(table: &str, rng: &mut R) -> Vec<SqlVal> {
+    SEEDED.with(|seeded| {
+        let seeded = seeded.borrow();
+        let Some(pks) = seeded.get(table) else {
+            return Vec::new();
+        };
+        let amount = rng.random_range(0..=pks.len().min(MAX_SEEDED_MANY));
+        rand::seq::index::sample(rng, pks.len(), amount)
+            .into_iter()
+            .map(|i| pks[i].clone())
+            .collect()
+    })
+}
@@ -28,6 +28,7 @@ pub enum SqlValRef<'a> {
     Real(f64),
     Text(&'a str),
     Blob(&'a [u8]),
+    TextList(Cow<'a, [String]>),
     #[cfg(feature = "json")]
     Json(serde_json::Value),
     #[cfg(feature = "datetime")]
@@ -51,6 +52,7 @@ pub fn sqltype(&self) -> Option<SqlType> {
             #[cfg(feature = "datetime")]
             SqlValRef::Timestamp(_) => Some(SqlType::Timestamp),
             SqlValRef::Blob(_) => Some(SqlType::Blob),
+            SqlValRef::TextList(_) => Some(SqlType::TextList),
             #[cfg(feature = "json")]
             SqlValRef::Json(_) => Some(SqlType::Json),
             #[cfg(feature = "pg")]
@@ -83,6 +85,7 @@ pub enum SqlVal {
     Real(f64),
     Text(String),
     Blob(Vec<u8>),
+    TextList(Vec<String>),
     #[cfg(feature = "json")]
     Json(serde_json::Value),
     #[cfg(feature = "datetime")]
@@ -174,6 +177,7 @@ pub fn sqltype(&self) -> Option<SqlType> {
             #[cfg(feature = "datetime")]
             SqlVal::Timestamp(_) => Some(SqlType::Timestamp),
             SqlVal::Blob(_) => Some(SqlType::Blob),
+            SqlVal::TextList(_) => Some(SqlType::TextList),
             #[cfg(feature = "json")]
             SqlVal::Json(_) => Some(SqlType::Json),
             #[cfg(feature = "pg")]
@@ -196,6 +200,7 @@ fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             Real(val) => val.fmt(f),
             Text(val) => val.fmt(f),
             Blob(val) => f.write_str(&hex::encode(val)),
+            TextList(val) => val.join(", ").fmt(f),
             #[cfg(feature = "json")]
             Json(val) => f.write_str(val.as_str().unwrap()),
             #[cfg(feature = "datetime")]
@@ -264,13 +269,17 @@ fn from(valref: SqlValRef) -> SqlVal {
             Real(v) => SqlVal::Real(v),
             Text(v) => SqlVal::Text(v.to_string()),
             Blob(v) => SqlVal::Blob(v.into()),
+            TextList(v) => SqlVal::TextList(v.into_owned()),
             #[cfg(feature = "json")]
             Json(v) => SqlVal::Json(v),
             #[cfg(feature = "datetime")]
             Date(v) => SqlVal::Date(v),
             #[cfg(feature = "datetime")]
             Timestamp(v) => SqlVal::Timestamp(v),
+            #[cfg(feature = "pg")]
             Custom(v) => SqlVal::Custom(Box::new(v.into())),
+            #[cfg(not(feature = "pg"))]
+            Custom(_) => panic!("phantom SqlValRefCustom should not be instantiated"),
         }
     }
 }
@@ -286,6 +295,7 @@ fn from(val: &'a SqlVal) -> SqlValRef<'a> {
             Real(v) => SqlValRef::Real(*v),
             Text(v) => SqlValRef::Text(v.as_ref()),
             Blob(v) => SqlValRef::Blob(v.as_ref()),
+            TextList(v) => SqlValRef::TextList(Cow::Borrowed(v)),
             #[cfg(feature = "json")]
             Json(v) => SqlValRef::Json(v.to_owned()),
             #[cfg(feature = "datetime")]
@@ -329,20 +339,16 @@ fn initialize(&mut self, value: SqlVal) -> Result<()> {
 
 /// Trait for referencing the primary key for a given model. Used to
 /// implement ForeignKey equality tests.
+///
+/// Implemented for the primary key type of each model by `#[model]`,
+/// rather than for every [`PrimaryKeyType`], as a [`ForeignKey`] may
+/// itself be a primary key.
+///
+/// [`ForeignKey`]: crate::fkey::ForeignKey
 pub trait AsPrimaryKey<T: DataObject> {
     fn as_pk(&self) -> Cow<'_, <T as DataObject>::PKType>;
 }
 
-impl<P, T> AsPrimaryKey<T> for P
-where
-    P: PrimaryKeyType,
-    T: DataObject<PKType = P>,
-{
-    fn as_pk(&self) -> Cow<'_, P> {
-        Cow::Borrowed(self)
-    }
-}
-
 macro_rules! sql_conv_err {
     ($val:ident, $sqltype:ident) => {
         Err(crate::Error::CannotConvertSqlVal(
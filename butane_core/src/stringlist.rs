@@ -0,0 +1,107 @@
+//! Contains the [StringList] type for fields holding several strings.
+
+use std::borrow::Cow;
+use std::ops::{Deref, DerefMut};
+
+use serde::{Deserialize, Serialize};
+
+use super::{FieldType, FromSql, Result, SqlType, SqlVal, SqlValRef, ToSql};
+
+/// A list of strings stored in a single column, for simple lists such
+/// as tags or labels which do not justify a [`Many`](crate::many::Many)
+/// relationship. Dereferences to a `Vec<String>`.
+///
+/// It is stored as a `TEXT[]` array on PostgreSQL and as a JSON array
+/// in a text column on other backends. Queries may filter on the
+/// strings it contains with [`FieldExpr::contains`], as in
+/// `filter!(Post, tags.contains("rust"))`, or with
+/// [`FieldExpr::contains_all`] and [`FieldExpr::overlaps`].
+///
+/// [`FieldExpr::contains`]: crate::query::FieldExpr::contains
+/// [`FieldExpr::contains_all`]: crate::query::FieldExpr::contains_all
+/// [`FieldExpr::overlaps`]: crate::query::FieldExpr::overlaps
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StringList(Vec<String>);
+
+impl StringList {
+    /// Create an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the strings of the list.
+    pub fn into_inner(self) -> Vec<String> {
+        self.0
+    }
+}
+
+impl Deref for StringList {
+    type Target = Vec<String>;
+    fn deref(&self) -> &Vec<String> {
+        &self.0
+    }
+}
+
+impl DerefMut for StringList {
+    fn deref_mut(&mut self) -> &mut Vec<String> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<String>> for StringList {
+    fn from(strings: Vec<String>) -> Self {
+        StringList(strings)
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for StringList {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        StringList(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl IntoIterator for StringList {
+    type Item = String;
+    type IntoIter = std::vec::IntoIter<String>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl FromSql for StringList {
+    fn from_sql_ref(valref: SqlValRef) -> Result<Self> {
+        if let SqlValRef::TextList(val) = valref {
+            Ok(StringList(val.into_owned()))
+        } else {
+            Err(crate::Error::CannotConvertSqlVal(
+                SqlType::TextList,
+                valref.into(),
+            ))
+        }
+    }
+    fn from_sql(val: SqlVal) -> Result<Self> {
+        if let SqlVal::TextList(val) = val {
+            Ok(StringList(val))
+        } else {
+            Err(crate::Error::CannotConvertSqlVal(SqlType::TextList, val))
+        }
+    }
+}
+
+impl ToSql for StringList {
+    fn to_sql(&self) -> SqlVal {
+        SqlVal::TextList(self.0.clone())
+    }
+    fn to_sql_ref(&self) -> SqlValRef<'_> {
+        SqlValRef::TextList(Cow::Borrowed(&self.0))
+    }
+    fn into_sql(self) -> SqlVal {
+        SqlVal::TextList(self.0)
+    }
+}
+
+impl FieldType for StringList {
+    const SQLTYPE: SqlType = SqlType::TextList;
+    type RefType = Self;
+}
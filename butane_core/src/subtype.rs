@@ -0,0 +1,217 @@
+//! Several models stored in one table, told apart by a discriminator
+//! column, such as the different kinds of events or notifications.
+//!
+//! Each model declared with `#[butane(subtype_of = Base, discriminator = "kind")]`
+//! is stored in the table named after `Base`, with the name of the model
+//! in the discriminator column. It is queried, saved and deleted as
+//! usual, and its queries and updates only ever match its own objects.
+//! `Base` is an enum with a variant wrapping each subtype, which derives
+//! [`Supertype`], so that the objects of all of them may be loaded at
+//! once, each as the right variant.
+//!
+//! ```ignore
+//! #[derive(Debug, Supertype)]
+//! enum Event {
+//!     Signup(Signup),
+//!     Purchase(Purchase),
+//! }
+//!
+//! #[model]
+//! #[butane(subtype_of = Event, discriminator = "kind")]
+//! struct Signup {
+//!     id: AutoPk<i64>,
+//!     email: String,
+//! }
+//!
+//! #[model]
+//! #[butane(subtype_of = Event, discriminator = "kind")]
+//! struct Purchase {
+//!     id: AutoPk<i64>,
+//!     amount: i64,
+//! }
+//!
+//! for event in Event::load(&conn, BoolExpr::True)? {
+//!     match event {
+//!         Event::Signup(signup) => println!("{} signed up", signup.email),
+//!         Event::Purchase(purchase) => println!("bought for {}", purchase.amount),
+//!     }
+//! }
+//! ```
+//!
+//! The subtypes must have the same primary key. A field of the same name
+//! in several of them is stored in the same column, so must have the
+//! same type. The columns other than the primary key and discriminator
+//! are nullable, as the rows of the other subtypes have no value for
+//! them.
+#![deny(missing_docs)]
+
+use fallible_iterator::FallibleIterator;
+
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::db::{BackendRow, BackendRows, Column, ConnectionMethods};
+use crate::query::{BoolExpr, Expr, Order, OrderDirection};
+use crate::{DataObject, Error, Result, SqlType, SqlVal, SqlValRef, ToSql};
+
+/// A model sharing its table with other subtypes of [`Self::Supertype`].
+/// Implemented by `#[butane(subtype_of = ...)]`.
+pub trait Subtype: DataObject {
+    /// The enum with a variant for this model and each other subtype.
+    type Supertype: Supertype;
+    /// The column telling the subtypes apart.
+    const DISCRIMINATOR: &'static str;
+    /// The value of the discriminator for objects of this model.
+    const KIND: &'static str;
+}
+
+/// An enum with a variant wrapping each [`Subtype`] stored in a table.
+/// Usually derived with `#[derive(Supertype)]`.
+pub trait Supertype: Sized {
+    /// The table of the subtypes.
+    const TABLE: &'static str;
+    /// The primary key column of the subtypes.
+    const PKCOL: &'static str;
+    /// The column telling the subtypes apart.
+    const DISCRIMINATOR: &'static str;
+    /// The value of the discriminator and the columns of each subtype,
+    /// in the order of the variants.
+    const SUBTYPES: &'static [(&'static str, &'static [Column])];
+
+    /// Loads the subtype numbered `subtype`, in the order of
+    /// [`Self::SUBTYPES`], from a row of its columns.
+    fn from_row(subtype: usize, row: &dyn BackendRow) -> Result<Self>;
+}
+
+/// Restricts `expr` to the objects of `T`, if it is a subtype sharing
+/// its table with others.
+pub(crate) fn restrict<T: DataObject>(expr: Option<BoolExpr>) -> Option<BoolExpr> {
+    let Some((column, kind)) = T::DISCRIMINATOR else {
+        return expr;
+    };
+    let scope = BoolExpr::Eq(column, Expr::Val(SqlVal::Text(kind.to_string())));
+    Some(match expr {
+        Some(expr) => scope.and(expr),
+        None => scope,
+    })
+}
+
+/// The columns selected to load any subtype of `T`: the discriminator,
+/// then those of each subtype not already selected. Also returns the
+/// positions among them of the columns of each subtype.
+fn columns<T: Supertype>() -> (Vec<Column>, Vec<Vec<usize>>) {
+    let mut columns = vec![Column::new(T::DISCRIMINATOR, SqlType::Text)];
+    let mut positions = Vec::with_capacity(T::SUBTYPES.len());
+    for (_, subtype_columns) in T::SUBTYPES {
+        let mut subtype_positions = Vec::with_capacity(subtype_columns.len());
+        for column in *subtype_columns {
+            match columns.iter().position(|c| c.name() == column.name()) {
+                Some(position) => subtype_positions.push(position),
+                None => {
+                    subtype_positions.push(columns.len());
+                    columns.push(column.clone());
+                }
+            }
+        }
+        positions.push(subtype_positions);
+    }
+    (columns, positions)
+}
+
+/// The columns of one subtype within a row of those of all of them.
+struct SubtypeRow<'a> {
+    row: &'a (dyn BackendRow + 'a),
+    positions: &'a [usize],
+}
+
+impl BackendRow for SubtypeRow<'_> {
+    fn get(&self, idx: usize, ty: SqlType) -> Result<SqlValRef<'_>> {
+        let position = self
+            .positions
+            .get(idx)
+            .ok_or_else(|| Error::BoundsError(format!("subtype has no column {idx}")))?;
+        self.row.get(*position, ty)
+    }
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+/// Loads the subtype named by the discriminator of `row`.
+fn from_row<T: Supertype>(row: &dyn BackendRow, positions: &[Vec<usize>]) -> Result<T> {
+    let kind = match row.get(0, SqlType::Text)? {
+        SqlValRef::Text(kind) => kind,
+        val => return Err(Error::CannotConvertSqlVal(SqlType::Text, val.into())),
+    };
+    let subtype = T::SUBTYPES
+        .iter()
+        .position(|(subtype_kind, _)| *subtype_kind == kind)
+        .ok_or_else(|| Error::UnknownEnumVariant(kind.to_string()))?;
+    T::from_row(
+        subtype,
+        &SubtypeRow {
+            row,
+            positions: &positions[subtype],
+        },
+    )
+}
+
+/// [`Supertype`] operations which require a `Connection`.
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"),),
+    sync(),
+    async(feature = "async")
+)]
+pub trait SupertypeOps: Supertype {
+    /// Loads the object with primary key `pk`, whichever subtype it is
+    /// of. Returns [`Error::NoSuchObject`] if there is none.
+    async fn get(conn: &impl ConnectionMethods, pk: impl ToSql) -> Result<Self>;
+
+    /// Loads the object with primary key `pk`, whichever subtype it is
+    /// of, if there is one.
+    async fn try_get(conn: &impl ConnectionMethods, pk: impl ToSql) -> Result<Option<Self>>;
+
+    /// Loads the objects of all the subtypes for which `filter` is
+    /// true, in the order of their primary keys.
+    async fn load(conn: &impl ConnectionMethods, filter: BoolExpr) -> Result<Vec<Self>>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"), SupertypeOps),
+    keep_self,
+    sync(),
+    async(feature = "async")
+)]
+impl<T: Supertype> SupertypeOps for T {
+    async fn get(conn: &impl ConnectionMethods, pk: impl ToSql) -> Result<Self> {
+        let found = <Self as SupertypeOps>::try_get(conn, pk).await?;
+        found.ok_or(Error::NoSuchObject)
+    }
+
+    async fn try_get(conn: &impl ConnectionMethods, pk: impl ToSql) -> Result<Option<Self>> {
+        let filter = BoolExpr::Eq(T::PKCOL, Expr::Val(pk.to_sql()));
+        let found = <Self as SupertypeOps>::load(conn, filter).await?;
+        Ok(found.into_iter().next())
+    }
+
+    async fn load(conn: &impl ConnectionMethods, filter: BoolExpr) -> Result<Vec<Self>> {
+        let (columns, positions) = columns::<T>();
+        // Rows of subtypes which are not variants of T are left out
+        let kinds = T::SUBTYPES
+            .iter()
+            .map(|(kind, _)| SqlVal::Text(kind.to_string()))
+            .collect();
+        let filter = BoolExpr::In(T::DISCRIMINATOR, kinds)
+            .and(filter)
+            .normalize();
+        let sort = [Order {
+            direction: OrderDirection::Ascending,
+            column: T::PKCOL,
+            expr: None,
+        }];
+        conn.query(T::TABLE, &columns, Some(filter), None, None, Some(&sort))
+            .await?
+            .mapped(|row| from_row::<T>(row, &positions))
+            .collect()
+    }
+}
@@ -0,0 +1,289 @@
+//! Implementation of many-to-many relationships through an association model.
+#![deny(missing_docs)]
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+#[cfg(feature = "fake")]
+use fake::{Dummy, Faker};
+use serde::{Deserialize, Serialize};
+
+use crate::db::ConnectionMethods;
+#[cfg(feature = "async")]
+use crate::db::ConnectionMethodsAsync;
+use crate::query::{BoolExpr, Expr};
+use crate::util::get_or_init_once_lock;
+#[cfg(feature = "async")]
+use crate::util::get_or_init_once_lock_async;
+use crate::{DataObject, Error, Result, SqlVal, ToSql, WritableDataObject};
+
+/// A model associating another model with `T` in a many-to-many
+/// relationship, which may have columns of its own, such as when a
+/// membership was created or the role it grants.
+///
+/// Implemented once for each model with a [`ManyThrough`] field using
+/// this association, naming the columns referring to that model and to
+/// `T`. These are usually [`ForeignKey`](crate::fkey::ForeignKey) fields.
+///
+/// ```ignore
+/// #[model]
+/// struct Membership {
+///     id: AutoPk<i64>,
+///     group: ForeignKey<Group>,
+///     user: ForeignKey<User>,
+///     role: String,
+/// }
+/// impl Association<User> for Membership {
+///     const OWNER_COLUMN: &'static str = "group";
+///     const TARGET_COLUMN: &'static str = "user";
+/// }
+/// impl Association<Group> for Membership {
+///     const OWNER_COLUMN: &'static str = "user";
+///     const TARGET_COLUMN: &'static str = "group";
+/// }
+/// ```
+pub trait Association<T: DataObject>: WritableDataObject {
+    /// Column referring to the model with the [`ManyThrough`] field.
+    const OWNER_COLUMN: &'static str;
+    /// Column referring to `T`.
+    const TARGET_COLUMN: &'static str;
+}
+
+/// Used to implement a many-to-many relationship between models
+/// through an association model `A`, rather than the table created for
+/// a [`Many`](crate::many::Many).
+///
+/// Values are added to the relationship by saving an association
+/// referring to both models, which may be done with
+/// [`add`](ManyThroughOpsSync::add). Unlike `Many`, there are no
+/// pending changes: each operation takes effect in the backend
+/// immediately.
+///
+/// See [`ManyThroughOpsSync`] and [`ManyThroughOpsAsync`] for operations requiring a live database connection.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ManyThrough<T, A>
+where
+    T: DataObject,
+    A: Association<T>,
+{
+    owner: Option<SqlVal>,
+    #[serde(skip)]
+    #[serde(default = "OnceLock::new")]
+    all_values: OnceLock<Vec<T>>,
+    #[serde(skip)]
+    #[serde(default = "OnceLock::new")]
+    associations: OnceLock<Vec<A>>,
+}
+impl<T, A> ManyThrough<T, A>
+where
+    T: DataObject,
+    A: Association<T>,
+{
+    /// Constructs a new ManyThrough. `init` must be called before it can be
+    /// loaded or modified (or those methods will return
+    /// `Error::NotInitialized`). `init` will automatically be called
+    /// when a [`DataObject`] with a `ManyThrough` field is loaded or saved.
+    pub fn new() -> Self {
+        ManyThrough {
+            owner: None,
+            all_values: OnceLock::new(),
+            associations: OnceLock::new(),
+        }
+    }
+
+    /// Used by macro-generated code. You do not need to call this directly.
+    pub fn ensure_init(&mut self, owner: SqlVal) {
+        if self.owner.is_some() {
+            return;
+        }
+        self.owner = Some(owner);
+    }
+
+    /// Returns already loaded values.
+    ///
+    /// Returns [`Error::ValueNotLoaded`] if `load()` has not been invoked prior.
+    pub fn get(&self) -> Result<impl Iterator<Item = &T>> {
+        self.all_values
+            .get()
+            .ok_or(Error::ValueNotLoaded)
+            .map(|v| v.iter())
+    }
+
+    /// Returns already loaded associations.
+    ///
+    /// Returns [`Error::ValueNotLoaded`] if `load_associations()` has not been invoked prior.
+    pub fn get_associations(&self) -> Result<impl Iterator<Item = &A>> {
+        self.associations
+            .get()
+            .ok_or(Error::ValueNotLoaded)
+            .map(|v| v.iter())
+    }
+
+    /// Expression matching the associations of the owner.
+    fn owner_expr(&self) -> Option<BoolExpr> {
+        self.owner
+            .as_ref()
+            .map(|owner| BoolExpr::Eq(A::OWNER_COLUMN, Expr::Val(owner.clone())))
+    }
+
+    /// Forgets loaded values, which are out of date.
+    fn invalidate(&mut self) {
+        self.all_values = OnceLock::new();
+        self.associations = OnceLock::new();
+    }
+}
+
+/// [`ManyThrough`] operations which require a `Connection`.
+#[allow(async_fn_in_trait)] // Not intended to be implemented outside Butane
+#[maybe_async_cfg::maybe(
+    idents(ConnectionMethods(sync = "ConnectionMethods"),),
+    sync(),
+    async(feature = "async")
+)]
+pub trait ManyThroughOps<T: DataObject, A: Association<T>> {
+    /// Saves `association`, adding the value it refers to.
+    ///
+    /// The association must refer to the owner of this relationship.
+    async fn add(&mut self, conn: &impl ConnectionMethods, association: &mut A) -> Result<()>;
+
+    /// Deletes the associations with `value`, returning the number deleted.
+    async fn remove(&mut self, conn: &impl ConnectionMethods, value: &T) -> Result<usize>;
+
+    /// Loads the values referred to by the associations.
+    async fn load<'a>(
+        &'a self,
+        conn: &impl ConnectionMethods,
+    ) -> Result<impl Iterator<Item = &'a T>>
+    where
+        T: 'a;
+
+    /// Loads the associations, including any columns of their own.
+    async fn load_associations<'a>(
+        &'a self,
+        conn: &impl ConnectionMethods,
+    ) -> Result<impl Iterator<Item = &'a A>>
+    where
+        A: 'a;
+
+    /// Returns whether there is an association with `value`.
+    ///
+    /// Answers from loaded values if available, without a query.
+    async fn contains(&self, conn: &impl ConnectionMethods, value: &T) -> Result<bool>;
+}
+
+#[maybe_async_cfg::maybe(
+    idents(
+        ConnectionMethods(sync = "ConnectionMethods"),
+        DataObjectOps,
+        ManyThroughOps,
+        QueryOps
+    ),
+    keep_self,
+    sync(),
+    async(feature = "async", idents(get_or_init_once_lock(snake)))
+)]
+impl<T: DataObject, A: Association<T>> ManyThroughOps<T, A> for ManyThrough<T, A> {
+    async fn add(&mut self, conn: &impl ConnectionMethods, association: &mut A) -> Result<()> {
+        use crate::DataObjectOps;
+        self.owner.as_ref().ok_or(Error::NotInitialized)?;
+        DataObjectOps::save(association, conn).await?;
+        self.invalidate();
+        Ok(())
+    }
+
+    async fn remove(&mut self, conn: &impl ConnectionMethods, value: &T) -> Result<usize> {
+        let owner = self.owner_expr().ok_or(Error::NotInitialized)?;
+        let removed = conn
+            .delete_where(
+                A::TABLE,
+                owner.and(BoolExpr::Eq(
+                    A::TARGET_COLUMN,
+                    Expr::Val(value.pk().to_sql()),
+                )),
+            )
+            .await?;
+        self.invalidate();
+        Ok(removed)
+    }
+
+    async fn load<'a>(
+        &'a self,
+        conn: &impl ConnectionMethods,
+    ) -> Result<impl Iterator<Item = &'a T>>
+    where
+        T: 'a,
+    {
+        use crate::query::QueryOps;
+        let Some(owner) = self.owner_expr() else {
+            // If not initialised then there are no values
+            return Ok(Vec::new().into_iter());
+        };
+        let query = T::query().filter(BoolExpr::Subquery {
+            col: T::PKCOL,
+            tbl2: Cow::Borrowed(A::TABLE),
+            tbl2_col: A::TARGET_COLUMN,
+            expr: Box::new(owner),
+        });
+        let vals: Vec<&T> = get_or_init_once_lock(&self.all_values, || query.load(conn))
+            .await?
+            .iter()
+            .collect();
+        Ok(vals.into_iter())
+    }
+
+    async fn load_associations<'a>(
+        &'a self,
+        conn: &impl ConnectionMethods,
+    ) -> Result<impl Iterator<Item = &'a A>>
+    where
+        A: 'a,
+    {
+        use crate::query::QueryOps;
+        let Some(owner) = self.owner_expr() else {
+            // If not initialised then there are no associations
+            return Ok(Vec::new().into_iter());
+        };
+        let query = A::query().filter(owner);
+        let vals: Vec<&A> = get_or_init_once_lock(&self.associations, || query.load(conn))
+            .await?
+            .iter()
+            .collect();
+        Ok(vals.into_iter())
+    }
+
+    async fn contains(&self, conn: &impl ConnectionMethods, value: &T) -> Result<bool> {
+        use crate::query::QueryOps;
+        let pk = value.pk().to_sql();
+        if let Some(vals) = self.all_values.get() {
+            return Ok(vals.iter().any(|v| v.pk().to_sql() == pk));
+        }
+        let Some(owner) = self.owner_expr() else {
+            return Ok(false);
+        };
+        let found = A::query()
+            .filter(owner.and(BoolExpr::Eq(A::TARGET_COLUMN, Expr::Val(pk))))
+            .limit(1)
+            .load(conn)
+            .await?;
+        Ok(!found.is_empty())
+    }
+}
+
+impl<T: DataObject, A: Association<T>> PartialEq<ManyThrough<T, A>> for ManyThrough<T, A> {
+    fn eq(&self, other: &ManyThrough<T, A>) -> bool {
+        self.owner == other.owner
+    }
+}
+impl<T: DataObject, A: Association<T>> Eq for ManyThrough<T, A> {}
+impl<T: DataObject, A: Association<T>> Default for ManyThrough<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "fake")]
+/// Fake data support is currently limited to uninitialised ManyThrough relationships.
+impl<T: DataObject, A: Association<T>> Dummy<Faker> for ManyThrough<T, A> {
+    fn dummy_with_rng<R: rand::Rng + ?Sized>(_: &Faker, _rng: &mut R) -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,92 @@
+//! Validation of the values of fields before they are saved.
+//!
+//! Fields may declare the values they accept with attributes, which
+//! `save` checks before writing the object, failing with
+//! [`Error::Validation`] without changing the database if any is not
+//! met:
+//!
+//! * `#[butane(max_length = N)]` on a string field, for at most `N`
+//!   characters.
+//! * `#[butane(min = X)]` and `#[butane(max = X)]` on a field of an
+//!   ordered type such as an integer, for a value of at least or at most
+//!   `X`.
+//! * `#[butane(regex = "PATTERN")]` on a string field, for a value
+//!   matching the regular expression, which is checked when the model
+//!   is compiled. The pattern is not anchored, so should start with `^`
+//!   and end with `$` to match the whole value.
+//!
+//! An optional field is only checked when it is `Some`. The checks are
+//! made by `save` and `save_ignore_conflict`, rather than by constraints
+//! in the database, so do not apply to rows written by other means such
+//! as update queries.
+//!
+//! The functions of this module are called by the code generated for
+//! the attributes, and the errors they return do not include the value,
+//! which may be sensitive.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{LazyLock, RwLock};
+
+use regex::Regex;
+
+use crate::{Error, Result};
+
+/// Regular expressions compiled for `#[butane(regex = "...")]`, by pattern.
+static REGEXES: LazyLock<RwLock<HashMap<&'static str, Regex>>> = LazyLock::new(Default::default);
+
+fn invalid(field: &'static str, detail: String) -> Error {
+    Error::Validation { field, detail }
+}
+
+/// Checks that `value` has at most `max` characters.
+pub fn max_length(field: &'static str, value: &str, max: usize) -> Result<()> {
+    if value.chars().count() > max {
+        return Err(invalid(
+            field,
+            format!("must be at most {max} characters long"),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `value` is at least `min`.
+pub fn min<T: PartialOrd + Display>(field: &'static str, value: &T, min: T) -> Result<()> {
+    if *value < min {
+        return Err(invalid(field, format!("must be at least {min}")));
+    }
+    Ok(())
+}
+
+/// Checks that `value` is at most `max`.
+pub fn max<T: PartialOrd + Display>(field: &'static str, value: &T, max: T) -> Result<()> {
+    if *value > max {
+        return Err(invalid(field, format!("must be at most {max}")));
+    }
+    Ok(())
+}
+
+/// Checks that `value` matches the regular expression `pattern`.
+pub fn regex(field: &'static str, value: &str, pattern: &'static str) -> Result<()> {
+    let cached = REGEXES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(pattern)
+        .map(|re| re.is_match(value));
+    let matched = match cached {
+        Some(matched) => matched,
+        None => {
+            let re = Regex::new(pattern).map_err(|e| invalid(field, e.to_string()))?;
+            let matched = re.is_match(value);
+            REGEXES
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(pattern, re);
+            matched
+        }
+    };
+    if !matched {
+        return Err(invalid(field, format!("must match {pattern}")));
+    }
+    Ok(())
+}
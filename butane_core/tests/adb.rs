@@ -50,6 +50,41 @@ fn remove_table() {
     assert_eq!(ops, expected_ops);
 }
 
+#[test]
+fn destructive_operations() {
+    let text = AColumn::new_simple(
+        "a",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+    );
+    let int = AColumn::new_simple(
+        "a",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+    );
+    let nullable_text = AColumn::new(
+        "a",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+        true,
+        false,
+        false,
+        false,
+        None,
+        None,
+    );
+
+    let op = Operation::ChangeColumn("t".to_owned(), text.clone(), int);
+    assert!(op.is_destructive());
+    assert_eq!(op.table_name(), "t");
+    let op = Operation::ChangeColumn("t".to_owned(), text.clone(), nullable_text);
+    assert!(!op.is_destructive());
+    assert!(Operation::RemoveColumn("t".to_owned(), "a".to_owned()).is_destructive());
+    assert!(Operation::RemoveTable("t".to_owned()).is_destructive());
+    let op = Operation::AddColumn("t".to_owned(), text);
+    assert!(!op.is_destructive());
+    let op = Operation::RenameTable("t".to_owned(), "u".to_owned());
+    assert!(!op.is_destructive());
+    assert_eq!(op.table_name(), "t");
+}
+
 #[test]
 fn stable_table_alpha_order() {
     let old = ADB::default();
@@ -616,6 +651,471 @@ fn add_renamed_table_fkey_ddl_pg() {
     );
 }
 
+#[cfg(feature = "odbc")]
+#[test]
+fn add_renamed_table_fkey_ddl_odbc() {
+    let (ops, new, ..) = create_add_renamed_table_fkey_ops();
+
+    let backend = butane_core::db::get_backend("odbc").unwrap();
+    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE a_table (",
+            "\"id\" INTEGER NOT NULL PRIMARY KEY",
+            ");",
+            "CREATE TABLE b (",
+            "b INTEGER NOT NULL PRIMARY KEY",
+            ");",
+            "ALTER TABLE b ADD FOREIGN KEY (b) REFERENCES a_table(\"id\");",
+        ]
+    );
+}
+
+/// Creates an ADB with table `a` and, if `query` is given, the view `v` defined by it.
+fn adb_with_view(query: Option<&str>) -> ADB {
+    let mut adb = ADB::default();
+    let mut table = ATable::new("a".to_owned());
+    table.add_column(AColumn::new_simple(
+        "x",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+    ));
+    adb.replace_table(table);
+    if let Some(query) = query {
+        let mut view = ATable::new("v".to_owned());
+        view.add_column(AColumn::new_simple(
+            "x",
+            DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+        ));
+        view.view = Some(AView::new(query));
+        adb.replace_table(view);
+    }
+    adb
+}
+
+#[test]
+fn add_view() {
+    let old = ADB::default();
+    let new = adb_with_view(Some("SELECT x FROM a"));
+    let ops = diff(&old, &new);
+
+    // The view is created after the table it selects from, despite its name
+    let view = new.get_table("v").unwrap().clone();
+    assert_eq!(
+        ops,
+        vec![
+            Operation::AddTable(new.get_table("a").unwrap().clone()),
+            Operation::AddTable(view),
+        ]
+    );
+
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    assert_eq!(
+        sql.lines().last(),
+        Some("CREATE VIEW v AS SELECT x FROM a;")
+    );
+}
+
+#[test]
+fn change_view() {
+    let old = adb_with_view(Some("SELECT x FROM a"));
+    let new = adb_with_view(Some("SELECT x FROM a WHERE x > 0"));
+    let ops = diff(&old, &new);
+
+    // Views cannot be altered, so are replaced
+    let view = new.get_table("v").unwrap().clone();
+    assert_eq!(
+        ops,
+        vec![
+            Operation::RemoveTable("v".to_owned()),
+            Operation::AddTable(view),
+        ]
+    );
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "DROP VIEW v;",
+            "CREATE VIEW v AS SELECT x FROM a WHERE x > 0;"
+        ]
+    );
+
+    assert_eq!(diff(&new, &new), vec![]);
+}
+
+#[test]
+fn remove_view() {
+    let old = adb_with_view(Some("SELECT x FROM a"));
+    let new = adb_with_view(None);
+    let ops = diff(&old, &new);
+    assert_eq!(ops, vec![Operation::RemoveTable("v".to_owned())]);
+
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    assert_eq!(sql, "DROP VIEW v;");
+}
+
+#[test]
+fn materialized_view_ddl() {
+    let old = adb_with_view(Some("SELECT x FROM a"));
+    let mut new = adb_with_view(None);
+    let mut view = old.get_table("v").unwrap().clone();
+    view.view = Some(AView {
+        query: "SELECT x FROM a".to_owned(),
+        materialized: true,
+        indexes: vec![AIndex {
+            column: "x".to_owned(),
+            extra_columns: Vec::new(),
+            unique: true,
+            concurrently: false,
+        }],
+    });
+    new.replace_table(view.clone());
+    let ops = diff(&old, &new);
+    assert_eq!(
+        ops,
+        vec![
+            Operation::RemoveTable("v".to_owned()),
+            Operation::AddTable(view),
+        ]
+    );
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "DROP VIEW v;",
+            "CREATE MATERIALIZED VIEW v AS SELECT x FROM a;",
+            "CREATE UNIQUE INDEX v_x_idx ON v (x);",
+        ]
+    );
+    assert_eq!(
+        backend.refresh_materialized_view_sql("v"),
+        Some("REFRESH MATERIALIZED VIEW v;".to_owned())
+    );
+
+    let ops = diff(&new, &adb_with_view(None));
+    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    assert_eq!(sql, "DROP MATERIALIZED VIEW v;");
+
+    // Backends without materialized views create a plain view
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let ops = diff(&adb_with_view(None), &new);
+    let sql = backend
+        .create_migration_sql(&adb_with_view(None), ops)
+        .unwrap();
+    assert_eq!(sql, "CREATE VIEW v AS SELECT x FROM a;");
+    assert_eq!(backend.refresh_materialized_view_sql("v"), None);
+}
+
+#[test]
+fn concurrent_index_on_new_table_ddl_pg() {
+    let old = ADB::default();
+    let mut new = ADB::default();
+    let mut table = ATable::new("a".to_owned());
+    table.add_column(AColumn::new(
+        "x",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+        false,
+        false,
+        false,
+        false,
+        None,
+        None,
+    ));
+    let index = AIndex {
+        column: "x".to_owned(),
+        extra_columns: Vec::new(),
+        unique: false,
+        concurrently: true,
+    };
+    table.replace_index(index.clone());
+    new.replace_table(table.clone());
+    let ops = diff(&old, &new);
+    assert_eq!(
+        ops,
+        vec![
+            Operation::AddTable(table),
+            Operation::AddIndex("a".to_owned(), index),
+        ]
+    );
+
+    // A new table is empty, so its index is built in the migration's transaction
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE a (",
+            "x INTEGER NOT NULL",
+            ");",
+            "CREATE INDEX a_x_idx ON a (x);"
+        ]
+    );
+}
+
+/// Creates a table partitioned by range of `bucket`, returning the migration operations
+/// and the target ADB.
+fn create_add_partitioned_table_ops() -> (Vec<Operation>, ADB) {
+    let old = ADB::default();
+    let mut new = ADB::default();
+    let mut table = ATable::new("events".to_owned());
+    table.add_column(AColumn::new(
+        "id",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+        false,
+        true,
+        false,
+        false,
+        None,
+        None,
+    ));
+    table.add_column(AColumn::new_simple(
+        "bucket",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int)),
+    ));
+    table.partition_by = Some("range(bucket)".parse().unwrap());
+    new.replace_table(table.clone());
+
+    let ops = diff(&old, &new);
+    assert_eq!(ops, vec![Operation::AddTable(table)]);
+    (ops, new)
+}
+
+#[test]
+fn add_partitioned_table_ddl_pg() {
+    let (ops, new) = create_add_partitioned_table_ops();
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE events (",
+            "\"id\" BIGINT NOT NULL,",
+            "\"bucket\" INTEGER NOT NULL,",
+            "PRIMARY KEY (\"id\", \"bucket\")",
+            ") PARTITION BY RANGE (\"bucket\");",
+        ]
+    );
+}
+
+#[test]
+fn add_partitioned_table_ddl_sqlite() {
+    let (ops, new) = create_add_partitioned_table_ops();
+
+    // Partitioning is ignored by backends which do not support it.
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE events (",
+            "\"id\" INTEGER NOT NULL PRIMARY KEY,",
+            "\"bucket\" INTEGER NOT NULL",
+            ") STRICT;",
+        ]
+    );
+}
+
+/// Creates a table with a case-insensitive unique column, returning the migration
+/// operations and the target ADB.
+fn create_add_collated_table_ops() -> (Vec<Operation>, ADB) {
+    let old = ADB::default();
+    let mut new = ADB::default();
+    let mut table = ATable::new("users".to_owned());
+    table.add_column(AColumn::new(
+        "id",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+        false,
+        true,
+        false,
+        false,
+        None,
+        None,
+    ));
+    let mut email = AColumn::new(
+        "email",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+        false,
+        false,
+        false,
+        true,
+        None,
+        None,
+    );
+    email.set_collation(Some("NOCASE".to_owned()));
+    table.add_column(email);
+    new.replace_table(table.clone());
+
+    let ops = diff(&old, &new);
+    assert_eq!(ops, vec![Operation::AddTable(table)]);
+    (ops, new)
+}
+
+#[test]
+fn add_collated_table_ddl_sqlite() {
+    let (ops, new) = create_add_collated_table_ops();
+
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE users (",
+            "\"id\" INTEGER NOT NULL PRIMARY KEY,",
+            "email TEXT COLLATE NOCASE NOT NULL UNIQUE",
+            ") STRICT;",
+        ]
+    );
+}
+
+#[test]
+fn add_collated_table_ddl_pg() {
+    let (ops, new) = create_add_collated_table_ops();
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE COLLATION IF NOT EXISTS butane_nocase \
+             (provider = icu, locale = 'und-u-ks-level2', deterministic = false);",
+            "CREATE TABLE users (",
+            "\"id\" BIGINT NOT NULL PRIMARY KEY,",
+            "email TEXT COLLATE butane_nocase NOT NULL UNIQUE",
+            ");",
+        ]
+    );
+}
+
+#[test]
+fn backfill_only_affects_added_columns() {
+    let (_, old) = create_add_collated_table_ops();
+    let mut new = old.clone();
+    let mut table = new.get_table("users").unwrap().clone();
+    let mut email = table.column("email").unwrap().clone();
+    email.set_backfill(true);
+    table.replace_column(email);
+    let mut name = AColumn::new_simple(
+        "name",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+    );
+    name.set_backfill(true);
+    table.add_column(name.clone());
+    new.replace_table(table);
+
+    // Marking an existing column is not a change
+    let ops = backfill_marked_columns(diff(&old, &new));
+    let mut nullable = AColumn::new(
+        "name",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+        true,
+        false,
+        false,
+        false,
+        None,
+        None,
+    );
+    nullable.set_backfill(true);
+    assert_eq!(
+        ops,
+        vec![
+            Operation::AddColumn("users".to_owned(), nullable.clone()),
+            Operation::BackfillColumn("users".to_owned(), name.clone()),
+            Operation::ChangeColumn("users".to_owned(), nullable, name),
+        ]
+    );
+}
+
+#[test]
+fn change_column_collation_ddl_pg() {
+    let (_, old) = create_add_collated_table_ops();
+    let mut new = old.clone();
+    let mut table = new.get_table("users").unwrap().clone();
+    let mut email = table.column("email").unwrap().clone();
+    email.set_collation(Some("und-x-icu".to_owned()));
+    table.replace_column(email);
+    new.replace_table(table);
+
+    let ops = diff(&old, &new);
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    assert_eq!(
+        sql,
+        "ALTER TABLE users ALTER COLUMN email SET DATA TYPE TEXT COLLATE \"und-x-icu\";"
+    );
+}
+
+#[test]
+fn create_partition_ddl() {
+    use butane_core::partition::PartitionBounds;
+    use butane_core::SqlVal;
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let range = PartitionBounds::Range {
+        from: SqlVal::Int(0),
+        to: SqlVal::Int(10),
+    };
+    assert_eq!(
+        backend
+            .create_partition_sql("events", "events_low", &range)
+            .unwrap(),
+        "CREATE TABLE events_low PARTITION OF events FOR VALUES FROM (0) TO (10);"
+    );
+    let list = PartitionBounds::List(vec![SqlVal::Int(1), SqlVal::Int(2)]);
+    assert_eq!(
+        backend
+            .create_partition_sql("events", "events_list", &list)
+            .unwrap(),
+        "CREATE TABLE events_list PARTITION OF events FOR VALUES IN (1, 2);"
+    );
+    let hash = PartitionBounds::Hash {
+        modulus: 4,
+        remainder: 1,
+    };
+    assert_eq!(
+        backend
+            .create_partition_sql("events", "events_1", &hash)
+            .unwrap(),
+        "CREATE TABLE events_1 PARTITION OF events FOR VALUES WITH (MODULUS 4, REMAINDER 1);"
+    );
+    assert_eq!(
+        backend
+            .create_partition_sql("events", "events_other", &PartitionBounds::Default)
+            .unwrap(),
+        "CREATE TABLE events_other PARTITION OF events DEFAULT;"
+    );
+
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    assert!(matches!(
+        backend.create_partition_sql("events", "events_low", &range),
+        Err(butane_core::Error::Unsupported(..))
+    ));
+}
+
+#[test]
+fn parse_partition_by() {
+    let partition_by: PartitionBy = "hash(id)".parse().unwrap();
+    assert_eq!(partition_by.method, PartitionMethod::Hash);
+    assert_eq!(partition_by.column, "id");
+    assert!("range".parse::<PartitionBy>().is_err());
+    assert!("range()".parse::<PartitionBy>().is_err());
+    assert!("interval(id)".parse::<PartitionBy>().is_err());
+}
+
 /// Creates the test case for adding a many table, returning the migration operations,
 /// the target ADB, and the tables which should be expected to be created.
 fn create_add_table_many_ops() -> (Vec<Operation>, ADB, ATable, ATable, ATable) {
@@ -684,6 +1184,20 @@ fn create_add_table_many_ops() -> (Vec<Operation>, ADB, ATable, ATable, ATable)
     );
     resolved_many_table.add_column(resolved_owner_column);
     resolved_many_table.add_column(resolved_has_column);
+    resolved_many_table.indexes = vec![
+        AIndex {
+            column: "owner".to_owned(),
+            extra_columns: vec!["has".to_owned()],
+            unique: true,
+            concurrently: false,
+        },
+        AIndex {
+            column: "has".to_owned(),
+            extra_columns: Vec::new(),
+            unique: false,
+            concurrently: false,
+        },
+    ];
 
     let ops = diff(&old, &new);
     (ops, new, table_a, table_b, resolved_many_table)
@@ -703,6 +1217,14 @@ fn add_table_many() {
             Operation::AddTable(table_a),
             Operation::AddTable(table_b.clone()),
             Operation::AddTable(resolved_many_table.clone()),
+            Operation::AddIndex(
+                resolved_many_table.name.clone(),
+                resolved_many_table.indexes[0].clone()
+            ),
+            Operation::AddIndex(
+                resolved_many_table.name.clone(),
+                resolved_many_table.indexes[1].clone()
+            ),
             Operation::AddTableConstraints(resolved_many_table.clone()),
         ]
     );
@@ -710,10 +1232,10 @@ fn add_table_many() {
 
 #[test]
 fn add_table_many_ddl_sqlite() {
-    let (ops, new, ..) = create_add_table_many_ops();
+    let (ops, ..) = create_add_table_many_ops();
 
     let backend = butane_core::db::get_backend("sqlite").unwrap();
-    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql = backend.create_migration_sql(&ADB::default(), ops).unwrap();
     let sql_lines: Vec<&str> = sql.lines().collect();
     assert_eq!(
         sql_lines,
@@ -730,16 +1252,47 @@ fn add_table_many_ddl_sqlite() {
             "FOREIGN KEY (\"owner\") REFERENCES b(\"id\")",
             "FOREIGN KEY (has) REFERENCES a(\"id\")",
             ") STRICT;",
+            "CREATE UNIQUE INDEX b_many_a_Many_owner_has_idx ON b_many_a_Many (\"owner\", has);",
+            "CREATE INDEX b_many_a_Many_has_idx ON b_many_a_Many (has);",
         ]
     );
 }
 
 #[test]
 fn add_table_many_ddl_pg() {
-    let (ops, new, ..) = create_add_table_many_ops();
+    let (ops, ..) = create_add_table_many_ops();
 
     let backend = butane_core::db::get_backend("pg").unwrap();
-    let sql = backend.create_migration_sql(&new, ops).unwrap();
+    let sql = backend.create_migration_sql(&ADB::default(), ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE a (",
+            "\"id\" INTEGER NOT NULL PRIMARY KEY",
+            ");",
+            "CREATE TABLE b (",
+            "\"id\" INTEGER NOT NULL PRIMARY KEY",
+            ");",
+            "CREATE TABLE b_many_a_Many (",
+            "\"owner\" INTEGER NOT NULL,",
+            "has INTEGER NOT NULL",
+            ");",
+            "CREATE UNIQUE INDEX b_many_a_Many_owner_has_idx ON b_many_a_Many (\"owner\", has);",
+            "CREATE INDEX b_many_a_Many_has_idx ON b_many_a_Many (has);",
+            "ALTER TABLE b_many_a_Many ADD FOREIGN KEY (\"owner\") REFERENCES b(\"id\");",
+            "ALTER TABLE b_many_a_Many ADD FOREIGN KEY (has) REFERENCES a(\"id\");",
+        ]
+    );
+}
+
+#[cfg(feature = "odbc")]
+#[test]
+fn add_table_many_ddl_odbc() {
+    let (ops, ..) = create_add_table_many_ops();
+
+    let backend = butane_core::db::get_backend("odbc").unwrap();
+    let sql = backend.create_migration_sql(&ADB::default(), ops).unwrap();
     let sql_lines: Vec<&str> = sql.lines().collect();
     assert_eq!(
         sql_lines,
@@ -754,8 +1307,417 @@ fn add_table_many_ddl_pg() {
             "\"owner\" INTEGER NOT NULL,",
             "has INTEGER NOT NULL",
             ");",
+            "CREATE UNIQUE INDEX b_many_a_Many_owner_has_idx ON b_many_a_Many (\"owner\", has);",
+            "CREATE INDEX b_many_a_Many_has_idx ON b_many_a_Many (has);",
             "ALTER TABLE b_many_a_Many ADD FOREIGN KEY (\"owner\") REFERENCES b(\"id\");",
             "ALTER TABLE b_many_a_Many ADD FOREIGN KEY (has) REFERENCES a(\"id\");",
         ]
     );
 }
+
+/// Creates a table `item` with a column of each portable [`SqlType`].
+#[cfg(feature = "odbc")]
+fn create_odbc_types_table() -> ATable {
+    let mut table = ATable::new("item".to_owned());
+    for (name, ty, pk, nullable) in [
+        ("quantity", SqlType::Int, true, false),
+        ("flag", SqlType::Bool, false, false),
+        ("amount", SqlType::BigInt, false, false),
+        ("ratio", SqlType::Real, false, false),
+        ("title", SqlType::Text, false, true),
+        ("payload", SqlType::Blob, false, true),
+        ("tags", SqlType::TextList, false, false),
+    ] {
+        table.add_column(AColumn::new(
+            name,
+            DeferredSqlType::KnownId(TypeIdentifier::Ty(ty)),
+            nullable,
+            pk,
+            false, // auto
+            false, // unique
+            None,  // default
+            None,  // reference
+        ));
+    }
+    table
+}
+
+#[cfg(feature = "odbc")]
+#[test]
+fn column_types_ddl_odbc() {
+    let table = create_odbc_types_table();
+    let mut new = ADB::default();
+    new.replace_table(table.clone());
+
+    let backend = butane_core::db::get_backend("odbc").unwrap();
+    let sql = backend
+        .create_migration_sql(&new, vec![Operation::AddTable(table)])
+        .unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE item (",
+            "quantity INTEGER NOT NULL PRIMARY KEY,",
+            "flag SMALLINT NOT NULL,",
+            "amount BIGINT NOT NULL,",
+            "ratio DOUBLE PRECISION NOT NULL,",
+            "title VARCHAR(4000),",
+            "payload VARBINARY(8000),",
+            "tags VARCHAR(4000) NOT NULL",
+            ");",
+        ]
+    );
+}
+
+#[cfg(feature = "odbc")]
+#[test]
+fn rename_table_ddl_odbc() {
+    let table = create_odbc_types_table();
+    let mut current = ADB::default();
+    current.replace_table(table);
+
+    let backend = butane_core::db::get_backend("odbc").unwrap();
+    let sql = backend
+        .create_migration_sql(
+            &current,
+            vec![
+                Operation::RenameTable("item".to_owned(), "Item".to_owned()),
+                Operation::RenameColumn("Item".to_owned(), "title".to_owned(), "label".to_owned()),
+                Operation::RemoveColumn("Item".to_owned(), "payload".to_owned()),
+            ],
+        )
+        .unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "ALTER TABLE item RENAME TO Item__butane_tmp;",
+            "ALTER TABLE Item__butane_tmp RENAME TO Item;",
+            "ALTER TABLE Item RENAME COLUMN title TO label;",
+            "ALTER TABLE Item DROP COLUMN payload;",
+        ]
+    );
+}
+
+#[cfg(feature = "odbc")]
+#[test]
+fn unsupported_ddl_odbc() {
+    let table = create_odbc_types_table();
+    let mut current = ADB::default();
+    current.replace_table(table.clone());
+    let backend = butane_core::db::get_backend("odbc").unwrap();
+
+    let mut auto_table = ATable::new("counter".to_owned());
+    auto_table.add_column(AColumn::new(
+        "id",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+        false, // nullable
+        true,  // pk
+        true,  // auto
+        false, // unique
+        None,  // default
+        None,  // reference
+    ));
+    let err = backend
+        .create_migration_sql(&current, vec![Operation::AddTable(auto_table)])
+        .unwrap_err();
+    assert!(matches!(err, butane_core::Error::Unsupported("odbc", _)));
+
+    let old = table.column("ratio").unwrap().clone();
+    let new = AColumn::new(
+        "ratio",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Real)),
+        true,  // nullable
+        false, // pk
+        false, // auto
+        false, // unique
+        None,  // default
+        None,  // reference
+    );
+    let err = backend
+        .create_migration_sql(
+            &current,
+            vec![Operation::ChangeColumn("item".to_owned(), old, new)],
+        )
+        .unwrap_err();
+    assert!(matches!(err, butane_core::Error::Unsupported("odbc", _)));
+}
+
+/// Creates a schema with mixed-case names, some of which are reserved words.
+fn create_mixed_case_adb() -> ADB {
+    let known_int_type = DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Int));
+
+    let mut db = ADB::default();
+    let mut user = ATable::new("User".to_owned());
+    user.add_column(AColumn::new(
+        "Id".to_owned(),
+        known_int_type.clone(),
+        false, // nullable
+        true,  // pk
+        false, // auto
+        false, // unique
+        None,  // default
+        None,  // reference
+    ));
+    db.replace_table(user);
+
+    let mut post = ATable::new("Post".to_owned());
+    post.add_column(AColumn::new(
+        "id".to_owned(),
+        known_int_type.clone(),
+        false, // nullable
+        true,  // pk
+        false, // auto
+        false, // unique
+        None,  // default
+        None,  // reference
+    ));
+    post.add_column(AColumn::new(
+        "Author".to_owned(),
+        known_int_type.clone(),
+        false, // nullable
+        false, // pk
+        false, // auto
+        false, // unique
+        None,  // default
+        Some(ARef::Literal(ARefLiteral::new("User", "Id"))),
+    ));
+    post.add_column(AColumn::new_simple("Order", known_int_type));
+    db.replace_table(post);
+    db
+}
+
+fn create_fold_identifier_case_ops() -> (Vec<Operation>, ADB, ADB) {
+    let old = create_mixed_case_adb();
+    let mut new = old.clone();
+    new.set_identifier_case(IdentifierCase::Lower);
+    let ops = diff(&old, &new);
+    (ops, old, new)
+}
+
+#[test]
+fn fold_identifier_case() {
+    let (_, _, new) = create_fold_identifier_case_ops();
+
+    assert_eq!(new.identifier_case(), IdentifierCase::Lower);
+    let names: Vec<&str> = new.tables().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["post", "user"]);
+    let post = new.get_table("post").unwrap();
+    let columns: Vec<&str> = post.columns.iter().map(|c| c.name()).collect();
+    assert_eq!(columns, vec!["id", "author", "order"]);
+    assert_eq!(
+        post.column("author").unwrap().reference(),
+        &Some(ARef::Literal(ARefLiteral::new("user", "id")))
+    );
+    assert_eq!(new.get_table("user").unwrap().pk().unwrap().name(), "id");
+}
+
+#[test]
+fn preserved_case_changes_are_not_renames() {
+    let old = create_mixed_case_adb();
+    let mut new = ADB::new();
+    let mut user = old.get_table("User").unwrap().clone();
+    user.name = "user".to_owned();
+    new.replace_table(user);
+    new.replace_table(old.get_table("Post").unwrap().clone());
+
+    let ops = diff(&old, &new);
+    assert!(!ops
+        .iter()
+        .any(|op| matches!(op, Operation::RenameTable(..) | Operation::RenameColumn(..))));
+    assert!(ops.contains(&Operation::RemoveTable("User".to_owned())));
+}
+
+#[test]
+fn fold_identifier_case_diff() {
+    let (ops, old, new) = create_fold_identifier_case_ops();
+
+    assert_eq!(
+        ops,
+        vec![
+            Operation::RenameTable("Post".to_owned(), "post".to_owned()),
+            Operation::RenameTable("User".to_owned(), "user".to_owned()),
+            Operation::RenameColumn("post".to_owned(), "Author".to_owned(), "author".to_owned()),
+            Operation::RenameColumn("post".to_owned(), "Order".to_owned(), "order".to_owned()),
+            Operation::RenameColumn("user".to_owned(), "Id".to_owned(), "id".to_owned()),
+        ]
+    );
+
+    let mut transformed = old;
+    for op in ops {
+        transformed.transform_with(op);
+    }
+    let transformed: Vec<&ATable> = transformed.tables().collect();
+    let new: Vec<&ATable> = new.tables().collect();
+    assert_eq!(transformed, new);
+}
+
+#[test]
+fn fold_identifier_case_ddl_sqlite() {
+    let (ops, old, _) = create_fold_identifier_case_ops();
+
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "ALTER TABLE Post RENAME TO post__butane_tmp;",
+            "ALTER TABLE post__butane_tmp RENAME TO post;",
+            "ALTER TABLE \"User\" RENAME TO user__butane_tmp;",
+            "ALTER TABLE user__butane_tmp RENAME TO \"user\";",
+            "ALTER TABLE post RENAME COLUMN Author TO author;",
+            "ALTER TABLE post RENAME COLUMN \"Order\" TO \"order\";",
+            "ALTER TABLE \"user\" RENAME COLUMN \"Id\" TO \"id\";",
+        ]
+    );
+}
+
+#[test]
+fn fold_identifier_case_ddl_pg() {
+    let (ops, old, _) = create_fold_identifier_case_ops();
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    // Unquoted names are already stored in lowercase.
+    assert_eq!(
+        sql_lines,
+        vec![
+            "ALTER TABLE \"User\" RENAME TO \"user\";",
+            "ALTER TABLE post RENAME COLUMN \"Order\" TO \"order\";",
+            "ALTER TABLE \"user\" RENAME COLUMN \"Id\" TO \"id\";",
+        ]
+    );
+}
+
+/// Creates a table described by comments, then changes the comments,
+/// returning the operations of each migration and the ADB after it.
+fn create_comment_ops() -> (Vec<Operation>, ADB, Vec<Operation>, ADB) {
+    let old = ADB::default();
+    let mut table = ATable::new("post".to_owned());
+    table.comment = Some("A post on a blog.".to_owned());
+    let mut id = AColumn::new(
+        "id",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::BigInt)),
+        false,
+        true,
+        false,
+        false,
+        None,
+        None,
+    );
+    id.set_comment(Some("Identifies the post.".to_owned()));
+    table.add_column(id);
+    let mut title = AColumn::new_simple(
+        "title",
+        DeferredSqlType::KnownId(TypeIdentifier::Ty(SqlType::Text)),
+    );
+    title.set_comment(Some("The post's title.".to_owned()));
+    table.add_column(title);
+    let mut created = ADB::default();
+    created.replace_table(table.clone());
+    let create_ops = diff(&old, &created);
+    assert_eq!(create_ops, vec![Operation::AddTable(table.clone())]);
+
+    // Changing only the comments does not change the columns
+    table.comment = None;
+    let mut title = table.column("title").unwrap().clone();
+    title.set_comment(Some("Shown in lists of posts.".to_owned()));
+    table.replace_column(title);
+    let mut changed = ADB::default();
+    changed.replace_table(table);
+    let change_ops = diff(&created, &changed);
+    assert_eq!(
+        change_ops,
+        vec![
+            Operation::SetColumnComment(
+                "post".to_owned(),
+                "title".to_owned(),
+                Some("Shown in lists of posts.".to_owned())
+            ),
+            Operation::SetTableComment("post".to_owned(), None),
+        ]
+    );
+    (create_ops, created, change_ops, changed)
+}
+
+#[test]
+fn comments_ddl_pg() {
+    let (create_ops, created, change_ops, changed) = create_comment_ops();
+
+    let backend = butane_core::db::get_backend("pg").unwrap();
+    let sql = backend.create_migration_sql(&created, create_ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "CREATE TABLE post (",
+            "\"id\" BIGINT NOT NULL PRIMARY KEY,",
+            "title TEXT NOT NULL",
+            ");",
+            "COMMENT ON TABLE post IS 'A post on a blog.';",
+            "COMMENT ON COLUMN post.\"id\" IS 'Identifies the post.';",
+            "COMMENT ON COLUMN post.title IS 'The post''s title.';",
+        ]
+    );
+    let sql = backend.create_migration_sql(&changed, change_ops).unwrap();
+    let sql_lines: Vec<&str> = sql.lines().collect();
+    assert_eq!(
+        sql_lines,
+        vec![
+            "COMMENT ON COLUMN post.title IS 'Shown in lists of posts.';",
+            "COMMENT ON TABLE post IS NULL;",
+        ]
+    );
+}
+
+#[test]
+fn comments_ddl_sqlite() {
+    let (_, _, change_ops, changed) = create_comment_ops();
+
+    // SQLite has no comments, so there is nothing to change
+    let backend = butane_core::db::get_backend("sqlite").unwrap();
+    let sql = backend.create_migration_sql(&changed, change_ops).unwrap();
+    assert_eq!(sql.trim(), "");
+}
+
+#[butane_test(nomigrate)]
+async fn comments_existing_schema(conn: ConnectionAsync) {
+    let (create_ops, created, change_ops, changed) = create_comment_ops();
+    let backend = conn.backend();
+
+    let sql = backend.create_migration_sql(&created, create_ops).unwrap();
+    conn.execute(&sql).await.unwrap();
+    let sql = backend.create_migration_sql(&changed, change_ops).unwrap();
+    conn.execute(&sql).await.unwrap();
+    conn.execute("SELECT \"id\", title FROM post")
+        .await
+        .unwrap();
+}
+
+#[butane_test(nomigrate)]
+async fn fold_identifier_case_existing_schema(conn: ConnectionAsync) {
+    let (ops, old, new) = create_fold_identifier_case_ops();
+    let backend = conn.backend();
+
+    let create_sql = backend
+        .create_migration_sql(&ADB::default(), diff(&ADB::default(), &old))
+        .unwrap();
+    conn.execute(&create_sql).await.unwrap();
+
+    let sql = backend.create_migration_sql(&old, ops).unwrap();
+    conn.execute(&sql).await.unwrap();
+    conn.execute("SELECT \"id\" FROM \"user\"").await.unwrap();
+    conn.execute("SELECT \"id\", author, \"order\" FROM post")
+        .await
+        .unwrap();
+
+    // And back again
+    let sql = backend
+        .create_migration_sql(&new, diff(&new, &old))
+        .unwrap();
+    conn.execute(&sql).await.unwrap();
+    conn.execute("SELECT \"Id\" FROM \"User\"").await.unwrap();
+}
@@ -228,3 +228,85 @@ pub struct fkey {
     assert_eq!(table.columns[0].name(), "id");
     assert_eq!(table.columns[1].name(), "foo");
 }
+
+#[test]
+fn subtypes_share_table() {
+    let mut migrations = MemMigrations::default();
+    let mention: syn::ItemStruct = parse_quote! {
+        #[butane(subtype_of = Notification, discriminator = "kind")]
+        pub struct Mention {
+            id: AutoPk<i64>,
+            recipient: String,
+            by: String,
+        }
+    };
+    let reminder: syn::ItemStruct = parse_quote! {
+        #[butane(subtype_of = Notification, discriminator = "kind")]
+        pub struct Reminder {
+            id: AutoPk<i64>,
+            recipient: String,
+            due: i64,
+        }
+    };
+    let _model = model_with_migrations(mention.to_token_stream(), &mut migrations);
+    let _model = model_with_migrations(reminder.to_token_stream(), &mut migrations);
+    let adb = migrations.current().db().unwrap();
+    assert!(adb.get_table("Mention").is_none());
+    let table = adb.get_table("Notification").unwrap();
+    let names: Vec<&str> = table.columns.iter().map(|col| col.name()).collect();
+    assert_eq!(names, ["id", "recipient", "by", "kind", "due"]);
+    assert!(!table.column("id").unwrap().nullable());
+    assert!(!table.column("kind").unwrap().nullable());
+    assert!(table.column("by").unwrap().nullable());
+    assert!(table.indexes.iter().any(|index| index.column == "kind"));
+
+    // A column removed from one subtype is kept while another has it
+    let reminder: syn::ItemStruct = parse_quote! {
+        #[butane(subtype_of = Notification, discriminator = "kind")]
+        pub struct Reminder {
+            id: AutoPk<i64>,
+            due: i64,
+        }
+    };
+    let _model = model_with_migrations(reminder.to_token_stream(), &mut migrations);
+    let mention: syn::ItemStruct = parse_quote! {
+        #[butane(subtype_of = Notification, discriminator = "kind")]
+        pub struct Mention {
+            id: AutoPk<i64>,
+            recipient: String,
+        }
+    };
+    let _model = model_with_migrations(mention.to_token_stream(), &mut migrations);
+    let adb = migrations.current().db().unwrap();
+    let table = adb.get_table("Notification").unwrap();
+    let names: Vec<&str> = table.columns.iter().map(|col| col.name()).collect();
+    assert_eq!(names, ["id", "recipient", "kind", "due"]);
+}
+
+#[test]
+fn doc_comments_describe_table() {
+    let mut migrations = MemMigrations::default();
+    let post: syn::ItemStruct = parse_quote! {
+        /// A post on a blog.
+        ///
+        /// Written by its author.
+        pub struct Post {
+            id: AutoPk<i64>,
+            /// The title, shown in lists of posts.
+            title: String,
+            body: String,
+        }
+    };
+    let _model = model_with_migrations(post.to_token_stream(), &mut migrations);
+    let adb = migrations.current().db().unwrap();
+    let table = adb.get_table("Post").unwrap();
+    assert_eq!(
+        table.comment.as_deref(),
+        Some("A post on a blog.\n\nWritten by its author.")
+    );
+    assert_eq!(
+        table.column("title").unwrap().comment(),
+        Some("The title, shown in lists of posts.")
+    );
+    assert_eq!(table.column("body").unwrap().comment(), None);
+}
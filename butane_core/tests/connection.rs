@@ -1,8 +1,12 @@
 use std::fs;
+use std::time::Duration;
 
+use butane_core::db::pg::PgBackend;
+use butane_core::db::sqlite::{JournalMode, SQLiteBackend, SQLiteOptions, Synchronous};
+use butane_core::db::{Backend, BackendConnectionAsync, BackendRows, Column, ConnectionMethods};
 use butane_core::{
-    db::{connect, connect_async, ConnectionAsync, ConnectionSpec},
-    Error,
+    db::{connect, connect_async, get_backend, register_backend, ConnectionAsync, ConnectionSpec},
+    Error, SqlType, SqlVal, SqlValRef,
 };
 use butane_test_helper::*;
 use butane_test_macros::butane_test;
@@ -12,6 +16,11 @@ async fn connection_not_closed(conn: ConnectionAsync) {
     assert!(!conn.is_closed());
 }
 
+#[butane_test(nomigrate)]
+async fn connection_ping(conn: ConnectionAsync) {
+    conn.ping().await.unwrap();
+}
+
 // The SQLite connection URI tests cover most cases described at https://www.sqlite.org/c3ref/open.html
 // and https://www.sqlite.org/inmemorydb.html
 
@@ -54,7 +63,7 @@ fn uri_sqlite_memory_parameter_fails() {
         // Windows does not support creating files that start with a colon.
         let connection_error = connect(&spec).unwrap_err();
         // Rust tools can not yet detect that this variable is used in the macro below
-        let _expected_error = format!("invalid uri authority: :memory:");
+        let _expected_error = "invalid uri authority: :memory:".to_string();
         eprintln!("{connection_error:?}");
         assert!(matches!(
             connection_error,
@@ -99,7 +108,7 @@ fn uri_sqlite_memory_file_scheme_with_slashes_fails() {
     assert_eq!(spec.connection_string(), uri);
     let connection_error = connect(&spec).unwrap_err();
     // Rust tools can not yet detect that this variable is used in the macro below
-    let _expected_error = format!("invalid uri authority: :memory:");
+    let _expected_error = "invalid uri authority: :memory:".to_string();
     assert!(matches!(
         connection_error,
         Error::SQLite(rusqlite::Error::SqliteFailure(_, Some(_expected_error)))
@@ -465,9 +474,116 @@ fn uri_sqlite_absolute_with_localhost() {
 // https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-CONNECT-HOST and
 // https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-PARAMKEYWORDS
 
+#[test]
+fn sqlite_options_connection_string() {
+    let options = SQLiteOptions::new()
+        .journal_mode(JournalMode::Wal)
+        .synchronous(Synchronous::Normal)
+        .busy_timeout(Duration::from_secs(5))
+        .busy_retries(3);
+    let conn_str = options.connection_string("app.db");
+    assert_eq!(
+        conn_str,
+        "file:app.db?journal_mode=wal&synchronous=normal&busy_timeout=5000&busy_retries=3"
+    );
+    assert_eq!(
+        SQLiteOptions::from_connection_string(&conn_str).unwrap(),
+        ("file:app.db".to_string(), options.clone())
+    );
+
+    // Other parameters are kept for SQLite
+    let conn_str = options.connection_string("file:app.db?mode=ro");
+    let (path, parsed) = SQLiteOptions::from_connection_string(&conn_str).unwrap();
+    assert_eq!(path, "file:app.db?mode=ro");
+    assert_eq!(parsed, options);
+
+    // Parameters of a file name which is not a URI are part of the name
+    let (path, parsed) = SQLiteOptions::from_connection_string("app.db?journal_mode=wal").unwrap();
+    assert_eq!(path, "app.db?journal_mode=wal");
+    assert_eq!(parsed, SQLiteOptions::new());
+
+    assert!(SQLiteOptions::from_connection_string("file:app.db?journal_mode=fast").is_err());
+    assert_eq!(SQLiteOptions::new().connection_string("app.db"), "app.db");
+}
+
+#[test]
+fn sqlite_options_applied() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("sqlite-test.db");
+    let options = SQLiteOptions::new()
+        .journal_mode(JournalMode::Wal)
+        .synchronous(Synchronous::Normal)
+        .foreign_keys(false)
+        .cache_size(-4000)
+        .page_size(8192);
+    let spec = ConnectionSpec::new("sqlite", options.connection_string(path.to_str().unwrap()));
+    let conn = connect(&spec).unwrap();
+
+    let pragma = |name: &'static str, ty: SqlType| -> SqlVal {
+        let column = Column::new(name, ty.clone());
+        let mut rows = conn
+            .query(&format!("pragma_{name}"), &[column], None, None, None, None)
+            .unwrap();
+        rows.next().unwrap().unwrap().get(0, ty).unwrap().into()
+    };
+    assert_eq!(
+        pragma("journal_mode", SqlType::Text),
+        SqlVal::Text("wal".to_string())
+    );
+    // NORMAL
+    assert_eq!(pragma("synchronous", SqlType::Int), SqlVal::Int(1));
+    assert_eq!(pragma("foreign_keys", SqlType::Int), SqlVal::Int(0));
+    assert_eq!(pragma("cache_size", SqlType::Int), SqlVal::Int(-4000));
+    assert_eq!(pragma("page_size", SqlType::Int), SqlVal::Int(8192));
+
+    // Foreign keys are enforced by default
+    let conn = connect(&ConnectionSpec::new("sqlite", ":memory:")).unwrap();
+    let column = Column::new("foreign_keys", SqlType::Int);
+    let mut rows = conn
+        .query("pragma_foreign_keys", &[column], None, None, None, None)
+        .unwrap();
+    let enabled: SqlVal = rows
+        .next()
+        .unwrap()
+        .unwrap()
+        .get(0, SqlType::Int)
+        .unwrap()
+        .into();
+    assert_eq!(enabled, SqlVal::Int(1));
+}
+
+#[test]
+fn sqlite_busy_retries() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("sqlite-test.db");
+    let path = path.to_str().unwrap();
+    let locker = connect(&ConnectionSpec::new("sqlite", path)).unwrap();
+    locker.execute("CREATE TABLE t (x INTEGER);").unwrap();
+    let columns = [Column::new("x", SqlType::BigInt)];
+    let values = [SqlValRef::BigInt(1)];
+
+    // Without waiting for the lock, inserting fails while it is held
+    let options = SQLiteOptions::new().busy_timeout(Duration::ZERO);
+    let spec = ConnectionSpec::new("sqlite", options.connection_string(path));
+    let conn = connect(&spec).unwrap();
+    locker.execute("BEGIN EXCLUSIVE;").unwrap();
+    let err = conn.insert_only("t", &columns, &values).unwrap_err();
+    assert!(err.is_retryable());
+
+    // With retries, inserting succeeds once the lock is released
+    let spec = ConnectionSpec::new("sqlite", options.busy_retries(10).connection_string(path));
+    let conn = connect(&spec).unwrap();
+    let unlock = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        locker.execute("COMMIT;").unwrap();
+    });
+    conn.insert_only("t", &columns, &values).unwrap();
+    unlock.join().unwrap();
+}
+
 #[test]
 fn pg_key_value_pairs() {
-    let pairs = format!("host=/tmp user=postgres");
+    let pairs = "host=/tmp user=postgres".to_string();
     let spec = ConnectionSpec::try_from(&pairs).unwrap();
     assert_eq!(spec.backend_name(), "pg");
     assert_eq!(spec.connection_string(), &pairs);
@@ -524,14 +640,14 @@ fn pg_key_value_pairs_host_only_unix_socket() {
 
 #[test]
 fn pg_key_value_pairs_host_only_tcpip() {
-    let pairs = format!("host=localhost");
+    let pairs = "host=localhost".to_string();
     let spec = ConnectionSpec::try_from(&pairs).unwrap();
     assert_eq!(spec.backend_name(), "pg");
     assert_eq!(spec.connection_string(), &pairs);
     // Same as above, this cant connect because it will attempt tp connect using the current username.
     // connect(&spec).unwrap();
 
-    let pairs = format!("host = localhost");
+    let pairs = "host = localhost".to_string();
     let spec = ConnectionSpec::try_from(&pairs).unwrap();
     assert_eq!(spec.backend_name(), "pg");
     assert_eq!(spec.connection_string(), &pairs);
@@ -820,6 +936,166 @@ async fn unreachable_pg_connection() {
     }
 }
 
+/// Terminates the sessions named `application_name` from another connection,
+/// and waits for `conn` to notice.
+async fn terminate_pg_session(connstr: &str, application_name: &str, conn: &ConnectionAsync) {
+    let other = connect_async(&ConnectionSpec::new("pg", connstr))
+        .await
+        .unwrap();
+    other
+        .execute(format!(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE application_name = '{application_name}'"
+        ))
+        .await
+        .unwrap();
+    for _ in 0..50 {
+        if conn.is_closed() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(conn.is_closed());
+}
+
+#[tokio::test]
+async fn pg_reconnect() {
+    let data = pg_setup().await;
+    let connstr = pg_connstr(&data);
+    let conn = PgBackend::new()
+        .with_reconnect(true)
+        .connect_async(&connstr)
+        .await
+        .unwrap();
+    conn.execute("SET application_name = 'butane_reconnect'")
+        .await
+        .unwrap();
+    terminate_pg_session(&connstr, "butane_reconnect", &conn).await;
+
+    conn.ping().await.unwrap();
+    assert!(!conn.is_closed());
+    // The session was restored with its application_name, so it can be found again.
+    terminate_pg_session(&connstr, "butane_reconnect", &conn).await;
+    conn.ping().await.unwrap();
+    pg_teardown(data);
+}
+
+#[tokio::test]
+async fn pg_reconnect_restores_config() {
+    use butane_core::db::ConnectionMethodsAsync;
+
+    let data = pg_setup().await;
+    let connstr = pg_connstr(&data);
+    let conn = PgBackend::new()
+        .with_reconnect(true)
+        .connect_async(&connstr)
+        .await
+        .unwrap();
+    conn.execute("SET application_name = 'butane_reconnect_config'")
+        .await
+        .unwrap();
+    conn.set_config("app.current_tenant", "first", false)
+        .await
+        .unwrap();
+    conn.set_config("app.current_tenant", "second", false)
+        .await
+        .unwrap();
+    terminate_pg_session(&connstr, "butane_reconnect_config", &conn).await;
+
+    // The parameter set for the session, such as a tenant read by
+    // row-level security policies, is set again in the new session
+    assert_eq!(
+        conn.current_config("app.current_tenant").await.unwrap(),
+        Some("second".to_string())
+    );
+    pg_teardown(data);
+}
+
+#[tokio::test]
+async fn pg_reconnect_restores_latest_parameters() {
+    use butane_core::db::ConnectionMethodsAsync;
+
+    let data = pg_setup().await;
+    let connstr = pg_connstr(&data);
+    let conn = PgBackend::new()
+        .with_reconnect(true)
+        .connect_async(&connstr)
+        .await
+        .unwrap();
+    conn.execute("SET application_name = 'butane_reconnect_latest'")
+        .await
+        .unwrap();
+    for schema in ["first", "second", "third"] {
+        conn.execute(&format!("SET search_path TO {schema}"))
+            .await
+            .unwrap();
+    }
+    conn.execute("SET statement_timeout = '5s'").await.unwrap();
+    conn.execute("RESET statement_timeout").await.unwrap();
+    conn.set_config("app.current_tenant", "first", false)
+        .await
+        .unwrap();
+    conn.execute("SET app.current_tenant = 'second'")
+        .await
+        .unwrap();
+    terminate_pg_session(&connstr, "butane_reconnect_latest", &conn).await;
+
+    assert_eq!(
+        conn.current_config("search_path").await.unwrap(),
+        Some("third".to_string())
+    );
+    assert_eq!(
+        conn.current_config("statement_timeout").await.unwrap(),
+        Some("0".to_string())
+    );
+    assert_eq!(
+        conn.current_config("app.current_tenant").await.unwrap(),
+        Some("second".to_string())
+    );
+    pg_teardown(data);
+}
+
+#[tokio::test]
+async fn pg_no_reconnect_within_transaction() {
+    use butane_core::db::ConnectionMethodsAsync;
+
+    let data = pg_setup().await;
+    let connstr = pg_connstr(&data);
+    let conn = PgBackend::new()
+        .with_reconnect(true)
+        .connect_async(&connstr)
+        .await
+        .unwrap();
+    conn.execute("SET application_name = 'butane_reconnect_transaction'")
+        .await
+        .unwrap();
+    conn.execute("BEGIN;").await.unwrap();
+    assert!(conn.in_transaction());
+    terminate_pg_session(&connstr, "butane_reconnect_transaction", &conn).await;
+
+    // The rest of the transaction is not run on a new session without
+    // what came before it
+    let err = conn.execute("COMMIT;").await.unwrap_err();
+    assert!(matches!(err, butane_core::Error::ConnectionClosed));
+    assert!(conn.is_closed());
+    pg_teardown(data);
+}
+
+#[tokio::test]
+async fn pg_connection_closed_without_reconnect() {
+    let data = pg_setup().await;
+    let connstr = pg_connstr(&data);
+    let conn = connect_async(&ConnectionSpec::new("pg", &connstr))
+        .await
+        .unwrap();
+    conn.execute("SET application_name = 'butane_no_reconnect'")
+        .await
+        .unwrap();
+    terminate_pg_session(&connstr, "butane_no_reconnect", &conn).await;
+
+    assert!(matches!(conn.ping().await, Err(Error::ConnectionClosed)));
+    pg_teardown(data);
+}
+
 #[butane_test(nomigrate)]
 async fn debug_connection(conn: ConnectionAsync) {
     let backend_name = conn.backend_name();
@@ -866,3 +1142,379 @@ fn saves_invalid_connection_spec_to_missing_path() {
     let loaded_spec = ConnectionSpec::load(path).unwrap();
     assert_eq!(spec, loaded_spec);
 }
+
+#[test]
+fn persist_connection_environments() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let dev = ConnectionSpec::new("sqlite", "dev.db");
+    let prod = ConnectionSpec::new("pg", "host=db user=app");
+    dev.save_environment(dir.path(), "dev").unwrap();
+    prod.save_environment(dir.path(), "prod").unwrap();
+
+    assert_eq!(
+        ConnectionSpec::load_environment(dir.path(), "dev").unwrap(),
+        dev
+    );
+    assert_eq!(
+        ConnectionSpec::load_environment(dir.path(), "prod").unwrap(),
+        prod
+    );
+    let result = ConnectionSpec::load_environment(dir.path(), "test");
+    assert!(matches!(result, Err(butane_core::Error::UnknownEnvironment(e)) if e == "test"));
+
+    // The default connection is separate from the environments
+    let result = ConnectionSpec::load(dir.path());
+    assert!(matches!(result, Err(butane_core::Error::IO(_))));
+}
+
+#[test]
+fn connection_spec_interpolates_env_vars() {
+    std::env::set_var("BUTANE_TEST_INTERPOLATE_PASSWORD", "s3cret");
+    let dir = tempfile::TempDir::new().unwrap();
+    let spec = ConnectionSpec::new(
+        "pg",
+        "host=db user=app password=${BUTANE_TEST_INTERPOLATE_PASSWORD}",
+    );
+    spec.save_environment(dir.path(), "prod").unwrap();
+    spec.save(dir.path()).unwrap();
+
+    let expected = ConnectionSpec::new("pg", "host=db user=app password=s3cret");
+    assert_eq!(
+        ConnectionSpec::load_environment(dir.path(), "prod").unwrap(),
+        expected
+    );
+    assert_eq!(ConnectionSpec::load(dir.path()).unwrap(), expected);
+
+    // The variable is saved, not its value
+    let saved = std::fs::read_to_string(dir.path().join("environments.json")).unwrap();
+    assert!(saved.contains("${BUTANE_TEST_INTERPOLATE_PASSWORD}"));
+
+    let spec = ConnectionSpec::new("sqlite", "${BUTANE_TEST_INTERPOLATE_UNSET}/test.db");
+    spec.save_environment(dir.path(), "dev").unwrap();
+    let result = ConnectionSpec::load_environment(dir.path(), "dev");
+    assert!(
+        matches!(result, Err(butane_core::Error::MissingEnvVar(v)) if v == "BUTANE_TEST_INTERPOLATE_UNSET")
+    );
+}
+
+/// A backend outside butane_core, here SQLite under another name.
+#[derive(Clone, Debug)]
+struct RenamedSqliteBackend;
+
+#[async_trait::async_trait]
+impl Backend for RenamedSqliteBackend {
+    fn name(&self) -> &'static str {
+        "renamed_sqlite"
+    }
+    fn row_id_column(&self) -> Option<&'static str> {
+        SQLiteBackend::new().row_id_column()
+    }
+    fn create_migration_sql(
+        &self,
+        current: &butane_core::migrations::adb::ADB,
+        ops: Vec<butane_core::migrations::adb::Operation>,
+    ) -> butane_core::Result<String> {
+        SQLiteBackend::new().create_migration_sql(current, ops)
+    }
+    fn connect(&self, conn_str: &str) -> butane_core::Result<butane_core::db::Connection> {
+        SQLiteBackend::new().connect(conn_str)
+    }
+    #[cfg(feature = "async")]
+    async fn connect_async(&self, conn_str: &str) -> butane_core::Result<ConnectionAsync> {
+        SQLiteBackend::new().connect_async(conn_str).await
+    }
+}
+
+#[test]
+fn registered_backend() {
+    let spec = ConnectionSpec::new("renamed_sqlite", ":memory:");
+    assert!(matches!(connect(&spec), Err(Error::UnknownBackend(_))));
+
+    register_backend(Box::new(RenamedSqliteBackend));
+    assert_eq!(
+        get_backend("renamed_sqlite").unwrap().name(),
+        "renamed_sqlite"
+    );
+    let conn = connect(&spec).unwrap();
+    conn.execute("CREATE TABLE t (x INTEGER)").unwrap();
+}
+
+/// Connection methods of a backend outside butane which implements only
+/// those every backend must.
+struct MinimalConnection;
+
+impl ConnectionMethods for MinimalConnection {
+    fn execute(&self, _sql: &str) -> butane_core::Result<()> {
+        Ok(())
+    }
+    fn query<'c>(
+        &'c self,
+        _table: &str,
+        _columns: &[Column],
+        _expr: Option<butane_core::query::BoolExpr>,
+        _limit: Option<i32>,
+        _offset: Option<i32>,
+        _sort: Option<&[butane_core::query::Order]>,
+    ) -> butane_core::Result<butane_core::db::RawQueryResult<'c>> {
+        unimplemented!()
+    }
+    fn insert_returning_pk(
+        &self,
+        _table: &str,
+        _columns: &[Column],
+        _pkcol: &Column,
+        _values: &[SqlValRef<'_>],
+    ) -> butane_core::Result<SqlVal> {
+        unimplemented!()
+    }
+    fn insert_only(
+        &self,
+        _table: &str,
+        _columns: &[Column],
+        _values: &[SqlValRef<'_>],
+    ) -> butane_core::Result<()> {
+        unimplemented!()
+    }
+    fn insert_or_replace(
+        &self,
+        _table: &str,
+        _columns: &[Column],
+        _pkcol: &Column,
+        _values: &[SqlValRef<'_>],
+    ) -> butane_core::Result<()> {
+        unimplemented!()
+    }
+    fn update(
+        &self,
+        _table: &str,
+        _pkcol: Column,
+        _pk: SqlValRef<'_>,
+        _columns: &[Column],
+        _values: &[SqlValRef<'_>],
+    ) -> butane_core::Result<()> {
+        unimplemented!()
+    }
+    fn delete_where(
+        &self,
+        _table: &str,
+        _expr: butane_core::query::BoolExpr,
+    ) -> butane_core::Result<usize> {
+        unimplemented!()
+    }
+    fn has_table(&self, _table: &str) -> butane_core::Result<bool> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn optional_connection_methods() {
+    let conn = MinimalConnection;
+    let unsupported = |result: butane_core::Result<()>| match result {
+        Err(Error::Unsupported(_, feature)) => feature,
+        other => panic!("expected Unsupported, got {other:?}"),
+    };
+    assert_eq!(unsupported(conn.notify("jobs", "")), "notifications");
+    assert_eq!(
+        unsupported(conn.set_config("app.tenant", "1", false)),
+        "configuration parameters"
+    );
+    assert_eq!(
+        unsupported(conn.list_tables().map(|_| ())),
+        "schema introspection"
+    );
+    assert_eq!(
+        unsupported(conn.execute_params("SELECT 1", &[]).map(|_| ())),
+        "parameters"
+    );
+}
+
+/// Connects to the database given by the ODBC connection string in the
+/// environment variable `BUTANE_ODBC_CONNSTR`. The ODBC tests are
+/// skipped when it is not set, as there is no database to start.
+#[cfg(feature = "odbc")]
+fn odbc_connection() -> Option<butane_core::db::Connection> {
+    let conn_str = std::env::var("BUTANE_ODBC_CONNSTR").ok()?;
+    Some(connect(&ConnectionSpec::new("odbc", conn_str)).unwrap())
+}
+
+#[cfg(feature = "odbc")]
+#[test]
+fn odbc_round_trip() {
+    use butane_core::query::{Order, OrderDirection};
+
+    let Some(conn) = odbc_connection() else {
+        return;
+    };
+    let _ = conn.execute("DROP TABLE butane_odbc_test");
+    conn.execute(
+        "CREATE TABLE butane_odbc_test (id INTEGER NOT NULL PRIMARY KEY, flag SMALLINT NOT NULL, ratio DOUBLE PRECISION, title VARCHAR(4000))",
+    )
+    .unwrap();
+    let columns = [
+        Column::new("id", SqlType::Int),
+        Column::new("flag", SqlType::Bool),
+        Column::new("ratio", SqlType::Real),
+        Column::new("title", SqlType::Text),
+    ];
+    conn.insert_only(
+        "butane_odbc_test",
+        &columns,
+        &[
+            SqlValRef::Int(1),
+            SqlValRef::Bool(true),
+            SqlValRef::Real(0.5),
+            SqlValRef::Text("one"),
+        ],
+    )
+    .unwrap();
+    // The emulated upsert updates the existing row and inserts the new one
+    for (id, title) in [(1, "uno"), (2, "two")] {
+        conn.insert_or_replace(
+            "butane_odbc_test",
+            &columns,
+            &columns[0],
+            &[
+                SqlValRef::Int(id),
+                SqlValRef::Bool(false),
+                SqlValRef::Null,
+                SqlValRef::Text(title),
+            ],
+        )
+        .unwrap();
+    }
+
+    let order = [Order {
+        direction: OrderDirection::Ascending,
+        column: "id",
+        expr: None,
+    }];
+    let mut rows = conn
+        .query("butane_odbc_test", &columns, None, None, None, Some(&order))
+        .unwrap();
+    let mut read = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        read.push(
+            (0..columns.len())
+                .map(|i| SqlVal::from(row.get(i, columns[i].ty().clone()).unwrap()))
+                .collect::<Vec<SqlVal>>(),
+        );
+    }
+    assert_eq!(
+        read,
+        vec![
+            vec![
+                SqlVal::Int(1),
+                SqlVal::Bool(false),
+                SqlVal::Null,
+                SqlVal::Text("uno".to_string()),
+            ],
+            vec![
+                SqlVal::Int(2),
+                SqlVal::Bool(false),
+                SqlVal::Null,
+                SqlVal::Text("two".to_string()),
+            ],
+        ]
+    );
+
+    // LIMIT and OFFSET are applied while reading the rows
+    let mut rows = conn
+        .query(
+            "butane_odbc_test",
+            &columns[..1],
+            None,
+            Some(1),
+            Some(1),
+            Some(&order),
+        )
+        .unwrap();
+    let id: SqlVal = rows
+        .next()
+        .unwrap()
+        .unwrap()
+        .get(0, SqlType::Int)
+        .unwrap()
+        .into();
+    assert_eq!(id, SqlVal::Int(2));
+    assert!(rows.next().unwrap().is_none());
+
+    conn.execute("DROP TABLE butane_odbc_test").unwrap();
+}
+
+#[cfg(feature = "odbc")]
+#[test]
+fn odbc_list_filters() {
+    use butane_core::query::{BoolExpr, Order, OrderDirection};
+
+    let Some(conn) = odbc_connection() else {
+        return;
+    };
+    let _ = conn.execute("DROP TABLE butane_odbc_lists");
+    conn.execute(
+        "CREATE TABLE butane_odbc_lists (id INTEGER NOT NULL PRIMARY KEY, tags VARCHAR(4000) NOT NULL)",
+    )
+    .unwrap();
+    let columns = [
+        Column::new("id", SqlType::Int),
+        Column::new("tags", SqlType::TextList),
+    ];
+    let lists: [&[&str]; 4] = [&["a", "b"], &["b"], &["a\",\"c", "50%_off"], &[]];
+    for (id, tags) in lists.iter().enumerate() {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        conn.insert_only(
+            "butane_odbc_lists",
+            &columns,
+            &[SqlValRef::Int(id as i32), SqlValRef::TextList(tags.into())],
+        )
+        .unwrap();
+    }
+
+    let order = [Order {
+        direction: OrderDirection::Ascending,
+        column: "id",
+        expr: None,
+    }];
+    let ids = |expr: BoolExpr| -> Vec<SqlVal> {
+        let mut rows = conn
+            .query(
+                "butane_odbc_lists",
+                &columns[..1],
+                Some(expr),
+                None,
+                None,
+                Some(&order),
+            )
+            .unwrap();
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().unwrap() {
+            ids.push(row.get(0, SqlType::Int).unwrap().into());
+        }
+        ids
+    };
+    let tags = |tags: &[&str]| -> Vec<String> { tags.iter().map(|tag| tag.to_string()).collect() };
+    assert_eq!(
+        ids(BoolExpr::ListContains("tags", tags(&["a"]))),
+        vec![SqlVal::Int(0)]
+    );
+    assert_eq!(
+        ids(BoolExpr::ListContains("tags", tags(&["b", "a"]))),
+        vec![SqlVal::Int(0)]
+    );
+    // Neither the quotes inside an element nor LIKE wildcards match more
+    assert_eq!(
+        ids(BoolExpr::ListContains("tags", tags(&["c"]))),
+        Vec::<SqlVal>::new()
+    );
+    assert_eq!(
+        ids(BoolExpr::ListContains("tags", tags(&["50%_off"]))),
+        vec![SqlVal::Int(2)]
+    );
+    assert_eq!(
+        ids(BoolExpr::ListOverlaps("tags", tags(&["b", "a\",\"c"]))),
+        vec![SqlVal::Int(0), SqlVal::Int(1), SqlVal::Int(2)]
+    );
+    assert_eq!(ids(BoolExpr::ListContains("tags", Vec::new())).len(), 4);
+    assert!(ids(BoolExpr::ListOverlaps("tags", Vec::new())).is_empty());
+
+    conn.execute("DROP TABLE butane_odbc_lists").unwrap();
+}
@@ -1,14 +1,22 @@
 extern crate alloc;
 
+use std::time::{Duration, SystemTime};
+
 use butane_core::codegen::{butane_type_with_migrations, model_with_migrations};
-use butane_core::db::{BackendConnection, Connection};
-use butane_core::migrations::adb::{DeferredSqlType, TypeIdentifier, TypeKey};
-use butane_core::migrations::{MemMigrations, Migration, MigrationMut, Migrations, MigrationsMut};
-use butane_core::{SqlType, SqlVal};
+use butane_core::db::{BackendConnection, BackendRows, Column, Connection, ConnectionMethods};
+use butane_core::migrations::adb::{
+    AIndex, AView, DeferredSqlType, OnDelete, PartitionBy, PartitionMethod, TypeIdentifier, TypeKey,
+};
+use butane_core::migrations::{
+    copy_migration, AppliedMigration, HookStage, MemMigrations, Migration, MigrationMut,
+    Migrations, MigrationsMut, BATCHED_MARKER, OUTSIDE_TRANSACTION_MARKER,
+};
+use butane_core::{FromSql, SqlType, SqlVal};
 #[cfg(feature = "pg")]
 use butane_test_helper::pg_connection;
 #[cfg(feature = "sqlite")]
 use butane_test_helper::sqlite_connection;
+use fallible_iterator::FallibleIterator;
 use pretty_assertions::assert_eq;
 use proc_macro2::TokenStream;
 use quote::quote;
@@ -139,6 +147,163 @@ struct Foo {
     assert_eq!(col.typeid().unwrap(), TypeIdentifier::Ty(SqlType::Text));
 }
 
+#[test]
+fn current_migration_many_table_attribute() {
+    let tokens = quote! {
+        struct Foo {
+            id: i64,
+            #[butane(many_table = "foo_tags")]
+            tags: butane::Many<Tag>,
+            others: butane::Many<Tag>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    model_with_migrations(quote! { struct Tag { id: i64 } }, &mut ms);
+    model_with_migrations(tokens, &mut ms);
+    let db = ms.current().db().unwrap();
+    let table = db.get_table("foo_tags").expect("No foo_tags table");
+    assert!(table.column("owner").is_some());
+    assert!(table.column("has").is_some());
+    assert!(db.get_table("Foo_tags_Many").is_none());
+    assert!(db.get_table("Foo_others_Many").is_some());
+}
+
+#[test]
+fn current_migration_on_delete_attribute() {
+    let tokens = quote! {
+        struct Foo {
+            id: i64,
+            #[butane(on_delete = "cascade")]
+            tag: butane::ForeignKey<Tag>,
+            other: butane::ForeignKey<Tag>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    model_with_migrations(quote! { struct Tag { id: i64 } }, &mut ms);
+    model_with_migrations(tokens, &mut ms);
+    let db = ms.current().db().unwrap();
+    let table = db.get_table("Foo").expect("No Foo table");
+    assert_eq!(
+        table.column("tag").unwrap().on_delete(),
+        Some(OnDelete::Cascade)
+    );
+    assert_eq!(table.column("other").unwrap().on_delete(), None);
+}
+
+#[test]
+fn current_migration_no_foreign_key_attribute() {
+    let tokens = quote! {
+        struct Foo {
+            id: i64,
+            #[butane(no_foreign_key)]
+            tag: butane::ForeignKey<Tag>,
+            other: butane::ForeignKey<Tag>,
+            #[butane(no_foreign_key)]
+            tags: butane::Many<Tag>,
+            others: butane::Many<Tag>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    model_with_migrations(quote! { struct Tag { id: i64 } }, &mut ms);
+    model_with_migrations(tokens, &mut ms);
+    let db = ms.current().db().unwrap();
+    let table = db.get_table("Foo").expect("No Foo table");
+    assert!(table.column("tag").unwrap().reference().is_none());
+    assert!(table.column("other").unwrap().reference().is_some());
+    let many_table = db
+        .get_table("Foo_tags_Many")
+        .expect("No Foo_tags_Many table");
+    assert!(many_table.columns.iter().all(|c| c.reference().is_none()));
+    let many_table = db
+        .get_table("Foo_others_Many")
+        .expect("No Foo_others_Many table");
+    assert!(many_table.columns.iter().all(|c| c.reference().is_some()));
+}
+
+#[test]
+fn current_migration_partition_by_attribute() {
+    let tokens = quote! {
+        #[butane(partition_by = "range(created_at)")]
+        struct Foo {
+            id: i64,
+            created_at: i64,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    model_with_migrations(tokens, &mut ms);
+    let db = ms.current().db().unwrap();
+    let table = db.get_table("Foo").expect("No Foo table");
+    assert_eq!(
+        table.partition_by,
+        Some(PartitionBy {
+            method: PartitionMethod::Range,
+            column: "created_at".to_string(),
+        })
+    );
+}
+
+#[test]
+fn current_migration_view_attribute() {
+    let tokens = quote! {
+        #[butane(view = "SELECT id, tag FROM Bar")]
+        struct Foo {
+            id: i64,
+            tag: butane::ForeignKey<Tag>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    model_with_migrations(quote! { struct Tag { id: i64 } }, &mut ms);
+    model_with_migrations(tokens, &mut ms);
+    let db = ms.current().db().unwrap();
+    let table = db.get_table("Foo").expect("No Foo table");
+    assert_eq!(table.view, Some(AView::new("SELECT id, tag FROM Bar")));
+    // Views cannot have foreign key constraints
+    assert!(table.column("tag").unwrap().reference().is_none());
+}
+
+#[test]
+fn current_migration_materialized_view_attribute() {
+    let tokens = quote! {
+        #[butane(view = "SELECT id, tag, name FROM Bar", materialized)]
+        struct Foo {
+            #[unique]
+            id: i64,
+            #[butane(index)]
+            tag: i64,
+            name: String,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    model_with_migrations(tokens, &mut ms);
+    let db = ms.current().db().unwrap();
+    let table = db.get_table("Foo").expect("No Foo table");
+    let view = table.view.as_ref().expect("Foo is not a view");
+    assert!(view.materialized);
+    assert_eq!(
+        view.indexes,
+        vec![
+            AIndex {
+                column: "id".to_owned(),
+                extra_columns: Vec::new(),
+                unique: true,
+                concurrently: false,
+            },
+            AIndex {
+                column: "tag".to_owned(),
+                extra_columns: Vec::new(),
+                unique: false,
+                concurrently: false,
+            },
+        ]
+    );
+}
+
 #[test]
 fn current_migration_custom_type() {
     let tokens = quote! {
@@ -197,6 +362,59 @@ fn migration_add_field_pg() {
     );
 }
 
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_hooks_sqlite() {
+    migration_hooks(&mut sqlite_connection());
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_hooks_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_hooks(&mut conn);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_custom_migrations_table_sqlite() {
+    migration_custom_migrations_table(&mut sqlite_connection(), "main.schema_migrations");
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_custom_migrations_table_pg() {
+    let (mut conn, _data) = pg_connection();
+    conn.execute("CREATE SCHEMA tooling;").unwrap();
+    migration_custom_migrations_table(&mut conn, "tooling.schema_migrations");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_applied_history_sqlite() {
+    migration_applied_history(&mut sqlite_connection());
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_applied_history_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_applied_history(&mut conn);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_check_sqlite() {
+    migration_check(&mut sqlite_connection());
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_check_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_check(&mut conn);
+}
+
 #[cfg(feature = "sqlite")]
 #[test]
 fn migration_add_field_with_default_sqlite() {
@@ -219,6 +437,71 @@ fn migration_add_field_with_default_pg() {
     );
 }
 
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_add_index_concurrently_sqlite() {
+    // Indexes are always built in the migration's transaction
+    migration_add_index_concurrently(
+        &mut sqlite_connection(),
+        "CREATE INDEX Foo_bar_idx ON Foo (bar);",
+        "DROP INDEX IF EXISTS Foo_bar_idx;",
+    );
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_add_index_concurrently_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_add_index_concurrently(
+        &mut conn,
+        "-- butane: outside transaction\n\
+         DROP INDEX CONCURRENTLY IF EXISTS Foo_bar_idx;\n\
+         -- butane: outside transaction\n\
+         CREATE INDEX CONCURRENTLY Foo_bar_idx ON Foo (bar);",
+        "-- butane: outside transaction\n\
+         DROP INDEX CONCURRENTLY IF EXISTS Foo_bar_idx;",
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_many_disallow_duplicates_sqlite() {
+    migration_many_disallow_duplicates(&mut sqlite_connection());
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_many_disallow_duplicates_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_many_disallow_duplicates(&mut conn);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_add_field_backfill_sqlite() {
+    migration_add_field_backfill(&mut sqlite_connection(), false);
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_add_field_backfill_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_add_field_backfill(&mut conn, false);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn migration_add_marked_field_backfill_sqlite() {
+    migration_add_field_backfill(&mut sqlite_connection(), true);
+}
+
+#[cfg(feature = "pg")]
+#[test]
+fn migration_add_marked_field_backfill_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_add_field_backfill(&mut conn, true);
+}
+
 #[cfg(feature = "pg")]
 #[test]
 fn migration_modify_field_pg() {
@@ -241,14 +524,14 @@ fn migration_modify_field_pg() {
 
     migration_modify_field_pkey_change(
         &mut conn,
-        "ALTER TABLE Foo DROP CONSTRAINT IF EXISTS Foo_pkey;\nALTER TABLE Foo ADD PRIMARY KEY (baz);",
-        "ALTER TABLE Foo DROP CONSTRAINT IF EXISTS Foo_pkey;\nALTER TABLE Foo ADD PRIMARY KEY (bar);",
+        "ALTER TABLE Foo DROP CONSTRAINT IF EXISTS \"foo_pkey\";\nALTER TABLE Foo ADD PRIMARY KEY (baz);",
+        "ALTER TABLE Foo DROP CONSTRAINT IF EXISTS \"foo_pkey\";\nALTER TABLE Foo ADD PRIMARY KEY (bar);",
     );
 
     migration_modify_field_uniqueness_change(
         &mut conn,
         "ALTER TABLE Foo ADD UNIQUE (bar);",
-        "ALTER TABLE Foo DROP CONSTRAINT Foo_bar_key;",
+        "ALTER TABLE Foo DROP CONSTRAINT \"foo_bar_key\";",
     );
 
     migration_modify_field_default_added(
@@ -264,6 +547,19 @@ fn migration_modify_field_pg() {
     );
 }
 
+#[cfg(all(feature = "sqlite", feature = "uuid"))]
+#[test]
+fn migration_convert_field_type_sqlite() {
+    migration_convert_field_type(&mut sqlite_connection());
+}
+
+#[cfg(all(feature = "pg", feature = "uuid"))]
+#[test]
+fn migration_convert_field_type_pg() {
+    let (mut conn, _data) = pg_connection();
+    migration_convert_field_type(&mut conn);
+}
+
 #[cfg(feature = "sqlite")]
 #[test]
 fn migration_add_and_remove_field_sqlite() {
@@ -399,6 +695,377 @@ struct Foo {
     test_migrate(conn, init, v2, up_sql, down_sql);
 }
 
+/// Adds an index to an existing column, comparing the generated SQL verbatim
+/// as sqlparser does not understand `DROP INDEX CONCURRENTLY`.
+fn migration_add_index_concurrently(conn: &mut Connection, up_sql: &str, down_sql: &str) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+            bar: String,
+        }
+    };
+
+    let v2 = quote! {
+        struct Foo {
+            id: i64,
+            #[butane(index(concurrently))]
+            bar: String,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend.clone()];
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    model_with_migrations(v2, &mut ms);
+    assert!(ms
+        .create_migration(&backends, "v2", ms.latest().as_ref())
+        .unwrap());
+
+    ms.migrate(conn).unwrap();
+    conn.execute("INSERT INTO Foo (id, bar) VALUES (1, 'a');")
+        .unwrap();
+
+    let v2_migration = ms.latest().unwrap();
+    let actual_up_sql = v2_migration.up_sql(backend.name()).unwrap().unwrap();
+    assert_eq!(actual_up_sql.trim(), up_sql);
+    let actual_down_sql = v2_migration.down_sql(backend.name()).unwrap().unwrap();
+    assert_eq!(actual_down_sql.trim(), down_sql);
+
+    ms.unmigrate(conn).unwrap();
+    assert_eq!(ms.unapplied_migrations(conn).unwrap().len(), 2);
+}
+
+/// Adds a NOT NULL field to a populated table, backfilling it because it
+/// is `marked` with `#[butane(backfill)]` or else because the whole
+/// migration is created to backfill.
+fn migration_add_field_backfill(conn: &mut Connection, marked: bool) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+            bar: String,
+        }
+    };
+
+    let v2 = if marked {
+        quote! {
+            struct Foo {
+                id: i64,
+                bar: String,
+                #[default=42]
+                #[butane(backfill)]
+                baz: i32,
+            }
+        }
+    } else {
+        quote! {
+            struct Foo {
+                id: i64,
+                bar: String,
+                #[default=42]
+                baz: i32,
+            }
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend];
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    ms.migrate(conn).unwrap();
+    conn.execute("INSERT INTO Foo (id, bar) VALUES (1, 'a');")
+        .unwrap();
+
+    model_with_migrations(v2, &mut ms);
+    let created = if marked {
+        ms.create_migration(&backends, "v2", ms.latest().as_ref())
+    } else {
+        ms.create_backfill_migration(&backends, "v2", ms.latest().as_ref())
+    };
+    assert!(created.unwrap());
+    let up_sql = ms
+        .latest()
+        .unwrap()
+        .up_sql(conn.backend_name())
+        .unwrap()
+        .unwrap();
+    assert!(up_sql.contains("UPDATE"), "no backfill in {up_sql}");
+    if conn.backend_name() == "pg" {
+        assert!(
+            up_sql.contains(BATCHED_MARKER),
+            "unbatched backfill in {up_sql}"
+        );
+        // The column is added in order, without filling it in, and
+        // before the batches which backfill it
+        let added = up_sql
+            .find("ALTER TABLE Foo ADD COLUMN IF NOT EXISTS baz INTEGER;")
+            .unwrap_or_else(|| panic!("column added with its default in {up_sql}"));
+        assert!(added < up_sql.find(BATCHED_MARKER).unwrap());
+        assert!(!up_sql.contains(OUTSIDE_TRANSACTION_MARKER));
+        // The steps before the last transaction may be repeated, so the
+        // migration can be run again if that transaction fails
+        let mut failing = ms.latest().unwrap();
+        failing
+            .set_hook_sql("pg", HookStage::After, Some("SELECT 1 / 0;"))
+            .unwrap();
+        failing.apply(conn).unwrap_err();
+        assert_eq!(ms.unapplied_migrations(conn).unwrap().len(), 1);
+    }
+    ms.migrate(conn).unwrap();
+
+    let column = Column::new("baz", SqlType::Int);
+    let vals: Vec<i32> = conn
+        .query("Foo", &[column], None, None, None, None)
+        .unwrap()
+        .mapped(|row| i32::from_sql_ref(row.get(0, SqlType::Int)?))
+        .collect()
+        .unwrap();
+    assert_eq!(vals, vec![42]);
+    // The column is NOT NULL once the migration has been applied
+    assert!(conn
+        .execute("INSERT INTO Foo (id, bar, baz) VALUES (2, 'b', NULL);")
+        .is_err());
+
+    ms.unmigrate(conn).unwrap();
+}
+
+/// Stops a `Many` field from allowing duplicates once its table holds
+/// some, which are deleted as its unique index is added.
+fn migration_many_disallow_duplicates(conn: &mut Connection) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+            #[butane(allow_duplicates)]
+            tags: butane::Many<Tag>,
+        }
+    };
+
+    let v2 = quote! {
+        struct Foo {
+            id: i64,
+            tags: butane::Many<Tag>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend];
+    model_with_migrations(quote! { struct Tag { id: i64 } }, &mut ms);
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    ms.migrate(conn).unwrap();
+    conn.execute("INSERT INTO \"Tag\" (id) VALUES (1), (2);")
+        .unwrap();
+    conn.execute("INSERT INTO Foo (id) VALUES (1);").unwrap();
+    conn.execute("INSERT INTO Foo_tags_Many (owner, has) VALUES (1, 1), (1, 2), (1, 1), (1, 1);")
+        .unwrap();
+
+    model_with_migrations(v2, &mut ms);
+    assert!(ms
+        .create_migration(&backends, "v2", ms.latest().as_ref())
+        .unwrap());
+    ms.migrate(conn).unwrap();
+
+    let column = Column::new("has", SqlType::BigInt);
+    let mut tags: Vec<i64> = conn
+        .query("Foo_tags_Many", &[column], None, None, None, None)
+        .unwrap()
+        .mapped(|row| i64::from_sql_ref(row.get(0, SqlType::BigInt)?))
+        .collect()
+        .unwrap();
+    tags.sort();
+    assert_eq!(tags, vec![1, 2]);
+    assert!(conn
+        .execute("INSERT INTO Foo_tags_Many (owner, has) VALUES (1, 2);")
+        .is_err());
+
+    conn.execute("DELETE FROM Foo_tags_Many;").unwrap();
+    ms.unmigrate(conn).unwrap();
+}
+
+fn migration_hooks(conn: &mut Connection) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+            bar: String,
+        }
+    };
+
+    let v2 = quote! {
+        struct Foo {
+            id: i64,
+            bar: String,
+            #[default=0]
+            baz: i32,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backend_name = backend.name();
+    let backends = nonempty::nonempty![backend];
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    model_with_migrations(v2, &mut ms);
+    assert!(ms
+        .create_migration(&backends, "v2", ms.latest().as_ref())
+        .unwrap());
+
+    // The hooks run either side of adding the baz column
+    let mut v2 = ms.latest().unwrap();
+    v2.set_hook_sql(
+        backend_name,
+        HookStage::Before,
+        Some("INSERT INTO Foo (id, bar) VALUES (1, 'a');"),
+    )
+    .unwrap();
+    v2.set_hook_sql(
+        backend_name,
+        HookStage::After,
+        Some("UPDATE Foo SET baz = 7 WHERE id = 1;"),
+    )
+    .unwrap();
+    assert_eq!(
+        v2.hook_sql(backend_name, HookStage::After).unwrap(),
+        Some("UPDATE Foo SET baz = 7 WHERE id = 1;".to_owned())
+    );
+    ms.add_migration(v2).unwrap();
+
+    // Hooks are kept when migrations are copied, e.g. for embedding
+    let mut copy = ms.new_migration("v2");
+    copy_migration(&ms.latest().unwrap(), &mut copy).unwrap();
+    assert_eq!(
+        copy.hook_sql(backend_name, HookStage::Before).unwrap(),
+        Some("INSERT INTO Foo (id, bar) VALUES (1, 'a');".to_owned())
+    );
+
+    ms.migrate(conn).unwrap();
+    let column = Column::new("baz", SqlType::Int);
+    let vals: Vec<i32> = conn
+        .query("Foo", &[column], None, None, None, None)
+        .unwrap()
+        .mapped(|row| i32::from_sql_ref(row.get(0, SqlType::Int)?))
+        .collect()
+        .unwrap();
+    assert_eq!(vals, vec![7]);
+
+    ms.unmigrate(conn).unwrap();
+}
+
+fn migration_custom_migrations_table(conn: &mut Connection, migrations_table: &str) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+            bar: String,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend];
+    ms.current().set_migrations_table(migrations_table).unwrap();
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    let init = ms.latest().unwrap();
+    assert_eq!(init.migrations_table().unwrap(), migrations_table);
+    assert_eq!(ms.migrations_table().unwrap(), migrations_table);
+
+    ms.migrate(conn).unwrap();
+    assert!(conn.has_table(migrations_table).unwrap());
+    assert!(!conn.has_table("butane_migrations").unwrap());
+    assert_eq!(ms.last_applied_migration(conn).unwrap(), Some(init));
+    assert!(ms.unapplied_migrations(conn).unwrap().is_empty());
+
+    ms.unmigrate(conn).unwrap();
+    assert_eq!(ms.last_applied_migration(conn).unwrap(), None);
+}
+
+fn migration_applied_history(conn: &mut Connection) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+        }
+    };
+    let v2 = quote! {
+        struct Foo {
+            id: i64,
+            bar: Option<String>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend];
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    model_with_migrations(v2, &mut ms);
+    assert!(ms
+        .create_migration(&backends, "v2", ms.latest().as_ref())
+        .unwrap());
+    assert!(ms.applied(conn).unwrap().is_empty());
+
+    // A migrations table created by an earlier version, without applied_at
+    conn.execute("CREATE TABLE butane_migrations (name TEXT NOT NULL PRIMARY KEY);")
+        .unwrap();
+    let init = ms.get_migration("init").unwrap();
+    conn.execute(init.up_sql(conn.backend_name()).unwrap().unwrap())
+        .unwrap();
+    conn.execute("INSERT INTO butane_migrations (name) VALUES ('init');")
+        .unwrap();
+    assert_eq!(
+        ms.applied(conn).unwrap(),
+        vec![AppliedMigration {
+            name: "init".to_owned(),
+            applied_at: None,
+        }]
+    );
+    let pending = ms.pending(conn).unwrap();
+    assert_eq!(pending, vec![ms.get_migration("v2").unwrap()]);
+
+    let before = SystemTime::now() - Duration::from_secs(1);
+    ms.migrate(conn).unwrap();
+    let applied = ms.applied(conn).unwrap();
+    let names: Vec<&str> = applied.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["init", "v2"]);
+    assert_eq!(applied[0].applied_at, None);
+    assert!(applied[1].applied_at.unwrap() >= before);
+    assert!(ms.pending(conn).unwrap().is_empty());
+}
+
+fn migration_check(conn: &mut Connection) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+        }
+    };
+    let v2 = quote! {
+        struct Foo {
+            id: i64,
+            bar: Option<String>,
+        }
+    };
+
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend];
+    assert!(!ms.has_pending(conn).unwrap());
+    model_with_migrations(init, &mut ms);
+    assert!(ms.has_unmigrated_changes().unwrap());
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    assert!(!ms.has_unmigrated_changes().unwrap());
+    assert!(ms.has_pending(conn).unwrap());
+
+    ms.migrate(conn).unwrap();
+    assert!(!ms.has_pending(conn).unwrap());
+
+    // A model change without a migration is not pending, but is unmigrated
+    model_with_migrations(v2, &mut ms);
+    assert!(ms.has_unmigrated_changes().unwrap());
+    assert!(!ms.has_pending(conn).unwrap());
+}
+
 fn migration_modify_field_type_change(conn: &mut Connection, up_sql: &str, down_sql: &str) {
     let init = quote! {
         struct Foo {
@@ -416,6 +1083,75 @@ struct Foo {
     test_migrate(conn, init, v2, up_sql, down_sql);
 }
 
+#[cfg(feature = "uuid")]
+fn migration_convert_field_type(conn: &mut Connection) {
+    let init = quote! {
+        struct Foo {
+            id: i64,
+            bar: String,
+            baz: i32,
+            qux: f64,
+        }
+    };
+
+    let v2 = quote! {
+        struct Foo {
+            id: i64,
+            bar: Uuid,
+            baz: i64,
+            qux: i32,
+        }
+    };
+
+    let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    let mut ms = MemMigrations::new();
+    let backend = conn.backend();
+    let backends = nonempty::nonempty![backend];
+    model_with_migrations(init, &mut ms);
+    assert!(ms.create_migration(&backends, "init", None).unwrap());
+    ms.migrate(conn).unwrap();
+    conn.execute(format!(
+        "INSERT INTO Foo (id, bar, baz, qux) VALUES (1, '{uuid}', 7, 2.0);"
+    ))
+    .unwrap();
+
+    model_with_migrations(v2, &mut ms);
+    assert!(ms
+        .create_migration(&backends, "v2", ms.latest().as_ref())
+        .unwrap());
+    ms.migrate(conn).unwrap();
+
+    let columns = [
+        Column::new("bar", SqlType::Blob),
+        Column::new("baz", SqlType::BigInt),
+        Column::new("qux", SqlType::Int),
+    ];
+    let rows: Vec<(uuid::Uuid, i64, i32)> = conn
+        .query("Foo", &columns, None, None, None, None)
+        .unwrap()
+        .mapped(|row| {
+            Ok((
+                uuid::Uuid::from_sql_ref(row.get(0, SqlType::Blob)?)?,
+                i64::from_sql_ref(row.get(1, SqlType::BigInt)?)?,
+                i32::from_sql_ref(row.get(2, SqlType::Int)?)?,
+            ))
+        })
+        .collect()
+        .unwrap();
+    assert_eq!(rows, vec![(uuid.parse().unwrap(), 7, 2)]);
+
+    // Converting back restores the hyphenated string
+    ms.latest().unwrap().downgrade(conn).unwrap();
+    let column = Column::new("bar", SqlType::Text);
+    let vals: Vec<String> = conn
+        .query("Foo", &[column], None, None, None, None)
+        .unwrap()
+        .mapped(|row| String::from_sql_ref(row.get(0, SqlType::Text)?))
+        .collect()
+        .unwrap();
+    assert_eq!(vals, vec![uuid.to_string()]);
+}
+
 fn migration_modify_field_nullability_change(conn: &mut Connection, up_sql: &str, down_sql: &str) {
     let init = quote! {
         struct Foo {
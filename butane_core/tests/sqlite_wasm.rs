@@ -0,0 +1,87 @@
+//! Smoke tests of the sqlite-wasm backend, run with
+//! `wasm-bindgen-test`. The database is a JavaScript object which
+//! records the statements it is given and answers each query with its
+//! parameters as the only row, so they test the bindings and the
+//! conversion of values rather than SQLite.
+#![cfg(all(feature = "sqlite-wasm", target_arch = "wasm32"))]
+
+use butane_core::db::sqlite_wasm::{Database, SQLiteWasmConnection};
+use butane_core::db::{BackendConnectionAsync, ConnectionMethodsAsync};
+use butane_core::{SqlVal, ToSql};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen(inline_js = r#"
+export function echoDatabase() {
+  const statements = [];
+  return {
+    statements,
+    exec: async (sql, params) => {
+      statements.push(sql);
+      return 1;
+    },
+    query: async (sql, params) => {
+      statements.push(sql);
+      return [params];
+    },
+  };
+}
+
+export function statements(db) {
+  return db.statements.join("\n");
+}
+"#)]
+extern "C" {
+    #[wasm_bindgen(js_name = echoDatabase)]
+    fn echo_database() -> Database;
+
+    fn statements(db: &Database) -> String;
+}
+
+#[wasm_bindgen_test]
+async fn connect_enables_foreign_keys() {
+    let db = echo_database();
+    SQLiteWasmConnection::new(db.clone()).await.unwrap();
+    assert_eq!(statements(&db), "PRAGMA foreign_keys = ON;");
+}
+
+#[wasm_bindgen_test]
+async fn values_round_trip() {
+    let conn = SQLiteWasmConnection::new(echo_database()).await.unwrap();
+    let big = 1i64 << 60;
+    let rows: Vec<(i64, i64, f64, String, Vec<u8>, Option<String>)> = conn
+        .query_as(
+            "SELECT ?, ?, ?, ?, ?, ?",
+            &[
+                SqlVal::BigInt(7).as_ref(),
+                big.to_sql_ref(),
+                SqlVal::Real(1.5).as_ref(),
+                "text".to_sql_ref(),
+                SqlVal::Blob(vec![0, 1, 255]).as_ref(),
+                SqlVal::Null.as_ref(),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![(7, big, 1.5, "text".to_string(), vec![0, 1, 255], None)]
+    );
+}
+
+#[wasm_bindgen_test]
+async fn transaction_commits() {
+    let db = echo_database();
+    let mut conn = SQLiteWasmConnection::new(db.clone()).await.unwrap();
+    let tx = conn.transaction().await.unwrap();
+    let changed = tx
+        .execute_params("UPDATE foo SET bar = ?", &[1i32.to_sql_ref()])
+        .await
+        .unwrap();
+    assert_eq!(changed, 1);
+    tx.commit().await.unwrap();
+    assert_eq!(
+        statements(&db),
+        "PRAGMA foreign_keys = ON;\nBEGIN;\nUPDATE foo SET bar = ?\nCOMMIT;"
+    );
+}
@@ -30,9 +30,13 @@ pub fn butane_test(args: TokenStream, input: TokenStream) -> TokenStream {
         use butane_core::DataObject;
         use butane_core::DataResult;
         use butane_core::db::BackendConnection;
-        use butane_core::fkey::ForeignKeyOpsSync;
+        use butane_core::fkey::{ForeignKeyOpsSync, GenericForeignKeyOpsSync};
         use butane_core::many::ManyOpsSync;
+        use butane_core::query::CursorOpsSync;
         use butane_core::query::QueryOpsSync;
+        use butane_core::query::{PatchOpsSync, UpdateOpsSync};
+        use butane_core::subtype::SupertypeOpsSync;
+        use butane_core::through::ManyThroughOpsSync;
         use butane_core::DataObjectOpsSync;
     ))
     .unwrap();
@@ -46,9 +50,13 @@ pub fn butane_test(args: TokenStream, input: TokenStream) -> TokenStream {
         use butane_core::DataObject;
         use butane_core::DataResult;
         use butane_core::db::BackendConnectionAsync;
-        use butane_core::fkey::ForeignKeyOpsAsync;
+        use butane_core::fkey::{ForeignKeyOpsAsync, GenericForeignKeyOpsAsync};
         use butane_core::many::ManyOpsAsync;
+        use butane_core::query::CursorOpsAsync;
         use butane_core::query::QueryOpsAsync;
+        use butane_core::query::{PatchOpsAsync, UpdateOpsAsync};
+        use butane_core::subtype::SupertypeOpsAsync;
+        use butane_core::through::ManyThroughOpsAsync;
         use butane_core::DataObjectOpsAsync;
     ))
     .unwrap();
@@ -65,8 +73,14 @@ pub fn butane_test(args: TokenStream, input: TokenStream) -> TokenStream {
             #[maybe_async_cfg::maybe(
                 sync(),
                 idents(
+                    AnyDataObjectAsync(sync="AnyDataObject"),
                     ConnectionAsync(sync="Connection"),
+                    ConnectionMethodsAsync(sync="ConnectionMethods"),
                     find_async(sync="find"),
+                    ModelOpsAsync(sync="ModelOpsSync"),
+                    save_all_partial_async(sync="save_all_partial"),
+                    save_all_partial_atomic_async(sync="save_all_partial_atomic"),
+                    SeederOpsAsync(sync="SeederOpsSync"),
                     setup_blog(sync="setup_blog_sync"),
                     create_tag(sync="create_tag_sync"),
                 )
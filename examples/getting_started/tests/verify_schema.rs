@@ -0,0 +1,43 @@
+use butane::db::Connection;
+use butane::migrations::Migrations;
+use butane_test_helper::*;
+use butane_test_macros::butane_test;
+
+#[butane_test(sync, nomigrate)]
+fn verify_schema_after_migrating(mut connection: Connection) {
+    let fingerprint = butane::schema_fingerprint!();
+    assert!(fingerprint.tables().any(|table| table == "Post"));
+
+    let result = butane::verify_schema(&connection, &fingerprint);
+    let Err(butane::Error::SchemaMismatch { tables }) = result else {
+        panic!("unmigrated database verified: {result:?}");
+    };
+    assert_eq!(tables.len(), fingerprint.tables().count());
+
+    let base_dir = std::path::PathBuf::from(".butane");
+    let migrations = butane_cli::get_migrations(&base_dir).unwrap();
+    migrations.migrate(&mut connection).unwrap();
+    butane::verify_schema(&connection, &fingerprint).unwrap();
+
+    // A column of another type, as if its migration had not been applied
+    connection
+        .execute("ALTER TABLE Post DROP COLUMN likes;")
+        .unwrap();
+    connection
+        .execute("ALTER TABLE Post ADD COLUMN likes TEXT NOT NULL DEFAULT '';")
+        .unwrap();
+    let result = butane::verify_schema(&connection, &fingerprint);
+    let Err(butane::Error::SchemaMismatch { tables }) = result else {
+        panic!("retyped column verified: {result:?}");
+    };
+    assert_eq!(tables, vec!["Post".to_string()]);
+
+    connection
+        .execute("ALTER TABLE Post DROP COLUMN likes;")
+        .unwrap();
+    let result = butane::verify_schema(&connection, &fingerprint);
+    let Err(butane::Error::SchemaMismatch { tables }) = result else {
+        panic!("changed table verified: {result:?}");
+    };
+    assert_eq!(tables, vec!["Post".to_string()]);
+}
@@ -58,15 +58,7 @@ async fn insert_data(connection: &Connection) {
         post.byline.unwrap().load(connection).await.unwrap().name,
         "Joe Bloggs"
     );
-    assert_eq!(
-        post.likes
-            .load(connection)
-            .await
-            .unwrap()
-            .into_iter()
-            .count(),
-        1
-    );
+    assert_eq!(post.likes.load(connection).await.unwrap().count(), 1);
 
     let mut rowid_test = RowidTest::new(5);
     rowid_test.save(connection).await.unwrap();